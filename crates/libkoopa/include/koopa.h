@@ -1,6 +1,7 @@
 #ifndef LIBKOOPA_KOOPA_H_
 #define LIBKOOPA_KOOPA_H_
 
+#include <stdbool.h>
 #include <stddef.h>
 #include <stdint.h>
 #if defined(_WIN32) || defined(_WIN64)
@@ -160,6 +161,12 @@ typedef struct {
   koopa_raw_slice_t params;
   /// Basic blocks, empty if is a function declaration.
   koopa_raw_slice_t bbs;
+  /// The function has no side effects.
+  bool pure;
+  /// The function never returns to its caller.
+  bool noreturn;
+  /// Calling convention of function, null if is the default one.
+  const char *conv;
 } koopa_raw_function_data_t;
 
 ///
@@ -415,6 +422,8 @@ typedef enum {
   KOOPA_RVT_CALL,
   /// Function return.
   KOOPA_RVT_RETURN,
+  /// Unreachable terminator.
+  KOOPA_RVT_UNREACHABLE,
 } koopa_raw_value_tag_t;
 
 ///
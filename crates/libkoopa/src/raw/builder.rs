@@ -19,6 +19,10 @@ pub struct RawProgramBuilder {
   funcs: HashMap<Function, Box<RawFunctionData>>,
   bbs: HashMap<BasicBlock, Box<RawBasicBlockData>>,
   values: HashMap<Value, Box<RawValueData>>,
+  /// Integer elements of byte data, which have no value handles.
+  /// They are boxed so that their addresses will not change.
+  #[allow(clippy::vec_box)]
+  bytes: Vec<Box<RawValueData>>,
 }
 
 impl RawProgramBuilder {
@@ -222,6 +226,9 @@ impl BuildRaw for FunctionData {
       name: self.name().build(builder, info),
       params: iter_into_raw(self.params().iter(), builder, info),
       bbs: iter_into_raw(self.layout().bbs().keys(), builder, info),
+      pure: self.func_attrs().pure,
+      noreturn: self.func_attrs().noreturn,
+      conv: self.func_attrs().conv.build(builder, info),
     }
   }
 }
@@ -320,6 +327,7 @@ impl BuildRaw for ValueKind {
       ValueKind::ZeroInit(_) => RawValueKind::ZeroInit,
       ValueKind::Undef(_) => RawValueKind::Undef,
       ValueKind::Aggregate(v) => RawValueKind::Aggregate(v.build(builder, info)),
+      ValueKind::Bytes(v) => RawValueKind::Aggregate(v.build(builder, info)),
       ValueKind::FuncArgRef(v) => RawValueKind::FuncArgRef(v.build(builder, info)),
       ValueKind::BlockArgRef(v) => RawValueKind::BlockArgRef(v.build(builder, info)),
       ValueKind::Alloc(_) => RawValueKind::Alloc,
@@ -333,6 +341,7 @@ impl BuildRaw for ValueKind {
       ValueKind::Jump(v) => RawValueKind::Jump(v.build(builder, info)),
       ValueKind::Call(v) => RawValueKind::Call(v.build(builder, info)),
       ValueKind::Return(v) => RawValueKind::Return(v.build(builder, info)),
+      ValueKind::Unreachable(_) => RawValueKind::Unreachable,
     }
   }
 }
@@ -357,6 +366,39 @@ impl BuildRaw for Aggregate {
   }
 }
 
+/// Byte data is converted to an aggregate of integers.
+impl BuildRaw for Bytes {
+  type Raw = RawAggregate;
+
+  fn build(&self, builder: &mut RawProgramBuilder, info: &mut ProgramInfo) -> Self::Raw {
+    let ty = Type::get_i32().build(builder, info);
+    let v: Vec<_> = self
+      .elems()
+      .map(|value| {
+        let data = Box::new(RawValueData {
+          ty,
+          name: null(),
+          used_by: RawSlice {
+            kind: RawSliceItemKind::Value,
+            ..RawSlice::default()
+          },
+          kind: RawValueKind::Integer(RawInteger { value }),
+        });
+        let raw = data.as_ref() as RawValue as *const ();
+        builder.bytes.push(data);
+        raw
+      })
+      .collect();
+    let elems = RawSlice {
+      buffer: v.as_ptr() as *const c_void,
+      len: v.len() as u32,
+      kind: RawSliceItemKind::Value,
+    };
+    builder.slices.push(v);
+    RawAggregate { elems }
+  }
+}
+
 impl BuildRaw for FuncArgRef {
   type Raw = RawFuncArgRef;
 
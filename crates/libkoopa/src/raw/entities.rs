@@ -70,6 +70,12 @@ pub struct RawFunctionData {
   pub params: RawSlice,
   /// Basic blocks, empty if is a function declaration.
   pub bbs: RawSlice,
+  /// The function has no side effects.
+  pub pure: bool,
+  /// The function never returns to its caller.
+  pub noreturn: bool,
+  /// Calling convention of function, null if is the default one.
+  pub conv: *const c_char,
 }
 
 /// A raw Koopa basic block.
@@ -141,6 +147,8 @@ pub enum RawValueKind {
   Call(RawCall),
   /// Function return.
   Return(RawReturn),
+  /// Unreachable terminator.
+  Unreachable,
 }
 
 /// Raw integer constant.
@@ -2,7 +2,9 @@ use super::builder::Pointer;
 use super::entities::*;
 use crate::errors::ErrorCode;
 use koopa::ir::builder_traits::*;
-use koopa::ir::{BasicBlock, BinaryOp, Function, FunctionData, Program, Type, TypeKind, Value};
+use koopa::ir::{BasicBlock, BinaryOp, FuncAttrs, Function, FunctionData, Program, Type};
+use koopa::ir::value_list::ValueList;
+use koopa::ir::{TypeKind, Value};
 use std::collections::HashMap;
 use std::ffi::CStr;
 use std::marker::PhantomData;
@@ -20,6 +22,16 @@ pub(crate) fn generate_program(raw: &RawProgram) -> Result<Program> {
   Ok(program)
 }
 
+impl TryFrom<&RawProgram<'_>> for Program {
+  type Error = ErrorCode;
+
+  /// Generates the given raw program to Koopa IR program,
+  /// see [`generate_program`].
+  fn try_from(raw: &RawProgram) -> Result<Self> {
+    generate_program(raw)
+  }
+}
+
 /// Information about the Koopa IR program.
 struct ProgramInfo {
   values: HashMap<RawValue, Value>,
@@ -255,6 +267,11 @@ impl GenerateOnRaw for RawFunction {
           .collect::<Result<_>>()?;
         FunctionData::with_param_names(name, params, ret)
       };
+      let data = data.with_func_attrs(FuncAttrs {
+        pure: raw.pure,
+        noreturn: raw.noreturn,
+        conv: raw.conv.generate(program, info)?,
+      });
       // generate function arguments
       let values = raw
         .params
@@ -354,6 +371,7 @@ impl GenerateOnRaw for RawValue {
         RawValueKind::Jump(v) => v.generate(program, info)?,
         RawValueKind::Call(v) => v.generate(program, info)?,
         RawValueKind::Return(v) => v.generate(program, info)?,
+        RawValueKind::Unreachable => builder!(program, info).unreachable(),
         _ => {
           let ty = raw.ty.generate(program, info)?;
           match &raw.kind {
@@ -395,7 +413,7 @@ impl GenerateOnRaw for RawAggregate {
   type Entity = Value;
 
   fn generate(&self, program: &mut Program, info: &mut ProgramInfo) -> Result<Self::Entity> {
-    let elems = self.elems.values()?.generate(program, info)?;
+    let elems: ValueList = self.elems.values()?.generate(program, info)?;
     Ok(build_value!(program, info, b, { b.aggregate(elems) }))
   }
 }
@@ -484,8 +502,8 @@ impl GenerateOnRaw for RawBranch {
     let cond = self.cond.generate(program, info)?;
     let true_bb = self.true_bb.generate(program, info)?;
     let false_bb = self.false_bb.generate(program, info)?;
-    let true_args = self.true_args.values()?.generate(program, info)?;
-    let false_args = self.false_args.values()?.generate(program, info)?;
+    let true_args: ValueList = self.true_args.values()?.generate(program, info)?;
+    let false_args: ValueList = self.false_args.values()?.generate(program, info)?;
     Ok(builder!(program, info).branch_with_args(cond, true_bb, false_bb, true_args, false_args))
   }
 }
@@ -495,7 +513,7 @@ impl GenerateOnRaw for RawJump {
 
   fn generate(&self, program: &mut Program, info: &mut ProgramInfo) -> Result<Self::Entity> {
     let target = self.target.generate(program, info)?;
-    let args = self.args.values()?.generate(program, info)?;
+    let args: ValueList = self.args.values()?.generate(program, info)?;
     Ok(builder!(program, info).jump_with_args(target, args))
   }
 }
@@ -505,7 +523,7 @@ impl GenerateOnRaw for RawCall {
 
   fn generate(&self, program: &mut Program, info: &mut ProgramInfo) -> Result<Self::Entity> {
     let callee = self.callee.generate(program, info)?;
-    let args = self.args.values()?.generate(program, info)?;
+    let args: ValueList = self.args.values()?.generate(program, info)?;
     Ok(builder!(program, info).call(callee, args))
   }
 }
@@ -71,6 +71,31 @@ fun @main(): i32 {
   %2 = load %1
   ret %2
 }
+"#;
+
+  const ALL_KINDS_PROGRAM: &str = r#"global @x = alloc i32, zeroinit
+global @arr = alloc [[i32, 2], 2], {{1, 2}, {3, 4}}
+
+decl @exit(i32) #[noreturn]
+
+fun @f(@a: i32, @p: *i32): i32 {
+%entry:
+  %0 = alloc [i32, 2]
+  store {5, 6}, %0
+  %1 = getelemptr %0, 1
+  %2 = getptr @p, @a
+  store undef, %2
+  %3 = load %1
+  %4 = xor %3, @a
+  br %4, %then(%4), %else
+
+%then(%v: i32):
+  ret %v
+
+%else:
+  call @exit(1)
+  unreachable
+}
 "#;
 
   fn build_raw<'rpb>(builder: &'rpb mut RawProgramBuilder, program: &str) -> RawProgram<'rpb> {
@@ -157,4 +182,24 @@ fun @main(): i32 {
     gen.generate_on(&program).unwrap();
     assert_eq!(std::str::from_utf8(&gen.writer()).unwrap(), ALLOC_PROGRAM);
   }
+
+  #[test]
+  fn test_raw_round_trip() {
+    for src in [
+      LOOP_PROGRAM,
+      RECURSIVE_PROGRAM,
+      ALLOC_PROGRAM,
+      ALL_KINDS_PROGRAM,
+    ] {
+      let mut builder = RawProgramBuilder::new();
+      let raw = build_raw(&mut builder, src);
+      let program = match Program::try_from(&raw) {
+        Ok(p) => p,
+        Err(e) => panic!("error code: {}", e as i32),
+      };
+      let mut gen = KoopaGenerator::new(Vec::new());
+      gen.generate_on(&program).unwrap();
+      assert_eq!(std::str::from_utf8(&gen.writer()).unwrap(), src);
+    }
+  }
 }
@@ -0,0 +1,252 @@
+#[allow(dead_code)]
+#[path = "../opt/dce.rs"]
+mod dce;
+
+use koopa::back::KoopaGenerator;
+use koopa::front::Driver;
+use koopa::ir::builder::FunctionBuilder;
+use koopa::ir::{BinaryOp, FunctionData, Program, Type, Validation};
+use koopa::opt::{Pass, PassManager, PurityInfo};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::env::{args, temp_dir};
+use std::fmt::Write;
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Default number of instructions in the synthetic function.
+const DEFAULT_INSTS: usize = 200_000;
+
+/// Number of calls in the synthetic function for counting allocations.
+const CALLS: usize = 50_000;
+
+/// Number of rounds to run.
+const ROUNDS: u32 = 5;
+
+/// Number of instructions in the program for printing.
+const PRINT_INSTS: usize = 50_000;
+
+/// Number of functions in the synthetic text form program.
+const PARSE_FUNCS: usize = 200;
+
+/// Number of loop iterations in each function of the synthetic text
+/// form program, each iteration has 8 instructions.
+const PARSE_ITERS: usize = 125;
+
+/// A global allocator that counts heap allocations.
+struct CountingAlloc;
+
+/// Number of heap allocations so far.
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    ALLOCS.fetch_add(1, Ordering::Relaxed);
+    System.alloc(layout)
+  }
+
+  unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    System.dealloc(ptr, layout)
+  }
+
+  unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+    ALLOCS.fetch_add(1, Ordering::Relaxed);
+    System.realloc(ptr, layout, new_size)
+  }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn main() {
+  let insts = args()
+    .nth(1)
+    .and_then(|s| s.parse().ok())
+    .unwrap_or(DEFAULT_INSTS);
+  let (mut build, mut logged, mut opt) = (Duration::ZERO, Duration::ZERO, Duration::ZERO);
+  let (mut fast, mut full) = (Duration::ZERO, Duration::ZERO);
+  for _ in 0..ROUNDS {
+    let time = Instant::now();
+    drop(build_program(insts, true, Validation::Off));
+    logged += time.elapsed();
+    let time = Instant::now();
+    drop(build_program(insts, false, Validation::Fast));
+    fast += time.elapsed();
+    let time = Instant::now();
+    drop(build_program(insts, false, Validation::Full));
+    full += time.elapsed();
+    let time = Instant::now();
+    let mut program = build_program(insts, false, Validation::Off);
+    build += time.elapsed();
+    let time = Instant::now();
+    let mut passman = PassManager::new();
+    let purity = PurityInfo::new(&program);
+    passman.register(Pass::Function(Box::new(dce::DeadCodeElimination::new(
+      purity,
+    ))));
+    passman.run_passes(&mut program);
+    opt += time.elapsed();
+  }
+  println!("instructions: {insts}, rounds: {ROUNDS}");
+  println!("build: {:?}/round", build / ROUNDS);
+  println!("build with mutation log: {:?}/round", logged / ROUNDS);
+  println!("build with fast validation: {:?}/round", fast / ROUNDS);
+  println!("build with full validation: {:?}/round", full / ROUNDS);
+  println!("dce:   {:?}/round", opt / ROUNDS);
+  let allocs = ALLOCS.load(Ordering::Relaxed);
+  let time = Instant::now();
+  let program = build_calls(CALLS);
+  let elapsed = time.elapsed();
+  let allocs = ALLOCS.load(Ordering::Relaxed) - allocs;
+  drop(program);
+  println!("calls: {CALLS}, build: {elapsed:?}, allocations: {allocs}");
+
+  let text = gen_text(PARSE_FUNCS, PARSE_ITERS);
+  let path = temp_dir().join("koopa-bench.koopa");
+  fs::write(&path, &text).unwrap();
+  let (mut buffer, mut file) = (Duration::ZERO, Duration::ZERO);
+  for _ in 0..ROUNDS {
+    let time = Instant::now();
+    let driver: Driver<_> = text.as_str().into();
+    driver.generate_program().unwrap();
+    buffer += time.elapsed();
+    let time = Instant::now();
+    let driver = Driver::from_path(&path).unwrap();
+    driver.generate_program().unwrap();
+    file += time.elapsed();
+  }
+  fs::remove_file(&path).unwrap();
+  let mib = text.len() as f64 / (1024.0 * 1024.0);
+  let throughput = |time: Duration| mib / (time / ROUNDS).as_secs_f64();
+  println!("text: {mib:.2} MiB, {PARSE_FUNCS} functions");
+  println!(
+    "parse buffer: {:?}/round, {:.2} MiB/s",
+    buffer / ROUNDS,
+    throughput(buffer)
+  );
+  println!(
+    "parse file:   {:?}/round, {:.2} MiB/s",
+    file / ROUNDS,
+    throughput(file)
+  );
+
+  let program = build_program(PRINT_INSTS, false, Validation::Off);
+  let path = temp_dir().join("koopa-bench-print.koopa");
+  let (mut vec, mut file, mut string) = (Duration::ZERO, Duration::ZERO, Duration::ZERO);
+  let mut len = 0;
+  for _ in 0..ROUNDS {
+    let time = Instant::now();
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    len = gen.writer().len();
+    vec += time.elapsed();
+    let time = Instant::now();
+    let mut gen = KoopaGenerator::from_path(&path).unwrap();
+    gen.generate_on(&program).unwrap();
+    file += time.elapsed();
+    let time = Instant::now();
+    let mut buf = String::new();
+    KoopaGenerator::new(Vec::new())
+      .emit_to_string(&program, &mut buf)
+      .unwrap();
+    string += time.elapsed();
+    assert_eq!(buf.len(), len);
+  }
+  fs::remove_file(&path).unwrap();
+  println!("print: {PRINT_INSTS} instructions, {len} bytes");
+  println!("print to vector: {:?}/round", vec / ROUNDS);
+  println!("print to file:   {:?}/round", file / ROUNDS);
+  println!("print to string: {:?}/round", string / ROUNDS);
+}
+
+/// Builds a program with a single large function, in which about
+/// half of the instructions are dead. Records all mutations of the
+/// function if `log` is `true`, and checks all mutations at the given
+/// validation level.
+fn build_program(insts: usize, log: bool, validation: Validation) -> Program {
+  let mut program = Program::new();
+  let func = program.new_func(FunctionData::with_param_names(
+    "@main".into(),
+    vec![(Some("%x".into()), Type::get_i32())],
+    Type::get_i32(),
+  ));
+  let data = program.func_mut(func);
+  if log {
+    data.enable_mutation_log();
+  }
+  let x = data.params()[0];
+  let mut builder = FunctionBuilder::new(data).with_validation(validation);
+  let entry = builder.create_bb(Some("%entry".into()));
+  builder.position_at_end(entry);
+  let mut live = x;
+  for i in 0..insts / 2 {
+    let rhs = builder.integer(i as i32);
+    live = builder.binary(BinaryOp::Add, live, rhs);
+    builder.binary(BinaryOp::Mul, live, rhs);
+  }
+  builder.ret(Some(live));
+  program
+}
+
+/// Builds a program with a single function which calls another function
+/// with two arguments for the given number of times.
+fn build_calls(calls: usize) -> Program {
+  let mut program = Program::new();
+  let callee = program.new_func(FunctionData::new_decl(
+    "@f".into(),
+    vec![Type::get_i32(), Type::get_i32()],
+    Type::get_i32(),
+  ));
+  let func = program.new_func(FunctionData::with_param_names(
+    "@main".into(),
+    vec![(Some("%x".into()), Type::get_i32())],
+    Type::get_i32(),
+  ));
+  let data = program.func_mut(func);
+  let x = data.params()[0];
+  let mut builder = FunctionBuilder::new(data);
+  let entry = builder.create_bb(Some("%entry".into()));
+  builder.position_at_end(entry);
+  let mut last = x;
+  for i in 0..calls {
+    let rhs = builder.integer(i as i32);
+    last = builder.call(callee, [last, rhs]);
+  }
+  builder.ret(Some(last));
+  program
+}
+
+/// Generates a text form program with the given number of functions,
+/// like the ones generated by compilers. Each function sums an array
+/// in a loop with the given number of unrolled iterations.
+fn gen_text(funcs: usize, iters: usize) -> String {
+  let mut text = String::from("global @arr = alloc [i32, 1024], zeroinit\n\n");
+  text += "decl @putint(i32)\n\n";
+  for f in 0..funcs {
+    writeln!(text, "fun @func_{f}(@n: i32): i32 {{").unwrap();
+    writeln!(text, "%entry:").unwrap();
+    writeln!(text, "  %sum_0 = alloc i32").unwrap();
+    writeln!(text, "  store 0, %sum_0").unwrap();
+    writeln!(text, "  jump %loop_0(0)\n").unwrap();
+    for i in 0..iters {
+      writeln!(text, "%loop_{i}(%i_{i}: i32):").unwrap();
+      writeln!(text, "  %ptr_{i} = getelemptr @arr, %i_{i}").unwrap();
+      writeln!(text, "  %elem_{i} = load %ptr_{i}").unwrap();
+      writeln!(text, "  %sum_val_{i} = load %sum_0").unwrap();
+      writeln!(text, "  %new_sum_{i} = add %sum_val_{i}, %elem_{i}").unwrap();
+      writeln!(text, "  store %new_sum_{i}, %sum_0").unwrap();
+      writeln!(text, "  %next_{i} = add %i_{i}, 1").unwrap();
+      writeln!(text, "  %cond_{i} = lt %next_{i}, @n").unwrap();
+      let next = i + 1;
+      writeln!(text, "  br %cond_{i}, %loop_{next}(%next_{i}), %end\n").unwrap();
+    }
+    writeln!(text, "%loop_{iters}(%i_{iters}: i32):").unwrap();
+    writeln!(text, "  jump %end\n").unwrap();
+    writeln!(text, "%end:").unwrap();
+    writeln!(text, "  %result = load %sum_0").unwrap();
+    writeln!(text, "  call @putint(%result)").unwrap();
+    writeln!(text, "  ret %result").unwrap();
+    writeln!(text, "}}\n").unwrap();
+  }
+  text
+}
@@ -3,6 +3,7 @@ use koopa::ir::builder_traits::*;
 use koopa::ir::{BasicBlock, BinaryOp, Function, FunctionData, Program, Type, Value};
 use std::env::args;
 use std::fs::File;
+use std::io::{BufReader, Read};
 use std::{fmt, io, process};
 
 fn main() {
@@ -199,7 +200,7 @@ fn generate_bbs(
   let jump = new_value!(env.main).jump(bb);
   add_inst!(env.main, entry, jump);
   let mut loop_info = Vec::new();
-  for result in input.bytes() {
+  for result in BufReader::new(input).bytes() {
     bb = match result.map_err(Error::Io)? {
       b'>' => generate_ptr_op(env, bb, 1),
       b'<' => generate_ptr_op(env, bb, -1),
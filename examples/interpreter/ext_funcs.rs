@@ -109,8 +109,10 @@ impl ExternFuncs {
     match val {
       Val::Undef => Ok(0),
       Val::Int(i) => Ok(*i as usize),
-      Val::Pointer { ptr: Some(p), .. } => Ok(p.as_ptr() as usize),
-      Val::Pointer { ptr: None, .. } => Ok(0),
+      Val::Pointer(Some(_)) => Err(new_error(
+        "pointers to the memory of the interpreter can not be passed to external functions",
+      )),
+      Val::Pointer(None) => Ok(0),
       Val::UnsafePointer(Some(p)) => Ok(p.as_ptr() as usize),
       Val::UnsafePointer(None) => Ok(0),
       _ => Err(new_error("unsupported value")),
@@ -1,29 +1,148 @@
-// Use `Vec<Box<T>>` to prevent reallocation.
-#![allow(clippy::vec_box)]
-// For convenience.
-#![allow(clippy::borrowed_box)]
+// Parts of the debugging API are only used by tests.
+#![allow(dead_code)]
 
 use super::ext_funcs::ExternFuncs;
+use super::memory::{Memory, Mode, Pointer};
 use koopa::back::{NameManager, Visitor};
-use koopa::ir::entities::ValueData;
-use koopa::ir::layout::BasicBlockNode;
+use koopa::ir::entities::{SourceSpan, ValueData};
 use koopa::ir::values::*;
 use koopa::ir::{BasicBlock, BinaryOp, FunctionData, Program, Type, TypeKind, Value, ValueKind};
-use std::collections::HashMap;
-use std::io::{Error, ErrorKind, Result, Write};
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, Result, Write};
 use std::ptr::{null, NonNull};
+use std::{error, fmt, mem};
 
 pub fn new_error(message: &str) -> Error {
-  Error::new(ErrorKind::Other, message)
+  Error::other(message)
 }
 
+/// Kind of a [`RuntimeError`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuntimeErrorKind {
+  /// An integer operation overflowed in `OverflowMode::Trap`.
+  Overflow,
+  /// Other errors, like invalid memory accesses.
+  Other,
+}
+
+/// Error of an instruction, which is returned as the inner error of
+/// `std::io::Error`.
+#[derive(Debug)]
+pub struct RuntimeError {
+  pub kind: RuntimeErrorKind,
+  /// Name of the function.
+  pub func: String,
+  /// Handle of the instruction.
+  pub value: Value,
+  /// The instruction in text form.
+  pub inst: String,
+  /// Source location of the instruction.
+  pub span: Option<SourceSpan>,
+  pub message: String,
+}
+
+impl fmt::Display for RuntimeError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "runtime error in function '{}', at `{}`: {}",
+      self.func, self.inst, self.message
+    )
+  }
+}
+
+impl error::Error for RuntimeError {}
+
+/// Observer of the execution, registered by [`Interpreter::add_hook`].
+pub trait ExecHook {
+  /// Called before executing the given instruction in the given frame.
+  fn before_inst(&mut self, _frame: &Frame, _inst: Value, _data: &ValueData) {}
+
+  /// Called after the frame of a called function is created,
+  /// external functions are not reported.
+  fn on_call(&mut self, _frame: &Frame) {}
+
+  /// Called when the given frame returns the given value.
+  fn on_ret(&mut self, _frame: &Frame, _ret: &Val) {}
+}
+
+/// Instructions and functions at which the execution pauses.
+#[derive(Clone, Default)]
+pub struct Breakpoints {
+  values: HashSet<Value>,
+  funcs: HashSet<String>,
+}
+
+impl Breakpoints {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Pauses before executing the given instruction.
+  pub fn on_value(mut self, inst: Value) -> Self {
+    self.values.insert(inst);
+    self
+  }
+
+  /// Pauses before executing the first instruction of the given function.
+  pub fn on_function(mut self, name: &str) -> Self {
+    self.funcs.insert(name.into());
+    self
+  }
+}
+
+#[derive(Default)]
 pub struct Interpreter {
   libs: Vec<String>,
+  mode: Mode,
+  hooks: Vec<Box<dyn ExecHook>>,
+  breakpoints: Breakpoints,
 }
 
 impl Interpreter {
-  pub fn new(libs: Vec<String>) -> Self {
-    Self { libs }
+  pub fn new(libs: Vec<String>, mode: Mode) -> Self {
+    Self {
+      libs,
+      mode,
+      ..Default::default()
+    }
+  }
+
+  /// Adds a hook, hooks are called in the order they are added.
+  pub fn add_hook(&mut self, hook: Box<dyn ExecHook>) {
+    self.hooks.push(hook);
+  }
+
+  pub fn set_breakpoints(&mut self, breakpoints: Breakpoints) {
+    self.breakpoints = breakpoints;
+  }
+
+  /// Runs the given program until `@main` returns or a breakpoint is hit.
+  pub fn run(self, program: &Program) -> Result<Exit<'_>> {
+    self.start(program)?.run(None)
+  }
+
+  /// Runs the given program like [`Interpreter::run`], but also pauses
+  /// after executing the given number of instructions.
+  pub fn run_with_limit(self, program: &Program, steps: usize) -> Result<Exit<'_>> {
+    self.start(program)?.run(Some(steps))
+  }
+
+  fn start(self, program: &Program) -> Result<InterpreterImpl<'_>> {
+    let ext_funcs = unsafe { ExternFuncs::new(&self.libs) }
+      .map_err(|e| new_error(&format!("invalid library: {}", e)))?;
+    let mut interpreter = InterpreterImpl {
+      program,
+      memory: Memory::new(self.mode),
+      vars: HashMap::new(),
+      frames: Vec::new(),
+      ext_funcs,
+      hooks: self.hooks,
+      breakpoints: self.breakpoints,
+      resumed: false,
+    };
+    interpreter.init()?;
+    Ok(interpreter)
   }
 }
 
@@ -31,24 +150,149 @@ impl<W: Write> Visitor<W> for Interpreter {
   type Output = i32;
 
   fn visit(&mut self, _: &mut W, _: &mut NameManager, program: &Program) -> Result<Self::Output> {
-    let ext_funcs = unsafe { ExternFuncs::new(&self.libs) }
-      .map_err(|e| new_error(&format!("invalid library: {}", e)))?;
-    let mut interpreter = InterpreterImpl::new(program, ext_funcs);
-    interpreter.interpret()
+    match mem::take(self).run(program)? {
+      Exit::Finished(ret) => Ok(ret),
+      Exit::Paused(_) => Err(new_error("execution paused")),
+    }
+  }
+}
+
+/// Result of running the interpreter.
+pub enum Exit<'a> {
+  /// `@main` returned the given value.
+  Finished(i32),
+  Paused(Paused<'a>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PauseReason {
+  Breakpoint,
+  StepLimit,
+}
+
+/// A paused execution, which can be inspected and resumed.
+pub struct Paused<'a> {
+  interpreter: Box<InterpreterImpl<'a>>,
+  reason: PauseReason,
+}
+
+impl<'a> Paused<'a> {
+  pub fn reason(&self) -> PauseReason {
+    self.reason
+  }
+
+  /// Returns all frames, from `@main` to the innermost one.
+  pub fn frames(&self) -> &[Frame<'a>] {
+    &self.interpreter.frames
+  }
+
+  /// Returns the innermost frame.
+  pub fn frame(&self) -> &Frame<'a> {
+    self.interpreter.frames.last().unwrap()
+  }
+
+  /// Returns the current contents of the memory allocated by the given
+  /// `alloc` in the given frame, or `None` if the `alloc` has not been
+  /// executed in the frame.
+  pub fn alloc_contents(&self, frame: &Frame, alloc: Value) -> Option<Val> {
+    let Some(Val::Pointer(Some(ptr))) = frame.vals.get(&alloc) else {
+      return None;
+    };
+    let ty = match frame.func.dfg().value(alloc).ty().kind() {
+      TypeKind::Pointer(base) => base.clone(),
+      _ => return None,
+    };
+    self.interpreter.memory.load(*ptr, &ty).ok()
+  }
+
+  /// Returns the current contents of the memory allocated by the given
+  /// global allocation, or `None` if it is not a global allocation.
+  pub fn global_contents(&self, global: Value) -> Option<Val> {
+    let Some(Val::Pointer(Some(ptr))) = self.interpreter.vars.get(&global) else {
+      return None;
+    };
+    let ty = match self.interpreter.program.borrow_value(global).ty().kind() {
+      TypeKind::Pointer(base) => base.clone(),
+      _ => return None,
+    };
+    self.interpreter.memory.load(*ptr, &ty).ok()
+  }
+
+  /// Resumes the execution until `@main` returns or a breakpoint is hit.
+  pub fn resume(self) -> Result<Exit<'a>> {
+    (*self.interpreter).run(None)
+  }
+
+  /// Resumes the execution like [`Paused::resume`], but also pauses after
+  /// executing the given number of instructions.
+  pub fn resume_with_limit(self, steps: usize) -> Result<Exit<'a>> {
+    (*self.interpreter).run(Some(steps))
+  }
+}
+
+/// Frame of a function call.
+pub struct Frame<'a> {
+  func: &'a FunctionData,
+  bb: BasicBlock,
+  /// The next instruction to be executed, or the call instruction if the
+  /// frame is calling another function.
+  inst: Value,
+  vals: HashMap<Value, Val>,
+  /// IDs of stack allocations.
+  allocs: Vec<usize>,
+}
+
+impl<'a> Frame<'a> {
+  pub fn func(&self) -> &'a FunctionData {
+    self.func
+  }
+
+  /// Returns the current basic block.
+  pub fn bb(&self) -> BasicBlock {
+    self.bb
+  }
+
+  /// Returns the next instruction to be executed, or the call instruction
+  /// if the frame is calling another function.
+  pub fn inst(&self) -> Value {
+    self.inst
+  }
+
+  /// Returns the value of the given function parameter, basic block
+  /// parameter or instruction, or `None` if the value has not been
+  /// evaluated in the frame.
+  pub fn value(&self, value: Value) -> Option<&Val> {
+    self.vals.get(&value)
+  }
+
+  /// Returns the values of the function parameters.
+  pub fn args(&self) -> Vec<&Val> {
+    self.func.params().iter().map(|p| &self.vals[p]).collect()
+  }
+
+  /// Returns the values of the parameters of the current basic block.
+  pub fn bb_args(&self) -> Vec<&Val> {
+    let params = self.func.dfg().bb(self.bb).params();
+    params.iter().map(|p| &self.vals[p]).collect()
   }
 }
 
 struct InterpreterImpl<'a> {
   program: &'a Program,
-  global_allocs: Vec<Box<Val>>,
-  vars: HashMap<*const ValueData, Val>,
-  envs: Vec<Environment<'a>>,
+  memory: Memory,
+  vars: HashMap<Value, Val>,
+  frames: Vec<Frame<'a>>,
   ext_funcs: ExternFuncs,
+  hooks: Vec<Box<dyn ExecHook>>,
+  breakpoints: Breakpoints,
+  /// Set when pausing, so that the execution does not pause at the same
+  /// instruction again after resuming.
+  resumed: bool,
 }
 
 macro_rules! func {
   ($self:ident) => {
-    $self.envs.last().unwrap().func
+    $self.frames.last().unwrap().func
   };
 }
 
@@ -58,54 +302,139 @@ macro_rules! value {
   };
 }
 
-macro_rules! bb_node {
-  ($self:ident, $bb:expr) => {
-    func!($self).layout().bbs().node(&$bb).unwrap()
-  };
-}
-
 impl<'a> InterpreterImpl<'a> {
-  fn new(program: &'a Program, ext_funcs: ExternFuncs) -> Self {
-    Self {
-      program,
-      global_allocs: Vec::new(),
-      vars: HashMap::new(),
-      envs: Vec::new(),
-      ext_funcs,
-    }
-  }
-
-  fn interpret(&mut self) -> Result<i32> {
+  /// Evaluates global variables, and enters the main function.
+  fn init(&mut self) -> Result<()> {
     // evaluate all global variables
     for var in self.program.inst_layout() {
       let value = self.program.borrow_value(*var);
       match value.kind() {
         ValueKind::GlobalAlloc(ga) => {
-          let val = self.eval_global_const(&self.program.borrow_value(ga.init()));
-          self.global_allocs.push(Box::new(val));
-          self.vars.insert(
-            &value as &ValueData,
-            Val::new_val_pointer(self.global_allocs.last()),
-          );
+          let init = self.program.borrow_value(ga.init());
+          let val = self.eval_global_const(&init);
+          let desc = match value.name() {
+            Some(name) => format!("global '{name}'"),
+            None => "an unnamed global".into(),
+          };
+          let ptr = self.memory.alloc(desc, init.ty(), val);
+          self.vars.insert(*var, Val::Pointer(Some(ptr)));
         }
         _ => panic!("invalid global variable"),
       }
     }
-    // evaluate on the main function
-    self
+    // enter the main function
+    let main = self
       .program
       .funcs()
       .values()
       .find(|f| f.name() == "@main")
-      .ok_or_else(|| new_error("function '@main' not found"))
-      .and_then(|f| self.eval_func(f, Vec::new()))
-      .and_then(|v| {
-        if let Val::Int(i) = v {
-          Ok(i)
-        } else {
-          Err(new_error("function '@main' must return an integer"))
-        }
-      })
+      .ok_or_else(|| new_error("function '@main' not found"))?;
+    if main.layout().entry_bb().is_none() {
+      return Err(new_error("function '@main' must be defined"));
+    }
+    self.push_frame(main, Vec::new());
+    Ok(())
+  }
+
+  /// Executes instructions until `@main` returns, a breakpoint is hit, or
+  /// the given number of instructions have been executed.
+  fn run(mut self, limit: Option<usize>) -> Result<Exit<'a>> {
+    let mut steps = 0;
+    loop {
+      let reason = if mem::take(&mut self.resumed) {
+        None
+      } else if self.hits_breakpoint() {
+        Some(PauseReason::Breakpoint)
+      } else if limit.is_some_and(|l| steps >= l) {
+        Some(PauseReason::StepLimit)
+      } else {
+        None
+      };
+      if let Some(reason) = reason {
+        self.resumed = true;
+        return Ok(Exit::Paused(Paused {
+          interpreter: Box::new(self),
+          reason,
+        }));
+      }
+      if let Some(ret) = self.step()? {
+        return Ok(Exit::Finished(ret));
+      }
+      steps += 1;
+    }
+  }
+
+  fn hits_breakpoint(&self) -> bool {
+    let frame = self.frames.last().unwrap();
+    self.breakpoints.values.contains(&frame.inst)
+      || (self.breakpoints.funcs.contains(frame.func.name())
+        && frame.func.layout().entry_bb() == Some(frame.bb)
+        && self.first_inst(frame.func, frame.bb) == frame.inst)
+  }
+
+  fn first_inst(&self, func: &FunctionData, bb: BasicBlock) -> Value {
+    let insts = func.layout().bbs().node(&bb).unwrap().insts();
+    *insts.front_key().expect("empty basic block")
+  }
+
+  fn push_frame(&mut self, func: &'a FunctionData, args: Vec<Val>) {
+    let bb = func.layout().entry_bb().unwrap();
+    self.frames.push(Frame {
+      func,
+      bb,
+      inst: self.first_inst(func, bb),
+      vals: func.params().iter().copied().zip(args).collect(),
+      allocs: Vec::new(),
+    });
+    let frame = self.frames.last().unwrap();
+    for hook in &mut self.hooks {
+      hook.on_call(frame);
+    }
+  }
+
+  /// Executes the next instruction. Returns the return value of `@main`
+  /// if it returns.
+  fn step(&mut self) -> Result<Option<i32>> {
+    let frame = self.frames.last().unwrap();
+    let (func, handle) = (frame.func, frame.inst);
+    let inst = func.dfg().value(handle);
+    for hook in &mut self.hooks {
+      hook.before_inst(frame, handle, inst);
+    }
+    match inst.kind() {
+      ValueKind::Alloc(_) => self.eval_alloc(handle, inst),
+      ValueKind::Load(v) => self.eval_load(handle, inst, v)?,
+      ValueKind::Store(v) => self.eval_store(handle, v)?,
+      ValueKind::GetPtr(v) => self.eval_getptr(handle, inst, v)?,
+      ValueKind::GetElemPtr(v) => self.eval_getelemptr(handle, inst, v)?,
+      ValueKind::Binary(v) => self.eval_binary(handle, v)?,
+      ValueKind::Call(v) => return self.eval_call(handle, v).map(|_| None),
+      ValueKind::Branch(v) => {
+        self.eval_branch(v);
+        return Ok(None);
+      }
+      ValueKind::Jump(v) => {
+        self.eval_jump(v);
+        return Ok(None);
+      }
+      ValueKind::Return(v) => return self.eval_return(v),
+      ValueKind::Unreachable(_) => {
+        return Err(self.runtime_error(handle, "unreachable executed".into()))
+      }
+      _ => panic!("invalid instruction"),
+    }
+    self.advance();
+    Ok(None)
+  }
+
+  /// Moves to the next instruction in the current basic block.
+  fn advance(&mut self) {
+    let frame = self.frames.last_mut().unwrap();
+    let insts = frame.func.layout().bbs().node(&frame.bb).unwrap().insts();
+    frame.inst = *insts
+      .cursor(frame.inst)
+      .next_key()
+      .expect("basic block without terminator");
   }
 
   fn eval_global_const(&self, value: &ValueData) -> Val {
@@ -119,6 +448,7 @@ impl<'a> InterpreterImpl<'a> {
           .map(|e| self.eval_global_const(&self.program.borrow_value(*e)))
           .collect(),
       ),
+      ValueKind::Bytes(v) => Val::Array(v.elems().map(Val::Int).collect()),
       _ => panic!("invalid constant"),
     }
   }
@@ -144,120 +474,95 @@ impl<'a> InterpreterImpl<'a> {
       TypeKind::Array(base, len) => {
         Val::Array((0..*len).map(|_| Self::new_zeroinit(base)).collect())
       }
-      TypeKind::Pointer(_) => Val::new_val_pointer(None),
+      TypeKind::Pointer(_) => Val::Pointer(None),
       _ => panic!("invalid type of zero initializer"),
     }
   }
 
-  fn get_pointer(src: Val, offset: isize, base_size: usize) -> Result<Val> {
-    match src {
-      Val::Pointer { ptr, index, len } => {
-        let new_index = (index as isize + offset) as usize;
-        (new_index < len)
-          .then(|| Val::Pointer {
-            ptr: ptr.map(|p| unsafe { NonNull::new_unchecked(p.as_ptr().offset(offset)) }),
-            index: new_index,
-            len,
-          })
-          .ok_or_else(|| {
-            new_error(&format!(
-              "pointer calculation out of bounds with index {} and length {}",
-              index, len
-            ))
-          })
-      }
-      Val::UnsafePointer(ptr) => Ok(Val::UnsafePointer(ptr.map(|p| unsafe {
-        NonNull::new_unchecked((p.as_ptr() as isize + base_size as isize * offset) as *mut ())
-      }))),
-      _ => panic!("invalid pointer"),
-    }
+  /// Returns a new error of the given instruction in the current function.
+  fn runtime_error(&self, inst: Value, message: String) -> Error {
+    self.runtime_error_of(RuntimeErrorKind::Other, inst, message)
   }
 
-  fn eval_func(&mut self, func: &'a FunctionData, args: Vec<Val>) -> Result<Val> {
-    // check parameter count
-    let param_len = match func.ty().kind() {
-      TypeKind::Function(params, _) => params.len(),
-      _ => panic!("invalid function"),
-    };
-    assert_eq!(param_len, args.len(), "parameter count mismatch");
-    // check if is a function declaration
-    if let Some(bb) = func.layout().bbs().front_node() {
-      // setup the environment
-      self.envs.push(Environment::new(
-        func,
-        func
-          .params()
-          .iter()
-          .map(|p| func.dfg().value(*p) as *const ValueData)
-          .zip(args.into_iter())
-          .collect(),
-      ));
-      // evaluate the entry basic block
-      let ret = self.eval_bb(bb);
-      self.envs.pop();
-      ret
-    } else {
-      // call the external function
-      unsafe { self.ext_funcs.call(func, args) }
-    }
+  /// Returns a new error of the given kind and the given instruction in
+  /// the current function.
+  fn runtime_error_of(&self, kind: RuntimeErrorKind, inst: Value, message: String) -> Error {
+    Error::other(RuntimeError {
+      kind,
+      func: func!(self).name().into(),
+      value: inst,
+      inst: func!(self).dfg().display_value(inst).to_string(),
+      span: value!(self, inst).span(),
+      message,
+    })
   }
 
-  fn eval_bb(&mut self, bb: &BasicBlockNode) -> Result<Val> {
-    // evaluate on all instructions
-    for inst in bb.insts().keys() {
-      let inst = func!(self).dfg().value(*inst);
-      match inst.kind() {
-        ValueKind::Alloc(_) => self.eval_alloc(inst),
-        ValueKind::Load(v) => self.eval_load(inst, v)?,
-        ValueKind::Store(v) => self.eval_store(v)?,
-        ValueKind::GetPtr(v) => self.eval_getptr(inst, v)?,
-        ValueKind::GetElemPtr(v) => self.eval_getelemptr(inst, v)?,
-        ValueKind::Binary(v) => self.eval_binary(inst, v),
-        ValueKind::Call(v) => self.eval_call(inst, v)?,
-        ValueKind::Branch(v) => return self.eval_branch(v),
-        ValueKind::Jump(v) => return self.eval_jump(v),
-        ValueKind::Return(v) => return Ok(self.eval_return(v)),
-        _ => panic!("invalid instruction"),
+  /// Offsets the given pointer by `offset` elements of size `base_size`.
+  fn get_pointer(&self, inst: Value, src: Val, offset: isize, base_size: usize) -> Result<Val> {
+    let bytes = offset * base_size as isize;
+    match src {
+      Val::Pointer(Some(ptr)) => self
+        .memory
+        .offset(ptr, bytes)
+        .map(|p| Val::Pointer(Some(p)))
+        .map_err(|e| self.runtime_error(inst, e)),
+      Val::Pointer(None) => {
+        Err(self.runtime_error(inst, "pointer calculation on null pointer".into()))
+      }
+      Val::UnsafePointer(ptr) => {
+        Ok(Val::UnsafePointer(ptr.map(|p| unsafe {
+          NonNull::new_unchecked((p.as_ptr() as isize + bytes) as *mut ())
+        })))
       }
+      _ => panic!("invalid pointer"),
     }
-    unreachable!()
   }
 
-  fn eval_alloc(&mut self, inst: &ValueData) {
+  fn eval_alloc(&mut self, handle: Value, inst: &ValueData) {
     let base = match inst.ty().kind() {
       TypeKind::Pointer(base) => base,
       _ => panic!("invalid pointer type"),
     };
-    let env = self.envs.last_mut().unwrap();
-    env.allocs.push(Box::new(Self::new_zeroinit(base)));
-    env
-      .vals
-      .insert(inst, Val::new_val_pointer(env.allocs.last()));
+    let desc = format!(
+      "`{}` in function '{}'",
+      func!(self).dfg().display_value(handle),
+      func!(self).name()
+    );
+    let ptr = self.memory.alloc(desc, base, Self::new_zeroinit(base));
+    self.frames.last_mut().unwrap().allocs.push(ptr.alloc);
+    self.insert_val(handle, Val::Pointer(Some(ptr)));
   }
 
-  fn eval_load(&mut self, inst: &ValueData, load: &Load) -> Result<()> {
+  fn eval_load(&mut self, handle: Value, inst: &ValueData, load: &Load) -> Result<()> {
     let val = match self.eval_value(load.src()) {
-      Val::Pointer { ptr, .. } => ptr.map(|p| unsafe { p.as_ref().clone() }),
+      Val::Pointer(Some(ptr)) => Some(
+        self
+          .memory
+          .load(ptr, inst.ty())
+          .map_err(|e| self.runtime_error(handle, e))?,
+      ),
+      Val::Pointer(None) => None,
       Val::UnsafePointer(ptr) => Val::load_from_unsafe_ptr(ptr, inst.ty()),
       _ => panic!("invalid pointer"),
     }
-    .ok_or_else(|| new_error("accessing to null pointer"))?;
-    self.insert_val(inst, val);
+    .ok_or_else(|| self.runtime_error(handle, "accessing to null pointer".into()))?;
+    self.insert_val(handle, val);
     Ok(())
   }
 
-  fn eval_store(&self, store: &Store) -> Result<()> {
+  fn eval_store(&mut self, handle: Value, store: &Store) -> Result<()> {
     let val = self.eval_value(store.value());
+    let ty = self.value_ty(store.value());
     match self.eval_value(store.dest()) {
-      Val::Pointer { ptr, .. } => ptr
-        .map(|p| unsafe { *p.as_ptr() = val })
-        .ok_or_else(|| new_error("accessing to null pointer")),
-      Val::UnsafePointer(ptr) => val.store_to_unsafe_ptr(ptr, value!(self, store.value()).ty()),
+      Val::Pointer(Some(ptr)) => self.memory.store(ptr, &ty, val),
+      Val::Pointer(None) => Err("accessing to null pointer".into()),
+      Val::UnsafePointer(ptr) => return val.store_to_unsafe_ptr(ptr, &ty),
       _ => panic!("invalid pointer"),
     }
+    .map_err(|e| self.runtime_error(handle, e))
   }
 
-  fn eval_getptr(&mut self, inst: &ValueData, gp: &GetPtr) -> Result<()> {
+  fn eval_getptr(&mut self, handle: Value, inst: &ValueData, gp: &GetPtr) -> Result<()> {
     // evaluate on index (offset)
     let offset = match self.eval_value(gp.index()) {
       Val::Int(i) => i as isize,
@@ -268,39 +573,30 @@ impl<'a> InterpreterImpl<'a> {
       TypeKind::Pointer(base) => base.size(),
       _ => panic!("invalid pointer"),
     };
-    let ptr = Self::get_pointer(self.eval_value(gp.src()), offset, base_size)?;
-    self.insert_val(inst, ptr);
+    let ptr = self.get_pointer(handle, self.eval_value(gp.src()), offset, base_size)?;
+    self.insert_val(handle, ptr);
     Ok(())
   }
 
-  fn eval_getelemptr(&mut self, inst: &ValueData, gep: &GetElemPtr) -> Result<()> {
+  fn eval_getelemptr(&mut self, handle: Value, inst: &ValueData, gep: &GetElemPtr) -> Result<()> {
     // evaluate on index (offset)
     let offset = match self.eval_value(gep.index()) {
       Val::Int(i) => i as isize,
       _ => panic!("invalid index"),
     };
-    // perform pointer calculation
+    // perform pointer calculation, elements of the array are laid out
+    // from the start of the array, so this is the same as `getptr`
+    // except for the element size
     let base_size = match inst.ty().kind() {
       TypeKind::Pointer(base) => base.size(),
       _ => panic!("invalid pointer"),
     };
-    let ptr = match self.eval_value(gep.src()) {
-      Val::Pointer { ptr, .. } => ptr
-        .map(|p| match unsafe { p.as_ref() } {
-          Val::Array(arr) => Self::get_pointer(Val::new_array_pointer(arr), offset, base_size),
-          _ => panic!("invalid array"),
-        })
-        .ok_or_else(|| new_error("accessing to null pointer"))??,
-      Val::UnsafePointer(ptr) => Val::UnsafePointer(ptr.map(|p| unsafe {
-        NonNull::new_unchecked((p.as_ptr() as isize + base_size as isize * offset) as *mut ())
-      })),
-      _ => panic!("invalid pointer"),
-    };
-    self.insert_val(inst, ptr);
+    let ptr = self.get_pointer(handle, self.eval_value(gep.src()), offset, base_size)?;
+    self.insert_val(handle, ptr);
     Ok(())
   }
 
-  fn eval_binary(&mut self, inst: &ValueData, bin: &Binary) {
+  fn eval_binary(&mut self, inst: Value, bin: &Binary) -> Result<()> {
     // evaluate lhs & rhs
     let lhs = self.eval_value(bin.lhs());
     let rhs = self.eval_value(bin.rhs());
@@ -308,143 +604,141 @@ impl<'a> InterpreterImpl<'a> {
       (Val::Int(lv), Val::Int(rv)) => (lv, rv),
       _ => panic!("invalid lhs or rhs"),
     };
-    // perform binary operation
-    let ans = match bin.op() {
-      BinaryOp::NotEq => (lv != rv) as i32,
-      BinaryOp::Eq => (lv == rv) as i32,
-      BinaryOp::Gt => (lv > rv) as i32,
-      BinaryOp::Lt => (lv < rv) as i32,
-      BinaryOp::Ge => (lv >= rv) as i32,
-      BinaryOp::Le => (lv <= rv) as i32,
-      BinaryOp::Add => lv + rv,
-      BinaryOp::Sub => lv - rv,
-      BinaryOp::Mul => lv * rv,
-      BinaryOp::Div => lv / rv,
-      BinaryOp::Mod => lv % rv,
-      BinaryOp::And => lv & rv,
-      BinaryOp::Or => lv | rv,
-      BinaryOp::Xor => lv ^ rv,
-      BinaryOp::Shl => lv << rv,
-      BinaryOp::Shr => ((lv as u32) >> rv) as i32,
-      BinaryOp::Sar => lv >> rv,
+    // perform binary operation with the overflow semantics of the function,
+    // which is the same as constant folding
+    let op = bin.op();
+    let ans = match op.eval_with(lv, rv, func!(self).dfg().overflow_mode()) {
+      Some(ans) => ans,
+      None if matches!(op, BinaryOp::Div | BinaryOp::Mod) && rv == 0 => {
+        return Err(self.runtime_error(inst, "division by zero".into()))
+      }
+      None => {
+        return Err(self.runtime_error_of(
+          RuntimeErrorKind::Overflow,
+          inst,
+          format!("integer overflow in `{op} {lv}, {rv}`"),
+        ))
+      }
     };
     self.insert_val(inst, Val::Int(ans));
+    Ok(())
   }
 
-  fn eval_call(&mut self, inst: &ValueData, call: &Call) -> Result<()> {
+  fn eval_call(&mut self, inst: Value, call: &Call) -> Result<()> {
     // evaluate arguments
-    let args = call.args().iter().map(|u| self.eval_value(*u)).collect();
-    // perform function call
-    let ret = self.eval_func(self.program.func(call.callee()), args)?;
-    self.insert_val(inst, ret);
+    let args: Vec<_> = call.args().iter().map(|u| self.eval_value(*u)).collect();
+    // check parameter count
+    let callee = self.program.func(call.callee());
+    let param_len = match callee.ty().kind() {
+      TypeKind::Function(params, _) => params.len(),
+      _ => panic!("invalid function"),
+    };
+    assert_eq!(param_len, args.len(), "parameter count mismatch");
+    // check if is a function declaration
+    if callee.layout().entry_bb().is_some() {
+      self.push_frame(callee, args);
+    } else {
+      // call the external function
+      let ret = unsafe { self.ext_funcs.call(callee, args) }?;
+      self.insert_val(inst, ret);
+      self.advance();
+    }
     Ok(())
   }
 
-  fn eval_branch(&mut self, br: &Branch) -> Result<Val> {
+  fn eval_branch(&mut self, br: &Branch) {
     // evaluate on condition
     let cond = self.eval_value(br.cond());
     // perform branching
     if cond.as_bool() {
-      self.update_bb_params(br.true_bb(), br.true_args());
-      self.eval_bb(bb_node!(self, br.true_bb()))
+      self.enter_bb(br.true_bb(), br.true_args());
     } else {
-      self.update_bb_params(br.false_bb(), br.false_args());
-      self.eval_bb(bb_node!(self, br.false_bb()))
+      self.enter_bb(br.false_bb(), br.false_args());
     }
   }
 
-  fn eval_jump(&mut self, jump: &Jump) -> Result<Val> {
-    self.update_bb_params(jump.target(), jump.args());
-    self.eval_bb(bb_node!(self, jump.target()))
+  fn eval_jump(&mut self, jump: &Jump) {
+    self.enter_bb(jump.target(), jump.args());
   }
 
-  fn eval_return(&self, ret: &Return) -> Val {
-    ret.value().map_or(Val::Undef, |v| self.eval_value(v))
+  fn eval_return(&mut self, ret: &Return) -> Result<Option<i32>> {
+    let val = ret.value().map_or(Val::Undef, |v| self.eval_value(v));
+    let frame = self.frames.pop().unwrap();
+    for hook in &mut self.hooks {
+      hook.on_ret(&frame, &val);
+    }
+    // stack allocations die when the function returns
+    for alloc in frame.allocs {
+      self.memory.free(alloc);
+    }
+    if self.frames.is_empty() {
+      return match val {
+        Val::Int(i) => Ok(Some(i)),
+        _ => Err(new_error("function '@main' must return an integer")),
+      };
+    }
+    // return to the call instruction of the caller
+    let call = self.frames.last().unwrap().inst;
+    self.insert_val(call, val);
+    self.advance();
+    Ok(None)
   }
 
   fn eval_value(&self, value: Value) -> Val {
     if value.is_global() {
-      let value = self.program.borrow_value(value);
-      assert!(!value.kind().is_const());
-      let v: *const ValueData = &value as &ValueData;
-      self.vars.get(&v).unwrap().clone()
+      assert!(!self.program.borrow_value(value).kind().is_const());
+      self.vars[&value].clone()
     } else {
-      let value = value!(self, value);
-      if value.kind().is_const() {
-        self.eval_local_const(value)
+      let data = value!(self, value);
+      if data.kind().is_const() {
+        self.eval_local_const(data)
       } else {
-        let v: *const ValueData = value;
-        self
-          .vars
-          .get(&v)
-          .or_else(|| self.envs.last().unwrap().vals.get(&v))
-          .unwrap()
-          .clone()
+        self.frames.last().unwrap().vals[&value].clone()
       }
     }
   }
 
-  fn insert_val(&mut self, inst: &ValueData, val: Val) {
-    self.envs.last_mut().unwrap().vals.insert(inst, val);
-  }
-
-  fn update_bb_params(&mut self, bb: BasicBlock, args: &[Value]) {
-    let bb = func!(self).dfg().bb(bb);
-    for (param, arg) in bb.params().iter().zip(args.iter()) {
-      self.insert_val(func!(self).dfg().value(*param), self.eval_value(*arg));
+  fn value_ty(&self, value: Value) -> Type {
+    if value.is_global() {
+      self.program.borrow_value(value).ty().clone()
+    } else {
+      value!(self, value).ty().clone()
     }
   }
-}
 
-struct Environment<'a> {
-  func: &'a FunctionData,
-  allocs: Vec<Box<Val>>,
-  vals: HashMap<*const ValueData, Val>,
-}
+  fn insert_val(&mut self, value: Value, val: Val) {
+    self.frames.last_mut().unwrap().vals.insert(value, val);
+  }
 
-impl<'a> Environment<'a> {
-  fn new(func: &'a FunctionData, vals: HashMap<*const ValueData, Val>) -> Self {
-    Self {
-      func,
-      allocs: Vec::new(),
-      vals,
+  /// Passes the given arguments to the parameters of the given basic
+  /// block, and moves to the first instruction of it.
+  fn enter_bb(&mut self, bb: BasicBlock, args: &[Value]) {
+    // evaluate all arguments before updating any parameters,
+    // since arguments may be the parameters themselves
+    let args: Vec<_> = args.iter().map(|a| self.eval_value(*a)).collect();
+    let func = func!(self);
+    for (param, arg) in func.dfg().bb(bb).params().iter().zip(args) {
+      self.insert_val(*param, arg);
     }
+    let inst = self.first_inst(func, bb);
+    let frame = self.frames.last_mut().unwrap();
+    frame.bb = bb;
+    frame.inst = inst;
   }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Val {
   Undef,
   Int(i32),
   Array(Box<[Val]>),
-  Pointer {
-    ptr: Option<NonNull<Val>>,
-    // if the pointer points to an array element,
-    // the index and the total length of the parent array are recorded
-    // in case of an out-of-bounds access
-    index: usize,
-    len: usize,
-  },
+  /// Pointer to the memory of the interpreter, or null pointer.
+  Pointer(Option<Pointer>),
+  /// Pointer returned by external functions.
   UnsafePointer(Option<NonNull<()>>),
 }
 
 impl Val {
-  fn new_val_pointer(parent: Option<&Box<Val>>) -> Self {
-    Self::Pointer {
-      ptr: parent.map(|p| unsafe { NonNull::new_unchecked(p.as_ref() as *const Val as *mut Val) }),
-      index: 0,
-      len: 0,
-    }
-  }
-
-  fn new_array_pointer(arr: &Box<[Val]>) -> Self {
-    Self::Pointer {
-      ptr: Some(unsafe { NonNull::new_unchecked(arr.as_ptr() as *mut Val) }),
-      index: 0,
-      len: arr.len(),
-    }
-  }
-
   fn load_from_unsafe_ptr(ptr: Option<NonNull<()>>, ty: &Type) -> Option<Self> {
     ptr.map(|p| match ty.kind() {
       TypeKind::Int32 => Val::Int(unsafe { *(p.as_ptr() as *const i32) }),
@@ -503,3 +797,967 @@ impl Val {
       .ok_or_else(|| new_error("accessing to null pointer"))?
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use koopa::back::Generator;
+  use koopa::front::Driver;
+  use koopa::ir::builder::Folded;
+  use koopa::ir::builder_traits::*;
+  use koopa::ir::OverflowMode;
+  use koopa::opt::{extract_region, flatten_operands, InductionVarSimplify};
+  use koopa::opt::{materialize_constants, MaterializeConfig};
+  use koopa::opt::{AlgebraicSimplify, FunctionSpecialization, LoopUnroll, Pass, PassManager};
+  use koopa::opt::{BoundsCheck, CounterStyle, ProfileInstrument};
+  use koopa::text::print_program;
+  use std::cell::{Cell, RefCell};
+  use std::io::sink;
+  use std::rc::Rc;
+
+  fn run(src: &str, mode: Mode) -> Result<i32> {
+    run_program(&parse(src), mode)
+  }
+
+  fn run_program(program: &Program, mode: Mode) -> Result<i32> {
+    Generator::with_visitor(sink(), Interpreter::new(Vec::new(), mode)).generate_on(program)
+  }
+
+  fn parse(src: &str) -> Program {
+    let driver: Driver<_> = src.into();
+    driver.generate_program().unwrap()
+  }
+
+  /// Returns the instruction with the given name in the given function.
+  fn inst(program: &Program, func: &str, name: &str) -> Value {
+    let func = program.funcs().values().find(|f| f.name() == func).unwrap();
+    let (inst, _) = func
+      .dfg()
+      .values()
+      .iter()
+      .find(|(_, d)| d.name().as_deref() == Some(name))
+      .unwrap();
+    *inst
+  }
+
+  fn paused(exit: Result<Exit>) -> Paused {
+    match exit.unwrap() {
+      Exit::Paused(paused) => paused,
+      Exit::Finished(_) => panic!("expected a paused execution"),
+    }
+  }
+
+  fn runtime_error(src: &str, mode: Mode) -> RuntimeError {
+    let error = run(src, mode).unwrap_err();
+    let error = error.into_inner().unwrap().downcast::<RuntimeError>();
+    *error.unwrap()
+  }
+
+  #[test]
+  fn use_after_return() {
+    let src = r#"
+      fun @leak(): *i32 {
+      %entry:
+        %x = alloc i32
+        store 1, %x
+        ret %x
+      }
+
+      fun @main(): i32 {
+      %entry:
+        %p = call @leak()
+        %v = load %p
+        ret %v
+      }
+    "#;
+    let error = runtime_error(src, Mode::Relaxed);
+    assert_eq!(error.func, "@main");
+    assert_eq!(error.inst, "%v = load %p");
+    assert!(error
+      .message
+      .contains("dangling pointer to `%x = alloc i32`"));
+  }
+
+  #[test]
+  fn return_pointer() {
+    let src = r#"
+      global @g = alloc i32, 5
+
+      fun @pick(@a: *i32, @c: i32): *i32 {
+      %entry:
+        br @c, %local, %global
+
+      %local:
+        ret @a
+
+      %global:
+        ret @g
+      }
+
+      fun @main(): i32 {
+      %entry:
+        %a = alloc [i32, 2]
+        %a1 = getelemptr %a, 1
+        %p = call @pick(%a1, 1)
+        store 7, %p
+        %q = call @pick(%a1, 0)
+        %v = load %q
+        %w = load %a1
+        %r = add %v, %w
+        ret %r
+      }
+    "#;
+    assert_eq!(run(src, Mode::Strict).unwrap(), 12);
+  }
+
+  #[test]
+  fn oob_store() {
+    let src = r#"
+      global @g = alloc [i32, 4], zeroinit
+
+      fun @main(): i32 {
+      %entry:
+        %p = getelemptr @g, 3
+        store 3, %p
+        %end = getptr %p, 1
+        store 4, %end
+        ret 0
+      }
+    "#;
+    // pointers one past the end are valid, but can not be accessed
+    let error = runtime_error(src, Mode::Strict);
+    assert_eq!(error.inst, "store 4, %end");
+    assert_eq!(
+      error.message,
+      "store of 4 bytes at offset 16 out of bounds of global '@g' (16 bytes)"
+    );
+  }
+
+  #[test]
+  fn negative_gep_index() {
+    let src = r#"
+      fun @main(): i32 {
+      %entry:
+        %arr = alloc [[i32, 2], 2]
+        %row = getelemptr %arr, 1
+        %elem = getelemptr %row, -1
+        store 42, %elem
+        %before = getelemptr %arr, -1
+        %back = getptr %before, 1
+        %ptr = getelemptr %back, 1
+        %val = load %ptr
+        ret %val
+      }
+    "#;
+    // `%elem` is still in the allocation, but `%before` is not
+    let error = runtime_error(src, Mode::Strict);
+    assert_eq!(error.inst, "%before = getelemptr %arr, -1");
+    assert!(error
+      .message
+      .starts_with("pointer calculation out of bounds"));
+    // moving `%before` back makes it valid in relaxed mode
+    assert_eq!(run(src, Mode::Relaxed).unwrap(), 42);
+    let src = src.replace("%ptr = getelemptr %back, 1", "%ptr = getelemptr %before, 1");
+    let error = runtime_error(&src, Mode::Relaxed);
+    assert_eq!(error.inst, "%val = load %ptr");
+  }
+
+  #[test]
+  fn overflow_modes() {
+    let src = r#"
+      fun @main(): i32 {
+      %entry:
+        %r = add 2147483647, 1
+        ret %r
+      }
+    "#;
+    for mode in [
+      OverflowMode::Wrapping,
+      OverflowMode::Saturating,
+      OverflowMode::Trap,
+    ] {
+      let mut program = parse(src);
+      let main = program.func_layout()[0];
+      let dfg = program.func_mut(main).dfg_mut();
+      dfg.set_overflow_mode(mode);
+      // fold the same operation in the same mode
+      let max = dfg.new_value().integer(i32::MAX);
+      let one = dfg.new_value().integer(1);
+      let folded = match dfg.new_value().binary_folded(BinaryOp::Add, max, one) {
+        Folded::Value(v) => dfg.value(v).kind().as_integer(),
+        Folded::Inst(_) => None,
+      };
+      let result = run_program(&program, Mode::Strict);
+      match mode {
+        OverflowMode::Wrapping => assert_eq!(folded, Some(i32::MIN)),
+        OverflowMode::Saturating => assert_eq!(folded, Some(i32::MAX)),
+        OverflowMode::Trap => assert_eq!(folded, None),
+      }
+      match folded {
+        Some(ans) => assert_eq!(result.unwrap(), ans),
+        None => {
+          let error = result.unwrap_err().into_inner().unwrap();
+          let error = error.downcast::<RuntimeError>().unwrap();
+          let r = inst(&program, "@main", "%r");
+          assert_eq!(error.kind, RuntimeErrorKind::Overflow);
+          assert_eq!(error.value, r);
+          assert_eq!(error.inst, "%r = add 2147483647, 1");
+          assert_eq!(error.span, program.func(main).dfg().value(r).span());
+          assert!(error.span.is_some());
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn zero_tail_init() {
+    let src = |global: &str, local: &str| {
+      format!(
+        r#"
+        global @g = alloc [[i32, 4], 3], {global}
+
+        fun @main(): i32 {{
+        %entry:
+          %arr = alloc [i32, 3]
+          store {local}, %arr
+          %p0 = getelemptr %arr, 0
+          %v0 = load %p0
+          %p2 = getelemptr %arr, 2
+          %v2 = load %p2
+          %row = getelemptr @g, 1
+          %p10 = getelemptr %row, 0
+          %v10 = load %p10
+          %p12 = getelemptr %row, 2
+          %v12 = load %p12
+          %a = mul %v0, 100
+          %b = mul %v10, 10
+          %c = add %v2, %v12
+          %d = add %a, %b
+          %r = add %d, %c
+          ret %r
+        }}
+      "#
+      )
+    };
+    let compact = src(
+      "{{1, 2, zeroinit}, {3, zeroinit}, zeroinit}",
+      "{5, zeroinit}",
+    );
+    let verbose = src("{{1, 2, 0, 0}, {3, 0, 0, 0}, {0, 0, 0, 0}}", "{5, 0, 0}");
+    assert_eq!(run(&compact, Mode::Strict).unwrap(), 530);
+    assert_eq!(run(&verbose, Mode::Strict).unwrap(), 530);
+  }
+
+  #[test]
+  fn flatten_stores() {
+    let src = r#"
+      global @g = alloc [[i32, 3], 2], zeroinit
+
+      fun @main(): i32 {
+      %entry:
+        %arr = alloc [[i32, 3], 2]
+        store {{1, 2, 3}, {4, 5, 6}}, %arr
+        %row = getelemptr %arr, 1
+        store {7, zeroinit}, %row
+        store {{8, 9, 10}, zeroinit}, @g
+        %p = getelemptr %row, 1
+        %a = load %p
+        %q = getelemptr %row, 2
+        %b = load %q
+        %r = getelemptr %arr, 0
+        %s = getelemptr %r, 2
+        %c = load %s
+        %t = getelemptr @g, 0
+        %u = getelemptr %t, 1
+        %d = load %u
+        %x = mul %a, 1000
+        %y = mul %b, 100
+        %z = mul %c, 10
+        %sum0 = add %x, %y
+        %sum1 = add %sum0, %z
+        %sum2 = add %sum1, %d
+        ret %sum2
+      }
+    "#;
+    let mut program = parse(src);
+    assert_eq!(run_program(&program, Mode::Strict).unwrap(), 39);
+    program.funcs_mut().values_mut().for_each(flatten_operands);
+    assert_eq!(run_program(&program, Mode::Strict).unwrap(), 39);
+  }
+
+  #[test]
+  fn materialize_large_constants() {
+    let src = r#"
+      global @g = alloc i32, 100000
+
+      fun @main(): i32 {
+      %entry:
+        %x = load @g
+        %c = lt %x, 100000
+        br %c, %small, %loop(0, %x)
+
+      %small:
+        ret 0
+
+      %loop(%i: i32, %acc: i32):
+        %a = add %acc, 70000
+        %b = mod %a, 100000
+        %i1 = add %i, 1
+        %c1 = lt %i1, 5000
+        br %c1, %loop(%i1, %b), %end(%b, 70000)
+
+      %end(%r: i32, %k: i32):
+        %s = sub %r, %k
+        store %s, @g
+        %t = load @g
+        ret %t
+      }
+    "#;
+    let expected = run(src, Mode::Strict).unwrap();
+    for across_bbs in [false, true] {
+      let mut program = parse(src);
+      let config = MaterializeConfig::new().with_across_bbs(across_bbs);
+      for func in program.funcs_mut().values_mut() {
+        assert!(materialize_constants(func, &config) > 0);
+      }
+      assert_eq!(run_program(&program, Mode::Strict).unwrap(), expected);
+    }
+  }
+
+  #[test]
+  fn simplify_same_results() {
+    // the functions in the tests of `AlgebraicSimplify`
+    let funcs = r#"
+      fun @div2(@x: i32): i32 {
+      %entry:
+        %0 = div @x, 2
+        ret %0
+      }
+
+      fun @div8(@x: i32): i32 {
+      %entry:
+        %0 = div @x, 8
+        ret %0
+      }
+
+      fun @mod4(@x: i32): i32 {
+      %entry:
+        %0 = mod @x, 4
+        ret %0
+      }
+
+      fun @mod1024(@x: i32): i32 {
+      %entry:
+        %0 = mod @x, 1024
+        ret %0
+      }
+
+      fun @div_neg(@x: i32): i32 {
+      %entry:
+        %0 = div @x, -4
+        %1 = mod @x, -4
+        %2 = add %0, %1
+        ret %2
+      }
+
+      fun @mul(@x: i32): i32 {
+      %entry:
+        %0 = mul @x, 16
+        %1 = mul -2147483648, @x
+        %2 = mul @x, 0
+        %3 = mul %2, @x
+        %4 = add %0, %1
+        %5 = add %4, %3
+        ret %5
+      }
+
+      fun @misc(@x: i32): i32 {
+      %entry:
+        %0 = sub @x, @x
+        %1 = div @x, 1
+        %2 = mod @x, 1
+        %3 = add %0, %1
+        %4 = add %3, %2
+        %5 = div %4, 1073741824
+        ret %5
+      }
+    "#;
+    // hashes the results of `@F` on the extreme values and `-1100..=1100`
+    let main = r#"
+      fun @check(@x: i32, @h: i32): i32 {
+      %entry:
+        %r = call @F(@x)
+        %m = mul @h, 31
+        %h1 = add %m, %r
+        ret %h1
+      }
+
+      fun @main(): i32 {
+      %entry:
+        %h0 = call @check(-2147483648, 0)
+        %h1 = call @check(-2147483647, %h0)
+        %h2 = call @check(2147483647, %h1)
+        %h3 = call @check(2147483646, %h2)
+        jump %loop(-1100, %h3)
+
+      %loop(%x: i32, %h: i32):
+        %h4 = call @check(%x, %h)
+        %x1 = add %x, 1
+        %c = le %x1, 1100
+        br %c, %loop(%x1, %h4), %end
+
+      %end:
+        ret %h4
+      }
+    "#;
+    let names = [
+      "@div2", "@div8", "@mod4", "@mod1024", "@div_neg", "@mul", "@misc",
+    ];
+    for name in names {
+      let src = format!("{funcs}{}", main.replace("@F", name));
+      let expected = run(&src, Mode::Strict).unwrap();
+      let mut program = parse(&src);
+      let func = program.func_layout()[names.iter().position(|n| *n == name).unwrap()];
+      // divisions by negative constants are kept
+      let count = AlgebraicSimplify::new().run(program.func_mut(func));
+      assert_eq!(count > 0, name != "@div_neg", "{name} is not rewritten");
+      let result = run_program(&program, Mode::Strict).unwrap();
+      assert_eq!(result, expected, "results of {name} differ");
+    }
+  }
+
+  /// Counts `0..3`, and visits `%then` for odd numbers.
+  const PROFILED: &str = r#"
+    fun @main(): i32 {
+    %entry:
+      jump %loop(0)
+
+    %loop(%i: i32):
+      %c = lt %i, 3
+      br %c, %body, %end
+
+    %body:
+      %odd = and %i, 1
+      br %odd, %then, %latch
+
+    %then:
+      jump %latch
+
+    %latch:
+      %i1 = add %i, 1
+      jump %loop(%i1)
+
+    %end:
+      ret %i
+    }
+  "#;
+
+  #[test]
+  fn profile_counter_array() {
+    let mut program = parse(PROFILED);
+    let pass = ProfileInstrument::with_style(CounterStyle::Array("@counters".into()));
+    let blocks = pass.run(&mut program);
+    // basic blocks are indexed in the layout order
+    let names: Vec<_> = blocks.iter().map(|b| b.bb.as_deref().unwrap()).collect();
+    assert_eq!(
+      names,
+      ["%entry", "%loop", "%body", "%end", "%then", "%latch"]
+    );
+    // pause before `ret` in `%end` to read the counters
+    let main = program.func(program.func_layout()[0]);
+    let (_, end) = main.layout().bbs().iter().nth(3).unwrap();
+    let ret = *end.insts().back_key().unwrap();
+    let mut interpreter = Interpreter::new(Vec::new(), Mode::Strict);
+    interpreter.set_breakpoints(Breakpoints::new().on_value(ret));
+    let paused = paused(interpreter.run(&program));
+    let counters = program.inst_layout()[0];
+    let counts = [1, 4, 3, 1, 1, 3].map(Val::Int);
+    assert_eq!(
+      paused.global_contents(counters),
+      Some(Val::Array(counts.into()))
+    );
+    assert!(matches!(paused.resume(), Ok(Exit::Finished(3))));
+  }
+
+  #[test]
+  fn profile_runtime_calls() {
+    struct Recorder(Rc<RefCell<Vec<i32>>>);
+
+    impl ExecHook for Recorder {
+      fn on_call(&mut self, frame: &Frame) {
+        if frame.func().name() == "@__koopa_profile" {
+          match frame.args()[..] {
+            [Val::Int(index)] => self.0.borrow_mut().push(*index),
+            _ => panic!("invalid arguments"),
+          }
+        }
+      }
+    }
+
+    let src = format!(
+      "{PROFILED}{}",
+      r#"
+      fun @__koopa_profile(@index: i32) {
+      %entry:
+        ret
+      }
+    "#
+    );
+    let mut program = parse(&src);
+    let pass = ProfileInstrument::with_style(CounterStyle::Call("@__koopa_profile".into()));
+    // the runtime function is not instrumented
+    assert_eq!(pass.run(&mut program).len(), 6);
+    let indices = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter = Interpreter::new(Vec::new(), Mode::Strict);
+    interpreter.add_hook(Box::new(Recorder(indices.clone())));
+    assert!(matches!(interpreter.run(&program), Ok(Exit::Finished(3))));
+    assert_eq!(*indices.borrow(), [0, 1, 2, 5, 1, 2, 4, 5, 1, 2, 5, 1, 3]);
+  }
+
+  /// Stores `i * i` to `@arr[i]` for `i` in `0..@n`, and returns the sum
+  /// of `@arr`.
+  fn array_sum(n: i32) -> String {
+    format!(
+      r#"
+      global @arr = alloc [i32, 4], zeroinit
+
+      fun @main(): i32 {{
+      %entry:
+        jump %fill(0)
+
+      %fill(%i: i32):
+        %c = lt %i, {n}
+        br %c, %store, %sum(0, 0)
+
+      %store:
+        %p = getelemptr @arr, %i
+        %sq = mul %i, %i
+        store %sq, %p
+        %i1 = add %i, 1
+        jump %fill(%i1)
+
+      %sum(%j: i32, %s: i32):
+        %d = lt %j, 4
+        br %d, %add, %end
+
+      %add:
+        %q = getelemptr @arr, %j
+        %v = load %q
+        %s1 = add %s, %v
+        %j1 = add %j, 1
+        jump %sum(%j1, %s1)
+
+      %end:
+        ret %s
+      }}
+    "#
+    )
+  }
+
+  #[test]
+  fn bounds_check_trap() {
+    struct Recorder(Rc<RefCell<Vec<(i32, i32)>>>);
+
+    impl ExecHook for Recorder {
+      fn on_call(&mut self, frame: &Frame) {
+        if frame.func().name() == "@__koopa_oob" {
+          match frame.args()[..] {
+            [Val::Int(index), Val::Int(len)] => self.0.borrow_mut().push((*index, *len)),
+            _ => panic!("invalid arguments"),
+          }
+        }
+      }
+    }
+
+    let handler = r#"
+      fun @__koopa_oob(@index: i32, @len: i32) {
+      %entry:
+        ret
+      }
+    "#;
+    // `@arr[4]` is stored in the 5th iteration
+    let mut program = parse(&format!("{handler}{}", array_sum(5)));
+    assert!(BoundsCheck::new().run(&mut program).is_empty());
+    let calls = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter = Interpreter::new(Vec::new(), Mode::Strict);
+    interpreter.add_hook(Box::new(Recorder(calls.clone())));
+    let error = interpreter.run(&program).err().unwrap();
+    let error = error
+      .into_inner()
+      .unwrap()
+      .downcast::<RuntimeError>()
+      .unwrap();
+    // the trap basic block executes `unreachable` after the handler
+    assert_eq!(error.func, "@main");
+    assert_eq!(error.message, "unreachable executed");
+    assert_eq!(*calls.borrow(), [(4, 4)]);
+  }
+
+  #[test]
+  fn bounds_check_in_bounds() {
+    let src = array_sum(4);
+    let expected = run(&src, Mode::Strict).unwrap();
+    assert_eq!(expected, 1 + 4 + 9);
+    let mut program = parse(&src);
+    assert!(BoundsCheck::new().run(&mut program).is_empty());
+    // both `getelemptr`s are checked, and the handler is declared
+    let main = program.funcs().values().find(|f| f.name() == "@main");
+    assert_eq!(main.unwrap().layout().bbs().len(), 6 + 2 + 1);
+    let handler = program
+      .funcs()
+      .values()
+      .find(|f| f.name() == "@__koopa_oob");
+    assert!(handler.unwrap().layout().entry_bb().is_none());
+    assert_eq!(run_program(&program, Mode::Strict).unwrap(), expected);
+  }
+
+  #[test]
+  fn specialize_pow() {
+    let src = r#"
+      fun @pow(@b: i32, @n: i32): i32 {
+      %entry:
+        jump %loop(0, 1)
+
+      %loop(%i: i32, %acc: i32):
+        %c = lt %i, @n
+        br %c, %body, %end
+
+      %body:
+        %acc1 = mul %acc, @b
+        %i1 = add %i, 1
+        jump %loop(%i1, %acc1)
+
+      %end:
+        ret %acc
+      }
+
+      fun @main(): i32 {
+      %entry:
+        %0 = call @pow(3, 2)
+        %1 = call @pow(%0, 2)
+        %2 = call @pow(2, 5)
+        %3 = add %1, %2
+        %4 = call @pow(%3, 2)
+        ret %4
+      }
+    "#;
+    let expected = run(src, Mode::Strict).unwrap();
+    let mut program = parse(src);
+    let mut passman = PassManager::new();
+    passman.register(Pass::Module(Box::new(FunctionSpecialization::new())));
+    passman.register(Pass::Function(Box::new(LoopUnroll::new())));
+    passman.register(Pass::Function(Box::new(AlgebraicSimplify::new())));
+    passman.run_passes(&mut program);
+    assert_eq!(program.func_layout().len(), 3);
+    assert_eq!(run_program(&program, Mode::Strict).unwrap(), expected);
+  }
+
+  #[test]
+  fn break_in_recursion() {
+    let program = parse(
+      r#"
+      fun @fact(@n: i32): i32 {
+      %entry:
+        %cond = le @n, 1
+        br %cond, %base, %rec
+
+      %base:
+        ret 1
+
+      %rec:
+        %m = sub @n, 1
+        %r = call @fact(%m)
+        %res = mul @n, %r
+        ret %res
+      }
+
+      fun @main(): i32 {
+      %entry:
+        %v = call @fact(5)
+        ret %v
+      }
+    "#,
+    );
+    let mut interpreter = Interpreter::new(Vec::new(), Mode::Strict);
+    interpreter.set_breakpoints(Breakpoints::new().on_function("@fact"));
+    let paused = self::paused(interpreter.run(&program));
+    assert_eq!(paused.reason(), PauseReason::Breakpoint);
+    assert_eq!(paused.frames().len(), 2);
+    let paused = self::paused(paused.resume());
+    // inspect the two frames of `@fact`
+    let frames = paused.frames();
+    assert_eq!(frames.len(), 3);
+    assert_eq!(frames[1].args(), [&Val::Int(5)]);
+    assert_eq!(frames[2].args(), [&Val::Int(4)]);
+    assert_eq!(frames[1].inst(), inst(&program, "@fact", "%r"));
+    assert_eq!(
+      frames[1].value(inst(&program, "@fact", "%m")),
+      Some(&Val::Int(4))
+    );
+    assert_eq!(frames[2].value(inst(&program, "@fact", "%m")), None);
+    // pauses at the entries of `@fact(3)`, `@fact(2)` and `@fact(1)`
+    let mut exit = paused.resume().unwrap();
+    let mut pauses = 0;
+    while let Exit::Paused(paused) = exit {
+      pauses += 1;
+      exit = paused.resume().unwrap();
+    }
+    assert_eq!(pauses, 3);
+    assert!(matches!(exit, Exit::Finished(120)));
+  }
+
+  const SUM: &str = r#"
+    fun @main(): i32 {
+    %entry:
+      %sum = alloc i32
+      store 0, %sum
+      jump %loop(0)
+
+    %loop(%i: i32):
+      %s = load %sum
+      %s1 = add %s, %i
+      store %s1, %sum
+      %i1 = add %i, 1
+      %c = lt %i1, 4
+      br %c, %loop(%i1), %end
+
+    %end:
+      %r = load %sum
+      ret %r
+    }
+  "#;
+
+  #[test]
+  fn inspect_locals() {
+    let program = parse(SUM);
+    let sum = inst(&program, "@main", "%sum");
+    let mut interpreter = Interpreter::new(Vec::new(), Mode::Strict);
+    let bp = Breakpoints::new().on_value(inst(&program, "@main", "%i1"));
+    interpreter.set_breakpoints(bp);
+    let mut exit = interpreter.run(&program).unwrap();
+    let mut partial_sums = Vec::new();
+    while let Exit::Paused(paused) = exit {
+      let frame = paused.frame();
+      let i = frame.bb_args()[0].clone();
+      partial_sums.push((i, paused.alloc_contents(frame, sum).unwrap()));
+      exit = paused.resume().unwrap();
+    }
+    let expected: Vec<_> = [(0, 0), (1, 1), (2, 3), (3, 6)]
+      .into_iter()
+      .map(|(i, s)| (Val::Int(i), Val::Int(s)))
+      .collect();
+    assert_eq!(partial_sums, expected);
+    assert!(matches!(exit, Exit::Finished(6)));
+  }
+
+  #[derive(Default)]
+  struct Counter {
+    insts: Rc<Cell<usize>>,
+    calls: Rc<Cell<usize>>,
+    rets: Rc<Cell<usize>>,
+  }
+
+  impl ExecHook for Counter {
+    fn before_inst(&mut self, _: &Frame, _: Value, _: &ValueData) {
+      self.insts.set(self.insts.get() + 1);
+    }
+
+    fn on_call(&mut self, _: &Frame) {
+      self.calls.set(self.calls.get() + 1);
+    }
+
+    fn on_ret(&mut self, _: &Frame, ret: &Val) {
+      assert_eq!(ret, &Val::Int(6));
+      self.rets.set(self.rets.get() + 1);
+    }
+  }
+
+  #[test]
+  fn step_limit_and_hooks() {
+    let program = parse(SUM);
+    let counter = Counter::default();
+    let (insts, calls, rets) = (
+      counter.insts.clone(),
+      counter.calls.clone(),
+      counter.rets.clone(),
+    );
+    let mut interpreter = Interpreter::new(Vec::new(), Mode::Strict);
+    interpreter.add_hook(Box::new(counter));
+    // `alloc`, `store` and `jump` are executed
+    let paused = self::paused(interpreter.run_with_limit(&program, 3));
+    assert_eq!(paused.reason(), PauseReason::StepLimit);
+    assert_eq!(paused.frame().inst(), inst(&program, "@main", "%s"));
+    assert_eq!(paused.frame().bb_args(), [&Val::Int(0)]);
+    assert_eq!(insts.get(), 3);
+    let paused = self::paused(paused.resume_with_limit(6));
+    assert_eq!(paused.frame().bb_args(), [&Val::Int(1)]);
+    assert!(matches!(paused.resume(), Ok(Exit::Finished(6))));
+    assert_eq!((insts.get(), calls.get(), rets.get()), (29, 1, 1));
+  }
+
+  /// Runs the induction variable simplification pass on the given
+  /// program, returns the number of rewritten induction variables and
+  /// the return value of the optimized program.
+  fn run_indvars(src: &str, always: bool) -> (usize, i32) {
+    let mut program = parse(src);
+    let expected = run_program(&program, Mode::Strict).unwrap();
+    let pass = InductionVarSimplify::new().with_always(always);
+    let count = program.funcs_mut().values_mut().map(|f| pass.run(f)).sum();
+    let result = run_program(&program, Mode::Strict).unwrap();
+    assert_eq!(result, expected);
+    (count, result)
+  }
+
+  #[test]
+  fn indvars_sum_array() {
+    let src = r#"
+      global @arr = alloc [i32, 8], {1, 2, 3, 4, 5, 6, 7, 8}
+
+      // sums `@arr[2 * i]` for `i` in `@start..4`
+      fun @sum_even(@start: i32): i32 {
+      %entry:
+        %base = getelemptr @arr, 0
+        %c0 = lt @start, 4
+        br %c0, %loop(@start, 0), %end(0)
+
+      %loop(%i: i32, %s: i32):
+        %j = mul %i, 2
+        %p = getptr %base, %j
+        %v = load %p
+        %s1 = add %s, %v
+        %i1 = add %i, 1
+        %c = lt %i1, 4
+        br %c, %loop(%i1, %s1), %end(%s1)
+
+      %end(%r: i32):
+        ret %r
+      }
+
+      // sums `@arr[i]` for `i` in `@start..8` by a top-tested loop
+      fun @sum(@start: i32): i32 {
+      %entry:
+        jump %cond(@start, 0)
+
+      %cond(%i: i32, %s: i32):
+        %c = lt %i, 8
+        br %c, %body, %end
+
+      %body:
+        %p = getelemptr @arr, %i
+        %v = load %p
+        %s1 = add %s, %v
+        %i1 = add %i, 1
+        jump %cond(%i1, %s1)
+
+      %end:
+        ret %s
+      }
+
+      fun @main(): i32 {
+      %entry:
+        %a = call @sum_even(1)
+        %b = call @sum(3)
+        %c = call @sum(8)
+        %d = mul %a, 100
+        %e = add %d, %b
+        %f = add %e, %c
+        ret %f
+      }
+    "#;
+    // `%j` and `%p` in `@sum_even`, `%p` in `@sum`
+    assert_eq!(
+      run_indvars(src, false),
+      (3, (3 + 5 + 7) * 100 + (4 + 5 + 6 + 7 + 8))
+    );
+  }
+
+  #[test]
+  fn indvars_bail_out() {
+    let src = r#"
+      global @arr = alloc [i32, 8], {1, 2, 3, 4, 5, 6, 7, 8}
+
+      fun @main(): i32 {
+      %entry:
+        jump %loop(0, 0)
+
+      // two back edges, one of them skips an element
+      %loop(%i: i32, %s: i32):
+        %p = getelemptr @arr, %i
+        %v = load %p
+        %s1 = add %s, %v
+        %i1 = add %i, 1
+        %c = lt %i1, 8
+        br %c, %next, %end
+
+      %next:
+        %odd = and %i1, 1
+        br %odd, %skip, %loop(%i1, %s1)
+
+      %skip:
+        %i2 = add %i1, 1
+        %c2 = lt %i2, 8
+        br %c2, %loop(%i2, %s1), %end
+
+      %end:
+        ret %s1
+      }
+    "#;
+    assert_eq!(run_indvars(src, true), (0, 1 + 3 + 5 + 7));
+  }
+
+  #[test]
+  fn outline_loop_body() {
+    let src = r#"
+      // sums `i` for even `i`, and `i * i` for odd `i` in `0..10`
+      fun @main(): i32 {
+      %entry:
+        %sum = alloc i32
+        store 0, %sum
+        jump %header(0)
+
+      %header(%i: i32):
+        %c = lt %i, 10
+        br %c, %body, %end
+
+      %body:
+        %odd = and %i, 1
+        br %odd, %then, %latch(%i)
+
+      %then:
+        %sq = mul %i, %i
+        jump %latch(%sq)
+
+      %latch(%x: i32):
+        %s = load %sum
+        %s1 = add %s, %x
+        store %s1, %sum
+        %i1 = add %i, 1
+        jump %header(%i1)
+
+      %end:
+        %r = load %sum
+        ret %r
+      }
+    "#;
+    let mut program = parse(src);
+    let expected = run_program(&program, Mode::Strict).unwrap();
+    assert_eq!(expected, (2 + 4 + 6 + 8) + (1 + 9 + 25 + 49 + 81));
+    let main = program.func_layout()[0];
+    let data = program.func(main);
+    let body: Vec<_> = (data.layout().bbs().keys().copied())
+      .filter(|bb| {
+        let name = data.dfg().bb(*bb).name().as_deref().unwrap();
+        ["%body", "%then", "%latch"].contains(&name)
+      })
+      .collect();
+    let body = extract_region(&mut program, main, &body, "@body").unwrap();
+    // the loop body takes `%i` and `%sum`, and returns the next `%i`
+    assert_eq!(program.func(body).params().len(), 2);
+    assert_eq!(program.func(main).layout().bbs().len(), 4);
+    assert_eq!(run_program(&program, Mode::Strict).unwrap(), expected);
+    // the printed program can be parsed and run again
+    assert_eq!(program.func_layout(), [body, main]);
+    let text = print_program(&program);
+    assert_eq!(run(&text, Mode::Strict).unwrap(), expected);
+  }
+}
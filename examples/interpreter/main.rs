@@ -1,9 +1,13 @@
 mod ext_funcs;
 mod interpreter;
+mod memory;
 
-use interpreter::Interpreter;
+use interpreter::{ExecHook, Frame, Interpreter};
 use koopa::back::Generator;
 use koopa::front::Driver;
+use koopa::ir::entities::ValueData;
+use koopa::ir::Value;
+use memory::Mode;
 use std::io::{sink, stdin, Error};
 use std::{env, fmt, process, result};
 
@@ -16,7 +20,12 @@ fn main() {
 
 fn try_main() -> result::Result<i32, MainError> {
   // parse command line arguments
-  let CommandLineArgs { input, libs } = parse_cmd_args()?;
+  let CommandLineArgs {
+    input,
+    libs,
+    relaxed,
+    trace,
+  } = parse_cmd_args()?;
   // parse the input file
   let program = if let Some(file) = input {
     Driver::from_path(file)
@@ -27,7 +36,11 @@ fn try_main() -> result::Result<i32, MainError> {
   }
   .map_err(|_| MainError::ParseError)?;
   // interpret the program
-  let interpreter = Interpreter::new(libs);
+  let mode = if relaxed { Mode::Relaxed } else { Mode::Strict };
+  let mut interpreter = Interpreter::new(libs, mode);
+  if trace {
+    interpreter.add_hook(Box::new(Tracer));
+  }
   Generator::with_visitor(sink(), interpreter)
     .generate_on(&program)
     .map_err(MainError::OtherError)
@@ -45,10 +58,12 @@ impl fmt::Display for MainError {
     match self {
       MainError::InvalidArgs => write!(
         f,
-        r#"Usage: interpreter [FILE] [-l DYN_LIB ...]
+        r#"Usage: interpreter [FILE] [-l DYN_LIB ...] [--relaxed] [--trace]
 Options:
   FILE        use FILE as input instead of stdin
-  -l DYN_LIB  load dynamic library DYN_LIB"#
+  -l DYN_LIB  load dynamic library DYN_LIB
+  --relaxed   only check the bounds of memory accesses
+  --trace     print executed instructions to stderr"#
       ),
       MainError::InvalidFile(error) => write!(f, "invalid file operation: {}", error),
       MainError::ParseError => write!(f, "error occurred when parsing the input"),
@@ -61,25 +76,34 @@ Options:
 struct CommandLineArgs {
   input: Option<String>,
   libs: Vec<String>,
+  relaxed: bool,
+  trace: bool,
 }
 
 fn parse_cmd_args() -> result::Result<CommandLineArgs, MainError> {
   let mut cmd_args = CommandLineArgs::default();
   let mut args = env::args();
   args.next();
-  loop {
-    match (args.next(), args.next()) {
-      (Some(file), Some(o)) if file != "-l" && o == "-l" => {
-        cmd_args.input = Some(file);
-        cmd_args
-          .libs
-          .push(args.next().ok_or(MainError::InvalidArgs)?);
-      }
-      (Some(file), _) if file != "-l" => cmd_args.input = Some(file),
-      (Some(o), Some(lib)) if o == "-l" => cmd_args.libs.push(lib),
-      (None, None) => break,
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "-l" => cmd_args
+        .libs
+        .push(args.next().ok_or(MainError::InvalidArgs)?),
+      "--relaxed" => cmd_args.relaxed = true,
+      "--trace" => cmd_args.trace = true,
+      _ if cmd_args.input.is_none() => cmd_args.input = Some(arg),
       _ => return Err(MainError::InvalidArgs),
     }
   }
   Ok(cmd_args)
 }
+
+/// Prints every executed instruction to stderr.
+struct Tracer;
+
+impl ExecHook for Tracer {
+  fn before_inst(&mut self, frame: &Frame, inst: Value, _: &ValueData) {
+    let func = frame.func();
+    eprintln!("{}: {}", func.name(), func.dfg().display_value(inst));
+  }
+}
@@ -0,0 +1,207 @@
+//! Memory model of the interpreter.
+//!
+//! Every `alloc` and global `alloc` owns a distinct [`Allocation`],
+//! whose size is the size of the allocated type. Pointers to the
+//! allocations carry their provenance, i.e. the allocation ID and the
+//! byte offset in the allocation, so every memory access can be checked
+//! against the allocation it was derived from.
+
+use super::interpreter::Val;
+use koopa::ir::{Type, TypeKind};
+use std::collections::BTreeMap;
+
+/// Checking mode of the memory model.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+  /// Checks pointer calculations, and the bounds and the alignment of
+  /// memory accesses.
+  #[default]
+  Strict,
+  /// Only checks the bounds of memory accesses, for programs that
+  /// intentionally perform pointer tricks, like computing pointers out
+  /// of bounds and then moving them back.
+  Relaxed,
+}
+
+/// A pointer to an allocation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pointer {
+  /// ID of the allocation, which is the index in the allocation list.
+  pub alloc: usize,
+  /// Offset in bytes.
+  pub offset: isize,
+}
+
+/// An allocation, which stores scalar values at their byte offsets.
+struct Allocation {
+  /// Description of the allocation, used in error messages.
+  desc: String,
+  size: usize,
+  /// Map of offsets to the stored scalar values and their sizes.
+  cells: BTreeMap<usize, (Val, usize)>,
+  /// Set to `false` once the function of a stack allocation returns.
+  alive: bool,
+}
+
+/// The memory of the interpreter.
+///
+/// Methods return error messages without the context of instructions,
+/// which are added by the interpreter.
+pub struct Memory {
+  mode: Mode,
+  allocs: Vec<Allocation>,
+}
+
+impl Memory {
+  pub fn new(mode: Mode) -> Self {
+    Self {
+      mode,
+      allocs: Vec::new(),
+    }
+  }
+
+  /// Creates a new allocation of the given type, and stores the initial
+  /// value to it. Returns the pointer to the new allocation.
+  pub fn alloc(&mut self, desc: String, ty: &Type, init: Val) -> Pointer {
+    self.allocs.push(Allocation {
+      desc,
+      size: ty.size(),
+      cells: BTreeMap::new(),
+      alive: true,
+    });
+    let ptr = Pointer {
+      alloc: self.allocs.len() - 1,
+      offset: 0,
+    };
+    self.store(ptr, ty, init).unwrap();
+    ptr
+  }
+
+  /// Marks the given allocation as dead. Later accesses to it trap.
+  pub fn free(&mut self, alloc: usize) {
+    let alloc = &mut self.allocs[alloc];
+    alloc.alive = false;
+    alloc.cells.clear();
+  }
+
+  /// Offsets the given pointer by the given number of bytes.
+  ///
+  /// In strict mode, the result must be in the bounds of the allocation,
+  /// or point to one byte past the end of the allocation.
+  pub fn offset(&self, ptr: Pointer, bytes: isize) -> Result<Pointer, String> {
+    let offset = ptr
+      .offset
+      .checked_add(bytes)
+      .ok_or("pointer calculation overflowed")?;
+    let alloc = &self.allocs[ptr.alloc];
+    if self.mode == Mode::Strict && !(0..=alloc.size as isize).contains(&offset) {
+      return Err(format!(
+        "pointer calculation out of bounds of {} ({} bytes), offset {} + {} = {}",
+        alloc.desc, alloc.size, ptr.offset, bytes, offset
+      ));
+    }
+    Ok(Pointer { offset, ..ptr })
+  }
+
+  /// Loads a value of the given type from the given pointer.
+  pub fn load(&self, ptr: Pointer, ty: &Type) -> Result<Val, String> {
+    let offset = self.check_access(ptr, ty, "load")?;
+    self.load_at(ptr.alloc, offset, ty)
+  }
+
+  /// Stores a value of the given type to the given pointer.
+  pub fn store(&mut self, ptr: Pointer, ty: &Type, val: Val) -> Result<(), String> {
+    let offset = self.check_access(ptr, ty, "store")?;
+    self.store_at(ptr.alloc, offset, ty, val);
+    Ok(())
+  }
+
+  /// Checks if the given memory access is valid.
+  /// Returns the offset of the access.
+  fn check_access(&self, ptr: Pointer, ty: &Type, access: &str) -> Result<usize, String> {
+    let alloc = &self.allocs[ptr.alloc];
+    if !alloc.alive {
+      return Err(format!(
+        "{access} through a dangling pointer to {}, the function has returned",
+        alloc.desc
+      ));
+    }
+    let in_bounds = usize::try_from(ptr.offset)
+      .ok()
+      .filter(|offset| offset + ty.size() <= alloc.size);
+    let offset = in_bounds.ok_or_else(|| {
+      format!(
+        "{access} of {} bytes at offset {} out of bounds of {} ({} bytes)",
+        ty.size(),
+        ptr.offset,
+        alloc.desc,
+        alloc.size
+      )
+    })?;
+    let align = align_of(ty);
+    if self.mode == Mode::Strict && offset % align != 0 {
+      return Err(format!(
+        "misaligned {access} at offset {offset} of {}, expected alignment {align}",
+        alloc.desc
+      ));
+    }
+    Ok(offset)
+  }
+
+  fn load_at(&self, alloc: usize, offset: usize, ty: &Type) -> Result<Val, String> {
+    match ty.kind() {
+      TypeKind::Array(base, len) => (0..*len)
+        .map(|i| self.load_at(alloc, offset + base.size() * i, base))
+        .collect::<Result<_, _>>()
+        .map(Val::Array),
+      _ => match self.allocs[alloc].cells.get(&offset) {
+        Some((val, size)) if *size == ty.size() => match (val, ty.kind()) {
+          (Val::Int(_), TypeKind::Int32)
+          | (Val::Pointer(_) | Val::UnsafePointer(_), TypeKind::Pointer(_))
+          | (Val::Undef, _) => Ok(val.clone()),
+          _ => Err(format!("type of the stored value is not `{ty}`")),
+        },
+        // partially overwritten values are undefined
+        _ => Ok(Val::Undef),
+      },
+    }
+  }
+
+  fn store_at(&mut self, alloc: usize, offset: usize, ty: &Type, val: Val) {
+    match (ty.kind(), val) {
+      (TypeKind::Array(base, len), val) => {
+        for i in 0..*len {
+          let elem = match &val {
+            Val::Array(elems) => elems[i].clone(),
+            _ => Val::Undef,
+          };
+          self.store_at(alloc, offset + base.size() * i, base, elem);
+        }
+      }
+      (_, val) => {
+        let size = ty.size();
+        let cells = &mut self.allocs[alloc].cells;
+        // remove all values that overlap with the stored one
+        let overlapped: Vec<_> = cells
+          .range(..offset + size)
+          .rev()
+          .take_while(|(o, (_, s))| *o + s > offset)
+          .map(|(o, _)| *o)
+          .collect();
+        for o in overlapped {
+          cells.remove(&o);
+        }
+        cells.insert(offset, (val, size));
+      }
+    }
+  }
+}
+
+/// Returns the alignment of the given type.
+fn align_of(ty: &Type) -> usize {
+  match ty.kind() {
+    TypeKind::Array(base, _) => align_of(base),
+    TypeKind::Unit => 1,
+    _ => ty.size(),
+  }
+}
@@ -11,6 +11,10 @@ impl FunctionPass for ConstantFolding {
     // handle all basic block parameters
     self.eval_bb_params(data);
   }
+
+  fn name(&self) -> &str {
+    "const-fold"
+  }
 }
 
 impl ConstantFolding {
@@ -96,7 +100,7 @@ impl ConstantFolding {
           };
           // check if is constant
           let value = data.dfg().value(value);
-          if !value.kind().is_const() || !ans.map_or(true, |v| data.dfg().data_eq(&v, value)) {
+          if !value.kind().is_const() || !ans.is_none_or(|v| data.dfg().data_eq(&v, value)) {
             continue 'outer;
           }
           ans = Some(value.clone());
@@ -1,11 +1,13 @@
-use koopa::ir::{builder_traits::*, Function, FunctionData, Value, ValueKind};
-use koopa::opt::FunctionPass;
+use koopa::ir::entities::ValueData;
+use koopa::ir::{Function, FunctionData, Value, ValueKind};
+use koopa::opt::{FunctionPass, PurityInfo};
 use std::collections::{HashMap, HashSet};
 
 /// Performs dead code elimination.
 pub struct DeadCodeElimination {
   worklist: Vec<Value>,
   liveset: HashSet<Value>,
+  purity: PurityInfo,
 }
 
 impl FunctionPass for DeadCodeElimination {
@@ -17,20 +19,39 @@ impl FunctionPass for DeadCodeElimination {
       changed = self.opt_bb_params(data);
     }
   }
+
+  fn name(&self) -> &str {
+    "dce"
+  }
 }
 
 impl DeadCodeElimination {
-  pub fn new() -> Self {
+  /// Creates a new DCE pass.
+  ///
+  /// Calls to pure functions in `purity` will be removed
+  /// if their results are unused.
+  pub fn new(purity: PurityInfo) -> Self {
     Self {
       worklist: Vec::new(),
       liveset: HashSet::new(),
+      purity,
+    }
+  }
+
+  fn is_critical_inst(&self, inst: &ValueData) -> bool {
+    match inst.kind() {
+      ValueKind::Call(call) => !self.purity.is_pure(call.callee()),
+      kind => kind.has_side_effects(),
     }
   }
 
   fn mark(&mut self, data: &FunctionData) {
+    // parameters may have been removed in the last round,
+    // so instructions that were live may be dead now
+    self.liveset.clear();
     // iterate through all values to find critical instructions
     for (v, value) in data.dfg().values() {
-      if Self::is_critical_inst(value.kind()) {
+      if self.is_critical_inst(value) {
         self.liveset.insert(*v);
         self.worklist.push(*v);
       }
@@ -39,7 +60,10 @@ impl DeadCodeElimination {
     while let Some(inst) = self.worklist.pop() {
       // mark all of its operand as undead
       for u in data.dfg().value(inst).kind().value_uses() {
-        if !self.liveset.contains(&u) && data.dfg().value(u).kind().is_local_inst() {
+        if !u.is_global()
+          && !self.liveset.contains(&u)
+          && data.dfg().value(u).kind().is_local_inst()
+        {
           self.liveset.insert(u);
           self.worklist.push(u);
         }
@@ -48,25 +72,16 @@ impl DeadCodeElimination {
   }
 
   fn sweep(&mut self, data: &mut FunctionData) {
-    // remove instructions in layout
-    let mut removed = Vec::new();
-    let mut bb_cur = data.layout_mut().bbs_mut().cursor_front_mut();
-    while let Some(bb) = bb_cur.node_mut() {
-      let mut inst_cur = bb.insts_mut().cursor_front_mut();
-      while let Some(inst) = inst_cur.key() {
-        if !self.liveset.contains(inst) {
-          removed.push(*inst);
-          inst_cur.remove_current();
-        } else {
-          inst_cur.move_next();
-        }
-      }
-      bb_cur.move_next();
-    }
-    // remove values in DFG
-    for v in removed {
-      data.dfg_mut().remove_value(v);
-    }
+    // dead instructions are only used by other dead instructions
+    let dead: Vec<_> = data
+      .layout()
+      .bbs()
+      .nodes()
+      .flat_map(|bb| bb.insts().keys())
+      .filter(|inst| !self.liveset.contains(inst))
+      .copied()
+      .collect();
+    data.remove_insts(dead);
   }
 
   fn opt_bb_params(&self, data: &mut FunctionData) -> bool {
@@ -102,53 +117,138 @@ impl DeadCodeElimination {
     bbs.into_iter().for_each(|(b, m)| {
       let users = data.dfg().bb(b).used_by().clone();
       users.into_iter().for_each(|user| {
-        // get instruction from DFG
-        let mut inst = data.dfg().value(user).clone();
-        // get argument list
-        let args = match inst.kind_mut() {
-          ValueKind::Branch(br) => {
-            if br.true_bb() == b {
-              br.true_args_mut()
-            } else {
-              br.false_args_mut()
+        // get the edges to the basic block
+        let edges: Vec<_> = data
+          .dfg()
+          .value(user)
+          .kind()
+          .edges()
+          .into_iter()
+          .enumerate()
+          .filter(|(_, (target, _))| *target == b)
+          .map(|(i, (_, args))| (i, args.to_vec()))
+          .collect();
+        for (i, args) in edges {
+          // update argument list, and record removed values
+          let (removed, kept): (Vec<_>, Vec<_>) = args
+            .into_iter()
+            .enumerate()
+            .partition(|(index, _)| m.contains_key(index));
+          let kept: Vec<_> = kept.into_iter().map(|(_, a)| a).collect();
+          data.dfg_mut().rewrite_edge(user, i, b, kept);
+          // remove unused constants, unused instructions will be removed
+          // by the next round of sweeping
+          removed.into_iter().for_each(|(_, v)| {
+            let value = data.dfg().value(v);
+            if value.kind().is_const() && value.used_by().is_empty() {
+              data.dfg_mut().remove_value(v);
             }
-          }
-          ValueKind::Jump(jump) => jump.args_mut(),
-          _ => panic!("invalid branch/jump instruction"),
-        };
-        // update argument list
-        let mut removed_args = HashSet::new();
-        let mut index = 0;
-        args.retain(|a| {
-          index += 1;
-          let removed = !m.contains_key(&(index - 1));
-          // record removed values
-          if removed {
-            removed_args.insert(*a);
-          }
-          removed
-        });
-        // replace the existing instruction
-        data.dfg_mut().replace_value_with(user).raw(inst);
-        // remove unused values
-        removed_args.into_iter().for_each(|v| {
-          if data.dfg().value(v).used_by().is_empty() {
-            data.dfg_mut().remove_value(v);
-          }
-        });
+          });
+        }
       });
     });
     changed
   }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use koopa::back::KoopaGenerator;
+  use koopa::front::Driver;
+  use koopa::opt::{Pass, PassManager};
+  use std::str;
 
-  fn is_critical_inst(kind: &ValueKind) -> bool {
-    matches!(
-      kind,
-      ValueKind::Store(_)
-        | ValueKind::Call(_)
-        | ValueKind::Branch(_)
-        | ValueKind::Jump(_)
-        | ValueKind::Return(_)
-    )
+  #[test]
+  fn remove_pure_calls() {
+    let driver: Driver<_> = r#"
+      global @g = alloc i32, 1
+      decl @getint(): i32
+      decl @abs(i32): i32 #[pure]
+
+      fun @square(@x: i32): i32 {
+      %entry:
+        %0 = mul @x, @x
+        ret %0
+      }
+
+      fun @get(): i32 {
+      %entry:
+        %0 = load @g
+        %1 = call @abs(%0)
+        ret %1
+      }
+
+      fun @set(@x: i32): i32 {
+      %entry:
+        store @x, @g
+        ret @x
+      }
+
+      fun @main(): i32 {
+      %entry:
+        %sq = call @square(2)
+        %get = call @get()
+        %set = call @set(3)
+        %input = call @getint()
+        ret 0
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    let mut passman = PassManager::new();
+    let dce = DeadCodeElimination::new(PurityInfo::new(&program));
+    passman.register(Pass::Function(Box::new(dce)));
+    passman.run_passes(&mut program);
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    let output = gen.writer();
+    let output = str::from_utf8(&output).unwrap();
+    assert_eq!(
+      &output[output.find("fun @main").unwrap()..],
+      r#"fun @main(): i32 {
+%entry:
+  %set = call @set(3)
+  %input = call @getint()
+  ret 0
+}
+"#
+    );
+  }
+
+  #[test]
+  fn remove_dead_args() {
+    let driver: Driver<_> = r#"
+      fun @main(@x: i32): i32 {
+      %entry:
+        %a = add @x, 2
+        %b = mul %a, %a
+        jump %next(%b, 1)
+
+      %next(%p: i32, %q: i32):
+        %c = add %q, 3
+        ret %q
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    let mut passman = PassManager::new();
+    let dce = DeadCodeElimination::new(PurityInfo::new(&program));
+    passman.register(Pass::Function(Box::new(dce)));
+    passman.run_passes(&mut program);
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    // `%a` and `%b` are dead after `%p` is removed
+    assert_eq!(
+      str::from_utf8(&gen.writer()).unwrap(),
+      r#"fun @main(@x: i32): i32 {
+%entry:
+  jump %next(1)
+
+%next(%q: i32):
+  ret %q
+}
+"#
+    );
   }
 }
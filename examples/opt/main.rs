@@ -3,7 +3,7 @@ mod dce;
 
 use koopa::back::KoopaGenerator;
 use koopa::front::Driver;
-use koopa::opt::{Pass, PassManager};
+use koopa::opt::{Pass, PassManager, PurityInfo};
 use std::env::args;
 use std::{fmt, io, process};
 
@@ -48,7 +48,10 @@ fn try_main() -> Result<(), Error> {
   // run passes
   let mut passman = PassManager::new();
   passman.register(Pass::Function(Box::new(const_fold::ConstantFolding::new())));
-  passman.register(Pass::Function(Box::new(dce::DeadCodeElimination::new())));
+  let purity = PurityInfo::new(&program);
+  passman.register(Pass::Function(Box::new(dce::DeadCodeElimination::new(
+    purity,
+  ))));
   passman.run_passes(&mut program);
   // dump the output
   let mut generator = KoopaGenerator::from_path(output).map_err(Error::InvalidFile)?;
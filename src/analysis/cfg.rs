@@ -0,0 +1,206 @@
+//! Control flow graph traversals of functions.
+//!
+//! All traversals start from the entry basic block, and skip basic
+//! blocks that are unreachable from it. Successors of a basic block are
+//! the targets of its terminator (see [`successors`]), so the results
+//! only depend on the function, and are deterministic.
+//!
+//! # Example
+//!
+//! ```
+//! use koopa::analysis::cfg;
+//! use koopa::front::Driver;
+//!
+//! let driver: Driver<_> = r#"
+//!   fun @main(): i32 {
+//!   %entry:
+//!     jump %loop
+//!
+//!   %dead:
+//!     jump %loop
+//!
+//!   %loop:
+//!     br 1, %loop, %end
+//!
+//!   %end:
+//!     ret 0
+//!   }
+//! "#
+//! .into();
+//! let program = driver.generate_program().unwrap();
+//! let func = program.func(program.func_layout()[0]);
+//!
+//! let name = |bb| func.dfg().bb(bb).name().clone().unwrap();
+//! let rpo: Vec<_> = cfg::rpo(func).into_iter().map(name).collect();
+//! assert_eq!(rpo, ["%entry", "%loop", "%end"]);
+//! ```
+
+use crate::ir::{BasicBlock, FunctionData};
+use std::collections::{HashMap, HashSet};
+
+/// Returns successors of the given basic block, which are the targets
+/// of its terminator in the operand order, without duplicates.
+///
+/// Returns an empty list if the basic block has no terminator.
+///
+/// # Panics
+///
+/// Panics if the given basic block is not in the layout.
+pub fn successors(func: &FunctionData, bb: BasicBlock) -> Vec<BasicBlock> {
+  let node = func
+    .layout()
+    .bbs()
+    .node(&bb)
+    .expect("`bb` is not in the layout");
+  let mut succs = Vec::new();
+  if let Some(term) = node.insts().back_key() {
+    for succ in func.dfg().value(*term).kind().bb_uses() {
+      if !succs.contains(&succ) {
+        succs.push(succ);
+      }
+    }
+  }
+  succs
+}
+
+/// Returns the reachable basic blocks of the given function in post
+/// order, for backward data flow problems.
+///
+/// Successors are visited in the order of [`successors`]. Returns an
+/// empty list if the function is a declaration.
+pub fn po(func: &FunctionData) -> Vec<BasicBlock> {
+  let Some(entry) = func.layout().entry_bb() else {
+    return Vec::new();
+  };
+  let mut order = Vec::new();
+  let mut visited = HashSet::from([entry]);
+  // use an explicit stack of basic blocks and their unvisited successors,
+  // so long chains of basic blocks will not overflow the stack
+  let mut stack = vec![(entry, successors(func, entry).into_iter())];
+  while let Some((bb, succs)) = stack.last_mut() {
+    match succs.next() {
+      Some(succ) => {
+        if func.layout().bbs().node(&succ).is_some() && visited.insert(succ) {
+          stack.push((succ, successors(func, succ).into_iter()));
+        }
+      }
+      None => {
+        order.push(*bb);
+        stack.pop();
+      }
+    }
+  }
+  order
+}
+
+/// Returns the reachable basic blocks of the given function in reverse
+/// post order, for forward data flow problems.
+///
+/// The entry basic block always comes first, and each basic block comes
+/// before its successors, except along back edges.
+pub fn rpo(func: &FunctionData) -> Vec<BasicBlock> {
+  let mut order = po(func);
+  order.reverse();
+  order
+}
+
+/// Returns predecessors of all reachable basic blocks of the given
+/// function.
+///
+/// Every reachable basic block has an entry, including the entry basic
+/// block. Predecessors are listed in reverse post order, without
+/// duplicates, and unreachable predecessors are skipped.
+pub fn preds_map(func: &FunctionData) -> HashMap<BasicBlock, Vec<BasicBlock>> {
+  let order = rpo(func);
+  let mut preds: HashMap<_, _> = order.iter().map(|bb| (*bb, Vec::new())).collect();
+  for &bb in &order {
+    for succ in successors(func, bb) {
+      if let Some(p) = preds.get_mut(&succ) {
+        p.push(bb);
+      }
+    }
+  }
+  preds
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::front::Driver;
+  use crate::ir::Program;
+
+  const SRC: &str = r#"
+    fun @f(@c: i32): i32 {
+    %entry:
+      br @c, %a, %b
+
+    %a:
+      jump %loop
+
+    %dead:
+      jump %b
+
+    %b:
+      jump %loop
+
+    %loop:
+      br @c, %body, %end
+
+    %body:
+      br @c, %loop, %loop
+
+    %end:
+      ret 0
+    }
+  "#;
+
+  fn names(program: &Program, bbs: &[BasicBlock]) -> Vec<String> {
+    let func = program.func(program.func_layout()[0]);
+    bbs
+      .iter()
+      .map(|bb| func.dfg().bb(*bb).name().clone().unwrap())
+      .collect()
+  }
+
+  #[test]
+  fn traversal_orders() {
+    let driver: Driver<_> = SRC.into();
+    let program = driver.generate_program().unwrap();
+    let func = program.func(program.func_layout()[0]);
+    assert_eq!(
+      names(&program, &po(func)),
+      ["%body", "%end", "%loop", "%a", "%b", "%entry"]
+    );
+    assert_eq!(
+      names(&program, &rpo(func)),
+      ["%entry", "%b", "%a", "%loop", "%end", "%body"]
+    );
+    // deterministic
+    assert_eq!(rpo(func), rpo(func));
+  }
+
+  #[test]
+  fn preds() {
+    let driver: Driver<_> = SRC.into();
+    let program = driver.generate_program().unwrap();
+    let func = program.func(program.func_layout()[0]);
+    let preds = preds_map(func);
+    // `%dead` is skipped, both as a block and as a predecessor
+    assert_eq!(preds.len(), 6);
+    let pred_names = |name: &str| {
+      let (bb, _) = func
+        .dfg()
+        .bbs()
+        .iter()
+        .find(|(_, d)| d.name().as_deref() == Some(name))
+        .unwrap();
+      names(&program, &preds[bb])
+    };
+    assert!(pred_names("%entry").is_empty());
+    assert_eq!(pred_names("%b"), ["%entry"]);
+    assert_eq!(pred_names("%loop"), ["%b", "%a", "%body"]);
+    // `%body` branches to `%loop` twice
+    assert_eq!(pred_names("%body"), ["%loop"]);
+    assert_eq!(pred_names("%end"), ["%loop"]);
+  }
+}
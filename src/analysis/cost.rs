@@ -0,0 +1,114 @@
+//! Instruction cost models ([`CostModel`]) for target-aware passes.
+//!
+//! Passes that trade code size or instruction counts for speed, like
+//! [`LoopUnroll`](crate::opt::LoopUnroll) and the strength reduction of
+//! [`AlgebraicSimplify`](crate::opt::AlgebraicSimplify), ask a cost
+//! model how expensive instructions are instead of counting them.
+//! [`UnitCost`] is the default model, and [`WeightedCost`] is a sample
+//! model for targets with slow multiplications and memory accesses.
+//!
+//! # Example
+//!
+//! ```
+//! use koopa::analysis::cost::{CostModel, UnitCost, WeightedCost};
+//! use koopa::front::Driver;
+//!
+//! let driver: Driver<_> = r#"
+//!   fun @f(@x: i32): i32 {
+//!   %entry:
+//!     %0 = mul @x, 3
+//!     %1 = add %0, 1
+//!     ret %1
+//!   }
+//! "#
+//! .into();
+//! let program = driver.generate_program().unwrap();
+//!
+//! let func = program.func(program.func_layout()[0]);
+//! assert_eq!(UnitCost.total_cost(func), 3);
+//! assert_eq!(WeightedCost.total_cost(func), 6);
+//! ```
+
+use crate::ir::dfg::DataFlowGraph;
+use crate::ir::{BinaryOp, FunctionData, Value, ValueKind};
+
+/// Trait of instruction cost models.
+///
+/// Costs are relative numbers without units, passes only compare them
+/// with each other or with their thresholds.
+pub trait CostModel {
+  /// Returns the cost of the given value in the given data flow graph.
+  ///
+  /// Values that are not instructions, like constants and arguments,
+  /// should cost 0.
+  fn cost(&self, dfg: &DataFlowGraph, inst: Value) -> u32;
+
+  /// Returns the total cost of all instructions in the layout of the
+  /// given function, or 0 for function declarations.
+  ///
+  /// The sum saturates at [`u32::MAX`].
+  fn total_cost(&self, func: &FunctionData) -> u32 {
+    let dfg = func.dfg();
+    func
+      .layout()
+      .bbs()
+      .iter()
+      .flat_map(|(_, node)| node.insts().keys())
+      .fold(0u32, |sum, inst| sum.saturating_add(self.cost(dfg, *inst)))
+  }
+}
+
+/// The default cost model, in which all instructions cost 1 and all
+/// other values cost 0.
+///
+/// The total cost of a function is the number of its instructions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UnitCost;
+
+impl CostModel for UnitCost {
+  fn cost(&self, dfg: &DataFlowGraph, inst: Value) -> u32 {
+    is_inst(dfg, inst) as u32
+  }
+}
+
+/// A sample cost model, with rough relative latencies of simple
+/// in-order processors, like RISC-V cores without caches:
+///
+/// | Instruction                | Cost |
+/// | -------------------------- | ---- |
+/// | `mul`, `div`, `mod`        | 4    |
+/// | `load`, `store`            | 2    |
+/// | `br`                       | 2    |
+/// | `call`                     | 10   |
+/// | Other instructions         | 1    |
+/// | Constants and arguments    | 0    |
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WeightedCost;
+
+impl CostModel for WeightedCost {
+  fn cost(&self, dfg: &DataFlowGraph, inst: Value) -> u32 {
+    if !is_inst(dfg, inst) {
+      return 0;
+    }
+    match dfg.value(inst).kind() {
+      ValueKind::Binary(bin) => match bin.op() {
+        BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => 4,
+        _ => 1,
+      },
+      ValueKind::Load(_) | ValueKind::Store(_) | ValueKind::Branch(_) => 2,
+      ValueKind::Call(_) => 10,
+      _ => 1,
+    }
+  }
+}
+
+/// Returns `true` if the given value is a local instruction.
+fn is_inst(dfg: &DataFlowGraph, value: Value) -> bool {
+  dfg.values().get(&value).is_some_and(|data| {
+    !data.kind().is_const()
+      && !matches!(
+        data.kind(),
+        ValueKind::FuncArgRef(_) | ValueKind::BlockArgRef(_)
+      )
+  })
+}
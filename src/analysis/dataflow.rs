@@ -0,0 +1,476 @@
+//! Data flow analysis framework ([`solve`]) related implementations.
+//!
+//! A data flow problem ([`DataflowProblem`]) describes facts of basic
+//! blocks, how facts flowing from several basic blocks are merged, and
+//! how a basic block transforms the fact flowing into it. The solver
+//! ([`Solver`]) visits reachable basic blocks with a worklist until no
+//! fact changes:
+//!
+//! * Forward problems visit basic blocks in reverse post order, facts
+//!   flow from predecessors to successors.
+//! * Backward problems visit basic blocks in post order, facts flow
+//!   from successors to predecessors.
+//!
+//! Each basic block is in the worklist at most once, and the worklist
+//! always yields the basic block that comes first in the visiting
+//! order, so problems without loops converge in a single pass.
+//!
+//! # Example
+//!
+//! ```
+//! use koopa::analysis::dataflow::{self, DataflowProblem, Direction};
+//! use koopa::front::Driver;
+//! use koopa::ir::{BasicBlock, FunctionData};
+//!
+//! /// Counts the instructions that may have been executed before the
+//! /// end of each basic block, saturated at 100.
+//! struct InstCount;
+//!
+//! impl DataflowProblem for InstCount {
+//!   type Fact = usize;
+//!   const DIRECTION: Direction = Direction::Forward;
+//!
+//!   fn init(&self, _: &FunctionData) -> usize {
+//!     0
+//!   }
+//!
+//!   fn boundary(&self, _: &FunctionData) -> usize {
+//!     0
+//!   }
+//!
+//!   fn merge(&self, fact: &mut usize, other: &usize) {
+//!     *fact = (*fact).max(*other);
+//!   }
+//!
+//!   fn transfer(&self, func: &FunctionData, bb: BasicBlock, fact: &usize) -> usize {
+//!     let len = func.layout().bbs().node(&bb).unwrap().insts().len();
+//!     (fact + len).min(100)
+//!   }
+//! }
+//!
+//! let driver: Driver<_> = r#"
+//!   fun @f(@c: i32) {
+//!   %entry:
+//!     br @c, %then, %end
+//!
+//!   %then:
+//!     %0 = add @c, 1
+//!     jump %end
+//!
+//!   %end:
+//!     ret
+//!   }
+//! "#
+//! .into();
+//! let program = driver.generate_program().unwrap();
+//! let func = program.func(program.func_layout()[0]);
+//! let end = func.layout().bbs().back_key().copied().unwrap();
+//!
+//! let results = dataflow::solve(func, &InstCount);
+//! assert_eq!(results[&end], (3, 4));
+//! ```
+
+use crate::analysis::cfg;
+use crate::ir::{BasicBlock, FunctionData};
+use std::collections::{BTreeSet, HashMap};
+use std::{error, fmt};
+
+/// Direction of data flow problems.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+  /// Facts flow from predecessors to successors.
+  Forward,
+  /// Facts flow from successors to predecessors.
+  Backward,
+}
+
+/// A data flow problem over basic blocks of a function.
+///
+/// Facts form a lattice, whose join (or meet) is
+/// [`merge`](DataflowProblem::merge). The solver only terminates if
+/// the transfer function is monotone and the lattice has no infinite
+/// ascending chains, otherwise it gives up after a number of visits
+/// (see [`Solver::with_max_visits`]).
+pub trait DataflowProblem {
+  /// Type of the facts. Facts are compared with `==` to find out if
+  /// the solver has converged.
+  type Fact: Clone + PartialEq;
+
+  /// Direction of the problem.
+  const DIRECTION: Direction;
+
+  /// Returns the initial fact of all basic blocks, which must be the
+  /// identity of [`merge`](DataflowProblem::merge), like the empty set
+  /// for unions, or the universal set for intersections.
+  fn init(&self, func: &FunctionData) -> Self::Fact;
+
+  /// Returns the fact flowing into the boundary basic blocks, that is,
+  /// the entry basic block for forward problems, or basic blocks
+  /// without successors for backward problems.
+  fn boundary(&self, func: &FunctionData) -> Self::Fact;
+
+  /// Merges fact `other` into fact `fact`.
+  fn merge(&self, fact: &mut Self::Fact, other: &Self::Fact);
+
+  /// Transfers the fact flowing into the given basic block through the
+  /// basic block, returns the fact flowing out of the basic block.
+  ///
+  /// For forward problems, the given fact is the fact at the start of
+  /// the basic block, and the returned fact is the fact at the end. For
+  /// backward problems, it's the other way around.
+  fn transfer(&self, func: &FunctionData, bb: BasicBlock, fact: &Self::Fact) -> Self::Fact;
+}
+
+/// Results of data flow problems, which map reachable basic blocks to
+/// the facts at the start and at the end of them.
+pub type Results<F> = HashMap<BasicBlock, (F, F)>;
+
+/// Solves the given data flow problem on the given function with the
+/// default [`Solver`].
+///
+/// # Panics
+///
+/// Panics if the solver does not converge, see [`Solver::solve`].
+pub fn solve<P: DataflowProblem>(func: &FunctionData, problem: &P) -> Results<P::Fact> {
+  Solver::new().solve(func, problem)
+}
+
+/// Solver of data flow problems.
+///
+/// Basic blocks that are unreachable from the entry basic block are not
+/// visited, and do not appear in the results.
+#[derive(Clone, Debug)]
+pub struct Solver {
+  max_visits: usize,
+  debug: bool,
+}
+
+impl Solver {
+  /// The default maximum number of visits of each basic block.
+  pub const DEFAULT_MAX_VISITS: usize = 1000;
+
+  /// Creates a new solver.
+  pub fn new() -> Self {
+    Self {
+      max_visits: Self::DEFAULT_MAX_VISITS,
+      debug: false,
+    }
+  }
+
+  /// Sets the maximum number of visits of each basic block, the solver
+  /// gives up if any basic block is visited more times.
+  pub fn with_max_visits(mut self, max_visits: usize) -> Self {
+    self.max_visits = max_visits;
+    self
+  }
+
+  /// Enables or disables the debug mode, which logs every visit of
+  /// basic blocks, and the number of visits of each basic block after
+  /// the solver converges, to the standard error.
+  pub fn with_debug(mut self, debug: bool) -> Self {
+    self.debug = debug;
+    self
+  }
+
+  /// Solves the given data flow problem on the given function.
+  ///
+  /// # Panics
+  ///
+  /// Panics if any basic block is visited more than the maximum number
+  /// of times.
+  pub fn solve<P: DataflowProblem>(&self, func: &FunctionData, problem: &P) -> Results<P::Fact> {
+    self
+      .try_solve(func, problem)
+      .unwrap_or_else(|e| panic!("{e}"))
+  }
+
+  /// Solves the given data flow problem on the given function.
+  ///
+  /// Returns [`NotConverged`] if any basic block is visited more than
+  /// the maximum number of times.
+  pub fn try_solve<P: DataflowProblem>(
+    &self,
+    func: &FunctionData,
+    problem: &P,
+  ) -> Result<Results<P::Fact>, NotConverged> {
+    let order = match P::DIRECTION {
+      Direction::Forward => cfg::rpo(func),
+      Direction::Backward => cfg::po(func),
+    };
+    let index: HashMap<_, _> = order.iter().enumerate().map(|(i, bb)| (*bb, i)).collect();
+    // facts of basic blocks in `sources` flow into the basic block,
+    // and facts of the basic block flow into basic blocks in `sinks`
+    let mut sources = vec![Vec::new(); order.len()];
+    let mut sinks = vec![Vec::new(); order.len()];
+    for (i, &bb) in order.iter().enumerate() {
+      for succ in cfg::successors(func, bb) {
+        let Some(&j) = index.get(&succ) else {
+          continue;
+        };
+        let (from, to) = match P::DIRECTION {
+          Direction::Forward => (i, j),
+          Direction::Backward => (j, i),
+        };
+        sources[to].push(from);
+        sinks[from].push(to);
+      }
+    }
+    // solve the problem
+    let init = problem.init(func);
+    let boundary = problem.boundary(func);
+    let mut facts_in = vec![init.clone(); order.len()];
+    let mut facts_out = vec![init.clone(); order.len()];
+    let mut visits = vec![0; order.len()];
+    let mut worklist: BTreeSet<_> = (0..order.len()).collect();
+    while let Some(i) = worklist.pop_first() {
+      visits[i] += 1;
+      if visits[i] > self.max_visits {
+        return Err(NotConverged {
+          func: func.name().into(),
+          bb: bb_name(func, order[i]),
+          visits: self.max_visits,
+        });
+      }
+      let is_boundary = match P::DIRECTION {
+        Direction::Forward => i == 0,
+        Direction::Backward => sources[i].is_empty(),
+      };
+      let mut fact = if is_boundary {
+        boundary.clone()
+      } else {
+        init.clone()
+      };
+      for &s in &sources[i] {
+        problem.merge(&mut fact, &facts_out[s]);
+      }
+      let out = problem.transfer(func, order[i], &fact);
+      facts_in[i] = fact;
+      let changed = out != facts_out[i];
+      if changed {
+        facts_out[i] = out;
+        worklist.extend(&sinks[i]);
+      }
+      if self.debug {
+        eprintln!(
+          "dataflow: {}, {}: visit {}, {}",
+          func.name(),
+          bb_name(func, order[i]),
+          visits[i],
+          if changed { "changed" } else { "unchanged" }
+        );
+      }
+    }
+    if self.debug {
+      for (bb, visits) in order.iter().zip(&visits) {
+        eprintln!(
+          "dataflow: {}, {}: converged after {visits} visits",
+          func.name(),
+          bb_name(func, *bb)
+        );
+      }
+    }
+    // collect the results
+    let facts = facts_in
+      .into_iter()
+      .zip(facts_out)
+      .map(|f| match P::DIRECTION {
+        Direction::Forward => f,
+        Direction::Backward => (f.1, f.0),
+      });
+    Ok(order.into_iter().zip(facts).collect())
+  }
+}
+
+impl Default for Solver {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Returns the name of the given basic block for messages.
+fn bb_name(func: &FunctionData, bb: BasicBlock) -> String {
+  match func.dfg().bbs().get(&bb).and_then(|b| b.name().clone()) {
+    Some(name) => name,
+    None => format!("{bb:?}"),
+  }
+}
+
+/// An error returned by [`Solver::try_solve`] if the solver does not
+/// converge.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NotConverged {
+  /// Name of the function.
+  pub func: String,
+  /// Name of the basic block that has been visited too many times.
+  pub bb: String,
+  /// The maximum number of visits.
+  pub visits: usize,
+}
+
+impl fmt::Display for NotConverged {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "{}, {}: data flow analysis did not converge after {} visits",
+      self.func, self.bb, self.visits
+    )
+  }
+}
+
+impl error::Error for NotConverged {}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::front::Driver;
+  use crate::ir::{BinaryOp, Value, ValueKind};
+  use std::collections::HashSet;
+
+  /// Available expressions, written as a forward data flow problem.
+  ///
+  /// An expression is a binary operator and its operands. It is
+  /// available at a point if it has been computed on every path from
+  /// the entry to the point, so a later computation can reuse it.
+  ///
+  /// * Facts are sets of expressions, and merging two facts is the
+  ///   intersection of them.
+  /// * The initial fact is the identity of intersections, which is the
+  ///   set of all expressions in the function.
+  /// * Nothing is available at the start of the entry basic block, so
+  ///   the boundary fact is the empty set.
+  /// * The transfer function adds the expressions computed in the basic
+  ///   block. Values in SSA form never change, so no expression is
+  ///   killed by later instructions.
+  struct AvailExprs;
+
+  type Expr = (BinaryOp, Value, Value);
+
+  impl AvailExprs {
+    /// Returns expressions computed in the given basic block.
+    fn exprs(func: &FunctionData, bb: BasicBlock) -> impl Iterator<Item = Expr> + '_ {
+      let node = func.layout().bbs().node(&bb).unwrap();
+      node
+        .insts()
+        .keys()
+        .filter_map(|inst| match func.dfg().value(*inst).kind() {
+          ValueKind::Binary(bin) => Some((bin.op(), bin.lhs(), bin.rhs())),
+          _ => None,
+        })
+    }
+  }
+
+  impl DataflowProblem for AvailExprs {
+    type Fact = HashSet<Expr>;
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn init(&self, func: &FunctionData) -> Self::Fact {
+      let bbs = func.layout().bbs().keys();
+      bbs.flat_map(|bb| Self::exprs(func, *bb)).collect()
+    }
+
+    fn boundary(&self, _: &FunctionData) -> Self::Fact {
+      HashSet::new()
+    }
+
+    fn merge(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+      fact.retain(|e| other.contains(e));
+    }
+
+    fn transfer(&self, func: &FunctionData, bb: BasicBlock, fact: &Self::Fact) -> Self::Fact {
+      let mut fact = fact.clone();
+      fact.extend(Self::exprs(func, bb));
+      fact
+    }
+  }
+
+  /// A problem whose facts keep growing, so it never converges.
+  struct Diverge;
+
+  impl DataflowProblem for Diverge {
+    type Fact = u64;
+    const DIRECTION: Direction = Direction::Backward;
+
+    fn init(&self, _: &FunctionData) -> u64 {
+      0
+    }
+
+    fn boundary(&self, _: &FunctionData) -> u64 {
+      0
+    }
+
+    fn merge(&self, fact: &mut u64, other: &u64) {
+      *fact = (*fact).max(*other);
+    }
+
+    fn transfer(&self, _: &FunctionData, _: BasicBlock, fact: &u64) -> u64 {
+      fact + 1
+    }
+  }
+
+  const SRC: &str = r#"
+    fun @f(@a: i32, @b: i32): i32 {
+    %entry:
+      %x = add @a, @b
+      br @a, %then, %else
+
+    %then:
+      %y = mul @a, @b
+      jump %loop
+
+    %else:
+      %z = mul @a, @b
+      %w = sub @a, @b
+      jump %loop
+
+    %loop:
+      %c = lt @a, @b
+      br %c, %loop, %end
+
+    %end:
+      ret %x
+    }
+  "#;
+
+  #[test]
+  fn avail_exprs() {
+    let driver: Driver<_> = SRC.into();
+    let program = driver.generate_program().unwrap();
+    let func = program.func(program.func_layout()[0]);
+    let bb = |name: &str| {
+      let name = Some(name.to_string());
+      let (bb, _) = func
+        .dfg()
+        .bbs()
+        .iter()
+        .find(|(_, d)| *d.name() == name)
+        .unwrap();
+      *bb
+    };
+    let ops = |fact: &HashSet<Expr>| {
+      let mut ops: Vec<_> = fact.iter().map(|(op, ..)| format!("{op}")).collect();
+      ops.sort();
+      ops
+    };
+    let results = solve(func, &AvailExprs);
+    assert_eq!(results.len(), 5);
+    assert!(results[&bb("%entry")].0.is_empty());
+    assert_eq!(ops(&results[&bb("%else")].1), ["add", "mul", "sub"]);
+    // `mul` is computed on both paths, but `sub` is not, and the back
+    // edge of `%loop` does not make anything unavailable
+    assert_eq!(ops(&results[&bb("%loop")].0), ["add", "mul"]);
+    assert_eq!(ops(&results[&bb("%end")].1), ["add", "lt", "mul"]);
+  }
+
+  #[test]
+  fn not_converged() {
+    let driver: Driver<_> = SRC.into();
+    let program = driver.generate_program().unwrap();
+    let func = program.func(program.func_layout()[0]);
+    let solver = Solver::new().with_max_visits(10).with_debug(true);
+    let error = solver.try_solve(func, &Diverge).unwrap_err();
+    assert_eq!(
+      error.to_string(),
+      "@f, %loop: data flow analysis did not converge after 10 visits"
+    );
+  }
+}
@@ -0,0 +1,214 @@
+//! Dominator tree ([`DomTree`]) related implementations.
+
+use crate::analysis::cfg;
+use crate::ir::{BasicBlock, FunctionData};
+use std::collections::HashMap;
+
+/// The dominator tree of a function.
+///
+/// A basic block `a` dominates basic block `b` if every path from the
+/// entry basic block to `b` goes through `a`. The immediate dominator
+/// of `b` is its closest strict dominator, which is the parent of `b`
+/// in the tree. Basic blocks that are unreachable from the entry basic
+/// block are not in the tree.
+///
+/// The tree is built by the iterative algorithm from "A Simple, Fast
+/// Dominance Algorithm" by Cooper, Harvey and Kennedy, on the reverse
+/// post order of the function (see [`cfg::rpo`]). Children of a basic
+/// block are also in reverse post order, so the tree is deterministic.
+///
+/// # Example
+///
+/// ```
+/// use koopa::analysis::dom::DomTree;
+/// use koopa::front::Driver;
+///
+/// let driver: Driver<_> = r#"
+///   fun @f(@c: i32) {
+///   %entry:
+///     br @c, %then, %else
+///
+///   %then:
+///     jump %end
+///
+///   %else:
+///     jump %end
+///
+///   %end:
+///     ret
+///   }
+/// "#
+/// .into();
+/// let program = driver.generate_program().unwrap();
+/// let func = program.func(program.func_layout()[0]);
+/// let bbs: Vec<_> = func.layout().bbs().keys().copied().collect();
+///
+/// let dom = DomTree::new(func);
+/// assert_eq!(dom.root(), Some(bbs[0]));
+/// // `%end` can be reached from both arms
+/// assert_eq!(dom.idom(bbs[3]), Some(bbs[0]));
+/// assert!(dom.dominates(bbs[0], bbs[3]));
+/// assert!(!dom.dominates(bbs[1], bbs[3]));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct DomTree {
+  root: Option<BasicBlock>,
+  idoms: HashMap<BasicBlock, BasicBlock>,
+  children: HashMap<BasicBlock, Vec<BasicBlock>>,
+}
+
+impl DomTree {
+  /// Builds the dominator tree of the given function.
+  ///
+  /// The tree is empty if the function is a declaration.
+  pub fn new(func: &FunctionData) -> Self {
+    let order = cfg::rpo(func);
+    let Some(&root) = order.first() else {
+      return Self::default();
+    };
+    let preds = cfg::preds_map(func);
+    let index: HashMap<_, _> = order.iter().enumerate().map(|(i, bb)| (*bb, i)).collect();
+    // immediate dominators by indices in reverse post order,
+    // the root is its own immediate dominator during the iteration
+    let mut idoms = vec![None; order.len()];
+    idoms[0] = Some(0);
+    let intersect = |idoms: &[Option<usize>], mut a: usize, mut b: usize| {
+      while a != b {
+        while a > b {
+          a = idoms[a].unwrap();
+        }
+        while b > a {
+          b = idoms[b].unwrap();
+        }
+      }
+      a
+    };
+    let mut changed = true;
+    while changed {
+      changed = false;
+      for (i, bb) in order.iter().enumerate().skip(1) {
+        let mut new_idom = None;
+        for pred in &preds[bb] {
+          let p = index[pred];
+          if idoms[p].is_some() {
+            new_idom = Some(new_idom.map_or(p, |n| intersect(&idoms, p, n)));
+          }
+        }
+        if new_idom != idoms[i] {
+          idoms[i] = new_idom;
+          changed = true;
+        }
+      }
+    }
+    let mut tree = Self {
+      root: Some(root),
+      idoms: HashMap::new(),
+      children: order.iter().map(|bb| (*bb, Vec::new())).collect(),
+    };
+    for (i, bb) in order.iter().enumerate().skip(1) {
+      let idom = order[idoms[i].unwrap()];
+      tree.idoms.insert(*bb, idom);
+      tree.children.get_mut(&idom).unwrap().push(*bb);
+    }
+    tree
+  }
+
+  /// Returns the root of the tree, which is the entry basic block,
+  /// or `None` if the tree is empty.
+  pub fn root(&self) -> Option<BasicBlock> {
+    self.root
+  }
+
+  /// Returns `true` if the given basic block is in the tree,
+  /// that is, reachable from the entry basic block.
+  pub fn contains(&self, bb: BasicBlock) -> bool {
+    self.children.contains_key(&bb)
+  }
+
+  /// Returns the immediate dominator of the given basic block, or `None`
+  /// if the basic block is the root or not in the tree.
+  pub fn idom(&self, bb: BasicBlock) -> Option<BasicBlock> {
+    self.idoms.get(&bb).copied()
+  }
+
+  /// Returns the basic blocks immediately dominated by the given basic
+  /// block, in reverse post order.
+  ///
+  /// Returns an empty slice if the basic block is not in the tree.
+  pub fn children(&self, bb: BasicBlock) -> &[BasicBlock] {
+    self.children.get(&bb).map_or(&[], |c| c.as_slice())
+  }
+
+  /// Returns `true` if basic block `a` dominates basic block `b`.
+  ///
+  /// Every basic block in the tree dominates itself. Returns `false`
+  /// if any of the given basic blocks is not in the tree.
+  pub fn dominates(&self, a: BasicBlock, b: BasicBlock) -> bool {
+    if !self.contains(a) || !self.contains(b) {
+      return false;
+    }
+    let mut cur = Some(b);
+    while let Some(bb) = cur {
+      if bb == a {
+        return true;
+      }
+      cur = self.idom(bb);
+    }
+    false
+  }
+
+  /// Returns all basic blocks in the tree in preorder, where every basic
+  /// block comes before the basic blocks it dominates.
+  pub fn preorder(&self) -> Vec<BasicBlock> {
+    let mut order = Vec::new();
+    let mut stack: Vec<_> = self.root.into_iter().collect();
+    while let Some(bb) = stack.pop() {
+      order.push(bb);
+      stack.extend(self.children(bb).iter().rev());
+    }
+    order
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::front::Driver;
+
+  #[test]
+  fn loops() {
+    let driver: Driver<_> = r#"
+      fun @f(@c: i32) {
+      %entry:
+        jump %loop
+
+      %loop:
+        br @c, %body, %end
+
+      %body:
+        br @c, %loop, %cont
+
+      %end:
+        ret
+
+      %cont:
+        jump %loop
+      }
+    "#
+    .into();
+    let program = driver.generate_program().unwrap();
+    let func = program.func(program.func_layout()[0]);
+    let bbs: Vec<_> = func.layout().bbs().keys().copied().collect();
+    let (entry, lp, body, end, cont) = (bbs[0], bbs[1], bbs[2], bbs[3], bbs[4]);
+    let dom = DomTree::new(func);
+    assert_eq!(dom.idom(entry), None);
+    assert_eq!(dom.idom(lp), Some(entry));
+    assert_eq!(dom.idom(body), Some(lp));
+    assert_eq!(dom.idom(cont), Some(body));
+    assert_eq!(dom.idom(end), Some(lp));
+    assert!(dom.contains(cont));
+    assert!(dom.dominates(lp, cont) && !dom.dominates(cont, lp));
+    assert_eq!(dom.children(lp), [end, body]);
+    assert_eq!(dom.preorder(), [entry, lp, end, body, cont]);
+  }
+}
@@ -0,0 +1,194 @@
+//! Escape analysis ([`EscapeInfo`]) related implementations.
+
+use crate::ir::{FunctionData, Value, ValueKind};
+use std::collections::{HashMap, HashSet};
+
+/// Escape information of local allocations in a function.
+///
+/// For each `alloc` in the layout of the function, uses of the
+/// allocation are followed through `getptr`s and `getelemptr`s, and the
+/// allocation is classified by the ways its address may leave the
+/// function or flow into other memory (see [`Escape`]).
+///
+/// The information is a snapshot of the function. It must be rebuilt
+/// after new uses of pointers are added.
+///
+/// # Example
+///
+/// ```
+/// use koopa::analysis::escape::{Escape, EscapeInfo};
+/// use koopa::front::Driver;
+///
+/// let driver: Driver<_> = r#"
+///   decl @use(*i32)
+///
+///   fun @f(): i32 {
+///   %entry:
+///     %a = alloc i32
+///     %b = alloc [i32, 2]
+///     store 1, %a
+///     %b1 = getelemptr %b, 1
+///     call @use(%b1)
+///     %v = load %a
+///     ret %v
+///   }
+/// "#
+/// .into();
+/// let program = driver.generate_program().unwrap();
+/// let func = program.func(*program.func_layout().last().unwrap());
+/// let insts: Vec<_> = func.layout().entry_bb().map(|bb| {
+///   func.layout().bbs().node(&bb).unwrap().insts().keys().copied().collect()
+/// }).unwrap();
+///
+/// let info = EscapeInfo::new(func);
+/// assert_eq!(info.escape(insts[0]), Some(Escape::NonEscaping));
+/// assert!(!info.escapes(insts[0]));
+/// // pointers derived from `%b` are passed to a call
+/// assert_eq!(info.escape(insts[1]), Some(Escape::ArgEscaping));
+/// assert!(info.escapes(insts[1]));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct EscapeInfo {
+  escapes: HashMap<Value, Escape>,
+}
+
+/// Escape class of local allocations.
+///
+/// Classes are ordered from the most precise to the least precise. If
+/// pointers derived from an allocation are used in several ways, the
+/// allocation gets the greatest class of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Escape {
+  /// Pointers derived from the allocation are only used as pointers of
+  /// `load`s, destinations of `store`s, and sources of `getptr`s and
+  /// `getelemptr`s.
+  NonEscaping,
+  /// Some pointers derived from the allocation are passed to `call`s.
+  ArgEscaping,
+  /// Some pointers derived from the allocation are stored to memory
+  /// as values of `store`s.
+  StoredEscaping,
+  /// Some pointers derived from the allocation are used elsewhere,
+  /// like returned, or passed to basic blocks.
+  Unknown,
+}
+
+impl EscapeInfo {
+  /// Analyzes local allocations of the given function.
+  pub fn new(func: &FunctionData) -> Self {
+    let dfg = func.dfg();
+    let mut escapes = HashMap::new();
+    for node in func.layout().bbs().nodes() {
+      for &alloc in node.insts().keys() {
+        if !matches!(dfg.value(alloc).kind(), ValueKind::Alloc(_)) {
+          continue;
+        }
+        // follow the uses of all pointers derived from the allocation
+        let mut escape = Escape::NonEscaping;
+        let mut visited = HashSet::from([alloc]);
+        let mut worklist = vec![alloc];
+        while let Some(ptr) = worklist.pop() {
+          for &user in dfg.value(ptr).used_by() {
+            let class = match dfg.value(user).kind() {
+              ValueKind::Load(_) => Escape::NonEscaping,
+              ValueKind::Store(store) if store.value() == ptr => Escape::StoredEscaping,
+              ValueKind::Store(_) => Escape::NonEscaping,
+              ValueKind::GetPtr(_) | ValueKind::GetElemPtr(_) => {
+                if visited.insert(user) {
+                  worklist.push(user);
+                }
+                Escape::NonEscaping
+              }
+              ValueKind::Call(_) => Escape::ArgEscaping,
+              _ => Escape::Unknown,
+            };
+            escape = escape.max(class);
+          }
+        }
+        escapes.insert(alloc, escape);
+      }
+    }
+    Self { escapes }
+  }
+
+  /// Returns the escape class of the given local allocation, or `None`
+  /// if the value is not an `alloc` in the analyzed function.
+  pub fn escape(&self, alloc: Value) -> Option<Escape> {
+    self.escapes.get(&alloc).copied()
+  }
+
+  /// Returns `true` if the address of the given local allocation may
+  /// escape, that is, the allocation is not [`Escape::NonEscaping`].
+  ///
+  /// Values that are not `alloc`s in the analyzed function always
+  /// escape.
+  pub fn escapes(&self, alloc: Value) -> bool {
+    self.escape(alloc) != Some(Escape::NonEscaping)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::front::Driver;
+
+  #[test]
+  fn classify() {
+    let driver: Driver<_> = r#"
+      decl @use(*i32)
+
+      fun @f(@c: i32): *i32 {
+      %entry:
+        %local = alloc [i32, 2]
+        %arg = alloc [i32, 2]
+        %stored = alloc [i32, 2]
+        %slot = alloc *i32
+        %returned = alloc i32
+        %l0 = getelemptr %local, 0
+        %l1 = getptr %l0, 1
+        store 1, %l1
+        %v = load %l0
+        %a0 = getelemptr %arg, 0
+        %a1 = getptr %a0, 1
+        call @use(%a1)
+        %s0 = getelemptr %stored, 1
+        store %s0, %slot
+        %p = load %slot
+        store %v, %p
+        br @c, %then, %else
+
+      %then:
+        ret %returned
+
+      %else:
+        ret %p
+      }
+    "#
+    .into();
+    let program = driver.generate_program().unwrap();
+    let func = program.func(*program.func_layout().last().unwrap());
+    let v = |name: &str| {
+      let name = Some(name.to_string());
+      *func
+        .dfg()
+        .values()
+        .iter()
+        .find(|(_, d)| *d.name() == name)
+        .unwrap()
+        .0
+    };
+    let info = EscapeInfo::new(func);
+    assert_eq!(info.escape(v("%local")), Some(Escape::NonEscaping));
+    // the `getelemptr` of `%arg` is passed to a call
+    assert_eq!(info.escape(v("%arg")), Some(Escape::ArgEscaping));
+    // the `getelemptr` of `%stored` is stored into `%slot`, which
+    // itself does not escape
+    assert_eq!(info.escape(v("%stored")), Some(Escape::StoredEscaping));
+    assert_eq!(info.escape(v("%slot")), Some(Escape::NonEscaping));
+    assert_eq!(info.escape(v("%returned")), Some(Escape::Unknown));
+    assert!(!info.escapes(v("%slot")) && info.escapes(v("%stored")));
+    // not allocations
+    assert_eq!(info.escape(v("%l0")), None);
+    assert!(info.escapes(v("%p")));
+  }
+}
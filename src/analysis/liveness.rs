@@ -0,0 +1,181 @@
+//! Liveness analysis ([`Liveness`]) related implementations.
+
+use crate::analysis::dataflow::{self, DataflowProblem, Direction};
+use crate::ir::{BasicBlock, FunctionData, Value};
+use std::collections::{HashMap, HashSet};
+
+/// Live values at the start and at the end of basic blocks.
+///
+/// A value is live at a point if it may be used after the point before
+/// being defined again. Only values defined in the function are
+/// tracked: function parameters, basic block parameters, and
+/// instructions in the layout. Constants and global values are never
+/// live.
+///
+/// Arguments passed to basic block parameters are used by the
+/// terminator of the predecessor, and parameters of a basic block are
+/// defined at the start of the basic block.
+///
+/// # Example
+///
+/// ```
+/// use koopa::analysis::liveness::Liveness;
+/// use koopa::front::Driver;
+///
+/// let driver: Driver<_> = r#"
+///   fun @f(@n: i32): i32 {
+///   %entry:
+///     %x = add @n, 1
+///     jump %next
+///
+///   %next:
+///     ret %x
+///   }
+/// "#
+/// .into();
+/// let program = driver.generate_program().unwrap();
+/// let func = program.func(program.func_layout()[0]);
+/// let bbs: Vec<_> = func.layout().bbs().keys().copied().collect();
+///
+/// let live = Liveness::new(func);
+/// let x = func.layout().bbs().node(&bbs[0]).unwrap().insts().front_key().copied().unwrap();
+/// assert_eq!(live.live_in(bbs[0]).unwrap().len(), 1);
+/// assert!(live.is_live_out(bbs[0], x));
+/// assert!(live.live_out(bbs[1]).unwrap().is_empty());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Liveness {
+  live: HashMap<BasicBlock, (HashSet<Value>, HashSet<Value>)>,
+}
+
+impl Liveness {
+  /// Analyzes live values of the given function.
+  pub fn new(func: &FunctionData) -> Self {
+    Self {
+      live: dataflow::solve(func, &LiveValues),
+    }
+  }
+
+  /// Returns values that are live at the start of the given basic
+  /// block, or `None` if the basic block is unreachable.
+  pub fn live_in(&self, bb: BasicBlock) -> Option<&HashSet<Value>> {
+    self.live.get(&bb).map(|(live_in, _)| live_in)
+  }
+
+  /// Returns values that are live at the end of the given basic block,
+  /// or `None` if the basic block is unreachable.
+  pub fn live_out(&self, bb: BasicBlock) -> Option<&HashSet<Value>> {
+    self.live.get(&bb).map(|(_, live_out)| live_out)
+  }
+
+  /// Returns `true` if the given value is live at the end of the given
+  /// basic block.
+  pub fn is_live_out(&self, bb: BasicBlock, value: Value) -> bool {
+    self.live_out(bb).is_some_and(|l| l.contains(&value))
+  }
+}
+
+/// Liveness as a backward data flow problem.
+struct LiveValues;
+
+impl DataflowProblem for LiveValues {
+  type Fact = HashSet<Value>;
+  const DIRECTION: Direction = Direction::Backward;
+
+  fn init(&self, _: &FunctionData) -> Self::Fact {
+    HashSet::new()
+  }
+
+  fn boundary(&self, _: &FunctionData) -> Self::Fact {
+    HashSet::new()
+  }
+
+  fn merge(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+    fact.extend(other);
+  }
+
+  fn transfer(&self, func: &FunctionData, bb: BasicBlock, fact: &Self::Fact) -> Self::Fact {
+    let dfg = func.dfg();
+    let mut live = fact.clone();
+    let node = func.layout().bbs().node(&bb).unwrap();
+    let insts: Vec<_> = node.insts().keys().copied().collect();
+    for inst in insts.into_iter().rev() {
+      live.remove(&inst);
+      let uses = dfg.value(inst).kind().value_uses();
+      live.extend(uses.filter(|u| {
+        !u.is_global()
+          && dfg
+            .values()
+            .get(u)
+            .is_some_and(|data| !data.kind().is_const())
+      }));
+    }
+    for param in dfg.bb(bb).params() {
+      live.remove(param);
+    }
+    live
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::front::Driver;
+
+  #[test]
+  fn loop_values() {
+    let driver: Driver<_> = r#"
+      fun @f(@n: i32): i32 {
+      %entry:
+        %base = mul @n, 2
+        jump %header(0)
+
+      %header(%i: i32):
+        %c = lt %i, @n
+        br %c, %body, %end
+
+      %body:
+        %i1 = add %i, 1
+        jump %header(%i1)
+
+      %end:
+        %r = add %i, %base
+        ret %r
+      }
+    "#
+    .into();
+    let program = driver.generate_program().unwrap();
+    let func = program.func(program.func_layout()[0]);
+    let bb = |name: &str| {
+      let name = Some(name.to_string());
+      let (bb, _) = func
+        .dfg()
+        .bbs()
+        .iter()
+        .find(|(_, d)| *d.name() == name)
+        .unwrap();
+      *bb
+    };
+    let names = |values: &HashSet<Value>| {
+      let mut names: Vec<_> = values
+        .iter()
+        .map(|v| func.dfg().value(*v).name().clone().unwrap())
+        .collect();
+      names.sort();
+      names
+    };
+    let live = Liveness::new(func);
+    assert_eq!(names(live.live_in(bb("%entry")).unwrap()), ["@n"]);
+    // `%base` lives through the whole loop
+    assert_eq!(names(live.live_out(bb("%entry")).unwrap()), ["%base", "@n"]);
+    assert_eq!(names(live.live_in(bb("%header")).unwrap()), ["%base", "@n"]);
+    assert_eq!(
+      names(live.live_in(bb("%body")).unwrap()),
+      ["%base", "%i", "@n"]
+    );
+    // `%i1` is passed to `%header`, but not live in it
+    assert_eq!(names(live.live_out(bb("%body")).unwrap()), ["%base", "@n"]);
+    assert_eq!(names(live.live_in(bb("%end")).unwrap()), ["%base", "%i"]);
+    assert!(live.live_out(bb("%end")).unwrap().is_empty());
+  }
+}
@@ -0,0 +1,193 @@
+//! Natural loop analysis ([`LoopInfo`]) related implementations.
+
+use crate::analysis::cfg;
+use crate::analysis::dom::DomTree;
+use crate::ir::{BasicBlock, FunctionData};
+use std::collections::HashSet;
+
+/// Natural loops of a function.
+///
+/// A back edge is an edge from basic block `latch` to basic block
+/// `header`, where `header` dominates `latch` (see [`DomTree`]). The
+/// natural loop of `header` contains `header`, and all basic blocks
+/// that can reach a latch of `header` without going through `header`.
+/// Back edges to the same header form one loop.
+///
+/// Loops are listed in reverse post order of their headers, so outer
+/// loops always come before the loops nested in them. Irreducible
+/// control flow, whose cycles have no dominating headers, is not
+/// recognized as loops.
+///
+/// # Example
+///
+/// ```
+/// use koopa::analysis::loops::LoopInfo;
+/// use koopa::front::Driver;
+///
+/// let driver: Driver<_> = r#"
+///   fun @f(@n: i32) {
+///   %entry:
+///     jump %header(0)
+///
+///   %header(%i: i32):
+///     %c = lt %i, @n
+///     br %c, %body, %end
+///
+///   %body:
+///     %i1 = add %i, 1
+///     jump %header(%i1)
+///
+///   %end:
+///     ret
+///   }
+/// "#
+/// .into();
+/// let program = driver.generate_program().unwrap();
+/// let func = program.func(program.func_layout()[0]);
+/// let bbs: Vec<_> = func.layout().bbs().keys().copied().collect();
+///
+/// let info = LoopInfo::new(func);
+/// let lp = &info.loops()[0];
+/// assert_eq!(lp.header(), bbs[1]);
+/// assert_eq!(lp.latches(), [bbs[2]]);
+/// assert!(lp.contains(bbs[2]) && !lp.contains(bbs[3]));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct LoopInfo {
+  loops: Vec<Loop>,
+}
+
+/// A natural loop, see [`LoopInfo`].
+#[derive(Clone, Debug)]
+pub struct Loop {
+  header: BasicBlock,
+  latches: Vec<BasicBlock>,
+  blocks: Vec<BasicBlock>,
+  block_set: HashSet<BasicBlock>,
+}
+
+impl LoopInfo {
+  /// Finds natural loops of the given function.
+  pub fn new(func: &FunctionData) -> Self {
+    let dom = DomTree::new(func);
+    let preds = cfg::preds_map(func);
+    let order = cfg::rpo(func);
+    let mut loops = Vec::new();
+    for &header in &order {
+      let latches: Vec<_> = preds[&header]
+        .iter()
+        .copied()
+        .filter(|pred| dom.dominates(header, *pred))
+        .collect();
+      if latches.is_empty() {
+        continue;
+      }
+      // walk backwards from the latches
+      let mut block_set = HashSet::from([header]);
+      let mut worklist = latches.clone();
+      while let Some(bb) = worklist.pop() {
+        if block_set.insert(bb) {
+          worklist.extend(&preds[&bb]);
+        }
+      }
+      let blocks = order
+        .iter()
+        .copied()
+        .filter(|bb| block_set.contains(bb))
+        .collect();
+      loops.push(Loop {
+        header,
+        latches,
+        blocks,
+        block_set,
+      });
+    }
+    Self { loops }
+  }
+
+  /// Returns all loops, in reverse post order of their headers.
+  pub fn loops(&self) -> &[Loop] {
+    &self.loops
+  }
+
+  /// Returns the loop with the given header, or `None` if the basic
+  /// block is not a loop header.
+  pub fn loop_of(&self, header: BasicBlock) -> Option<&Loop> {
+    self.loops.iter().find(|lp| lp.header == header)
+  }
+}
+
+impl Loop {
+  /// Returns the header of the loop.
+  pub fn header(&self) -> BasicBlock {
+    self.header
+  }
+
+  /// Returns the sources of the back edges, in reverse post order.
+  pub fn latches(&self) -> &[BasicBlock] {
+    &self.latches
+  }
+
+  /// Returns all basic blocks of the loop in reverse post order,
+  /// the first one is the header.
+  pub fn blocks(&self) -> &[BasicBlock] {
+    &self.blocks
+  }
+
+  /// Returns `true` if the given basic block is in the loop.
+  pub fn contains(&self, bb: BasicBlock) -> bool {
+    self.block_set.contains(&bb)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::front::Driver;
+
+  #[test]
+  fn nested_loops() {
+    let driver: Driver<_> = r#"
+      fun @f(@n: i32) {
+      %entry:
+        jump %outer
+
+      %outer:
+        br @n, %inner, %end
+
+      %inner:
+        br @n, %inner_body, %outer_latch
+
+      %inner_body:
+        br @n, %inner, %inner
+
+      %outer_latch:
+        jump %outer
+
+      %end:
+        ret
+      }
+    "#
+    .into();
+    let program = driver.generate_program().unwrap();
+    let func = program.func(program.func_layout()[0]);
+    let name = |bb: BasicBlock| func.dfg().bb(bb).name().clone().unwrap();
+    let names = |bbs: &[BasicBlock]| bbs.iter().map(|bb| name(*bb)).collect::<Vec<_>>();
+    let info = LoopInfo::new(func);
+    let [outer, inner] = info.loops() else {
+      panic!("expected two loops");
+    };
+    assert_eq!(name(outer.header()), "%outer");
+    assert_eq!(names(outer.latches()), ["%outer_latch"]);
+    assert_eq!(
+      names(outer.blocks()),
+      ["%outer", "%inner", "%outer_latch", "%inner_body"]
+    );
+    // both edges of the branch in `%inner_body` are back edges,
+    // but there is only one latch
+    assert_eq!(names(inner.latches()), ["%inner_body"]);
+    assert!(!inner.contains(outer.header()));
+    assert!(info.loop_of(inner.header()).is_some());
+    assert!(info.loop_of(outer.latches()[0]).is_none());
+  }
+}
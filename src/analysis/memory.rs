@@ -0,0 +1,387 @@
+//! Memory dependence analysis ([`MemoryInfo`]) related implementations.
+
+use crate::analysis::escape::EscapeInfo;
+use crate::ir::dfg::DataFlowGraph;
+use crate::ir::{FunctionData, TypeKind, Value, ValueKind};
+use std::collections::HashMap;
+
+/// Memory access information of a function.
+///
+/// Every pointer in the function is traced back through `getptr`s and
+/// `getelemptr`s to its base, which is the root of the pointer (see
+/// [`MemRoot`]). Constant indices along the way are recorded, so
+/// pointers to different elements of the same allocation are known to
+/// point to disjoint memory.
+///
+/// Memory-touching instructions are classified by the root they may
+/// access (see [`Access`]), which is used to answer whether an
+/// instruction may clobber the memory read by a `load`:
+///
+/// * A `store` clobbers a `load` if their pointers may alias
+///   (see [`MemoryInfo::may_alias`]).
+/// * A `call` clobbers a `load` from a global allocation, an unknown
+///   pointer, or a local allocation that escapes (see
+///   [`MemoryInfo::escapes`]). Private non-escaping allocations can
+///   never be accessed by callees.
+///
+/// The information is a snapshot of the function. It stays valid when
+/// instructions are removed or their uses are replaced with equivalent
+/// values, but it must be rebuilt after new memory-touching
+/// instructions are added.
+///
+/// # Example
+///
+/// ```
+/// use koopa::analysis::memory::{MemRoot, MemoryInfo};
+/// use koopa::front::Driver;
+///
+/// let driver: Driver<_> = r#"
+///   fun @f(@p: *i32): i32 {
+///   %entry:
+///     %a = alloc [i32, 2]
+///     %a0 = getelemptr %a, 0
+///     %a1 = getelemptr %a, 1
+///     store 1, %a0
+///     %v = load %a1
+///     ret %v
+///   }
+/// "#
+/// .into();
+/// let program = driver.generate_program().unwrap();
+/// let func = program.func(program.func_layout()[0]);
+/// let insts: Vec<_> = func.layout().entry_bb().map(|bb| {
+///   func.layout().bbs().node(&bb).unwrap().insts().keys().copied().collect()
+/// }).unwrap();
+/// let (alloc, a0, a1, store, load) = (insts[0], insts[1], insts[2], insts[3], insts[4]);
+/// let p = func.params()[0];
+///
+/// let info = MemoryInfo::new(func);
+/// assert_eq!(info.root(a1), MemRoot::Alloc(alloc));
+/// assert_eq!(info.root(p), MemRoot::Unknown);
+/// // different elements of the same allocation do not alias
+/// assert!(!info.may_alias(a0, a1));
+/// assert!(!info.clobbers(store, load));
+/// // unknown pointers alias everything
+/// assert!(info.may_alias(p, a0));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MemoryInfo {
+  paths: HashMap<Value, Path>,
+  accesses: HashMap<Value, Access>,
+  /// Pointers of `load`s and `store`s.
+  pointers: HashMap<Value, Value>,
+  escape: EscapeInfo,
+}
+
+/// Root of pointers, which is the allocation a pointer points into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MemRoot {
+  /// A local allocation (`alloc`).
+  Alloc(Value),
+  /// A global allocation (`global alloc`).
+  Global(Value),
+  /// Unknown memory, e.g. pointers returned by calls, loaded from
+  /// memory, or passed in as parameters.
+  Unknown,
+}
+
+/// Memory access of an instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Access {
+  /// Reads memory with the given root (`load`).
+  Read(MemRoot),
+  /// Writes memory with the given root (`store`).
+  Write(MemRoot),
+  /// May read or write any memory that callees can access (`call`).
+  Call,
+}
+
+/// An access path, which is a root with steps of indexing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Path {
+  root: MemRoot,
+  steps: Vec<Step>,
+}
+
+/// A step of indexing in access paths.
+///
+/// `getptr`s are merged into the previous steps, since `getptr`
+/// moves the pointer within the array indexed by the previous step.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Step {
+  /// `true` for `getelemptr`, `false` for `getptr` at the beginning.
+  elem: bool,
+  /// The index, or `None` if it is not a constant.
+  index: Option<i32>,
+}
+
+impl MemoryInfo {
+  /// Analyzes memory accesses of the given function.
+  pub fn new(func: &FunctionData) -> Self {
+    let dfg = func.dfg();
+    let mut info = Self::default();
+    for (bb, node) in func.layout().bbs() {
+      let params = dfg.bb(*bb).params().iter();
+      for value in params.chain(node.insts().keys()) {
+        if is_ptr(dfg, *value) {
+          info.trace(dfg, *value);
+        }
+        let access = match dfg.value(*value).kind() {
+          ValueKind::Load(load) => Access::Read(info.trace_access(dfg, *value, load.src())),
+          ValueKind::Store(store) => Access::Write(info.trace_access(dfg, *value, store.dest())),
+          ValueKind::Call(_) => Access::Call,
+          _ => continue,
+        };
+        info.accesses.insert(*value, access);
+      }
+    }
+    for param in func.params() {
+      if is_ptr(dfg, *param) {
+        info.trace(dfg, *param);
+      }
+    }
+    info.escape = EscapeInfo::new(func);
+    info
+  }
+
+  /// Records the pointer of the given `load` or `store`,
+  /// returns the root of the pointer.
+  fn trace_access(&mut self, dfg: &DataFlowGraph, inst: Value, ptr: Value) -> MemRoot {
+    self.pointers.insert(inst, ptr);
+    self.trace(dfg, ptr).root
+  }
+
+  /// Returns the access path of the given pointer, and records it and
+  /// the access paths of all pointers it is derived from.
+  fn trace(&mut self, dfg: &DataFlowGraph, ptr: Value) -> Path {
+    if let Some(path) = self.paths.get(&ptr) {
+      return path.clone();
+    }
+    // collect the chain of pointer calculations
+    let mut chain = Vec::new();
+    let mut base = ptr;
+    let mut path = loop {
+      if let Some(path) = self.paths.get(&base) {
+        break path.clone();
+      }
+      let Some(data) = dfg.values().get(&base) else {
+        break Path::new(MemRoot::Global(base));
+      };
+      match data.kind() {
+        ValueKind::GetPtr(gp) => {
+          chain.push((base, false, gp.index()));
+          base = gp.src();
+        }
+        ValueKind::GetElemPtr(gep) => {
+          chain.push((base, true, gep.index()));
+          base = gep.src();
+        }
+        ValueKind::Alloc(_) => break Path::new(MemRoot::Alloc(base)),
+        _ => break Path::new(MemRoot::Unknown),
+      }
+    };
+    self.paths.entry(base).or_insert_with(|| path.clone());
+    // apply the steps from the base
+    for (value, elem, index) in chain.into_iter().rev() {
+      let index = dfg.values().get(&index).and_then(|d| d.kind().as_integer());
+      path.push(elem, index);
+      self.paths.insert(value, path.clone());
+    }
+    path
+  }
+
+  /// Returns the root of the given pointer.
+  ///
+  /// Returns [`MemRoot::Unknown`] if the pointer was not in the
+  /// analyzed function.
+  pub fn root(&self, ptr: Value) -> MemRoot {
+    self.paths.get(&ptr).map_or(MemRoot::Unknown, |p| p.root)
+  }
+
+  /// Returns the memory access of the given instruction, or `None` if
+  /// the instruction does not touch memory.
+  pub fn access(&self, inst: Value) -> Option<Access> {
+    self.accesses.get(&inst).copied()
+  }
+
+  /// Returns `true` if pointers derived from the given local allocation
+  /// may be accessed outside of the function, or through unknown
+  /// pointers in the function.
+  ///
+  /// See [`EscapeInfo::escapes`] for details.
+  pub fn escapes(&self, alloc: Value) -> bool {
+    self.escape.escapes(alloc)
+  }
+
+  /// Returns `true` if the two given pointers may point to overlapping
+  /// memory.
+  ///
+  /// Pointers with different known roots never alias, and unknown
+  /// pointers alias everything. Pointers with the same root do not
+  /// alias if they index the same array with different constants.
+  pub fn may_alias(&self, a: Value, b: Value) -> bool {
+    match (self.paths.get(&a), self.paths.get(&b)) {
+      (Some(a), Some(b)) => a.may_alias(b),
+      _ => true,
+    }
+  }
+
+  /// Returns `true` if the given instruction may write to the memory
+  /// read by the given `load`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `load` is not a `load` in the analyzed function.
+  pub fn clobbers(&self, inst: Value, load: Value) -> bool {
+    let Some(Access::Read(root)) = self.access(load) else {
+      panic!("`load` is not a load in the analyzed function");
+    };
+    match self.accesses.get(&inst) {
+      Some(Access::Write(_)) => self.may_alias(self.pointers[&inst], self.pointers[&load]),
+      Some(Access::Call) => match root {
+        MemRoot::Alloc(alloc) => self.escapes(alloc),
+        MemRoot::Global(_) | MemRoot::Unknown => true,
+      },
+      Some(Access::Read(_)) | None => false,
+    }
+  }
+}
+
+/// Returns `true` if the given value is a pointer.
+fn is_ptr(dfg: &DataFlowGraph, value: Value) -> bool {
+  // global values used by functions are always global allocations
+  dfg
+    .values()
+    .get(&value)
+    .is_none_or(|data| matches!(data.ty().kind(), TypeKind::Pointer(_)))
+}
+
+impl Path {
+  fn new(root: MemRoot) -> Self {
+    Self {
+      root,
+      steps: Vec::new(),
+    }
+  }
+
+  /// Appends a step of indexing to the path.
+  fn push(&mut self, elem: bool, index: Option<i32>) {
+    match (elem, self.steps.last_mut()) {
+      (false, Some(last)) => {
+        last.index = last.index.zip(index).and_then(|(l, i)| l.checked_add(i));
+      }
+      // `getptr` by 0 at the beginning does nothing
+      (false, None) if index == Some(0) => {}
+      _ => self.steps.push(Step { elem, index }),
+    }
+  }
+
+  /// Returns `true` if the two paths may point to overlapping memory.
+  fn may_alias(&self, other: &Self) -> bool {
+    match (self.root, other.root) {
+      (MemRoot::Unknown, _) | (_, MemRoot::Unknown) => return true,
+      (l, r) if l != r => return false,
+      _ => {}
+    }
+    for (l, r) in self.steps.iter().zip(&other.steps) {
+      if l.elem != r.elem {
+        return true;
+      }
+      if let (Some(li), Some(ri)) = (l.index, r.index) {
+        if li != ri {
+          return false;
+        }
+      }
+    }
+    true
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::front::Driver;
+  use crate::ir::Program;
+
+  #[test]
+  fn alias() {
+    let driver: Driver<_> = r#"
+      global @g = alloc [i32, 4], zeroinit
+      decl @h(*i32)
+
+      fun @f(@p: *i32, @i: i32): i32 {
+      %entry:
+        %a = alloc i32
+        %b = alloc i32
+        %e = alloc i32
+        %arr = alloc [[i32, 2], 2]
+        %x0 = getelemptr %arr, 0
+        %x1 = getelemptr %arr, 1
+        %x00 = getelemptr %x0, 0
+        %x01 = getelemptr %x0, 1
+        %x0i = getelemptr %x0, @i
+        %x1i = getelemptr %x1, @i
+        %y = getptr %x00, 1
+        %g0 = getelemptr @g, 0
+        call @h(%e)
+        %v = load %a
+        store 1, %b
+        %w = load @p
+        %u = load %g0
+        %z = load %e
+        ret %v
+      }
+    "#
+    .into();
+    let program: Program = driver.generate_program().unwrap();
+    let func = program.func(*program.func_layout().last().unwrap());
+    let dfg = func.dfg();
+    let v = |name: &str| {
+      let name = Some(name.to_string());
+      *dfg
+        .values()
+        .iter()
+        .find(|(_, d)| *d.name() == name)
+        .unwrap()
+        .0
+    };
+    let inst = |f: fn(&ValueKind) -> bool| {
+      let entry = func.layout().entry_bb().unwrap();
+      let insts = func.layout().bbs().node(&entry).unwrap().insts();
+      *insts.keys().find(|i| f(dfg.value(**i).kind())).unwrap()
+    };
+    let call = inst(|k| matches!(k, ValueKind::Call(_)));
+    let store = inst(|k| matches!(k, ValueKind::Store(_)));
+    let info = MemoryInfo::new(func);
+    // distinct allocations never alias
+    assert!(!info.may_alias(v("%a"), v("%b")));
+    assert!(!info.may_alias(v("%a"), v("%g0")));
+    assert!(info.may_alias(v("%a"), v("%a")));
+    // same allocation with different constant indices
+    assert!(!info.may_alias(v("%x0"), v("%x1")));
+    assert!(!info.may_alias(v("%x00"), v("%x01")));
+    assert!(!info.may_alias(v("%x0i"), v("%x1i")));
+    assert!(info.may_alias(v("%x0i"), v("%x01")));
+    assert!(info.may_alias(v("%x0"), v("%x01")));
+    // `getptr` moves within the array
+    assert!(info.may_alias(v("%y"), v("%x01")));
+    assert!(!info.may_alias(v("%y"), v("%x00")));
+    // unknown pointers alias everything
+    assert_eq!(info.root(v("@p")), MemRoot::Unknown);
+    assert!(info.may_alias(v("@p"), v("%a")));
+    assert!(info.may_alias(v("@p"), v("%g0")));
+    // clobbers
+    assert!(info.escapes(v("%e")) && !info.escapes(v("%a")));
+    assert_eq!(
+      info.access(store),
+      Some(Access::Write(MemRoot::Alloc(v("%b"))))
+    );
+    assert!(!info.clobbers(call, v("%v")));
+    assert!(info.clobbers(call, v("%w")));
+    assert!(info.clobbers(call, v("%u")));
+    assert!(info.clobbers(call, v("%z")));
+    assert!(!info.clobbers(store, v("%v")));
+    assert!(info.clobbers(store, v("%w")));
+    assert!(!info.clobbers(v("%w"), v("%v")));
+  }
+}
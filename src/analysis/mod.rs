@@ -0,0 +1,35 @@
+//! Analyses of the in-memory form Koopa IR.
+//!
+//! This module provides analyses that only inspect Koopa IR programs,
+//! including:
+//!
+//! * Control flow graph traversals ([`cfg`](mod@cfg)), like reverse post order
+//!   and predecessors of basic blocks.
+//! * Instruction cost models ([`cost`]) for target-aware passes, like
+//!   the cost of a `mul` compared to an `add`.
+//! * A framework of data flow analyses ([`dataflow`]), which solves
+//!   forward and backward problems with worklists.
+//! * Dominator trees of functions ([`dom`]).
+//! * Escape analysis of local allocations ([`escape`]), like whether
+//!   addresses of allocations are passed to calls.
+//! * Live values of basic blocks ([`liveness`]).
+//! * Natural loops of functions ([`loops`]).
+//! * Memory dependences between instructions ([`memory`]), like
+//!   whether two pointers may alias.
+//! * Structured regions of functions ([`regions`]), like `if`/`else`
+//!   and `while` statements.
+//! * Program statistics ([`stats`]), like numbers of instructions and
+//!   basic blocks of functions.
+//! * Structural verification of functions ([`verifier`]).
+
+pub mod cfg;
+pub mod cost;
+pub mod dataflow;
+pub mod dom;
+pub mod escape;
+pub mod liveness;
+pub mod loops;
+pub mod memory;
+pub mod regions;
+pub mod stats;
+pub mod verifier;
@@ -0,0 +1,627 @@
+//! Structured control flow ([`RegionTree`]) related implementations.
+
+use crate::analysis::cfg;
+use crate::analysis::dom::DomTree;
+use crate::analysis::loops::{Loop, LoopInfo};
+use crate::ir::{BasicBlock, FunctionData};
+use std::collections::HashMap;
+
+/// Structured regions of a function, like `if`/`else` and `while`
+/// statements, for generating readable code from the control flow
+/// graph.
+///
+/// The tree is built on the dominator tree (see [`DomTree`]). Every
+/// region starts at a basic block, covers the basic blocks dominated by
+/// it, and then either returns or continues with the basic block after
+/// the region, which is the next region in the enclosing [`Region::Seq`],
+/// the merge of the enclosing [`Region::If`], or the header of the
+/// enclosing [`Region::Loop`]. The root region covers every basic block
+/// reachable from the entry exactly once.
+///
+/// Control flow that can not be expressed without `goto`s is put into
+/// [`Region::Unstructured`] regions, including:
+///
+/// * Irreducible control flow.
+/// * Loops that exit from basic blocks other than the header (like
+///   `break` and `return` in loops), or exit to more than one basic
+///   block.
+/// * Loops whose headers branch to two basic blocks in the loop (like
+///   `do`/`while` loops).
+/// * Jumps out of arms of `if`s to other basic blocks than the merge
+///   (like `continue`), and branches with more than one merge.
+///
+/// The unstructured region covers the first basic block whose control
+/// flow can not be expressed, and all the basic blocks dominated by it.
+/// Other regions are still structured.
+///
+/// # Example
+///
+/// ```
+/// use koopa::analysis::regions::{Region, RegionTree};
+/// use koopa::front::Driver;
+///
+/// let driver: Driver<_> = r#"
+///   fun @f(@n: i32): i32 {
+///   %entry:
+///     jump %header(0)
+///
+///   %header(%i: i32):
+///     %c = lt %i, @n
+///     br %c, %body, %end
+///
+///   %body:
+///     %i1 = add %i, 1
+///     jump %header(%i1)
+///
+///   %end:
+///     ret %i
+///   }
+/// "#
+/// .into();
+/// let program = driver.generate_program().unwrap();
+/// let func = program.func(program.func_layout()[0]);
+/// let bbs: Vec<_> = func.layout().bbs().keys().copied().collect();
+///
+/// let tree = RegionTree::new(func);
+/// let Some(Region::Seq(regions)) = tree.root() else {
+///   panic!("expected a sequence");
+/// };
+/// assert_eq!(regions[0], Region::Linear(vec![bbs[0]]));
+/// assert_eq!(
+///   regions[1],
+///   Region::Loop {
+///     header: bbs[1],
+///     body: Box::new(Region::Linear(vec![bbs[2]])),
+///     exit: Some(bbs[3]),
+///   },
+/// );
+/// assert_eq!(regions[2], Region::Linear(vec![bbs[3]]));
+/// assert!(tree.root().unwrap().is_structured());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RegionTree {
+  root: Option<Region>,
+}
+
+/// A region of a function, see [`RegionTree`].
+///
+/// Arguments of branches and jumps are not recorded in regions, code
+/// generators should pass them to the parameters of the target basic
+/// blocks on each edge.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Region {
+  /// Basic blocks that run one after another. Every basic block except
+  /// the last one ends with a jump to the next one.
+  Linear(Vec<BasicBlock>),
+  /// Regions that run one after another. Every region except the last
+  /// one continues with the entry of the next one.
+  Seq(Vec<Region>),
+  /// An `if` statement.
+  If {
+    /// The basic block that ends with the branch.
+    cond_block: BasicBlock,
+    /// The region that runs if the condition is true, or `None` if the
+    /// true target is the basic block after the `if`.
+    then: Option<Box<Region>>,
+    /// The region that runs if the condition is false, or `None` if the
+    /// false target is the basic block after the `if`.
+    else_: Option<Box<Region>>,
+    /// The basic block where the arms meet, which is the entry of the
+    /// next region in the enclosing sequence. `None` if the arms return,
+    /// or meet at the basic block after the enclosing region.
+    merge: Option<BasicBlock>,
+  },
+  /// A `while` loop, or an infinite loop.
+  ///
+  /// A loop runs the header first. The header either ends with a branch
+  /// to the body and the exit, or ends with a jump to the body if the
+  /// loop never exits. The body ends with a jump to the header.
+  Loop {
+    /// The header of the loop.
+    header: BasicBlock,
+    /// The body of the loop, an empty [`Region::Linear`] if the header
+    /// branches to itself.
+    body: Box<Region>,
+    /// The basic block that the loop exits to, or `None` if the loop
+    /// never exits.
+    exit: Option<BasicBlock>,
+  },
+  /// Basic blocks that can not be structured, in reverse post order,
+  /// the first one is the entry of the region. Basic blocks in it may
+  /// jump to any basic block of the function.
+  Unstructured(Vec<BasicBlock>),
+}
+
+impl RegionTree {
+  /// Builds the region tree of the given function.
+  ///
+  /// The tree is empty if the function is a declaration.
+  pub fn new(func: &FunctionData) -> Self {
+    let order = cfg::rpo(func);
+    let Some(&entry) = order.first() else {
+      return Self::default();
+    };
+    let builder = Builder {
+      func,
+      dom: DomTree::new(func),
+      loops: LoopInfo::new(func),
+      preds: cfg::preds_map(func),
+      index: order.iter().enumerate().map(|(i, bb)| (*bb, i)).collect(),
+    };
+    Self {
+      root: Some(builder.region(entry, None)),
+    }
+  }
+
+  /// Returns the root region, or `None` if the tree is empty.
+  pub fn root(&self) -> Option<&Region> {
+    self.root.as_ref()
+  }
+}
+
+impl Region {
+  /// Returns all basic blocks in the region, in the order of the tree.
+  pub fn blocks(&self) -> Vec<BasicBlock> {
+    let mut bbs = Vec::new();
+    self.collect_blocks(&mut bbs);
+    bbs
+  }
+
+  /// Returns `true` if the region contains no [`Region::Unstructured`].
+  pub fn is_structured(&self) -> bool {
+    match self {
+      Region::Linear(_) => true,
+      Region::Seq(regions) => regions.iter().all(Region::is_structured),
+      Region::If { then, else_, .. } => [then, else_]
+        .into_iter()
+        .flatten()
+        .all(|r| r.is_structured()),
+      Region::Loop { body, .. } => body.is_structured(),
+      Region::Unstructured(_) => false,
+    }
+  }
+
+  /// Pushes all basic blocks in the region to the given vector.
+  fn collect_blocks(&self, bbs: &mut Vec<BasicBlock>) {
+    match self {
+      Region::Linear(blocks) | Region::Unstructured(blocks) => bbs.extend(blocks),
+      Region::Seq(regions) => regions.iter().for_each(|r| r.collect_blocks(bbs)),
+      Region::If {
+        cond_block,
+        then,
+        else_,
+        ..
+      } => {
+        bbs.push(*cond_block);
+        [then, else_]
+          .into_iter()
+          .flatten()
+          .for_each(|r| r.collect_blocks(bbs));
+      }
+      Region::Loop { header, body, .. } => {
+        bbs.push(*header);
+        body.collect_blocks(bbs);
+      }
+    }
+  }
+}
+
+/// Builder of region trees.
+struct Builder<'a> {
+  func: &'a FunctionData,
+  dom: DomTree,
+  loops: LoopInfo,
+  preds: HashMap<BasicBlock, Vec<BasicBlock>>,
+  /// Indices of basic blocks in reverse post order.
+  index: HashMap<BasicBlock, usize>,
+}
+
+/// A region of a basic block, and the basic block to continue with,
+/// which is `None` if the region returns, or continues with the basic
+/// block after the enclosing region.
+type Construct = (Region, Option<BasicBlock>);
+
+impl Builder<'_> {
+  /// Builds the region that starts at the given basic block, and then
+  /// continues with `follow`, or returns if `follow` is `None`.
+  ///
+  /// `follow` must not be dominated by the given basic block.
+  fn region(&self, bb: BasicBlock, follow: Option<BasicBlock>) -> Region {
+    let mut regions = Vec::new();
+    let mut cur = Some(bb);
+    while let Some(bb) = cur {
+      let (region, next) = self
+        .construct(bb, follow)
+        .unwrap_or_else(|| (Region::Unstructured(self.subtree(bb)), None));
+      match (regions.last_mut(), region) {
+        (Some(Region::Linear(bbs)), Region::Linear(new_bbs)) => bbs.extend(new_bbs),
+        (_, region) => regions.push(region),
+      }
+      cur = next;
+    }
+    if regions.len() == 1 {
+      regions.pop().unwrap()
+    } else {
+      Region::Seq(regions)
+    }
+  }
+
+  /// Builds the region of the given basic block, without the basic
+  /// blocks it continues with. Returns `None` if the control flow of
+  /// the basic block can not be structured.
+  fn construct(&self, bb: BasicBlock, follow: Option<BasicBlock>) -> Option<Construct> {
+    if let Some(lp) = self.loops.loop_of(bb) {
+      return self.loop_region(lp, follow);
+    }
+    match cfg::successors(self.func, bb).as_slice() {
+      [] => Some((Region::Linear(vec![bb]), None)),
+      [succ] => Some((Region::Linear(vec![bb]), self.next(bb, *succ, follow)?)),
+      [then, else_] => {
+        // the arms can only meet at one basic block
+        let mut merges = self
+          .dom
+          .children(bb)
+          .iter()
+          .copied()
+          .filter(|c| self.forward_preds(*c) > 1);
+        let merge = merges.next();
+        if merges.next().is_some() {
+          return None;
+        }
+        let arm = |target| {
+          if Some(target) == merge || (merge.is_none() && Some(target) == follow) {
+            Some(None)
+          } else if self.dom.idom(target) == Some(bb) {
+            Some(Some(Box::new(self.region(target, merge.or(follow)))))
+          } else {
+            None
+          }
+        };
+        let region = Region::If {
+          cond_block: bb,
+          then: arm(*then)?,
+          else_: arm(*else_)?,
+          merge,
+        };
+        Some((region, merge))
+      }
+      _ => None,
+    }
+  }
+
+  /// Builds the region of the given loop.
+  fn loop_region(&self, lp: &Loop, follow: Option<BasicBlock>) -> Option<Construct> {
+    let header = lp.header();
+    let exits: Vec<_> = lp
+      .blocks()
+      .iter()
+      .flat_map(|bb| {
+        cfg::successors(self.func, *bb)
+          .into_iter()
+          .filter(|s| !lp.contains(*s))
+          .map(move |s| (*bb, s))
+      })
+      .collect();
+    let (body, exit) = match (cfg::successors(self.func, header).as_slice(), &exits[..]) {
+      ([body], []) => (*body, None),
+      ([then, else_], [(from, exit)]) if *from == header => {
+        let body = if *then == *exit { else_ } else { then };
+        (*body, Some(*exit))
+      }
+      _ => return None,
+    };
+    let body = if body == header {
+      Region::Linear(Vec::new())
+    } else if self.dom.idom(body) == Some(header) {
+      self.region(body, Some(header))
+    } else {
+      return None;
+    };
+    let next = match exit {
+      Some(exit) => self.next(header, exit, follow)?,
+      None => None,
+    };
+    let region = Region::Loop {
+      header,
+      body: Box::new(body),
+      exit,
+    };
+    Some((region, next))
+  }
+
+  /// Returns the basic block to continue with after basic block `bb`,
+  /// which passes control to basic block `target`. Returns `None` if
+  /// `target` is neither `follow` nor immediately dominated by `bb`.
+  fn next(
+    &self,
+    bb: BasicBlock,
+    target: BasicBlock,
+    follow: Option<BasicBlock>,
+  ) -> Option<Option<BasicBlock>> {
+    if Some(target) == follow {
+      Some(None)
+    } else if self.dom.idom(target) == Some(bb) {
+      Some(Some(target))
+    } else {
+      None
+    }
+  }
+
+  /// Returns the number of predecessors of the given basic block,
+  /// excluding the ones along back edges.
+  fn forward_preds(&self, bb: BasicBlock) -> usize {
+    self.preds[&bb]
+      .iter()
+      .filter(|p| !self.dom.dominates(bb, **p))
+      .count()
+  }
+
+  /// Returns the given basic block and all basic blocks dominated by it,
+  /// in reverse post order.
+  fn subtree(&self, bb: BasicBlock) -> Vec<BasicBlock> {
+    let mut bbs = Vec::new();
+    let mut stack = vec![bb];
+    while let Some(bb) = stack.pop() {
+      bbs.push(bb);
+      stack.extend(self.dom.children(bb));
+    }
+    bbs.sort_by_key(|bb| self.index[bb]);
+    bbs
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::front::Driver;
+  use std::collections::HashSet;
+
+  /// An `if` with a loop, which contains nested `if`s.
+  const NESTED: &str = r#"
+      fun @f(@n: i32): i32 {
+      %entry:
+        br @n, %outer, %end
+
+      %outer:
+        %c = lt @n, 10
+        br %c, %body, %end
+
+      %body:
+        br @n, %then, %else
+
+      %then:
+        jump %merge
+
+      %else:
+        br @n, %inner, %merge
+
+      %inner:
+        jump %merge
+
+      %merge:
+        jump %outer
+
+      %end:
+        ret 0
+      }
+"#;
+
+  /// Nested loops, a self loop and an infinite loop.
+  const LOOPS: &str = r#"
+      fun @f(@n: i32): i32 {
+      %entry:
+        jump %outer
+
+      %outer:
+        br @n, %inner, %self
+
+      %inner:
+        br @n, %inner_body, %outer_latch
+
+      %inner_body:
+        jump %inner
+
+      %outer_latch:
+        jump %outer
+
+      %self:
+        br @n, %self, %forever
+
+      %forever:
+        jump %forever_body
+
+      %forever_body:
+        br @n, %then, %forever
+
+      %then:
+        jump %forever
+      }
+"#;
+
+  /// Builds the region tree of the first function in the given program,
+  /// checks that the tree covers every reachable basic block once, and
+  /// returns the tree with basic blocks replaced by their names.
+  fn regions(src: &str) -> String {
+    let driver: Driver<_> = src.into();
+    let program = driver.generate_program().unwrap();
+    let func = program.func(program.func_layout()[0]);
+    let tree = RegionTree::new(func);
+    let root = tree.root().unwrap();
+    let bbs = root.blocks();
+    let reachable: HashSet<_> = cfg::rpo(func).into_iter().collect();
+    assert_eq!(bbs.len(), reachable.len());
+    assert_eq!(bbs.into_iter().collect::<HashSet<_>>(), reachable);
+    let name = |bb: &BasicBlock| func.dfg().bb(*bb).name().clone().unwrap();
+    let mut text = format!("{root:?}");
+    for bb in func.layout().bbs().keys() {
+      text = text.replace(&format!("{bb:?}"), &name(bb));
+    }
+    text
+  }
+
+  #[test]
+  fn nested_regions() {
+    let text = regions(NESTED);
+    assert_eq!(
+      text,
+      "Seq([If { cond_block: %entry, then: Some(Loop { header: %outer, body: \
+       Seq([If { cond_block: %body, then: Some(Linear([%then])), else_: \
+       Some(If { cond_block: %else, then: Some(Linear([%inner])), else_: \
+       None, merge: None }), merge: Some(%merge) }, Linear([%merge])]), \
+       exit: Some(%end) }), else_: None, merge: Some(%end) }, Linear([%end])])"
+    );
+  }
+
+  #[test]
+  fn unstructured_fallbacks() {
+    // `break` leaves the loop from the body
+    let text = regions(
+      r#"
+      fun @f(@n: i32): i32 {
+      %entry:
+        jump %header
+
+      %header:
+        br @n, %body, %end
+
+      %body:
+        br @n, %end, %latch
+
+      %latch:
+        jump %header
+
+      %end:
+        ret 0
+      }
+    "#,
+    );
+    assert_eq!(
+      text,
+      "Seq([Linear([%entry]), Unstructured([%header, %body, %latch, %end])])"
+    );
+    // irreducible loop in an arm, the code after it is still structured
+    let text = regions(
+      r#"
+      fun @f(@n: i32): i32 {
+      %entry:
+        br @n, %pre, %end
+
+      %pre:
+        br @n, %a, %b
+
+      %a:
+        br @n, %b, %end
+
+      %b:
+        br @n, %a, %end
+
+      %end:
+        ret 0
+      }
+    "#,
+    );
+    assert_eq!(
+      text,
+      "Seq([If { cond_block: %entry, then: Some(Unstructured([%pre, %a, %b])), \
+       else_: None, merge: Some(%end) }, Linear([%end])])"
+    );
+  }
+
+  /// Number of basic blocks visited by [`Walk`].
+  const STEPS: usize = 64;
+
+  /// Walks basic blocks of a function, and takes branches by the given
+  /// bits, until returning or visiting [`STEPS`] basic blocks.
+  struct Walk<'a> {
+    func: &'a FunctionData,
+    bits: u64,
+    trace: Vec<BasicBlock>,
+  }
+
+  impl<'a> Walk<'a> {
+    fn new(func: &'a FunctionData, bits: u64) -> Self {
+      Self {
+        func,
+        bits,
+        trace: Vec::new(),
+      }
+    }
+
+    /// Visits the given basic block, returns `None` if the walk stops.
+    fn visit(&mut self, bb: BasicBlock) -> Option<()> {
+      self.trace.push(bb);
+      (self.trace.len() < STEPS && !cfg::successors(self.func, bb).is_empty()).then_some(())
+    }
+
+    /// Returns the next branch direction.
+    fn take(&mut self) -> bool {
+      self.bits = self.bits.rotate_right(1);
+      self.bits & 1 != 0
+    }
+
+    /// Walks along the edges of the control flow graph.
+    fn walk_cfg(&mut self, mut bb: BasicBlock) {
+      while self.visit(bb).is_some() {
+        let succs = cfg::successors(self.func, bb);
+        bb = match succs.as_slice() {
+          [succ] => *succ,
+          _ => succs[if self.take() { 0 } else { 1 }],
+        };
+      }
+    }
+
+    /// Walks through the given structured region.
+    fn walk_region(&mut self, region: &Region) -> Option<()> {
+      match region {
+        Region::Linear(bbs) => bbs.iter().try_for_each(|bb| self.visit(*bb)),
+        Region::Seq(regions) => regions.iter().try_for_each(|r| self.walk_region(r)),
+        Region::If {
+          cond_block,
+          then,
+          else_,
+          ..
+        } => {
+          self.visit(*cond_block)?;
+          let arm = if self.take() { then } else { else_ };
+          arm.as_ref().map_or(Some(()), |r| self.walk_region(r))
+        }
+        Region::Loop { header, body, exit } => loop {
+          self.visit(*header)?;
+          if exit.is_some() {
+            let succs = cfg::successors(self.func, *header);
+            if Some(succs[if self.take() { 0 } else { 1 }]) == *exit {
+              return Some(());
+            }
+          }
+          self.walk_region(body)?;
+        },
+        Region::Unstructured(_) => panic!("unstructured region"),
+      }
+    }
+  }
+
+  #[test]
+  fn same_paths_as_cfg() {
+    for src in [NESTED, LOOPS] {
+      let driver: Driver<_> = src.into();
+      let program = driver.generate_program().unwrap();
+      let func = program.func(program.func_layout()[0]);
+      let root = RegionTree::new(func).root().cloned().unwrap();
+      assert!(root.is_structured());
+      let entry = func.layout().entry_bb().unwrap();
+      for bits in [
+        0,
+        u64::MAX,
+        0x5555_5555_5555_5555,
+        0x0123_4567_89ab_cdef,
+        0xa5a5_0ff0_3c3c_9669,
+      ] {
+        let mut cfg = Walk::new(func, bits);
+        cfg.walk_cfg(entry);
+        let mut structured = Walk::new(func, bits);
+        structured.walk_region(&root);
+        assert_eq!(structured.trace, cfg.trace);
+      }
+    }
+  }
+}
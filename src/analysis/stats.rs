@@ -0,0 +1,313 @@
+//! Program statistics ([`ProgramStats`]) related implementations.
+//!
+//! # Example
+//!
+//! ```
+//! use koopa::analysis::stats;
+//! use koopa::front::Driver;
+//!
+//! let driver: Driver<_> = r#"
+//!   global @arr = alloc [i32, 4], zeroinit
+//!
+//!   fun @main(): i32 {
+//!   %entry:
+//!     %0 = getelemptr @arr, 1
+//!     %1 = load %0
+//!     ret %1
+//!   }
+//! "#
+//! .into();
+//! let program = driver.generate_program().unwrap();
+//!
+//! let stats = stats::collect(&program);
+//! assert_eq!(stats.global_bytes, 16);
+//! assert_eq!(stats.num_insts(), 3);
+//! assert_eq!(stats.insts["load"], 1);
+//! println!("{stats}");
+//! ```
+
+use crate::ir::{FunctionData, Program, ValueKind};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Collects statistics of the given program.
+pub fn collect(program: &Program) -> ProgramStats {
+  let values = program.borrow_values();
+  let global_bytes = program
+    .inst_layout()
+    .iter()
+    .map(|v| match values[v].kind() {
+      ValueKind::GlobalAlloc(alloc) => values[&alloc.init()].ty().size(),
+      _ => panic!("invalid global instruction"),
+    })
+    .sum();
+  let mut stats = ProgramStats {
+    globals: program.inst_layout().len(),
+    global_bytes,
+    ..Default::default()
+  };
+  for &func in program.func_layout() {
+    let data = program.func(func);
+    if data.layout().entry_bb().is_none() {
+      stats.decls += 1;
+      continue;
+    }
+    let func = FunctionStats::new(data);
+    for (kind, count) in &func.insts {
+      *stats.insts.entry(kind).or_default() += count;
+    }
+    stats.funcs.push(func);
+  }
+  stats
+}
+
+/// Statistics of a program, returned by [`collect`].
+///
+/// The statistics can be displayed as aligned tables.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProgramStats {
+  /// Statistics of function definitions, in the order of the function
+  /// layout.
+  pub funcs: Vec<FunctionStats>,
+  /// Number of function declarations.
+  pub decls: usize,
+  /// Number of global allocations.
+  pub globals: usize,
+  /// Total size of global allocations in bytes.
+  pub global_bytes: usize,
+  /// Numbers of instructions in all functions, keyed by kind names
+  /// (see [`FunctionStats::insts`]).
+  pub insts: HashMap<&'static str, usize>,
+}
+
+impl ProgramStats {
+  /// Returns the number of instructions in all functions.
+  pub fn num_insts(&self) -> usize {
+    self.funcs.iter().map(FunctionStats::num_insts).sum()
+  }
+
+  /// Returns the number of basic blocks in all functions.
+  pub fn num_bbs(&self) -> usize {
+    self.funcs.iter().map(|f| f.bbs).sum()
+  }
+
+  /// Returns the average number of operands per instruction
+  /// in all functions, or `0` if there are no instructions.
+  pub fn avg_operands(&self) -> f64 {
+    let operands = self.funcs.iter().map(|f| f.operands).sum();
+    average(operands, self.num_insts())
+  }
+}
+
+impl fmt::Display for ProgramStats {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    // functions
+    let mut rows: Vec<_> = self
+      .funcs
+      .iter()
+      .map(|func| {
+        [
+          func.name.clone(),
+          func.bbs.to_string(),
+          func.num_insts().to_string(),
+          func.max_bb_insts.to_string(),
+          format!("{:.2}", func.avg_operands()),
+        ]
+      })
+      .collect();
+    rows.push([
+      "total".into(),
+      self.num_bbs().to_string(),
+      self.num_insts().to_string(),
+      self
+        .funcs
+        .iter()
+        .map(|f| f.max_bb_insts)
+        .max()
+        .unwrap_or(0)
+        .to_string(),
+      format!("{:.2}", self.avg_operands()),
+    ]);
+    let header = ["function", "bbs", "insts", "max bb insts", "avg operands"];
+    write_table(f, header, &rows)?;
+    // instructions
+    let mut kinds: Vec<_> = self.insts.iter().collect();
+    kinds.sort_unstable();
+    let rows: Vec<_> = kinds
+      .into_iter()
+      .map(|(kind, count)| [kind.to_string(), count.to_string()])
+      .collect();
+    writeln!(f)?;
+    write_table(f, ["kind", "count"], &rows)?;
+    // others
+    writeln!(f)?;
+    writeln!(f, "declarations: {}", self.decls)?;
+    writeln!(f, "globals: {} ({} bytes)", self.globals, self.global_bytes)
+  }
+}
+
+/// Statistics of a function definition.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FunctionStats {
+  /// Name of the function.
+  pub name: String,
+  /// Number of basic blocks in the layout.
+  pub bbs: usize,
+  /// Maximum number of instructions in a basic block.
+  pub max_bb_insts: usize,
+  /// Numbers of instructions in the layout, keyed by kind names
+  /// in text form, like `alloc`, `binary`, `br` and `ret`.
+  pub insts: HashMap<&'static str, usize>,
+  /// Total number of value operands of instructions.
+  pub operands: usize,
+}
+
+impl FunctionStats {
+  /// Collects statistics of the given function.
+  fn new(data: &FunctionData) -> Self {
+    let mut stats = Self {
+      name: data.name().into(),
+      ..Default::default()
+    };
+    for (_, node) in data.layout().bbs() {
+      stats.bbs += 1;
+      stats.max_bb_insts = stats.max_bb_insts.max(node.insts().len());
+      for inst in node.insts().keys() {
+        let kind = data.dfg().value(*inst).kind();
+        *stats.insts.entry(kind_name(kind)).or_default() += 1;
+        stats.operands += kind.value_uses().count();
+      }
+    }
+    stats
+  }
+
+  /// Returns the number of instructions.
+  pub fn num_insts(&self) -> usize {
+    self.insts.values().sum()
+  }
+
+  /// Returns the average number of operands per instruction,
+  /// or `0` if there are no instructions.
+  pub fn avg_operands(&self) -> f64 {
+    average(self.operands, self.num_insts())
+  }
+}
+
+/// Returns the kind name of the given instruction.
+fn kind_name(kind: &ValueKind) -> &'static str {
+  match kind {
+    ValueKind::Alloc(_) => "alloc",
+    ValueKind::Load(_) => "load",
+    ValueKind::Store(_) => "store",
+    ValueKind::GetPtr(_) => "getptr",
+    ValueKind::GetElemPtr(_) => "getelemptr",
+    ValueKind::Binary(_) => "binary",
+    ValueKind::Branch(_) => "br",
+    ValueKind::Jump(_) => "jump",
+    ValueKind::Call(_) => "call",
+    ValueKind::Return(_) => "ret",
+    ValueKind::Unreachable(_) => "unreachable",
+    _ => panic!("invalid local instruction"),
+  }
+}
+
+/// Returns `sum / count`, or `0` if `count` is zero.
+fn average(sum: usize, count: usize) -> f64 {
+  if count == 0 {
+    0.0
+  } else {
+    sum as f64 / count as f64
+  }
+}
+
+/// Writes a table with the given header and rows. The first column is
+/// left-aligned, and the other columns are right-aligned.
+fn write_table<const N: usize>(
+  f: &mut fmt::Formatter,
+  header: [&str; N],
+  rows: &[[String; N]],
+) -> fmt::Result {
+  let mut widths = header.map(str::len);
+  for row in rows {
+    for (w, cell) in widths.iter_mut().zip(row) {
+      *w = (*w).max(cell.len());
+    }
+  }
+  let header = header.map(String::from);
+  for row in Some(&header).into_iter().chain(rows) {
+    for (i, (cell, w)) in row.iter().zip(widths).enumerate() {
+      if i == 0 {
+        write!(f, "{cell:<w$}")?;
+      } else {
+        write!(f, "  {cell:>w$}")?;
+      }
+    }
+    writeln!(f)?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::front::Driver;
+
+  #[test]
+  fn collect_stats() {
+    let driver: Driver<_> = r#"
+      global @x = alloc i32, 1
+      global @arr = alloc [[i32, 3], 2], zeroinit
+      decl @getint(): i32
+
+      fun @f(@a: i32): i32 {
+      %entry:
+        %0 = add @a, 1
+        br %0, %then, %end
+      %then:
+        store %0, @x
+        jump %end
+      %end:
+        ret %0
+      }
+
+      fun @main(): i32 {
+      %entry:
+        %0 = call @getint()
+        %1 = call @f(%0)
+        ret %1
+      }
+    "#
+    .into();
+    let program = driver.generate_program().unwrap();
+    let stats = collect(&program);
+    assert_eq!(stats.decls, 1);
+    assert_eq!(stats.globals, 2);
+    assert_eq!(stats.global_bytes, 28);
+    assert_eq!(stats.num_bbs(), 4);
+    assert_eq!(stats.num_insts(), 8);
+    assert_eq!(stats.funcs[0].max_bb_insts, 2);
+    assert_eq!(stats.funcs[0].operands, 6);
+    assert_eq!(stats.avg_operands(), 8.0 / 8.0);
+    assert_eq!(stats.insts["call"], 2);
+    assert_eq!(stats.insts["ret"], 2);
+    assert_eq!(
+      stats.to_string(),
+      r#"function  bbs  insts  max bb insts  avg operands
+@f          3      5             2          1.20
+@main       1      3             3          0.67
+total       4      8             3          1.00
+
+kind    count
+binary      1
+br          1
+call        2
+jump        1
+ret         2
+store       1
+
+declarations: 1
+globals: 2 (28 bytes)
+"#
+    );
+  }
+}
@@ -0,0 +1,753 @@
+//! Structural verifier ([`verify`]) related implementations.
+//!
+//! IR builders check the types of operands when creating values, but
+//! passes that edit the layout or the operands directly can still leave
+//! a program in an inconsistent state. The verifier reports the
+//! following kinds of errors in global allocations:
+//!
+//! * Initializers that do not exist, or whose types do not match the
+//!   allocated types.
+//! * Aggregates or byte data whose lengths do not match the lengths of
+//!   their array types.
+//!
+//! And the following kinds of errors in functions:
+//!
+//! * Basic blocks or instructions in the layout that do not exist in
+//!   the data flow graph, values in the layout that are not local
+//!   instructions, and instructions in the data flow graph that are not
+//!   in the layout. See [`FunctionData::prune_dangling_layout_entries`]
+//!   and [`FunctionData::collect_garbage`] for repairing them.
+//! * Empty basic blocks, basic blocks that are not terminated, and
+//!   terminators in the middle of basic blocks.
+//! * Operands that do not exist, or are neither constants nor defined
+//!   in the function (parameters, basic block parameters and
+//!   instructions in the layout).
+//! * Target basic blocks that are not in the layout, and callees that
+//!   do not exist.
+//! * Entry basic blocks with parameters or predecessors.
+//! * Returns whose values (or unit if nothing is returned) do not
+//!   match the return types of functions, and returns of unit values.
+//! * Byte data used by instructions, which are only valid in global
+//!   initializers.
+//! * Violated ordering constraints between instructions, see
+//!   [`FunctionData::try_add_order_constraint`].
+//!
+//! # Example
+//!
+//! ```
+//! use koopa::analysis::verifier;
+//! use koopa::front::Driver;
+//!
+//! let driver: Driver<_> = r#"
+//!   fun @main(): i32 {
+//!   %entry:
+//!     %sum = add 1, 2
+//!     ret %sum
+//!   }
+//! "#
+//! .into();
+//! let mut program = driver.generate_program().unwrap();
+//! assert!(verifier::verify(&program).is_ok());
+//!
+//! // remove the `add` instruction from the layout
+//! let main = program.func_layout()[0];
+//! let data = program.func_mut(main);
+//! let entry = data.layout().entry_bb().unwrap();
+//! data.layout_mut().bb_mut(entry).insts_mut().pop_front();
+//!
+//! let errors = verifier::verify(&program).unwrap_err();
+//! assert_eq!(errors.len(), 2);
+//! assert_eq!(
+//!   errors[0].to_string(),
+//!   "@main, %entry: `ret` uses %sum, which is not defined in the function",
+//! );
+//! assert_eq!(
+//!   errors[1].to_string(),
+//!   "@main: `binary` instruction %sum is not in the layout",
+//! );
+//! ```
+
+use crate::diagnostic::{code, Diagnostic, Location, Severity};
+use crate::ir::{
+  BasicBlock, Error, Function, FunctionData, Program, Type, TypeKind, Value, ValueKind,
+};
+use std::collections::HashSet;
+use std::{error, fmt};
+
+/// Verifies all global allocations and functions in the given program.
+///
+/// Returns all errors found in the program if it is invalid.
+pub fn verify(program: &Program) -> Result<(), Vec<VerifyError>> {
+  let mut errors = verify_globals(program);
+  errors.extend(
+    program
+      .func_layout()
+      .iter()
+      .flat_map(|&func| Verifier::new(program, program.func(func)).run()),
+  );
+  if errors.is_empty() {
+    Ok(())
+  } else {
+    Err(errors)
+  }
+}
+
+/// Verifies the given function in the given program.
+///
+/// Returns all errors found in the function if it is invalid.
+///
+/// # Panics
+///
+/// Panics if the given function does not exist.
+pub fn verify_func(program: &Program, func: Function) -> Result<(), Vec<VerifyError>> {
+  let errors = Verifier::new(program, program.func(func)).run();
+  if errors.is_empty() {
+    Ok(())
+  } else {
+    Err(errors)
+  }
+}
+
+/// Verifies the initializers of all global allocations.
+fn verify_globals(program: &Program) -> Vec<VerifyError> {
+  let values = program.borrow_values();
+  let mut errors = Vec::new();
+  for value in program.inst_layout() {
+    let data = &values[value];
+    let (ValueKind::GlobalAlloc(alloc), TypeKind::Pointer(ty)) = (data.kind(), data.ty().kind())
+    else {
+      continue;
+    };
+    let name = data.name().clone().unwrap_or_else(|| format!("{value:?}"));
+    let mut error = |code, message| {
+      errors.push(VerifyError {
+        code,
+        func: name.clone(),
+        bb: None,
+        message,
+      })
+    };
+    let mut worklist: Vec<(Value, Type)> = vec![(alloc.init(), ty.clone())];
+    while let Some((init, ty)) = worklist.pop() {
+      let Some(data) = values.get(&init) else {
+        error(
+          code::INIT_NOT_EXIST,
+          format!("initializer {init:?} does not exist"),
+        );
+        continue;
+      };
+      if data.ty() != &ty {
+        error(
+          code::INIT_TYPE_MISMATCH,
+          format!("initializer has type `{}`, expected `{ty}`", data.ty()),
+        );
+        continue;
+      }
+      match (data.kind(), ty.kind()) {
+        (ValueKind::Bytes(b), TypeKind::Array(_, len)) if b.bytes().len() != *len => error(
+          code::BYTES_LEN_MISMATCH,
+          format!("byte data has {} bytes, expected {len}", b.bytes().len()),
+        ),
+        (ValueKind::Aggregate(a), TypeKind::Array(base, len)) => {
+          if a.elems().len() != *len {
+            error(
+              code::AGGREGATE_LEN_MISMATCH,
+              format!("aggregate has {} elements, expected {len}", a.elems().len()),
+            );
+          }
+          worklist.extend(a.elems().iter().rev().map(|e| (*e, base.clone())));
+        }
+        _ => {}
+      }
+    }
+  }
+  errors
+}
+
+/// An error reported by the verifier.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerifyError {
+  /// Code of the error, see [`diagnostic::code`](crate::diagnostic::code).
+  pub code: &'static str,
+  /// Name of the function, or name of the global allocation for errors
+  /// in global initializers.
+  pub func: String,
+  /// Name of the basic block, or `None` if the error is not specific to
+  /// a basic block.
+  pub bb: Option<String>,
+  /// The error message.
+  pub message: String,
+}
+
+impl VerifyError {
+  /// Converts the current error to a [`Diagnostic`].
+  pub fn to_diagnostic(&self) -> Diagnostic {
+    Diagnostic::new(self.code, Severity::Error, self.message.clone())
+      .with_primary(Location::ir(self.func.clone(), self.bb.clone()))
+  }
+}
+
+impl fmt::Display for VerifyError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    self.to_diagnostic().fmt(f)
+  }
+}
+
+impl error::Error for VerifyError {}
+
+/// Verifier of a function.
+struct Verifier<'a> {
+  program: &'a Program,
+  data: &'a FunctionData,
+  defined: HashSet<Value>,
+  errors: Vec<VerifyError>,
+}
+
+impl<'a> Verifier<'a> {
+  fn new(program: &'a Program, data: &'a FunctionData) -> Self {
+    Self {
+      program,
+      data,
+      defined: HashSet::new(),
+      errors: Vec::new(),
+    }
+  }
+
+  /// Runs the verifier and returns all errors.
+  fn run(mut self) -> Vec<VerifyError> {
+    let dfg = self.data.dfg();
+    self.defined.extend(self.data.params());
+    for (&bb, node) in self.data.layout().bbs() {
+      match dfg.bbs().get(&bb) {
+        Some(bb) => self.defined.extend(bb.params()),
+        None => self.error(
+          None,
+          code::BB_NOT_EXIST,
+          format!("{bb:?} in the layout does not exist"),
+        ),
+      }
+      self.defined.extend(node.insts().keys());
+    }
+    if let Some(entry) = self.data.entry().filter(|bb| dfg.bbs().contains_key(bb)) {
+      self.verify_entry(entry);
+    }
+    for (&bb, node) in self.data.layout().bbs() {
+      if !dfg.bbs().contains_key(&bb) {
+        continue;
+      }
+      if node.insts().is_empty() {
+        self.error(Some(bb), code::EMPTY_BB, "basic block is empty".into());
+      }
+      let len = node.insts().len();
+      for (i, &inst) in node.insts().keys().enumerate() {
+        self.verify_inst(bb, inst, i + 1 == len);
+      }
+    }
+    self.verify_unplaced();
+    self.verify_order();
+    self.errors
+  }
+
+  /// Verifies that all ordering constraints hold.
+  fn verify_order(&mut self) {
+    for (before, after) in self.data.violated_order_constraints() {
+      let msg = format!(
+        "{} must be placed before {}",
+        self.value_name(before),
+        self.value_name(after)
+      );
+      let bb = self.data.layout().parent_bb(after);
+      self.error(bb, code::ORDER_VIOLATION, msg);
+    }
+  }
+
+  /// Verifies that all instructions in the data flow graph are in the
+  /// layout.
+  fn verify_unplaced(&mut self) {
+    let layout = self.data.layout();
+    let unplaced: Vec<_> = self
+      .data
+      .dfg()
+      .values()
+      .iter()
+      .filter(|(v, d)| d.kind().is_local_inst() && layout.parent_bb(**v).is_none())
+      .map(|(v, d)| (*v, inst_name(d.kind())))
+      .collect();
+    for (inst, name) in unplaced {
+      let msg = format!(
+        "`{name}` instruction {} is not in the layout",
+        self.value_name(inst)
+      );
+      self.error(None, code::INST_NOT_IN_LAYOUT, msg);
+    }
+  }
+
+  /// Verifies that the given entry basic block has no parameters and no
+  /// predecessors.
+  fn verify_entry(&mut self, entry: BasicBlock) {
+    let data = self.data.dfg().bb(entry);
+    if !data.params().is_empty() {
+      self.error(
+        Some(entry),
+        code::ENTRY_HAS_PARAMS,
+        "entry basic block has parameters".into(),
+      );
+    }
+    let layout = self.data.layout();
+    if data
+      .used_by()
+      .iter()
+      .any(|u| layout.parent_bb(*u).is_some())
+    {
+      self.error(
+        Some(entry),
+        code::ENTRY_HAS_PREDS,
+        "entry basic block has predecessors".into(),
+      );
+    }
+  }
+
+  /// Verifies an instruction in the given basic block.
+  fn verify_inst(&mut self, bb: BasicBlock, inst: Value, is_last: bool) {
+    let Some(data) = self.data.dfg().values().get(&inst) else {
+      let msg = format!("instruction {inst:?} in the layout does not exist");
+      return self.error(Some(bb), code::INST_NOT_EXIST, msg);
+    };
+    let kind = data.kind();
+    if !kind.is_local_inst() {
+      let msg = format!("{} is not an instruction", self.value_name(inst));
+      return self.error(Some(bb), code::NOT_INST, msg);
+    }
+    if kind.is_terminator() && !is_last {
+      let msg = format!("`{}` is not at the end of the basic block", inst_name(kind));
+      self.error(Some(bb), code::MISPLACED_TERMINATOR, msg);
+    } else if !kind.is_terminator() && is_last {
+      self.error(
+        Some(bb),
+        code::UNTERMINATED_BB,
+        "basic block is not terminated".into(),
+      );
+    }
+    for value in kind.value_uses() {
+      if let Some((code, reason)) = self.check_operand(value) {
+        let msg = format!("`{}` uses {reason}", inst_name(kind));
+        self.error(Some(bb), code, msg);
+      }
+    }
+    for target in kind.bb_uses() {
+      if self.data.layout().bbs().node(&target).is_none() {
+        let msg = format!(
+          "`{}` targets {}, which is not in the layout",
+          inst_name(kind),
+          self.bb_name(target)
+        );
+        self.error(Some(bb), code::TARGET_NOT_IN_LAYOUT, msg);
+      }
+    }
+    if let ValueKind::Call(call) = kind {
+      if !self.program.funcs().contains_key(&call.callee()) {
+        let msg = format!("`call` calls {:?}, which does not exist", call.callee());
+        self.error(Some(bb), code::CALLEE_NOT_EXIST, msg);
+      }
+    }
+    if let ValueKind::Return(ret) = kind {
+      self.verify_return(bb, ret.value());
+    }
+  }
+
+  /// Verifies that the value returned in the given basic block matches
+  /// the return type of the function.
+  fn verify_return(&mut self, bb: BasicBlock, value: Option<Value>) {
+    let TypeKind::Function(_, expected) = self.data.ty().kind() else {
+      panic!("expected a function type");
+    };
+    let found = match value {
+      // operands that do not exist have already been reported
+      Some(value) => match self.value_type(value) {
+        Some(ty) if ty.is_unit() => {
+          let msg = format!(
+            "`ret` returns {}, which is of unit type",
+            self.value_name(value)
+          );
+          return self.error(Some(bb), code::RET_MISMATCH, msg);
+        }
+        Some(ty) => ty,
+        None => return,
+      },
+      None => Type::get_unit(),
+    };
+    if found != *expected {
+      let msg = Error::RetMismatch {
+        expected: expected.clone(),
+        found,
+      }
+      .to_string();
+      self.error(Some(bb), code::RET_MISMATCH, msg);
+    }
+  }
+
+  /// Checks the given operand, returns the error code and the reason
+  /// if it is invalid.
+  fn check_operand(&self, value: Value) -> Option<(&'static str, String)> {
+    if value.is_global() {
+      return (!self.program.borrow_values().contains_key(&value)).then(|| {
+        let reason = format!("global value {value:?}, which does not exist");
+        (code::OPERAND_NOT_EXIST, reason)
+      });
+    }
+    match self.data.dfg().values().get(&value) {
+      None => Some((
+        code::OPERAND_NOT_EXIST,
+        format!("{value:?}, which does not exist"),
+      )),
+      Some(data) if data.kind().is_const() => self.has_bytes(value).then(|| {
+        let reason = "byte data, which can only be used in initializers of global allocations";
+        (code::BYTES_IN_INST, reason.into())
+      }),
+      Some(_) if self.defined.contains(&value) => None,
+      Some(_) => Some((
+        code::OPERAND_NOT_DEFINED,
+        format!(
+          "{}, which is not defined in the function",
+          self.value_name(value)
+        ),
+      )),
+    }
+  }
+
+  /// Checks if the given local constant is or contains byte data.
+  fn has_bytes(&self, value: Value) -> bool {
+    let mut worklist = vec![value];
+    while let Some(value) = worklist.pop() {
+      match self.data.dfg().values().get(&value).map(|v| v.kind()) {
+        Some(ValueKind::Bytes(_)) => return true,
+        Some(ValueKind::Aggregate(a)) => worklist.extend(a.elems()),
+        _ => {}
+      }
+    }
+    false
+  }
+
+  /// Returns the type of the given value, or `None` if the value does
+  /// not exist.
+  fn value_type(&self, value: Value) -> Option<Type> {
+    if value.is_global() {
+      let values = self.program.borrow_values();
+      values.get(&value).map(|v| v.ty().clone())
+    } else {
+      self.data.dfg().values().get(&value).map(|v| v.ty().clone())
+    }
+  }
+
+  /// Returns the name of the given local value for error messages.
+  fn value_name(&self, value: Value) -> String {
+    match self
+      .data
+      .dfg()
+      .values()
+      .get(&value)
+      .and_then(|v| v.name().clone())
+    {
+      Some(name) => name,
+      None => format!("{value:?}"),
+    }
+  }
+
+  /// Returns the name of the given basic block for error messages.
+  fn bb_name(&self, bb: BasicBlock) -> String {
+    match self
+      .data
+      .dfg()
+      .bbs()
+      .get(&bb)
+      .and_then(|b| b.name().clone())
+    {
+      Some(name) => name,
+      None => format!("{bb:?}"),
+    }
+  }
+
+  /// Records an error.
+  fn error(&mut self, bb: Option<BasicBlock>, code: &'static str, message: String) {
+    let bb = bb.map(|bb| self.bb_name(bb));
+    self.errors.push(VerifyError {
+      code,
+      func: self.data.name().into(),
+      bb,
+      message,
+    });
+  }
+}
+
+/// Returns the name of the given instruction kind in text form.
+fn inst_name(kind: &ValueKind) -> &'static str {
+  match kind {
+    ValueKind::Alloc(_) => "alloc",
+    ValueKind::Load(_) => "load",
+    ValueKind::Store(_) => "store",
+    ValueKind::GetPtr(_) => "getptr",
+    ValueKind::GetElemPtr(_) => "getelemptr",
+    ValueKind::Binary(_) => "binary",
+    ValueKind::Branch(_) => "br",
+    ValueKind::Jump(_) => "jump",
+    ValueKind::Call(_) => "call",
+    ValueKind::Return(_) => "ret",
+    ValueKind::Unreachable(_) => "unreachable",
+    _ => "value",
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::front::Driver;
+  use crate::ir::builder_traits::*;
+  use crate::ir::ValueLocation;
+
+  #[test]
+  fn verify_broken_funcs() {
+    let driver: Driver<_> = r#"
+      decl @f()
+
+      fun @main(): i32 {
+      %entry:
+        %0 = add 1, 2
+        jump %next
+
+      %next:
+        call @f()
+        ret %0
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    assert_eq!(verify(&program), Ok(()));
+    let main = program.func_layout()[1];
+    let data = program.func_mut(main);
+    let bbs: Vec<_> = data.layout().bbs().keys().copied().collect();
+    // terminator in the middle, and a target not in the layout
+    let other = data.dfg_mut().new_bb().basic_block(Some("%other".into()));
+    let jump = data.dfg_mut().new_value().jump(other);
+    data
+      .layout_mut()
+      .bb_mut(bbs[1])
+      .insts_mut()
+      .push_key_front(jump)
+      .unwrap();
+    // unterminated basic block
+    let entry_jump = *data
+      .layout()
+      .bbs()
+      .node(&bbs[0])
+      .unwrap()
+      .insts()
+      .back_key()
+      .unwrap();
+    data.remove_inst(entry_jump);
+    let errors: Vec<_> = verify(&program)
+      .unwrap_err()
+      .into_iter()
+      .map(|e| e.to_string())
+      .collect();
+    assert_eq!(
+      errors,
+      [
+        "@main, %entry: basic block is not terminated",
+        "@main, %next: `jump` is not at the end of the basic block",
+        "@main, %next: `jump` targets %other, which is not in the layout",
+      ]
+    );
+    // removed callee
+    program.remove_func(program.func_layout()[0]);
+    let errors = verify_func(&program, main).unwrap_err();
+    assert_eq!(errors.len(), 4);
+    assert!(errors[3].message.starts_with("`call` calls Function("));
+    assert_eq!(errors[3].code, code::CALLEE_NOT_EXIST);
+  }
+
+  #[test]
+  fn verify_dangling_insts() {
+    let driver: Driver<_> = r#"
+      fun @main(): i32 {
+      %entry:
+        %x = add 1, 2
+        %y = mul %x, 3
+        jump %end
+
+      %end:
+        ret %x
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    let main = program.func_layout()[0];
+    let data = program.func_mut(main);
+    let entry = data.layout().entry_bb().unwrap();
+    let insts: Vec<_> = data
+      .layout()
+      .bbs()
+      .node(&entry)
+      .unwrap()
+      .insts()
+      .keys()
+      .copied()
+      .collect();
+    // `%y` is removed from the data flow graph only, `%x` from the layout only
+    data.dfg_mut().remove_value(insts[1]);
+    data
+      .layout_mut()
+      .bb_mut(entry)
+      .insts_mut()
+      .remove(&insts[0]);
+    let errors = verify(&program).unwrap_err();
+    let codes: Vec<_> = errors.iter().map(|e| e.code).collect();
+    assert_eq!(
+      codes,
+      [
+        code::INST_NOT_EXIST,
+        code::OPERAND_NOT_DEFINED,
+        code::INST_NOT_IN_LAYOUT
+      ]
+    );
+    assert_eq!(errors[0].func, "@main");
+    assert_eq!(errors[0].bb.as_deref(), Some("%entry"));
+    assert_eq!(
+      errors[2].to_string(),
+      "@main: `binary` instruction %x is not in the layout"
+    );
+    // prune the dangling entry, and place `%x` back
+    let data = program.func_mut(main);
+    assert_eq!(data.prune_dangling_layout_entries(), [insts[1]]);
+    assert!(data.prune_dangling_layout_entries().is_empty());
+    data
+      .layout_mut()
+      .bb_mut(entry)
+      .insts_mut()
+      .push_key_front(insts[0])
+      .unwrap();
+    assert_eq!(verify(&program), Ok(()));
+  }
+
+  #[test]
+  fn verify_returns() {
+    let driver: Driver<_> = r#"
+      decl @h()
+
+      fun @f(): i32 {
+      %entry:
+        ret 0
+      }
+
+      fun @g() {
+      %entry:
+        call @h()
+        ret
+      }
+
+      fun @p(@a: *i32): *i32 {
+      %entry:
+        %x = load @a
+        ret @a
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    assert_eq!(verify(&program), Ok(()));
+    let value = |program: &Program, name: &str| {
+      let name = Some(name.to_string());
+      program
+        .funcs()
+        .values()
+        .flat_map(|f| f.dfg().values())
+        .find(|(_, d)| *d.name() == name)
+        .map(|(v, _)| *v)
+        .unwrap()
+    };
+    let x = value(&program, "%x");
+    let funcs = program.func_layout().to_vec();
+    // name the result of the call, so it can be shown in the error
+    let g = program.func_mut(funcs[2]);
+    let entry = g.layout().entry_bb().unwrap();
+    let u = *g
+      .layout()
+      .bbs()
+      .node(&entry)
+      .unwrap()
+      .insts()
+      .front_key()
+      .unwrap();
+    g.dfg_mut().set_value_name(u, Some("%u".into()));
+    program.for_each_value_mut(|loc, _, data| {
+      let ValueKind::Return(ret) = data.kind_mut() else {
+        return;
+      };
+      match loc {
+        ValueLocation::Local(f, _) if f == funcs[1] => *ret.value_mut() = None,
+        ValueLocation::Local(f, _) if f == funcs[2] => *ret.value_mut() = Some(u),
+        ValueLocation::Local(f, _) if f == funcs[3] => *ret.value_mut() = Some(x),
+        _ => {}
+      }
+    });
+    let errors = verify(&program).unwrap_err();
+    assert!(errors.iter().all(|e| e.code == code::RET_MISMATCH));
+    let errors: Vec<_> = errors.into_iter().map(|e| e.to_string()).collect();
+    assert_eq!(
+      errors,
+      [
+        "@f, %entry: expected return value of type i32, but returned nothing",
+        "@g, %entry: `ret` returns %u, which is of unit type",
+        "@p, %entry: return value must be of type *i32, found type i32",
+      ]
+    );
+  }
+
+  #[test]
+  fn verify_bytes() {
+    let driver: Driver<_> = r#"
+      global @s = alloc [i32, 4], "hi\0A\00"
+      global @t = alloc [[i32, 3], 2], {"ab\00", zeroinit}
+
+      fun @main() {
+      %entry:
+        ret
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    assert_eq!(verify(&program), Ok(()));
+    // byte data longer than its type
+    program.for_each_value_mut(|_, _, data| {
+      if let ValueKind::Bytes(b) = data.kind_mut() {
+        if b.bytes() == b"ab\0" {
+          b.bytes_mut().push(0);
+        }
+      }
+    });
+    // byte data used by an instruction
+    let main = program.func_layout()[0];
+    let data = program.func_mut(main);
+    let entry = data.layout().entry_bb().unwrap();
+    let alloc = data
+      .dfg_mut()
+      .new_value()
+      .alloc(Type::get_array(Type::get_i32(), 2));
+    let bytes = data.dfg_mut().new_value().bytes(*b"hi");
+    let store = data.dfg_mut().new_value().store(bytes, alloc);
+    let insts = data.layout_mut().bb_mut(entry).insts_mut();
+    insts.push_key_front(store).unwrap();
+    insts.push_key_front(alloc).unwrap();
+    let errors: Vec<_> = verify(&program)
+      .unwrap_err()
+      .into_iter()
+      .map(|e| e.to_string())
+      .collect();
+    assert_eq!(
+      errors,
+      [
+        "@t: byte data has 4 bytes, expected 3",
+        "@main, %entry: `store` uses byte data, which can only be used in \
+         initializers of global allocations",
+      ]
+    );
+  }
+}
@@ -0,0 +1,158 @@
+//! Implementations of the visitor for the DOT control flow graph generator.
+
+use crate::back::{self, NameManager};
+use crate::ir::entities::{BasicBlockData, ValueData};
+use crate::ir::values::{Branch, Jump};
+use crate::ir::visit::{self, Context};
+use crate::ir::{BasicBlock, Function, FunctionData, Program, Value};
+use std::collections::HashMap;
+use std::io::{Error, Result, Write};
+
+/// Visitor for generating control flow graphs of functions in the
+/// in-memory form Koopa IR program in the DOT language of Graphviz.
+///
+/// Each function definition is generated as a cluster, and each basic
+/// block in the function is generated as a node. Edges of conditional
+/// branches are labeled with `T` (the true edge) and `F` (the false edge).
+/// Function declarations are skipped.
+#[derive(Default)]
+pub struct Visitor;
+
+impl<W: Write> back::Visitor<W> for Visitor {
+  type Output = ();
+
+  fn visit(&mut self, w: &mut W, nm: &mut NameManager, program: &Program) -> Result<()> {
+    let mut visitor = VisitorImpl {
+      w,
+      nm,
+      func_index: 0,
+      ids: HashMap::new(),
+    };
+    visit::walk(program, &mut visitor)
+  }
+}
+
+/// The implementation of DOT generator.
+struct VisitorImpl<'a, W: Write> {
+  w: &'a mut W,
+  nm: &'a mut NameManager,
+  func_index: usize,
+  ids: HashMap<BasicBlock, String>,
+}
+
+impl<W: Write> VisitorImpl<'_, W> {
+  /// Generates an edge from the current basic block to the given basic
+  /// block. Edges to basic blocks that are not in the layout are skipped.
+  fn edge(&mut self, ctx: &Context, to: BasicBlock, label: Option<&str>) -> Result<()> {
+    let (Some(from), Some(to)) = (ctx.bb().map(|bb| &self.ids[&bb]), self.ids.get(&to)) else {
+      return Ok(());
+    };
+    match label {
+      Some(label) => writeln!(self.w, "    {from} -> {to} [label = \"{label}\"];"),
+      None => writeln!(self.w, "    {from} -> {to};"),
+    }
+  }
+}
+
+impl<W: Write> visit::Visitor for VisitorImpl<'_, W> {
+  type Error = Error;
+
+  fn visit_program(&mut self, _: &Context) -> Result<()> {
+    writeln!(self.w, "digraph program {{")?;
+    writeln!(self.w, "  node [shape = box];")
+  }
+
+  fn leave_program(&mut self, _: &Context) -> Result<()> {
+    writeln!(self.w, "}}")
+  }
+
+  fn visit_global(&mut self, _: &Context, _: Value, _: &ValueData) -> Result<()> {
+    Ok(())
+  }
+
+  fn visit_decl(&mut self, _: &Context, _: Function, _: &FunctionData) -> Result<()> {
+    self.func_index += 1;
+    Ok(())
+  }
+
+  fn visit_func(&mut self, _: &Context, _: Function, data: &FunctionData) -> Result<()> {
+    let i = self.func_index;
+    self.nm.enter_func_scope();
+    writeln!(self.w, "  subgraph cluster_{i} {{")?;
+    writeln!(self.w, "    label = \"{}\";", self.nm.func_name(data))?;
+    // assign node IDs first, since edges may target later basic blocks
+    self.ids = data
+      .layout()
+      .bbs()
+      .keys()
+      .enumerate()
+      .map(|(j, &bb)| (bb, format!("f{i}_bb{j}")))
+      .collect();
+    Ok(())
+  }
+
+  fn leave_func(&mut self, _: &Context, _: Function, _: &FunctionData) -> Result<()> {
+    self.func_index += 1;
+    self.nm.exit_func_scope();
+    writeln!(self.w, "  }}")
+  }
+
+  fn visit_bb(&mut self, _: &Context, bb: BasicBlock, data: &BasicBlockData) -> Result<()> {
+    let name = self.nm.bb_name(data);
+    writeln!(self.w, "    {} [label = \"{name}\"];", self.ids[&bb])
+  }
+
+  fn visit_branch(&mut self, ctx: &Context, _: Value, _: &ValueData, br: &Branch) -> Result<()> {
+    self.edge(ctx, br.true_bb(), Some("T"))?;
+    self.edge(ctx, br.false_bb(), Some("F"))
+  }
+
+  fn visit_jump(&mut self, ctx: &Context, _: Value, _: &ValueData, jump: &Jump) -> Result<()> {
+    self.edge(ctx, jump.target(), None)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::back::DotGenerator;
+  use crate::front::Driver;
+
+  #[test]
+  fn generate_cfg() {
+    let driver: Driver<_> = r#"
+      decl @getint(): i32
+
+      fun @main(): i32 {
+      %entry:
+        %0 = call @getint()
+        br %0, %then, %end
+
+      %then:
+        jump %end
+
+      %end:
+        ret 0
+      }
+    "#
+    .into();
+    let program = driver.generate_program().unwrap();
+    let mut gen = DotGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    assert_eq!(
+      std::str::from_utf8(&gen.writer()).unwrap(),
+      r#"digraph program {
+  node [shape = box];
+  subgraph cluster_1 {
+    label = "@main";
+    f1_bb0 [label = "%entry"];
+    f1_bb0 -> f1_bb1 [label = "T"];
+    f1_bb0 -> f1_bb2 [label = "F"];
+    f1_bb1 [label = "%then"];
+    f1_bb1 -> f1_bb2;
+    f1_bb2 [label = "%end"];
+  }
+}
+"#
+    );
+  }
+}
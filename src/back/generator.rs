@@ -6,11 +6,14 @@
 //! to generate function/basic block/value names when visiting IR programs.
 
 use crate::ir::entities::{BasicBlockData, FunctionData, Program, ValueData};
+use crate::ir::is_valid_symbol;
 use std::borrow::Borrow;
 use std::collections::{HashMap, HashSet};
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
 use std::fs::File;
 use std::io::{Result, Write};
 use std::num::NonZeroUsize;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
 use std::path::Path;
 use std::rc::Rc;
 
@@ -19,6 +22,9 @@ use std::rc::Rc;
 #[derive(Default)]
 pub struct NameManager {
   next_id: usize,
+  stable_temps: bool,
+  /// ID of the next temporary name when entering the function scope.
+  func_first_id: usize,
   cur_scope: ScopeKind,
   prefix: Prefix,
   global_names: HashSet<StringRc>,
@@ -47,6 +53,7 @@ impl NameManager {
       "already in function scope"
     );
     self.cur_scope = ScopeKind::Function;
+    self.func_first_id = self.next_id;
     self.values.clear();
   }
 
@@ -62,6 +69,9 @@ impl NameManager {
       "not in function scope"
     );
     self.cur_scope = ScopeKind::Global;
+    if self.stable_temps {
+      self.next_id = self.func_first_id;
+    }
     self.bb_names.clear();
     self.bbs.clear();
     for name in self.values.values() {
@@ -69,6 +79,16 @@ impl NameManager {
     }
   }
 
+  /// Enables or disables stable temporary names.
+  ///
+  /// If enabled, temporary IDs allocated in a function scope are
+  /// released when exiting the scope, so temporary names of every
+  /// function start from the same ID, and do not depend on the number of
+  /// temporaries in the previous functions.
+  pub fn set_stable_temps(&mut self, enable: bool) {
+    self.stable_temps = enable;
+  }
+
   /// Sets the prefix of generated names.
   pub fn set_prefix(&mut self, prefix: Prefix) {
     self.prefix = prefix;
@@ -81,8 +101,8 @@ impl NameManager {
       name.clone()
     } else {
       let name = self.next_name_str(func.name(), |s| &mut s.global_names);
-      self.funcs.insert(ptr, name);
-      self.funcs[&ptr].clone()
+      self.funcs.insert(ptr, name.clone());
+      name
     }
   }
 
@@ -93,8 +113,8 @@ impl NameManager {
       name.clone()
     } else {
       let name = self.next_name(bb.name(), |s| &mut s.bb_names);
-      self.bbs.insert(ptr, name);
-      self.bbs[&ptr].clone()
+      self.bbs.insert(ptr, name.clone());
+      name
     }
   }
 
@@ -121,9 +141,8 @@ impl NameManager {
       name.clone()
     } else {
       let name = self.next_name(value.name(), |s| &mut s.global_names);
-      let values = value_set(self);
-      values.insert(ptr, name);
-      values[&ptr].clone()
+      value_set(self).insert(ptr, name.clone());
+      name
     }
   }
 
@@ -143,11 +162,10 @@ impl NameManager {
       self.next_name_str(name, name_set)
     } else {
       // generate a temporary name
-      let name = self.prefix.temp_name(self.next_id);
+      let name = StringRc::from(self.prefix.temp_name(self.next_id));
       self.next_id += 1;
-      let names = name_set(self);
-      names.insert(name.clone().into());
-      names.get(&name).unwrap().to_rc()
+      name_set(self).insert(name.clone());
+      name.to_rc()
     }
   }
 
@@ -157,19 +175,22 @@ impl NameManager {
   where
     F: for<'a> FnOnce(&'a mut Self) -> &'a mut HashSet<StringRc>,
   {
+    debug_assert!(is_valid_symbol(name), "invalid symbol name '{name}'");
     let name = self.prefix.name(name);
     let names = name_set(self);
     // check for duplicate names
     if !names.contains(&name) {
-      names.insert(name.clone().into());
-      names.get(&name).unwrap().to_rc()
+      let name = StringRc::from(name);
+      names.insert(name.clone());
+      name.to_rc()
     } else {
       // generate a new name
       for id in 0.. {
         let new_name = format!("{}_{}", name, id);
         if !names.contains(&new_name) {
-          names.insert(new_name.clone().into());
-          return names.get(&new_name).unwrap().to_rc();
+          let new_name = StringRc::from(new_name);
+          names.insert(new_name.clone());
+          return new_name.to_rc();
         }
       }
       unreachable!()
@@ -314,6 +335,16 @@ impl<W: Write, V: Visitor<W>> Generator<W, V> {
     self.writer
   }
 
+  /// Returns a reference to the visitor.
+  pub(in crate::back) fn visitor(&self) -> &V {
+    &self.visitor
+  }
+
+  /// Returns mutable references to the visitor and the name manager.
+  pub(in crate::back) fn parts_mut(&mut self) -> (&mut V, &mut NameManager) {
+    (&mut self.visitor, &mut self.name_man)
+  }
+
   /// Generates on the given Koopa IR program.
   pub fn generate_on(&mut self, program: &Program) -> Result<V::Output> {
     self
@@ -322,8 +353,11 @@ impl<W: Write, V: Visitor<W>> Generator<W, V> {
   }
 }
 
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
 impl<V: Visitor<File>> Generator<File, V> {
   /// Creates a new generator from the given path.
+  ///
+  /// Not available on `wasm32-unknown-unknown`, which has no file system.
   pub fn from_path<P>(path: P) -> Result<Self>
   where
     V: Default,
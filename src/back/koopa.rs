@@ -1,86 +1,778 @@
 //! Implementations of the visitor for the text form Koopa IR generator.
 
-use crate::back::{self, NameManager};
-use crate::ir::entities::{FunctionData, ValueData};
+use crate::back::{self, Generator, NameManager};
+use crate::ir::dfg::DataFlowGraph;
+use crate::ir::entities::{FunctionData, SourceSpan, ValueData};
 use crate::ir::layout::BasicBlockNode;
 use crate::ir::values::*;
-use crate::ir::{BasicBlock, Program, Type, TypeKind, Value, ValueKind};
-use std::io::{Result, Write};
+use crate::ir::{AttrValue, BasicBlock, Function, Program, Type, TypeKind, Value, ValueKind};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+use std::mem;
+
+/// Checks that all basic blocks and instructions in the layout of the
+/// given function exist, so broken functions are reported as errors of
+/// kind [`ErrorKind::InvalidData`] instead of panics.
+fn check_layout(func: &FunctionData) -> Result<()> {
+  let dfg = func.dfg();
+  let error = |msg: String| {
+    let msg = format!("{msg} in the layout of function {}", func.name());
+    Err(Error::new(ErrorKind::InvalidData, msg))
+  };
+  for (bb, node) in func.layout().bbs() {
+    let Some(data) = dfg.bbs().get(bb) else {
+      return error(format!("basic block {bb:?} does not exist"));
+    };
+    if let Some(inst) = node.insts().keys().find(|i| !dfg.values().contains_key(i)) {
+      let bb = data.name().clone().unwrap_or_else(|| format!("{bb:?}"));
+      return error(format!(
+        "instruction {inst:?} of basic block {bb} does not exist"
+      ));
+    }
+  }
+  Ok(())
+}
+
+/// Versions of the output format of the text form Koopa IR generator.
+///
+/// The output of a numbered version is frozen: generating the same
+/// program with the same version and the same other configurations
+/// produces the same bytes in all future releases, so it can be pinned
+/// in test fixtures. New formatting improvements are only added as new
+/// versions, and [`FormatVersion::Latest`] always refers to the newest
+/// one, so its output may change between releases.
+///
+/// The output of all versions can be parsed by [`Driver`](crate::front::Driver).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FormatVersion {
+  /// The format of koopa 0.0.7 and earlier.
+  V1,
+  /// Same as [`FormatVersion::V1`], except that consecutive function
+  /// declarations are not separated by blank lines.
+  V2,
+  /// Same as [`FormatVersion::V2`], except that trailing runs of more
+  /// than 16 zero elements in aggregates are written as one `zeroinit`,
+  /// like `{1, 2, zeroinit}`. See [`Config::expand_zeros`].
+  V3,
+  /// The newest version, which is [`FormatVersion::V3`] currently.
+  #[default]
+  Latest,
+}
+
+impl FormatVersion {
+  /// Returns the formatting decisions of the version.
+  fn style(self) -> Style {
+    match self {
+      FormatVersion::V1 => Style {
+        group_decls: false,
+        compact_zeros: false,
+      },
+      FormatVersion::V2 => Style {
+        group_decls: true,
+        compact_zeros: false,
+      },
+      FormatVersion::V3 | FormatVersion::Latest => Style {
+        group_decls: true,
+        compact_zeros: true,
+      },
+    }
+  }
+}
+
+/// Formatting decisions that differ between format versions.
+struct Style {
+  /// Do not separate consecutive function declarations by blank lines.
+  group_decls: bool,
+  /// Write long trailing runs of zero elements as one `zeroinit`.
+  compact_zeros: bool,
+}
+
+/// Trailing runs of zero elements longer than this are compacted.
+const ZERO_RUN_THRESHOLD: usize = 16;
+
+/// Configurations of the text form Koopa IR generator.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Config {
+  /// Version of the output format, see [`FormatVersion`] for the
+  /// compatibility promise. Defaults to [`FormatVersion::Latest`].
+  ///
+  /// Comments emitted by other configurations are formatted in the same
+  /// way in all versions.
+  pub version: FormatVersion,
+  /// Emits attributes of instructions and functions as trailing
+  /// comments like `// !key = value`.
+  ///
+  /// The emitted attributes can be read back by
+  /// [`Driver::with_attrs`](crate::front::Driver::with_attrs).
+  pub emit_attrs: bool,
+  /// Emits source locations of instructions and basic blocks as
+  /// trailing comments like `// <line>:<col>`.
+  ///
+  /// If the source code is not provided by [`Visitor::with_source`],
+  /// byte offsets like `// <start>..<end>` will be emitted instead.
+  pub emit_spans: bool,
+  /// Numbers temporary names in every function from the same ID, so
+  /// adding or removing temporaries in one function does not rename the
+  /// temporaries in the following functions. Useful for diffing outputs.
+  ///
+  /// See [`NameManager::set_stable_temps`].
+  pub stable_temps: bool,
+  /// Writes all elements of aggregates, even if the version compacts
+  /// long trailing runs of zero elements (see [`FormatVersion::V3`]).
+  ///
+  /// Both forms are parsed into the same aggregate.
+  pub expand_zeros: bool,
+}
 
 /// Visitor for generating the in-memeory form Koopa IR program into
 /// the text form.
 #[derive(Default)]
-pub struct Visitor;
+pub struct Visitor {
+  config: Config,
+  line_starts: Option<Vec<usize>>,
+  focus: Option<Function>,
+  roots: Option<Vec<String>>,
+}
+
+impl Visitor {
+  /// Creates a new visitor with the given configurations.
+  pub fn new(config: Config) -> Self {
+    Self {
+      config,
+      line_starts: None,
+      focus: None,
+      roots: None,
+    }
+  }
+
+  /// Sets the source code of the program, which is used to convert
+  /// source locations to line numbers and column numbers.
+  pub fn with_source(mut self, src: &str) -> Self {
+    let lines = src.bytes().enumerate().filter(|(_, c)| *c == b'\n');
+    let starts = Some(0).into_iter().chain(lines.map(|(i, _)| i + 1));
+    self.line_starts = Some(starts.collect());
+    self
+  }
+
+  /// Only generates the body of the given function, other function
+  /// definitions are generated as declarations. Global values are
+  /// generated as usual, so the output is still a valid program.
+  pub fn with_focus(mut self, func: Function) -> Self {
+    self.focus = Some(func);
+    self
+  }
+
+  /// Only generates the functions named in `roots`, and the functions
+  /// and global allocations they reference transitively, in the original
+  /// layout order. Other symbols do not appear in the output, so dumps of
+  /// huge programs can be pruned to the parts of interest, like `@main`.
+  ///
+  /// Callees of generated functions are always generated with their
+  /// bodies (or as declarations if they are declarations), so the output
+  /// is still a valid program, and no declarations are synthesized.
+  /// Global allocations referenced by initializers of generated global
+  /// allocations are also generated.
+  ///
+  /// Generating returns an error of kind [`ErrorKind::InvalidInput`]
+  /// if any of the roots is not a function in the program.
+  pub fn emit_reachable_from(mut self, roots: &[&str]) -> Self {
+    self.roots = Some(roots.iter().map(|r| r.to_string()).collect());
+    self
+  }
+
+  /// Returns a reference to the configurations.
+  pub fn config(&self) -> &Config {
+    &self.config
+  }
+}
 
 impl<W: Write> back::Visitor<W> for Visitor {
   type Output = ();
 
+  /// Visits the program. The output is buffered internally, so writers
+  /// like [`File`](std::fs::File) need not be wrapped in a [`BufWriter`].
   fn visit(&mut self, w: &mut W, nm: &mut NameManager, program: &Program) -> Result<()> {
+    let mut w = BufWriter::new(w);
+    self.visit_into(&mut w, nm, program)?;
+    w.flush()
+  }
+}
+
+impl Visitor {
+  /// Generates the given program to the given writer without buffering.
+  fn visit_into<W: Write>(&self, w: &mut W, nm: &mut NameManager, program: &Program) -> Result<()> {
+    let reachable = match &self.roots {
+      Some(roots) => Some(Reachable::new(program, roots)?),
+      None => None,
+    };
     let mut visitor = VisitorImpl {
       w,
       nm,
-      program,
-      func: None,
+      program: Some(program),
+      config: &self.config,
+      line_starts: self.line_starts.as_deref(),
+      dfg: None,
+      handle_names: false,
+      markup: Markup::Plain,
+      scope: String::new(),
+      focus: self.focus,
+      reachable,
     };
     visitor.visit()
   }
 }
 
+impl<W: Write> Generator<W, Visitor> {
+  /// Returns an estimate of the size in bytes of the text form of the
+  /// given program, computed from the numbers of instructions and the
+  /// lengths of names without generating the program.
+  ///
+  /// The estimate is an upper bound of the output in practice, unless
+  /// attributes are emitted or names are lengthened by a custom
+  /// [`Prefix`](crate::back::Prefix). It is used by
+  /// [`emit_to_string`](Generator::emit_to_string) to reserve the
+  /// capacity of the buffer.
+  pub fn estimate_size(&self, program: &Program) -> usize {
+    let config = &self.visitor().config;
+    let values = program.borrow_values();
+    let mut size = 0;
+    for inst in program.inst_layout() {
+      let data = &values[inst];
+      size += GLOBAL_SIZE + name_size(data.name());
+      if let ValueKind::GlobalAlloc(alloc) = data.kind() {
+        let init = &values[&alloc.init()];
+        size += display_len(init.ty()) + const_size(init, |v| &values[&v]);
+      }
+    }
+    for func in program.funcs().values() {
+      let dfg = func.dfg();
+      size += FUNC_SIZE + func.name().len() + NAME_SLACK + display_len(func.ty());
+      for param in func.params() {
+        size += name_size(dfg.value(*param).name());
+      }
+      for (bb, node) in func.layout().bbs() {
+        let Some(bb) = dfg.bbs().get(bb) else {
+          continue;
+        };
+        size += BB_SIZE + name_size(bb.name());
+        for param in bb.params() {
+          size += name_size(dfg.value(*param).name()) + display_len(dfg.value(*param).ty());
+        }
+        for inst in node.insts().keys() {
+          let Some(data) = dfg.values().get(inst) else {
+            continue;
+          };
+          size += INST_SIZE + name_size(data.name());
+          if let ValueKind::Alloc(_) = data.kind() {
+            size += display_len(data.ty());
+          }
+          for value in data.kind().value_uses() {
+            size += match dfg.values().get(&value) {
+              Some(v) if v.kind().is_const() => const_size(v, |v| dfg.value(v)),
+              Some(v) => name_size(v.name()),
+              None => values
+                .get(&value)
+                .map_or(NAME_SLACK, |v| name_size(v.name())),
+            };
+          }
+          for bb in data.kind().bb_uses() {
+            size += dfg
+              .bbs()
+              .get(&bb)
+              .map_or(NAME_SLACK, |b| name_size(b.name()));
+          }
+          if config.emit_spans && data.span().is_some() {
+            size += SPAN_SIZE;
+          }
+        }
+      }
+    }
+    size
+  }
+
+  /// Generates the given program, and appends the text form to the
+  /// given string.
+  ///
+  /// The capacity of the string is reserved once by
+  /// [`estimate_size`](Generator::estimate_size), and the program is
+  /// formatted into the string directly, so the output is not copied
+  /// through the writer of the generator, which is unused. The output is
+  /// the same as the output of [`generate_on`](Generator::generate_on).
+  ///
+  /// On errors, the string contains the text generated before the error.
+  pub fn emit_to_string(&mut self, program: &Program, buf: &mut String) -> Result<()> {
+    let mut bytes = mem::take(buf).into_bytes();
+    bytes.reserve(self.estimate_size(program));
+    let (visitor, nm) = self.parts_mut();
+    let result = visitor.visit_into(&mut bytes, nm, program);
+    *buf = String::from_utf8(bytes).expect("the text form is always valid UTF-8");
+    result
+  }
+}
+
+/// Estimated size of a global allocation, excluding its name, type and
+/// initializer.
+const GLOBAL_SIZE: usize = 24;
+
+/// Estimated size of a function header and closing brace, excluding its
+/// name and type.
+const FUNC_SIZE: usize = 24;
+
+/// Estimated size of a basic block label, excluding its name.
+const BB_SIZE: usize = 8;
+
+/// Estimated size of an instruction, excluding its name and operands.
+/// Contains the indentation, the longest keyword and separators.
+const INST_SIZE: usize = 24;
+
+/// Estimated size of a source location comment.
+const SPAN_SIZE: usize = 32;
+
+/// Size added to every name for suffixes like `_1`, and the size of
+/// temporary names.
+const NAME_SLACK: usize = 8;
+
+/// Maximum size of an integer constant with a separator.
+const INT_SIZE: usize = 13;
+
+/// Returns the estimated size of the given name.
+fn name_size(name: &Option<String>) -> usize {
+  name.as_ref().map_or(0, |n| n.len()) + NAME_SLACK
+}
+
+/// Returns the estimated size of the given constant, `data` is used to
+/// look up the elements of aggregates.
+fn const_size<'d, F>(value: &'d ValueData, data: F) -> usize
+where
+  F: Fn(Value) -> &'d ValueData,
+{
+  let scalar_size = |value: &ValueData| match value.kind() {
+    ValueKind::Bytes(bytes) => display_len(bytes),
+    _ => INT_SIZE,
+  };
+  // scalar constants need no stack
+  let ValueKind::Aggregate(agg) = value.kind() else {
+    return scalar_size(value);
+  };
+  let mut size = 0;
+  let mut stack: Vec<_> = agg.elems().iter().map(|e| data(*e)).collect();
+  while let Some(value) = stack.pop() {
+    size += match value.kind() {
+      ValueKind::Aggregate(agg) => {
+        stack.extend(agg.elems().iter().map(|e| data(*e)));
+        4
+      }
+      _ => scalar_size(value),
+    };
+  }
+  size + 4
+}
+
+/// Returns the length of the given value when displayed.
+fn display_len(value: impl fmt::Display) -> usize {
+  /// A writer that only counts the written bytes.
+  struct Counter(usize);
+
+  impl fmt::Write for Counter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+      self.0 += s.len();
+      Ok(())
+    }
+  }
+
+  let mut counter = Counter(0);
+  fmt::write(&mut counter, format_args!("{value}")).unwrap();
+  counter.0
+}
+
+/// Generates the given value in the given data flow graph to the text
+/// form, for [`DataFlowGraph::display_value`].
+///
+/// Local instructions are generated as a whole instruction, and other
+/// values are generated as operands. Unnamed local values are named by
+/// their handles, since there is no program for generating names.
+pub(crate) fn fmt_value(f: &mut fmt::Formatter, dfg: &DataFlowGraph, value: Value) -> fmt::Result {
+  let mut w = Vec::new();
+  let mut nm = NameManager::new();
+  nm.enter_func_scope();
+  let mut visitor = VisitorImpl {
+    w: &mut w,
+    nm: &mut nm,
+    program: None,
+    config: &Config::default(),
+    line_starts: None,
+    dfg: Some(dfg),
+    handle_names: true,
+    markup: Markup::Plain,
+    scope: String::new(),
+    focus: None,
+    reachable: None,
+  };
+  let is_inst = dfg
+    .values()
+    .get(&value)
+    .is_some_and(|v| v.kind().is_local_inst());
+  let result = if is_inst {
+    visitor.visit_inst(value)
+  } else {
+    visitor.visit_value(value)
+  };
+  result.map_err(|_| fmt::Error)?;
+  f.write_str(&String::from_utf8_lossy(&w))
+}
+
+/// Generates the global values and every function of the given program
+/// separately, with attributes and stable temporary names. Returns the
+/// text of all global values and the texts of all functions in the
+/// function layout order, for detecting changes of programs.
+pub(crate) fn snapshot(program: &Program) -> (String, Vec<(Function, String)>) {
+  let mut w = Vec::new();
+  let mut nm = NameManager::new();
+  nm.set_stable_temps(true);
+  let config = Config {
+    emit_attrs: true,
+    ..Config::default()
+  };
+  let mut visitor = VisitorImpl {
+    w: &mut w,
+    nm: &mut nm,
+    program: Some(program),
+    config: &config,
+    line_starts: None,
+    dfg: None,
+    handle_names: false,
+    markup: Markup::Plain,
+    scope: String::new(),
+    focus: None,
+    reachable: None,
+  };
+  let ends = visitor
+    .visit_snapshot()
+    .expect("writing to a vector never fails");
+  let text = |range| String::from_utf8_lossy(&w[range]).into_owned();
+  let funcs = program
+    .func_layout()
+    .iter()
+    .zip(ends.windows(2))
+    .map(|(f, e)| (*f, text(e[0]..e[1])))
+    .collect();
+  (text(0..ends[0]), funcs)
+}
+
+/// Markup of the generated tokens.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(in crate::back) enum Markup {
+  /// No markup.
+  Plain,
+  /// ANSI escape sequences.
+  Ansi,
+  /// HTML elements.
+  Html,
+}
+
+/// Kinds of tokens, which may be marked up differently.
+#[derive(Clone, Copy)]
+pub(in crate::back) enum Token {
+  /// Keywords, like instruction names and `zeroinit`.
+  Keyword,
+  /// Types.
+  Type,
+  /// Integer constants.
+  Const,
+  /// Names of functions.
+  Func,
+  /// Names of basic blocks.
+  BasicBlock,
+  /// Names of values.
+  Value,
+  /// Comments and function attributes.
+  Comment,
+}
+
+impl Token {
+  /// Returns the ANSI SGR parameters of the token.
+  pub(in crate::back) fn ansi(self) -> &'static str {
+    match self {
+      Token::Keyword => "1;35",
+      Token::Type => "36",
+      Token::Const => "33",
+      Token::Func => "1;34",
+      Token::BasicBlock => "32",
+      Token::Value => "34",
+      Token::Comment => "2",
+    }
+  }
+
+  /// Returns the class name of the token in HTML.
+  pub(in crate::back) fn class(self) -> &'static str {
+    match self {
+      Token::Keyword => "kw",
+      Token::Type => "ty",
+      Token::Const => "c",
+      Token::Func => "fn",
+      Token::BasicBlock => "bb",
+      Token::Value => "v",
+      Token::Comment => "cm",
+    }
+  }
+}
+
+/// Escapes HTML-special characters in the given text.
+pub(in crate::back) struct Escape<'a>(pub(in crate::back) &'a str);
+
+impl fmt::Display for Escape<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut rest = self.0;
+    while let Some(i) = rest.find(['&', '<', '>', '"', '\'']) {
+      f.write_str(&rest[..i])?;
+      f.write_str(match rest.as_bytes()[i] {
+        b'&' => "&amp;",
+        b'<' => "&lt;",
+        b'>' => "&gt;",
+        b'"' => "&quot;",
+        _ => "&#39;",
+      })?;
+      rest = &rest[i + 1..];
+    }
+    f.write_str(rest)
+  }
+}
+
 /// The implementation of text form Koopa IR generator.
-struct VisitorImpl<'a, W: Write> {
-  w: &'a mut W,
-  nm: &'a mut NameManager,
-  program: &'a Program,
-  func: Option<&'a FunctionData>,
+pub(in crate::back) struct VisitorImpl<'a, W: Write> {
+  pub(in crate::back) w: &'a mut W,
+  pub(in crate::back) nm: &'a mut NameManager,
+  pub(in crate::back) program: Option<&'a Program>,
+  pub(in crate::back) config: &'a Config,
+  pub(in crate::back) line_starts: Option<&'a [usize]>,
+  pub(in crate::back) dfg: Option<&'a DataFlowGraph>,
+  pub(in crate::back) handle_names: bool,
+  pub(in crate::back) markup: Markup,
+  /// Scope of HTML anchors of local names, like `f0`.
+  pub(in crate::back) scope: String,
+  /// The only function whose body is generated, see [`Visitor::with_focus`].
+  pub(in crate::back) focus: Option<Function>,
+  /// Symbols to be generated, see [`Visitor::emit_reachable_from`].
+  pub(in crate::back) reachable: Option<Reachable>,
+}
+
+/// Functions and global allocations reachable from some root functions,
+/// see [`Visitor::emit_reachable_from`].
+pub(in crate::back) struct Reachable {
+  funcs: HashSet<Function>,
+  globals: HashSet<Value>,
 }
 
-/// Returns a reference to the current function.
-macro_rules! func {
+impl Reachable {
+  /// Collects symbols reachable from the given roots in the program.
+  fn new(program: &Program, roots: &[String]) -> Result<Self> {
+    let mut funcs = HashSet::new();
+    let mut worklist = Vec::new();
+    for root in roots {
+      let func = program
+        .func_layout()
+        .iter()
+        .find(|f| program.func(**f).name() == root)
+        .ok_or_else(|| {
+          Error::new(
+            ErrorKind::InvalidInput,
+            format!("function '{root}' not found"),
+          )
+        })?;
+      if funcs.insert(*func) {
+        worklist.push(*func);
+      }
+    }
+    // collect callees and global values used by functions
+    let mut globals = HashSet::new();
+    let mut global_worklist = Vec::new();
+    while let Some(func) = worklist.pop() {
+      let data = program.func(func);
+      for (_, node) in data.layout().bbs() {
+        for inst in node.insts().keys() {
+          // dangling instructions are reported when generating the function
+          let Some(inst) = data.dfg().values().get(inst) else {
+            continue;
+          };
+          let kind = inst.kind();
+          if let ValueKind::Call(call) = kind {
+            if funcs.insert(call.callee()) {
+              worklist.push(call.callee());
+            }
+          }
+          for value in kind.value_uses() {
+            if !data.dfg().values().contains_key(&value) && globals.insert(value) {
+              global_worklist.push(value);
+            }
+          }
+        }
+      }
+    }
+    // collect global values used by initializers
+    let values = program.borrow_values();
+    while let Some(value) = global_worklist.pop() {
+      for value in values[&value].kind().value_uses() {
+        if globals.insert(value) {
+          global_worklist.push(value);
+        }
+      }
+    }
+    Ok(Self { funcs, globals })
+  }
+}
+
+/// Returns a reference to the program.
+macro_rules! program {
   ($self:ident) => {
-    $self.func.unwrap()
+    $self.program.unwrap()
+  };
+}
+
+/// Returns a reference to the data flow graph of the current function.
+macro_rules! dfg {
+  ($self:ident) => {
+    $self.dfg.unwrap()
   };
 }
 
 /// Returns a reference to the given value in the current function.
 macro_rules! value {
   ($self:ident, $value:expr) => {
-    func!($self).dfg().value($value)
+    dfg!($self).value($value)
   };
 }
 
+impl VisitorImpl<'_, Vec<u8>> {
+  /// Visits the program for [`snapshot`]. Returns the end offsets of the
+  /// global values and every function in the output.
+  fn visit_snapshot(&mut self) -> Result<Vec<usize>> {
+    let program = program!(self);
+    for inst in program.inst_layout() {
+      self.visit_global_inst(&program.borrow_value(*inst))?;
+    }
+    let mut ends = vec![self.w.len()];
+    for &func in program.func_layout() {
+      let func = program.func(func);
+      self.dfg = Some(func.dfg());
+      self.nm.enter_func_scope();
+      // keep snapshots of broken functions, so passes that break
+      // functions can still be debugged
+      match self.visit_func(func, func.dfg().bbs().is_empty()) {
+        Err(e) if e.kind() == ErrorKind::InvalidData => writeln!(self.w, "\n// {e}")?,
+        result => result?,
+      }
+      self.nm.exit_func_scope();
+      ends.push(self.w.len());
+    }
+    Ok(ends)
+  }
+}
+
 impl<'a, W: Write> VisitorImpl<'a, W> {
   /// Visits the program.
-  fn visit(&mut self) -> Result<()> {
-    for inst in self.program.inst_layout() {
-      self.visit_global_inst(&self.program.borrow_value(*inst))?;
+  pub(in crate::back) fn visit(&mut self) -> Result<()> {
+    let program = program!(self);
+    let reachable = self.reachable.as_ref();
+    let insts: Vec<_> = program
+      .inst_layout()
+      .iter()
+      .filter(|v| reachable.is_none_or(|r| r.globals.contains(v)))
+      .copied()
+      .collect();
+    let funcs: Vec<_> = program
+      .func_layout()
+      .iter()
+      .filter(|f| reachable.is_none_or(|r| r.funcs.contains(f)))
+      .copied()
+      .collect();
+    for inst in &insts {
+      self.visit_global_inst(&program.borrow_value(*inst))?;
     }
-    if !self.program.inst_layout().is_empty() {
+    if !insts.is_empty() {
       writeln!(self.w)?;
     }
-    for (i, func) in self.program.func_layout().iter().enumerate() {
-      if i != 0 {
+    let style = self.config.version.style();
+    let mut prev_decl = false;
+    self.nm.set_stable_temps(self.config.stable_temps);
+    for (i, &handle) in funcs.iter().enumerate() {
+      let func = program.func(handle);
+      let is_decl = func.dfg().bbs().is_empty() || self.focus.is_some_and(|f| f != handle);
+      if i != 0 && !(style.group_decls && prev_decl && is_decl) {
         writeln!(self.w)?;
       }
-      let func = self.program.func(*func);
-      self.func = Some(func);
+      prev_decl = is_decl;
+      self.dfg = Some(func.dfg());
+      if self.markup == Markup::Html {
+        self.scope = format!("f{i}");
+      }
       self.nm.enter_func_scope();
-      self.visit_func(func)?;
+      self.visit_func(func, is_decl)?;
       self.nm.exit_func_scope();
     }
     Ok(())
   }
 
-  /// Generates the given function.
-  fn visit_func(&mut self, func: &FunctionData) -> Result<()> {
-    // header
-    let is_decl = func.dfg().bbs().is_empty();
-    if is_decl {
-      write!(self.w, "decl")?;
-    } else {
-      write!(self.w, "fun")?;
+  /// Generates the given token.
+  fn token(&mut self, token: Token, text: impl fmt::Display) -> Result<()> {
+    match self.markup {
+      Markup::Plain => write!(self.w, "{text}"),
+      Markup::Ansi => write!(self.w, "\x1b[{}m{text}\x1b[0m", token.ansi()),
+      Markup::Html => write!(
+        self.w,
+        "<span class=\"{}\">{}</span>",
+        token.class(),
+        Escape(&text.to_string())
+      ),
+    }
+  }
+
+  /// Generates the given keyword followed by a space.
+  fn keyword(&mut self, keyword: impl fmt::Display) -> Result<()> {
+    self.token(Token::Keyword, keyword)?;
+    write!(self.w, " ")
+  }
+
+  /// Generates the given name of a function, a basic block or a value.
+  ///
+  /// In HTML, definitions are generated with anchors, and uses are
+  /// generated as links to the definitions. `ty` is shown as the title.
+  fn name(
+    &mut self,
+    token: Token,
+    name: &str,
+    global: bool,
+    def: bool,
+    ty: Option<&Type>,
+  ) -> Result<()> {
+    if self.markup != Markup::Html {
+      return self.token(token, name);
+    }
+    let scope = if global { "g" } else { &self.scope };
+    let anchor = format!("{scope}-{}", name.replace('@', "at_").replace('%', "pc_"));
+    let (tag, attr) = if def { ("span", "id") } else { ("a", "href") };
+    let hash = if def { "" } else { "#" };
+    write!(
+      self.w,
+      "<{tag} class=\"{}\" {attr}=\"{hash}{}\"",
+      token.class(),
+      Escape(&anchor)
+    )?;
+    if let Some(ty) = ty {
+      write!(self.w, " title=\"{}\"", Escape(&ty.to_string()))?;
     }
+    write!(self.w, ">{}</{tag}>", Escape(name))
+  }
+
+  /// Generates the given function, as a declaration if `is_decl` is set.
+  fn visit_func(&mut self, func: &FunctionData, is_decl: bool) -> Result<()> {
+    // header
+    self.token(Token::Keyword, if is_decl { "decl" } else { "fun" })?;
     // function name
-    write!(self.w, " {}(", self.nm.func_name(func))?;
+    write!(self.w, " ")?;
+    let name = self.nm.func_name(func);
+    self.name(Token::Func, &name, true, true, Some(func.ty()))?;
+    write!(self.w, "(")?;
     // unwrap function type
     let (param_ty, ret_ty) = match func.ty().kind() {
       TypeKind::Function(param, ret) => (param, ret),
@@ -92,25 +784,33 @@ impl<'a, W: Write> VisitorImpl<'a, W> {
         if i != 0 {
           write!(self.w, ", ")?;
         }
-        write!(self.w, "{}", ty)?;
+        self.token(Token::Type, ty)?;
       }
     } else {
       for (i, param) in func.params().iter().enumerate() {
         if i != 0 {
           write!(self.w, ", ")?;
         }
-        let param = value!(self, *param);
-        write!(self.w, "{}: {}", self.nm.value_name(param), param.ty())?;
+        self.visit_param(*param)?;
       }
     }
     write!(self.w, ")")?;
     // return type
     if !ret_ty.is_unit() {
-      write!(self.w, ": {}", ret_ty)?;
+      write!(self.w, ": ")?;
+      self.token(Token::Type, ret_ty)?;
+    }
+    // function attributes
+    if !func.func_attrs().is_empty() {
+      write!(self.w, " ")?;
+      self.token(Token::Comment, func.func_attrs())?;
     }
     // function body
     if !is_decl {
-      writeln!(self.w, " {{")?;
+      check_layout(func)?;
+      write!(self.w, " {{")?;
+      self.visit_attrs(func.attrs())?;
+      writeln!(self.w)?;
       for (i, (bb, node)) in func.layout().bbs().iter().enumerate() {
         if i != 0 {
           writeln!(self.w)?;
@@ -119,6 +819,7 @@ impl<'a, W: Write> VisitorImpl<'a, W> {
       }
       writeln!(self.w, "}}")
     } else {
+      self.visit_attrs(func.attrs())?;
       writeln!(self.w)
     }
   }
@@ -126,8 +827,9 @@ impl<'a, W: Write> VisitorImpl<'a, W> {
   /// Generates the given basic block.
   fn visit_bb(&mut self, bb: BasicBlock, node: &BasicBlockNode) -> Result<()> {
     // basic block name
-    let bb = func!(self).dfg().bb(bb);
-    write!(self.w, "{}", self.nm.bb_name(bb))?;
+    let bb = dfg!(self).bb(bb);
+    let name = self.nm.bb_name(bb);
+    self.name(Token::BasicBlock, &name, false, true, None)?;
     // basic block parameters
     if !bb.params().is_empty() {
       write!(self.w, "(")?;
@@ -135,42 +837,68 @@ impl<'a, W: Write> VisitorImpl<'a, W> {
         if i != 0 {
           write!(self.w, ", ")?;
         }
-        let param = value!(self, *param);
-        write!(self.w, "{}: {}", self.nm.value_name(param), param.ty())?;
+        self.visit_param(*param)?;
       }
       write!(self.w, ")")?;
     }
-    writeln!(self.w, ":")?;
+    write!(self.w, ":")?;
+    self.visit_span(bb.span())?;
+    writeln!(self.w)?;
     // instrustions in basic block
     for inst in node.insts().keys() {
       write!(self.w, "  ")?;
-      self.visit_local_inst(value!(self, *inst))?;
+      self.visit_local_inst(*inst)?;
     }
     Ok(())
   }
 
+  /// Generates the given function or basic block parameter.
+  fn visit_param(&mut self, param: Value) -> Result<()> {
+    let param = value!(self, param);
+    let name = self.nm.value_name(param);
+    self.name(Token::Value, &name, false, true, Some(param.ty()))?;
+    write!(self.w, ": ")?;
+    self.token(Token::Type, param.ty())
+  }
+
   /// Generates the given global instruction.
   fn visit_global_inst(&mut self, inst: &ValueData) -> Result<()> {
     let alloc = match inst.kind() {
       ValueKind::GlobalAlloc(alloc) => alloc,
       _ => panic!("invalid global instruction"),
     };
-    let init = self.program.borrow_value(alloc.init());
-    write!(
-      self.w,
-      "global {} = alloc {}, ",
-      self.nm.value_name(inst),
-      init.ty()
-    )?;
+    let init = program!(self).borrow_value(alloc.init());
+    self.token(Token::Keyword, "global")?;
+    write!(self.w, " ")?;
+    let name = self.nm.value_name(inst);
+    self.name(Token::Value, &name, true, true, Some(inst.ty()))?;
+    write!(self.w, " = ")?;
+    self.token(Token::Keyword, "alloc")?;
+    write!(self.w, " ")?;
+    self.token(Token::Type, init.ty())?;
+    write!(self.w, ", ")?;
     self.visit_global_const(&init)?;
+    self.visit_attrs(inst.attrs())?;
+    self.visit_span(inst.span())?;
     writeln!(self.w)
   }
 
   /// Generates the given local instruction.
-  fn visit_local_inst(&mut self, inst: &ValueData) -> Result<()> {
+  fn visit_local_inst(&mut self, inst: Value) -> Result<()> {
+    self.visit_inst(inst)?;
+    let inst = value!(self, inst);
+    self.visit_attrs(inst.attrs())?;
+    self.visit_span(inst.span())?;
+    writeln!(self.w)
+  }
+
+  /// Generates the definition and the content of the given local instruction.
+  fn visit_inst(&mut self, value: Value) -> Result<()> {
+    let inst = value!(self, value);
     // definition
     if !inst.ty().is_unit() {
-      write!(self.w, "{} = ", self.nm.value_name(inst))?;
+      self.visit_local_name(value, true)?;
+      write!(self.w, " = ")?;
     }
     // content of instruction
     match inst.kind() {
@@ -184,9 +912,55 @@ impl<'a, W: Write> VisitorImpl<'a, W> {
       ValueKind::Jump(v) => self.visit_jump(v),
       ValueKind::Call(v) => self.visit_call(v),
       ValueKind::Return(v) => self.visit_return(v),
+      ValueKind::Unreachable(_) => self.token(Token::Keyword, "unreachable"),
       _ => panic!("invalid local instruction"),
-    }?;
-    writeln!(self.w)
+    }
+  }
+
+  /// Generates the name of the given local value,
+  /// `def` indicates whether it is a definition.
+  fn visit_local_name(&mut self, value: Value, def: bool) -> Result<()> {
+    let data = value!(self, value);
+    if self.handle_names && data.name().is_none() {
+      write!(self.w, "%{}", value)
+    } else {
+      let name = self.nm.value_name(data);
+      self.name(Token::Value, &name, false, def, Some(data.ty()))
+    }
+  }
+
+  /// Generates the given attributes as a trailing comment if needed.
+  fn visit_attrs(&mut self, attrs: &BTreeMap<String, AttrValue>) -> Result<()> {
+    if !self.config.emit_attrs || attrs.is_empty() {
+      return Ok(());
+    }
+    let mut comment = String::from("//");
+    for (i, (key, attr)) in attrs.iter().enumerate() {
+      if i != 0 {
+        comment.push(',');
+      }
+      comment += &format!(" !{} = {}", key, attr);
+    }
+    write!(self.w, " ")?;
+    self.token(Token::Comment, comment)
+  }
+
+  /// Generates the given source location as a trailing comment if needed.
+  fn visit_span(&mut self, span: Option<SourceSpan>) -> Result<()> {
+    let span = match span {
+      Some(span) if self.config.emit_spans => span,
+      _ => return Ok(()),
+    };
+    let comment = match self.line_starts {
+      Some(starts) => {
+        let line = starts.partition_point(|s| *s <= span.start);
+        let col = span.start - starts[line - 1] + 1;
+        format!("// {}:{}", line, col)
+      }
+      None => format!("// {}..{}", span.start, span.end),
+    };
+    write!(self.w, " ")?;
+    self.token(Token::Comment, comment)
   }
 
   /// Generates allocation.
@@ -195,18 +969,20 @@ impl<'a, W: Write> VisitorImpl<'a, W> {
       TypeKind::Pointer(base) => base,
       _ => panic!("invalid pointer type"),
     };
-    write!(self.w, "alloc {}", base)
+    self.token(Token::Keyword, "alloc")?;
+    write!(self.w, " ")?;
+    self.token(Token::Type, base)
   }
 
   /// Generates memory load.
   fn visit_load(&mut self, load: &Load) -> Result<()> {
-    write!(self.w, "load ")?;
+    self.keyword("load")?;
     self.visit_value(load.src())
   }
 
   /// Generates memory store.
   fn visit_store(&mut self, store: &Store) -> Result<()> {
-    write!(self.w, "store ")?;
+    self.keyword("store")?;
     self.visit_value(store.value())?;
     write!(self.w, ", ")?;
     self.visit_value(store.dest())
@@ -214,7 +990,7 @@ impl<'a, W: Write> VisitorImpl<'a, W> {
 
   /// Generates pointer calculation.
   fn visit_getptr(&mut self, gp: &GetPtr) -> Result<()> {
-    write!(self.w, "getptr ")?;
+    self.keyword("getptr")?;
     self.visit_value(gp.src())?;
     write!(self.w, ", ")?;
     self.visit_value(gp.index())
@@ -222,7 +998,7 @@ impl<'a, W: Write> VisitorImpl<'a, W> {
 
   /// Generates element pointer calculation.
   fn visit_getelemptr(&mut self, gep: &GetElemPtr) -> Result<()> {
-    write!(self.w, "getelemptr ")?;
+    self.keyword("getelemptr")?;
     self.visit_value(gep.src())?;
     write!(self.w, ", ")?;
     self.visit_value(gep.index())
@@ -230,7 +1006,7 @@ impl<'a, W: Write> VisitorImpl<'a, W> {
 
   /// Generates binary operation.
   fn visit_binary(&mut self, bin: &Binary) -> Result<()> {
-    write!(self.w, "{} ", bin.op())?;
+    self.keyword(bin.op())?;
     self.visit_value(bin.lhs())?;
     write!(self.w, ", ")?;
     self.visit_value(bin.rhs())
@@ -238,7 +1014,7 @@ impl<'a, W: Write> VisitorImpl<'a, W> {
 
   /// Generates branch.
   fn visit_branch(&mut self, br: &Branch) -> Result<()> {
-    write!(self.w, "br ")?;
+    self.keyword("br")?;
     self.visit_value(br.cond())?;
     write!(self.w, ", ")?;
     self.visit_bb_target(br.true_bb(), br.true_args())?;
@@ -248,17 +1024,22 @@ impl<'a, W: Write> VisitorImpl<'a, W> {
 
   /// Generates jump.
   fn visit_jump(&mut self, jump: &Jump) -> Result<()> {
-    write!(self.w, "jump ")?;
+    self.keyword("jump")?;
     self.visit_bb_target(jump.target(), jump.args())
   }
 
   /// Generates function call.
   fn visit_call(&mut self, call: &Call) -> Result<()> {
-    write!(
-      self.w,
-      "call {}(",
-      self.nm.func_name(self.program.func(call.callee()))
-    )?;
+    self.keyword("call")?;
+    match self.program {
+      Some(program) => {
+        let callee = program.func(call.callee());
+        let name = self.nm.func_name(callee);
+        self.name(Token::Func, &name, true, false, Some(callee.ty()))?;
+        write!(self.w, "(")?;
+      }
+      None => write!(self.w, "{:?}(", call.callee())?,
+    }
     for (i, arg) in call.args().iter().enumerate() {
       if i != 0 {
         write!(self.w, ", ")?;
@@ -270,7 +1051,7 @@ impl<'a, W: Write> VisitorImpl<'a, W> {
 
   /// Generates function return.
   fn visit_return(&mut self, ret: &Return) -> Result<()> {
-    write!(self.w, "ret")?;
+    self.token(Token::Keyword, "ret")?;
     if let Some(val) = ret.value() {
       write!(self.w, " ")?;
       self.visit_value(val)?;
@@ -281,62 +1062,121 @@ impl<'a, W: Write> VisitorImpl<'a, W> {
   /// Generates the given value.
   fn visit_value(&mut self, value: Value) -> Result<()> {
     if value.is_global() {
-      let value = self.program.borrow_value(value);
-      assert!(!value.kind().is_const());
-      write!(self.w, "{}", self.nm.value_name(&value))
+      match self.program {
+        Some(program) => {
+          let value = program.borrow_value(value);
+          assert!(!value.kind().is_const());
+          let name = self.nm.value_name(&value);
+          self.name(Token::Value, &name, true, false, Some(value.ty()))
+        }
+        None => match dfg!(self).global_value_data(value) {
+          Some(data) if data.name().is_some() => {
+            write!(self.w, "{}", data.name().as_ref().unwrap())
+          }
+          _ => write!(self.w, "@{}", value),
+        },
+      }
     } else {
-      let value = value!(self, value);
-      if value.kind().is_const() {
-        self.visit_local_const(value)
+      let data = value!(self, value);
+      if data.kind().is_const() {
+        self.visit_local_const(data)
       } else {
-        write!(self.w, "{}", self.nm.value_name(value))
+        self.visit_local_name(value, false)
       }
     }
   }
 
   /// Generates the given global constant.
   fn visit_global_const(&mut self, value: &ValueData) -> Result<()> {
-    match value.kind() {
-      ValueKind::Integer(v) => write!(self.w, "{}", v.value()),
-      ValueKind::ZeroInit(_) => write!(self.w, "zeroinit"),
-      ValueKind::Undef(_) => write!(self.w, "undef"),
-      ValueKind::Aggregate(v) => {
-        write!(self.w, "{{")?;
-        for (i, elem) in v.elems().iter().enumerate() {
-          if i != 0 {
-            write!(self.w, ", ")?;
-          }
-          self.visit_global_const(&self.program.borrow_value(*elem))?;
-        }
-        write!(self.w, "}}")
-      }
-      _ => panic!("invalid constant"),
-    }
+    let values = program!(self).borrow_values();
+    self.visit_const(value, |v| &values[&v])
   }
 
   /// Generates the given local constant.
   fn visit_local_const(&mut self, value: &ValueData) -> Result<()> {
-    match value.kind() {
-      ValueKind::Integer(v) => write!(self.w, "{}", v.value()),
-      ValueKind::ZeroInit(_) => write!(self.w, "zeroinit"),
-      ValueKind::Undef(_) => write!(self.w, "undef"),
-      ValueKind::Aggregate(v) => {
-        write!(self.w, "{{")?;
-        for (i, elem) in v.elems().iter().enumerate() {
-          if i != 0 {
-            write!(self.w, ", ")?;
+    let dfg = dfg!(self);
+    self.visit_const(value, |v| dfg.value(v))
+  }
+
+  /// Generates the given constant, `data` is used to look up the
+  /// elements of aggregates.
+  fn visit_const<'d, F>(&mut self, value: &'d ValueData, data: F) -> Result<()>
+  where
+    F: Fn(Value) -> &'d ValueData,
+  {
+    enum Item<'d> {
+      Const(&'d ValueData),
+      Zeros,
+      Comma,
+      End,
+    }
+    // scalar constants need no stack
+    if !matches!(value.kind(), ValueKind::Aggregate(_)) {
+      return self.visit_scalar_const(value);
+    }
+    // elements and punctuation still to be written, the next one on top
+    let mut stack = vec![Item::Const(value)];
+    while let Some(item) = stack.pop() {
+      match item {
+        Item::Const(value) => match value.kind() {
+          ValueKind::Aggregate(v) => {
+            write!(self.w, "{{")?;
+            stack.push(Item::End);
+            let mut elems = v.elems();
+            if self.compact_zeros() {
+              let is_zero = |e: &Value| match data(*e).kind() {
+                ValueKind::ZeroInit(_) => true,
+                ValueKind::Integer(i) => i.value() == 0,
+                _ => false,
+              };
+              let run = elems.iter().rev().take_while(|e| is_zero(e)).count();
+              if run > ZERO_RUN_THRESHOLD {
+                let zero = elems.len() - run;
+                stack.push(Item::Zeros);
+                if zero != 0 {
+                  stack.push(Item::Comma);
+                }
+                elems = &elems[..zero];
+              }
+            }
+            for (i, elem) in elems.iter().enumerate().rev() {
+              stack.push(Item::Const(data(*elem)));
+              if i != 0 {
+                stack.push(Item::Comma);
+              }
+            }
           }
-          self.visit_local_const(value!(self, *elem))?;
-        }
-        write!(self.w, "}}")
+          _ => self.visit_scalar_const(value)?,
+        },
+        Item::Zeros => self.token(Token::Keyword, "zeroinit")?,
+        Item::Comma => write!(self.w, ", ")?,
+        Item::End => write!(self.w, "}}")?,
       }
+    }
+    Ok(())
+  }
+
+  /// Generates the given constant that is not an aggregate.
+  fn visit_scalar_const(&mut self, value: &ValueData) -> Result<()> {
+    match value.kind() {
+      ValueKind::Integer(v) => self.token(Token::Const, v.value()),
+      ValueKind::ZeroInit(_) => self.token(Token::Keyword, "zeroinit"),
+      ValueKind::Undef(_) => self.token(Token::Keyword, "undef"),
+      ValueKind::Bytes(v) => self.token(Token::Const, v),
       _ => panic!("invalid constant"),
     }
   }
 
+  /// Returns `true` if long trailing runs of zero elements in
+  /// aggregates should be compacted.
+  fn compact_zeros(&self) -> bool {
+    self.config.version.style().compact_zeros && !self.config.expand_zeros
+  }
+
   /// Generates the given basic block target.
   fn visit_bb_target(&mut self, bb: BasicBlock, params: &[Value]) -> Result<()> {
-    write!(self.w, "{}", self.nm.bb_name(func!(self).dfg().bb(bb)))?;
+    let name = self.nm.bb_name(dfg!(self).bb(bb));
+    self.name(Token::BasicBlock, &name, false, false, None)?;
     if !params.is_empty() {
       write!(self.w, "(")?;
       for (i, param) in params.iter().enumerate() {
@@ -353,7 +1193,7 @@ impl<'a, W: Write> VisitorImpl<'a, W> {
 
 #[cfg(test)]
 mod test {
-  use crate::back::KoopaGenerator;
+  use crate::back::{Generator, KoopaGenerator};
   use crate::front::Driver;
   use std::str;
 
@@ -370,6 +1210,20 @@ fun @test(@i: i32): i32 {
   %3 = mul %2, 7
   ret %3
 }
+"#;
+    let driver: Driver<_> = src.into();
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen
+      .generate_on(&driver.generate_program().unwrap())
+      .unwrap();
+    assert_eq!(str::from_utf8(&gen.writer()).unwrap(), src);
+  }
+
+  #[test]
+  fn dump_bytes() {
+    let src = r#"global @s = alloc [i32, 6], "hi\n\00\FF\01"
+global @t = alloc [[i32, 3], 2], {"\"\\a", zeroinit}
+
 "#;
     let driver: Driver<_> = src.into();
     let mut gen = KoopaGenerator::new(Vec::new());
@@ -451,4 +1305,455 @@ fun @main(): i32 {
       .unwrap();
     assert_eq!(str::from_utf8(&gen.writer()).unwrap(), src);
   }
+
+  #[test]
+  fn dump_attrs() {
+    let src = r#"global @x = alloc i32, zeroinit // !section = ".bss"
+
+decl @exit(i32) // !noreturn = true
+
+fun @main(): i32 { // !entry = true
+%entry:
+  %0 = alloc i32 // !name = "a\"b", !spill = 2
+  store 1, %0
+  ret 0
+}
+"#;
+    let driver: Driver<_> = src.into();
+    let program = driver.with_attrs().generate_program().unwrap();
+    let visitor = super::Visitor::new(super::Config {
+      emit_attrs: true,
+      ..Default::default()
+    });
+    let mut gen = Generator::with_visitor(Vec::new(), visitor);
+    gen.generate_on(&program).unwrap();
+    assert_eq!(str::from_utf8(&gen.writer()).unwrap(), src);
+    // attributes are not emitted by default
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    assert!(!str::from_utf8(&gen.writer()).unwrap().contains("//"));
+  }
+
+  #[test]
+  fn dump_spans() {
+    let src = r#"global @x = alloc i32, zeroinit
+
+fun @main(): i32 {
+%entry:
+  %0 = load @x // !a = 1
+  ret %0
+}
+"#;
+    let expected = r#"global @x = alloc i32, zeroinit // 1:1
+
+fun @main(): i32 {
+%entry: // 4:1
+  %0 = load @x // !a = 1 // 5:3
+  ret %0 // 6:3
+}
+"#;
+    let driver: Driver<_> = src.into();
+    let program = driver.with_attrs().generate_program().unwrap();
+    let config = super::Config {
+      emit_attrs: true,
+      emit_spans: true,
+      ..Default::default()
+    };
+    let visitor = super::Visitor::new(config).with_source(src);
+    let mut gen = Generator::with_visitor(Vec::new(), visitor);
+    gen.generate_on(&program).unwrap();
+    let dumped = String::from_utf8(gen.writer()).unwrap();
+    assert_eq!(dumped, expected);
+    // attributes can still be read back
+    let driver: Driver<_> = dumped.into();
+    let program = driver.with_attrs().generate_program().unwrap();
+    let main = program.func(program.func_layout()[0]);
+    let entry = main.layout().entry_bb().unwrap();
+    let load = *main
+      .layout()
+      .bbs()
+      .node(&entry)
+      .unwrap()
+      .insts()
+      .front_key()
+      .unwrap();
+    let load = main.dfg().value(load);
+    assert!(load.attr("a").is_some());
+    assert_eq!(load.span().unwrap().start_line_col(expected), (5, 3));
+  }
+
+  #[test]
+  fn dump_func_attrs() {
+    let src = r#"decl @exit(i32) #[noreturn]
+decl @abs(i32): i32 #[pure, conv = "c\"c"]
+
+fun @main(): i32 #[pure] {
+%entry:
+  %0 = call @abs(1)
+  call @exit(%0)
+  ret 0
+}
+"#;
+    let driver: Driver<_> = src.into();
+    let program = driver.generate_program().unwrap();
+    let funcs: Vec<_> = program
+      .func_layout()
+      .iter()
+      .map(|f| program.func(*f))
+      .collect();
+    assert!(funcs[0].is_noreturn() && !funcs[0].is_pure());
+    assert_eq!(funcs[1].func_attrs().conv.as_deref(), Some("c\"c"));
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    assert_eq!(str::from_utf8(&gen.writer()).unwrap(), src);
+  }
+
+  #[test]
+  fn dump_unreachable() {
+    let src = r#"decl @exit(i32) #[noreturn]
+
+fun @main(%x: i32): i32 {
+%entry:
+  br %x, %then, %end
+
+%then:
+  call @exit(1)
+  unreachable
+
+%end:
+  ret 0
+}
+"#;
+    let driver: Driver<_> = src.into();
+    let program = driver.generate_program().unwrap();
+    let main = program.func(program.func_layout()[1]);
+    let (_, then) = main.layout().bbs().iter().nth(1).unwrap();
+    let term = main.dfg().value(*then.insts().back_key().unwrap());
+    assert!(term.kind().is_terminator());
+    assert_eq!(term.kind().bb_uses().count(), 0);
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    assert_eq!(str::from_utf8(&gen.writer()).unwrap(), src);
+  }
+
+  #[test]
+  fn dump_renamed() {
+    let driver: Driver<_> = r#"
+      global @x = alloc i32, 1
+      decl @f(i32)
+      fun @main() {
+      %entry:
+        %0 = load @x
+        call @f(%0)
+        ret
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    let x = program.inst_layout()[0];
+    let f = program.func_layout()[0];
+    let main = program.func_layout()[1];
+    assert!(matches!(
+      program.rename_func(f, "f"),
+      Err(crate::ir::Error::InvalidName { .. })
+    ));
+    assert!(matches!(
+      program.rename_func(f, "@1"),
+      Err(crate::ir::Error::InvalidName { .. })
+    ));
+    assert!(matches!(
+      program.rename_value(x, "@main"),
+      Err(crate::ir::Error::NameConflict { .. })
+    ));
+    program.rename_func(main, "@main").unwrap();
+    program.rename_func(f, "@koopa_f").unwrap();
+    program.rename_value(x, "@koopa_x").unwrap();
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    assert_eq!(
+      str::from_utf8(&gen.writer()).unwrap(),
+      r#"global @koopa_x = alloc i32, 1
+
+decl @koopa_f(i32)
+
+fun @main() {
+%entry:
+  %0 = load @koopa_x
+  call @koopa_f(%0)
+  ret
+}
+"#
+    );
+  }
+
+  #[test]
+  fn dump_format_versions() {
+    let v1 = r#"global @x = alloc i32, zeroinit
+
+decl @getint(): i32
+
+decl @putint(i32)
+
+fun @main(): i32 {
+%entry:
+  %0 = call @getint()
+  call @putint(%0)
+  ret 0
+}
+
+decl @exit(i32)
+"#;
+    let v2 = r#"global @x = alloc i32, zeroinit
+
+decl @getint(): i32
+decl @putint(i32)
+
+fun @main(): i32 {
+%entry:
+  %0 = call @getint()
+  call @putint(%0)
+  ret 0
+}
+
+decl @exit(i32)
+"#;
+    let generate = |src: &str, version| {
+      let driver: Driver<_> = src.into();
+      let program = driver.generate_program().unwrap();
+      let visitor = super::Visitor::new(super::Config {
+        version,
+        ..Default::default()
+      });
+      let mut gen = Generator::with_visitor(Vec::new(), visitor);
+      gen.generate_on(&program).unwrap();
+      String::from_utf8(gen.writer()).unwrap()
+    };
+    use super::FormatVersion::*;
+    // the parser accepts the output of all versions
+    for src in [v1, v2] {
+      assert_eq!(generate(src, V1), v1);
+      assert_eq!(generate(src, V2), v2);
+      assert_eq!(generate(src, Latest), v2);
+    }
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen
+      .generate_on(&Driver::from(v1).generate_program().unwrap())
+      .unwrap();
+    assert_eq!(str::from_utf8(&gen.writer()).unwrap(), v2);
+  }
+
+  #[test]
+  fn dump_zero_runs() {
+    let compact = r#"global @x = alloc [[i32, 20], 20], {{1, 2, zeroinit}, {3, zeroinit}, zeroinit}
+global @y = alloc [i32, 17], {zeroinit}
+global @z = alloc [i32, 17], {1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0}
+
+"#;
+    let generate = |src: &str, config| {
+      let program = Driver::from(src).generate_program().unwrap();
+      let mut gen = Generator::with_visitor(Vec::new(), super::Visitor::new(config));
+      gen.generate_on(&program).unwrap();
+      String::from_utf8(gen.writer()).unwrap()
+    };
+    assert_eq!(generate(compact, Default::default()), compact);
+    let expanded = super::Config {
+      expand_zeros: true,
+      ..Default::default()
+    };
+    let verbose = generate(compact, expanded);
+    let y = format!("{{{}}}", ["zeroinit"; 17].join(", "));
+    assert!(verbose.contains(&format!("@y = alloc [i32, 17], {y}\n")));
+    let v2 = super::Config {
+      version: super::FormatVersion::V2,
+      ..Default::default()
+    };
+    assert_eq!(generate(compact, v2), verbose);
+    // both forms are parsed into the same aggregates
+    assert_eq!(generate(&verbose, expanded), verbose);
+    assert_eq!(generate(&verbose, Default::default()), compact);
+  }
+
+  #[test]
+  fn stable_temps_and_focus() {
+    let src = r#"fun @f(): i32 {
+%entry:
+  %0 = add 1, 2
+  ret %0
+}
+
+fun @g(): i32 {
+%entry:
+  %1 = add 1, 2
+  jump %2
+
+%2:
+  ret %1
+}
+"#;
+    let program = Driver::from(src).generate_program().unwrap();
+    let generate = |visitor| {
+      let mut gen = Generator::with_visitor(Vec::new(), visitor);
+      gen.generate_on(&program).unwrap();
+      String::from_utf8(gen.writer()).unwrap()
+    };
+    let config = super::Config {
+      stable_temps: true,
+      ..Default::default()
+    };
+    assert_eq!(generate(super::Visitor::default()), src);
+    // temporaries of `@g` are numbered from 0
+    let stable = src.replace("%1", "%0").replace("%2", "%1");
+    assert_eq!(generate(super::Visitor::new(config)), stable);
+    // only `@g` is generated as a definition
+    let g = program.func_layout()[1];
+    let focused = format!(
+      "decl @f(): i32\n\n{}",
+      &stable[stable.find("fun @g").unwrap()..]
+    );
+    assert_eq!(generate(super::Visitor::new(config).with_focus(g)), focused);
+  }
+
+  #[test]
+  fn reachable_from() {
+    let src = r#"global @a = alloc i32, 1
+global @b = alloc i32, 2
+
+decl @getint(): i32
+decl @putint(i32)
+
+fun @f(): i32 {
+%entry:
+  %0 = call @getint()
+  %1 = load @a
+  %2 = add %0, %1
+  ret %2
+}
+
+fun @unused() {
+%entry:
+  %0 = load @b
+  call @putint(%0)
+  ret
+}
+
+fun @main(): i32 {
+%entry:
+  %0 = call @f()
+  ret %0
+}
+"#;
+    let program = Driver::from(src).generate_program().unwrap();
+    let generate = |roots: &[&str]| {
+      let visitor = super::Visitor::default().emit_reachable_from(roots);
+      let mut gen = Generator::with_visitor(Vec::new(), visitor);
+      gen.generate_on(&program)?;
+      Ok::<_, std::io::Error>(String::from_utf8(gen.writer()).unwrap())
+    };
+    let pruned = generate(&["@main"]).unwrap();
+    assert_eq!(
+      pruned,
+      r#"global @a = alloc i32, 1
+
+decl @getint(): i32
+
+fun @f(): i32 {
+%entry:
+  %0 = call @getint()
+  %1 = load @a
+  %2 = add %0, %1
+  ret %2
+}
+
+fun @main(): i32 {
+%entry:
+  %3 = call @f()
+  ret %3
+}
+"#
+    );
+    let program = Driver::from(pruned).generate_program().unwrap();
+    assert_eq!(crate::analysis::verifier::verify(&program), Ok(()));
+    assert_eq!(generate(&[]).unwrap(), "");
+    let err = generate(&["@main", "@g"]).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+  }
+
+  #[test]
+  fn dump_dangling_insts() {
+    let src = r#"fun @f(): i32 {
+%entry:
+  %0 = add 1, 2
+  %1 = mul 3, 4
+  ret %0
+}
+"#;
+    let mut program = Driver::from(src).generate_program().unwrap();
+    let f = program.func_layout()[0];
+    let data = program.func_mut(f);
+    let entry = data.layout().entry_bb().unwrap();
+    let mul = *data
+      .layout()
+      .bbs()
+      .node(&entry)
+      .unwrap()
+      .insts()
+      .keys()
+      .nth(1)
+      .unwrap();
+    data.dfg_mut().remove_value(mul);
+    let mut gen = KoopaGenerator::new(Vec::new());
+    let err = gen.generate_on(&program).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(
+      err.to_string(),
+      format!(
+        "instruction {mul:?} of basic block %entry does not exist in the layout of function @f"
+      )
+    );
+    assert_eq!(program.func_mut(f).prune_dangling_layout_entries(), [mul]);
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    assert_eq!(
+      str::from_utf8(&gen.writer()).unwrap(),
+      src.replace("  %1 = mul 3, 4\n", "")
+    );
+  }
+
+  #[test]
+  fn emit_to_string() {
+    let src = r#"global @x = alloc [i32, 10], {1, 2, 3, 0, 0, 0, 0, 0, 0, 0}
+global @s = alloc [i32, 3], "a\n\00"
+
+decl @getint(): i32
+
+fun @main(@n: i32): i32 {
+%entry:
+  %0 = call @getint()
+  %p = alloc [[i32, 2], 5]
+  jump %loop(0, %0)
+
+%loop(%i: i32, %sum: i32):
+  %1 = getelemptr @x, %i
+  %2 = load %1
+  %3 = add %sum, %2
+  %4 = add %i, 1
+  %cond = lt %4, @n
+  br %cond, %loop(%4, %3), %end
+
+%end:
+  ret %sum
+}
+"#;
+    let program = Driver::from(src).generate_program().unwrap();
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    let text = String::from_utf8(gen.writer()).unwrap();
+    assert_eq!(text, src);
+    // the output is appended, and is the same as the output of `generate_on`
+    let mut gen = KoopaGenerator::new(Vec::new());
+    assert!(gen.estimate_size(&program) >= src.len());
+    let mut buf = String::from("// header\n");
+    gen.emit_to_string(&program, &mut buf).unwrap();
+    assert_eq!(buf, format!("// header\n{src}"));
+  }
 }
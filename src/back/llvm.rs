@@ -3,6 +3,7 @@
 use crate::back::{self, NameManager, Prefix};
 use crate::ir::entities::{FunctionData, ValueData};
 use crate::ir::layout::BasicBlockNode;
+use crate::ir::phi::{self, PhiView};
 use crate::ir::values::*;
 use crate::ir::{BasicBlock, Program, Type, TypeKind, Value, ValueKind};
 use std::io::{Result, Write};
@@ -22,6 +23,7 @@ impl<W: Write> back::Visitor<W> for Visitor {
       nm,
       program,
       func: None,
+      phis: PhiView::default(),
     };
     visitor.visit()
   }
@@ -33,6 +35,7 @@ struct VisitorImpl<'a, W: Write> {
   nm: &'a mut NameManager,
   program: &'a Program,
   func: Option<&'a FunctionData>,
+  phis: PhiView,
 }
 
 /// Returns a reference to the current function.
@@ -70,7 +73,7 @@ impl<'a, W: Write> VisitorImpl<'a, W> {
     self.nm.set_prefix(Prefix::Custom {
       named: "@".into(),
       temp: "@$".into(),
-      max_len: Some(unsafe { NonZeroUsize::new_unchecked(Self::MAX_ID_LEN + 1) }),
+      max_len: NonZeroUsize::new(Self::MAX_ID_LEN + 1),
     });
     for inst in self.program.inst_layout() {
       self.visit_global_inst(&self.program.borrow_value(*inst))?;
@@ -85,6 +88,7 @@ impl<'a, W: Write> VisitorImpl<'a, W> {
       }
       let func = self.program.func(*func);
       self.func = Some(func);
+      self.phis = phi::to_phi_view(func);
       self.nm.enter_func_scope();
       self.visit_func(func)?;
       self.nm.exit_func_scope();
@@ -115,7 +119,7 @@ impl<'a, W: Write> VisitorImpl<'a, W> {
     self.nm.set_prefix(Prefix::Custom {
       named: "%".into(),
       temp: "%$".into(),
-      max_len: Some(unsafe { NonZeroUsize::new_unchecked(Self::MAX_ID_LEN + 1) }),
+      max_len: NonZeroUsize::new(Self::MAX_ID_LEN + 1),
     });
     // parameters
     if is_decl {
@@ -136,6 +140,9 @@ impl<'a, W: Write> VisitorImpl<'a, W> {
       }
     }
     write!(self.w, ")")?;
+    if func.is_noreturn() {
+      write!(self.w, " noreturn")?;
+    }
     // function body
     if !is_decl {
       writeln!(self.w, " {{")?;
@@ -153,7 +160,7 @@ impl<'a, W: Write> VisitorImpl<'a, W> {
     self.nm.set_prefix(Prefix::Custom {
       named: "@".into(),
       temp: "@$".into(),
-      max_len: Some(unsafe { NonZeroUsize::new_unchecked(Self::MAX_ID_LEN + 1) }),
+      max_len: NonZeroUsize::new(Self::MAX_ID_LEN + 1),
     });
     Ok(())
   }
@@ -164,29 +171,20 @@ impl<'a, W: Write> VisitorImpl<'a, W> {
     let data = func!(self).dfg().bb(bb);
     writeln!(self.w, "{}:", &self.nm.bb_name(data)[1..])?;
     // basic block parameters (phi functions)
-    for (i, param) in data.params().iter().enumerate() {
+    for param in data.params() {
+      let incoming = self.phis.incoming(*param).unwrap().to_vec();
       let param = value!(self, *param);
       write!(self.w, "  {} = phi ", self.nm.value_name(param))?;
       self.visit_type(param.ty())?;
       write!(self.w, " ")?;
-      for (k, user) in data.used_by().iter().enumerate() {
+      for (k, (pred, value)) in incoming.into_iter().enumerate() {
         if k != 0 {
           write!(self.w, ", ")?;
         }
         write!(self.w, "[")?;
-        match value!(self, *user).kind() {
-          ValueKind::Branch(br) => {
-            if br.true_bb() == bb {
-              self.visit_value(false, br.true_args()[i])?;
-            } else {
-              self.visit_value(false, br.false_args()[i])?;
-            }
-          }
-          ValueKind::Jump(jump) => self.visit_value(false, jump.args()[i])?,
-          _ => panic!("invalid branch/jump instruction"),
-        }
+        self.visit_value(false, value)?;
         write!(self.w, ", ")?;
-        self.visit_bb_ref(func!(self).layout().parent_bb(*user).unwrap())?;
+        self.visit_bb_ref(pred)?;
         write!(self.w, "]")?;
       }
       writeln!(self.w)?;
@@ -229,6 +227,7 @@ impl<'a, W: Write> VisitorImpl<'a, W> {
       ValueKind::Jump(v) => self.visit_jump(v),
       ValueKind::Call(v) => self.visit_call(inst.ty(), v),
       ValueKind::Return(v) => self.visit_return(v),
+      ValueKind::Unreachable(_) => write!(self.w, "unreachable"),
       _ => panic!("invalid instruction"),
     }?;
     writeln!(self.w)
@@ -422,6 +421,16 @@ impl<'a, W: Write> VisitorImpl<'a, W> {
         }
         write!(self.w, "]")
       }
+      ValueKind::Bytes(v) => {
+        write!(self.w, "[")?;
+        for (i, elem) in v.elems().enumerate() {
+          if i != 0 {
+            write!(self.w, ", ")?;
+          }
+          write!(self.w, "i32 {}", elem)?;
+        }
+        write!(self.w, "]")
+      }
       _ => panic!("invalid constant"),
     }
   }
@@ -553,6 +562,35 @@ $entry:
     );
   }
 
+  #[test]
+  fn dump_ptr_return() {
+    let driver: Driver<_> = r#"
+      global @x = alloc [i32, 10], zeroinit
+
+      fun @elem(@i: i32): *i32 {
+      %entry:
+        %p = getelemptr @x, @i
+        ret %p
+      }
+    "#
+    .into();
+    let mut gen = LlvmGenerator::new(Vec::new());
+    gen
+      .generate_on(&driver.generate_program().unwrap())
+      .unwrap();
+    assert_eq!(
+      str::from_utf8(&gen.writer()).unwrap(),
+      r#"@x = global [10 x i32] zeroinitializer
+
+define i32* @elem(i32 %i) {
+$entry:
+  %$p = getelementptr inbounds [10 x i32], [10 x i32]* @x, i32 0, i32 %i
+  ret i32* %$p
+}
+"#
+    );
+  }
+
   #[test]
   fn dump_ir_bb_params() {
     let driver: Driver<_> = r#"
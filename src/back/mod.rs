@@ -6,7 +6,11 @@
 //! * The Koopa IR generator ([`Generator`]), name manager ([`NameManager`])
 //!   and the Koopa IR visitor trait ([`Visitor`]).
 //! * The text form Koopa IR generator ([`KoopaGenerator`]).
+//! * The highlighted text form Koopa IR generator for terminals and
+//!   HTML ([`PrettyGenerator`]).
 //! * The LLVM IR generator ([`LlvmGenerator`]).
+//! * The Rust builder code generator ([`RustGenerator`]).
+//! * The DOT control flow graph generator ([`DotGenerator`]).
 //!
 //! # Examples
 //!
@@ -52,14 +56,30 @@
 //! # }
 //! ```
 
+pub mod dot;
 pub mod generator;
 pub mod koopa;
 pub mod llvm;
+pub mod pretty;
+pub mod rust;
 
 pub use generator::{Generator, NameManager, Prefix, Visitor};
+pub use koopa::FormatVersion;
 
 /// Generator for generating Koopa IR structures into text formatted Koopa IR.
 pub type KoopaGenerator<W> = Generator<W, koopa::Visitor>;
 
+/// Generator for generating Koopa IR structures into highlighted text
+/// formatted Koopa IR.
+pub type PrettyGenerator<W> = Generator<W, pretty::Visitor>;
+
 /// Generator for generating Koopa IR into LLVM IR.
 pub type LlvmGenerator<W> = Generator<W, llvm::Visitor>;
+
+/// Generator for generating Koopa IR into Rust code
+/// which rebuilds the program by using the builder APIs.
+pub type RustGenerator<W> = Generator<W, rust::Visitor>;
+
+/// Generator for generating control flow graphs of Koopa IR functions
+/// into the DOT language.
+pub type DotGenerator<W> = Generator<W, dot::Visitor>;
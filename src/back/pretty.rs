@@ -0,0 +1,207 @@
+//! Implementations of the visitor for the highlighted text form Koopa IR
+//! generator.
+//!
+//! The generated programs have the same content as the ones generated
+//! by the [text form generator](crate::back::KoopaGenerator), only with
+//! additional markups.
+
+use crate::back::koopa::{Config, Markup, Token, VisitorImpl};
+use crate::back::{self, NameManager};
+use crate::ir::Program;
+use std::io::{Result, Write};
+
+/// Output modes of the highlighted text form Koopa IR generator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+  /// Text for terminals, tokens are colored by ANSI escape sequences.
+  ///
+  /// If `color` is `false`, no escape sequence is generated, and the
+  /// output is the same as the plain text form.
+  Ansi {
+    /// Whether to color the tokens.
+    color: bool,
+  },
+  /// Standalone HTML document, tokens are wrapped in `<span>`s.
+  ///
+  /// Each definition of values, basic blocks and functions has an `id`
+  /// anchor, and each use of them links to the definition. The type of
+  /// values and functions is shown when hovering over the names.
+  Html,
+}
+
+impl Default for Mode {
+  fn default() -> Self {
+    Mode::Ansi { color: true }
+  }
+}
+
+/// Visitor for generating the in-memory form Koopa IR program into
+/// the highlighted text form.
+#[derive(Default)]
+pub struct Visitor {
+  mode: Mode,
+  config: Config,
+}
+
+impl Visitor {
+  /// Creates a new visitor with the given output mode.
+  pub fn new(mode: Mode) -> Self {
+    Self {
+      mode,
+      config: Config::default(),
+    }
+  }
+
+  /// Sets the configurations of the text form, like whether to emit
+  /// attributes.
+  pub fn with_config(mut self, config: Config) -> Self {
+    self.config = config;
+    self
+  }
+}
+
+impl<W: Write> back::Visitor<W> for Visitor {
+  type Output = ();
+
+  fn visit(&mut self, w: &mut W, nm: &mut NameManager, program: &Program) -> Result<()> {
+    let markup = match self.mode {
+      Mode::Ansi { color: false } => Markup::Plain,
+      Mode::Ansi { color: true } => Markup::Ansi,
+      Mode::Html => Markup::Html,
+    };
+    if markup == Markup::Html {
+      write_html_header(w)?;
+    }
+    let mut visitor = VisitorImpl {
+      w,
+      nm,
+      program: Some(program),
+      config: &self.config,
+      line_starts: None,
+      dfg: None,
+      handle_names: false,
+      markup,
+      scope: String::new(),
+      focus: None,
+      reachable: None,
+    };
+    visitor.visit()?;
+    if markup == Markup::Html {
+      writeln!(visitor.w, "</pre>\n</body>\n</html>")?;
+    }
+    Ok(())
+  }
+}
+
+/// Writes the header of the HTML document, including the styles
+/// of all tokens.
+fn write_html_header<W: Write>(w: &mut W) -> Result<()> {
+  writeln!(w, "<!DOCTYPE html>")?;
+  writeln!(w, "<html>\n<head>\n<meta charset=\"utf-8\">")?;
+  writeln!(w, "<title>Koopa IR</title>\n<style>")?;
+  writeln!(w, "a {{ text-decoration: none; }}")?;
+  writeln!(w, ":target {{ background-color: #ffef9f; }}")?;
+  for (token, style) in [
+    (Token::Keyword, "color: #a626a4; font-weight: bold;"),
+    (Token::Type, "color: #0184bc;"),
+    (Token::Const, "color: #986801;"),
+    (Token::Func, "color: #4078f2; font-weight: bold;"),
+    (Token::BasicBlock, "color: #50a14f;"),
+    (Token::Value, "color: #383a42;"),
+    (Token::Comment, "color: #a0a1a7;"),
+  ] {
+    writeln!(w, ".{} {{ {style} }}", token.class())?;
+  }
+  writeln!(w, "</style>\n</head>\n<body>\n<pre>")
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::back::koopa::Escape;
+  use crate::back::{Generator, KoopaGenerator};
+  use crate::front::Driver;
+
+  const SRC: &str = r#"global @g = alloc i32, 1
+
+fun @main(@x: i32): i32 {
+%entry:
+  %0 = load @g
+  %1 = add %0, @x
+  jump %end(%1)
+
+%end(%r: i32):
+  ret %r
+}
+"#;
+
+  fn generate(mode: Mode) -> String {
+    let driver: Driver<_> = SRC.into();
+    let program = driver.generate_program().unwrap();
+    let mut gen = Generator::with_visitor(Vec::new(), Visitor::new(mode));
+    gen.generate_on(&program).unwrap();
+    String::from_utf8(gen.writer()).unwrap()
+  }
+
+  /// Removes ANSI escape sequences in the given text.
+  fn strip_ansi(text: &str) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(i) = rest.find('\x1b') {
+      result += &rest[..i];
+      rest = &rest[i + rest[i..].find('m').unwrap() + 1..];
+    }
+    result + rest
+  }
+
+  /// Removes HTML tags in the given text, and unescapes the entities.
+  fn strip_html(text: &str) -> String {
+    let body = &text[text.find("<pre>").unwrap() + 6..text.find("</pre>").unwrap()];
+    let mut result = String::new();
+    let mut in_tag = false;
+    for c in body.chars() {
+      match c {
+        '<' => in_tag = true,
+        '>' => in_tag = false,
+        _ if !in_tag => result.push(c),
+        _ => {}
+      }
+    }
+    result
+      .replace("&lt;", "<")
+      .replace("&gt;", ">")
+      .replace("&amp;", "&")
+  }
+
+  #[test]
+  fn same_content() {
+    let driver: Driver<_> = SRC.into();
+    let program = driver.generate_program().unwrap();
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    let plain = String::from_utf8(gen.writer()).unwrap();
+    assert_eq!(plain, SRC);
+    assert_eq!(generate(Mode::Ansi { color: false }), plain);
+    let ansi = generate(Mode::Ansi { color: true });
+    assert!(ansi.contains("\x1b[1;35mglobal\x1b[0m"));
+    assert_eq!(strip_ansi(&ansi), plain);
+    assert_eq!(strip_html(&generate(Mode::Html)), plain);
+  }
+
+  #[test]
+  fn html_links() {
+    let html = generate(Mode::Html);
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains(r#"<span class="v" id="g-at_g" title="*i32">@g</span>"#));
+    assert!(html.contains(r##"<a class="v" href="#g-at_g" title="*i32">@g</a>"##));
+    assert!(html.contains(r#"<span class="v" id="f0-pc_0" title="i32">%0</span>"#));
+    assert!(html.contains(r##"<a class="v" href="#f0-pc_0" title="i32">%0</a>"##));
+    assert!(html.contains(r#"<span class="bb" id="f0-pc_end">%end</span>"#));
+    assert!(html.contains(r##"<a class="bb" href="#f0-pc_end">%end</a>"##));
+    assert!(html.contains(r#"<span class="fn" id="g-at_main" title="(i32): i32">@main</span>"#));
+    assert_eq!(
+      Escape(r#"<a href="x">&'"#).to_string(),
+      "&lt;a href=&quot;x&quot;&gt;&amp;&#39;"
+    );
+  }
+}
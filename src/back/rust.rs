@@ -0,0 +1,733 @@
+//! Implementations of the visitor for the Rust builder code generator.
+
+use crate::back::{self, NameManager};
+use crate::ir::entities::{FunctionData, ValueData};
+use crate::ir::{
+  AttrValue, BasicBlock, FuncAttrs, Function, OverflowMode, Program, Type, TypeKind, Value,
+  ValueKind,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::io::{Result, Write};
+
+/// Visitor for generating the in-memory form Koopa IR program into
+/// Rust code, which rebuilds the program by using the builder APIs
+/// of this crate.
+///
+/// The generated code is a function that returns the program:
+///
+/// ```text
+/// use koopa::ir::*;
+///
+/// /// Builds the program.
+/// pub fn build_program() -> Program {
+///   let mut program = Program::new();
+///   ...
+///   program
+/// }
+/// ```
+///
+/// Global values are created first, then all functions, and then the
+/// basic blocks and instructions of each function definition. Names,
+/// attributes and layouts are kept, and source locations are dropped.
+/// Variables are named after the names of values, or numbered if the
+/// values have no names.
+///
+/// Instructions are created in the reverse post order of basic blocks,
+/// so operands are always created before their users in valid programs.
+/// Constructs that can not be generated, like operands defined after
+/// their users in the same basic block, are generated as `todo!()`s
+/// with comments.
+pub struct Visitor {
+  func_name: String,
+}
+
+impl Visitor {
+  /// Creates a new visitor that generates a function with the given name.
+  pub fn new(func_name: &str) -> Self {
+    Self {
+      func_name: func_name.into(),
+    }
+  }
+}
+
+impl Default for Visitor {
+  fn default() -> Self {
+    Self::new("build_program")
+  }
+}
+
+impl<W: Write> back::Visitor<W> for Visitor {
+  type Output = ();
+
+  fn visit(&mut self, w: &mut W, _: &mut NameManager, program: &Program) -> Result<()> {
+    let mut visitor = VisitorImpl {
+      program,
+      body: String::new(),
+      indent: 1,
+      global_idents: Idents::default(),
+      local_idents: Idents::default(),
+      funcs: HashMap::new(),
+      globals: HashMap::new(),
+      values: HashMap::new(),
+      bbs: HashMap::new(),
+      builder_traits: false,
+      func_builder: false,
+    };
+    visitor.visit();
+    // imports are generated after the body, since only the used ones
+    // are generated
+    if visitor.func_builder {
+      writeln!(w, "use koopa::ir::builder::FunctionBuilder;")?;
+    }
+    if visitor.builder_traits {
+      writeln!(w, "use koopa::ir::builder_traits::*;")?;
+    }
+    writeln!(w, "use koopa::ir::*;")?;
+    writeln!(w)?;
+    writeln!(w, "/// Builds the program.")?;
+    writeln!(w, "pub fn {}() -> Program {{", self.func_name)?;
+    write!(w, "{}", visitor.body)?;
+    writeln!(w, "}}")
+  }
+}
+
+/// The implementation of Rust builder code generator.
+struct VisitorImpl<'a> {
+  program: &'a Program,
+  body: String,
+  indent: usize,
+  global_idents: Idents,
+  local_idents: Idents,
+  funcs: HashMap<Function, String>,
+  globals: HashMap<Value, String>,
+  values: HashMap<Value, String>,
+  bbs: HashMap<BasicBlock, String>,
+  builder_traits: bool,
+  func_builder: bool,
+}
+
+/// Emits a line with the current indentation to the body.
+macro_rules! emit {
+  ($self:ident) => {
+    $self.body.push('\n')
+  };
+  ($self:ident, $($arg:tt)+) => {{
+    for _ in 0..$self.indent {
+      $self.body.push_str("  ");
+    }
+    writeln!($self.body, $($arg)+).unwrap();
+  }};
+}
+
+impl<'a> VisitorImpl<'a> {
+  /// Visits the program.
+  fn visit(&mut self) {
+    let program = self.program;
+    if program.inst_layout().is_empty() && program.func_layout().is_empty() {
+      emit!(self, "Program::new()");
+      return;
+    }
+    emit!(self, "let mut program = Program::new();");
+    // global values
+    if !program.inst_layout().is_empty() {
+      emit!(self);
+      emit!(self, "// global values");
+      for inst in program.inst_layout() {
+        self.visit_global_inst(*inst);
+      }
+    }
+    // functions, all functions are created before function bodies,
+    // so calls can refer to functions after the caller
+    if !program.func_layout().is_empty() {
+      emit!(self);
+      emit!(self, "// functions");
+      let callees = callees(program);
+      for func in program.func_layout() {
+        self.visit_func_decl(*func, &callees);
+      }
+      for func in program.func_layout() {
+        let data = program.func(*func);
+        if !data.dfg().bbs().is_empty() {
+          emit!(self);
+          self.visit_func(*func, data);
+        }
+      }
+    }
+    emit!(self);
+    emit!(self, "program");
+  }
+
+  /// Generates the given global instruction.
+  fn visit_global_inst(&mut self, inst: Value) {
+    let (kind, info) = {
+      let data = self.program.borrow_value(inst);
+      (data.kind().clone(), ValueInfo::new(&data))
+    };
+    match kind {
+      ValueKind::GlobalAlloc(alloc) => {
+        let init = self.global_const(alloc.init());
+        self.builder_traits = true;
+        let expr = format!("program.new_value().global_alloc({init})");
+        self.define(inst, info, "g", expr);
+      }
+      _ => {
+        self.todo("unsupported global instruction");
+        emit!(self, "todo!();");
+      }
+    }
+  }
+
+  /// Generates the given global constant, returns its variable name.
+  fn global_const(&mut self, value: Value) -> String {
+    if let Some(ident) = self.globals.get(&value) {
+      return ident.clone();
+    }
+    let (kind, ty, mut info) = {
+      let data = self.program.borrow_value(value);
+      (
+        data.kind().clone(),
+        data.ty().clone(),
+        ValueInfo::new(&data),
+      )
+    };
+    let expr = match kind {
+      ValueKind::Integer(int) => format!("program.new_value().integer({})", int.value()),
+      ValueKind::ZeroInit(_) => format!("program.new_value().zero_init({})", type_expr(&ty)),
+      ValueKind::Undef(_) => format!("program.new_value().undef({})", type_expr(&ty)),
+      ValueKind::Aggregate(agg) => {
+        let elems: Vec<_> = agg.elems().iter().map(|e| self.global_const(*e)).collect();
+        format!("program.new_value().aggregate(vec![{}])", elems.join(", "))
+      }
+      ValueKind::Bytes(bytes) => {
+        let lit: String = bytes
+          .bytes()
+          .iter()
+          .flat_map(|b| std::ascii::escape_default(*b))
+          .map(char::from)
+          .collect();
+        format!("program.new_value().bytes(*b\"{lit}\")")
+      }
+      _ => return self.todo("unsupported global initializer"),
+    };
+    self.builder_traits = true;
+    info.used = true;
+    self.define(value, info, "c", expr).unwrap()
+  }
+
+  /// Generates the creation of the given function.
+  fn visit_func_decl(&mut self, func: Function, callees: &HashSet<Function>) {
+    let data = self.program.func(func);
+    let TypeKind::Function(params_ty, ret_ty) = data.ty().kind() else {
+      panic!("invalid function type");
+    };
+    let name = data.name();
+    let is_decl = data.dfg().bbs().is_empty();
+    let mut expr = if is_decl {
+      let params: Vec<_> = params_ty.iter().map(type_expr).collect();
+      format!(
+        "FunctionData::new_decl({name:?}.into(), vec![{}], {})",
+        params.join(", "),
+        type_expr(ret_ty)
+      )
+    } else {
+      let params: Vec<_> = data
+        .params()
+        .iter()
+        .map(|p| {
+          let param = data.dfg().value(*p);
+          format!("({}, {})", opt_str(param.name()), type_expr(param.ty()))
+        })
+        .collect();
+      format!(
+        "FunctionData::with_param_names({name:?}.into(), vec![{}], {})",
+        params.join(", "),
+        type_expr(ret_ty)
+      )
+    };
+    if !data.func_attrs().is_empty() {
+      write!(
+        expr,
+        ".with_func_attrs({})",
+        func_attrs_expr(data.func_attrs())
+      )
+      .unwrap();
+    }
+    if is_decl && !callees.contains(&func) && data.attrs().is_empty() {
+      emit!(self, "program.new_func({expr});");
+      return;
+    }
+    let ident = self.global_idents.alloc("f", Some(name));
+    emit!(self, "let {ident} = program.new_func({expr});");
+    for (key, attr) in data.attrs() {
+      emit!(
+        self,
+        "program.func_mut({ident}).set_attr({key:?}, {});",
+        attr_expr(attr)
+      );
+    }
+    self.funcs.insert(func, ident);
+  }
+
+  /// Generates basic blocks and instructions of the given function.
+  fn visit_func(&mut self, func: Function, data: &'a FunctionData) {
+    self.local_idents = Idents::default();
+    self.values.clear();
+    self.bbs.clear();
+    self.func_builder = true;
+    emit!(self, "// function `{}`", data.name());
+    emit!(self, "{{");
+    self.indent += 1;
+    // parameters
+    emit!(self, "let func = program.func_mut({});", self.funcs[&func]);
+    for (i, p) in data.params().iter().enumerate() {
+      let param = data.dfg().value(*p);
+      if !param.used_by().is_empty() {
+        let ident = self.local_idents.alloc("p", param.name().as_deref());
+        emit!(self, "let {ident} = func.params()[{i}];");
+        self.values.insert(*p, ident);
+      }
+    }
+    emit!(self, "let mut b = FunctionBuilder::new(func);");
+    if data.dfg().intern_consts() {
+      emit!(self, "b.func_mut().dfg_mut().set_intern_consts(true);");
+    }
+    let mode = data.dfg().overflow_mode();
+    if mode != OverflowMode::default() {
+      emit!(
+        self,
+        "b.func_mut().dfg_mut().set_overflow_mode(OverflowMode::{mode:?});"
+      );
+    }
+    // basic blocks
+    for bb in data.layout().bbs().keys() {
+      let bb_data = data.dfg().bb(*bb);
+      let ident = self.local_idents.alloc("bb", bb_data.name().as_deref());
+      let name = opt_str(bb_data.name());
+      if bb_data.params().is_empty() {
+        emit!(self, "let {ident} = b.create_bb({name});");
+      } else {
+        self.builder_traits = true;
+        let params: Vec<_> = bb_data
+          .params()
+          .iter()
+          .map(|p| {
+            let param = data.dfg().value(*p);
+            format!("({}, {})", opt_str(param.name()), type_expr(param.ty()))
+          })
+          .collect();
+        emit!(
+          self,
+          "let {ident} = b.func_mut().dfg_mut().new_bb().basic_block_with_param_names({name}, vec![{}]);",
+          params.join(", ")
+        );
+        emit!(
+          self,
+          "b.func_mut().layout_mut().bbs_mut().push_key_back({ident}).unwrap();"
+        );
+        for (i, p) in bb_data.params().iter().enumerate() {
+          let param = data.dfg().value(*p);
+          if !param.used_by().is_empty() {
+            let pid = self.local_idents.alloc("v", param.name().as_deref());
+            emit!(
+              self,
+              "let {pid} = b.func().dfg().bb({ident}).params()[{i}];"
+            );
+            self.values.insert(*p, pid);
+          }
+        }
+      }
+      self.bbs.insert(*bb, ident);
+    }
+    // instructions
+    for bb in bb_order(data) {
+      emit!(self);
+      emit!(self, "b.position_at_end({});", self.bbs[&bb]);
+      for inst in data.layout().bbs().node(&bb).unwrap().insts().keys() {
+        self.visit_local_inst(data, *inst);
+      }
+    }
+    self.indent -= 1;
+    emit!(self, "}}");
+  }
+
+  /// Generates the given local instruction.
+  fn visit_local_inst(&mut self, data: &'a FunctionData, inst: Value) {
+    let value = data.dfg().value(inst);
+    let expr = match value.kind() {
+      ValueKind::Alloc(_) => match value.ty().kind() {
+        TypeKind::Pointer(base) => format!("b.alloc({})", type_expr(base)),
+        _ => panic!("invalid pointer type"),
+      },
+      ValueKind::Load(load) => format!("b.load({})", self.operand(data, load.src())),
+      ValueKind::Store(store) => {
+        let v = self.operand(data, store.value());
+        let dest = self.operand(data, store.dest());
+        format!("b.store({v}, {dest})")
+      }
+      ValueKind::GetPtr(gp) => {
+        let src = self.operand(data, gp.src());
+        let index = self.operand(data, gp.index());
+        format!("b.get_ptr({src}, {index})")
+      }
+      ValueKind::GetElemPtr(gep) => {
+        let src = self.operand(data, gep.src());
+        let index = self.operand(data, gep.index());
+        format!("b.get_elem_ptr({src}, {index})")
+      }
+      ValueKind::Binary(bin) => {
+        let lhs = self.operand(data, bin.lhs());
+        let rhs = self.operand(data, bin.rhs());
+        format!("b.binary(BinaryOp::{:?}, {lhs}, {rhs})", bin.op())
+      }
+      ValueKind::Branch(br) => {
+        let cond = self.operand(data, br.cond());
+        let tbb = self.bb_ref(br.true_bb());
+        let fbb = self.bb_ref(br.false_bb());
+        if br.true_args().is_empty() && br.false_args().is_empty() {
+          format!("b.branch({cond}, {tbb}, {fbb})")
+        } else {
+          let targs = self.operands(data, br.true_args());
+          let fargs = self.operands(data, br.false_args());
+          format!("b.branch_with_args({cond}, {tbb}, {fbb}, vec![{targs}], vec![{fargs}])")
+        }
+      }
+      ValueKind::Jump(jump) => {
+        let target = self.bb_ref(jump.target());
+        if jump.args().is_empty() {
+          format!("b.jump({target})")
+        } else {
+          let args = self.operands(data, jump.args());
+          format!("b.jump_with_args({target}, vec![{args}])")
+        }
+      }
+      ValueKind::Call(call) => {
+        let callee = match self.funcs.get(&call.callee()) {
+          Some(callee) => callee.clone(),
+          None => self.todo("callee is not in the program"),
+        };
+        let args = self.operands(data, call.args());
+        format!("b.call({callee}, vec![{args}])")
+      }
+      ValueKind::Return(ret) => match ret.value() {
+        Some(v) => format!("b.ret(Some({}))", self.operand(data, v)),
+        None => "b.ret(None)".into(),
+      },
+      ValueKind::Unreachable(_) => "b.unreachable()".into(),
+      _ => {
+        self.todo("unsupported local instruction");
+        emit!(self, "todo!();");
+        return;
+      }
+    };
+    self.define(inst, ValueInfo::new(value), "v", expr);
+  }
+
+  /// Returns the variable name of the given operand.
+  ///
+  /// Constants are generated on their first uses.
+  fn operand(&mut self, data: &'a FunctionData, value: Value) -> String {
+    let ident = if value.is_global() {
+      self.globals.get(&value)
+    } else {
+      self.values.get(&value)
+    };
+    if let Some(ident) = ident {
+      return ident.clone();
+    }
+    let Some(v) = data.dfg().values().get(&value) else {
+      return self.todo("operand is not in the function");
+    };
+    let expr = match v.kind() {
+      ValueKind::Integer(int) => format!("b.integer({})", int.value()),
+      ValueKind::ZeroInit(_) => format!("b.zero_init({})", type_expr(v.ty())),
+      ValueKind::Undef(_) => format!("b.undef({})", type_expr(v.ty())),
+      ValueKind::Aggregate(agg) => {
+        self.builder_traits = true;
+        let elems = self.operands(data, agg.elems());
+        format!("b.func_mut().dfg_mut().new_value().aggregate(vec![{elems}])")
+      }
+      _ => {
+        let name = v.name().as_deref().unwrap_or("unnamed value");
+        return self.todo(&format!("operand `{name}` is used before its definition"));
+      }
+    };
+    let mut info = ValueInfo::new(v);
+    info.used = true;
+    self.define(value, info, "c", expr).unwrap()
+  }
+
+  /// Returns the comma separated variable names of the given operands.
+  fn operands(&mut self, data: &'a FunctionData, values: &[Value]) -> String {
+    let values: Vec<_> = values.iter().map(|v| self.operand(data, *v)).collect();
+    values.join(", ")
+  }
+
+  /// Returns the variable name of the given basic block.
+  fn bb_ref(&mut self, bb: BasicBlock) -> String {
+    match self.bbs.get(&bb) {
+      Some(ident) => ident.clone(),
+      None => self.todo("basic block is not in the layout"),
+    }
+  }
+
+  /// Generates a statement that creates a value by the given expression.
+  ///
+  /// The value will be bound to a new variable if it is used, or it has
+  /// a name or attributes. Returns the variable name if exists.
+  fn define(
+    &mut self,
+    value: Value,
+    info: ValueInfo,
+    prefix: &str,
+    expr: String,
+  ) -> Option<String> {
+    if !info.used && info.name.is_none() && info.attrs.is_empty() {
+      emit!(self, "{expr};");
+      return None;
+    }
+    let (idents, dfg) = if value.is_global() {
+      (&mut self.global_idents, "program")
+    } else {
+      (&mut self.local_idents, "b.func_mut().dfg_mut()")
+    };
+    let ident = idents.alloc(prefix, info.name.as_deref());
+    emit!(self, "let {ident} = {expr};");
+    if let Some(name) = &info.name {
+      emit!(
+        self,
+        "{dfg}.set_value_name({ident}, Some({name:?}.into()));"
+      );
+    }
+    for (key, attr) in &info.attrs {
+      emit!(
+        self,
+        "{dfg}.set_value_attr({ident}, {key:?}, {});",
+        attr_expr(attr)
+      );
+    }
+    if value.is_global() {
+      self.globals.insert(value, ident.clone());
+    } else {
+      self.values.insert(value, ident.clone());
+    }
+    Some(ident)
+  }
+
+  /// Generates a comment for the unsupported construct,
+  /// returns a `todo!()` expression.
+  fn todo(&mut self, msg: &str) -> String {
+    emit!(self, "// TODO: {msg}");
+    "todo!()".into()
+  }
+}
+
+/// Information of a value that should be kept in the generated code.
+struct ValueInfo {
+  name: Option<String>,
+  attrs: BTreeMap<String, AttrValue>,
+  used: bool,
+}
+
+impl ValueInfo {
+  fn new(data: &ValueData) -> Self {
+    Self {
+      name: data.name().clone(),
+      attrs: data.attrs().clone(),
+      used: !data.used_by().is_empty(),
+    }
+  }
+}
+
+/// Allocator of unique variable names in a scope.
+#[derive(Default)]
+struct Idents {
+  used: HashSet<String>,
+  next_id: HashMap<String, usize>,
+}
+
+impl Idents {
+  /// Returns a new variable name with the given prefix
+  /// for the entity with the given symbol name.
+  fn alloc(&mut self, prefix: &str, name: Option<&str>) -> String {
+    let base = match name {
+      Some(name) => {
+        let name: String = name
+          .chars()
+          .skip(1)
+          .map(|c| {
+            if c.is_ascii_alphanumeric() {
+              c.to_ascii_lowercase()
+            } else {
+              '_'
+            }
+          })
+          .collect();
+        format!("{prefix}_{name}")
+      }
+      None => prefix.into(),
+    };
+    let next_id = self.next_id.entry(base.clone()).or_default();
+    loop {
+      let ident = match (name, *next_id) {
+        (Some(_), 0) => base.clone(),
+        (Some(_), id) => format!("{base}_{id}"),
+        (None, id) => format!("{base}{id}"),
+      };
+      *next_id += 1;
+      if self.used.insert(ident.clone()) {
+        return ident;
+      }
+    }
+  }
+}
+
+/// Returns all functions that are called in the given program.
+fn callees(program: &Program) -> HashSet<Function> {
+  program
+    .funcs()
+    .values()
+    .flat_map(|data| data.dfg().values().values())
+    .filter_map(|v| match v.kind() {
+      ValueKind::Call(call) => Some(call.callee()),
+      _ => None,
+    })
+    .collect()
+}
+
+/// Returns basic blocks in the layout of the given function in the
+/// reverse post order, followed by unreachable basic blocks in the
+/// layout order.
+fn bb_order(data: &FunctionData) -> Vec<BasicBlock> {
+  let layout = data.layout();
+  let successors = |bb: BasicBlock| {
+    let node = layout.bbs().node(&bb).unwrap();
+    let succs = node
+      .insts()
+      .back_key()
+      .map(|inst| match data.dfg().value(*inst).kind() {
+        ValueKind::Branch(br) => vec![br.true_bb(), br.false_bb()],
+        ValueKind::Jump(jump) => vec![jump.target()],
+        _ => vec![],
+      })
+      .unwrap_or_default();
+    succs
+      .into_iter()
+      .filter(|bb| layout.bbs().contains_key(bb))
+      .collect::<Vec<_>>()
+  };
+  let mut visited = HashSet::new();
+  let mut order = Vec::new();
+  if let Some(entry) = layout.entry_bb() {
+    visited.insert(entry);
+    let mut stack = vec![(entry, successors(entry), 0)];
+    while let Some((bb, succs, i)) = stack.last_mut() {
+      if let Some(&succ) = succs.get(*i) {
+        *i += 1;
+        if visited.insert(succ) {
+          stack.push((succ, successors(succ), 0));
+        }
+      } else {
+        order.push(*bb);
+        stack.pop();
+      }
+    }
+  }
+  order.reverse();
+  order.extend(layout.bbs().keys().filter(|bb| !visited.contains(bb)));
+  order
+}
+
+/// Returns the expression that creates the given type.
+fn type_expr(ty: &Type) -> String {
+  match ty.kind() {
+    TypeKind::Int32 => "Type::get_i32()".into(),
+    TypeKind::Unit => "Type::get_unit()".into(),
+    TypeKind::Array(base, len) => format!("Type::get_array({}, {len})", type_expr(base)),
+    TypeKind::Pointer(base) => format!("Type::get_pointer({})", type_expr(base)),
+    TypeKind::Function(params, ret) => {
+      let params: Vec<_> = params.iter().map(type_expr).collect();
+      format!(
+        "Type::get_function(vec![{}], {})",
+        params.join(", "),
+        type_expr(ret)
+      )
+    }
+  }
+}
+
+/// Returns the expression of the given optional string.
+fn opt_str(s: &Option<String>) -> String {
+  match s {
+    Some(s) => format!("Some({s:?}.into())"),
+    None => "None".into(),
+  }
+}
+
+/// Returns the expression of the given function attributes.
+fn func_attrs_expr(attrs: &FuncAttrs) -> String {
+  let mut fields = Vec::new();
+  if attrs.pure {
+    fields.push("pure: true".to_string());
+  }
+  if attrs.noreturn {
+    fields.push("noreturn: true".to_string());
+  }
+  if let Some(conv) = &attrs.conv {
+    fields.push(format!("conv: Some({conv:?}.into())"));
+  }
+  format!(
+    "FuncAttrs {{ {}, ..Default::default() }}",
+    fields.join(", ")
+  )
+}
+
+/// Returns the expression of the given attribute value.
+fn attr_expr(attr: &AttrValue) -> String {
+  match attr {
+    AttrValue::Int(i) => format!("AttrValue::Int({i})"),
+    AttrValue::Str(s) => format!("AttrValue::Str({s:?}.into())"),
+    AttrValue::Bool(b) => format!("AttrValue::Bool({b})"),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::back::RustGenerator;
+  use crate::ir::builder::FunctionBuilder;
+  use crate::ir::BinaryOp;
+  use std::str;
+
+  #[test]
+  fn forward_reference() {
+    let mut program = Program::new();
+    let func = FunctionData::new("@f".into(), vec![], Type::get_i32());
+    let func = program.new_func(func);
+    let mut builder = FunctionBuilder::new(program.func_mut(func));
+    let entry = builder.create_bb(None);
+    builder.position_at_end(entry);
+    let one = builder.integer(1);
+    let x = builder.binary(BinaryOp::Add, one, one);
+    builder.ret(Some(x));
+    // `%y` uses `%x` before its definition
+    builder.position_before(x);
+    let y = builder.binary(BinaryOp::Add, x, one);
+    let dfg = builder.func_mut().dfg_mut();
+    dfg.set_value_name(x, Some("%x".into()));
+    dfg.set_value_name(y, Some("%y".into()));
+    let mut gen = RustGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    let code = gen.writer();
+    let code = str::from_utf8(&code).unwrap();
+    assert!(code.contains(
+      r#"    b.position_at_end(bb0);
+    // TODO: operand `%x` is used before its definition
+    let c0 = b.integer(1);
+    let v_y = b.binary(BinaryOp::Add, todo!(), c0);
+    b.func_mut().dfg_mut().set_value_name(v_y, Some("%y".into()));
+    let v_x = b.binary(BinaryOp::Add, c0, c0);
+"#
+    ));
+  }
+}
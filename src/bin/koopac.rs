@@ -0,0 +1,24 @@
+//! The Koopa IR command line driver.
+//!
+//! See [`koopa::driver`] for the details.
+
+use koopa::driver::Driver;
+use std::env::args;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+  let driver = match Driver::from_args(args().skip(1)) {
+    Ok(driver) => driver,
+    Err(error) => {
+      eprintln!("{error}");
+      return ExitCode::from(error.exit_code());
+    }
+  };
+  match driver.run() {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(error) => {
+      driver.report(&error);
+      ExitCode::from(error.exit_code())
+    }
+  }
+}
@@ -0,0 +1,358 @@
+//! Diagnostic ([`Diagnostic`]) related implementations.
+//!
+//! Errors reported by the front-end, the verifier and the command line
+//! driver can all be converted to diagnostics, which can be rendered in
+//! the human readable format by [`Display`](fmt::Display), or in the
+//! machine readable format by [`Diagnostic::to_json`].
+//!
+//! Every diagnostic has a code (see [`code`]), which identifies the kind
+//! of the diagnostic. Codes are stable: once assigned, a code will not be
+//! reused for another kind of diagnostics.
+//!
+//! # Example
+//!
+//! ```
+//! use koopa::diagnostic::{code, Diagnostic, Location, Severity};
+//!
+//! let diag = Diagnostic::new(code::EMPTY_BB, Severity::Error, "basic block is empty")
+//!   .with_primary(Location::ir("@main", Some("%entry".into())));
+//! assert_eq!(diag.to_string(), "@main, %entry: basic block is empty");
+//! assert_eq!(
+//!   diag.to_json(),
+//!   r#"{"code":"E0113","severity":"error","message":"basic block is empty","primary":{"func":"@main","bb":"%entry"},"secondary":[],"notes":[]}"#,
+//! );
+//! ```
+
+use std::fmt::{self, Write};
+
+/// Codes of diagnostics.
+///
+/// Codes starting with `E` are for errors, and codes starting with `W`
+/// are for warnings.
+pub mod code {
+  /// Invalid command line arguments or driver configurations.
+  pub const USAGE: &str = "E0001";
+  /// Failed to read the input or write the output.
+  pub const IO: &str = "E0002";
+
+  /// Errors reported by the front-end, like syntax errors and
+  /// undefined symbols.
+  pub const FRONT_ERROR: &str = "E0010";
+  /// Warnings reported by the front-end, like unknown escapes and
+  /// unreachable basic blocks.
+  pub const FRONT_WARNING: &str = "W0010";
+
+  /// Initializer of a global allocation does not exist.
+  pub const INIT_NOT_EXIST: &str = "E0100";
+  /// Type of the initializer does not match the allocated type.
+  pub const INIT_TYPE_MISMATCH: &str = "E0101";
+  /// Length of byte data does not match the length of the array type.
+  pub const BYTES_LEN_MISMATCH: &str = "E0102";
+  /// Length of an aggregate does not match the length of the array type.
+  pub const AGGREGATE_LEN_MISMATCH: &str = "E0103";
+  /// Basic block in the layout does not exist.
+  pub const BB_NOT_EXIST: &str = "E0110";
+  /// Instruction in the layout does not exist.
+  pub const INST_NOT_EXIST: &str = "E0111";
+  /// Value in the layout is not a local instruction.
+  pub const NOT_INST: &str = "E0112";
+  /// Basic block is empty.
+  pub const EMPTY_BB: &str = "E0113";
+  /// Terminator is not at the end of the basic block.
+  pub const MISPLACED_TERMINATOR: &str = "E0114";
+  /// Basic block is not terminated.
+  pub const UNTERMINATED_BB: &str = "E0115";
+  /// Operand does not exist.
+  pub const OPERAND_NOT_EXIST: &str = "E0116";
+  /// Operand is not defined in the function.
+  pub const OPERAND_NOT_DEFINED: &str = "E0117";
+  /// Byte data is used by an instruction.
+  pub const BYTES_IN_INST: &str = "E0118";
+  /// Target basic block is not in the layout.
+  pub const TARGET_NOT_IN_LAYOUT: &str = "E0119";
+  /// Callee does not exist.
+  pub const CALLEE_NOT_EXIST: &str = "E0120";
+  /// Entry basic block has parameters.
+  pub const ENTRY_HAS_PARAMS: &str = "E0121";
+  /// Entry basic block has predecessors.
+  pub const ENTRY_HAS_PREDS: &str = "E0122";
+  /// Returned value does not match the return type of the function.
+  pub const RET_MISMATCH: &str = "E0123";
+  /// Instruction in the data flow graph is not in the layout.
+  pub const INST_NOT_IN_LAYOUT: &str = "E0124";
+  /// Ordering constraint between instructions is violated.
+  pub const ORDER_VIOLATION: &str = "E0125";
+}
+
+/// A diagnostic message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+  /// Code of the diagnostic, see [`code`].
+  pub code: &'static str,
+  /// Severity of the diagnostic.
+  pub severity: Severity,
+  /// The message.
+  pub message: String,
+  /// Location the diagnostic is about, or `None` if the diagnostic
+  /// is not specific to a location.
+  pub primary: Option<Location>,
+  /// Other related locations with labels.
+  pub secondary: Vec<Label>,
+  /// Additional notes, like suggestions for fixing the problem.
+  pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+  /// Creates a new diagnostic with no locations.
+  pub fn new<S: Into<String>>(code: &'static str, severity: Severity, message: S) -> Self {
+    Self {
+      code,
+      severity,
+      message: message.into(),
+      primary: None,
+      secondary: Vec::new(),
+      notes: Vec::new(),
+    }
+  }
+
+  /// Sets the primary location.
+  pub fn with_primary(mut self, location: Location) -> Self {
+    self.primary = Some(location);
+    self
+  }
+
+  /// Adds a secondary location with the given label.
+  pub fn with_secondary<S: Into<String>>(mut self, location: Location, label: S) -> Self {
+    self.secondary.push(Label {
+      location,
+      message: label.into(),
+    });
+    self
+  }
+
+  /// Adds a note.
+  pub fn with_note<S: Into<String>>(mut self, note: S) -> Self {
+    self.notes.push(note.into());
+    self
+  }
+
+  /// Returns the diagnostic as a single line JSON object.
+  ///
+  /// The object has fields `code`, `severity` (`"error"` or `"warning"`),
+  /// `message`, `primary` (a location or `null`), `secondary` (an
+  /// array of objects with fields `location` and `message`) and `notes`
+  /// (an array of strings). Locations
+  /// in source files have fields `file`, `start` and `end`, where `start`
+  /// and `end` are objects with fields `line` and `col`. Locations in IR
+  /// have fields `func` and `bb`, where `bb` may be `null`.
+  pub fn to_json(&self) -> String {
+    let mut json = String::new();
+    write!(
+      json,
+      r#"{{"code":{},"severity":{},"message":{},"primary":"#,
+      JsonStr(self.code),
+      JsonStr(self.severity.as_str()),
+      JsonStr(&self.message)
+    )
+    .unwrap();
+    match &self.primary {
+      Some(loc) => loc.write_json(&mut json),
+      None => json.push_str("null"),
+    }
+    json.push_str(r#","secondary":["#);
+    for (i, label) in self.secondary.iter().enumerate() {
+      if i != 0 {
+        json.push(',');
+      }
+      json.push_str(r#"{"location":"#);
+      label.location.write_json(&mut json);
+      write!(json, r#","message":{}}}"#, JsonStr(&label.message)).unwrap();
+    }
+    json.push_str(r#"],"notes":["#);
+    for (i, note) in self.notes.iter().enumerate() {
+      if i != 0 {
+        json.push(',');
+      }
+      write!(json, "{}", JsonStr(note)).unwrap();
+    }
+    json.push_str("]}");
+    json
+  }
+}
+
+impl fmt::Display for Diagnostic {
+  /// Renders the diagnostic in the human readable format, that is,
+  /// `location: message` followed by secondary locations with labels,
+  /// and then notes prefixed with `note: `, one per line.
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match &self.primary {
+      Some(loc) => write!(f, "{loc}: {}", self.message)?,
+      None => f.write_str(&self.message)?,
+    }
+    self
+      .secondary
+      .iter()
+      .try_for_each(|l| write!(f, "\n  {}: {}", l.location, l.message))?;
+    self
+      .notes
+      .iter()
+      .try_for_each(|n| write!(f, "\n  note: {n}"))
+  }
+}
+
+/// Severity of diagnostics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Severity {
+  /// Error.
+  Error,
+  /// Warning.
+  Warning,
+}
+
+impl Severity {
+  /// Returns the name of the severity.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Severity::Error => "error",
+      Severity::Warning => "warning",
+    }
+  }
+}
+
+impl fmt::Display for Severity {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+/// Location of diagnostics.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Location {
+  /// A range in the source file.
+  ///
+  /// Positions are pairs of the line number and the column number.
+  Source {
+    /// Name of the source file.
+    file: String,
+    /// The start position.
+    start: (u32, u32),
+    /// The end position.
+    end: (u32, u32),
+  },
+  /// A function or a global allocation in the IR,
+  /// and optionally a basic block in the function.
+  Ir {
+    /// Name of the function or the global allocation.
+    func: String,
+    /// Name of the basic block.
+    bb: Option<String>,
+  },
+}
+
+impl Location {
+  /// Creates a new location in the IR.
+  pub fn ir<S: Into<String>>(func: S, bb: Option<String>) -> Self {
+    Location::Ir {
+      func: func.into(),
+      bb,
+    }
+  }
+
+  /// Writes the location as a JSON object to the given string.
+  fn write_json(&self, json: &mut String) {
+    match self {
+      Location::Source { file, start, end } => write!(
+        json,
+        r#"{{"file":{},"start":{{"line":{},"col":{}}},"end":{{"line":{},"col":{}}}}}"#,
+        JsonStr(file),
+        start.0,
+        start.1,
+        end.0,
+        end.1
+      ),
+      Location::Ir { func, bb } => {
+        write!(json, r#"{{"func":{},"bb":"#, JsonStr(func)).unwrap();
+        match bb {
+          Some(bb) => write!(json, "{}}}", JsonStr(bb)),
+          None => write!(json, "null}}"),
+        }
+      }
+    }
+    .unwrap()
+  }
+}
+
+impl fmt::Display for Location {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Location::Source { file, start, .. } => write!(f, "{file}:{}:{}", start.0, start.1),
+      Location::Ir { func, bb: Some(bb) } => write!(f, "{func}, {bb}"),
+      Location::Ir { func, bb: None } => f.write_str(func),
+    }
+  }
+}
+
+/// A location with a label.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Label {
+  /// The location.
+  pub location: Location,
+  /// The label.
+  pub message: String,
+}
+
+/// Helper for writing strings as JSON string literals.
+struct JsonStr<'a>(&'a str);
+
+impl fmt::Display for JsonStr<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_char('"')?;
+    for c in self.0.chars() {
+      match c {
+        '"' => f.write_str("\\\"")?,
+        '\\' => f.write_str("\\\\")?,
+        '\n' => f.write_str("\\n")?,
+        '\r' => f.write_str("\\r")?,
+        '\t' => f.write_str("\\t")?,
+        c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+        c => f.write_char(c)?,
+      }
+    }
+    f.write_char('"')
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn render() {
+    let diag = Diagnostic::new(code::FRONT_ERROR, Severity::Error, "expected \"}\"\n")
+      .with_primary(Location::Source {
+        file: "a.koopa".into(),
+        start: (3, 5),
+        end: (3, 7),
+      })
+      .with_secondary(Location::ir("@f", None), "\tdefined here")
+      .with_note("did you mean '}'?");
+    assert_eq!(
+      diag.to_string(),
+      "a.koopa:3:5: expected \"}\"\n\n  @f: \tdefined here\n  note: did you mean '}'?"
+    );
+    assert_eq!(
+      diag.to_json(),
+      concat!(
+        r#"{"code":"E0010","severity":"error","message":"expected \"}\"\n","#,
+        r#""primary":{"file":"a.koopa","start":{"line":3,"col":5},"end":{"line":3,"col":7}},"#,
+        r#""secondary":[{"location":{"func":"@f","bb":null},"message":"\tdefined here"}],"#,
+        r#""notes":["did you mean '}'?"]}"#,
+      )
+    );
+    let diag = Diagnostic::new(code::IO, Severity::Warning, "\u{1}");
+    assert_eq!(
+      diag.to_json(),
+      r#"{"code":"E0002","severity":"warning","message":"\u0001","primary":null,"secondary":[],"notes":[]}"#
+    );
+  }
+}
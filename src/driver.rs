@@ -0,0 +1,465 @@
+//! Command line driver ([`Driver`]) related implementations.
+//!
+//! The driver reads a text form Koopa IR program from a file or the
+//! standard input, verifies it, runs the given passes on it, and emits
+//! the result in one of the supported formats ([`Emit`]). It is the
+//! library part of the `koopac` binary, and can be configured either
+//! from command line arguments ([`Driver::from_args`]) or by builder
+//! methods:
+//!
+//! ```no_run
+//! use koopa::driver::{Driver, Emit, Input, Output};
+//!
+//! let driver = Driver::new()
+//!   .input(Input::File("input.koopa".into()))
+//!   .output(Output::File("output.ll".into()))
+//!   .passes(["simplify", "global-dce"])
+//!   .emit(Emit::Llvm);
+//! if let Err(e) = driver.run() {
+//!   eprintln!("{e}");
+//!   std::process::exit(e.exit_code().into());
+//! }
+//! ```
+//!
+//! Not available on `wasm32-unknown-unknown`, which has no file system.
+
+use crate::analysis::verifier::{self, VerifyError};
+use crate::back::{DotGenerator, KoopaGenerator, LlvmGenerator, RustGenerator};
+use crate::diagnostic::{code, Diagnostic, Severity};
+use crate::front::{self, span::Span};
+use crate::ir::Program;
+use crate::opt::{Pass, PassManager};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::{error, fmt, fs};
+
+/// Usage of the command line driver.
+pub const USAGE: &str = "\
+usage: koopac [OPTIONS] [INPUT]
+
+Reads Koopa IR from INPUT, or the standard input if INPUT is `-` or
+not given.
+
+options:
+  -o, --output FILE    write the output to FILE (`-` for the standard output)
+  --passes P1,P2,...   run the given passes in order
+  --emit FORMAT        output format: koopa (default), llvm, rust or dot
+  --verify             only verify the input and report errors
+  --error-format FMT   error format: human (default) or json, which prints
+                       one JSON object per line for each diagnostic
+  -h, --help           print this help message
+
+passes: simplify, canonicalize-ptr, unroll, indvars, licm, gvn,
+        dead-args, return-prop, global-dce, specialize, normalize-names";
+
+/// Input of the driver.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Input {
+  /// The standard input.
+  #[default]
+  Stdin,
+  /// The file at the given path.
+  File(PathBuf),
+}
+
+/// Output of the driver.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Output {
+  /// The standard output.
+  #[default]
+  Stdout,
+  /// The file at the given path.
+  File(PathBuf),
+}
+
+/// Output format of the driver.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Emit {
+  /// Text form Koopa IR.
+  #[default]
+  Koopa,
+  /// Text form LLVM IR.
+  Llvm,
+  /// Rust code which rebuilds the program by using the builder APIs.
+  Rust,
+  /// Control flow graphs of functions in the DOT language.
+  Dot,
+}
+
+impl Emit {
+  /// Returns the output format of the given name,
+  /// or [`None`] if the name is unknown.
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "koopa" => Some(Emit::Koopa),
+      "llvm" => Some(Emit::Llvm),
+      "rust" => Some(Emit::Rust),
+      "dot" => Some(Emit::Dot),
+      _ => None,
+    }
+  }
+}
+
+/// Format of errors reported by the driver, see [`Driver::report`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorFormat {
+  /// Human readable messages.
+  #[default]
+  Human,
+  /// One JSON object per line for each diagnostic,
+  /// see [`Diagnostic::to_json`].
+  Json,
+}
+
+impl ErrorFormat {
+  /// Returns the error format of the given name,
+  /// or [`None`] if the name is unknown.
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "human" => Some(ErrorFormat::Human),
+      "json" => Some(ErrorFormat::Json),
+      _ => None,
+    }
+  }
+}
+
+/// A driver that processes text form Koopa IR programs.
+///
+/// See the [module-level documentation](crate::driver) for more details.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Driver {
+  input: Input,
+  output: Output,
+  passes: Vec<String>,
+  emit: Emit,
+  verify_only: bool,
+  error_format: ErrorFormat,
+  help: bool,
+}
+
+impl Driver {
+  /// Creates a new driver, which reads the standard input, runs no pass,
+  /// and writes text form Koopa IR to the standard output.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Creates a new driver from the given command line arguments,
+  /// excluding the program name. See [`USAGE`] for the usage.
+  pub fn from_args<I, S>(args: I) -> Result<Self, Error>
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    let mut driver = Self::new();
+    let mut input = None;
+    let mut args = args.into_iter().map(Into::into);
+    while let Some(arg) = args.next() {
+      // split `--option=value`
+      let (opt, mut value) = match arg.split_once('=') {
+        Some((opt, value)) if opt.starts_with("--") => (opt.to_string(), Some(value.to_string())),
+        _ => (arg, None),
+      };
+      let mut take_value = |opt: &str| {
+        value
+          .take()
+          .or_else(|| args.next())
+          .ok_or_else(|| Error::Usage(format!("missing value for option '{opt}'")))
+      };
+      match opt.as_str() {
+        "-o" | "--output" => driver.output = Output::from_arg(take_value(&opt)?),
+        "--passes" => driver.passes.extend(
+          take_value(&opt)?
+            .split(',')
+            .filter(|p| !p.is_empty())
+            .map(Into::into),
+        ),
+        "--emit" => {
+          let format = take_value(&opt)?;
+          driver.emit = Emit::from_name(&format)
+            .ok_or_else(|| Error::Usage(format!("unknown output format '{format}'")))?;
+        }
+        "--verify" => driver.verify_only = true,
+        "--error-format" => {
+          let format = take_value(&opt)?;
+          driver.error_format = ErrorFormat::from_name(&format)
+            .ok_or_else(|| Error::Usage(format!("unknown error format '{format}'")))?;
+        }
+        "-h" | "--help" => driver.help = true,
+        _ if opt.starts_with('-') && opt != "-" => {
+          return Err(Error::Usage(format!("unknown option '{opt}'")));
+        }
+        _ if input.is_some() => return Err(Error::Usage("too many inputs".into())),
+        _ => input = Some(Input::from_arg(opt)),
+      }
+    }
+    driver.input = input.unwrap_or_default();
+    Ok(driver)
+  }
+
+  /// Sets the input.
+  pub fn input(mut self, input: Input) -> Self {
+    self.input = input;
+    self
+  }
+
+  /// Sets the output.
+  pub fn output(mut self, output: Output) -> Self {
+    self.output = output;
+    self
+  }
+
+  /// Appends the given passes, which will be run in order.
+  ///
+  /// See [`Pass::builtin`] for the available names.
+  pub fn passes<I, S>(mut self, passes: I) -> Self
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    self.passes.extend(passes.into_iter().map(Into::into));
+    self
+  }
+
+  /// Sets the output format.
+  pub fn emit(mut self, emit: Emit) -> Self {
+    self.emit = emit;
+    self
+  }
+
+  /// Sets whether to only verify the input, without running passes and
+  /// generating the output.
+  pub fn verify_only(mut self, verify_only: bool) -> Self {
+    self.verify_only = verify_only;
+    self
+  }
+
+  /// Sets the error format.
+  ///
+  /// With [`ErrorFormat::Json`], the front-end logger does not print
+  /// messages, and all front-end diagnostics are returned in
+  /// [`Error::Parse`].
+  pub fn error_format(mut self, error_format: ErrorFormat) -> Self {
+    self.error_format = error_format;
+    self
+  }
+
+  /// Prints the given error to the standard error in the error format.
+  pub fn report(&self, error: &Error) {
+    match self.error_format {
+      ErrorFormat::Human => eprintln!("{error}"),
+      ErrorFormat::Json => {
+        for diag in error.diagnostics() {
+          eprintln!("{}", diag.to_json());
+        }
+      }
+    }
+  }
+
+  /// Runs the driver.
+  ///
+  /// The output is written only if the whole process succeeds,
+  /// so the output file will not be truncated on error.
+  pub fn run(&self) -> Result<(), Error> {
+    if self.help {
+      return self.write_output(format!("{USAGE}\n").as_bytes());
+    }
+    let program = match &self.input {
+      Input::Stdin => self.parse(front::Driver::from(io::stdin())),
+      Input::File(path) => self.parse(front::Driver::from_path(path).map_err(Error::Io)?),
+    }?;
+    let mut buf = Vec::new();
+    self.run_on(program, &mut buf)?;
+    self.write_output(&buf)
+  }
+
+  /// Runs the driver on the given text form Koopa IR, and writes the
+  /// output to the given writer. The input and output settings are
+  /// ignored.
+  pub fn run_on_text<W: Write>(&self, src: &str, w: W) -> Result<(), Error> {
+    let program = self.parse(src.into())?;
+    self.run_on(program, w)
+  }
+
+  /// Runs the driver on the given program, and writes the output to the
+  /// given writer. The input and output settings are ignored.
+  ///
+  /// The program is verified before and after running passes.
+  pub fn run_on<W: Write>(&self, mut program: Program, mut w: W) -> Result<(), Error> {
+    let passes = self
+      .passes
+      .iter()
+      .map(|name| Pass::builtin(name).ok_or_else(|| Error::Usage(format!("unknown pass '{name}'"))))
+      .collect::<Result<Vec<_>, _>>()?;
+    verifier::verify(&program).map_err(Error::Verify)?;
+    if self.verify_only {
+      return Ok(());
+    }
+    if !passes.is_empty() {
+      PassManager::from(passes).run_passes(&mut program);
+      verifier::verify(&program).map_err(Error::Verify)?;
+    }
+    match self.emit {
+      Emit::Koopa => KoopaGenerator::new(&mut w).generate_on(&program),
+      Emit::Llvm => LlvmGenerator::new(&mut w).generate_on(&program),
+      Emit::Rust => RustGenerator::new(&mut w).generate_on(&program),
+      Emit::Dot => DotGenerator::new(&mut w).generate_on(&program),
+    }
+    .map_err(Error::Io)
+  }
+
+  /// Generates a program by using the given front-end driver.
+  fn parse<T: io::Read>(&self, driver: front::Driver<T>) -> Result<Program, Error> {
+    // diagnostics are printed by the front-end logger in the human format
+    let printed = !cfg!(feature = "no-front-logger") && self.error_format == ErrorFormat::Human;
+    Span::set_quiet(!printed);
+    driver.generate_program().map_err(|_| {
+      let diags = Span::take_diagnostics();
+      Error::Parse(if printed { Vec::new() } else { diags })
+    })
+  }
+
+  /// Writes the given bytes to the output.
+  fn write_output(&self, bytes: &[u8]) -> Result<(), Error> {
+    match &self.output {
+      Output::Stdout => io::stdout().write_all(bytes),
+      Output::File(path) => fs::write(path, bytes),
+    }
+    .map_err(Error::Io)
+  }
+}
+
+impl Input {
+  /// Creates an input from the given command line argument,
+  /// `-` for the standard input.
+  fn from_arg(arg: String) -> Self {
+    if arg == "-" {
+      Input::Stdin
+    } else {
+      Input::File(arg.into())
+    }
+  }
+}
+
+impl Output {
+  /// Creates an output from the given command line argument,
+  /// `-` for the standard output.
+  fn from_arg(arg: String) -> Self {
+    if arg == "-" {
+      Output::Stdout
+    } else {
+      Output::File(arg.into())
+    }
+  }
+}
+
+/// Errors reported by the driver.
+#[derive(Debug)]
+pub enum Error {
+  /// Invalid arguments or configurations, like unknown passes.
+  Usage(String),
+  /// Failed to read the input or write the output.
+  Io(io::Error),
+  /// Failed to parse the input, with diagnostics reported by the
+  /// front-end. Diagnostics that have been printed by the front-end
+  /// logger are not included.
+  Parse(Vec<Diagnostic>),
+  /// The program failed to pass the verification.
+  Verify(Vec<VerifyError>),
+}
+
+impl Error {
+  /// Returns the exit code for the error.
+  ///
+  /// The exit codes are: 1 for usage errors, 2 for I/O errors,
+  /// 3 for parse errors and 4 for verification failures.
+  pub fn exit_code(&self) -> u8 {
+    match self {
+      Error::Usage(_) => 1,
+      Error::Io(_) => 2,
+      Error::Parse(_) => 3,
+      Error::Verify(_) => 4,
+    }
+  }
+
+  /// Converts the error to diagnostics.
+  pub fn diagnostics(&self) -> Vec<Diagnostic> {
+    match self {
+      Error::Usage(msg) => vec![Diagnostic::new(code::USAGE, Severity::Error, msg.clone())],
+      Error::Io(e) => vec![Diagnostic::new(
+        code::IO,
+        Severity::Error,
+        format!("I/O error: {e}"),
+      )],
+      Error::Parse(diags) => diags.clone(),
+      Error::Verify(errors) => errors.iter().map(VerifyError::to_diagnostic).collect(),
+    }
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Error::Usage(msg) => write!(f, "{msg}, run with '--help' for the usage"),
+      Error::Io(e) => write!(f, "I/O error: {e}"),
+      Error::Parse(diags) => {
+        let mut diags = diags.iter();
+        if let Some(diag) = diags.next() {
+          write!(f, "{diag}")?;
+        }
+        diags.try_for_each(|d| write!(f, "\n{d}"))
+      }
+      Error::Verify(errors) => {
+        write!(f, "verification failed")?;
+        errors.iter().try_for_each(|e| write!(f, "\n  {e}"))
+      }
+    }
+  }
+}
+
+impl error::Error for Error {}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn parse_args() {
+    let driver = Driver::from_args([
+      "-o",
+      "out.ll",
+      "--passes=simplify,global-dce",
+      "--emit",
+      "llvm",
+      "in.koopa",
+    ])
+    .unwrap();
+    assert_eq!(
+      driver,
+      Driver::new()
+        .input(Input::File("in.koopa".into()))
+        .output(Output::File("out.ll".into()))
+        .passes(["simplify", "global-dce"])
+        .emit(Emit::Llvm)
+    );
+    let driver = Driver::from_args(["--verify", "--error-format=json", "-"]).unwrap();
+    assert_eq!(
+      driver,
+      Driver::new()
+        .verify_only(true)
+        .error_format(ErrorFormat::Json)
+    );
+    for (args, msg) in [
+      (&["--emit", "asm"][..], "unknown output format 'asm'"),
+      (&["--error-format", "xml"], "unknown error format 'xml'"),
+      (&["-o"], "missing value for option '-o'"),
+      (&["--opt"], "unknown option '--opt'"),
+      (&["a", "b"], "too many inputs"),
+    ] {
+      match Driver::from_args(args.iter().copied()) {
+        Err(Error::Usage(m)) => assert_eq!(m, msg),
+        _ => panic!("expected usage error for {args:?}"),
+      }
+    }
+  }
+}
@@ -5,8 +5,9 @@
 //! ([`Parser`](crate::front::parser::Parser)) during the parsing process.
 
 use crate::front::span::Span;
-use crate::ir::BinaryOp;
+use crate::ir::{BinaryOp, FuncAttrs};
 use std::cmp::PartialEq;
+use std::mem;
 
 /// An abstract syntax tree (AST) of Koopa IR.
 #[derive(Debug)]
@@ -25,6 +26,17 @@ impl Ast {
   }
 }
 
+impl Drop for Ast {
+  fn drop(&mut self) {
+    // move the nested ASTs out, so each one is dropped with no children
+    let mut stack = Vec::new();
+    self.kind.take_nested(&mut stack);
+    while let Some(mut ast) = stack.pop() {
+      ast.kind.take_nested(&mut stack);
+    }
+  }
+}
+
 impl PartialEq for Ast {
   fn eq(&self, other: &Self) -> bool {
     // ignore field `span`
@@ -51,6 +63,8 @@ pub enum AstKind {
   UndefVal(UndefVal),
   /// Aggregate value.
   Aggregate(Aggregate),
+  /// Byte data.
+  Bytes(Bytes),
   /// Zero initializer.
   ZeroInit(ZeroInit),
   /// Symbol definition.
@@ -79,6 +93,8 @@ pub enum AstKind {
   FunCall(FunCall),
   /// Return.
   Return(Return),
+  /// Unreachable.
+  Unreachable(Unreachable),
   /// Function definition.
   FunDef(FunDef),
   /// Basic block.
@@ -91,6 +107,29 @@ pub enum AstKind {
   Error(Error),
 }
 
+impl AstKind {
+  /// Moves the nested types and aggregates of the current AST to the
+  /// given stack, leaves an [`End`] if there are any.
+  fn take_nested(&mut self, stack: &mut Vec<AstBox>) {
+    if !matches!(
+      self,
+      AstKind::ArrayType(_) | AstKind::PointerType(_) | AstKind::FunType(_) | AstKind::Aggregate(_)
+    ) {
+      return;
+    }
+    match mem::replace(self, AstKind::End(End)) {
+      AstKind::ArrayType(ty) => stack.push(ty.base),
+      AstKind::PointerType(ty) => stack.push(ty.base),
+      AstKind::FunType(ty) => {
+        stack.extend(ty.params);
+        stack.extend(ty.ret);
+      }
+      AstKind::Aggregate(agg) => stack.extend(agg.elems),
+      _ => unreachable!(),
+    }
+  }
+}
+
 /// 32-bit integer type.
 #[derive(Debug, PartialEq, Eq)]
 pub struct IntType;
@@ -193,6 +232,19 @@ impl Aggregate {
   }
 }
 
+/// Byte data.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Bytes {
+  pub bytes: Vec<u8>,
+}
+
+impl Bytes {
+  /// Creates a new boxed `Bytes` AST.
+  pub fn new_boxed(span: Span, bytes: Vec<u8>) -> AstBox {
+    Ast::new_boxed(span, AstKind::Bytes(Self { bytes }))
+  }
+}
+
 /// Zero initializer.
 #[derive(Debug, PartialEq, Eq)]
 pub struct ZeroInit;
@@ -403,12 +455,24 @@ impl Return {
   }
 }
 
+/// Unreachable.
+#[derive(Debug, PartialEq)]
+pub struct Unreachable;
+
+impl Unreachable {
+  /// Creates a new boxed `Unreachable` AST.
+  pub fn new_boxed(span: Span) -> AstBox {
+    Ast::new_boxed(span, AstKind::Unreachable(Self))
+  }
+}
+
 /// Function definition.
 #[derive(Debug, PartialEq)]
 pub struct FunDef {
   pub name: String,
   pub params: Vec<(String, AstBox)>,
   pub ret: Option<AstBox>,
+  pub attrs: FuncAttrs,
   pub bbs: Vec<AstBox>,
 }
 
@@ -419,6 +483,7 @@ impl FunDef {
     name: String,
     params: Vec<(String, AstBox)>,
     ret: Option<AstBox>,
+    attrs: FuncAttrs,
     bbs: Vec<AstBox>,
   ) -> AstBox {
     Ast::new_boxed(
@@ -427,6 +492,7 @@ impl FunDef {
         name,
         params,
         ret,
+        attrs,
         bbs,
       }),
     )
@@ -466,12 +532,27 @@ pub struct FunDecl {
   pub name: String,
   pub params: Vec<AstBox>,
   pub ret: Option<AstBox>,
+  pub attrs: FuncAttrs,
 }
 
 impl FunDecl {
   /// Creates a new boxed `FunDecl` AST.
-  pub fn new_boxed(span: Span, name: String, params: Vec<AstBox>, ret: Option<AstBox>) -> AstBox {
-    Ast::new_boxed(span, AstKind::FunDecl(Self { name, params, ret }))
+  pub fn new_boxed(
+    span: Span,
+    name: String,
+    params: Vec<AstBox>,
+    ret: Option<AstBox>,
+    attrs: FuncAttrs,
+  ) -> AstBox {
+    Ast::new_boxed(
+      span,
+      AstKind::FunDecl(Self {
+        name,
+        params,
+        ret,
+        attrs,
+      }),
+    )
   }
 }
 
@@ -1,6 +1,7 @@
 //! Koopa IR builder ([`Builder`]) related implementations.
 
 use crate::front::ast::{self, AstBox, AstKind};
+use crate::front::lexer::LineAttrs;
 use crate::front::span::{Error, Span};
 use crate::ir::builder_traits::*;
 use crate::ir::dfg::DataFlowGraph;
@@ -16,11 +17,13 @@ struct BasicBlockInfo {
 }
 
 impl BasicBlockInfo {
-  fn new(bb: BasicBlock) -> Self {
+  /// Creates a new basic block information, with space for at least
+  /// `defs` local definitions.
+  fn new(bb: BasicBlock, defs: usize) -> Self {
     Self {
       bb,
       preds: Vec::new(),
-      local_defs: HashMap::new(),
+      local_defs: HashMap::with_capacity(defs),
     }
   }
 }
@@ -29,13 +32,21 @@ impl BasicBlockInfo {
 ///
 /// `Builder` performs semantic checks (e.g. type checking) on
 /// Koopa IR ASTs, and then builds the in-memory form Koopa IR.
+///
+/// Symbols consisting of a sigil and digits (like `%0`, which the
+/// printer emits for unnamed values) are temporary symbols. They are
+/// treated exactly like other symbols: numbers may have gaps and appear
+/// in any order, but each symbol can only be defined once in a
+/// function. Values and basic blocks defined by temporary symbols have
+/// no names in the in-memory form, so the printer renumbers them.
 #[derive(Default)]
 pub struct Builder {
   program: Program,
   global_vars: HashMap<String, Value>,
   global_funcs: HashMap<String, Function>,
   local_bbs: HashMap<String, BasicBlockInfo>,
-  local_symbols: HashSet<String>,
+  local_symbols: HashMap<String, Span>,
+  attrs: LineAttrs,
 }
 
 /// Result returned by value generator methods in `Builder`.
@@ -53,43 +64,96 @@ macro_rules! unwrap_ast {
 
 /// Generates global/local initializer, used in method
 /// `generate_global_init` and `generate_local_init`.
+///
+/// An array aggregate ending with `zeroinit` can be shorter than the
+/// array, the remaining elements are filled with the trailing `zeroinit`.
 macro_rules! generate_init {
-  ($ty:expr, $ast:expr, $builder:expr, $agg_rec:expr, $elem_ty:ident) => {
-    match &$ast.kind {
-      AstKind::UndefVal(_) => Ok($builder.undef($ty.clone())),
-      AstKind::ZeroInit(_) => Ok($builder.zero_init($ty.clone())),
-      AstKind::IntVal(int) => {
-        if !$ty.is_i32() {
-          return_error!(
-            $ast.span,
-            "found type '{}', but it can not be applied to integers",
-            $ty
-          );
+  ($ty:expr, $ast:expr, $builder:expr) => {{
+    // remaining element ASTs, element type, generated elements and
+    // the length to fill to of the unfinished aggregates
+    let mut stack: Vec<(&[AstBox], &Type, Vec<Value>, Option<usize>)> = Vec::new();
+    let (mut ty, mut ast): (&Type, &AstBox) = ($ty, $ast);
+    'init: loop {
+      let mut value = match &ast.kind {
+        AstKind::UndefVal(_) => $builder.undef(ty.clone()),
+        AstKind::ZeroInit(_) => $builder.zero_init(ty.clone()),
+        AstKind::IntVal(int) => {
+          if !ty.is_i32() {
+            return_error!(
+              ast.span,
+              "found type '{}', but it can not be applied to integers",
+              ty
+            );
+          }
+          $builder.integer(int.value)
         }
-        Ok($builder.integer(int.value))
-      }
-      AstKind::Aggregate(agg) => {
-        let $elem_ty = match $ty.kind() {
-          TypeKind::Array(base, len) => {
-            if *len != agg.elems.len() {
-              log_error!(
-                $ast.span,
-                "expected array length {}, found length {}",
-                len,
-                agg.elems.len()
-              );
+        AstKind::Aggregate(agg) => {
+          let mut fill = None;
+          let elem_ty = match ty.kind() {
+            TypeKind::Array(base, len) => {
+              let zero_tail = matches!(agg.elems.last(), Some(e) if matches!(e.kind, AstKind::ZeroInit(_)));
+              if zero_tail && agg.elems.len() < *len {
+                fill = Some(*len);
+              } else if *len != agg.elems.len() {
+                log_error!(
+                  ast.span,
+                  "expected array length {}, found length {}",
+                  len,
+                  agg.elems.len()
+                );
+              }
+              base
+            }
+            TypeKind::Pointer(base) => base,
+            _ => return_error!(ast.span, "invalid aggregate type '{}'", ty),
+          };
+          let (first, rest) = agg.elems.split_first().expect("empty aggregate AST");
+          let cap = fill.unwrap_or(agg.elems.len());
+          stack.push((rest, elem_ty, Vec::with_capacity(cap), fill));
+          (ty, ast) = (elem_ty, first);
+          continue;
+        }
+        AstKind::Bytes(bytes) => {
+          match ty.kind() {
+            TypeKind::Array(base, len) if base.is_i32() => {
+              if *len != bytes.bytes.len() {
+                log_error!(
+                  ast.span,
+                  "expected array length {}, found length {}",
+                  len,
+                  bytes.bytes.len()
+                );
+              }
             }
-            base
+            _ => return_error!(
+              ast.span,
+              "found type '{}', but it can not be applied to byte data",
+              ty
+            ),
           }
-          TypeKind::Pointer(base) => base,
-          _ => return_error!($ast.span, "invalid aggregate type '{}'", $ty),
+          $builder.bytes(bytes.bytes.clone())
+        }
+        _ => panic!("invalid initializer AST"),
+      };
+      // complete the outer aggregates
+      loop {
+        let Some((rest, elem_ty, elems, _)) = stack.last_mut() else {
+          break 'init Ok(value);
         };
-        let elems = agg.elems.iter().map($agg_rec).collect::<Result<_, _>>()?;
-        Ok($builder.aggregate(elems))
+        elems.push(value);
+        if let Some((next, rest)) = rest.split_first() {
+          (ty, ast) = (elem_ty, next);
+          stack.last_mut().unwrap().0 = rest;
+          continue 'init;
+        }
+        let (_, _, mut elems, fill) = stack.pop().unwrap();
+        if let Some(len) = fill {
+          elems.resize(len, *elems.last().unwrap());
+        }
+        value = $builder.aggregate(elems);
       }
-      _ => panic!("invalid initializer AST"),
     }
-  };
+  }};
 }
 
 impl Builder {
@@ -109,6 +173,14 @@ impl Builder {
     }
   }
 
+  /// Adds attributes read by the lexer.
+  ///
+  /// Attributes will be attached to global allocations and instructions
+  /// that end at the same line, or functions that start at the same line.
+  pub fn add_attrs(&mut self, attrs: LineAttrs) {
+    self.attrs.extend(attrs);
+  }
+
   /// Consumes the builder and get the generated program.
   ///
   /// Available only when no error has occurred.
@@ -150,6 +222,12 @@ impl Builder {
     let decl = unwrap_ast!(ast.value, GlobalDecl);
     if let Ok(init) = self.generate_global_init(&Self::generate_type(&decl.ty), &decl.init) {
       let alloc = self.program.new_value().global_alloc(init);
+      self
+        .program
+        .set_value_span(alloc, Some(span.to_source_span()));
+      for (k, v) in self.attrs.remove(&span.end().line()).unwrap_or_default() {
+        self.program.set_value_attr(alloc, &k, v);
+      }
       // set name for the created value
       if !ast.name.is_temp() {
         self.program.set_value_name(alloc, Some(ast.name.clone()));
@@ -170,12 +248,11 @@ impl Builder {
   /// Builds on function definitions.
   fn build_on_fun_def(&mut self, span: &Span, ast: &ast::FunDef) {
     // generate return type
-    let ret_ty = ast
-      .ret
-      .as_ref()
-      .map_or_else(Type::get_unit, Self::generate_type);
+    let Some(ret_ty) = Self::generate_ret_type(&ast.ret) else {
+      return;
+    };
     // create function definition
-    let def = FunctionData::with_param_names(
+    let mut def = FunctionData::with_param_names(
       ast.name.clone(),
       ast
         .params
@@ -183,15 +260,21 @@ impl Builder {
         .map(|(n, a)| ((!n.is_temp()).then(|| n.clone()), Self::generate_type(a)))
         .collect(),
       ret_ty.clone(),
-    );
-    // create argument map
+    )
+    .with_func_attrs(ast.attrs.clone());
+    // reset local symbol set
+    self.local_symbols.clear();
+    // create argument map, and add arguments to local symbols
     let mut args = HashMap::new();
     for ((n, a), p) in ast.params.iter().zip(def.params()) {
       if args.insert(n.clone(), *p).is_some() {
         log_error!(a.span, "duplicate parameter name '{}'", n);
+      } else {
+        self.local_symbols.insert(n.clone(), a.span);
       }
     }
     // add to program
+    self.set_func_attrs(span, &mut def);
     let func = self.program.new_func(def);
     // add to global function map
     if self.global_vars.contains_key(&ast.name)
@@ -203,30 +286,36 @@ impl Builder {
         ast.name
       );
     }
-    // reset local symbol set
-    self.local_symbols.clear();
-    // get basic block list
+    // get basic block list, stop if the basic blocks are invalid,
+    // since the other checks rely on valid basic blocks
+    let err_num = Span::error_num();
     let bbs = self.get_block_list(&ast.bbs);
+    if Span::error_num() > err_num {
+      return;
+    }
     // initialize local basic block map
     self.init_local_bbs(func, args, &bbs);
     // build on all basic blocks
-    for block in bbs {
+    for (_, block) in bbs {
       self.build_on_block(func, &ret_ty, block);
     }
   }
 
   /// Builds on function declarations.
   fn build_on_fun_decl(&mut self, span: &Span, ast: &ast::FunDecl) {
+    // generate return type
+    let Some(ret_ty) = Self::generate_ret_type(&ast.ret) else {
+      return;
+    };
     // create function declaration
-    let decl = FunctionData::new_decl(
+    let mut decl = FunctionData::new_decl(
       ast.name.clone(),
       ast.params.iter().map(Self::generate_type).collect(),
-      ast
-        .ret
-        .as_ref()
-        .map_or_else(Type::get_unit, Self::generate_type),
-    );
+      ret_ty,
+    )
+    .with_func_attrs(ast.attrs.clone());
     // add to program
+    self.set_func_attrs(span, &mut decl);
     let func = self.program.new_func(decl);
     // add to global function map
     if self.global_vars.contains_key(&ast.name)
@@ -240,8 +329,15 @@ impl Builder {
     }
   }
 
+  /// Sets attributes of the given function by the given span.
+  fn set_func_attrs(&mut self, span: &Span, func: &mut FunctionData) {
+    for (k, v) in self.attrs.remove(&span.start().line()).unwrap_or_default() {
+      func.set_attr(&k, v);
+    }
+  }
+
   /// Gets basic block list in BFS order.
-  fn get_block_list<'a>(&self, bbs: &'a [AstBox]) -> Vec<&'a ast::Block> {
+  fn get_block_list<'a>(&self, bbs: &'a [AstBox]) -> Vec<(&'a Span, &'a ast::Block)> {
     // initialize queue and set
     let mut queue = VecDeque::new();
     let mut visited = HashSet::new();
@@ -265,7 +361,7 @@ impl Builder {
       if visited.insert(bb) {
         let info = bb_map[bb];
         // add to basic block list
-        bb_list.push(info.1);
+        bb_list.push(info);
         // add the successors to queue
         let last_stmt = info.1.stmts.last().unwrap();
         let mut add_target = |bb_name| {
@@ -277,7 +373,11 @@ impl Builder {
           } else if bb_map.contains_key(bb_name) {
             queue.push_back(bb_name);
           } else {
-            log_error!(last_stmt.span, "invalid basic block name '{}'", bb_name);
+            let notes = suggest_similar(bb_name, bb_map.keys().map(|n| n.as_str()));
+            last_stmt.span.log_error_with_notes(
+              format_args!("invalid basic block name '{}'", bb_name),
+              notes,
+            );
           }
         };
         match &last_stmt.kind {
@@ -286,7 +386,7 @@ impl Builder {
             add_target(fbb);
           }
           AstKind::Jump(ast::Jump { target, .. }) => add_target(target),
-          AstKind::Return(_) | AstKind::Error(_) => {}
+          AstKind::Return(_) | AstKind::Unreachable(_) | AstKind::Error(_) => {}
           _ => panic!("invalid end statement"),
         }
       }
@@ -301,10 +401,19 @@ impl Builder {
   }
 
   /// Initializes local basic block map.
-  fn init_local_bbs(&mut self, func: Function, args: HashMap<String, Value>, bbs: &[&ast::Block]) {
-    // create all basic blocks
+  fn init_local_bbs(
+    &mut self,
+    func: Function,
+    args: HashMap<String, Value>,
+    bbs: &[(&Span, &ast::Block)],
+  ) {
+    // create all basic blocks, pre-size the tables by the number of
+    // statements, which is the upper bound of the number of definitions
     self.local_bbs.clear();
-    for block in bbs {
+    self.local_bbs.reserve(bbs.len());
+    let defs = bbs.iter().map(|(_, b)| b.params.len() + b.stmts.len());
+    self.local_symbols.reserve(defs.sum::<usize>() + args.len());
+    for (span, block) in bbs {
       // create name and type of block parameters
       let params = block
         .params
@@ -316,6 +425,10 @@ impl Builder {
         .dfg_mut(func)
         .new_bb()
         .basic_block_with_param_names((!block.name.is_temp()).then(|| block.name.clone()), params);
+      self
+        .dfg_mut(func)
+        .bb_mut(bb)
+        .set_span(Some(span.to_source_span()));
       // add to function layout
       self
         .program
@@ -325,28 +438,23 @@ impl Builder {
         .push_key_back(bb)
         .unwrap();
       // create basic block info
-      let mut info = BasicBlockInfo::new(bb);
+      let mut info = BasicBlockInfo::new(bb, block.params.len() + block.stmts.len());
       // add basic block parameter to local definitions
       let params = self.program.func(func).dfg().bb(bb).params().to_vec();
-      for ((n, a), p) in block.params.iter().zip(params.into_iter()) {
-        // check if has already been defined
-        if self.global_vars.contains_key(n) || !self.local_symbols.insert(n.clone()) {
-          log_error!(a.span, "symbol '{}' has already been defined", n);
-        }
+      for ((n, a), p) in block.params.iter().zip(params) {
+        self.define_local_symbol(n, &a.span);
         // add to local basic block
         info.local_defs.insert(n.clone(), p);
       }
       // insert block info to local basic block map
       self.local_bbs.insert(block.name.clone(), info);
     }
-    // add argument references local symbols
-    self.local_symbols.extend(args.keys().cloned());
     // add argument references to the entry basic block
-    let entry_bb_name = &bbs[0].name;
+    let entry_bb_name = &bbs[0].1.name;
     let entry_info = &mut self.local_bbs.get_mut(entry_bb_name).unwrap();
     entry_info.local_defs = args;
     // fill predecessors
-    for block in bbs {
+    for (_, block) in bbs {
       let last_inst = block.stmts.last().unwrap();
       let mut add_pred = |bb_name| {
         self
@@ -371,7 +479,18 @@ impl Builder {
   fn build_on_block(&mut self, func: Function, ret_ty: &Type, ast: &ast::Block) {
     // generate each statements
     for stmt in &ast.stmts {
-      if let Ok(stmt) = self.generate_stmt(func, &ast.name, ret_ty, stmt) {
+      if let Ok(value) = self.generate_stmt(func, &ast.name, ret_ty, stmt) {
+        // set source location and attributes
+        self
+          .dfg_mut(func)
+          .set_value_span(value, Some(stmt.span.to_source_span()));
+        for (k, v) in self
+          .attrs
+          .remove(&stmt.span.end().line())
+          .unwrap_or_default()
+        {
+          self.dfg_mut(func).set_value_attr(value, &k, v);
+        }
         let info = self.local_bbs.get_mut(&ast.name).unwrap();
         // add statement to the current basic block
         self
@@ -380,49 +499,82 @@ impl Builder {
           .layout_mut()
           .bb_mut(info.bb)
           .insts_mut()
-          .push_key_back(stmt)
+          .push_key_back(value)
           .unwrap();
       }
     }
   }
 
+  /// Generates the return type of functions by the given AST.
+  ///
+  /// Logs an error and returns `None` if the return type is an array
+  /// type, which can not be returned.
+  fn generate_ret_type(ret: &Option<AstBox>) -> Option<Type> {
+    let Some(ret) = ret else {
+      return Some(Type::get_unit());
+    };
+    let ty = Self::generate_type(ret);
+    if let TypeKind::Array(..) = ty.kind() {
+      log_error!(ret.span, "{}", crate::ir::Error::ArrayReturn { ty });
+      None
+    } else {
+      Some(ty)
+    }
+  }
+
   /// Generates the type by the given AST.
   fn generate_type(ast: &AstBox) -> Type {
-    match &ast.kind {
-      AstKind::IntType(_) => Type::get_i32(),
-      AstKind::ArrayType(ast) => Type::get_array(Self::generate_type(&ast.base), ast.len),
-      AstKind::PointerType(ast) => Type::get_pointer(Self::generate_type(&ast.base)),
-      AstKind::FunType(ast) => Type::get_function(
-        ast.params.iter().map(Self::generate_type).collect(),
-        ast
-          .ret
-          .as_ref()
-          .map_or(Type::get_unit(), Self::generate_type),
-      ),
-      _ => panic!("invalid type AST"),
+    // type ASTs to visit, and whether their base types have been
+    // generated and pushed to `types`
+    let mut stack = vec![(ast, false)];
+    let mut types = Vec::new();
+    while let Some((ast, nested_done)) = stack.pop() {
+      match &ast.kind {
+        AstKind::IntType(_) => types.push(Type::get_i32()),
+        AstKind::ArrayType(ty) if nested_done => {
+          let base = types.pop().unwrap();
+          types.push(Type::get_array(base, ty.len));
+        }
+        AstKind::PointerType(_) if nested_done => {
+          let base = types.pop().unwrap();
+          types.push(Type::get_pointer(base));
+        }
+        AstKind::FunType(ty) if nested_done => {
+          let ret = match ty.ret {
+            Some(_) => types.pop().unwrap(),
+            None => Type::get_unit(),
+          };
+          let params = types.split_off(types.len() - ty.params.len());
+          types.push(Type::get_function(params, ret));
+        }
+        AstKind::ArrayType(ty) => stack.extend([(ast, true), (&ty.base, false)]),
+        AstKind::PointerType(ty) => stack.extend([(ast, true), (&ty.base, false)]),
+        AstKind::FunType(ty) => {
+          // parameters are generated before the return type
+          stack.push((ast, true));
+          stack.extend(ty.ret.iter().map(|ret| (ret, false)));
+          stack.extend(ty.params.iter().rev().map(|param| (param, false)));
+        }
+        _ => panic!("invalid type AST"),
+      }
     }
+    types.pop().unwrap()
   }
 
   /// Generates the global initializer by the given AST.
   fn generate_global_init(&mut self, ty: &Type, ast: &AstBox) -> ValueResult {
-    generate_init!(
-      ty,
-      ast,
-      self.program.new_value(),
-      |e| self.generate_global_init(elem_ty, e),
-      elem_ty
-    )
+    generate_init!(ty, ast, self.program.new_value())
   }
 
   /// Generates the local initializer by the given AST.
   fn generate_local_init(&mut self, func: Function, ty: &Type, ast: &AstBox) -> ValueResult {
-    generate_init!(
-      ty,
-      ast,
-      self.dfg_mut(func).new_value(),
-      |e| self.generate_local_init(func, elem_ty, e),
-      elem_ty
-    )
+    if let AstKind::Bytes(_) = &ast.kind {
+      return_error!(
+        ast.span,
+        "byte data can only be used in initializers of global allocations"
+      );
+    }
+    generate_init!(ty, ast, self.dfg_mut(func).new_value())
   }
 
   /// Generates the value by the given AST.
@@ -454,38 +606,48 @@ impl Builder {
   }
 
   /// Generates the symbol by the symbol name.
-  fn generate_symbol(&self, span: &Span, bb_name: &str, symbol: &str) -> ValueResult {
+  fn generate_symbol(&mut self, span: &Span, bb_name: &str, symbol: &str) -> ValueResult {
     // try to find symbol in global scope
     // if not found, find symbol in local definitions
-    let mut visited_bbs = HashSet::new();
-    self
-      .global_vars
-      .get(symbol)
-      .copied()
-      .or_else(|| self.generate_local_symbol(&mut visited_bbs, bb_name, symbol))
-      .ok_or_else(|| return_error!(span, "symbol '{}' not found", symbol))
+    match self.global_vars.get(symbol) {
+      Some(value) => Ok(*value),
+      None => self.generate_local_symbol(bb_name, symbol).ok_or_else(|| {
+        let globals = self.global_vars.keys();
+        let candidates = globals.chain(self.local_symbols.keys());
+        let notes = suggest_similar(symbol, candidates.map(String::as_str));
+        span.log_error_with_notes(format_args!("symbol '{}' not found", symbol), notes)
+      }),
+    }
   }
 
   /// Generates the symbol locally by the symbol name.
-  fn generate_local_symbol<'a>(
-    &'a self,
-    visited_bbs: &mut HashSet<&'a str>,
-    bb_name: &'a str,
-    symbol: &str,
-  ) -> Option<Value> {
-    if visited_bbs.insert(bb_name) {
-      // find symbol in local scope of the current basic block
-      // if not found, try to find symbol in all predecessors
-      let bb_info = &self.local_bbs[bb_name];
-      bb_info.local_defs.get(symbol).copied().or_else(|| {
-        bb_info
-          .preds
-          .iter()
-          .find_map(|pred| self.generate_local_symbol(visited_bbs, pred, symbol))
-      })
-    } else {
-      None
+  fn generate_local_symbol(&mut self, bb_name: &str, symbol: &str) -> Option<Value> {
+    // find symbol in local scope of the current basic block
+    // if not found, try to find symbol in all predecessors,
+    // use an explicit stack so long chains of blocks will not overflow
+    let mut visited_bbs = HashSet::new();
+    let mut stack = vec![bb_name];
+    let mut found = None;
+    while let Some(bb) = stack.pop() {
+      if !visited_bbs.insert(bb) {
+        continue;
+      }
+      let bb_info = &self.local_bbs[bb];
+      if let Some(value) = bb_info.local_defs.get(symbol) {
+        found = Some(*value);
+        break;
+      }
+      stack.extend(bb_info.preds.iter().rev().map(String::as_str));
+    }
+    // local symbols are unique in a function, so the found definition
+    // can be cached in the current basic block, this makes looking up
+    // the same symbol in successors cheap
+    let value = found?;
+    let bb_info = self.local_bbs.get_mut(bb_name).unwrap();
+    if !bb_info.local_defs.contains_key(symbol) {
+      bb_info.local_defs.insert(symbol.into(), value);
     }
+    Some(value)
   }
 
   /// Generates the basic block handle by the given basic block name.
@@ -494,7 +656,13 @@ impl Builder {
       .local_bbs
       .get(bb_name)
       .map(|info| info.bb)
-      .ok_or_else(|| log_error!(span, "invalid basic block name '{}'", bb_name))
+      .ok_or_else(|| {
+        let notes = suggest_similar(bb_name, self.local_bbs.keys().map(String::as_str));
+        span.log_error_with_notes(
+          format_args!("invalid basic block name '{}'", bb_name),
+          notes,
+        )
+      })
   }
 
   /// Generates argument list.
@@ -503,18 +671,20 @@ impl Builder {
     func: Function,
     span: &Span,
     bb_name: &str,
+    target: &str,
     args: &[AstBox],
     args_ty: &[Type],
   ) -> Result<Vec<Value>, Error> {
     // check length of argument list
-    if args.len() != args.len() {
+    if args.len() != args_ty.len() {
       return_error!(
         span,
-        "expected {} {}, found {} {}",
+        "{} expects {} {}, found {} {}",
+        target,
+        args_ty.len(),
+        "argument".to_plural(args_ty.len()),
         args.len(),
-        "arguments".to_plural(args.len()),
-        args.len(),
-        "arguments".to_plural(args.len())
+        "argument".to_plural(args.len())
       );
     }
     // generate arguments
@@ -539,13 +709,10 @@ impl Builder {
       AstKind::Jump(jump) => self.generate_jump(func, &ast.span, bb_name, jump),
       AstKind::FunCall(call) => self.generate_fun_call(func, &ast.span, bb_name, call),
       AstKind::Return(ret) => self.generate_return(func, &ast.span, bb_name, ret_ty, ret),
+      AstKind::Unreachable(_) => Ok(self.dfg_mut(func).new_value().unreachable()),
       AstKind::Error(_) => Error::default().into(),
       AstKind::SymbolDef(def) => {
-        // check if has already been defined
-        if self.global_vars.contains_key(&def.name) || !self.local_symbols.insert(def.name.clone())
-        {
-          log_error!(ast.span, "symbol '{}' has already been defined", def.name);
-        }
+        self.define_local_symbol(&def.name, &ast.span);
         // generate the value of the instruction
         let inst = self.generate_inst(func, bb_name, &def.value)?;
         // check type
@@ -575,6 +742,27 @@ impl Builder {
     }
   }
 
+  /// Adds the given symbol to the local symbol set, logs an error if the
+  /// symbol has already been defined.
+  ///
+  /// Temporary symbols (like `%0`) are checked in the same way as other
+  /// symbols. They need not be sequential, but must be unique in the
+  /// function.
+  fn define_local_symbol(&mut self, name: &str, span: &Span) {
+    if self.global_vars.contains_key(name) {
+      log_error!(span, "symbol '{}' has already been defined", name);
+    } else if let Some(other) = self.local_symbols.get(name) {
+      log_error!(
+        span,
+        "symbol '{}' is defined more than once, the other definition is at {}",
+        name,
+        other.start()
+      );
+    } else {
+      self.local_symbols.insert(name.into(), *span);
+    }
+  }
+
   /// Generates the instruction by the given AST.
   fn generate_inst(&mut self, func: Function, bb_name: &str, ast: &AstBox) -> ValueResult {
     match &ast.kind {
@@ -700,11 +888,13 @@ impl Builder {
     // get true target basic block and true arguments
     let tbb = self.generate_bb(span, &ast.tbb)?;
     let tbb_ty = self.bb_params_ty(func, tbb);
-    let targs = self.generate_args(func, span, bb_name, &ast.targs, &tbb_ty)?;
+    let target = format!("basic block '{}'", ast.tbb);
+    let targs = self.generate_args(func, span, bb_name, &target, &ast.targs, &tbb_ty)?;
     // get false target basic block and false arguments
     let fbb = self.generate_bb(span, &ast.fbb)?;
     let fbb_ty = self.bb_params_ty(func, fbb);
-    let fargs = self.generate_args(func, span, bb_name, &ast.fargs, &fbb_ty)?;
+    let target = format!("basic block '{}'", ast.fbb);
+    let fargs = self.generate_args(func, span, bb_name, &target, &ast.fargs, &fbb_ty)?;
     // check branch targets and arguments
     if tbb == fbb && (!targs.is_empty() || !fargs.is_empty()) {
       return_error!(
@@ -733,7 +923,8 @@ impl Builder {
     let target = self.generate_bb(span, &ast.target)?;
     // generate arguments
     let target_ty = self.bb_params_ty(func, target);
-    let args = self.generate_args(func, span, bb_name, &ast.args, &target_ty)?;
+    let name = format!("basic block '{}'", ast.target);
+    let args = self.generate_args(func, span, bb_name, &name, &ast.args, &target_ty)?;
     Ok(self.dfg_mut(func).new_value().jump_with_args(target, args))
   }
 
@@ -746,16 +937,17 @@ impl Builder {
     ast: &ast::FunCall,
   ) -> ValueResult {
     // get callee
-    let callee = *self
-      .global_funcs
-      .get(&ast.fun)
-      .ok_or_else(|| log_error!(span, "function '{}' not found", ast.fun))?;
+    let callee = *self.global_funcs.get(&ast.fun).ok_or_else(|| {
+      let notes = suggest_similar(&ast.fun, self.global_funcs.keys().map(String::as_str));
+      span.log_error_with_notes(format_args!("function '{}' not found", ast.fun), notes)
+    })?;
     // get arguments
     let args_ty = match self.program.func(callee).ty().kind() {
       TypeKind::Function(args, _) => args.clone(),
       _ => panic!("invalid function"),
     };
-    let args = self.generate_args(func, span, bb_name, &ast.args, &args_ty)?;
+    let name = format!("function '{}'", ast.fun);
+    let args = self.generate_args(func, span, bb_name, &name, &ast.args, &args_ty)?;
     Ok(self.dfg_mut(func).new_value().call(callee, args))
   }
 
@@ -810,10 +1002,90 @@ trait ToPlural {
 
 impl ToPlural for &str {
   fn to_plural(self, num: usize) -> String {
-    if num > 1 {
+    if num != 1 {
       format!("{}s", self)
     } else {
       self.into()
     }
   }
 }
+
+/// Maximum number of candidates [`suggest_similar`] will search.
+const MAX_SUGGESTION_CANDIDATES: usize = 4096;
+
+/// Maximum number of suggestions [`suggest_similar`] will make.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Returns notes that suggest candidates similar to the given unknown
+/// symbol, like `did you mean '@putint'?`.
+///
+/// Only candidates with the same sigil are suggested, and they are
+/// compared by the edit distance of the names without the sigil.
+/// Temporary symbols like `%0` are never suggested, and nothing is
+/// suggested for them. To keep reporting errors cheap, nothing is
+/// suggested if there are too many candidates.
+fn suggest_similar<'a, I>(symbol: &str, candidates: I) -> Vec<String>
+where
+  I: IntoIterator<Item = &'a str>,
+{
+  let is_temp = |s: &str| s[1..].chars().all(|c| c.is_ascii_digit());
+  if symbol.len() <= 1 || is_temp(symbol) {
+    return Vec::new();
+  }
+  let candidates: Vec<_> = candidates
+    .into_iter()
+    .take(MAX_SUGGESTION_CANDIDATES + 1)
+    .collect();
+  if candidates.len() > MAX_SUGGESTION_CANDIDATES {
+    return Vec::new();
+  }
+  let (sigil, name) = symbol.split_at(1);
+  let name: Vec<_> = name.chars().collect();
+  let threshold = (name.len() / 3).max(1);
+  let mut similar: Vec<_> = candidates
+    .into_iter()
+    .filter(|c| c.len() > 1 && c.starts_with(sigil) && *c != symbol && !is_temp(c))
+    .filter_map(|c| {
+      let dist = edit_distance(&name, &c[1..], threshold)?;
+      Some((dist, c))
+    })
+    .collect();
+  if similar.is_empty() {
+    return Vec::new();
+  }
+  similar.sort_unstable();
+  similar.dedup();
+  let names: Vec<_> = similar
+    .into_iter()
+    .take(MAX_SUGGESTIONS)
+    .map(|(_, c)| format!("'{c}'"))
+    .collect();
+  let note = match names.as_slice() {
+    [name] => format!("did you mean {name}?"),
+    [names @ .., last] => format!("did you mean {} or {last}?", names.join(", ")),
+    [] => unreachable!(),
+  };
+  vec![note]
+}
+
+/// Returns the Levenshtein distance between the given names, or `None`
+/// if the distance is greater than the given threshold.
+fn edit_distance(lhs: &[char], rhs: &str, threshold: usize) -> Option<usize> {
+  let rhs: Vec<_> = rhs.chars().collect();
+  if lhs.len().abs_diff(rhs.len()) > threshold {
+    return None;
+  }
+  // distances between prefixes of `lhs` and the current prefix of `rhs`
+  let mut dists: Vec<_> = (0..=lhs.len()).collect();
+  for (j, rc) in rhs.iter().enumerate() {
+    let mut diag = dists[0];
+    dists[0] = j + 1;
+    for (i, lc) in lhs.iter().enumerate() {
+      let sub = diag + usize::from(lc != rc);
+      diag = dists[i + 1];
+      dists[i + 1] = sub.min(dists[i] + 1).min(diag + 1);
+    }
+  }
+  let dist = dists[lhs.len()];
+  (dist <= threshold).then_some(dist)
+}
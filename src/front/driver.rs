@@ -7,8 +7,10 @@ use crate::front::parser::Parser;
 use crate::front::span::{Error, FileType, Span};
 use crate::ir::Program;
 use crate::log_raw_error;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
 use std::fs::File;
 use std::io::{self, Read};
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
 use std::path::Path;
 
 /// A driver for converting text form Koopa IR to IR structures.
@@ -32,6 +34,18 @@ impl<T: Read> Driver<T> {
     }
   }
 
+  /// Enables reading attribute comments (like `// !key = value`),
+  /// attributes will be attached to the corresponding instructions
+  /// and functions in the generated program.
+  ///
+  /// See [`Lexer::enable_attrs`] for more details.
+  pub fn with_attrs(mut self) -> Self {
+    if let Ok(parser) = &mut self.parser {
+      parser.lexer_mut().enable_attrs();
+    }
+    self
+  }
+
   /// Consumes the current driver and generates Koopa IR program
   /// from the reader.
   pub fn generate_program(mut self) -> Result<Program, Error> {
@@ -44,6 +58,7 @@ impl<T: Read> Driver<T> {
         break;
       }
       // build on the current AST
+      self.builder.add_attrs(parser.lexer_mut().take_attrs());
       self.builder.build_on(&ast);
       // exit if too many errors are generated
       if Span::error_num() > Self::MAX_ERR_NUM {
@@ -63,8 +78,11 @@ impl<T: Read> Driver<T> {
   }
 }
 
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
 impl Driver<File> {
   /// Creates a new driver from the given path.
+  ///
+  /// Not available on `wasm32-unknown-unknown`, which has no file system.
   pub fn from_path<P>(path: P) -> io::Result<Self>
   where
     P: AsRef<Path> + Clone,
@@ -97,6 +115,7 @@ impl<'a> From<&'a str> for Driver<io::Cursor<&'a str>> {
 #[cfg(test)]
 mod test {
   use super::*;
+  use crate::back::KoopaGenerator;
   use crate::ir::{Type, ValueKind};
 
   #[test]
@@ -214,6 +233,201 @@ mod test {
     assert_eq!(Span::error_num() + Span::warning_num(), 0);
   }
 
+  #[test]
+  fn generate_relaxed_lists() {
+    let driver: Driver<_> = r#"
+      decl @f(i32, i32,): i32
+
+      fun @main(@x: i32,): i32 {
+      %entry():
+        br @x, %then(1,), %end(2, @x,)
+
+      %then(%a: i32,):
+        %0 = call @f(%a, @x,)
+        jump %end(%0, %a)
+
+      %end(%b: i32, %c: i32,):
+        jump %exit()
+
+      %exit:
+        ret %b
+      }
+    "#
+    .into();
+    let program = driver.generate_program().unwrap();
+    assert_eq!(Span::error_num() + Span::warning_num(), 0);
+    let main = program.func(program.func_layout()[1]);
+    let params: Vec<_> = main
+      .dfg()
+      .bbs()
+      .values()
+      .map(|bb| bb.params().len())
+      .collect();
+    assert_eq!(params.iter().sum::<usize>(), 3);
+  }
+
+  #[test]
+  fn generate_bb_arg_count_mismatch() {
+    for (args, params) in [("", "(%x: i32)"), ("(1, 2)", "(%x: i32)"), ("(1)", "")] {
+      let src = format!(
+        r#"
+        fun @main(): i32 {{
+        %entry:
+          jump %end{args}
+
+        %end{params}:
+          ret 0
+        }}
+      "#
+      );
+      let driver: Driver<_> = src.as_str().into();
+      assert!(driver.generate_program().is_err());
+      assert_ne!(Span::error_num(), 0);
+    }
+  }
+
+  #[test]
+  fn generate_array_return() {
+    for src in [
+      "decl @f(): [i32, 2]",
+      "fun @f(): [[i32, 2], 2] {\n%entry:\n  ret zeroinit\n}",
+    ] {
+      let driver: Driver<_> = src.into();
+      assert!(driver.generate_program().is_err());
+      assert_ne!(Span::error_num(), 0);
+    }
+  }
+
+  #[test]
+  fn generate_zero_tail() {
+    let driver: Driver<_> = "global @x = alloc [[i32, 3], 4], {{1, zeroinit}, zeroinit}".into();
+    let program = driver.generate_program().unwrap();
+    let x = program.borrow_value(program.inst_layout()[0]);
+    let ValueKind::GlobalAlloc(alloc) = x.kind() else {
+      panic!("expected global allocation");
+    };
+    let init = program.borrow_value(alloc.init());
+    let ValueKind::Aggregate(agg) = init.kind() else {
+      panic!("expected aggregate");
+    };
+    assert_eq!(agg.elems().len(), 4);
+    let first = program.borrow_value(agg.elems()[0]);
+    let ValueKind::Aggregate(first) = first.kind() else {
+      panic!("expected aggregate");
+    };
+    assert_eq!(first.elems().len(), 3);
+    let zero = program.borrow_value(first.elems()[2]);
+    assert!(matches!(zero.kind(), ValueKind::ZeroInit(_)));
+    assert_eq!(zero.ty(), &Type::get_i32());
+    for src in [
+      "global @x = alloc [i32, 3], {1, 2}",
+      "global @x = alloc [i32, 3], {zeroinit, 1}",
+      "global @x = alloc [i32, 3], {1, 2, 3, zeroinit}",
+    ] {
+      let driver: Driver<_> = src.into();
+      assert!(driver.generate_program().is_err());
+    }
+  }
+
+  #[test]
+  fn generate_edited_temps() {
+    let dump = |src: &str| {
+      let driver: Driver<_> = src.into();
+      let mut gen = KoopaGenerator::new(Vec::new());
+      gen
+        .generate_on(&driver.generate_program().unwrap())
+        .unwrap();
+      String::from_utf8(gen.writer()).unwrap()
+    };
+    let text = dump(
+      r#"
+      fun @f(@x: i32): i32 {
+      %entry:
+        %5 = add @x, 1
+        %3 = mul %5, 2
+        %9 = sub %3, 3
+        ret %9
+      }
+    "#,
+    );
+    // the printer renumbers the temporaries
+    assert!(text.contains("%1 = mul %0, 2"));
+    // insert a new temporary with a gap in the numbers, and out of order
+    let edited = text.replace("  %2 =", "  %7 = add %1, 4\n  %2 =");
+    let edited = edited.replace("sub %1, 3", "sub %7, 3");
+    let text = dump(&edited);
+    assert!(text.contains("%2 = add %1, 4\n  %3 = sub %2, 3"));
+    // a duplicated `%3`
+    let edited = text.replace("  ret %3", "  %3 = add %2, 5\n  ret %3");
+    let driver: Driver<_> = edited.as_str().into();
+    assert!(driver.generate_program().is_err());
+    let diags = Span::take_diagnostics();
+    assert_eq!(diags.len(), 1);
+    assert_eq!(
+      diags[0].message,
+      "symbol '%3' is defined more than once, the other definition is at 6:3"
+    );
+  }
+
+  #[test]
+  fn generate_bb_param_same_as_arg() {
+    let driver: Driver<_> = r#"
+      fun @f(%0: i32) {
+      %entry:
+        jump %bb(1)
+
+      %bb(%0: i32):
+        ret
+      }
+    "#
+    .into();
+    assert!(driver.generate_program().is_err());
+    assert_eq!(Span::error_num(), 1);
+  }
+
+  #[test]
+  fn generate_bytes() {
+    let driver: Driver<_> = r#"
+      global @s = alloc [[i32, 4], 2], {"\FF\"\n\00", zeroinit}
+    "#
+    .into();
+    let program = driver.generate_program().unwrap();
+    let init = program
+      .borrow_values()
+      .values()
+      .find_map(|v| match v.kind() {
+        ValueKind::Bytes(b) => Some(b.bytes().to_vec()),
+        _ => None,
+      });
+    assert_eq!(init.as_deref(), Some(&b"\xff\"\n\0"[..]));
+    // non-ASCII characters are kept as UTF-8 bytes
+    let driver: Driver<_> = "global @s = alloc [i32, 2], \"\u{e9}\"".into();
+    let program = driver.generate_program().unwrap();
+    let init = program
+      .borrow_values()
+      .values()
+      .find_map(|v| match v.kind() {
+        ValueKind::Bytes(b) => Some(b.bytes().to_vec()),
+        _ => None,
+      });
+    assert_eq!(init.as_deref(), Some("\u{e9}".as_bytes()));
+    for src in [
+      r#"global @s = alloc [i32, 3], "hello""#,
+      r#"global @s = alloc [i32, 3], """#,
+      r#"global @s = alloc i32, "a""#,
+      r#"fun @main() {
+      %entry:
+        %s = alloc [i32, 2]
+        store "hi", %s
+        ret
+      }"#,
+    ] {
+      let driver: Driver<_> = src.into();
+      assert!(driver.generate_program().is_err(), "{src}");
+      assert_ne!(Span::error_num(), 0);
+    }
+  }
+
   #[test]
   fn generate_duplicate_symbol() {
     let driver: Driver<_> = r#"
@@ -260,4 +474,82 @@ mod test {
     assert_eq!(Span::warning_num(), 0);
     assert_ne!(Span::error_num(), 0);
   }
+
+  #[test]
+  fn generate_deeply_nested() {
+    const DEPTH: usize = 10_000;
+    let arr = format!("{}i32{}", "[".repeat(DEPTH), ", 1]".repeat(DEPTH));
+    let init = format!("{}1{}", "{".repeat(DEPTH), "}".repeat(DEPTH));
+    let ptr = format!("{}i32", "*".repeat(DEPTH));
+    let fun = format!("{}{}", "(".repeat(DEPTH), ")".repeat(DEPTH));
+    let src = format!("global @g = alloc {arr}, {init}\n\ndecl @f({ptr}, {fun})\n");
+    let driver: Driver<_> = src.as_str().into();
+    let program = driver.generate_program().unwrap();
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    assert!(String::from_utf8(gen.writer()).unwrap() == src);
+    // unfinished types and aggregates are reported as errors
+    for src in [
+      format!("global @g = alloc {}", "[".repeat(DEPTH)),
+      format!("decl @f({}", "*".repeat(DEPTH)),
+      format!("decl @f({})", "(".repeat(DEPTH)),
+      format!("global @g = alloc {arr}, {}", "{".repeat(DEPTH)),
+      format!("global @g = alloc {arr}, {}", &init[1..]),
+    ] {
+      let driver: Driver<_> = src.as_str().into();
+      assert!(driver.generate_program().is_err());
+      assert_ne!(Span::error_num(), 0);
+    }
+  }
+
+  #[test]
+  fn generate_suggestions() {
+    let notes = |src: &str| {
+      let driver: Driver<_> = src.into();
+      assert!(driver.generate_program().is_err());
+      let diags = Span::take_diagnostics();
+      assert_eq!(diags.len(), 1);
+      diags[0].notes.clone()
+    };
+    let src = r#"
+      decl @putint(i32)
+      decl @putch(i32)
+      global @entrey = alloc i32, 0
+
+      fun @main(@x: i32) {
+      %entry:
+        %xx = add @x, 1
+        call @putin(%x)
+        ret
+      }
+    "#;
+    assert_eq!(notes(src), ["did you mean '@putint'?"]);
+    let src = src.replace("@putin(%x)", "@putint(%xy)");
+    // `%` symbols never suggest `@` symbols
+    assert_eq!(notes(&src), ["did you mean '%xx'?"]);
+    let src = src.replace("%xy", "%xx");
+    let bbs = src.replace("ret\n", "jump %entry_\n");
+    assert_eq!(notes(&bbs), ["did you mean '%entry'?"]);
+    // unrelated names, and several similar names
+    let src = src.replace("@putint(%xx)", "@foo(%xx)");
+    assert!(notes(&src).is_empty());
+    let src = src.replace("@foo(%xx)", "@putich(%xx)");
+    assert_eq!(notes(&src), ["did you mean '@putch' or '@putint'?"]);
+  }
+
+  #[test]
+  fn generate_long_chain() {
+    // symbols defined in the entry are looked up through all
+    // predecessors of the last basic blocks
+    const LEN: usize = 20_000;
+    let mut src = String::from("fun @f(@x: i32): i32 {\n%entry:\n  %a = add @x, 1\n  jump %bb0\n");
+    for i in 0..LEN {
+      src += &format!("%bb{i}:\n  %v{i} = add %a, {i}\n  jump %bb{}\n", i + 1);
+    }
+    src += &format!("%bb{LEN}:\n  ret %a\n}}\n");
+    let driver: Driver<_> = src.as_str().into();
+    let program = driver.generate_program().unwrap();
+    let func = program.func(program.func_layout()[0]);
+    assert_eq!(func.layout().bbs().len(), LEN + 2);
+  }
 }
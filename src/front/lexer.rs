@@ -2,10 +2,12 @@
 
 use crate::front::span::{Error, Pos, Span};
 use crate::front::token::{Keyword, Token, TokenKind};
-use crate::ir::BinaryOp;
-use crate::{log_raw_fatal_error, return_error};
+use crate::ir::{AttrValue, BinaryOp};
+use crate::{log_raw_fatal_error, log_warning, return_error};
 use std::collections::HashMap;
 use std::io::Read;
+use std::iter::Peekable;
+use std::str::Chars;
 
 /// A lexer for lexing text form Koopa IR.
 ///
@@ -13,36 +15,109 @@ use std::io::Read;
 /// token stream for the [`Parser`](crate::front::parser::Parser).
 pub struct Lexer<T: Read> {
   reader: T,
+  // bytes read from the reader, and the range of the unread ones
+  buf: Box<[u8]>,
+  buf_pos: usize,
+  buf_len: usize,
+  // text of the current token, reused to avoid allocations
+  text: String,
   pos: Pos,
   // `None` if EOF
   last_char: Option<char>,
+  // `None` if attribute comments are not read
+  attrs: Option<LineAttrs>,
+  // `None` if comments are not retained
+  comments: Option<Vec<Comment>>,
 }
 
 /// Result that returned by [`Lexer`].
 pub type Result = std::result::Result<Token, Error>;
 
+/// Attributes read from attribute comments, indexed by line number.
+pub type LineAttrs = HashMap<u32, Vec<(String, AttrValue)>>;
+
+/// A comment retained by the lexer, see [`Lexer::enable_comments`].
+#[derive(Clone, Debug)]
+pub struct Comment {
+  /// Span of the comment, including the delimiters.
+  pub span: Span,
+  /// Text of the comment, including the delimiters
+  /// (like `// text` or `/* text */`).
+  pub text: String,
+}
+
 impl<T: Read> Lexer<T> {
+  /// Size of the buffer for reading from the reader.
+  const BUF_SIZE: usize = 64 * 1024;
+
   /// Creates a new lexer from the given reader.
   pub fn new(reader: T) -> Self {
     Self {
       reader,
+      buf: vec![0; Self::BUF_SIZE].into_boxed_slice(),
+      buf_pos: 0,
+      buf_len: 0,
+      text: String::new(),
       pos: Pos::new(),
       last_char: Some(' '),
+      attrs: None,
+      comments: None,
     }
   }
 
+  /// Enables reading attribute comments.
+  ///
+  /// Attribute comments are line comments like
+  /// `// !key1 = 1, !key2 = "str", !key3 = true`, which are generated by
+  /// [`KoopaGenerator`](crate::back::KoopaGenerator) when
+  /// [`emit_attrs`](crate::back::koopa::Config::emit_attrs) is set.
+  /// Other comments are still ignored.
+  pub fn enable_attrs(&mut self) {
+    self.attrs.get_or_insert_with(HashMap::new);
+  }
+
+  /// Takes all attributes that have been read since the last call.
+  ///
+  /// Returns an empty map if reading attribute comments is not enabled.
+  pub fn take_attrs(&mut self) -> LineAttrs {
+    self.attrs.as_mut().map(std::mem::take).unwrap_or_default()
+  }
+
+  /// Enables retaining comments.
+  ///
+  /// All line comments and block comments, including attribute comments,
+  /// will be recorded with their spans in the order they appear, so that
+  /// they can be emitted again, by a formatter for example.
+  pub fn enable_comments(&mut self) {
+    self.comments.get_or_insert_with(Vec::new);
+  }
+
+  /// Takes all comments that have been read since the last call.
+  ///
+  /// Returns an empty list if retaining comments is not enabled.
+  pub fn take_comments(&mut self) -> Vec<Comment> {
+    self
+      .comments
+      .as_mut()
+      .map(std::mem::take)
+      .unwrap_or_default()
+  }
+
   /// Returns the next token from file, or a lexer error.
   pub fn next_token(&mut self) -> Result {
-    // skip spaces
-    while self.last_char.map_or(false, |c| c.is_whitespace()) {
-      self.next_char()?;
+    // skip spaces and comments
+    loop {
+      while self.last_char.is_some_and(|c| c.is_whitespace()) {
+        self.next_char()?;
+      }
+      if self.last_char != Some('/') {
+        break;
+      }
+      self.handle_comment()?;
     }
     // check the last character
     if let Some(c) = self.last_char {
-      if c == '/' {
-        // skip comments
-        self.handle_comment()
-      } else if c == '@' || c == '%' {
+      if c == '@' || c == '%' {
         // symbols
         self.handle_symbol()
       } else if c.is_alphabetic() {
@@ -51,6 +126,9 @@ impl<T: Read> Lexer<T> {
       } else if c.is_numeric() || c == '-' {
         // integer literals
         self.handle_integer()
+      } else if c == '"' {
+        // string literals
+        self.handle_string()
       } else {
         // other characters
         let pos = self.pos;
@@ -67,19 +145,25 @@ impl<T: Read> Lexer<T> {
   ///
   /// If fails, this method will always return a fatal error.
   fn next_char(&mut self) -> std::result::Result<(), Error> {
+    // read a chunk from the reader instead of a single byte at a time,
+    // readers like files would otherwise make a system call per byte
+    if self.buf_pos == self.buf_len {
+      self.buf_len = self
+        .reader
+        .read(&mut self.buf)
+        .map_err(|err| log_raw_fatal_error!("{}", err))?;
+      self.buf_pos = 0;
+    }
     // NOTE: UTF-8 characters will not be handled here.
-    let mut single_char = [0];
-    self.last_char = (self
-      .reader
-      .read(&mut single_char)
-      .map_err(|err| log_raw_fatal_error!("{}", err))?
-      != 0)
-      .then(|| {
-        let c = single_char[0] as char;
-        // update the current position
-        self.pos.update(c);
-        c
-      });
+    self.last_char = if self.buf_pos < self.buf_len {
+      let c = self.buf[self.buf_pos] as char;
+      self.buf_pos += 1;
+      // update the current position
+      self.pos.update(c);
+      Some(c)
+    } else {
+      None
+    };
     Ok(())
   }
 
@@ -87,19 +171,69 @@ impl<T: Read> Lexer<T> {
   fn handle_integer(&mut self) -> Result {
     let mut span = Span::new(self.pos);
     // read to string
-    let mut num = String::from(self.last_char.unwrap());
+    self.text.clear();
+    self.text.push(self.last_char.unwrap());
     self.next_char()?;
-    while self.last_char.map_or(false, |c| c.is_numeric()) {
-      num.push(self.last_char.unwrap());
+    while let Some(c) = self.last_char.filter(|c| c.is_numeric()) {
+      self.text.push(c);
       span.update(self.pos);
       self.next_char()?;
     }
     // convert to integer
-    if let Ok(i) = num.parse() {
+    if let Ok(i) = self.text.parse() {
       Ok(Token::new(span, TokenKind::Int(i)))
     } else {
-      self.log_err_and_skip(span, &format!("invalid integer literal '{}'", num))
+      let message = format!("invalid integer literal '{}'", self.text);
+      self.log_err_and_skip(span, &message)
+    }
+  }
+
+  /// Handles string literals.
+  fn handle_string(&mut self) -> Result {
+    let mut span = Span::new(self.pos);
+    // eat '"'
+    self.next_char()?;
+    // read until there is '"' in stream
+    let mut bytes = Vec::new();
+    loop {
+      match self.last_char {
+        None | Some('\n') => {
+          return self.log_err_and_skip(span.into_updated(self.pos), "string unclosed")
+        }
+        Some('"') => break,
+        Some('\\') => {
+          self.next_char()?;
+          bytes.push(match self.last_char {
+            Some('n') => b'\n',
+            Some('r') => b'\r',
+            Some('t') => b'\t',
+            Some(c @ ('"' | '\\')) => c as u8,
+            Some(c) if c.is_ascii_hexdigit() => {
+              // two hexadecimal digits, like `\0A`
+              let hi = c.to_digit(16).unwrap() as u8;
+              self.next_char()?;
+              match self.last_char.and_then(|c| c.to_digit(16)) {
+                Some(lo) => hi << 4 | lo as u8,
+                None => {
+                  return self
+                    .log_err_and_skip(span.into_updated(self.pos), "invalid escape sequence")
+                }
+              }
+            }
+            _ => {
+              return self.log_err_and_skip(span.into_updated(self.pos), "invalid escape sequence")
+            }
+          });
+        }
+        // characters are read byte by byte, see `next_char`
+        Some(c) => bytes.push(c as u8),
+      }
+      self.next_char()?;
     }
+    // eat '"'
+    span.update(self.pos);
+    self.next_char()?;
+    Ok(Token::new(span, TokenKind::Str(bytes)))
   }
 
   /// Handles symbols.
@@ -107,43 +241,46 @@ impl<T: Read> Lexer<T> {
     let mut span = Span::new(self.pos);
     let tag = self.last_char.unwrap();
     // read the first char to string
-    let mut symbol = String::from(tag);
+    self.text.clear();
+    self.text.push(tag);
     self.next_char()?;
     // check if number
-    if self.last_char.map_or(false, |c| c.is_numeric()) {
+    if self.last_char.is_some_and(|c| c.is_numeric()) {
       // check if is named symbol
       if tag == '@' {
         return self.log_err_and_skip(span, "invalid named symbol");
       }
       // check the first digit
       let digit = self.last_char.unwrap();
-      symbol.push(digit);
+      self.text.push(digit);
       span.update(self.pos);
       self.next_char()?;
       if digit != '0' {
         // read the rest numbers to string
-        while self.last_char.map_or(false, |c| c.is_numeric()) {
-          symbol.push(self.last_char.unwrap());
+        while let Some(c) = self.last_char.filter(|c| c.is_numeric()) {
+          self.text.push(c);
           span.update(self.pos);
           self.next_char()?;
         }
       }
     } else {
       // read letters, numbers or underscores
-      while self
-        .last_char
-        .map_or(false, |c| c.is_alphanumeric() || c == '_')
-      {
-        symbol.push(self.last_char.unwrap());
+      while let Some(c) = self.last_char.filter(|c| c.is_alphanumeric() || *c == '_') {
+        self.text.push(c);
         span.update(self.pos);
         self.next_char()?;
       }
     }
     // check if only the prefix
-    if symbol.len() == 1 {
-      self.log_err_and_skip(span, &format!("invalid symbol '{}'", symbol))
+    if self.text.len() == 1 {
+      let message = format!("invalid symbol '{}'", self.text);
+      self.log_err_and_skip(span, &message)
     } else {
-      Ok(Token::new(span, TokenKind::Symbol(symbol)))
+      // allocate only once for the symbol, with the exact length
+      Ok(Token::new(
+        span,
+        TokenKind::Symbol(self.text.as_str().into()),
+      ))
     }
   }
 
@@ -151,25 +288,27 @@ impl<T: Read> Lexer<T> {
   fn handle_keyword(&mut self) -> Result {
     let mut span = Span::new(self.pos);
     // read to string
-    let mut keyword = String::new();
-    while self.last_char.map_or(false, |c| c.is_alphanumeric()) {
-      keyword.push(self.last_char.unwrap());
+    self.text.clear();
+    while let Some(c) = self.last_char.filter(|c| c.is_alphanumeric()) {
+      self.text.push(c);
       span.update(self.pos);
       self.next_char()?;
     }
     // check the string
-    if let Some(keyword) = KEYWORDS.with(|m| m.get(keyword.as_str()).copied()) {
+    let keyword = self.text.as_str();
+    if let Some(keyword) = KEYWORDS.with(|m| m.get(keyword).copied()) {
       Ok(Token::new(span, TokenKind::Keyword(keyword)))
-    } else if let Some(op) = BINARY_OPS.with(|m| m.get(keyword.as_str()).copied()) {
+    } else if let Some(op) = BINARY_OPS.with(|m| m.get(keyword).copied()) {
       Ok(Token::new(span, TokenKind::BinaryOp(op)))
     } else {
-      self.log_err_and_skip(span, &format!("invalid keyword/operator '{}'", keyword))
+      let message = format!("invalid keyword/operator '{}'", keyword);
+      self.log_err_and_skip(span, &message)
     }
   }
 
-  /// Handles comments.
-  fn handle_comment(&mut self) -> Result {
-    let span = Span::new(self.pos);
+  /// Handles comments, consumes the comment if it is valid.
+  fn handle_comment(&mut self) -> std::result::Result<(), Error> {
+    let mut span = Span::new(self.pos);
     // eat '/'
     self.next_char()?;
     // check if is block comment
@@ -177,33 +316,83 @@ impl<T: Read> Lexer<T> {
       self.handle_block_comment(span)
     } else if self.last_char == Some('/') {
       // skip the current line
-      while self.last_char.map_or(false, |c| c != '\r' && c != '\n') {
+      let line = self.pos.line();
+      let mut text = Vec::new();
+      span.update(self.pos);
+      self.next_char()?;
+      while let Some(c) = self.last_char.filter(|c| *c != '\r' && *c != '\n') {
+        text.push(c as u8);
+        span.update(self.pos);
         self.next_char()?;
       }
-      // return the next token
-      self.next_token()
+      self.retain_comment(span, b"//", &text, b"");
+      // read attributes if needed
+      if let Some(attrs) = &mut self.attrs {
+        let text = String::from_utf8_lossy(&text);
+        if text.trim_start().starts_with('!') {
+          match parse_attrs(&text) {
+            Some(a) => attrs.entry(line).or_default().extend(a),
+            None => log_warning!(
+              span.into_updated(self.pos),
+              "invalid attribute comment, ignored"
+            ),
+          }
+        }
+      }
+      Ok(())
     } else {
-      self.log_err_and_skip(span.into_updated(self.pos), "invalid comment")
+      self
+        .log_err_and_skip(span.into_updated(self.pos), "invalid comment")
+        .map(|_| ())
     }
   }
 
-  /// Handles block comments.
-  fn handle_block_comment(&mut self, span: Span) -> Result {
+  /// Handles block comments, which can be nested.
+  fn handle_block_comment(&mut self, mut span: Span) -> std::result::Result<(), Error> {
+    // span of the opening '/*'
+    let open = span.into_updated(self.pos);
     // eat '*'
     self.next_char()?;
-    // read until there is '*/' in stream
-    let mut star = false;
-    while self.last_char.is_some() && !(star && self.last_char == Some('/')) {
-      star = self.last_char == Some('*');
+    // read until the outermost '*/'
+    let mut text = Vec::new();
+    let mut depth = 1;
+    let mut last = None;
+    loop {
+      let Some(c) = self.last_char else {
+        return_error!(open, "comment unclosed at EOF");
+      };
+      match (last, c) {
+        (Some('*'), '/') => {
+          depth -= 1;
+          last = None;
+        }
+        (Some('/'), '*') => {
+          depth += 1;
+          last = None;
+        }
+        _ => last = Some(c),
+      }
+      span.update(self.pos);
       self.next_char()?;
+      if depth == 0 {
+        // remove the closing '*'
+        text.pop();
+        break;
+      }
+      text.push(c as u8);
     }
-    // check unclosed block comment
-    if self.last_char.is_none() {
-      self.log_err_and_skip(span.into_updated(self.pos), "comment unclosed at EOF")
-    } else {
-      // eat '/'
-      self.next_char()?;
-      self.next_token()
+    self.retain_comment(span, b"/*", &text, b"*/");
+    Ok(())
+  }
+
+  /// Records the given comment if retaining comments is enabled.
+  fn retain_comment(&mut self, span: Span, open: &[u8], text: &[u8], close: &[u8]) {
+    if let Some(comments) = &mut self.comments {
+      let text = [open, text, close].concat();
+      comments.push(Comment {
+        span,
+        text: String::from_utf8_lossy(&text).into(),
+      });
     }
   }
 
@@ -211,13 +400,76 @@ impl<T: Read> Lexer<T> {
   ///
   /// For error recovery support.
   fn log_err_and_skip(&mut self, span: Span, message: &str) -> Result {
-    while self.last_char.map_or(false, |c| !c.is_whitespace()) {
+    while self.last_char.is_some_and(|c| !c.is_whitespace()) {
       self.next_char()?;
     }
     return_error!(span, "{}", message)
   }
 }
 
+/// Parses attributes in the given attribute comment.
+///
+/// Returns `None` if the comment is invalid.
+fn parse_attrs(text: &str) -> Option<Vec<(String, AttrValue)>> {
+  fn skip_spaces(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+  }
+  let mut chars = text.chars().peekable();
+  let mut attrs = Vec::new();
+  loop {
+    // `!key =`
+    skip_spaces(&mut chars);
+    chars.next_if_eq(&'!')?;
+    let mut key = String::new();
+    while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '=' && *c != ',') {
+      key.push(c);
+    }
+    skip_spaces(&mut chars);
+    chars.next_if_eq(&'=')?;
+    skip_spaces(&mut chars);
+    if key.is_empty() {
+      return None;
+    }
+    // value
+    let value = if chars.next_if_eq(&'"').is_some() {
+      let mut s = String::new();
+      loop {
+        match chars.next()? {
+          '"' => break,
+          '\\' => s.push(match chars.next()? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            c => c,
+          }),
+          c => s.push(c),
+        }
+      }
+      AttrValue::Str(s)
+    } else {
+      let mut v = String::new();
+      while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != ',') {
+        v.push(c);
+      }
+      match v.as_str() {
+        "true" => AttrValue::Bool(true),
+        "false" => AttrValue::Bool(false),
+        _ => AttrValue::Int(v.parse().ok()?),
+      }
+    };
+    attrs.push((key, value));
+    // `,` or end of attributes
+    skip_spaces(&mut chars);
+    match chars.next() {
+      None => break Some(attrs),
+      Some(',') => {}
+      // other comments after attributes
+      Some('/') if chars.peek() == Some(&'/') => break Some(attrs),
+      Some(_) => break None,
+    }
+  }
+}
+
 macro_rules! hash_map {
   ($($k:expr => $v:expr),* $(,)?) => {{
     let mut map = HashMap::new();
@@ -242,8 +494,12 @@ thread_local! {
     "jump" => Keyword::Jump,
     "call" => Keyword::Call,
     "ret" => Keyword::Ret,
+    "unreachable" => Keyword::Unreachable,
     "fun" => Keyword::Fun,
     "decl" => Keyword::Decl,
+    "pure" => Keyword::Pure,
+    "noreturn" => Keyword::NoReturn,
+    "conv" => Keyword::Conv,
   };
 
   /// All supported binary operators.
@@ -379,4 +635,103 @@ mod test {
     assert_eq!(lexer.next_token().unwrap().kind, TokenKind::End);
     assert_eq!(lexer.next_token().unwrap().kind, TokenKind::End);
   }
+
+  #[test]
+  fn read_attrs() {
+    let buf = Cursor::new(
+      r#"ret // comment
+      ret // !a = 1, !b = "x\"y\n", !c = false
+      ret // ! = 1"#,
+    );
+    let mut lexer = Lexer::new(buf);
+    lexer.enable_attrs();
+    while lexer.next_token().unwrap().kind != TokenKind::End {}
+    let attrs = lexer.take_attrs();
+    assert_eq!(attrs.len(), 1);
+    assert_eq!(
+      attrs[&2],
+      [
+        ("a".into(), AttrValue::Int(1)),
+        ("b".into(), AttrValue::Str("x\"y\n".into())),
+        ("c".into(), AttrValue::Bool(false)),
+      ]
+    );
+    assert!(lexer.take_attrs().is_empty());
+  }
+
+  #[test]
+  fn read_comments() {
+    let buf =
+      Cursor::new("// line\n/* outer /* inner */\n still outer */ fun\n/**/ /***/ @f // !a = 1\n");
+    let mut lexer = Lexer::new(buf);
+    lexer.enable_comments();
+    let token = lexer.next_token().unwrap();
+    assert_eq!(token.kind, TokenKind::Keyword(Keyword::Fun));
+    // positions are counted through multi-line comments
+    let start = token.span.start();
+    assert_eq!((start.line(), start.col()), (3, 17));
+    let token = lexer.next_token().unwrap();
+    assert_eq!(token.kind, TokenKind::Symbol("@f".into()));
+    assert_eq!(lexer.next_token().unwrap().kind, TokenKind::End);
+    let comments: Vec<_> = lexer
+      .take_comments()
+      .into_iter()
+      .map(|c| (c.span.start().line(), c.text))
+      .collect();
+    assert_eq!(
+      comments,
+      [
+        (1, "// line".into()),
+        (2, "/* outer /* inner */\n still outer */".into()),
+        (4, "/**/".into()),
+        (4, "/***/".into()),
+        (4, "// !a = 1".into()),
+      ]
+    );
+    assert!(lexer.take_comments().is_empty());
+  }
+
+  #[test]
+  fn unclosed_block_comment() {
+    let mut lexer = Lexer::new(Cursor::new("fun /* a /* b */\n"));
+    assert_eq!(
+      lexer.next_token().unwrap().kind,
+      TokenKind::Keyword(Keyword::Fun)
+    );
+    let err = lexer.next_token().err().unwrap();
+    // the error points at the opening `/*`
+    assert!(matches!(err, Error::Normal(msg) if msg.ends_with("1:5: comment unclosed at EOF")));
+  }
+
+  #[test]
+  fn read_chunks() {
+    /// A reader that returns at most 3 bytes at a time.
+    struct Chunks<'a>(&'a [u8]);
+
+    impl Read for Chunks<'_> {
+      fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(self.0.len()).min(3);
+        buf[..len].copy_from_slice(&self.0[..len]);
+        self.0 = &self.0[len..];
+        Ok(len)
+      }
+    }
+
+    // longer than the buffer of the lexer
+    let src = "%symbol_1 = add 12345, %0 // comment\n".repeat(5000);
+    fn tokens(mut lexer: Lexer<impl Read>) -> Vec<TokenKind> {
+      let mut tokens = Vec::new();
+      loop {
+        match lexer.next_token().unwrap().kind {
+          TokenKind::End => break tokens,
+          kind => tokens.push(kind),
+        }
+      }
+    }
+    let expected = tokens(Lexer::new(Cursor::new(&src)));
+    assert_eq!(expected.len(), 6 * 5000);
+    assert_eq!(expected[1], TokenKind::Other('='));
+    assert_eq!(expected.last(), Some(&TokenKind::Symbol("%0".into())));
+    assert_eq!(tokens(Lexer::new(Chunks(src.as_bytes()))), expected);
+  }
 }
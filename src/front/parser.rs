@@ -4,8 +4,10 @@ use crate::front::ast::{self, AstBox};
 use crate::front::lexer::Lexer;
 use crate::front::span::{Error, Span};
 use crate::front::token::{Keyword, Token, TokenKind};
+use crate::ir::FuncAttrs;
 use crate::return_error;
 use std::io::Read;
+use std::mem;
 
 /// A parser for parsing the text form Koopa IR.
 ///
@@ -19,12 +21,29 @@ pub struct Parser<T: Read> {
 /// Result that returned by [`Parser`].
 pub type Result = std::result::Result<AstBox, Error>;
 
+/// Unfinished types in [`Parser::parse_type`].
+enum TypeFrame {
+  /// Array type, with the span of '['.
+  Array(Span),
+  /// Pointer type, with the span of '*'.
+  Pointer(Span),
+  /// Function type with parsed parameter types, before ')'.
+  Params(Span, Vec<AstBox>),
+  /// Function type with parameter types, before the return type.
+  Ret(Span, Vec<AstBox>),
+}
+
 /// Reads the value of the given kind of token from lexer.
+///
+/// The value is moved out of the current token instead of being cloned,
+/// since the token will be replaced by the next one.
 macro_rules! read {
   ($self:ident, $p:path, $prompt:expr) => {{
-    let Token { span, kind } = &$self.cur_token;
-    if let $p(v) = kind {
-      let v = v.clone();
+    let Token { span, kind } = &mut $self.cur_token;
+    if let $p(_) = kind {
+      let $p(v) = mem::replace(kind, TokenKind::End) else {
+        unreachable!()
+      };
       $self.next_token()?;
       Ok(v)
     } else {
@@ -78,6 +97,11 @@ impl<T: Read> Parser<T> {
     Ok(parser)
   }
 
+  /// Returns a mutable reference to the lexer.
+  pub fn lexer_mut(&mut self) -> &mut Lexer<T> {
+    &mut self.lexer
+  }
+
   /// Parses the next AST and returns the box of paarsed AST.
   pub fn parse_next(&mut self) -> Result {
     match_token! {
@@ -147,6 +171,8 @@ impl<T: Read> Parser<T> {
       self.next_token()?;
       ret = Some(self.parse_type()?);
     }
+    // get function attributes
+    let (attrs, _) = self.parse_func_attrs()?;
     // check & eat '{'
     self.expect(TokenKind::Other('{'))?;
     // get basic blocks
@@ -164,7 +190,7 @@ impl<T: Read> Parser<T> {
         "expected at least one basic block in function definition"
       )
     } else {
-      Ok(ast::FunDef::new_boxed(span, name, params, ret, bbs))
+      Ok(ast::FunDef::new_boxed(span, name, params, ret, attrs, bbs))
     }
   }
 
@@ -186,19 +212,133 @@ impl<T: Read> Parser<T> {
       span.update_span(ty.span);
       ret = Some(ty);
     }
+    // get function attributes
+    let (attrs, sp) = self.parse_func_attrs()?;
+    if let Some(sp) = sp {
+      span.update_span(sp);
+    }
     // create function declaration
-    Ok(ast::FunDecl::new_boxed(span, name, params, ret))
+    Ok(ast::FunDecl::new_boxed(span, name, params, ret, attrs))
+  }
+
+  /// Parses function attributes (if exists) like `#[pure, noreturn]`.
+  ///
+  /// Returns the parsed attributes, and the span of the closing bracket.
+  fn parse_func_attrs(&mut self) -> std::result::Result<(FuncAttrs, Option<Span>), Error> {
+    let mut attrs = FuncAttrs::default();
+    if !self.is_token(TokenKind::Other('#')) {
+      return Ok((attrs, None));
+    }
+    // eat '#' and '['
+    self.next_token()?;
+    self.expect(TokenKind::Other('['))?;
+    // get attributes
+    if !self.is_token(TokenKind::Other(']')) {
+      loop {
+        match self.cur_token.kind {
+          TokenKind::Keyword(Keyword::Pure) => {
+            attrs.pure = true;
+            self.next_token()?;
+          }
+          TokenKind::Keyword(Keyword::NoReturn) => {
+            attrs.noreturn = true;
+            self.next_token()?;
+          }
+          TokenKind::Keyword(Keyword::Conv) => {
+            self.next_token()?;
+            self.expect(TokenKind::Other('='))?;
+            let span = self.span();
+            let conv = read!(self, TokenKind::Str, "calling convention")?;
+            match String::from_utf8(conv) {
+              Ok(conv) => attrs.conv = Some(conv),
+              Err(_) => return_error!(span, "invalid UTF-8 string"),
+            }
+          }
+          _ => {
+            let Token { span, kind } = &self.cur_token;
+            return_error!(span, "expected function attribute, found {}", kind)
+          }
+        }
+        // eat ','
+        if !self.is_token(TokenKind::Other(',')) {
+          break;
+        }
+        self.next_token()?;
+      }
+    }
+    // eat ']'
+    let span = self.expect(TokenKind::Other(']'))?;
+    Ok((attrs, Some(span)))
   }
 
   /// Parses types.
   fn parse_type(&mut self) -> Result {
-    let Token { span, kind } = &self.cur_token;
-    match kind {
-      TokenKind::Keyword(Keyword::I32) => self.parse_int_type(),
-      TokenKind::Other('[') => self.parse_array_type(),
-      TokenKind::Other('*') => self.parse_pointer_type(),
-      TokenKind::Other('(') => self.parse_fun_type(),
-      _ => return_error!(span, "expected type, found {}", kind),
+    let mut stack = Vec::new();
+    loop {
+      // push the outer types to the stack, until a complete type is found
+      let Token { span, kind } = &self.cur_token;
+      let span = *span;
+      let mut ty = match kind {
+        TokenKind::Keyword(Keyword::I32) => self.parse_int_type()?,
+        TokenKind::Other('[') => {
+          self.next_token()?;
+          stack.push(TypeFrame::Array(span));
+          continue;
+        }
+        TokenKind::Other('*') => {
+          self.next_token()?;
+          stack.push(TypeFrame::Pointer(span));
+          continue;
+        }
+        TokenKind::Other('(') => {
+          self.next_token()?;
+          if !self.is_token(TokenKind::Other(')')) {
+            stack.push(TypeFrame::Params(span, Vec::new()));
+            continue;
+          }
+          match self.parse_fun_type_end(span, Vec::new(), &mut stack)? {
+            Some(ty) => ty,
+            None => continue,
+          }
+        }
+        _ => return_error!(span, "expected type, found {}", kind),
+      };
+      // complete the outer types
+      loop {
+        ty = match stack.pop() {
+          None => return Ok(ty),
+          Some(TypeFrame::Array(mut span)) => {
+            // check & eat ','
+            self.expect(TokenKind::Other(','))?;
+            // get length
+            let len = read!(self, TokenKind::Int, "length")? as usize;
+            // check & eat ']'
+            span.update_span(self.expect(TokenKind::Other(']'))?);
+            ast::ArrayType::new_boxed(span, ty, len)
+          }
+          Some(TypeFrame::Pointer(span)) => {
+            ast::PointerType::new_boxed(span.into_updated_span(ty.span), ty)
+          }
+          Some(TypeFrame::Params(span, mut params)) => {
+            params.push(ty);
+            // eat ',', a trailing ',' is allowed
+            if self.is_token(TokenKind::Other(',')) {
+              self.next_token()?;
+              if !self.is_token(TokenKind::Other(')')) {
+                stack.push(TypeFrame::Params(span, params));
+                break;
+              }
+            }
+            match self.parse_fun_type_end(span, params, &mut stack)? {
+              Some(ty) => ty,
+              None => break,
+            }
+          }
+          Some(TypeFrame::Ret(span, params)) => {
+            ast::FunType::new_boxed(span.into_updated_span(ty.span), params, Some(ty))
+          }
+        };
+      }
     }
   }
 
@@ -209,49 +349,26 @@ impl<T: Read> Parser<T> {
     Ok(ast::IntType::new_boxed(span))
   }
 
-  /// Parses array types.
-  fn parse_array_type(&mut self) -> Result {
-    let mut span = self.span();
-    // eat '['
-    self.next_token()?;
-    // get base type
-    let base = self.parse_type()?;
-    // check & eat ','
-    self.expect(TokenKind::Other(','))?;
-    // get length
-    let len = read!(self, TokenKind::Int, "length")? as usize;
-    // check & eat ']'
-    span.update_span(self.expect(TokenKind::Other(']'))?);
-    Ok(ast::ArrayType::new_boxed(span, base, len))
-  }
-
-  /// Parses pointer types.
-  fn parse_pointer_type(&mut self) -> Result {
-    let span = self.span();
-    // eat '*'
-    self.next_token()?;
-    // get base type
-    self
-      .parse_type()
-      .map(|base| ast::PointerType::new_boxed(span.into_updated_span(base.span), base))
-  }
-
-  /// Parses function types.
-  fn parse_fun_type(&mut self) -> Result {
-    let mut span = self.span();
-    // get parameter type list
-    let (params, sp) = self.parse_list(|s| s.parse_type())?;
-    span.update_span(sp);
+  /// Parses the ')' and the optional return type of function types.
+  ///
+  /// Returns the function type if there is no return type, otherwise
+  /// pushes the function type to the stack, and returns `None`.
+  fn parse_fun_type_end(
+    &mut self,
+    mut span: Span,
+    params: Vec<AstBox>,
+    stack: &mut Vec<TypeFrame>,
+  ) -> std::result::Result<Option<AstBox>, Error> {
+    // check & eat ')'
+    span.update_span(self.expect(TokenKind::Other(')'))?);
     // get return type
-    let mut ret = None;
     if self.is_token(TokenKind::Other(':')) {
       self.next_token()?;
-      let ty = self.parse_type()?;
-      span.update_span(ty.span);
-      ret = Some(ty);
+      stack.push(TypeFrame::Ret(span, params));
+      Ok(None)
+    } else {
+      Ok(Some(ast::FunType::new_boxed(span, params, None)))
     }
-    // create function type
-    Ok(ast::FunType::new_boxed(span, params, ret))
   }
 
   /// Parses basic blocks.
@@ -282,6 +399,7 @@ impl<T: Read> Parser<T> {
         TokenKind::Keyword(Keyword::Br) => { exit_flag = true; self.parse_branch() },
         TokenKind::Keyword(Keyword::Jump) => { exit_flag = true; self.parse_jump() },
         TokenKind::Keyword(Keyword::Ret) => { exit_flag = true; self.parse_return() },
+        TokenKind::Keyword(Keyword::Unreachable) => { exit_flag = true; self.parse_unreachable() },
         ? => return_error!(span, "expected statement, found {}", kind),
         break if TokenKind::Other('}') | TokenKind::End => { exit_flag = true; },
       }?);
@@ -346,9 +464,9 @@ impl<T: Read> Parser<T> {
     let value = if let Token {
       span,
       kind: TokenKind::Symbol(symbol),
-    } = &self.cur_token
+    } = &mut self.cur_token
     {
-      let sym = ast::SymbolRef::new_boxed(*span, symbol.clone());
+      let sym = ast::SymbolRef::new_boxed(*span, mem::take(symbol));
       self.next_token()?;
       sym
     } else {
@@ -485,12 +603,20 @@ impl<T: Read> Parser<T> {
     Ok(ast::Return::new_boxed(span, value))
   }
 
+  /// Parses unreachables.
+  fn parse_unreachable(&mut self) -> Result {
+    let span = self.span();
+    // eat 'unreachable'
+    self.next_token()?;
+    Ok(ast::Unreachable::new_boxed(span))
+  }
+
   /// Parses values.
   fn parse_value(&mut self) -> Result {
-    let Token { span, kind } = &self.cur_token;
+    let Token { span, kind } = &mut self.cur_token;
     let ret = match kind {
       // symbol reference
-      TokenKind::Symbol(s) => ast::SymbolRef::new_boxed(*span, s.clone()),
+      TokenKind::Symbol(s) => ast::SymbolRef::new_boxed(*span, mem::take(s)),
       // integer literal
       TokenKind::Int(i) => ast::IntVal::new_boxed(*span, *i as i32),
       // undefined value
@@ -504,7 +630,38 @@ impl<T: Read> Parser<T> {
 
   /// Parses initializers.
   fn parse_init(&mut self) -> Result {
-    let Token { span, kind } = &self.cur_token;
+    // spans and elements of the unfinished aggregates
+    let mut stack: Vec<(Span, Vec<AstBox>)> = Vec::new();
+    loop {
+      // eat '{'
+      if self.is_token(TokenKind::Other('{')) {
+        stack.push((self.span(), Vec::new()));
+        self.next_token()?;
+        continue;
+      }
+      // complete the outer aggregates
+      let mut init = self.parse_scalar_init()?;
+      loop {
+        let Some((span, mut elems)) = stack.pop() else {
+          return Ok(init);
+        };
+        elems.push(init);
+        // eat ','
+        if self.is_token(TokenKind::Other(',')) {
+          self.next_token()?;
+          stack.push((span, elems));
+          break;
+        }
+        // check & eat '}'
+        let span = span.into_updated_span(self.expect(TokenKind::Other('}'))?);
+        init = ast::Aggregate::new_boxed(span, elems);
+      }
+    }
+  }
+
+  /// Parses initializers except aggregates.
+  fn parse_scalar_init(&mut self) -> Result {
+    let Token { span, kind } = &mut self.cur_token;
     match kind {
       // integer literal
       TokenKind::Int(i) => {
@@ -524,56 +681,45 @@ impl<T: Read> Parser<T> {
         self.next_token()?;
         Ok(ast)
       }
-      // aggregate
-      TokenKind::Other('{') => self.parse_aggregate(),
+      // byte data
+      TokenKind::Str(bytes) => {
+        if bytes.is_empty() {
+          return_error!(span, "byte data can not be empty");
+        }
+        let ast = ast::Bytes::new_boxed(*span, mem::take(bytes));
+        self.next_token()?;
+        Ok(ast)
+      }
       // unknown
       _ => return_error!(span, "expected initializer, found {}", kind),
     }
   }
 
-  /// Parses aggregates.
-  fn parse_aggregate(&mut self) -> Result {
-    let span = self.span();
-    // eat '{'
-    self.expect(TokenKind::Other('{'))?;
-    // get elements
-    let mut elems = vec![self.parse_init()?];
-    while self.is_token(TokenKind::Other(',')) {
-      self.next_token()?;
-      elems.push(self.parse_init()?);
-    }
-    // check & eat '}'
-    Ok(ast::Aggregate::new_boxed(
-      span.into_updated_span(self.expect(TokenKind::Other('}'))?),
-      elems,
-    ))
-  }
-
-  /// Parses comma-separated lists.
+  /// Parses comma-separated lists in parentheses, like `(a, b)`, `(a, b,)`
+  /// and `()`.
   fn parse_list<F, U>(&mut self, parser: F) -> std::result::Result<(Vec<U>, Span), Error>
   where
     F: Fn(&mut Self) -> std::result::Result<U, Error>,
   {
     // check & eat left bracket
     self.expect(TokenKind::Other('('))?;
-    // get items
+    // get items, a trailing ',' is allowed
     let mut items = Vec::new();
-    if !self.is_token(TokenKind::Other(')')) {
-      loop {
-        // get item
-        items.push(parser(self)?);
-        // eat ','
-        if !self.is_token(TokenKind::Other(',')) {
-          break;
-        }
-        self.next_token()?;
+    while !self.is_token(TokenKind::Other(')')) {
+      // get item
+      items.push(parser(self)?);
+      // eat ','
+      if !self.is_token(TokenKind::Other(',')) {
+        break;
       }
+      self.next_token()?;
     }
     // check & eat ')'
     Ok((items, self.expect(TokenKind::Other(')'))?))
   }
 
-  /// Parses optional comma-separated lists.
+  /// Parses optional comma-separated lists in parentheses, returns an
+  /// empty list if there is no left parenthesis.
   fn parse_opt_list<F, U>(&mut self, parser: F) -> std::result::Result<(Vec<U>, Span), Error>
   where
     F: Fn(&mut Self) -> std::result::Result<U, Error>,
@@ -654,6 +800,7 @@ mod test {
       name: "@test".into(),
       params: vec![("@i".into(), new_ast!(IntType))],
       ret: Some(new_ast!(IntType)),
+      attrs: FuncAttrs::default(),
       bbs: vec![new_ast!(Block {
         name: "%entry".into(),
         params: vec![],
@@ -740,6 +887,7 @@ mod test {
       name: "@test".into(),
       params: vec![("@i".into(), new_ast!(IntType))],
       ret: Some(new_ast!(IntType)),
+      attrs: FuncAttrs::default(),
       bbs: vec![new_ast!(Block {
         name: "%entry".into(),
         params: vec![],
@@ -780,4 +928,49 @@ mod test {
     assert_eq!(parser.parse_next().unwrap(), new_ast!(End));
     assert_eq!(parser.parse_next().unwrap(), new_ast!(End));
   }
+
+  #[test]
+  fn parse_nested() {
+    let mut parser = Parser::new(Lexer::new(Cursor::new(
+      r#"
+      global @x = alloc *(i32, *[i32, 2],): (): i32, {{1, 2}, {3}}
+      "#,
+    )))
+    .unwrap();
+    let fun_ty = new_ast!(FunType {
+      params: vec![],
+      ret: Some(new_ast!(IntType)),
+    });
+    let expected = new_ast!(GlobalDef {
+      name: "@x".into(),
+      value: new_ast!(GlobalDecl {
+        ty: new_ast!(PointerType {
+          base: new_ast!(FunType {
+            params: vec![
+              new_ast!(IntType),
+              new_ast!(PointerType {
+                base: new_ast!(ArrayType {
+                  base: new_ast!(IntType),
+                  len: 2,
+                }),
+              }),
+            ],
+            ret: Some(fun_ty),
+          }),
+        }),
+        init: new_ast!(Aggregate {
+          elems: vec![
+            new_ast!(Aggregate {
+              elems: vec![new_ast!(IntVal { value: 1 }), new_ast!(IntVal { value: 2 })],
+            }),
+            new_ast!(Aggregate {
+              elems: vec![new_ast!(IntVal { value: 3 })],
+            }),
+          ],
+        }),
+      }),
+    });
+    assert_eq!(parser.parse_next().unwrap(), expected);
+    assert_eq!(parser.parse_next().unwrap(), new_ast!(End));
+  }
 }
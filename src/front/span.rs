@@ -1,5 +1,7 @@
 //! Span ([`Span`]) and error ([`Error`]) related implementations.
 
+use crate::diagnostic::{code, Diagnostic, Location, Severity};
+use crate::ir::entities::SourceSpan;
 use std::cell::RefCell;
 use std::fmt::{self, Arguments};
 use std::path::PathBuf;
@@ -10,7 +12,8 @@ use colored::*;
 use std::{fs::File, io::BufRead, io::BufReader, io::Result as IoResult};
 
 /// The type of error returned by logger methods of [`Span`].
-#[cfg(feature = "no-front-logger")]
+///
+/// Each variant holds the formatted message of the logged diagnostic.
 #[derive(Debug)]
 pub enum Error {
   /// Normal error.
@@ -19,42 +22,18 @@ pub enum Error {
   Fatal(String),
 }
 
-/// The type of error returned by logger methods of [`Span`].
-#[cfg(not(feature = "no-front-logger"))]
-#[derive(Debug)]
-pub enum Error {
-  /// Normal error.
-  Normal,
-  /// Fatal error.
-  Fatal,
-}
-
 impl Error {
   /// Returns `true` if the current error is fatal.
-  #[cfg(feature = "no-front-logger")]
   pub fn is_fatal(&self) -> bool {
     matches!(self, Error::Fatal(..))
   }
-
-  /// Returns `true` if the current error is fatal.
-  #[cfg(not(feature = "no-front-logger"))]
-  pub fn is_fatal(&self) -> bool {
-    matches!(self, Error::Fatal)
-  }
 }
 
 impl Default for Error {
   /// Creates a normal error.
-  #[cfg(feature = "no-front-logger")]
   fn default() -> Self {
     Error::Normal(String::default())
   }
-
-  /// Creates a normal error.
-  #[cfg(not(feature = "no-front-logger"))]
-  fn default() -> Self {
-    Error::Normal
-  }
 }
 
 impl<T> From<Error> for Result<T, Error> {
@@ -65,6 +44,15 @@ impl<T> From<Error> for Result<T, Error> {
   }
 }
 
+impl From<Error> for crate::ir::Error {
+  /// Converts the given front-end error to
+  /// [`Error::Parse`](crate::ir::Error::Parse).
+  fn from(error: Error) -> Self {
+    let (Error::Normal(message) | Error::Fatal(message)) = error;
+    crate::ir::Error::Parse { message }
+  }
+}
+
 /// A span that records source code locations.
 ///
 /// Used to print error messages.
@@ -111,11 +99,13 @@ impl Span {
   const TAB_WIDTH: usize = 2;
 
   thread_local! {
-    static STATE: RefCell<GlobalState> = RefCell::new(GlobalState {
+    static STATE: RefCell<GlobalState> = const { RefCell::new(GlobalState {
       file: FileType::Buffer,
       err_num: 0,
       warn_num: 0,
-    });
+      quiet: false,
+      diagnostics: Vec::new(),
+    }) };
   }
 
   /// Creates a new span from [`Pos`].
@@ -130,66 +120,40 @@ impl Span {
         file,
         err_num: 0,
         warn_num: 0,
+        quiet: false,
+        diagnostics: Vec::new(),
       }
     });
   }
 
-  /// Logs normal error with no span provided.
-  #[cfg(feature = "no-front-logger")]
-  pub fn log_raw_error(args: Arguments) -> Error {
-    // update error number
-    Self::STATE.with(|gs| gs.borrow_mut().err_num += 1);
-    Error::Normal(format!("{}", args))
+  /// Sets whether to stop the logger from printing messages.
+  ///
+  /// Diagnostics are still recorded, and can be taken by
+  /// [`Span::take_diagnostics`]. The setting lasts until the next call
+  /// of [`Span::reset`].
+  pub fn set_quiet(quiet: bool) {
+    Self::STATE.with(|gs| gs.borrow_mut().quiet = quiet);
   }
 
-  /// Logs normal error with no span provided.
-  #[cfg(not(feature = "no-front-logger"))]
-  pub fn log_raw_error(args: Arguments) -> Error {
-    Self::STATE.with(|gs| {
-      // update error number
-      gs.borrow_mut().err_num += 1;
-      // print message to stderr
-      eprintln!("{}: {}", "error".bright_red(), args);
-    });
-    Error::Normal
+  /// Takes all diagnostics logged since the last call of [`Span::reset`]
+  /// or this method, in the logging order.
+  pub fn take_diagnostics() -> Vec<Diagnostic> {
+    Self::STATE.with(|gs| std::mem::take(&mut gs.borrow_mut().diagnostics))
   }
 
-  /// Logs fatal error with no span provided.
-  #[cfg(feature = "no-front-logger")]
-  pub fn log_raw_fatal_error(args: Arguments) -> Error {
-    // update error number
-    Self::STATE.with(|gs| gs.borrow_mut().err_num += 1);
-    Error::Fatal(format!("{}", args))
+  /// Logs normal error with no span provided.
+  pub fn log_raw_error(args: Arguments) -> Error {
+    Error::Normal(Self::log(None, Severity::Error, args, Vec::new()).to_string())
   }
 
   /// Logs fatal error with no span provided.
-  #[cfg(not(feature = "no-front-logger"))]
   pub fn log_raw_fatal_error(args: Arguments) -> Error {
-    Self::STATE.with(|gs| {
-      // update error number
-      gs.borrow_mut().err_num += 1;
-      // print message to stderr
-      eprintln!("{}: {}", "error".bright_red(), args);
-    });
-    Error::Fatal
-  }
-
-  /// Logs warning with no span provided.
-  #[cfg(feature = "no-front-logger")]
-  pub fn log_raw_warning(_: Arguments) {
-    // update warning number
-    Self::STATE.with(|gs| gs.borrow_mut().warn_num += 1);
+    Error::Fatal(Self::log(None, Severity::Error, args, Vec::new()).to_string())
   }
 
   /// Logs warning with no span provided.
-  #[cfg(not(feature = "no-front-logger"))]
   pub fn log_raw_warning(args: Arguments) {
-    Self::STATE.with(|gs| {
-      // update warning number
-      gs.borrow_mut().warn_num += 1;
-      // print message to stderr
-      eprintln!("{}: {}", "warning".yellow(), args);
-    });
+    Self::log(None, Severity::Warning, args, Vec::new());
   }
 
   /// Logs global information (total error/warning number).
@@ -201,6 +165,9 @@ impl Span {
   pub fn log_global() {
     Self::STATE.with(|gs| {
       let gs = gs.borrow();
+      if gs.quiet {
+        return;
+      }
       let mut msg = String::new();
       // error info
       if gs.err_num != 0 {
@@ -237,46 +204,79 @@ impl Span {
   }
 
   /// Logs normal error message.
-  #[cfg(feature = "no-front-logger")]
   pub fn log_error(&self, args: Arguments) -> Error {
-    Self::log_raw_error(args);
-    Error::Normal(self.error_message(args))
+    Error::Normal(Self::log(Some(self), Severity::Error, args, Vec::new()).to_string())
   }
 
-  /// Logs normal error message.
-  #[cfg(not(feature = "no-front-logger"))]
-  pub fn log_error(&self, args: Arguments) -> Error {
-    Self::log_raw_error(args);
-    Self::STATE.with(|gs| self.print_file_info(&gs.borrow().file, Color::BrightRed));
-    Error::Normal
+  /// Logs normal error message with the given notes, like suggestions
+  /// for fixing the error.
+  pub fn log_error_with_notes(&self, args: Arguments, notes: Vec<String>) -> Error {
+    Error::Normal(Self::log(Some(self), Severity::Error, args, notes).to_string())
   }
 
   /// Logs fatal error message.
-  #[cfg(feature = "no-front-logger")]
   pub fn log_fatal_error(&self, args: Arguments) -> Error {
-    Self::log_raw_error(args);
-    Error::Fatal(self.error_message(args))
-  }
-
-  /// Logs fatal error message.
-  #[cfg(not(feature = "no-front-logger"))]
-  pub fn log_fatal_error(&self, args: Arguments) -> Error {
-    Self::log_raw_error(args);
-    Self::STATE.with(|gs| self.print_file_info(&gs.borrow().file, Color::BrightRed));
-    Error::Fatal
+    Error::Fatal(Self::log(Some(self), Severity::Error, args, Vec::new()).to_string())
   }
 
   /// Logs warning message.
-  #[cfg(feature = "no-front-logger")]
   pub fn log_warning(&self, args: Arguments) {
-    Self::log_raw_warning(args);
+    Self::log(Some(self), Severity::Warning, args, Vec::new());
   }
 
-  /// Logs warning message.
-  #[cfg(not(feature = "no-front-logger"))]
-  pub fn log_warning(&self, args: Arguments) {
-    Self::log_raw_warning(args);
-    Self::STATE.with(|gs| self.print_file_info(&gs.borrow().file, Color::Yellow));
+  /// Records a diagnostic with the given span and notes, updates the
+  /// error/warning number, and prints the diagnostic if the logger is
+  /// enabled.
+  ///
+  /// Returns the recorded diagnostic.
+  fn log(
+    span: Option<&Span>,
+    severity: Severity,
+    args: Arguments,
+    notes: Vec<String>,
+  ) -> Diagnostic {
+    Self::STATE.with(|gs| {
+      let mut gs = gs.borrow_mut();
+      let code = match severity {
+        Severity::Error => {
+          gs.err_num += 1;
+          code::FRONT_ERROR
+        }
+        Severity::Warning => {
+          gs.warn_num += 1;
+          code::FRONT_WARNING
+        }
+      };
+      let mut diag = Diagnostic::new(code, severity, args.to_string());
+      diag.notes = notes;
+      if let Some(span) = span {
+        diag = diag.with_primary(Location::Source {
+          file: gs.file.to_string(),
+          start: (span.start.line, span.start.col),
+          end: (span.end.line, span.end.col),
+        });
+      }
+      #[cfg(not(feature = "no-front-logger"))]
+      if !gs.quiet {
+        // print message to stderr
+        let (name, color) = match severity {
+          Severity::Error => ("error", Color::BrightRed),
+          Severity::Warning => ("warning", Color::Yellow),
+        };
+        eprintln!("{}: {}", name.color(color), diag.message);
+        if let Some(span) = span {
+          span.print_file_info(&gs.file, color);
+        }
+        for note in &diag.notes {
+          eprintln!("  {} {}", "note:".bold(), note);
+        }
+        if span.is_some() {
+          eprintln!();
+        }
+      }
+      gs.diagnostics.push(diag.clone());
+      diag
+    })
   }
 
   /// Converts the current span into a new one
@@ -307,17 +307,26 @@ impl Span {
     self.end = span.end;
   }
 
+  /// Returns the start position of the current span.
+  pub fn start(&self) -> Pos {
+    self.start
+  }
+
+  /// Returns the end position of the current span.
+  pub fn end(&self) -> Pos {
+    self.end
+  }
+
+  /// Converts the current span into a [`SourceSpan`] in byte offsets.
+  pub fn to_source_span(&self) -> SourceSpan {
+    SourceSpan::new(self.start.offset.saturating_sub(1), self.end.offset)
+  }
+
   /// Checks if the current span is in the same line as the given span.
   pub fn is_in_same_line_as(&self, span: &Span) -> bool {
     self.end.line == span.start.line
   }
 
-  /// Returns the error message.
-  #[cfg(feature = "no-front-logger")]
-  fn error_message(&self, args: Arguments) -> String {
-    Self::STATE.with(|gs| format!("{}:{}: {}", gs.borrow().file, self.start, args))
-  }
-
   /// Prints the file information.
   #[cfg(not(feature = "no-front-logger"))]
   fn print_file_info(&self, file: &FileType, color: Color) {
@@ -333,7 +342,6 @@ impl Span {
         }
       }
     }
-    eprintln!();
   }
 
   /// Prints the single line information.
@@ -424,16 +432,37 @@ impl fmt::Debug for Span {
 pub struct Pos {
   line: u32,
   col: u32,
+  offset: usize,
 }
 
 impl Pos {
   /// Creates a new mark.
   pub fn new() -> Self {
-    Self { line: 1, col: 0 }
+    Self {
+      line: 1,
+      col: 0,
+      offset: 0,
+    }
+  }
+
+  /// Returns the line number.
+  pub fn line(&self) -> u32 {
+    self.line
+  }
+
+  /// Returns the column number.
+  pub fn col(&self) -> u32 {
+    self.col
+  }
+
+  /// Returns the byte offset after the last character.
+  pub fn offset(&self) -> usize {
+    self.offset
   }
 
   /// Updates the line number ans column number based on the given character.
   pub fn update(&mut self, c: char) {
+    self.offset += 1;
     match c {
       '\n' => {
         self.col = 0;
@@ -461,6 +490,8 @@ struct GlobalState {
   file: FileType,
   err_num: usize,
   warn_num: usize,
+  quiet: bool,
+  diagnostics: Vec<Diagnostic>,
 }
 
 /// Type of input file.
@@ -570,14 +601,45 @@ mod test {
     log_warning!(sp2, "test warning");
     log_warning!(sp2, "test warning 2");
     Span::log_global();
+    let diags = Span::take_diagnostics();
+    assert_eq!(diags.len(), 3);
+    assert_eq!(diags[0].code, code::FRONT_ERROR);
+    assert_eq!(diags[0].to_string(), "<buffer>:1:1: test error");
+    assert_eq!(diags[1].severity, Severity::Warning);
+    assert!(Span::take_diagnostics().is_empty());
     assert_eq!(format!("{}", sp2.start), "1:1");
     assert_eq!(format!("{}", sp2.end), "1:3");
-    let mut sp = Span::new(Pos { line: 10, col: 10 });
-    sp.update(Pos { line: 10, col: 15 });
+    let mut sp = Span::new(Pos {
+      line: 10,
+      col: 10,
+      offset: 0,
+    });
+    sp.update(Pos {
+      line: 10,
+      col: 15,
+      offset: 0,
+    });
     assert!(!sp2.is_in_same_line_as(&sp));
     let sp3 = sp2.into_updated_span(sp);
     assert!(sp2.is_in_same_line_as(&sp3));
     assert_eq!(format!("{}", sp3.start), "1:1");
     assert_eq!(format!("{}", sp3.end), "10:15");
   }
+  #[test]
+  fn error_to_ir_error() {
+    let mut pos = Pos::new();
+    pos.update(' ');
+    let sp = Span::new(pos);
+    Span::set_quiet(true);
+    let err = sp.log_fatal_error(format_args!("test fatal error"));
+    Span::set_quiet(false);
+    assert!(err.is_fatal());
+    Span::take_diagnostics();
+    match crate::ir::Error::from(err) {
+      crate::ir::Error::Parse { message } => {
+        assert_eq!(message, "<buffer>:1:1: test fatal error")
+      }
+      err => panic!("unexpected error: {err}"),
+    }
+  }
 }
@@ -39,6 +39,8 @@ pub enum TokenKind {
   Int(i64),
   /// Symbol (identifier like `@id` or `%id`).
   Symbol(String),
+  /// String literal, which may not be valid UTF-8.
+  Str(Vec<u8>),
   /// Keyword.
   Keyword(Keyword),
   /// Binary operator.
@@ -54,6 +56,7 @@ impl fmt::Display for TokenKind {
     match self {
       TokenKind::Int(v) => write!(f, "integer '{}'", v),
       TokenKind::Symbol(v) => write!(f, "symbol '{}'", v),
+      TokenKind::Str(v) => write!(f, "string {:?}", String::from_utf8_lossy(v)),
       TokenKind::Keyword(v) => write!(f, "keyword '{}'", v),
       TokenKind::BinaryOp(v) => write!(f, "binary operator '{}'", v),
       TokenKind::Other(v) => write!(f, "character '{}'", v),
@@ -91,10 +94,18 @@ pub enum Keyword {
   Call,
   /// Keyword `ret`.
   Ret,
+  /// Keyword `unreachable`.
+  Unreachable,
   /// Keyword `fun`.
   Fun,
   /// Keyword `decl`.
   Decl,
+  /// Keyword `pure`.
+  Pure,
+  /// Keyword `noreturn`.
+  NoReturn,
+  /// Keyword `conv`.
+  Conv,
 }
 
 impl fmt::Display for Keyword {
@@ -113,8 +124,12 @@ impl fmt::Display for Keyword {
       Keyword::Jump => f.write_str("jump"),
       Keyword::Call => f.write_str("call"),
       Keyword::Ret => f.write_str("ret"),
+      Keyword::Unreachable => f.write_str("unreachable"),
       Keyword::Fun => f.write_str("fun"),
       Keyword::Decl => f.write_str("decl"),
+      Keyword::Pure => f.write_str("pure"),
+      Keyword::NoReturn => f.write_str("noreturn"),
+      Keyword::Conv => f.write_str("conv"),
     }
   }
 }
@@ -0,0 +1,359 @@
+//! Slot-based arena ([`Arena`]) for storing values and basic blocks
+//! in data flow graphs.
+//!
+//! Handles of local values and basic blocks encode the ID of the arena,
+//! the index of the slot and the generation of the slot, so looking up
+//! a handle is just an index operation. Slots of removed entries can be
+//! reused, and the generation makes sure that stale handles will never
+//! refer to the new entries.
+
+use crate::ir::idman::{next_arena_id, ArenaId, LOCAL_ID_FLAG};
+use std::iter::FusedIterator;
+use std::num::NonZeroU64;
+use std::ops::Index;
+use std::{fmt, slice};
+
+/// Number of bits of the slot index in handles.
+const SLOT_BITS: u32 = 32;
+
+/// Number of bits of the slot generation in handles.
+const GEN_BITS: u32 = 8;
+
+/// Maximum generation of slots, slots reached this generation
+/// will not be reused.
+const MAX_GEN: u8 = u8::MAX;
+
+mod private {
+  pub trait Sealed {}
+}
+
+/// Handles that can be used as keys of [`Arena`]s.
+///
+/// This trait is sealed and can not be implemented outside this crate.
+pub trait ArenaKey: Copy + private::Sealed {
+  #[doc(hidden)]
+  fn from_id(id: NonZeroU64) -> Self;
+
+  #[doc(hidden)]
+  fn id(self) -> NonZeroU64;
+}
+
+macro_rules! impl_arena_key {
+  ($ty:ty) => {
+    impl private::Sealed for $ty {}
+
+    impl ArenaKey for $ty {
+      fn from_id(id: NonZeroU64) -> Self {
+        Self(id)
+      }
+
+      fn id(self) -> NonZeroU64 {
+        self.0
+      }
+    }
+  };
+}
+
+impl_arena_key!(crate::ir::entities::Value);
+impl_arena_key!(crate::ir::entities::BasicBlock);
+
+/// A slot in arena.
+struct Slot<K, V> {
+  generation: u8,
+  entry: Option<(K, V)>,
+}
+
+/// A slot-based arena that maps handles to their data.
+///
+/// The interface of `Arena` is similar to [`HashMap`](std::collections::HashMap),
+/// but keys are allocated by the arena itself when inserting entries.
+pub struct Arena<K, V> {
+  id: ArenaId,
+  slots: Vec<Slot<K, V>>,
+  free: Vec<u32>,
+  len: usize,
+}
+
+impl<K: ArenaKey, V> Arena<K, V> {
+  /// Creates a new empty arena.
+  pub(in crate::ir) fn new() -> Self {
+    Self {
+      id: next_arena_id(),
+      slots: Vec::new(),
+      free: Vec::new(),
+      len: 0,
+    }
+  }
+
+  /// Inserts the given data to the arena. Returns the allocated key.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the arena is full.
+  pub(in crate::ir) fn insert(&mut self, value: V) -> K {
+    let index = match self.free.pop() {
+      Some(index) => index,
+      None => {
+        let index = u32::try_from(self.slots.len()).expect("arena is full");
+        self.slots.push(Slot {
+          generation: 0,
+          entry: None,
+        });
+        index
+      }
+    };
+    let key = K::from_id(self.encode(self.slots[index as usize].generation, index));
+    self.slots[index as usize].entry = Some((key, value));
+    self.len += 1;
+    key
+  }
+
+  /// Returns the key that will be allocated by the next insertion.
+  pub(in crate::ir) fn next_key(&self) -> K {
+    let index = match self.free.last() {
+      Some(index) => *index,
+      None => u32::try_from(self.slots.len()).expect("arena is full"),
+    };
+    let generation = self.slots.get(index as usize).map_or(0, |s| s.generation);
+    K::from_id(self.encode(generation, index))
+  }
+
+  /// Takes the data of the given key out of the arena, but keeps the
+  /// slot reserved for the key. The data can be put back by calling
+  /// [`Arena::restore`].
+  pub(in crate::ir) fn take(&mut self, key: &K) -> Option<V> {
+    let index = self.slot_index(key)?;
+    let (_, value) = self.slots[index].entry.take()?;
+    self.len -= 1;
+    Some(value)
+  }
+
+  /// Puts the data taken by [`Arena::take`] back to the arena.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the slot of the given key is not reserved.
+  pub(in crate::ir) fn restore(&mut self, key: K, value: V) {
+    let index = self.slot_index(&key).expect("invalid key");
+    let entry = &mut self.slots[index].entry;
+    assert!(entry.is_none(), "slot is not empty");
+    *entry = Some((key, value));
+    self.len += 1;
+  }
+
+  /// Removes the given key from the arena. Returns the data of the key
+  /// if the key was previously in the arena.
+  pub(in crate::ir) fn remove(&mut self, key: &K) -> Option<V> {
+    let index = self.slot_index(key)?;
+    let slot = &mut self.slots[index];
+    let (_, value) = slot.entry.take()?;
+    self.len -= 1;
+    if slot.generation < MAX_GEN {
+      slot.generation += 1;
+      self.free.push(index as u32);
+    }
+    Some(value)
+  }
+
+  /// Returns a reference to the data of the given key.
+  pub fn get(&self, key: &K) -> Option<&V> {
+    let index = self.slot_index(key)?;
+    self.slots[index].entry.as_ref().map(|(_, v)| v)
+  }
+
+  /// Returns a mutable reference to the data of the given key.
+  pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+    let index = self.slot_index(key)?;
+    self.slots[index].entry.as_mut().map(|(_, v)| v)
+  }
+
+  /// Returns `true` if the arena contains the given key.
+  pub fn contains_key(&self, key: &K) -> bool {
+    self.get(key).is_some()
+  }
+
+  /// Returns the number of entries in the arena.
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  /// Returns `true` if the arena contains no entries.
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  /// Returns an iterator over all key-data pairs in the arena,
+  /// in the order of slots.
+  pub fn iter(&self) -> Iter<'_, K, V> {
+    Iter {
+      slots: self.slots.iter(),
+      len: self.len,
+    }
+  }
+
+  /// Returns an iterator over all key-data pairs in the arena,
+  /// with mutable references to the data.
+  pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+    IterMut {
+      slots: self.slots.iter_mut(),
+      len: self.len,
+    }
+  }
+
+  /// Returns an iterator over all keys in the arena.
+  pub fn keys(&self) -> impl Iterator<Item = &K> + '_ {
+    self.iter().map(|(k, _)| k)
+  }
+
+  /// Returns an iterator over all data in the arena.
+  pub fn values(&self) -> impl Iterator<Item = &V> + '_ {
+    self.iter().map(|(_, v)| v)
+  }
+
+  /// Returns an iterator over mutable references to all data in the arena.
+  pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> + '_ {
+    self.iter_mut().map(|(_, v)| v)
+  }
+
+  /// Encodes the given generation and slot index to a key ID.
+  fn encode(&self, generation: u8, index: u32) -> NonZeroU64 {
+    let id = LOCAL_ID_FLAG
+      | ((self.id as u64) << (SLOT_BITS + GEN_BITS))
+      | ((generation as u64) << SLOT_BITS)
+      | index as u64;
+    NonZeroU64::new(id).unwrap()
+  }
+
+  /// Returns the slot index of the given key, or `None` if the key
+  /// does not belong to the current arena, or the key is stale.
+  fn slot_index(&self, key: &K) -> Option<usize> {
+    let id = key.id().get();
+    if id & LOCAL_ID_FLAG == 0 || (id & !LOCAL_ID_FLAG) >> (SLOT_BITS + GEN_BITS) != self.id as u64
+    {
+      return None;
+    }
+    let index = (id & u32::MAX as u64) as usize;
+    let generation = (id >> SLOT_BITS) as u8;
+    let slot = self.slots.get(index)?;
+    (slot.generation == generation).then_some(index)
+  }
+}
+
+/// Returns the slot index and the generation encoded in the given
+/// local handle ID.
+pub(in crate::ir) fn decode_id(id: NonZeroU64) -> (u32, u8) {
+  let id = id.get();
+  (id as u32, (id >> SLOT_BITS) as u8)
+}
+
+impl<K: ArenaKey, V> Index<&K> for Arena<K, V> {
+  type Output = V;
+
+  /// Returns a reference to the data of the given key.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the key is not in the arena.
+  fn index(&self, key: &K) -> &V {
+    self.get(key).expect("key does not exist")
+  }
+}
+
+impl<'a, K: ArenaKey, V> IntoIterator for &'a Arena<K, V> {
+  type Item = (&'a K, &'a V);
+  type IntoIter = Iter<'a, K, V>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.iter()
+  }
+}
+
+impl<'a, K: ArenaKey, V> IntoIterator for &'a mut Arena<K, V> {
+  type Item = (&'a K, &'a mut V);
+  type IntoIter = IterMut<'a, K, V>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.iter_mut()
+  }
+}
+
+impl<K: ArenaKey + fmt::Debug, V: fmt::Debug> fmt::Debug for Arena<K, V> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_map().entries(self.iter()).finish()
+  }
+}
+
+/// An iterator over the key-data pairs of an [`Arena`].
+pub struct Iter<'a, K, V> {
+  slots: slice::Iter<'a, Slot<K, V>>,
+  len: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+  type Item = (&'a K, &'a V);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let (k, v) = self.slots.find_map(|s| s.entry.as_ref())?;
+    self.len -= 1;
+    Some((k, v))
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.len, Some(self.len))
+  }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+impl<K, V> FusedIterator for Iter<'_, K, V> {}
+
+/// A mutable iterator over the key-data pairs of an [`Arena`].
+pub struct IterMut<'a, K, V> {
+  slots: slice::IterMut<'a, Slot<K, V>>,
+  len: usize,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+  type Item = (&'a K, &'a mut V);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let (k, v) = self.slots.find_map(|s| s.entry.as_mut())?;
+    self.len -= 1;
+    Some((&*k, v))
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.len, Some(self.len))
+  }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+impl<K, V> FusedIterator for IterMut<'_, K, V> {}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::ir::entities::Value;
+
+  #[test]
+  fn reuse_slots() {
+    let mut arena = Arena::<Value, i32>::new();
+    let a = arena.insert(1);
+    let b = arena.insert(2);
+    assert_eq!(arena.remove(&a), Some(1));
+    assert_eq!(arena.get(&a), None);
+    // the slot of `a` is reused, but `a` is still stale
+    let c = arena.insert(3);
+    assert_ne!(a, c);
+    assert_eq!(arena.get(&a), None);
+    assert_eq!(arena[&c], 3);
+    assert_eq!(arena.slots.len(), 2);
+    assert_eq!(arena.iter().map(|(_, v)| *v).collect::<Vec<_>>(), [3, 2]);
+    // keys of other arenas are not accepted
+    let other = Arena::<Value, i32>::new();
+    assert!(!other.contains_key(&b) && arena.contains_key(&b));
+    assert!(!c.is_global());
+    // global handles are never in arenas
+    let global = Value(NonZeroU64::new(c.0.get() & !LOCAL_ID_FLAG).unwrap());
+    assert!(global.is_global() && !arena.contains_key(&global));
+  }
+}
@@ -4,20 +4,35 @@
 //! [`DataFlowGraph::replace_value_with`] will return an IR builder object.
 //! You can only create values or basic blocks by using the interface
 //! provided by the builder traits.
+//!
+//! [`FunctionBuilder`] wraps these builders, and inserts the created
+//! instructions into the layout of the function automatically.
 
 use crate::ir::dfg::DataFlowGraph;
-use crate::ir::entities::{BasicBlock, BasicBlockData, Function, Program, Value, ValueData};
+use crate::ir::entities::{BasicBlock, BasicBlockData, Const, Function, FunctionData};
+use crate::ir::entities::{Program, Value, ValueData, ValueKind};
+use crate::ir::error::Error;
+use crate::ir::symbol::checked_symbol;
 use crate::ir::types::{Type, TypeKind};
+use crate::ir::validation::Validation;
+use crate::ir::value_list::ValueList;
 use crate::ir::values::*;
+use std::ops::{Deref, DerefMut};
 
 /// A trait that provides methods for querying entity information.
 pub trait EntityInfoQuerier {
+  /// Returns the type information of the given value,
+  /// or an error if the given value does not exist.
+  fn try_value_type(&self, value: Value) -> Result<Type, Error>;
+
   /// Returns the type information of the given value.
   ///
   /// # Panics
   ///
   /// Panics if the given value does not exist.
-  fn value_type(&self, value: Value) -> Type;
+  fn value_type(&self, value: Value) -> Type {
+    unwrap_or_panic(self.try_value_type(value))
+  }
 
   /// Checks if the given value is a constant.
   ///
@@ -26,19 +41,79 @@ pub trait EntityInfoQuerier {
   /// Panics if the given value does not exist.
   fn is_const(&self, value: Value) -> bool;
 
+  /// Returns a reference to the parameters of the given basic block,
+  /// or an error if the given basic block does not exist.
+  fn try_bb_params(&self, bb: BasicBlock) -> Result<&[Value], Error>;
+
   /// Returns a reference to the parameters of the given basic block.
   ///
   /// # Panics
   ///
   /// Panics if the given basic block does not exist.
-  fn bb_params(&self, bb: BasicBlock) -> &[Value];
+  fn bb_params(&self, bb: BasicBlock) -> &[Value] {
+    unwrap_or_panic(self.try_bb_params(bb))
+  }
+
+  /// Returns the type information of the given function,
+  /// or an error if the given function does not exist.
+  fn try_func_type(&self, func: Function) -> Result<Type, Error>;
 
   /// Returns the type information of the given function.
   ///
   /// # Panics
   ///
   /// Panics if the given function does not exist.
-  fn func_type(&self, func: Function) -> Type;
+  fn func_type(&self, func: Function) -> Type {
+    unwrap_or_panic(self.try_func_type(func))
+  }
+
+  /// Returns the name of the given function,
+  /// or an error if the given function does not exist.
+  ///
+  /// The default implementation always returns an error.
+  fn try_func_name(&self, func: Function) -> Result<String, Error> {
+    Err(Error::FunctionNotFound { func })
+  }
+
+  /// Returns the return type of the function that values are created in,
+  /// or `None` if it is unknown, e.g. when creating global values, or
+  /// the function is not in a program.
+  ///
+  /// The default implementation always returns `None`.
+  fn ret_type(&self) -> Option<Type> {
+    None
+  }
+}
+
+/// Returns the value of the given result, or panics with the error message.
+fn unwrap_or_panic<T>(result: Result<T, Error>) -> T {
+  result.unwrap_or_else(|e| panic!("{e}"))
+}
+
+/// Returns an error if the given type of the operand is a unit type.
+fn check_not_unit(operand: &'static str, ty: &Type) -> Result<(), Error> {
+  if ty.is_unit() {
+    Err(Error::TypeMismatch {
+      operand,
+      expected: "a non-unit type",
+      found: ty.clone(),
+    })
+  } else {
+    Ok(())
+  }
+}
+
+/// Returns an error if the given type of the operand is not an integer type.
+fn check_i32(operand: &'static str, ty: &Type) -> Result<(), Error> {
+  if ty.is_i32() {
+    Ok(())
+  } else {
+    Err(Error::TypeMismatch {
+      operand,
+      expected: "an integer",
+      found: ty.clone(),
+    })
+  }
 }
 
 /// A builder trait that provides method for inserting value data
@@ -70,9 +145,14 @@ pub trait ValueBuilder: Sized + EntityInfoQuerier + ValueInserter {
   /// # Panics
   ///
   /// Panics if the given type is a unit type.
-  fn zero_init(mut self, ty: Type) -> Value {
-    assert!(!ty.is_unit(), "`ty` can not be unit");
-    self.insert_value(ZeroInit::new_data(ty))
+  fn zero_init(self, ty: Type) -> Value {
+    unwrap_or_panic(self.try_zero_init(ty))
+  }
+
+  /// Fallible version of [`ValueBuilder::zero_init`].
+  fn try_zero_init(mut self, ty: Type) -> Result<Value, Error> {
+    check_not_unit("`ty`", &ty)?;
+    Ok(self.insert_value(ZeroInit::new_data(ty)))
   }
 
   /// Create a new undefined value.
@@ -80,9 +160,14 @@ pub trait ValueBuilder: Sized + EntityInfoQuerier + ValueInserter {
   /// # Panics
   ///
   /// Panics if the given type is a unit type.
-  fn undef(mut self, ty: Type) -> Value {
-    assert!(!ty.is_unit(), "`ty` can not be unit");
-    self.insert_value(Undef::new_data(ty))
+  fn undef(self, ty: Type) -> Value {
+    unwrap_or_panic(self.try_undef(ty))
+  }
+
+  /// Fallible version of [`ValueBuilder::undef`].
+  fn try_undef(mut self, ty: Type) -> Result<Value, Error> {
+    check_not_unit("`ty`", &ty)?;
+    Ok(self.insert_value(Undef::new_data(ty)))
   }
 
   /// Creates an aggregate constant with elements `elems`.
@@ -94,27 +179,143 @@ pub trait ValueBuilder: Sized + EntityInfoQuerier + ValueInserter {
   /// * No elements are provided.
   /// * Presence of non-constant elements or unit type elements.
   /// * Elements have different types.
-  fn aggregate(mut self, elems: Vec<Value>) -> Value {
+  fn aggregate(self, elems: impl Into<ValueList>) -> Value {
+    unwrap_or_panic(self.try_aggregate(elems))
+  }
+
+  /// Fallible version of [`ValueBuilder::aggregate`].
+  fn try_aggregate(mut self, elems: impl Into<ValueList>) -> Result<Value, Error> {
+    let elems = elems.into();
     // element list should not be empty
-    assert!(!elems.is_empty(), "`elems` must not be empty");
-    // check if all elements are constant
-    assert!(
-      elems.iter().all(|e| self.is_const(*e)),
-      "`elems` must all be constants"
-    );
-    // check if all elements have the same type
-    assert!(
-      elems
-        .windows(2)
-        .all(|e| self.value_type(e[0]) == self.value_type(e[1])),
-      "type mismatch in `elems`"
-    );
-    // check base type
-    let base = self.value_type(elems[0]);
-    assert!(!base.is_unit(), "base type must not be `unit`");
+    let base = match elems.first() {
+      Some(elem) => self.try_value_type(*elem)?,
+      None => return Err(Error::EmptyAggregate),
+    };
+    check_not_unit("elements of `elems`", &base)?;
+    for elem in &elems {
+      // check if all elements are constants of the same type
+      let ty = self.try_value_type(*elem)?;
+      if ty != base {
+        return Err(Error::TypeMismatch {
+          operand: "elements of `elems`",
+          expected: "of the same type",
+          found: ty,
+        });
+      }
+      if !self.is_const(*elem) {
+        return Err(Error::NotConstant);
+      }
+    }
     // create array type
     let ty = Type::get_array(base, elems.len());
-    self.insert_value(Aggregate::new_data(elems, ty))
+    Ok(self.insert_value(Aggregate::new_data(elems, ty)))
+  }
+
+  /// Creates a raw byte data constant of type `[i32, n]`, where `n` is
+  /// the number of the given bytes.
+  ///
+  /// Byte data constants can only be used in the initializers of
+  /// global allocations.
+  ///
+  /// # Panics
+  ///
+  /// Panics if no bytes are provided.
+  fn bytes(self, bytes: impl Into<Vec<u8>>) -> Value {
+    unwrap_or_panic(self.try_bytes(bytes))
+  }
+
+  /// Fallible version of [`ValueBuilder::bytes`].
+  fn try_bytes(mut self, bytes: impl Into<Vec<u8>>) -> Result<Value, Error> {
+    let bytes = bytes.into();
+    if bytes.is_empty() {
+      return Err(Error::EmptyAggregate);
+    }
+    Ok(self.insert_value(Bytes::new_data(bytes)))
+  }
+
+  /// Creates a constant of type `ty` from the given initializer.
+  ///
+  /// Integers, aggregates and zero initializers are created recursively
+  /// to match the target type. Like the initializer lists in C, a list
+  /// shorter than the array type is padded with zero initializers, and
+  /// byte data shorter than the array type is padded with zero bytes.
+  ///
+  /// Returns an error if a list has more elements than the array type,
+  /// or the initializer does not match the target type (an integer for
+  /// an array type, or a list for a non-array type, for example).
+  /// No value will be created in this case.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given type is a unit type.
+  fn aggregate_from(mut self, ty: Type, init: &ConstInit) -> Result<Value, Error> {
+    assert!(!ty.is_unit(), "`ty` can not be unit");
+    init.check(&ty)?;
+    Ok(init.build(&mut self, &ty))
+  }
+}
+
+/// Initializer of a constant, used by [`ValueBuilder::aggregate_from`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConstInit {
+  /// An integer.
+  Int(i32),
+  /// An initializer list of an array.
+  List(Vec<ConstInit>),
+  /// Byte data of an integer array, like string literals in C.
+  Bytes(Vec<u8>),
+  /// A zero initializer.
+  Zero,
+}
+
+impl ConstInit {
+  /// Checks if the initializer matches the given type.
+  fn check(&self, ty: &Type) -> Result<(), Error> {
+    match (self, ty.kind()) {
+      (ConstInit::Zero, _) | (ConstInit::Int(_), TypeKind::Int32) => Ok(()),
+      (ConstInit::List(elems), TypeKind::Array(base, len)) => {
+        if elems.len() > *len {
+          Err(Error::ExcessElements {
+            ty: ty.clone(),
+            len: elems.len(),
+          })
+        } else {
+          elems.iter().try_for_each(|e| e.check(base))
+        }
+      }
+      (ConstInit::Bytes(bytes), TypeKind::Array(base, len)) if base.is_i32() => {
+        if bytes.len() > *len {
+          Err(Error::ExcessElements {
+            ty: ty.clone(),
+            len: bytes.len(),
+          })
+        } else {
+          Ok(())
+        }
+      }
+      _ => Err(Error::InitTypeMismatch { ty: ty.clone() }),
+    }
+  }
+
+  /// Builds the initializer of the given type,
+  /// which must have been checked.
+  fn build<B: ValueInserter>(&self, builder: &mut B, ty: &Type) -> Value {
+    match (self, ty.kind()) {
+      (ConstInit::Int(i), _) => builder.insert_value(Integer::new_data(*i)),
+      (ConstInit::List(elems), TypeKind::Array(base, len)) if !elems.is_empty() => {
+        let mut values: Vec<_> = elems.iter().map(|e| e.build(builder, base)).collect();
+        values.resize_with(*len, || {
+          builder.insert_value(ZeroInit::new_data(base.clone()))
+        });
+        builder.insert_value(Aggregate::new_data(values.into(), ty.clone()))
+      }
+      (ConstInit::Bytes(bytes), TypeKind::Array(_, len)) if !bytes.is_empty() => {
+        let mut bytes = bytes.clone();
+        bytes.resize(*len, 0);
+        builder.insert_value(Bytes::new_data(bytes))
+      }
+      _ => builder.insert_value(ZeroInit::new_data(ty.clone())),
+    }
   }
 }
 
@@ -124,25 +325,43 @@ pub trait GlobalInstBuilder: ValueBuilder {
   ///
   /// # Panics
   ///
-  /// Panics if the type of the initialize is a unit type.
-  fn global_alloc(mut self, init: Value) -> Value {
-    let init_ty = self.value_type(init);
-    assert!(!init_ty.is_unit(), "the type of `init` must not be unit");
+  /// Panics if the type of the initialize is a unit type, or the
+  /// initializer is not a constant.
+  fn global_alloc(self, init: impl Into<Const>) -> Value {
+    unwrap_or_panic(self.try_global_alloc(init))
+  }
+
+  /// Fallible version of [`GlobalInstBuilder::global_alloc`].
+  fn try_global_alloc(mut self, init: impl Into<Const>) -> Result<Value, Error> {
+    let init = init.into().value();
+    let init_ty = self.try_value_type(init)?;
+    check_not_unit("`init`", &init_ty)?;
+    if !self.is_const(init) {
+      return Err(Error::NotConstant);
+    }
     let ty = Type::get_pointer(init_ty);
-    self.insert_value(GlobalAlloc::new_data(init, ty))
+    Ok(self.insert_value(GlobalAlloc::new_data(init, ty)))
   }
 }
 
 /// A builder for building and inserting local instructions.
+///
+/// Each method has a fallible version named `try_*`, which returns an
+/// [`Error`] instead of panicking, and creates no value in this case.
 pub trait LocalInstBuilder: ValueBuilder {
   /// Creates a local memory allocation.
   ///
   /// # Panics
   ///
   /// Panics if the given type is a unit type.
-  fn alloc(mut self, ty: Type) -> Value {
-    assert!(!ty.is_unit(), "`ty` can not be unit");
-    self.insert_value(Alloc::new_data(Type::get_pointer(ty)))
+  fn alloc(self, ty: Type) -> Value {
+    unwrap_or_panic(self.try_alloc(ty))
+  }
+
+  /// Fallible version of [`LocalInstBuilder::alloc`].
+  fn try_alloc(mut self, ty: Type) -> Result<Value, Error> {
+    check_not_unit("`ty`", &ty)?;
+    Ok(self.insert_value(Alloc::new_data(Type::get_pointer(ty))))
   }
 
   /// Creates a memory load with the given source.
@@ -150,13 +369,18 @@ pub trait LocalInstBuilder: ValueBuilder {
   /// # Panics
   ///
   /// Panics if the type of the source value is not a pointer type.
-  fn load(mut self, src: Value) -> Value {
-    let src_ty = self.value_type(src);
+  fn load(self, src: Value) -> Value {
+    unwrap_or_panic(self.try_load(src))
+  }
+
+  /// Fallible version of [`LocalInstBuilder::load`].
+  fn try_load(mut self, src: Value) -> Result<Value, Error> {
+    let src_ty = self.try_value_type(src)?;
     let ty = match src_ty.kind() {
       TypeKind::Pointer(ty) => ty.clone(),
-      _ => panic!("expected a pointer type"),
+      _ => return Err(mismatch("`src`", "a pointer", src_ty)),
     };
-    self.insert_value(Load::new_data(src, ty))
+    Ok(self.insert_value(Load::new_data(src, ty)))
   }
 
   /// Creates a memory store with the given value and destination.
@@ -164,12 +388,24 @@ pub trait LocalInstBuilder: ValueBuilder {
   /// # Panics
   ///
   /// Panics if the dest type is not a pointer of the value type.
-  fn store(mut self, value: Value, dest: Value) -> Value {
-    assert!(
-      Type::get_pointer(self.value_type(value)) == self.value_type(dest),
-      "the type of `dest` must be the pointer of `value`'s type"
-    );
-    self.insert_value(Store::new_data(value, dest))
+  fn store(self, value: Value, dest: Value) -> Value {
+    unwrap_or_panic(self.try_store(value, dest))
+  }
+
+  /// Fallible version of [`LocalInstBuilder::store`].
+  ///
+  /// Returns [`Error::StoreMismatch`] if the dest type is not a pointer
+  /// of the value type.
+  fn try_store(mut self, value: Value, dest: Value) -> Result<Value, Error> {
+    let value_ty = self.try_value_type(value)?;
+    let dest_ty = self.try_value_type(dest)?;
+    if Type::get_pointer(value_ty.clone()) != dest_ty {
+      return Err(Error::StoreMismatch {
+        value: value_ty,
+        dest: dest_ty,
+      });
+    }
+    Ok(self.insert_value(Store::new_data(value, dest)))
   }
 
   /// Creates a pointer calculation with the given source pointer and index.
@@ -178,17 +414,15 @@ pub trait LocalInstBuilder: ValueBuilder {
   ///
   /// Panics if the source type is not a pointer type, or the index type is
   /// not an integer type.
-  fn get_ptr(mut self, src: Value, index: Value) -> Value {
-    let src_ty = self.value_type(src);
-    assert!(
-      matches!(src_ty.kind(), TypeKind::Pointer(..)),
-      "`src` must be a pointer"
-    );
-    assert!(
-      self.value_type(index).is_i32(),
-      "`index` must be an integer"
-    );
-    self.insert_value(GetPtr::new_data(src, index, src_ty))
+  fn get_ptr(self, src: Value, index: Value) -> Value {
+    unwrap_or_panic(self.try_get_ptr(src, index))
+  }
+
+  /// Fallible version of [`LocalInstBuilder::get_ptr`].
+  fn try_get_ptr(mut self, src: Value, index: Value) -> Result<Value, Error> {
+    let ty = get_ptr_result_type(&self.try_value_type(src)?)?;
+    check_i32("`index`", &self.try_value_type(index)?)?;
+    Ok(self.insert_value(GetPtr::new_data(src, index, ty)))
   }
 
   /// Creates a element pointer calculation with the given source pointer
@@ -198,19 +432,15 @@ pub trait LocalInstBuilder: ValueBuilder {
   ///
   /// Panics if the source type is not a pointer type of an array, or the
   /// index type is not an integer type.
-  fn get_elem_ptr(mut self, src: Value, index: Value) -> Value {
-    assert!(
-      self.value_type(index).is_i32(),
-      "`index` must be an integer"
-    );
-    let ty = match self.value_type(src).kind() {
-      TypeKind::Pointer(ty) => match ty.kind() {
-        TypeKind::Array(base, _) => Type::get_pointer(base.clone()),
-        _ => panic!("`src` must be a pointer of array"),
-      },
-      _ => panic!("`src` must be a pointer of array"),
-    };
-    self.insert_value(GetElemPtr::new_data(src, index, ty))
+  fn get_elem_ptr(self, src: Value, index: Value) -> Value {
+    unwrap_or_panic(self.try_get_elem_ptr(src, index))
+  }
+
+  /// Fallible version of [`LocalInstBuilder::get_elem_ptr`].
+  fn try_get_elem_ptr(mut self, src: Value, index: Value) -> Result<Value, Error> {
+    check_i32("`index`", &self.try_value_type(index)?)?;
+    let ty = gep_result_type(&self.try_value_type(src)?)?;
+    Ok(self.insert_value(GetElemPtr::new_data(src, index, ty)))
   }
 
   /// Creates a binary operation.
@@ -218,14 +448,16 @@ pub trait LocalInstBuilder: ValueBuilder {
   /// # Panics
   ///
   /// Panics if the lhs/rhs type is not an integer type.
-  fn binary(mut self, op: BinaryOp, lhs: Value, rhs: Value) -> Value {
-    let lhs_ty = self.value_type(lhs);
-    let rhs_ty = self.value_type(rhs);
-    assert!(
-      lhs_ty.is_i32() && lhs_ty == rhs_ty,
-      "both `lhs` and `rhs` must be integer"
-    );
-    self.insert_value(Binary::new_data(op, lhs, rhs, lhs_ty))
+  fn binary(self, op: BinaryOp, lhs: Value, rhs: Value) -> Value {
+    unwrap_or_panic(self.try_binary(op, lhs, rhs))
+  }
+
+  /// Fallible version of [`LocalInstBuilder::binary`].
+  fn try_binary(mut self, op: BinaryOp, lhs: Value, rhs: Value) -> Result<Value, Error> {
+    let lhs_ty = self.try_value_type(lhs)?;
+    check_i32("`lhs`", &lhs_ty)?;
+    check_i32("`rhs`", &self.try_value_type(rhs)?)?;
+    Ok(self.insert_value(Binary::new_data(op, lhs, rhs, lhs_ty)))
   }
 
   /// Creates a conditional branch with the given condition and targets.
@@ -234,17 +466,21 @@ pub trait LocalInstBuilder: ValueBuilder {
   ///
   /// Panics if the condition type is not an integer type, or the true/false
   /// basic block has parameters.
-  fn branch(mut self, cond: Value, true_bb: BasicBlock, false_bb: BasicBlock) -> Value {
-    assert!(self.value_type(cond).is_i32(), "`cond` must be integer");
-    assert!(
-      self.bb_params(true_bb).is_empty(),
-      "`true_bb` must not have parameters"
-    );
-    assert!(
-      self.bb_params(false_bb).is_empty(),
-      "`false_bb` must not have parameters"
-    );
-    self.insert_value(Branch::new_data(cond, true_bb, false_bb))
+  fn branch(self, cond: Value, true_bb: BasicBlock, false_bb: BasicBlock) -> Value {
+    unwrap_or_panic(self.try_branch(cond, true_bb, false_bb))
+  }
+
+  /// Fallible version of [`LocalInstBuilder::branch`].
+  fn try_branch(
+    mut self,
+    cond: Value,
+    true_bb: BasicBlock,
+    false_bb: BasicBlock,
+  ) -> Result<Value, Error> {
+    check_i32("`cond`", &self.try_value_type(cond)?)?;
+    check_bb_arg_types(&self, "`true_bb`", true_bb, &[])?;
+    check_bb_arg_types(&self, "`false_bb`", false_bb, &[])?;
+    Ok(self.insert_value(Branch::new_data(cond, true_bb, false_bb)))
   }
 
   /// Creates a conditional branch with the given condition, targets
@@ -256,23 +492,35 @@ pub trait LocalInstBuilder: ValueBuilder {
   /// false basic blocks are same but they have one or more parameters, or
   /// the argument types of the true/false basic block do not match.
   fn branch_with_args(
-    mut self,
+    self,
     cond: Value,
     true_bb: BasicBlock,
     false_bb: BasicBlock,
-    true_args: Vec<Value>,
-    false_args: Vec<Value>,
+    true_args: impl Into<ValueList>,
+    false_args: impl Into<ValueList>,
   ) -> Value {
-    assert!(self.value_type(cond).is_i32(), "`cond` must be integer");
-    assert!(
-      true_bb != false_bb || (true_args.is_empty() && false_args.is_empty()),
-      "branches with same targets and one or more arguments are illegal"
-    );
-    check_bb_arg_types(&self, self.bb_params(true_bb), &true_args);
-    check_bb_arg_types(&self, self.bb_params(false_bb), &false_args);
-    self.insert_value(Branch::with_args(
+    unwrap_or_panic(self.try_branch_with_args(cond, true_bb, false_bb, true_args, false_args))
+  }
+
+  /// Fallible version of [`LocalInstBuilder::branch_with_args`].
+  fn try_branch_with_args(
+    mut self,
+    cond: Value,
+    true_bb: BasicBlock,
+    false_bb: BasicBlock,
+    true_args: impl Into<ValueList>,
+    false_args: impl Into<ValueList>,
+  ) -> Result<Value, Error> {
+    let (true_args, false_args) = (true_args.into(), false_args.into());
+    check_i32("`cond`", &self.try_value_type(cond)?)?;
+    if true_bb == false_bb && (!true_args.is_empty() || !false_args.is_empty()) {
+      return Err(Error::SameTargetArgs);
+    }
+    check_bb_arg_types(&self, "`true_bb`", true_bb, &true_args)?;
+    check_bb_arg_types(&self, "`false_bb`", false_bb, &false_args)?;
+    Ok(self.insert_value(Branch::with_args(
       cond, true_bb, false_bb, true_args, false_args,
-    ))
+    )))
   }
 
   /// Creates a unconditional jump with the given target.
@@ -280,12 +528,14 @@ pub trait LocalInstBuilder: ValueBuilder {
   /// # Panics
   ///
   /// Panics if the target basic block has parameters.
-  fn jump(mut self, target: BasicBlock) -> Value {
-    assert!(
-      self.bb_params(target).is_empty(),
-      "`target` must not have parameters"
-    );
-    self.insert_value(Jump::new_data(target))
+  fn jump(self, target: BasicBlock) -> Value {
+    unwrap_or_panic(self.try_jump(target))
+  }
+
+  /// Fallible version of [`LocalInstBuilder::jump`].
+  fn try_jump(mut self, target: BasicBlock) -> Result<Value, Error> {
+    check_bb_arg_types(&self, "`target`", target, &[])?;
+    Ok(self.insert_value(Jump::new_data(target)))
   }
 
   /// Creates a unconditional jump with the given target and arguments.
@@ -293,44 +543,88 @@ pub trait LocalInstBuilder: ValueBuilder {
   /// # Panics
   ///
   /// Panics if the argument types of the target basic block do not match.
-  fn jump_with_args(mut self, target: BasicBlock, args: Vec<Value>) -> Value {
-    check_bb_arg_types(&self, self.bb_params(target), &args);
-    self.insert_value(Jump::with_args(target, args))
+  fn jump_with_args(self, target: BasicBlock, args: impl Into<ValueList>) -> Value {
+    unwrap_or_panic(self.try_jump_with_args(target, args))
+  }
+
+  /// Fallible version of [`LocalInstBuilder::jump_with_args`].
+  fn try_jump_with_args(
+    mut self,
+    target: BasicBlock,
+    args: impl Into<ValueList>,
+  ) -> Result<Value, Error> {
+    let args = args.into();
+    check_bb_arg_types(&self, "`target`", target, &args)?;
+    Ok(self.insert_value(Jump::with_args(target, args)))
   }
 
   /// Creates a function call.
   ///
   /// # Panics
   ///
-  /// Panics if the argument types of the callee do not match.
-  fn call(mut self, callee: Function, args: Vec<Value>) -> Value {
-    let ty = match self.func_type(callee).kind() {
-      TypeKind::Function(params, ret) => {
-        assert!(
-          params
-            .iter()
-            .zip(args.iter())
-            .all(|(ty, a)| ty == &self.value_type(*a)),
-          "argument type mismatch"
-        );
-        ret.clone()
-      }
-      _ => panic!("expected a function type"),
-    };
-    self.insert_value(Call::new_data(callee, args, ty))
+  /// Panics if the number or the types of the arguments do not match
+  /// the parameters of the callee.
+  fn call(self, callee: Function, args: impl Into<ValueList>) -> Value {
+    unwrap_or_panic(self.try_call(callee, args))
+  }
+
+  /// Fallible version of [`LocalInstBuilder::call`].
+  ///
+  /// Returns [`Error::CallMismatch`] if the number or the types of the
+  /// arguments do not match the parameters of the callee.
+  fn try_call(mut self, callee: Function, args: impl Into<ValueList>) -> Result<Value, Error> {
+    let args = args.into();
+    let ty = check_call_args(&self, callee, &args)?;
+    Ok(self.insert_value(Call::new_data(callee, args, ty)))
   }
 
   /// Creates a new return instruction.
   ///
   /// # Panics
   ///
-  /// Panics if the value type (if value is not `None`) is a unit type.
-  fn ret(mut self, value: Option<Value>) -> Value {
-    assert!(
-      value.map_or(true, |v| !self.value_type(v).is_unit()),
-      "the type of `value` must not be `unit`"
-    );
-    self.insert_value(Return::new_data(value))
+  /// Panics if the value type (if value is not `None`) is a unit type,
+  /// or does not match the return type of the function (see
+  /// [`EntityInfoQuerier::ret_type`]).
+  fn ret(self, value: Option<Value>) -> Value {
+    unwrap_or_panic(self.try_ret(value))
+  }
+
+  /// Fallible version of [`LocalInstBuilder::ret`].
+  ///
+  /// Returns [`Error::RetMismatch`] if the value type (or unit if value
+  /// is `None`) does not match the return type of the function.
+  fn try_ret(mut self, value: Option<Value>) -> Result<Value, Error> {
+    let ty = match value {
+      Some(value) => {
+        let ty = self.try_value_type(value)?;
+        check_not_unit("`value`", &ty)?;
+        ty
+      }
+      None => Type::get_unit(),
+    };
+    if let Some(expected) = self.ret_type() {
+      if expected != ty {
+        return Err(Error::RetMismatch {
+          expected,
+          found: ty,
+        });
+      }
+    }
+    Ok(self.insert_value(Return::new_data(value)))
+  }
+
+  /// Creates a new unreachable instruction.
+  fn unreachable(mut self) -> Value {
+    self.insert_value(Unreachable::new_data())
+  }
+}
+
+/// Returns a type mismatch error of the given operand.
+fn mismatch(operand: &'static str, expected: &'static str, found: Type) -> Error {
+  Error::TypeMismatch {
+    operand,
+    expected,
+    found,
   }
 }
 
@@ -345,9 +639,9 @@ pub trait BasicBlockBuilder: Sized + ValueInserter {
   ///
   /// # Panics
   ///
-  /// Panics if the given name (if exists) not starts with `%` or `@`.
+  /// Panics if the given name (if exists) is not a valid symbol name.
   fn basic_block(mut self, name: Option<String>) -> BasicBlock {
-    check_bb_name(&name);
+    let name = check_bb_name(name);
     self.insert_bb(BasicBlockData::new(name))
   }
 
@@ -357,7 +651,7 @@ pub trait BasicBlockBuilder: Sized + ValueInserter {
   ///
   /// Panics if there are unit types in the given parameter types.
   fn basic_block_with_params(mut self, name: Option<String>, params_ty: Vec<Type>) -> BasicBlock {
-    check_bb_name(&name);
+    let name = check_bb_name(name);
     assert!(
       params_ty.iter().all(|p| !p.is_unit()),
       "parameter type must not be `unit`!"
@@ -381,7 +675,7 @@ pub trait BasicBlockBuilder: Sized + ValueInserter {
     name: Option<String>,
     params: Vec<(Option<String>, Type)>,
   ) -> BasicBlock {
-    check_bb_name(&name);
+    let name = check_bb_name(name);
     assert!(
       params.iter().all(|(_, p)| !p.is_unit()),
       "parameter type must not be `unit`!"
@@ -399,35 +693,68 @@ pub trait BasicBlockBuilder: Sized + ValueInserter {
   }
 }
 
-/// Checks if the parameter types of the given basic block type matches
+/// Checks if the parameter types of the given basic block matches
 /// the given argument types.
-///
-/// # Panics
-///
-/// Panics if the parameter types of the given basic block type does not
-/// match the given argument types.
-fn check_bb_arg_types(querier: &impl EntityInfoQuerier, params: &[Value], args: &[Value]) {
-  assert!(
-    params.len() == args.len()
-      && params
-        .iter()
-        .zip(args.iter())
-        .all(|(p, a)| querier.value_type(*p) == querier.value_type(*a)),
-    "arguments type of basic block mismatch"
-  );
+pub(in crate::ir) fn check_bb_arg_types(
+  querier: &impl EntityInfoQuerier,
+  target: &'static str,
+  bb: BasicBlock,
+  args: &[Value],
+) -> Result<(), Error> {
+  let params = querier.try_bb_params(bb)?;
+  if params.len() != args.len() {
+    return Err(Error::ArgMismatch { target });
+  }
+  for (p, a) in params.iter().zip(args) {
+    if querier.try_value_type(*p)? != querier.try_value_type(*a)? {
+      return Err(Error::ArgMismatch { target });
+    }
+  }
+  Ok(())
+}
+
+/// Checks if the parameters of the given callee match the given
+/// arguments. Returns the return type of the callee.
+pub(in crate::ir) fn check_call_args(
+  querier: &impl EntityInfoQuerier,
+  callee: Function,
+  args: &[Value],
+) -> Result<Type, Error> {
+  let callee_ty = querier.try_func_type(callee)?;
+  let (params, ret) = match callee_ty.kind() {
+    TypeKind::Function(params, ret) => (params, ret),
+    _ => return Err(mismatch("`callee`", "a function", callee_ty)),
+  };
+  let arg_tys = args
+    .iter()
+    .map(|arg| querier.try_value_type(*arg))
+    .collect::<Result<Vec<_>, _>>()?;
+  let arg = params
+    .iter()
+    .zip(&arg_tys)
+    .position(|(param, arg)| param != arg)
+    .map(|i| (i, params[i].clone(), arg_tys[i].clone()));
+  if params.len() != args.len() || arg.is_some() {
+    return Err(Error::CallMismatch {
+      callee: querier
+        .try_func_name(callee)
+        .unwrap_or_else(|_| format!("{callee:?}")),
+      expected: params.len(),
+      found: args.len(),
+      arg,
+    });
+  }
+  Ok(ret.clone())
 }
 
 /// Checks if the given name is a valid basic block name.
+/// Returns the (possibly sanitized) name.
 ///
 /// # Panics
 ///
-/// Panics if the given name (if exists) not starts with `%` or `@`.
-fn check_bb_name(name: &Option<String>) {
-  assert!(
-    name.as_ref().map_or(true, |n| n.len() > 1
-      && (n.starts_with('%') || n.starts_with('@'))),
-    "invalid basic block name"
-  );
+/// Panics if the given name (if exists) is not a valid symbol name.
+fn check_bb_name(name: Option<String>) -> Option<String> {
+  name.map(|n| checked_symbol(n, "basic block"))
 }
 
 /// An entity information querier based on data flow graph.
@@ -436,53 +763,50 @@ pub trait DfgBasedInfoQuerier {
 }
 
 impl<T: DfgBasedInfoQuerier> EntityInfoQuerier for T {
-  fn value_type(&self, value: Value) -> Type {
+  fn try_value_type(&self, value: Value) -> Result<Type, Error> {
     self
       .dfg()
-      .globals
-      .upgrade()
-      .unwrap()
-      .borrow()
-      .get(&value)
-      .or_else(|| self.dfg().values().get(&value))
-      .expect("value does not exist")
-      .ty()
-      .clone()
+      .try_with_value_data(value, |data| data.ty().clone())
+      .ok_or(Error::ValueNotFound { value })
   }
 
   fn is_const(&self, value: Value) -> bool {
     self
       .dfg()
-      .globals
-      .upgrade()
-      .unwrap()
-      .borrow()
-      .get(&value)
-      .or_else(|| self.dfg().values().get(&value))
-      .expect("value does not exist")
-      .kind()
-      .is_const()
+      .with_value_data(value, |data| data.kind().is_const())
   }
 
-  fn bb_params(&self, bb: BasicBlock) -> &[Value] {
+  fn try_bb_params(&self, bb: BasicBlock) -> Result<&[Value], Error> {
+    match self.dfg().bbs().get(&bb) {
+      Some(data) => Ok(data.params()),
+      None => Err(Error::BasicBlockNotFound { bb }),
+    }
+  }
+
+  fn try_func_type(&self, func: Function) -> Result<Type, Error> {
     self
       .dfg()
-      .bbs()
-      .get(&bb)
-      .expect("basic block does not exist")
-      .params()
+      .func_tys
+      .upgrade()
+      .and_then(|tys| tys.read().unwrap().get(&func).cloned())
+      .ok_or(Error::FunctionNotFound { func })
   }
 
-  fn func_type(&self, func: Function) -> Type {
+  fn try_func_name(&self, func: Function) -> Result<String, Error> {
     self
       .dfg()
-      .func_tys
+      .func_names
       .upgrade()
-      .unwrap()
-      .borrow()
-      .get(&func)
-      .expect("function does not exist")
-      .clone()
+      .and_then(|names| names.read().unwrap().get(&func).cloned())
+      .ok_or(Error::FunctionNotFound { func })
+  }
+
+  fn ret_type(&self) -> Option<Type> {
+    let func = self.dfg().func?;
+    match self.try_func_type(func).ok()?.kind() {
+      TypeKind::Function(_, ret) => Some(ret.clone()),
+      _ => None,
+    }
   }
 }
 
@@ -509,6 +833,99 @@ impl<'a> ValueInserter for LocalBuilder<'a> {
 impl<'a> ValueBuilder for LocalBuilder<'a> {}
 impl<'a> LocalInstBuilder for LocalBuilder<'a> {}
 
+impl<'a> LocalBuilder<'a> {
+  /// Creates a binary operation, or folds it if possible.
+  ///
+  /// The operation will be folded if:
+  ///
+  /// * both operands are integer constants, in which case the operation
+  ///   is evaluated with the overflow semantics of the data flow graph
+  ///   (see [`DataFlowGraph::set_overflow_mode`]). Division and modulo
+  ///   by zero, and overflowing operations in [`OverflowMode::Trap`]
+  ///   will not be folded.
+  /// * the operation is a trivial identity, like `x + 0`, `x * 1` or
+  ///   `x & x`.
+  ///
+  /// If the operator is commutative and only the left-hand side is an
+  /// integer constant, the operands will be swapped, so the constant is
+  /// always on the right-hand side of the created instruction.
+  ///
+  /// Returns [`Folded::Value`] if the operation has been folded to a
+  /// new integer constant or an existing value. Constants should never
+  /// be inserted into the layout, and existing values should not be
+  /// inserted again, so only [`Folded::Inst`] needs to be inserted.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the lhs/rhs type is not an integer type.
+  pub fn binary_folded(self, op: BinaryOp, lhs: Value, rhs: Value) -> Folded {
+    let lhs_ty = self.value_type(lhs);
+    assert!(
+      lhs_ty.is_i32() && lhs_ty == self.value_type(rhs),
+      "both `lhs` and `rhs` must be integer"
+    );
+    let int = |v| {
+      self
+        .dfg
+        .values()
+        .get(&v)
+        .and_then(|d| d.kind().as_integer())
+    };
+    // fold operations on constants
+    let (lhs, rhs) = match (int(lhs), int(rhs)) {
+      (Some(l), Some(r)) => match op.eval_with(l, r, self.dfg.overflow_mode()) {
+        Some(ans) => return Folded::Value(self.integer(ans)),
+        None => (lhs, rhs),
+      },
+      (Some(_), None) if op.is_commutative() => (rhs, lhs),
+      _ => (lhs, rhs),
+    };
+    // fold trivial identities
+    match (op, int(rhs)) {
+      (
+        BinaryOp::Add
+        | BinaryOp::Sub
+        | BinaryOp::Or
+        | BinaryOp::Xor
+        | BinaryOp::Shl
+        | BinaryOp::Shr
+        | BinaryOp::Sar,
+        Some(0),
+      )
+      | (BinaryOp::Mul | BinaryOp::Div, Some(1))
+      | (BinaryOp::And, Some(-1)) => return Folded::Value(lhs),
+      (BinaryOp::Mul | BinaryOp::And, Some(0)) => return Folded::Value(self.integer(0)),
+      (BinaryOp::And | BinaryOp::Or, _) if lhs == rhs => return Folded::Value(lhs),
+      (BinaryOp::Sub | BinaryOp::Xor | BinaryOp::NotEq, _) if lhs == rhs => {
+        return Folded::Value(self.integer(0))
+      }
+      (BinaryOp::Eq, _) if lhs == rhs => return Folded::Value(self.integer(1)),
+      _ => {}
+    }
+    Folded::Inst(self.binary(op, lhs, rhs))
+  }
+}
+
+/// The result of [`LocalBuilder::binary_folded`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Folded {
+  /// The operation has been folded to an integer constant or an existing
+  /// value, which should not be inserted into the layout.
+  Value(Value),
+  /// The operation can not be folded, a new binary instruction has been
+  /// created, which should be inserted into the layout.
+  Inst(Value),
+}
+
+impl Folded {
+  /// Returns the handle of the folded value or the created instruction.
+  pub fn value(self) -> Value {
+    match self {
+      Folded::Value(v) | Folded::Inst(v) => v,
+    }
+  }
+}
+
 /// An basic block builder that builds a new basic block and inserts it
 /// to the data flow graph.
 ///
@@ -563,34 +980,32 @@ pub struct GlobalBuilder<'a> {
 }
 
 impl<'a> EntityInfoQuerier for GlobalBuilder<'a> {
-  fn value_type(&self, value: Value) -> Type {
-    self
-      .program
-      .values
-      .borrow()
-      .get(&value)
-      .expect("value does not exist")
-      .ty()
-      .clone()
+  fn try_value_type(&self, value: Value) -> Result<Type, Error> {
+    let values = self.program.values.read().unwrap();
+    match values.get(&value) {
+      Some(data) => Ok(data.ty().clone()),
+      None => Err(Error::ValueNotFound { value }),
+    }
   }
 
   fn is_const(&self, value: Value) -> bool {
     self
       .program
       .values
-      .borrow()
+      .read()
+      .unwrap()
       .get(&value)
       .expect("value does not exist")
       .kind()
       .is_const()
   }
 
-  fn bb_params(&self, _: BasicBlock) -> &[Value] {
-    unimplemented!()
+  fn try_bb_params(&self, bb: BasicBlock) -> Result<&[Value], Error> {
+    Err(Error::BasicBlockNotFound { bb })
   }
 
-  fn func_type(&self, _: Function) -> Type {
-    unimplemented!()
+  fn try_func_type(&self, func: Function) -> Result<Type, Error> {
+    Err(Error::FunctionNotFound { func })
   }
 }
 
@@ -607,3 +1022,937 @@ impl<'a> ValueInserter for GlobalBuilder<'a> {
 
 impl<'a> ValueBuilder for GlobalBuilder<'a> {}
 impl<'a> GlobalInstBuilder for GlobalBuilder<'a> {}
+
+/// Position where [`FunctionBuilder`] inserts new instructions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InsertPoint {
+  /// At the end of the given basic block.
+  End(BasicBlock),
+  /// Before the given instruction.
+  Before(Value),
+}
+
+/// A function builder that creates instructions and inserts them into
+/// the layout at the current insert point.
+///
+/// # Examples
+///
+/// ```
+/// use koopa::ir::builder::FunctionBuilder;
+/// use koopa::ir::{BinaryOp, FunctionData, Program, Type};
+///
+/// let mut program = Program::new();
+/// let func = program.new_func(FunctionData::new(
+///   "@inc".into(),
+///   vec![Type::get_i32()],
+///   Type::get_i32(),
+/// ));
+/// let func = program.func_mut(func);
+/// let x = func.params()[0];
+///
+/// let mut builder = FunctionBuilder::new(func);
+/// let entry = builder.create_bb(Some("%entry".into()));
+/// builder.position_at_end(entry);
+/// let one = builder.integer(1);
+/// let ans = builder.binary(BinaryOp::Add, x, one);
+/// builder.ret(Some(ans));
+/// assert!(builder.is_terminated(entry));
+/// ```
+pub struct FunctionBuilder<'a> {
+  func: &'a mut FunctionData,
+  pos: Option<InsertPoint>,
+  saved: Vec<Option<InsertPoint>>,
+  /// The validation level of the operations of the builder.
+  validation: Option<Validation>,
+}
+
+/// Creates an instruction by the given [`LocalInstBuilder`] method,
+/// and inserts it at the current insert point.
+macro_rules! build_inst {
+  ($self:ident, $is_term:expr, $method:ident($($arg:expr),*)) => {{
+    $self.check_insertable($is_term);
+    let inst = $self.with_dfg(|dfg| dfg.new_value().$method($($arg),*));
+    $self.insert(inst)
+  }};
+}
+
+impl<'a> FunctionBuilder<'a> {
+  /// Creates a new function builder on the given function.
+  ///
+  /// The insert point of the created builder is unset.
+  pub fn new(func: &'a mut FunctionData) -> Self {
+    Self {
+      func,
+      pos: None,
+      saved: Vec::new(),
+      validation: None,
+    }
+  }
+
+  /// Sets the level of the checks performed when the builder creates
+  /// values, see [`Validation`]. Defaults to the level of the function.
+  ///
+  /// The level of the function is not changed, so mutations that are
+  /// not made by the builder are checked at the level of the function.
+  pub fn with_validation(mut self, level: Validation) -> Self {
+    self.validation = Some(level);
+    self
+  }
+
+  /// Calls the given closure with the data flow graph of the function,
+  /// at the validation level of the builder.
+  fn with_dfg<F, R>(&mut self, f: F) -> R
+  where
+    F: FnOnce(&mut DataFlowGraph) -> R,
+  {
+    let dfg = self.func.dfg_mut();
+    let Some(level) = self.validation else {
+      return f(dfg);
+    };
+    let saved = dfg.validation();
+    dfg.set_validation(level);
+    let result = f(dfg);
+    dfg.set_validation(saved);
+    result
+  }
+
+  /// Returns a reference to the function data.
+  pub fn func(&self) -> &FunctionData {
+    self.func
+  }
+
+  /// Returns a mutable reference to the function data.
+  pub fn func_mut(&mut self) -> &mut FunctionData {
+    self.func
+  }
+
+  /// Returns the current insert point.
+  pub fn insert_point(&self) -> Option<InsertPoint> {
+    self.pos
+  }
+
+  /// Returns the basic block of the current insert point.
+  pub fn current_bb(&self) -> Option<BasicBlock> {
+    match self.pos? {
+      InsertPoint::End(bb) => Some(bb),
+      InsertPoint::Before(inst) => self.func.layout().parent_bb(inst),
+    }
+  }
+
+  /// Creates a new basic block with the given name, and appends it to
+  /// the end of the function's layout.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given name (if exists) is not a valid symbol name.
+  pub fn create_bb(&mut self, name: Option<String>) -> BasicBlock {
+    let bb = self.func.dfg_mut().new_bb().basic_block(name);
+    self.func.layout_mut().bbs_mut().push_key_back(bb).unwrap();
+    bb
+  }
+
+  /// Creates a new basic block with the given name and parameter types,
+  /// and appends it to the end of the function's layout.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given name (if exists) is not a valid symbol name,
+  /// or there are unit types in the given parameter types.
+  pub fn create_bb_with_params(
+    &mut self,
+    name: Option<String>,
+    params_ty: Vec<Type>,
+  ) -> BasicBlock {
+    let bb = self
+      .func
+      .dfg_mut()
+      .new_bb()
+      .basic_block_with_params(name, params_ty);
+    self.func.layout_mut().bbs_mut().push_key_back(bb).unwrap();
+    bb
+  }
+
+  /// Sets the insert point to the end of the given basic block.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given basic block is not in the layout.
+  pub fn position_at_end(&mut self, bb: BasicBlock) {
+    assert!(
+      self.func.layout().bbs().contains_key(&bb),
+      "`bb` is not in the layout"
+    );
+    self.pos = Some(InsertPoint::End(bb));
+  }
+
+  /// Sets the insert point to the position before the given instruction.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given instruction is not in the layout.
+  pub fn position_before(&mut self, inst: Value) {
+    assert!(
+      self.func.layout().parent_bb(inst).is_some(),
+      "`inst` is not in the layout"
+    );
+    self.pos = Some(InsertPoint::Before(inst));
+  }
+
+  /// Saves the current insert point to the insert point stack.
+  ///
+  /// The saved insert point can be restored by
+  /// [`pop_insert_point`](FunctionBuilder::pop_insert_point) later.
+  /// An insert point before an instruction is still valid after
+  /// new instructions being inserted around that instruction.
+  pub fn push_insert_point(&mut self) {
+    self.saved.push(self.pos);
+  }
+
+  /// Restores the insert point from the top of the insert point stack.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the insert point stack is empty.
+  pub fn pop_insert_point(&mut self) {
+    self.pos = self.saved.pop().expect("insert point stack is empty");
+  }
+
+  /// Saves the current insert point, returns a guard that restores
+  /// the insert point when dropped.
+  ///
+  /// The returned guard can be dereferenced to the builder.
+  pub fn save_insert_point(&mut self) -> InsertPointGuard<'_, 'a> {
+    self.push_insert_point();
+    InsertPointGuard { builder: self }
+  }
+
+  /// Calls the given closure with the insert point set to the entry
+  /// basic block, and restores the insert point after that.
+  ///
+  /// The insert point is set before the first non-`alloc` instruction
+  /// of the entry basic block, or at the end of the entry basic block
+  /// if there is no such instruction. So this is useful for hoisting
+  /// `alloc`s to the entry basic block, and the hoisted `alloc`s keep
+  /// their creation order.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the function has no basic blocks.
+  pub fn with_entry_block<F, R>(&mut self, f: F) -> R
+  where
+    F: FnOnce(&mut Self) -> R,
+  {
+    let layout = self.func.layout();
+    let entry = layout.entry_bb().expect("function has no basic blocks");
+    let first = layout
+      .bbs()
+      .node(&entry)
+      .unwrap()
+      .insts()
+      .keys()
+      .find(|i| !matches!(self.func.dfg().value(**i).kind(), ValueKind::Alloc(_)))
+      .copied();
+    let mut guard = self.save_insert_point();
+    match first {
+      Some(inst) => guard.position_before(inst),
+      None => guard.position_at_end(entry),
+    }
+    f(&mut guard)
+  }
+
+  /// Returns `true` if the last instruction of the given basic block
+  /// is a terminator.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given basic block is not in the layout.
+  pub fn is_terminated(&self, bb: BasicBlock) -> bool {
+    self
+      .func
+      .layout()
+      .bbs()
+      .node(&bb)
+      .expect("`bb` is not in the layout")
+      .insts()
+      .back_key()
+      .is_some_and(|i| self.func.dfg().value(*i).kind().is_terminator())
+  }
+
+  /// Checks if an instruction can be inserted at the current insert point.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the insert point is unset, or the instruction is inserted
+  /// after a terminator, or a terminator is inserted before another
+  /// instruction.
+  fn check_insertable(&self, is_term: bool) {
+    match self.pos.expect("insert point is not set") {
+      InsertPoint::End(bb) => assert!(
+        !self.is_terminated(bb),
+        "basic block has already been terminated"
+      ),
+      InsertPoint::Before(_) => assert!(
+        !is_term,
+        "terminators can only be inserted at the end of basic block"
+      ),
+    }
+  }
+
+  /// Inserts the given instruction at the current insert point.
+  fn insert(&mut self, inst: Value) -> Value {
+    match self.pos.unwrap() {
+      InsertPoint::End(bb) => {
+        let insts = self.func.layout_mut().bb_mut(bb).insts_mut();
+        insts.push_key_back(inst).unwrap();
+      }
+      InsertPoint::Before(before) => {
+        let bb = self.func.layout().parent_bb(before).unwrap();
+        let insts = self.func.layout_mut().bb_mut(bb).insts_mut();
+        insts.cursor_mut(before).insert_key_before(inst).unwrap();
+      }
+    }
+    inst
+  }
+
+  /// Creates a new integer constant.
+  ///
+  /// Constants will not be inserted into the layout.
+  pub fn integer(&mut self, value: i32) -> Value {
+    self.with_dfg(|dfg| dfg.new_value().integer(value))
+  }
+
+  /// Creates a new zero initializer.
+  ///
+  /// Constants will not be inserted into the layout.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given type is a unit type.
+  pub fn zero_init(&mut self, ty: Type) -> Value {
+    self.with_dfg(|dfg| dfg.new_value().zero_init(ty))
+  }
+
+  /// Creates a new undefined value.
+  ///
+  /// Constants will not be inserted into the layout.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given type is a unit type.
+  pub fn undef(&mut self, ty: Type) -> Value {
+    self.with_dfg(|dfg| dfg.new_value().undef(ty))
+  }
+
+  /// Creates a local memory allocation at the current insert point.
+  ///
+  /// See [`LocalInstBuilder::alloc`].
+  pub fn alloc(&mut self, ty: Type) -> Value {
+    build_inst!(self, false, alloc(ty))
+  }
+
+  /// Creates a memory load at the current insert point.
+  ///
+  /// See [`LocalInstBuilder::load`].
+  pub fn load(&mut self, src: Value) -> Value {
+    build_inst!(self, false, load(src))
+  }
+
+  /// Creates a memory store at the current insert point.
+  ///
+  /// See [`LocalInstBuilder::store`].
+  pub fn store(&mut self, value: Value, dest: Value) -> Value {
+    build_inst!(self, false, store(value, dest))
+  }
+
+  /// Creates a pointer calculation at the current insert point.
+  ///
+  /// See [`LocalInstBuilder::get_ptr`].
+  pub fn get_ptr(&mut self, src: Value, index: Value) -> Value {
+    build_inst!(self, false, get_ptr(src, index))
+  }
+
+  /// Creates a element pointer calculation at the current insert point.
+  ///
+  /// See [`LocalInstBuilder::get_elem_ptr`].
+  pub fn get_elem_ptr(&mut self, src: Value, index: Value) -> Value {
+    build_inst!(self, false, get_elem_ptr(src, index))
+  }
+
+  /// Creates a sequence of pointer calculations that indexes `base`
+  /// with `indices`, and inserts them at the current insert point.
+  /// Returns the final pointer, or `base` if `indices` is empty.
+  ///
+  /// One instruction is created for each index. If `base_is_decayed`
+  /// is `true`, `base` is treated as a decayed pointer (like an array
+  /// parameter `*T`), and a `getptr` is used for the first index.
+  /// Otherwise and for the rest indices, `getelemptr`s are used.
+  ///
+  /// Returns an error if the pointer can not be indexed at some step,
+  /// no instructions will be created in this case.
+  ///
+  /// # Panics
+  ///
+  /// Panics if instructions can not be inserted at the current
+  /// insert point.
+  pub fn index_ptr(
+    &mut self,
+    base: Value,
+    indices: &[Value],
+    base_is_decayed: bool,
+  ) -> Result<Value, Error> {
+    // check the type of each step
+    let mut ty = self.func.dfg().value(base).ty().clone();
+    for step in 0..indices.len() {
+      let next = match ty.kind() {
+        TypeKind::Pointer(_) if step == 0 && base_is_decayed => ty.clone(),
+        TypeKind::Pointer(base) => match base.kind() {
+          TypeKind::Array(elem, _) => Type::get_pointer(elem.clone()),
+          _ => return Err(Error::NotIndexable { step, ty }),
+        },
+        _ => return Err(Error::NotIndexable { step, ty }),
+      };
+      ty = next;
+    }
+    // create instructions
+    let mut ptr = base;
+    for (step, index) in indices.iter().enumerate() {
+      ptr = if step == 0 && base_is_decayed {
+        self.get_ptr(ptr, *index)
+      } else {
+        self.get_elem_ptr(ptr, *index)
+      };
+    }
+    Ok(ptr)
+  }
+
+  /// Creates a binary operation at the current insert point.
+  ///
+  /// See [`LocalInstBuilder::binary`].
+  pub fn binary(&mut self, op: BinaryOp, lhs: Value, rhs: Value) -> Value {
+    build_inst!(self, false, binary(op, lhs, rhs))
+  }
+
+  /// Creates a binary operation at the current insert point, or folds it
+  /// if possible. Folded values will not be inserted into the layout.
+  ///
+  /// See [`LocalBuilder::binary_folded`].
+  pub fn binary_folded(&mut self, op: BinaryOp, lhs: Value, rhs: Value) -> Value {
+    self.check_insertable(false);
+    match self.with_dfg(|dfg| dfg.new_value().binary_folded(op, lhs, rhs)) {
+      Folded::Value(value) => value,
+      Folded::Inst(inst) => self.insert(inst),
+    }
+  }
+
+  /// Creates a conditional branch at the current insert point.
+  ///
+  /// See [`LocalInstBuilder::branch`].
+  pub fn branch(&mut self, cond: Value, true_bb: BasicBlock, false_bb: BasicBlock) -> Value {
+    build_inst!(self, true, branch(cond, true_bb, false_bb))
+  }
+
+  /// Creates a conditional branch with arguments at the current
+  /// insert point.
+  ///
+  /// See [`LocalInstBuilder::branch_with_args`].
+  pub fn branch_with_args(
+    &mut self,
+    cond: Value,
+    true_bb: BasicBlock,
+    false_bb: BasicBlock,
+    true_args: impl Into<ValueList>,
+    false_args: impl Into<ValueList>,
+  ) -> Value {
+    build_inst!(
+      self,
+      true,
+      branch_with_args(cond, true_bb, false_bb, true_args, false_args)
+    )
+  }
+
+  /// Creates a unconditional jump at the current insert point.
+  ///
+  /// See [`LocalInstBuilder::jump`].
+  pub fn jump(&mut self, target: BasicBlock) -> Value {
+    build_inst!(self, true, jump(target))
+  }
+
+  /// Creates a unconditional jump with arguments at the current
+  /// insert point.
+  ///
+  /// See [`LocalInstBuilder::jump_with_args`].
+  pub fn jump_with_args(&mut self, target: BasicBlock, args: impl Into<ValueList>) -> Value {
+    build_inst!(self, true, jump_with_args(target, args))
+  }
+
+  /// Creates a function call at the current insert point.
+  ///
+  /// See [`LocalInstBuilder::call`].
+  pub fn call(&mut self, callee: Function, args: impl Into<ValueList>) -> Value {
+    build_inst!(self, false, call(callee, args))
+  }
+
+  /// Creates a return instruction at the current insert point.
+  ///
+  /// See [`LocalInstBuilder::ret`].
+  pub fn ret(&mut self, value: Option<Value>) -> Value {
+    build_inst!(self, true, ret(value))
+  }
+
+  /// Creates an unreachable instruction at the current insert point.
+  ///
+  /// See [`LocalInstBuilder::unreachable`].
+  pub fn unreachable(&mut self) -> Value {
+    build_inst!(self, true, unreachable())
+  }
+}
+
+/// A guard that restores the insert point of [`FunctionBuilder`]
+/// when dropped.
+///
+/// Returned by method [`FunctionBuilder::save_insert_point`].
+pub struct InsertPointGuard<'b, 'a> {
+  builder: &'b mut FunctionBuilder<'a>,
+}
+
+impl<'b, 'a> Deref for InsertPointGuard<'b, 'a> {
+  type Target = FunctionBuilder<'a>;
+
+  fn deref(&self) -> &Self::Target {
+    self.builder
+  }
+}
+
+impl<'b, 'a> DerefMut for InsertPointGuard<'b, 'a> {
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    self.builder
+  }
+}
+
+impl<'b, 'a> Drop for InsertPointGuard<'b, 'a> {
+  fn drop(&mut self) {
+    self.builder.pop_insert_point();
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn binary_folded() {
+    let mut program = Program::new();
+    let func = program.new_func(FunctionData::new(
+      "@test".into(),
+      vec![Type::get_i32()],
+      Type::get_i32(),
+    ));
+    let func = program.func_mut(func);
+    let x = func.params()[0];
+    let dfg = func.dfg_mut();
+    let one = dfg.new_value().integer(1);
+    let two = dfg.new_value().integer(2);
+    let zero = dfg.new_value().integer(0);
+    // 1 + 2 => 3
+    let v = dfg.new_value().binary_folded(BinaryOp::Add, one, two);
+    assert!(matches!(v, Folded::Value(_)));
+    assert_eq!(dfg.value(v.value()).kind().as_integer(), Some(3));
+    // wrapping semantics
+    let max = dfg.new_value().integer(i32::MAX);
+    let v = dfg.new_value().binary_folded(BinaryOp::Add, max, one);
+    assert_eq!(dfg.value(v.value()).kind().as_integer(), Some(i32::MIN));
+    // x + 0 => x, x * 1 => x, x & x => x
+    assert_eq!(
+      dfg.new_value().binary_folded(BinaryOp::Add, x, zero),
+      Folded::Value(x)
+    );
+    assert_eq!(
+      dfg.new_value().binary_folded(BinaryOp::Mul, x, one),
+      Folded::Value(x)
+    );
+    assert_eq!(
+      dfg.new_value().binary_folded(BinaryOp::And, x, x),
+      Folded::Value(x)
+    );
+    // 2 * x => x * 2
+    let v = dfg.new_value().binary_folded(BinaryOp::Mul, two, x);
+    let bin = dfg.value(v.value()).kind().as_binary().unwrap();
+    assert!(matches!(v, Folded::Inst(_)));
+    assert_eq!((bin.lhs(), bin.rhs()), (x, two));
+    // 1 / 0 and x % 0 must not be folded
+    let v = dfg.new_value().binary_folded(BinaryOp::Div, one, zero);
+    assert!(matches!(v, Folded::Inst(_)));
+    let v = dfg.new_value().binary_folded(BinaryOp::Mod, x, zero);
+    assert!(matches!(v, Folded::Inst(_)));
+  }
+
+  #[test]
+  fn overflow_modes() {
+    let mut program = Program::new();
+    let func = program.new_func(FunctionData::new("@test".into(), vec![], Type::get_i32()));
+    let dfg = program.func_mut(func).dfg_mut();
+    let max = dfg.new_value().integer(i32::MAX);
+    let min = dfg.new_value().integer(i32::MIN);
+    let one = dfg.new_value().integer(1);
+    let neg_one = dfg.new_value().integer(-1);
+    for (mode, add, div, rem) in [
+      (
+        OverflowMode::Wrapping,
+        Some(i32::MIN),
+        Some(i32::MIN),
+        Some(0),
+      ),
+      (
+        OverflowMode::Saturating,
+        Some(i32::MAX),
+        Some(i32::MAX),
+        Some(0),
+      ),
+      (OverflowMode::Trap, None, None, None),
+    ] {
+      dfg.set_overflow_mode(mode);
+      for (op, lhs, rhs, expected) in [
+        (BinaryOp::Add, max, one, add),
+        (BinaryOp::Div, min, neg_one, div),
+        (BinaryOp::Mod, min, neg_one, rem),
+      ] {
+        let (l, r) = (
+          dfg.value(lhs).kind().as_integer(),
+          dfg.value(rhs).kind().as_integer(),
+        );
+        assert_eq!(op.eval_with(l.unwrap(), r.unwrap(), mode), expected);
+        // the folder agrees with the evaluation
+        let folded = match dfg.new_value().binary_folded(op, lhs, rhs) {
+          Folded::Value(v) => dfg.value(v).kind().as_integer(),
+          Folded::Inst(_) => None,
+        };
+        assert_eq!(folded, expected, "{op} under {mode:?}");
+      }
+    }
+  }
+
+  #[test]
+  fn aggregate_from() {
+    use ConstInit::*;
+    let mut program = Program::new();
+    // [[i32, 3], 2] = {{1, 2}, zeroinit}
+    let ty = Type::get_array(Type::get_array(Type::get_i32(), 3), 2);
+    let init = List(vec![List(vec![Int(1), Int(2)])]);
+    let agg = program
+      .new_value()
+      .aggregate_from(ty.clone(), &init)
+      .unwrap();
+    let values = program.borrow_values();
+    assert_eq!(values[&agg].ty(), &ty);
+    let elems = values[&agg].kind().as_aggregate().unwrap().elems();
+    assert!(matches!(values[&elems[1]].kind(), ValueKind::ZeroInit(_)));
+    let inner = values[&elems[0]].kind().as_aggregate().unwrap().elems();
+    assert_eq!(values[&inner[1]].kind().as_integer(), Some(2));
+    assert!(matches!(values[&inner[2]].kind(), ValueKind::ZeroInit(_)));
+    drop(values);
+    // errors
+    let init = List(vec![Int(1), Int(2), Int(3)]);
+    assert_eq!(
+      program.new_value().aggregate_from(ty.clone(), &init),
+      Err(Error::ExcessElements {
+        ty: ty.clone(),
+        len: 3
+      })
+    );
+    let init = List(vec![Int(1)]);
+    assert_eq!(
+      program.new_value().aggregate_from(ty, &init),
+      Err(Error::InitTypeMismatch {
+        ty: Type::get_array(Type::get_i32(), 3)
+      })
+    );
+  }
+
+  #[test]
+  fn bytes() {
+    use ConstInit::*;
+    let mut program = Program::new();
+    let bytes = program.new_value().bytes(*b"hi\0");
+    assert_eq!(
+      program.borrow_value(bytes).ty(),
+      &Type::get_array(Type::get_i32(), 3)
+    );
+    assert_eq!(
+      program.new_value().try_bytes(vec![]),
+      Err(Error::EmptyAggregate)
+    );
+    // [[i32, 4], 2] = {"hi", zeroinit}
+    let ty = Type::get_array(Type::get_array(Type::get_i32(), 4), 2);
+    let init = List(vec![Bytes(b"hi".to_vec())]);
+    let agg = program
+      .new_value()
+      .aggregate_from(ty.clone(), &init)
+      .unwrap();
+    let values = program.borrow_values();
+    let elems = values[&agg].kind().as_aggregate().unwrap().elems();
+    let inner = values[&elems[0]].kind().as_bytes().unwrap();
+    assert_eq!(inner.bytes(), b"hi\0\0");
+    assert_eq!(inner.elems().collect::<Vec<_>>(), [104, 105, 0, 0]);
+    assert!(matches!(values[&elems[1]].kind(), ValueKind::ZeroInit(_)));
+    drop(values);
+    // errors
+    let init = List(vec![Bytes(b"hello".to_vec())]);
+    assert_eq!(
+      program.new_value().aggregate_from(ty.clone(), &init),
+      Err(Error::ExcessElements {
+        ty: Type::get_array(Type::get_i32(), 4),
+        len: 5
+      })
+    );
+    assert_eq!(
+      program
+        .new_value()
+        .aggregate_from(ty, &Bytes(b"hi".to_vec())),
+      Err(Error::InitTypeMismatch {
+        ty: Type::get_array(Type::get_array(Type::get_i32(), 4), 2)
+      })
+    );
+  }
+
+  #[test]
+  fn try_builders() {
+    let mut program = Program::new();
+    let func = program.new_func(FunctionData::new(
+      "@test".into(),
+      vec![Type::get_i32()],
+      Type::get_unit(),
+    ));
+    let func = program.func_mut(func);
+    let x = func.params()[0];
+    let dfg = func.dfg_mut();
+    let len = dfg.values().len();
+    // type mismatches
+    assert_eq!(
+      dfg.new_value().try_load(x),
+      Err(Error::TypeMismatch {
+        operand: "`src`",
+        expected: "a pointer",
+        found: Type::get_i32(),
+      })
+    );
+    let bb = dfg
+      .new_bb()
+      .basic_block_with_params(None, vec![Type::get_i32()]);
+    assert_eq!(
+      dfg.new_value().try_jump(bb),
+      Err(Error::ArgMismatch { target: "`target`" })
+    );
+    assert_eq!(
+      dfg.new_value().try_aggregate(vec![]),
+      Err(Error::EmptyAggregate)
+    );
+    // unexisted values
+    let v = dfg.new_value().integer(1);
+    dfg.remove_value(v);
+    assert_eq!(
+      dfg.new_value().try_binary(BinaryOp::Add, x, v),
+      Err(Error::ValueNotFound { value: v })
+    );
+    // nothing is created on error
+    assert_eq!(dfg.values().len(), len + 1);
+    let jump = dfg.new_value().try_jump_with_args(bb, vec![x]).unwrap();
+    assert!(matches!(dfg.value(jump).kind(), ValueKind::Jump(j) if j.args() == [x]));
+  }
+
+  #[test]
+  fn call_site_mismatches() {
+    let mut program = Program::new();
+    let callee = program.new_func(FunctionData::new(
+      "@callee".into(),
+      vec![Type::get_i32(), Type::get_i32()],
+      Type::get_i32(),
+    ));
+    let func = program.new_func(FunctionData::new(
+      "@test".into(),
+      vec![Type::get_i32()],
+      Type::get_i32(),
+    ));
+    let func = program.func_mut(func);
+    let x = func.params()[0];
+    let dfg = func.dfg_mut();
+    let p = dfg.new_value().alloc(Type::get_i32());
+    // calls
+    let err = dfg.new_value().try_call(callee, vec![x]).unwrap_err();
+    assert_eq!(
+      err,
+      Error::CallMismatch {
+        callee: "@callee".into(),
+        expected: 2,
+        found: 1,
+        arg: None,
+      }
+    );
+    assert_eq!(
+      err.to_string(),
+      "arguments of call to @callee do not match its parameters, expected 2 arguments, found 1"
+    );
+    let err = dfg.new_value().try_call(callee, vec![x, p, x]).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "arguments of call to @callee do not match its parameters, \
+       expected 2 arguments, found 3, argument 1 must be of type i32, found type *i32"
+    );
+    let call = dfg.new_value().try_call(callee, vec![x, x]).unwrap();
+    // returns
+    let err = dfg.new_value().try_ret(Some(p)).unwrap_err();
+    assert_eq!(
+      err,
+      Error::RetMismatch {
+        expected: Type::get_i32(),
+        found: Type::get_pointer(Type::get_i32()),
+      }
+    );
+    assert_eq!(
+      dfg.new_value().try_ret(None).unwrap_err().to_string(),
+      "expected return value of type i32, but returned nothing"
+    );
+    assert!(dfg.new_value().try_ret(Some(call)).is_ok());
+    // stores
+    assert_eq!(
+      dfg.new_value().try_store(p, p).unwrap_err().to_string(),
+      "can not store value of type *i32 to pointer of type *i32"
+    );
+    assert!(dfg.new_value().try_store(x, p).is_ok());
+    // names of renamed callees are reported
+    program.func_mut(callee).set_name("@renamed".into());
+    let func = program.func_mut(program.func_layout()[1]);
+    let err = func
+      .dfg_mut()
+      .new_value()
+      .try_call(callee, vec![])
+      .unwrap_err();
+    assert!(matches!(err, Error::CallMismatch { callee, .. } if callee == "@renamed"));
+  }
+
+  #[test]
+  fn function_builder() {
+    let mut program = Program::new();
+    let func = program.new_func(FunctionData::new(
+      "@test".into(),
+      vec![Type::get_i32()],
+      Type::get_unit(),
+    ));
+    let func = program.func_mut(func);
+    let x = func.params()[0];
+    let mut builder = FunctionBuilder::new(func);
+    let entry = builder.create_bb(Some("%entry".into()));
+    let exit = builder.create_bb(None);
+    builder.position_at_end(entry);
+    let alloc = builder.alloc(Type::get_i32());
+    let jump = builder.jump(exit);
+    assert!(builder.is_terminated(entry));
+    // insert instructions before the terminator
+    builder.position_before(jump);
+    let store = builder.store(x, alloc);
+    let zero = builder.integer(0);
+    assert_eq!(builder.current_bb(), Some(entry));
+    builder.position_at_end(exit);
+    let ret = builder.ret(None);
+    // check the layout
+    let layout = func.layout();
+    let insts: Vec<_> = layout
+      .bbs()
+      .node(&entry)
+      .unwrap()
+      .insts()
+      .keys()
+      .copied()
+      .collect();
+    assert_eq!(insts, [alloc, store, jump]);
+    assert_eq!(layout.parent_bb(ret), Some(exit));
+    assert_eq!(layout.parent_bb(zero), None);
+  }
+
+  #[test]
+  fn save_insert_point() {
+    let mut program = Program::new();
+    let func = program.new_func(FunctionData::new("@test".into(), vec![], Type::get_unit()));
+    let mut builder = FunctionBuilder::new(program.func_mut(func));
+    let entry = builder.create_bb(None);
+    let other = builder.create_bb(None);
+    builder.position_at_end(entry);
+    let a1 = builder.alloc(Type::get_i32());
+    builder.position_at_end(other);
+    let jump = builder.jump(entry);
+    builder.position_before(jump);
+    {
+      let mut guard = builder.save_insert_point();
+      guard.position_at_end(entry);
+      guard.ret(None);
+    }
+    assert_eq!(builder.insert_point(), Some(InsertPoint::Before(jump)));
+    // hoist allocations to the entry basic block
+    let a2 = builder.with_entry_block(|b| b.alloc(Type::get_i32()));
+    let a3 = builder.with_entry_block(|b| b.alloc(Type::get_i32()));
+    let load = builder.load(a3);
+    assert_eq!(builder.insert_point(), Some(InsertPoint::Before(jump)));
+    let insts = |bb| -> Vec<_> {
+      let node = builder.func().layout().bbs().node(&bb).unwrap();
+      node.insts().keys().copied().collect()
+    };
+    assert_eq!(insts(entry)[..3], [a1, a2, a3]);
+    assert_eq!(insts(other), [load, jump]);
+  }
+
+  #[test]
+  fn index_ptr() {
+    let mut program = Program::new();
+    // *[[i32, 3], 2]
+    let arr_ty = Type::get_array(Type::get_array(Type::get_i32(), 3), 2);
+    let func = program.new_func(FunctionData::new(
+      "@test".into(),
+      vec![Type::get_pointer(arr_ty)],
+      Type::get_unit(),
+    ));
+    let func = program.func_mut(func);
+    let p = func.params()[0];
+    let mut builder = FunctionBuilder::new(func);
+    let entry = builder.create_bb(None);
+    builder.position_at_end(entry);
+    let zero = builder.integer(0);
+    let ptr = builder.index_ptr(p, &[zero; 3], true).unwrap();
+    assert_eq!(
+      builder.func().dfg().value(ptr).ty(),
+      &Type::get_pointer(Type::get_i32())
+    );
+    let dfg = builder.func().dfg();
+    let gep = dfg.value(ptr).kind().as_get_elem_ptr().unwrap();
+    let gep = dfg.value(gep.src()).kind().as_get_elem_ptr().unwrap();
+    assert!(dfg.value(gep.src()).kind().as_get_ptr().is_some());
+    assert_eq!(
+      builder.index_ptr(p, &[zero; 4], true),
+      Err(Error::NotIndexable {
+        step: 3,
+        ty: Type::get_pointer(Type::get_i32())
+      })
+    );
+    assert_eq!(
+      builder
+        .func()
+        .layout()
+        .bbs()
+        .node(&entry)
+        .unwrap()
+        .insts()
+        .len(),
+      3
+    );
+  }
+
+  #[test]
+  #[should_panic(expected = "basic block has already been terminated")]
+  fn function_builder_after_terminator() {
+    let mut program = Program::new();
+    let func = program.new_func(FunctionData::new("@test".into(), vec![], Type::get_unit()));
+    let mut builder = FunctionBuilder::new(program.func_mut(func));
+    let entry = builder.create_bb(None);
+    builder.position_at_end(entry);
+    builder.ret(None);
+    builder.alloc(Type::get_i32());
+  }
+}
@@ -1,49 +1,98 @@
 //! Data flow graph ([`DataFlowGraph`]) related implementations.
 
-use crate::ir::builder::{BlockBuilder, LocalBuilder, ReplaceBuilder};
-use crate::ir::entities::{BasicBlock, BasicBlockData, Value, ValueData};
-use crate::ir::entities::{FuncTypeMapCell, GlobalValueMapCell};
-use crate::ir::idman::{next_bb_id, next_local_value_id};
-use std::collections::HashMap;
+use crate::ir::arena::Arena;
+use crate::ir::builder::{check_bb_arg_types, BlockBuilder, LocalBuilder, ReplaceBuilder};
+use crate::ir::builder_traits::*;
+use crate::ir::entities::{AttrValue, BasicBlock, BasicBlockData, SourceSpan, Value, ValueData};
+use crate::ir::entities::{Const, FuncNameMapCell, FuncTypeMapCell, FuncUserMapCell};
+use crate::ir::entities::{Function, GlobalValueMapCell};
+use crate::ir::entities::{Inst, ValueKind, ValueRef, ValueToken};
+use crate::ir::error::Error;
+use crate::ir::mutation::{MutationLog, MutationLogCell, MutationOp};
+use crate::ir::types::Type;
+use crate::ir::validation::{self, Validation};
+use crate::ir::value_list::ValueList;
+use crate::ir::values::{BlockArgRef, OverflowMode};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ops::{Index, IndexMut};
+use std::sync::MutexGuard;
 
 /// Data flow graph of a function.
 ///
 /// `DataFlowGraph` holds all data of values ([`ValueData`]) and basic
 /// blocks ([`BasicBlockData`]), and maintains their use-define and
 /// define-use chain.
+///
+/// # Constant interning
+///
+/// By default, every integer, zero initializer and undefined value
+/// created in the data flow graph gets a new value handle. After calling
+/// [`DataFlowGraph::set_intern_consts`], creating such a constant returns
+/// the existing handle if an equal constant has been created, and the
+/// `used_by` set of the shared constant contains all of its users. In
+/// this case, a constant can only be removed after all its users have
+/// been removed.
+///
+/// # Handle stability
+///
+/// Side tables keyed by local value handles, like the ones of register
+/// allocators, can rely on the following guarantees:
+///
+/// * Handles never change while the values exist. Replacing a value by
+///   [`DataFlowGraph::replace_value_with`] keeps its handle, so entries
+///   of the value are still valid, but may describe the old value data.
+/// * Removing a value invalidates its handle. The slot of the removed
+///   value may be reused by a new value, but the new value always gets a
+///   different handle with the next generation of the slot, so the old
+///   handle never refers to the new value. Slots that have been reused
+///   255 times are never reused again.
+/// * Replacing all uses of a value and then removing it, as the passes
+///   usually do, invalidates the old handle, and users refer to the
+///   replacement instead.
+/// * [`FunctionData::compact`](crate::ir::FunctionData::compact)
+///   invalidates all handles, and returns maps of old handles to new
+///   handles.
+///
+/// Use [`DataFlowGraph::token`] and [`DataFlowGraph::is_valid`] to
+/// detect stale entries in side tables.
 pub struct DataFlowGraph {
   pub(in crate::ir) globals: GlobalValueMapCell,
   pub(in crate::ir) func_tys: FuncTypeMapCell,
-  values: HashMap<Value, ValueData>,
-  bbs: HashMap<BasicBlock, BasicBlockData>,
+  pub(in crate::ir) func_names: FuncNameMapCell,
+  pub(in crate::ir) func_users: FuncUserMapCell,
+  /// The function that the data flow graph belongs to, or `None` if the
+  /// function is not in a program.
+  pub(in crate::ir) func: Option<Function>,
+  values: Arena<Value, ValueData>,
+  bbs: Arena<BasicBlock, BasicBlockData>,
+  intern_consts: bool,
+  consts: HashMap<ConstKey, Value>,
+  overflow_mode: OverflowMode,
+  validation: Validation,
+  log: Option<MutationLogCell>,
 }
 
-/// Returns a reference to the value data by the given value handle.
-macro_rules! data {
-  ($self:ident, $value:expr) => {
-    $self
-      .globals
-      .upgrade()
-      .unwrap()
-      .borrow()
-      .get(&$value)
-      .or_else(|| $self.values.get(&$value))
-      .expect("value does not exist")
-  };
+/// Key of interned constants.
+#[derive(Hash, PartialEq, Eq)]
+enum ConstKey {
+  Integer(i32),
+  ZeroInit(Type),
+  Undef(Type),
 }
 
-/// Returns a mutable reference to the value data by the given value handle.
-macro_rules! data_mut {
-  ($self:ident, $value:expr) => {
-    $self
-      .globals
-      .upgrade()
-      .unwrap()
-      .borrow_mut()
-      .get_mut(&$value)
-      .or_else(|| $self.values.get_mut(&$value))
-      .expect("value does not exist")
-  };
+impl ConstKey {
+  /// Returns the key of the given value data,
+  /// or `None` if the value can not be interned.
+  fn new(data: &ValueData) -> Option<Self> {
+    match data.kind() {
+      ValueKind::Integer(i) => Some(Self::Integer(i.value())),
+      ValueKind::ZeroInit(_) => Some(Self::ZeroInit(data.ty().clone())),
+      ValueKind::Undef(_) => Some(Self::Undef(data.ty().clone())),
+      _ => None,
+    }
+  }
 }
 
 impl DataFlowGraph {
@@ -52,14 +101,198 @@ impl DataFlowGraph {
     Self {
       globals: GlobalValueMapCell::new(),
       func_tys: FuncTypeMapCell::new(),
-      values: HashMap::new(),
-      bbs: HashMap::new(),
+      func_names: FuncNameMapCell::new(),
+      func_users: FuncUserMapCell::new(),
+      func: None,
+      values: Arena::new(),
+      bbs: Arena::new(),
+      intern_consts: false,
+      consts: HashMap::new(),
+      overflow_mode: OverflowMode::default(),
+      validation: Validation::default(),
+      log: None,
+    }
+  }
+
+  /// Enables or disables interning of integers, zero initializers and
+  /// undefined values in the current data flow graph.
+  ///
+  /// Disabling interning does not split the existing shared constants.
+  /// See [`DataFlowGraph`] for details.
+  pub fn set_intern_consts(&mut self, enable: bool) {
+    self.intern_consts = enable;
+    if !enable {
+      self.consts.clear();
+    }
+  }
+
+  /// Returns `true` if constant interning is enabled.
+  pub fn intern_consts(&self) -> bool {
+    self.intern_consts
+  }
+
+  /// Sets the semantics of integer overflows in the current data flow
+  /// graph, which is used when folding and evaluating operations.
+  /// The default is [`OverflowMode::Wrapping`].
+  pub fn set_overflow_mode(&mut self, mode: OverflowMode) {
+    self.overflow_mode = mode;
+  }
+
+  /// Returns the semantics of integer overflows.
+  pub fn overflow_mode(&self) -> OverflowMode {
+    self.overflow_mode
+  }
+
+  /// Sets the level of the checks performed when mutating the current
+  /// data flow graph. The default is [`Validation::Off`].
+  pub fn set_validation(&mut self, level: Validation) {
+    self.validation = level;
+  }
+
+  /// Returns the level of the checks performed when mutating the
+  /// current data flow graph.
+  pub fn validation(&self) -> Validation {
+    self.validation
+  }
+
+  /// Checks the given value data as if it were created in the current
+  /// data flow graph at the given level, regardless of the level of the
+  /// data flow graph. Returns the first error found.
+  ///
+  /// See [`Validation`] for the checks of each level.
+  pub fn validate_value_data(&self, data: &ValueData, level: Validation) -> Result<(), Error> {
+    validation::check_value_data(self, data, level)
+  }
+
+  /// Panics with the message of the given error, if any.
+  fn check(result: Result<(), Error>) {
+    if let Err(e) = result {
+      panic!("validation failed: {e}");
+    }
+  }
+
+  /// Enables the mutation log of the current data flow graph, which
+  /// records all the following mutations. Does nothing if the log has
+  /// already been enabled.
+  ///
+  /// See [`mutation`](crate::ir::mutation) for details.
+  pub fn enable_mutation_log(&mut self) {
+    self.log.get_or_insert_with(Default::default);
+  }
+
+  /// Returns the mutation log, or `None` if the log is not enabled.
+  pub fn mutation_log(&self) -> Option<MutexGuard<'_, MutationLog>> {
+    self.log.as_ref().map(|log| log.lock().unwrap())
+  }
+
+  /// Sets the label of the following mutations, like the name of the
+  /// running pass. Does nothing if the mutation log is not enabled.
+  pub fn set_mutation_label(&mut self, label: &str) {
+    if let Some(mut log) = self.mutation_log() {
+      log.set_label(Some(label));
+    }
+  }
+
+  /// Clears the label of the following mutations.
+  pub fn clear_mutation_label(&mut self) {
+    if let Some(mut log) = self.mutation_log() {
+      log.set_label(None);
+    }
+  }
+
+  /// Returns the shared mutation log, for attaching it to the layout.
+  pub(in crate::ir) fn mutation_log_cell(&self) -> Option<MutationLogCell> {
+    self.log.clone()
+  }
+
+  /// Records a mutation of a value if the mutation log is enabled.
+  fn record_value(&self, op: MutationOp, value: Value, kind: Option<&ValueKind>) {
+    if let Some(log) = &self.log {
+      log.lock().unwrap().record(op, Some(value), None, kind);
+    }
+  }
+
+  /// Calls `f` with a reference to the data of the given local value or
+  /// global value, and returns the result of `f`.
+  ///
+  /// Local values are looked up first, the global value map will only be
+  /// borrowed (immutably) if the given value is not a local value.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given value does not exist.
+  pub(in crate::ir) fn with_value_data<F, R>(&self, value: Value, f: F) -> R
+  where
+    F: FnOnce(&ValueData) -> R,
+  {
+    self
+      .try_with_value_data(value, f)
+      .expect("value does not exist")
+  }
+
+  /// Same as [`DataFlowGraph::with_value_data`], but returns `None`
+  /// if the given value does not exist.
+  pub(in crate::ir) fn try_with_value_data<F, R>(&self, value: Value, f: F) -> Option<R>
+  where
+    F: FnOnce(&ValueData) -> R,
+  {
+    if let Some(data) = self.values.get(&value) {
+      return Some(f(data));
+    }
+    let globals = self.globals.upgrade()?;
+    let globals = globals.read().unwrap();
+    globals.get(&value).map(f)
+  }
+
+  /// Mutable version of [`DataFlowGraph::with_value_data`], the global
+  /// value map will only be borrowed (mutably) if the given value is not
+  /// a local value.
+  fn with_value_data_mut<F, R>(&mut self, value: Value, f: F) -> R
+  where
+    F: FnOnce(&mut ValueData) -> R,
+  {
+    if let Some(data) = self.values.get_mut(&value) {
+      return f(data);
+    }
+    let globals = self.globals.upgrade().expect("value does not exist");
+    let mut globals = globals.write().unwrap();
+    f(globals.get_mut(&value).expect("value does not exist"))
+  }
+
+  /// Adds `user` to the `used_by` set of the given value.
+  fn add_user(&mut self, value: Value, user: Value) {
+    self.with_value_data_mut(value, |data| data.used_by.insert(user));
+  }
+
+  /// Removes `user` from the `used_by` set of the given value.
+  fn remove_user(&mut self, value: Value, user: Value) {
+    self.with_value_data_mut(value, |data| data.used_by.remove(&user));
+  }
+
+  /// Adds the given value to (if `add` is `true`) or removes it from the
+  /// users of its callee, if it is a function call and the current data
+  /// flow graph is in a program.
+  pub(in crate::ir) fn update_func_user(&self, value: Value, kind: &ValueKind, add: bool) {
+    let (ValueKind::Call(call), Some(func)) = (kind, self.func) else {
+      return;
+    };
+    let Some(users) = self.func_users.upgrade() else {
+      return;
+    };
+    let mut users = users.write().unwrap();
+    if add {
+      users
+        .entry(call.callee())
+        .or_default()
+        .insert((func, value));
+    } else if let Some(users) = users.get_mut(&call.callee()) {
+      users.remove(&(func, value));
     }
   }
 
   /// Creates a new value in the current data flow graph.
   /// Returns a [`LocalBuilder`] for building the new local value.
-  pub fn new_value(&mut self) -> LocalBuilder {
+  pub fn new_value(&mut self) -> LocalBuilder<'_> {
     LocalBuilder { dfg: self }
   }
 
@@ -68,26 +301,41 @@ impl DataFlowGraph {
   ///
   /// # Panics
   ///
-  /// Panics if the given value data uses unexisted values or basic blocks.
+  /// Panics if the given value data uses unexisted values or basic
+  /// blocks, or fails the checks of the validation level.
   pub(in crate::ir) fn new_value_data(&mut self, data: ValueData) -> Value {
-    let value = Value(next_local_value_id());
+    Self::check(validation::check_value_data(self, &data, self.validation));
+    let key = self.intern_consts.then(|| ConstKey::new(&data)).flatten();
+    if let Some(value) = key.as_ref().and_then(|k| self.consts.get(k)) {
+      return *value;
+    }
+    let value = self.values.next_key();
+    if let Some(key) = key {
+      self.consts.insert(key, value);
+    }
     for v in data.kind().value_uses() {
-      data_mut!(self, v).used_by.insert(value);
+      self.add_user(v, value);
     }
     for bb in data.kind().bb_uses() {
       self.bb_mut(bb).used_by.insert(value);
     }
-    self.values.insert(value, data);
+    self.update_func_user(value, data.kind(), true);
+    self.record_value(MutationOp::NewValue, value, Some(data.kind()));
+    self.values.insert(data);
     value
   }
 
   /// Replaces the given value with a new value.
   /// Returns a [`ReplaceBuilder`] for building the new value.
   ///
+  /// The new value inherits the source location (if the new value does
+  /// not have one) and the attributes of the old value. Attributes of
+  /// the new value take precedence over the old ones.
+  ///
   /// # Panics
   ///
   /// Panics if the given value does not exist.
-  pub fn replace_value_with(&mut self, value: Value) -> ReplaceBuilder {
+  pub fn replace_value_with(&mut self, value: Value) -> ReplaceBuilder<'_> {
     ReplaceBuilder { dfg: self, value }
   }
 
@@ -96,23 +344,78 @@ impl DataFlowGraph {
   ///
   /// # Panics
   ///
-  /// Panics if the given value does not exist.
+  /// Panics if the given value does not exist, or the new value data
+  /// fails the checks of the validation level.
   pub(in crate::ir) fn replace_value_with_data(&mut self, value: Value, mut data: ValueData) {
-    let old = self.values.remove(&value).unwrap();
+    Self::check(validation::check_value_data(self, &data, self.validation));
+    let old = self.values.take(&value).unwrap();
+    self.remove_interned(value, &old);
     for v in old.kind().value_uses() {
-      data_mut!(self, v).used_by.remove(&value);
+      self.remove_user(v, value);
     }
     for bb in old.kind().bb_uses() {
       self.bb_mut(bb).used_by.remove(&value);
     }
+    self.update_func_user(value, old.kind(), false);
     for v in data.kind().value_uses() {
-      data_mut!(self, v).used_by.insert(value);
+      self.add_user(v, value);
     }
     for bb in data.kind().bb_uses() {
       self.bb_mut(bb).used_by.insert(value);
     }
+    self.update_func_user(value, data.kind(), true);
+    self.record_value(MutationOp::ReplaceValue, value, Some(data.kind()));
     data.used_by = old.used_by;
-    self.values.insert(value, data);
+    // preserve source location and attributes of the old value
+    data.span = data.span.or(old.span);
+    let mut attrs = old.attrs;
+    attrs.append(&mut data.attrs);
+    data.attrs = attrs;
+    self.values.restore(value, data);
+  }
+
+  /// Calls `f` with a mutable reference to the data of the given local
+  /// value, and updates the `used_by` set of the operands and the target
+  /// basic blocks and the interned constants if they are changed.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given value does not exist.
+  pub(in crate::ir) fn update_value_data<F>(&mut self, value: Value, f: F)
+  where
+    F: FnOnce(&mut ValueData),
+  {
+    let mut data = self.values.take(&value).expect("`value` does not exist");
+    let old_uses: Vec<_> = data.kind().value_uses().collect();
+    let old_bbs: Vec<_> = data.kind().bb_uses().collect();
+    let old_key = ConstKey::new(&data);
+    self.update_func_user(value, data.kind(), false);
+    f(&mut data);
+    self.update_func_user(value, data.kind(), true);
+    if let Some(key) = old_key {
+      if ConstKey::new(&data).as_ref() != Some(&key) && self.consts.get(&key) == Some(&value) {
+        self.consts.remove(&key);
+      }
+    }
+    let new_uses: Vec<_> = data.kind().value_uses().collect();
+    let new_bbs: Vec<_> = data.kind().bb_uses().collect();
+    self.values.restore(value, data);
+    if old_uses != new_uses {
+      for v in old_uses {
+        self.remove_user(v, value);
+      }
+      for v in new_uses {
+        self.add_user(v, value);
+      }
+    }
+    if old_bbs != new_bbs {
+      for bb in old_bbs {
+        self.bb_mut(bb).used_by.remove(&value);
+      }
+      for bb in new_bbs {
+        self.bb_mut(bb).used_by.insert(value);
+      }
+    }
   }
 
   /// Removes the given value. Returns the corresponding value data.
@@ -122,24 +425,204 @@ impl DataFlowGraph {
   /// Panics if the given value does not exist, or the removed value is
   /// currently used by other values.
   pub fn remove_value(&mut self, value: Value) -> ValueData {
-    let data = self.values.remove(&value).expect("`value` does not exist");
-    assert!(data.used_by.is_empty(), "`value` is used by other values");
+    self.try_remove_value(value).unwrap_or_else(|e| match e {
+      Error::ValueInUse { .. } => panic!("`value` is used by other values"),
+      _ => panic!("`value` does not exist"),
+    })
+  }
+
+  /// Removes the given value. Returns the corresponding value data,
+  /// or an error if the given value does not exist, or is currently
+  /// used by other values. The data flow graph is not changed on error.
+  pub fn try_remove_value(&mut self, value: Value) -> Result<ValueData, Error> {
+    match self.values.get(&value) {
+      None => return Err(Error::ValueNotFound { value }),
+      Some(data) if !data.used_by.is_empty() => return Err(Error::ValueInUse { value }),
+      _ => {}
+    }
+    let data = self.values.remove(&value).unwrap();
+    self.remove_interned(value, &data);
+    self.update_func_user(value, data.kind(), false);
     for v in data.kind().value_uses() {
-      data_mut!(self, v).used_by.remove(&value);
+      self.remove_user(v, value);
     }
     for bb in data.kind().bb_uses() {
       self.bb_mut(bb).used_by.remove(&value);
     }
-    data
+    self.record_value(MutationOp::RemoveValue, value, Some(data.kind()));
+    Ok(data)
+  }
+
+  /// Removes all the given values, which can only be used by each other.
+  ///
+  /// The `used_by` sets of the remaining operands and target basic blocks
+  /// of the removed values are updated.
+  pub(in crate::ir) fn remove_unused_values(&mut self, values: &HashSet<Value>) {
+    for &value in values {
+      let data = self.values.remove(&value).unwrap();
+      if self.validation >= Validation::Fast {
+        assert!(
+          data.used_by.iter().all(|u| values.contains(u)),
+          "removed value {value:?} is used by other values"
+        );
+      }
+      self.remove_interned(value, &data);
+      self.update_func_user(value, data.kind(), false);
+      for v in data.kind().value_uses() {
+        if !values.contains(&v) {
+          self.remove_user(v, value);
+        }
+      }
+      for bb in data.kind().bb_uses() {
+        if let Some(bb) = self.bbs.get_mut(&bb) {
+          bb.used_by.remove(&value);
+        }
+      }
+      self.record_value(MutationOp::RemoveValue, value, Some(data.kind()));
+    }
+  }
+
+  /// Removes the given value from the interned constants.
+  fn remove_interned(&mut self, value: Value, data: &ValueData) {
+    if let Some(key) = ConstKey::new(data) {
+      if self.consts.get(&key) == Some(&value) {
+        self.consts.remove(&key);
+      }
+    }
+  }
+
+  /// Sets the value operand at the given index of the given user value,
+  /// and updates the `used_by` set of both the old and the new operand.
+  /// Returns the old operand.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given user value or the new operand does not exist,
+  /// or the given index is out of range, or the new operand fails the
+  /// checks of the validation level.
+  pub fn set_value_operand(&mut self, user: Value, index: usize, value: Value) -> Value {
+    if self.validation != Validation::Off {
+      let data = self.values.get(&user).expect("`user` does not exist");
+      let old = data.kind().operand(index);
+      Self::check(validation::check_operand(self, old, value, self.validation));
+    }
+    let kind = self
+      .values
+      .get_mut(&user)
+      .expect("`user` does not exist")
+      .kind_mut();
+    let old = kind.set_operand(index, value);
+    let still_used = kind.value_uses().any(|v| v == old);
+    if !still_used {
+      self.remove_user(old, user);
+    }
+    self.add_user(value, user);
+    old
+  }
+
+  /// Replaces the edge at the given index of the given terminator with
+  /// an edge to `target` that passes `args`, and updates the `used_by` set
+  /// of the old and the new arguments and target basic blocks.
+  ///
+  /// Edges are indexed in the same order as [`ValueKind::edges`].
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given terminator or target basic block does not exist,
+  /// the index is out of range, or the arguments do not match the
+  /// parameters of `target`.
+  pub fn rewrite_edge(
+    &mut self,
+    inst: Value,
+    index: usize,
+    target: BasicBlock,
+    args: impl Into<ValueList>,
+  ) {
+    if let Err(e) = self.try_rewrite_edge(inst, index, target, args) {
+      panic!("{e}")
+    }
+  }
+
+  /// Fallible version of [`DataFlowGraph::rewrite_edge`].
+  /// The data flow graph is not changed on error.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the index is out of range.
+  pub fn try_rewrite_edge(
+    &mut self,
+    inst: Value,
+    index: usize,
+    target: BasicBlock,
+    args: impl Into<ValueList>,
+  ) -> Result<(), Error> {
+    let mut kind = self
+      .values
+      .get(&inst)
+      .ok_or(Error::ValueNotFound { value: inst })?
+      .kind()
+      .clone();
+    kind.set_edge_target(index, target);
+    *kind.edge_args_mut(index) = args.into();
+    if let ValueKind::Branch(br) = &kind {
+      if br.true_bb() == br.false_bb() && !(br.true_args().is_empty() && br.false_args().is_empty())
+      {
+        return Err(Error::SameTargetArgs);
+      }
+    }
+    check_bb_arg_types(
+      &self.replace_value_with(inst),
+      "`target`",
+      target,
+      kind.edges()[index].1,
+    )?;
+    self.update_value_data(inst, |data| *data.kind_mut() = kind);
+    Ok(())
+  }
+
+  /// Replaces all uses of the given value with another value, and updates
+  /// the `used_by` set of both values.
+  ///
+  /// Only the users defined in the current data flow graph will be updated,
+  /// so for global values, users in other functions will be left unchanged.
+  ///
+  /// # Panics
+  ///
+  /// Panics if any of the given values does not exist, or the new value
+  /// fails the checks of the validation level.
+  pub fn replace_all_uses_with(&mut self, value: Value, new: Value) {
+    if value == new {
+      return;
+    }
+    Self::check(validation::check_operand(self, value, new, self.validation));
+    let users: Vec<_> = self.with_value_data(value, |data| {
+      data
+        .used_by
+        .iter()
+        .copied()
+        .filter(|u| self.values.contains_key(u))
+        .collect()
+    });
+    for user in users {
+      for i in 0..self.value(user).kind().num_operands() {
+        if self.value(user).kind().operand(i) == value {
+          self.set_value_operand(user, i, new);
+        }
+      }
+    }
   }
 
   /// Sets the name of the given value.
   ///
   /// # Panics
   ///
-  /// Panics if the given value does not exist,
-  /// or the given name (if exists) not starts with `%` or `@`.
+  /// Panics if the given value does not exist, or the given name (if
+  /// exists) is not a valid symbol name, or is used by another local
+  /// value at [`Validation::Full`].
   pub fn set_value_name(&mut self, value: Value, name: Option<String>) {
+    if self.validation == Validation::Full {
+      Self::check(validation::check_name(self, &name, value));
+    }
     self
       .values
       .get_mut(&value)
@@ -147,27 +630,253 @@ impl DataFlowGraph {
       .set_name(name);
   }
 
+  /// Sets the source location of the given value.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given value does not exist.
+  pub fn set_value_span(&mut self, value: Value, span: Option<SourceSpan>) {
+    self
+      .values
+      .get_mut(&value)
+      .expect("`value` does not exist")
+      .set_span(span);
+  }
+
+  /// Sets the attribute `key` of the given value to `attr`.
+  /// Returns the old attribute if exists.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given value does not exist.
+  pub fn set_value_attr(&mut self, value: Value, key: &str, attr: AttrValue) -> Option<AttrValue> {
+    self
+      .values
+      .get_mut(&value)
+      .expect("`value` does not exist")
+      .set_attr(key, attr)
+  }
+
+  /// Removes the attribute `key` of the given value.
+  /// Returns the removed attribute if exists.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given value does not exist.
+  pub fn remove_value_attr(&mut self, value: Value, key: &str) -> Option<AttrValue> {
+    self
+      .values
+      .get_mut(&value)
+      .expect("`value` does not exist")
+      .remove_attr(key)
+  }
+
   /// Returns a reference to the given local value.
   ///
+  /// Same as [`DataFlowGraph::value_data`].
+  ///
   /// # Panics
   ///
   /// Panics if the given local value does not exist.
   pub fn value(&self, value: Value) -> &ValueData {
-    self.values.get(&value).expect("`value` does not exist")
+    self.value_data(value)
+  }
+
+  /// Returns a reference to the given local value,
+  /// you can also write `&dfg[value]`.
+  ///
+  /// Global values are stored in the program, and can not be borrowed
+  /// from the data flow graph. Use [`DataFlowGraph::value_ref`] for
+  /// values which may be global values.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given local value does not exist.
+  pub fn value_data(&self, value: Value) -> &ValueData {
+    match self.values.get(&value) {
+      Some(data) => data,
+      None if self.is_global(value) => {
+        panic!("{value:?} is a global value, use `value_ref` instead")
+      }
+      None => panic!("{value:?} does not exist"),
+    }
+  }
+
+  /// Returns a reference to the given local value or global value.
+  ///
+  /// Local values are borrowed from the data flow graph. Global values
+  /// are looked up in the global value map of the program, and the
+  /// returned guard holds a copy of the data, since the data flow graph
+  /// only holds a weak pointer to the map, which can not be locked by
+  /// the guard. The borrow rules are the same as
+  /// [`DataFlowGraph::global_value_data`].
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given value is neither a local value nor a global
+  /// value of the program.
+  pub fn value_ref(&self, value: Value) -> ValueRef<'_> {
+    match self.values.get(&value) {
+      Some(data) => ValueRef::local(data),
+      None => match self.global_value_data(value) {
+        Some(data) => ValueRef::copied(data),
+        None => panic!("{value:?} does not exist"),
+      },
+    }
+  }
+
+  /// Returns an object that displays the given value in text form,
+  /// for example, `%x = add %#1, 1`.
+  ///
+  /// Local instructions are displayed as their definitions, and other
+  /// values (constants, arguments and global values) are displayed as
+  /// operands. Unnamed values are named by their handles (see [`Value`]),
+  /// so the names are stable across calls, but may be different from
+  /// the names generated by [`KoopaGenerator`](crate::back::KoopaGenerator).
+  /// Function calls show the callee handles instead of the callee names.
+  pub fn display_value(&self, value: Value) -> DisplayValue<'_> {
+    DisplayValue { dfg: self, value }
+  }
+
+  /// Returns a token of the given local value, for detecting whether
+  /// the value has been removed later. See [`ValueToken`] for details.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given local value does not exist.
+  pub fn token(&self, value: Value) -> ValueToken {
+    assert!(self.values.contains_key(&value), "`value` does not exist");
+    ValueToken(value)
+  }
+
+  /// Returns `true` if the value of the given token still exists in the
+  /// current data flow graph, that is, the value has not been removed,
+  /// and the token is not created by other data flow graphs.
+  pub fn is_valid(&self, token: ValueToken) -> bool {
+    self.values.contains_key(&token.value())
   }
 
   /// Returns a reference to the value map.
-  pub fn values(&self) -> &HashMap<Value, ValueData> {
+  pub fn values(&self) -> &Arena<Value, ValueData> {
     &self.values
   }
 
+  /// Returns a copy of the value data of the given global value.
+  ///
+  /// Returns `None` if the given value is not a global value, or the
+  /// data flow graph is not in a program, or the program has been dropped.
+  ///
+  /// This method only borrows the global value map of the program
+  /// immutably during the call, so it is fine to call it while holding
+  /// the guards returned by [`Program::borrow_values`] or
+  /// [`Program::borrow_value`]. No borrow is held after it returns.
+  ///
+  /// [`Program::borrow_values`]: crate::ir::Program::borrow_values
+  /// [`Program::borrow_value`]: crate::ir::Program::borrow_value
+  pub fn global_value_data(&self, value: Value) -> Option<ValueData> {
+    let globals = self.globals.upgrade()?;
+    let data = globals.read().unwrap().get(&value).cloned();
+    data
+  }
+
+  /// Returns `true` if the given value is a global value of the program
+  /// which the current data flow graph belongs to.
+  ///
+  /// Returns `false` if the data flow graph is not in a program, or the
+  /// program has been dropped. The borrow rules are the same as
+  /// [`DataFlowGraph::global_value_data`].
+  pub fn is_global(&self, value: Value) -> bool {
+    value.is_global()
+      && self
+        .globals
+        .upgrade()
+        .is_some_and(|g| g.read().unwrap().contains_key(&value))
+  }
+
+  /// Returns the given value as a checked [`Inst`] handle, or `None` if
+  /// it does not exist or is not a local instruction.
+  pub fn as_inst(&self, value: Value) -> Option<Inst> {
+    let data = self.values.get(&value)?;
+    data.kind().is_local_inst().then_some(Inst::from(value))
+  }
+
+  /// Returns the given value as a checked [`Const`] handle, or `None` if
+  /// it does not exist or is not a constant. The borrow rules are the
+  /// same as [`DataFlowGraph::global_value_data`].
+  pub fn as_const(&self, value: Value) -> Option<Const> {
+    self
+      .try_with_value_data(value, |data| data.kind().is_const())?
+      .then_some(Const::from(value))
+  }
+
+  /// Returns the value of the given integer constant, or `None` if the
+  /// given value is not a local integer constant.
+  pub fn const_int(&self, value: Value) -> Option<i32> {
+    self.values.get(&value)?.kind().as_integer()
+  }
+
   /// Checks if the two given values are equal.
   pub fn value_eq(&self, lhs: Value, rhs: Value) -> bool {
-    self.data_eq(data!(self, lhs), data!(self, rhs))
+    self.data_eq(&self.data_cow(lhs), &self.data_cow(rhs))
   }
 
   /// Checks if the two given value data are equal.
+  ///
+  /// Operands are compared with a work list rather than recursion,
+  /// so deeply nested values can not overflow the stack.
   pub fn data_eq(&self, lhs: &ValueData, rhs: &ValueData) -> bool {
+    if !Self::shallow_eq(lhs, rhs) {
+      return false;
+    }
+    let mut worklist: Vec<_> = lhs
+      .kind()
+      .value_uses()
+      .zip(rhs.kind().value_uses())
+      .collect();
+    while let Some((l, r)) = worklist.pop() {
+      if l == r {
+        continue;
+      }
+      let (l, r) = (self.data_cow(l), self.data_cow(r));
+      if !Self::shallow_eq(&l, &r) {
+        return false;
+      }
+      worklist.extend(l.kind().value_uses().zip(r.kind().value_uses()));
+    }
+    true
+  }
+
+  /// Returns the data of the given local value or global value.
+  ///
+  /// The data of global values are copied, so that the global value map
+  /// will not be locked while comparing values.
+  fn data_cow(&self, value: Value) -> Cow<'_, ValueData> {
+    match self.values.get(&value) {
+      Some(data) => Cow::Borrowed(data),
+      None => Cow::Owned(self.global_value_data(value).expect("value does not exist")),
+    }
+  }
+
+  /// Checks if the two given value data are equal, without comparing
+  /// their operands.
+  fn shallow_eq(lhs: &ValueData, rhs: &ValueData) -> bool {
+    Self::shallow_eq_by(lhs, rhs, |l, r| l == r, |l, r| l == r)
+  }
+
+  /// Checks if the two given value data are equal, without comparing
+  /// their operands. Basic block targets and callees are compared by
+  /// the given functions, so values in different functions or programs
+  /// can also be compared.
+  pub(in crate::ir) fn shallow_eq_by<B, F>(
+    lhs: &ValueData,
+    rhs: &ValueData,
+    bb_eq: B,
+    func_eq: F,
+  ) -> bool
+  where
+    B: Fn(BasicBlock, BasicBlock) -> bool,
+    F: Fn(Function, Function) -> bool,
+  {
     use crate::ir::entities::ValueKind::*;
     macro_rules! return_if {
       ($e:expr) => {
@@ -179,12 +888,13 @@ impl DataFlowGraph {
     return_if!(lhs.ty() != rhs.ty());
     match (lhs.kind(), rhs.kind()) {
       (Integer(l), Integer(r)) => return_if!(l.value() != r.value()),
-      (ZeroInit(_), ZeroInit(_)) => return true,
-      (Undef(_), Undef(_)) => return true,
+      (ZeroInit(_), ZeroInit(_)) => (),
+      (Undef(_), Undef(_)) => (),
       (Aggregate(l), Aggregate(r)) => return_if!(l.elems().len() != r.elems().len()),
+      (Bytes(l), Bytes(r)) => return_if!(l.bytes() != r.bytes()),
       (FuncArgRef(l), FuncArgRef(r)) => return_if!(l.index() != r.index()),
       (BlockArgRef(l), BlockArgRef(r)) => return_if!(l.index() != r.index()),
-      (Alloc(_), Alloc(_)) => return true,
+      (Alloc(_), Alloc(_)) => (),
       (GlobalAlloc(_), GlobalAlloc(_)) => (),
       (Load(_), Load(_)) => (),
       (Store(_), Store(_)) => (),
@@ -193,38 +903,102 @@ impl DataFlowGraph {
       (Binary(l), Binary(r)) => return_if!(l.op() != r.op()),
       (Branch(l), Branch(r)) => {
         return_if!(
-          l.true_bb() != r.true_bb()
-            || l.false_bb() != r.false_bb()
+          !bb_eq(l.true_bb(), r.true_bb())
+            || !bb_eq(l.false_bb(), r.false_bb())
             || l.true_args().len() != r.true_args().len()
             || l.false_args().len() != r.false_args().len()
         )
       }
       (Jump(l), Jump(r)) => {
-        return_if!(l.target() != r.target() || l.args().len() != r.args().len())
+        return_if!(!bb_eq(l.target(), r.target()) || l.args().len() != r.args().len())
       }
       (Call(l), Call(r)) => {
-        return_if!(l.callee() != r.callee() || l.args().len() != r.args().len())
+        return_if!(!func_eq(l.callee(), r.callee()) || l.args().len() != r.args().len())
       }
       (Return(l), Return(r)) => return_if!(l.value().xor(r.value()).is_some()),
+      (Unreachable(_), Unreachable(_)) => (),
       _ => return false,
     }
-    for (lu, ru) in lhs.kind().value_uses().zip(rhs.kind().value_uses()) {
-      return_if!(!self.value_eq(lu, ru));
-    }
     true
   }
 
+  /// Moves all local values and basic blocks to new arenas in the given
+  /// orders, which must contain all of them exactly once, and updates
+  /// all references to them. Returns maps of old handles to new handles.
+  ///
+  /// Global values keep their handles, but their `used_by` sets are
+  /// updated. References outside the data flow graph (like the layout)
+  /// are not updated.
+  pub(in crate::ir) fn renumber(
+    &mut self,
+    values: &[Value],
+    bbs: &[BasicBlock],
+  ) -> (HashMap<Value, Value>, HashMap<BasicBlock, BasicBlock>) {
+    let mut old_values = std::mem::replace(&mut self.values, Arena::new());
+    let value_map: HashMap<_, _> = values
+      .iter()
+      .map(|v| (*v, self.values.insert(old_values.remove(v).unwrap())))
+      .collect();
+    let mut old_bbs = std::mem::replace(&mut self.bbs, Arena::new());
+    let bb_map: HashMap<_, _> = bbs
+      .iter()
+      .map(|bb| (*bb, self.bbs.insert(old_bbs.remove(bb).unwrap())))
+      .collect();
+    if self.validation >= Validation::Fast {
+      assert!(
+        old_values.is_empty() && old_bbs.is_empty(),
+        "values or basic blocks are lost when renumbering"
+      );
+    }
+    let value = |v: &Value| value_map.get(v).copied().unwrap_or(*v);
+    let users = |used_by: &HashSet<Value>| used_by.iter().map(value).collect();
+    for data in self.values.values_mut() {
+      for i in 0..data.kind().num_operands() {
+        let operand = data.kind().operand(i);
+        data.kind_mut().set_operand(i, value(&operand));
+      }
+      for i in 0..data.kind().edges().len() {
+        let target = data.kind().edges()[i].0;
+        data.kind_mut().set_edge_target(i, bb_map[&target]);
+      }
+      data.used_by = users(&data.used_by);
+    }
+    for data in self.bbs.values_mut() {
+      data.params_mut().iter_mut().for_each(|p| *p = value(p));
+      data.used_by = users(&data.used_by);
+    }
+    self.consts.values_mut().for_each(|v| *v = value(v));
+    // remove all old call sites first, since old handles may be reused
+    for (old, new) in &value_map {
+      self.update_func_user(*old, self.values[new].kind(), false);
+    }
+    for new in value_map.values() {
+      self.update_func_user(*new, self.values[new].kind(), true);
+    }
+    if let Some(globals) = self.globals.upgrade() {
+      for data in globals.write().unwrap().values_mut() {
+        data.used_by = users(&data.used_by);
+      }
+    }
+    (value_map, bb_map)
+  }
+
   /// Creates a new basic block in the current data flow graph.
   /// Returns a [`BlockBuilder`] for building the new basic block.
-  pub fn new_bb(&mut self) -> BlockBuilder {
+  pub fn new_bb(&mut self) -> BlockBuilder<'_> {
     BlockBuilder { dfg: self }
   }
 
   /// Creates a new basic block in the current data flow graph.
   /// Returns the handle of the created basic block.
   pub(in crate::ir) fn new_bb_data(&mut self, data: BasicBlockData) -> BasicBlock {
-    let bb = BasicBlock(next_bb_id());
-    self.bbs.insert(bb, data);
+    let bb = self.bbs.insert(data);
+    if let Some(log) = &self.log {
+      log
+        .lock()
+        .unwrap()
+        .record(MutationOp::NewBasicBlock, None, Some(bb), None);
+    }
     bb
   }
 
@@ -240,54 +1014,377 @@ impl DataFlowGraph {
   /// * the parameters of the removed basic block do not exist, or
   ///   are currently used by other values.
   pub fn remove_bb(&mut self, bb: BasicBlock) -> BasicBlockData {
-    let data = self.bbs.remove(&bb).expect("`bb` does not exist");
-    assert!(data.used_by.is_empty(), "`bb` is used by other values");
+    self.try_remove_bb(bb).unwrap_or_else(|e| match e {
+      Error::BasicBlockNotFound { .. } => panic!("`bb` does not exist"),
+      Error::BasicBlockInUse { .. } => panic!("`bb` is used by other values"),
+      Error::ValueNotFound { .. } => panic!("basic block parameter does not exist"),
+      _ => panic!("basic block parameter is used by other values"),
+    })
+  }
+
+  /// Removes the given basic block, also removes all basic block
+  /// parameters. Returns the corresponding basic block data, or an
+  /// error if [`DataFlowGraph::remove_bb`] would panic. The data flow
+  /// graph is not changed on error.
+  pub fn try_remove_bb(&mut self, bb: BasicBlock) -> Result<BasicBlockData, Error> {
+    let data = self.bbs.get(&bb).ok_or(Error::BasicBlockNotFound { bb })?;
+    if !data.used_by.is_empty() {
+      return Err(Error::BasicBlockInUse { bb });
+    }
+    for &p in data.params() {
+      match self.values.get(&p) {
+        None => return Err(Error::ValueNotFound { value: p }),
+        Some(param) if !param.used_by.is_empty() => return Err(Error::ValueInUse { value: p }),
+        _ => {}
+      }
+    }
+    let data = self.bbs.remove(&bb).unwrap();
     for p in data.params() {
-      let param = self
-        .values
-        .remove(p)
-        .expect("basic block parameter does not exist");
-      assert!(
-        param.used_by.is_empty(),
-        "basic block parameter is used by other values"
-      );
+      let param = self.values.remove(p).unwrap();
+      self.record_value(MutationOp::RemoveValue, *p, Some(param.kind()));
+    }
+    if let Some(log) = &self.log {
+      log
+        .lock()
+        .unwrap()
+        .record(MutationOp::RemoveBasicBlock, None, Some(bb), None);
+    }
+    Ok(data)
+  }
+
+  /// Replaces all uses of the given basic block with another basic block,
+  /// i.e. retargets all branches and jumps from `old` to `new`, and
+  /// updates the `used_by` set of both basic blocks.
+  ///
+  /// Arguments passed to `old` are passed to `new` unchanged.
+  ///
+  /// # Panics
+  ///
+  /// Panics if any of the given basic blocks does not exist, or the
+  /// parameter types of `new` are different from the ones of `old`.
+  pub fn replace_bb_with(&mut self, old: BasicBlock, new: BasicBlock) {
+    if old == new {
+      return;
+    }
+    let param_tys = |dfg: &Self, bb| -> Vec<_> {
+      dfg
+        .bb(bb)
+        .params()
+        .iter()
+        .map(|p| dfg.value(*p).ty().clone())
+        .collect()
+    };
+    assert!(
+      param_tys(self, old) == param_tys(self, new),
+      "parameter types of `new` are different from the ones of `old`"
+    );
+    let users: Vec<_> = self.bb(old).used_by().iter().copied().collect();
+    for user in users {
+      self.update_value_data(user, |data| {
+        let kind = data.kind_mut();
+        for i in 0..kind.edges().len() {
+          if kind.edges()[i].0 == old {
+            kind.set_edge_target(i, new);
+          }
+        }
+      });
+    }
+  }
+
+  /// Appends a new parameter of type `ty` to the given basic block.
+  /// Returns the [`BlockArgRef`] value of the new parameter.
+  ///
+  /// An `undef` of type `ty` is appended to the argument list of every
+  /// branch or jump that targets the given basic block.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given basic block does not exist,
+  /// or `ty` is a unit type.
+  pub fn add_bb_param(&mut self, bb: BasicBlock, ty: Type) -> Value {
+    assert!(!ty.is_unit(), "parameter type must not be `unit`");
+    let index = self.bb(bb).params().len();
+    let param = self.new_value_data(BlockArgRef::new_data(index, ty.clone()));
+    self.bb_mut(bb).params_mut().push(param);
+    // fix up the argument lists of all users
+    let users: Vec<_> = self.bb(bb).used_by().iter().copied().collect();
+    if !users.is_empty() {
+      let undef = self.new_value().undef(ty);
+      for user in users {
+        let kind = self.values.get_mut(&user).unwrap().kind_mut();
+        for args in target_args_mut(kind, bb) {
+          args.push(undef);
+        }
+        self.values.get_mut(&undef).unwrap().used_by.insert(user);
+      }
+    }
+    param
+  }
+
+  /// Removes the parameter at the given index from the given basic block,
+  /// and renumbers the remaining parameters. Returns the value data of
+  /// the removed parameter.
+  ///
+  /// The corresponding argument is removed from the argument list of
+  /// every branch or jump that targets the given basic block.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given basic block does not exist, the given index is
+  /// out of range, or the removed parameter is currently used by other
+  /// values.
+  pub fn remove_bb_param(&mut self, bb: BasicBlock, index: usize) -> ValueData {
+    let params = self.bb(bb).params();
+    assert!(
+      index < params.len(),
+      "parameter index {} out of range ({} parameters)",
+      index,
+      params.len()
+    );
+    let param = params[index];
+    assert!(
+      self.value(param).used_by().is_empty(),
+      "basic block parameter is used by other values"
+    );
+    self.bb_mut(bb).params_mut().remove(index);
+    let data = self.remove_value(param);
+    // renumber the remaining parameters
+    for p in &self.bbs[&bb].params()[index..] {
+      match self.values.get_mut(p).unwrap().kind_mut() {
+        ValueKind::BlockArgRef(arg) => *arg.index_mut() -= 1,
+        _ => panic!("invalid basic block parameter"),
+      }
+    }
+    // fix up the argument lists of all users
+    let users: Vec<_> = self.bb(bb).used_by().iter().copied().collect();
+    for user in users {
+      let kind = self.values.get_mut(&user).unwrap().kind_mut();
+      let removed: Vec<_> = target_args_mut(kind, bb)
+        .into_iter()
+        .map(|args| args.remove(index))
+        .collect();
+      for arg in removed {
+        if !self.value(user).kind().value_uses().any(|v| v == arg) {
+          self.remove_user(arg, user);
+        }
+      }
     }
     data
   }
 
-  /// Returns a reference to the given basic block.
+  /// Returns a reference to the given basic block,
+  /// you can also write `&dfg[bb]`.
   ///
   /// # Panics
   ///
   /// Panics if the given basic block does not exist.
   pub fn bb(&self, bb: BasicBlock) -> &BasicBlockData {
-    self.bbs.get(&bb).expect("`bb` does not exist")
+    match self.bbs.get(&bb) {
+      Some(data) => data,
+      None => panic!("{bb:?} does not exist"),
+    }
   }
 
-  /// Returns a mutable reference to the given basic block.
+  /// Returns a mutable reference to the given basic block,
+  /// you can also write `&mut dfg[bb]`.
   ///
   /// # Panics
   ///
   /// Panics if the given basic block does not exist.
   pub fn bb_mut(&mut self, bb: BasicBlock) -> &mut BasicBlockData {
-    self.bbs.get_mut(&bb).expect("`bb` does not exist")
+    match self.bbs.get_mut(&bb) {
+      Some(data) => data,
+      None => panic!("{bb:?} does not exist"),
+    }
   }
 
   /// Returns a reference to the basic block map.
-  pub fn bbs(&self) -> &HashMap<BasicBlock, BasicBlockData> {
+  pub fn bbs(&self) -> &Arena<BasicBlock, BasicBlockData> {
     &self.bbs
   }
 
   /// Returns a mutable reference to the basic block map.
-  pub fn bbs_mut(&mut self) -> &mut HashMap<BasicBlock, BasicBlockData> {
+  pub fn bbs_mut(&mut self) -> &mut Arena<BasicBlock, BasicBlockData> {
     &mut self.bbs
   }
 }
 
+/// Displays a value in text form,
+/// returned by [`DataFlowGraph::display_value`].
+pub struct DisplayValue<'a> {
+  dfg: &'a DataFlowGraph,
+  value: Value,
+}
+
+impl fmt::Display for DisplayValue<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    crate::back::koopa::fmt_value(f, self.dfg, self.value)
+  }
+}
+
+impl Index<Value> for DataFlowGraph {
+  type Output = ValueData;
+
+  /// Returns a reference to the given local value, same as
+  /// [`DataFlowGraph::value_data`].
+  ///
+  /// Global values can not be indexed, since the reference can not
+  /// borrow the global value map of the program. Use
+  /// [`DataFlowGraph::value_ref`] to read global values.
+  ///
+  /// There is no `IndexMut` for values, because modifying value data
+  /// directly may break the use-define chains. Use methods like
+  /// [`DataFlowGraph::replace_value_with`] instead.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given local value does not exist.
+  fn index(&self, value: Value) -> &ValueData {
+    self.value_data(value)
+  }
+}
+
+impl Index<BasicBlock> for DataFlowGraph {
+  type Output = BasicBlockData;
+
+  /// Returns a reference to the given basic block.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given basic block does not exist.
+  fn index(&self, bb: BasicBlock) -> &BasicBlockData {
+    self.bb(bb)
+  }
+}
+
+impl IndexMut<BasicBlock> for DataFlowGraph {
+  /// Returns a mutable reference to the given basic block.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given basic block does not exist.
+  fn index_mut(&mut self, bb: BasicBlock) -> &mut BasicBlockData {
+    self.bb_mut(bb)
+  }
+}
+
+/// Clones the expression rooted at the given value in data flow graph
+/// `src` into data flow graph `dst`, returns the cloned root.
+///
+/// Constants and pure instructions (binary operations and pointer
+/// calculations) are cloned together with their operands, see
+/// [`try_clone_expr`].
+///
+/// # Panics
+///
+/// Panics if the expression can not be cloned.
+pub fn clone_expr(
+  src: &DataFlowGraph,
+  dst: &mut DataFlowGraph,
+  root: Value,
+  map: &mut HashMap<Value, Value>,
+) -> Value {
+  try_clone_expr(src, dst, root, map).unwrap_or_else(|e| panic!("{e}"))
+}
+
+/// Clones the expression rooted at the given value in data flow graph
+/// `src` into data flow graph `dst`, returns the cloned root.
+///
+/// `map` maps values in `src` to values in `dst`. Values in the map are
+/// reused instead of being cloned, and all cloned values are added to
+/// the map, so values shared by several users are only cloned once, and
+/// the cloned expression is a DAG as well. Global values are mapped to
+/// themselves. Other values, like parameters or results of calls, must
+/// be mapped by the caller.
+///
+/// The cloned values keep their names, attributes and source
+/// locations, but not the `used_by` sets. The cloned instructions are
+/// not in the layout, they can be found from the new entries of `map`.
+///
+/// Returns [`Error::NotCloneable`] if the expression contains a value
+/// that is not in the map, and is neither a constant nor a pure
+/// instruction, or [`Error::ValueNotFound`] if the expression contains
+/// a value that does not exist. Nothing is cloned in this case.
+pub fn try_clone_expr(
+  src: &DataFlowGraph,
+  dst: &mut DataFlowGraph,
+  root: Value,
+  map: &mut HashMap<Value, Value>,
+) -> Result<Value, Error> {
+  // collect the values to be cloned in post order, using an explicit
+  // stack, so long chains of operands will not overflow the stack
+  let mut order = Vec::new();
+  let mut visited = HashSet::new();
+  let mut stack = vec![(root, false)];
+  while let Some((value, expanded)) = stack.pop() {
+    if expanded {
+      order.push(value);
+      continue;
+    }
+    if value.is_global() || map.contains_key(&value) || !visited.insert(value) {
+      continue;
+    }
+    let data = src
+      .values()
+      .get(&value)
+      .ok_or(Error::ValueNotFound { value })?;
+    let cloneable = matches!(
+      data.kind(),
+      ValueKind::Integer(_)
+        | ValueKind::ZeroInit(_)
+        | ValueKind::Undef(_)
+        | ValueKind::Aggregate(_)
+        | ValueKind::Bytes(_)
+        | ValueKind::GetPtr(_)
+        | ValueKind::GetElemPtr(_)
+        | ValueKind::Binary(_)
+    );
+    if !cloneable {
+      return Err(Error::NotCloneable { value });
+    }
+    stack.push((value, true));
+    stack.extend(data.kind().value_uses().map(|v| (v, false)));
+  }
+  // clone operands before their users
+  for value in order {
+    let mut data = src.value(value).clone();
+    let kind = data.kind_mut();
+    for i in 0..kind.num_operands() {
+      if let Some(v) = map.get(&kind.operand(i)) {
+        kind.set_operand(i, *v);
+      }
+    }
+    let cloned = dst.new_value().raw(data);
+    map.insert(value, cloned);
+  }
+  Ok(map.get(&root).copied().unwrap_or(root))
+}
+
+/// Returns mutable references to all argument lists of the given
+/// terminator that pass arguments to the given basic block.
+fn target_args_mut(kind: &mut ValueKind, bb: BasicBlock) -> Vec<&mut ValueList> {
+  match kind {
+    ValueKind::Branch(br) => {
+      let (tbb, fbb) = (br.true_bb(), br.false_bb());
+      let (targs, fargs) = br.args_mut();
+      let mut lists = Vec::new();
+      if tbb == bb {
+        lists.push(targs);
+      }
+      if fbb == bb {
+        lists.push(fargs);
+      }
+      lists
+    }
+    ValueKind::Jump(jump) if jump.target() == bb => vec![jump.args_mut()],
+    _ => Vec::new(),
+  }
+}
+
 #[cfg(test)]
 mod test {
   use crate::ir::builder_traits::*;
-  use crate::ir::{BinaryOp, FunctionData, Program, Type};
+  use crate::ir::{AttrValue, BinaryOp, Error, FunctionData, Program, Type, ValueKind};
 
   #[test]
   fn value_eq() {
@@ -315,4 +1412,389 @@ mod test {
     let sub2 = func.dfg_mut().new_value().binary(BinaryOp::Sub, add2, int2);
     assert!(!func.dfg().value_eq(sub1, sub2));
   }
+
+  #[test]
+  fn clone_expr() {
+    use super::{clone_expr, try_clone_expr};
+    use std::collections::HashMap;
+
+    let mut program = Program::new();
+    let zero = program.new_value().zero_init(Type::get_i32());
+    let g = program.new_value().global_alloc(zero);
+    let src = program.new_func(FunctionData::new(
+      "@src".into(),
+      vec![Type::get_i32()],
+      Type::get_i32(),
+    ));
+    // build expressions in `@src`
+    let data = program.func_mut(src);
+    let p = data.params()[0];
+    let dfg = data.dfg_mut();
+    let one = dfg.new_value().integer(1);
+    let x = dfg.new_value().binary(BinaryOp::Add, p, one);
+    let y = dfg.new_value().binary(BinaryOp::Mul, x, x);
+    let inner = dfg.new_value().aggregate(vec![one, one]);
+    let outer = dfg.new_value().aggregate(vec![inner, inner]);
+    let load = dfg.new_value().load(g);
+    let z = dfg.new_value().binary(BinaryOp::Sub, y, load);
+    let mut chain = p;
+    for _ in 0..100_000 {
+      chain = dfg.new_value().binary(BinaryOp::Add, chain, one);
+    }
+    // clone them into another function
+    let src_data = program.func(src);
+    let mut dst_data = FunctionData::new("@dst".into(), vec![Type::get_i32()], Type::get_i32());
+    let mut map = HashMap::from([(p, dst_data.params()[0])]);
+    let dst_dfg = dst_data.dfg_mut();
+    let cloned = clone_expr(src_data.dfg(), dst_dfg, y, &mut map);
+    let ValueKind::Binary(bin) = dst_dfg.value(cloned).kind() else {
+      panic!("expected a binary operation");
+    };
+    // `%x` is cloned once, the constant `1` is shared
+    assert_eq!(bin.lhs(), bin.rhs());
+    assert_eq!(map[&x], bin.lhs());
+    assert_eq!(dst_dfg.value(map[&x]).used_by().len(), 1);
+    let cloned = clone_expr(src_data.dfg(), dst_dfg, outer, &mut map);
+    let ValueKind::Aggregate(agg) = dst_dfg.value(cloned).kind() else {
+      panic!("expected an aggregate");
+    };
+    assert_eq!(agg.elems()[0], agg.elems()[1]);
+    assert_eq!(agg.elems()[0], map[&inner]);
+    assert_eq!(dst_dfg.value(map[&one]).used_by().len(), 2);
+    // globals are mapped to themselves
+    assert_eq!(clone_expr(src_data.dfg(), dst_dfg, g, &mut map), g);
+    // loads can not be cloned, and nothing is cloned
+    let len = dst_dfg.values().len();
+    assert_eq!(
+      try_clone_expr(src_data.dfg(), dst_dfg, z, &mut map),
+      Err(Error::NotCloneable { value: load })
+    );
+    assert_eq!(dst_dfg.values().len(), len);
+    // long chains
+    clone_expr(src_data.dfg(), dst_dfg, chain, &mut map);
+    assert_eq!(dst_dfg.values().len(), len + 100_000);
+  }
+
+  #[test]
+  fn set_operand() {
+    let mut program = Program::new();
+    let func = program.new_func(FunctionData::new("@test".into(), vec![], Type::get_unit()));
+    let func = program.func_mut(func);
+    let one = func.dfg_mut().new_value().integer(1);
+    let two = func.dfg_mut().new_value().integer(2);
+    let add = func.dfg_mut().new_value().binary(BinaryOp::Add, one, one);
+    assert_eq!(func.dfg().value(add).kind().num_operands(), 2);
+    // `one` is still used by the rhs operand
+    assert_eq!(func.dfg_mut().set_value_operand(add, 0, two), one);
+    assert_eq!(func.dfg().value(add).kind().operand(0), two);
+    assert!(func.dfg().value(one).used_by().contains(&add));
+    assert!(func.dfg().value(two).used_by().contains(&add));
+    // replace all uses of `one` with `two`
+    func.dfg_mut().replace_all_uses_with(one, two);
+    assert_eq!(func.dfg().value(add).kind().operand(1), two);
+    assert!(func.dfg().value(one).used_by().is_empty());
+    func.dfg_mut().remove_value(one);
+  }
+
+  #[test]
+  fn replace_value_attrs() {
+    let mut program = Program::new();
+    let func = program.new_func(FunctionData::new("@test".into(), vec![], Type::get_unit()));
+    let func = program.func_mut(func);
+    let one = func.dfg_mut().new_value().integer(1);
+    let add = func.dfg_mut().new_value().binary(BinaryOp::Add, one, one);
+    func.dfg_mut().set_value_attr(add, "a", 1.into());
+    func.dfg_mut().set_value_attr(add, "b", "x".into());
+    // attributes of the new data take precedence
+    let mut data = func.dfg().value(add).clone();
+    data.remove_attr("a");
+    data.set_attr("b", true.into());
+    func.dfg_mut().replace_value_with(add).raw(data);
+    let data = func.dfg().value(add);
+    assert_eq!(data.attr("a"), Some(&AttrValue::Int(1)));
+    assert_eq!(data.attr("b"), Some(&AttrValue::Bool(true)));
+  }
+
+  #[test]
+  #[should_panic(expected = "operand index 2 out of range (2 operands)")]
+  fn set_operand_out_of_range() {
+    let mut program = Program::new();
+    let func = program.new_func(FunctionData::new("@test".into(), vec![], Type::get_unit()));
+    let func = program.func_mut(func);
+    let one = func.dfg_mut().new_value().integer(1);
+    let add = func.dfg_mut().new_value().binary(BinaryOp::Add, one, one);
+    func.dfg_mut().set_value_operand(add, 2, one);
+  }
+
+  #[test]
+  fn bb_params() {
+    let mut program = Program::new();
+    let func = program.new_func(FunctionData::new("@test".into(), vec![], Type::get_unit()));
+    let func = program.func_mut(func);
+    let bb = func
+      .dfg_mut()
+      .new_bb()
+      .basic_block_with_params(None, vec![Type::get_i32(), Type::get_i32()]);
+    let one = func.dfg_mut().new_value().integer(1);
+    let two = func.dfg_mut().new_value().integer(2);
+    let jump = func
+      .dfg_mut()
+      .new_value()
+      .jump_with_args(bb, vec![one, two]);
+    let p1 = func.dfg().bb(bb).params()[1];
+    // remove the first parameter
+    func.remove_bb_param(bb, 0);
+    assert_eq!(func.dfg().bb(bb).params(), [p1]);
+    let index = func
+      .dfg()
+      .value(p1)
+      .kind()
+      .as_block_arg_ref()
+      .unwrap()
+      .index();
+    assert_eq!(index, 0);
+    let args = func.dfg().value(jump).kind().as_jump().unwrap().args();
+    assert_eq!(args, [two]);
+    assert!(func.dfg().value(one).used_by().is_empty());
+    // append a new parameter
+    let p2 = func.add_bb_param(bb, Type::get_i32());
+    assert_eq!(func.dfg().bb(bb).params(), [p1, p2]);
+    let index = func
+      .dfg()
+      .value(p2)
+      .kind()
+      .as_block_arg_ref()
+      .unwrap()
+      .index();
+    assert_eq!(index, 1);
+    let args = func.dfg().value(jump).kind().as_jump().unwrap().args();
+    assert_eq!(args.len(), 2);
+    let undef = func.dfg().value(args[1]);
+    assert!(matches!(undef.kind(), ValueKind::Undef(_)));
+    assert!(undef.used_by().contains(&jump));
+  }
+
+  #[test]
+  fn rewrite_edge() {
+    let mut program = Program::new();
+    let func = program.new_func(FunctionData::new("@test".into(), vec![], Type::get_unit()));
+    let dfg = program.func_mut(func).dfg_mut();
+    let a = dfg.new_bb().basic_block(None);
+    let b = dfg
+      .new_bb()
+      .basic_block_with_params(None, vec![Type::get_i32()]);
+    let c = dfg
+      .new_bb()
+      .basic_block_with_params(None, vec![Type::get_i32()]);
+    let one = dfg.new_value().integer(1);
+    let two = dfg.new_value().integer(2);
+    let br = dfg
+      .new_value()
+      .branch_with_args(one, a, b, vec![], vec![one]);
+    let edges = dfg.value(br).kind().edges();
+    assert_eq!(edges, [(a, &[][..]), (b, &[one][..])]);
+    // retarget the false edge from `%b` to `%c`
+    dfg.rewrite_edge(br, 1, c, vec![two]);
+    assert_eq!(dfg.value(br).kind().edges()[1], (c, &[two][..]));
+    assert!(dfg.bb(b).used_by().is_empty());
+    assert!(dfg.bb(c).used_by().contains(&br));
+    // `one` is still used as the condition
+    assert!(dfg.value(one).used_by().contains(&br));
+    assert!(dfg.value(two).used_by().contains(&br));
+    // invalid edges
+    assert_eq!(
+      dfg.try_rewrite_edge(br, 0, b, vec![]),
+      Err(Error::ArgMismatch { target: "`target`" })
+    );
+    assert_eq!(
+      dfg.try_rewrite_edge(br, 0, c, vec![one]),
+      Err(Error::SameTargetArgs)
+    );
+    assert_eq!(dfg.value(br).kind().edges()[0], (a, &[][..]));
+    // returns have no edges
+    let ret = dfg.new_value().ret(None);
+    assert!(dfg.value(ret).kind().edges().is_empty());
+  }
+
+  #[test]
+  fn global_value_data() {
+    let mut program = Program::new();
+    let zero = program.new_value().zero_init(Type::get_i32());
+    let global = program.new_value().global_alloc(zero);
+    let func = program.new_func(FunctionData::new("@test".into(), vec![], Type::get_unit()));
+    let one = program.func_mut(func).dfg_mut().new_value().integer(1);
+    let values = program.borrow_values();
+    let dfg = program.func(func).dfg();
+    assert!(dfg.is_global(global) && !dfg.is_global(one));
+    let data = dfg.global_value_data(global).unwrap();
+    assert_eq!(data.ty(), &Type::get_pointer(Type::get_i32()));
+    assert!(dfg.global_value_data(one).is_none());
+    drop(values);
+    // the program has been dropped
+    let func = program.remove_func(func).unwrap();
+    drop(program);
+    assert!(!func.dfg().is_global(global));
+    assert!(func.dfg().global_value_data(global).is_none());
+  }
+
+  #[test]
+  fn index() {
+    let mut program = Program::new();
+    let func = program.new_func(FunctionData::new("@test".into(), vec![], Type::get_unit()));
+    let dfg = program.func_mut(func).dfg_mut();
+    let one = dfg.new_value().integer(1);
+    let bb = dfg.new_bb().basic_block(Some("%entry".into()));
+    dfg[bb].set_name(Some("%bb".into()));
+    assert_eq!(dfg[one].ty(), &Type::get_i32());
+    assert!(std::ptr::eq(&dfg[one], dfg.value_data(one)));
+    assert_eq!(dfg[bb].name().as_deref(), Some("%bb"));
+  }
+
+  #[test]
+  #[should_panic(expected = "is a global value, use `value_ref` instead")]
+  fn index_global() {
+    let mut program = Program::new();
+    let zero = program.new_value().zero_init(Type::get_i32());
+    let global = program.new_value().global_alloc(zero);
+    let func = program.new_func(FunctionData::new("@test".into(), vec![], Type::get_unit()));
+    let _ = &program.func(func).dfg()[global];
+  }
+
+  #[test]
+  fn value_ref() {
+    let mut program = Program::new();
+    let zero = program.new_value().zero_init(Type::get_i32());
+    let global = program.new_value().global_alloc(zero);
+    let func = program.new_func(FunctionData::new("@test".into(), vec![], Type::get_unit()));
+    let one = program.func_mut(func).dfg_mut().new_value().integer(1);
+    let globals = program.values.clone();
+    let dfg = program.func(func).dfg();
+    assert!(std::ptr::eq(&*dfg.value_ref(one), &dfg[one]));
+    let global_ref = dfg.value_ref(global);
+    assert_eq!(global_ref.ty(), &Type::get_pointer(Type::get_i32()));
+    // the global value map is not locked by the guard
+    drop(globals.write().unwrap());
+    assert!(global_ref.name().is_none());
+  }
+
+  #[test]
+  fn display_value() {
+    let mut program = Program::new();
+    let zero = program.new_value().zero_init(Type::get_i32());
+    let global = program.new_value().global_alloc(zero);
+    program.set_value_name(global, Some("@g".into()));
+    let func = program.new_func(FunctionData::new("@test".into(), vec![], Type::get_unit()));
+    let dfg = program.func_mut(func).dfg_mut();
+    let load = dfg.new_value().load(global);
+    let one = dfg.new_value().integer(1);
+    let add = dfg.new_value().binary(BinaryOp::Add, load, one);
+    dfg.set_value_name(add, Some("%y".into()));
+    assert_eq!(dfg.display_value(load).to_string(), "%#0 = load @g");
+    assert_eq!(dfg.display_value(add).to_string(), "%y = add %#0, 1");
+    assert_eq!(dfg.display_value(one).to_string(), "1");
+    assert_eq!(dfg.display_value(global).to_string(), "@g");
+    assert_eq!(format!("{:?}", load), "Value(#0)");
+    assert!(format!("{:?}", global).starts_with("Value(#g"));
+    assert_eq!(
+      format!("{:?}", dfg[add]),
+      r#"ValueData { name: Some("%y"), ty: i32, kind: Binary(add), operands: [Value(#0), Value(#1)], used_by: 0 }"#
+    );
+    // slots of removed values are reused with a new generation
+    dfg.remove_value(add);
+    let sub = dfg.new_value().binary(BinaryOp::Sub, load, one);
+    assert_eq!(format!("{:?}", sub), "Value(#2.1)");
+  }
+
+  #[test]
+  fn intern_consts() {
+    let mut program = Program::new();
+    let func = program.new_func(FunctionData::new("@test".into(), vec![], Type::get_unit()));
+    let dfg = program.func_mut(func).dfg_mut();
+    let zero = dfg.new_value().integer(0);
+    assert_ne!(zero, dfg.new_value().integer(0));
+    dfg.set_intern_consts(true);
+    let zero = dfg.new_value().integer(0);
+    assert_eq!(zero, dfg.new_value().integer(0));
+    assert_ne!(zero, dfg.new_value().integer(1));
+    let undef = dfg.new_value().undef(Type::get_i32());
+    assert_eq!(undef, dfg.new_value().undef(Type::get_i32()));
+    let ptr_ty = Type::get_pointer(Type::get_i32());
+    assert_ne!(undef, dfg.new_value().undef(ptr_ty));
+    // users are accumulated on the shared constant
+    let add = dfg.new_value().binary(BinaryOp::Add, zero, undef);
+    let zero2 = dfg.new_value().integer(0);
+    let sub = dfg.new_value().binary(BinaryOp::Sub, zero2, zero);
+    assert_eq!(dfg.value(zero).used_by().len(), 2);
+    dfg.remove_value(add);
+    dfg.remove_value(sub);
+    // removed constants are no longer interned
+    dfg.remove_value(zero);
+    assert_ne!(zero, dfg.new_value().integer(0));
+  }
+
+  #[test]
+  #[should_panic(expected = "`value` is used by other values")]
+  fn remove_interned_const_in_use() {
+    let mut program = Program::new();
+    let func = program.new_func(FunctionData::new("@test".into(), vec![], Type::get_unit()));
+    let dfg = program.func_mut(func).dfg_mut();
+    dfg.set_intern_consts(true);
+    let one = dfg.new_value().integer(1);
+    let add = dfg.new_value().binary(BinaryOp::Add, one, one);
+    let one2 = dfg.new_value().integer(1);
+    dfg.new_value().binary(BinaryOp::Sub, one2, one);
+    dfg.remove_value(add);
+    dfg.remove_value(one);
+  }
+
+  #[test]
+  fn global_users() {
+    let mut program = Program::new();
+    let zero = program.new_value().zero_init(Type::get_i32());
+    let global = program.new_value().global_alloc(zero);
+    let func = program.new_func(FunctionData::new("@test".into(), vec![], Type::get_unit()));
+    let globals = program.values.clone();
+    let dfg = program.func_mut(func).dfg_mut();
+    // local users of global values are recorded in the global value map
+    let load1 = dfg.new_value().load(global);
+    let load2 = dfg.new_value().load(global);
+    assert!(dfg.value_eq(load1, load2));
+    assert_eq!(globals.read().unwrap()[&global].used_by().len(), 2);
+    // operations on local values do not borrow the global value map
+    let guard = globals.write().unwrap();
+    let one = dfg.new_value().integer(1);
+    let add = dfg.new_value().binary(BinaryOp::Add, load1, one);
+    dfg.replace_all_uses_with(one, load2);
+    assert_eq!(dfg.value(add).kind().operand(1), load2);
+    dfg.remove_value(add);
+    drop(guard);
+    dfg.remove_value(load1);
+    assert_eq!(globals.read().unwrap()[&global].used_by().len(), 1);
+  }
+
+  #[test]
+  fn stale_tokens() {
+    let mut func = FunctionData::new("@test".into(), vec![], Type::get_unit());
+    let dfg = func.dfg_mut();
+    let one = dfg.new_value().integer(1);
+    let token = dfg.token(one);
+    // replacing keeps the handle
+    dfg.replace_value_with(one).integer(2);
+    assert!(dfg.is_valid(token));
+    assert_eq!(dfg.token(one), token);
+    // the slot of the removed value is reused by a different value
+    dfg.remove_value(one);
+    assert!(!dfg.is_valid(token));
+    let three = dfg.new_value().integer(3);
+    let new_token = dfg.token(three);
+    assert_eq!(new_token.index(), token.index());
+    assert_eq!(new_token.generation(), token.generation() + 1);
+    assert_ne!(three, one);
+    assert!(!dfg.is_valid(token) && dfg.is_valid(new_token));
+    assert_eq!(format!("{token} {new_token}"), "#0 #0.1");
+    // compacting invalidates all handles
+    let (values, _) = func.compact();
+    assert!(!func.dfg().is_valid(new_token));
+    assert!(func.dfg().is_valid(func.dfg().token(values[&three])));
+  }
 }
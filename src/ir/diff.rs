@@ -0,0 +1,733 @@
+//! Structural diffs between programs ([`ProgramDiff`]) related
+//! implementations.
+//!
+//! Diffs are computed on the in-memory form Koopa IR rather than the
+//! text form, so renumbered values (like `%0` becoming `%1`) and
+//! renumbered handles do not show up as changes.
+//!
+//! # Example
+//!
+//! ```
+//! use koopa::front::Driver;
+//! use koopa::ir::diff::*;
+//!
+//! let parse = |src: &str| Driver::from(src).generate_program().unwrap();
+//! let before = parse(r#"
+//!   fun @f(@x: i32): i32 {
+//!   %entry:
+//!     %0 = add @x, 1
+//!     %1 = mul %0, 2
+//!     ret %1
+//!   }
+//! "#);
+//! let after = parse(r#"
+//!   fun @f(@x: i32): i32 {
+//!   %entry:
+//!     %t = add @x, 1
+//!     %0 = shl %t, 1
+//!     ret %0
+//!   }
+//! "#);
+//!
+//! let diff = diff_programs(&before, &after);
+//! assert_eq!(diff.to_string(), r#"--- @f
+//! +++ @f
+//!  %entry:
+//! -  %1 = mul %0, 2
+//! +  %0 = shl %t, 1
+//! -  ret %1
+//! +  ret %0
+//! "#);
+//! ```
+
+use crate::back::koopa::{Config, Visitor};
+use crate::back::{FormatVersion, Generator};
+use crate::ir::dfg::DataFlowGraph;
+use crate::ir::{BasicBlock, Function, FunctionData, Program, Value, ValueKind};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Computes the structural diff from program `before` to program `after`.
+///
+/// Functions are matched by names. Basic blocks in matched functions
+/// are matched by names, and the remaining unnamed basic blocks are
+/// matched by their positions in the layout. Instructions in matched
+/// basic blocks are matched by the longest common subsequence, in which
+/// two instructions are equal if they are structurally equal (like
+/// [`DataFlowGraph::value_eq`]). When comparing instructions, function
+/// parameters are compared by their positions, basic block parameters
+/// by their positions in matched basic blocks, global allocations by
+/// their names, and other operands by their structures.
+///
+/// Global values except the ones used by functions are not compared.
+pub fn diff_programs(before: &Program, after: &Program) -> ProgramDiff {
+  let ((_, texts_l), (_, texts_r)) = (Text::new(before), Text::new(after));
+  let globals = match_globals(before, after);
+  let funcs_r: HashMap<_, _> = after
+    .func_layout()
+    .iter()
+    .map(|f| (after.func(*f).name(), *f))
+    .collect();
+  let mut matched = HashSet::new();
+  let mut funcs = Vec::new();
+  for &l in before.func_layout() {
+    let name = before.func(l).name();
+    let diff = match funcs_r.get(name) {
+      Some(&r) => {
+        matched.insert(r);
+        let differ = FunctionDiffer {
+          programs: (before, after),
+          funcs: (before.func(l), after.func(r)),
+          texts: (&texts_l[&l], &texts_r[&r]),
+          bbs: HashMap::new(),
+          leaves: globals.clone(),
+        };
+        differ.diff()
+      }
+      None => Some(FunctionDiff::Removed {
+        name: name.into(),
+        lines: texts_l[&l].lines.clone(),
+      }),
+    };
+    funcs.extend(diff);
+  }
+  for &r in after.func_layout() {
+    if !matched.contains(&r) {
+      funcs.push(FunctionDiff::Added {
+        name: after.func(r).name().into(),
+        lines: texts_r[&r].lines.clone(),
+      });
+    }
+  }
+  ProgramDiff { funcs }
+}
+
+/// Structural diff between two programs, returned by [`diff_programs`].
+///
+/// The diff can be displayed as a unified-diff-like text.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProgramDiff {
+  /// Diffs of the changed, removed and added functions. Changed and
+  /// removed functions are in the order of the function layout of the
+  /// old program, and followed by the added functions.
+  pub funcs: Vec<FunctionDiff>,
+}
+
+impl ProgramDiff {
+  /// Returns `true` if the two programs have no differences.
+  pub fn is_empty(&self) -> bool {
+    self.funcs.is_empty()
+  }
+}
+
+impl fmt::Display for ProgramDiff {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for func in &self.funcs {
+      write!(f, "{func}")?;
+    }
+    Ok(())
+  }
+}
+
+/// Diff of a function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FunctionDiff {
+  /// The function only exists in the new program.
+  Added {
+    /// Name of the function.
+    name: String,
+    /// Lines of the function in text form.
+    lines: Vec<String>,
+  },
+  /// The function only exists in the old program.
+  Removed {
+    /// Name of the function.
+    name: String,
+    /// Lines of the function in text form.
+    lines: Vec<String>,
+  },
+  /// The function exists in both programs, but has been changed.
+  Changed {
+    /// Name of the function.
+    name: String,
+    /// The old and the new header of the function in text form,
+    /// like `fun @f(@x: i32): i32`, if the header has been changed.
+    header: Option<(String, String)>,
+    /// Diffs of the changed, removed and added basic blocks.
+    bbs: Vec<BlockDiff>,
+  },
+}
+
+impl fmt::Display for FunctionDiff {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Self::Added { name, lines } => {
+        writeln!(f, "+++ {name}")?;
+        lines.iter().try_for_each(|l| writeln!(f, "+{l}"))
+      }
+      Self::Removed { name, lines } => {
+        writeln!(f, "--- {name}")?;
+        lines.iter().try_for_each(|l| writeln!(f, "-{l}"))
+      }
+      Self::Changed { name, header, bbs } => {
+        writeln!(f, "--- {name}")?;
+        writeln!(f, "+++ {name}")?;
+        if let Some((before, after)) = header {
+          writeln!(f, "-{before}")?;
+          writeln!(f, "+{after}")?;
+        }
+        bbs.iter().try_for_each(|bb| write!(f, "{bb}"))
+      }
+    }
+  }
+}
+
+/// Diff of a basic block.
+///
+/// Labels of basic blocks are in text form without the trailing colon,
+/// like `%bb(%0: i32)`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BlockDiff {
+  /// The basic block only exists in the new function.
+  Added {
+    /// Label of the basic block.
+    label: String,
+    /// Instructions of the basic block in text form.
+    insts: Vec<String>,
+  },
+  /// The basic block only exists in the old function.
+  Removed {
+    /// Label of the basic block.
+    label: String,
+    /// Instructions of the basic block in text form.
+    insts: Vec<String>,
+  },
+  /// The basic block exists in both functions, but has been changed.
+  Changed {
+    /// The old label of the basic block.
+    ///
+    /// This is the same as `after` if types of parameters
+    /// of the basic block have not been changed.
+    before: String,
+    /// The new label of the basic block.
+    after: String,
+    /// Diffs of the changed, removed and added instructions.
+    insts: Vec<InstDiff>,
+  },
+}
+
+impl fmt::Display for BlockDiff {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Self::Added { label, insts } => {
+        writeln!(f, "+{label}:")?;
+        insts.iter().try_for_each(|i| writeln!(f, "+  {i}"))
+      }
+      Self::Removed { label, insts } => {
+        writeln!(f, "-{label}:")?;
+        insts.iter().try_for_each(|i| writeln!(f, "-  {i}"))
+      }
+      Self::Changed {
+        before,
+        after,
+        insts,
+      } => {
+        if before == after {
+          writeln!(f, " {after}:")?;
+        } else {
+          writeln!(f, "-{before}:")?;
+          writeln!(f, "+{after}:")?;
+        }
+        insts.iter().try_for_each(|i| write!(f, "{i}"))
+      }
+    }
+  }
+}
+
+/// Diff of an instruction in text form.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InstDiff {
+  /// The instruction has been added.
+  Added(String),
+  /// The instruction has been removed.
+  Removed(String),
+  /// The instruction (the first one) has been replaced with a different
+  /// instruction (the second one).
+  Changed(String, String),
+}
+
+impl fmt::Display for InstDiff {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Self::Added(inst) => writeln!(f, "+  {inst}"),
+      Self::Removed(inst) => writeln!(f, "-  {inst}"),
+      Self::Changed(before, after) => {
+        writeln!(f, "-  {before}")?;
+        writeln!(f, "+  {after}")
+      }
+    }
+  }
+}
+
+/// Text form of a function.
+pub(super) struct Text {
+  lines: Vec<String>,
+  pub(super) header: String,
+  pub(super) labels: HashMap<BasicBlock, String>,
+  pub(super) insts: HashMap<Value, String>,
+}
+
+impl Text {
+  /// Generates text forms of all global allocations and all functions
+  /// in the given program.
+  pub(super) fn new(program: &Program) -> (HashMap<Value, String>, HashMap<Function, Self>) {
+    // lines are split by the layout of version 1
+    let config = Config {
+      version: FormatVersion::V1,
+      ..Default::default()
+    };
+    let mut gen = Generator::with_visitor(Vec::new(), Visitor::new(config));
+    gen.generate_on(program).unwrap();
+    let output = String::from_utf8(gen.writer()).unwrap();
+    let mut lines = output.lines().map(String::from);
+    let globals: HashMap<_, _> = program
+      .inst_layout()
+      .iter()
+      .map(|v| (*v, lines.next().unwrap()))
+      .collect();
+    if !globals.is_empty() {
+      lines.next();
+    }
+    let mut texts = HashMap::new();
+    for (i, &func) in program.func_layout().iter().enumerate() {
+      if i != 0 {
+        lines.next();
+      }
+      let data = program.func(func);
+      let header = lines.next().unwrap();
+      let mut text = Self {
+        lines: vec![header.clone()],
+        header: header.trim_end_matches(" {").into(),
+        labels: HashMap::new(),
+        insts: HashMap::new(),
+      };
+      if !data.dfg().bbs().is_empty() {
+        for (j, (bb, node)) in data.layout().bbs().iter().enumerate() {
+          if j != 0 {
+            text.lines.push(lines.next().unwrap());
+          }
+          let label = lines.next().unwrap();
+          text.labels.insert(*bb, label.trim_end_matches(':').into());
+          text.lines.push(label);
+          for inst in node.insts().keys() {
+            let line = lines.next().unwrap();
+            text.insts.insert(*inst, line.trim_start().into());
+            text.lines.push(line);
+          }
+        }
+        text.lines.push(lines.next().unwrap());
+      }
+      texts.insert(func, text);
+    }
+    (globals, texts)
+  }
+}
+
+/// Matches global allocations of the two programs by names,
+/// or by positions if they are unnamed.
+fn match_globals(before: &Program, after: &Program) -> HashMap<Value, Value> {
+  let (values_l, values_r) = (before.borrow_values(), after.borrow_values());
+  let names_r: HashMap<_, _> = after
+    .inst_layout()
+    .iter()
+    .filter_map(|v| Some((values_r[v].name().clone()?, *v)))
+    .collect();
+  let mut globals: HashMap<_, _> = before
+    .inst_layout()
+    .iter()
+    .filter_map(|v| Some((*v, *names_r.get(values_l[v].name().as_ref()?)?)))
+    .collect();
+  let unnamed_l = before
+    .inst_layout()
+    .iter()
+    .filter(|v| values_l[v].name().is_none());
+  let unnamed_r = after
+    .inst_layout()
+    .iter()
+    .filter(|v| values_r[v].name().is_none());
+  globals.extend(unnamed_l.copied().zip(unnamed_r.copied()));
+  globals
+}
+
+/// Diff of two functions with the same name.
+struct FunctionDiffer<'a> {
+  programs: (&'a Program, &'a Program),
+  funcs: (&'a FunctionData, &'a FunctionData),
+  texts: (&'a Text, &'a Text),
+  /// Matched basic blocks.
+  bbs: HashMap<BasicBlock, BasicBlock>,
+  /// Matched function parameters, basic block parameters, global
+  /// allocations and instructions.
+  leaves: HashMap<Value, Value>,
+}
+
+impl FunctionDiffer<'_> {
+  /// Returns the diff of the two functions,
+  /// or `None` if they have no differences.
+  fn diff(mut self) -> Option<FunctionDiff> {
+    let (l, r) = self.funcs;
+    let header = (self.texts.0.header != self.texts.1.header)
+      .then(|| (self.texts.0.header.clone(), self.texts.1.header.clone()));
+    let (params_l, params_r) = (l.params(), r.params());
+    self.match_params(params_l, params_r);
+    // match basic blocks
+    let bbs_l: Vec<_> = l.layout().bbs().keys().copied().collect();
+    let bbs_r: Vec<_> = r.layout().bbs().keys().copied().collect();
+    let mut unmatched_r = bbs_r.clone();
+    for &bb in &bbs_l {
+      let name = l.dfg().bb(bb).name();
+      if name.is_some() {
+        if let Some(i) = unmatched_r
+          .iter()
+          .position(|b| r.dfg().bb(*b).name() == name)
+        {
+          self.bbs.insert(bb, unmatched_r.remove(i));
+        }
+      }
+    }
+    let unnamed_l = bbs_l
+      .iter()
+      .filter(|b| l.dfg().bb(**b).name().is_none() && !self.bbs.contains_key(b));
+    let unnamed_r = unmatched_r
+      .iter()
+      .filter(|b| r.dfg().bb(**b).name().is_none());
+    let unnamed: Vec<_> = unnamed_l.copied().zip(unnamed_r.copied()).collect();
+    self.bbs.extend(unnamed);
+    for (bl, br) in self.bbs.clone() {
+      self.match_params(l.dfg().bb(bl).params(), r.dfg().bb(br).params());
+    }
+    // compare basic blocks in the order of the old layout,
+    // so operands are likely to be matched before their users
+    let mut changed = HashMap::new();
+    for &bb in &bbs_l {
+      if let Some(&br) = self.bbs.get(&bb) {
+        if let Some(diff) = self.diff_bb(bb, br) {
+          changed.insert(br, diff);
+        }
+      }
+    }
+    // generate diffs of basic blocks in the order of the new layout,
+    // with removed basic blocks placed before their matched successors
+    let matched_r: HashMap<_, _> = self.bbs.iter().map(|(l, r)| (*r, *l)).collect();
+    let mut removed = bbs_l
+      .iter()
+      .filter(|b| !self.bbs.contains_key(b))
+      .peekable();
+    let pos_l: HashMap<_, _> = bbs_l.iter().enumerate().map(|(i, b)| (*b, i)).collect();
+    let mut bbs = Vec::new();
+    for br in bbs_r {
+      if let Some(bl) = matched_r.get(&br) {
+        while let Some(b) = removed.next_if(|b| pos_l[b] < pos_l[bl]) {
+          bbs.push(self.removed_bb(*b));
+        }
+        bbs.extend(changed.remove(&br));
+      } else {
+        bbs.push(BlockDiff::Added {
+          label: self.texts.1.labels[&br].clone(),
+          insts: r.layout().bbs()[&br]
+            .insts()
+            .keys()
+            .map(|i| self.texts.1.insts[i].clone())
+            .collect(),
+        });
+      }
+    }
+    bbs.extend(removed.map(|b| self.removed_bb(*b)));
+    (header.is_some() || !bbs.is_empty()).then(|| FunctionDiff::Changed {
+      name: r.name().into(),
+      header,
+      bbs,
+    })
+  }
+
+  /// Matches the given parameters by their positions.
+  fn match_params(&mut self, params_l: &[Value], params_r: &[Value]) {
+    let (dfg_l, dfg_r) = (self.funcs.0.dfg(), self.funcs.1.dfg());
+    for (l, r) in params_l.iter().zip(params_r) {
+      if dfg_l.value(*l).ty() == dfg_r.value(*r).ty() {
+        self.leaves.insert(*l, *r);
+      }
+    }
+  }
+
+  /// Returns the diff of a removed basic block.
+  fn removed_bb(&self, bb: BasicBlock) -> BlockDiff {
+    BlockDiff::Removed {
+      label: self.texts.0.labels[&bb].clone(),
+      insts: self.funcs.0.layout().bbs()[&bb]
+        .insts()
+        .keys()
+        .map(|i| self.texts.0.insts[i].clone())
+        .collect(),
+    }
+  }
+
+  /// Returns the diff of the two matched basic blocks,
+  /// or `None` if they have no differences.
+  fn diff_bb(&mut self, bl: BasicBlock, br: BasicBlock) -> Option<BlockDiff> {
+    let (l, r) = self.funcs;
+    let insts_l: Vec<_> = l.layout().bbs()[&bl].insts().keys().copied().collect();
+    let insts_r: Vec<_> = r.layout().bbs()[&br].insts().keys().copied().collect();
+    // find the longest common subsequence, after trimming
+    // the common prefix and the common suffix
+    let mut prefix = 0;
+    while prefix < insts_l.len().min(insts_r.len())
+      && self.value_eq(insts_l[prefix], insts_r[prefix])
+    {
+      prefix += 1;
+    }
+    let mut suffix = 0;
+    while suffix < (insts_l.len() - prefix).min(insts_r.len() - prefix)
+      && self.value_eq(
+        insts_l[insts_l.len() - 1 - suffix],
+        insts_r[insts_r.len() - 1 - suffix],
+      )
+    {
+      suffix += 1;
+    }
+    let mid_l = &insts_l[prefix..insts_l.len() - suffix];
+    let mid_r = &insts_r[prefix..insts_r.len() - suffix];
+    let (n, m) = (mid_l.len(), mid_r.len());
+    let mut lcs = vec![vec![0; m + 1]; n + 1];
+    for i in (0..n).rev() {
+      for j in (0..m).rev() {
+        lcs[i][j] = if self.value_eq(mid_l[i], mid_r[j]) {
+          lcs[i + 1][j + 1] + 1
+        } else {
+          lcs[i + 1][j].max(lcs[i][j + 1])
+        };
+      }
+    }
+    // collect matched instructions and differences
+    let mut matched: Vec<_> = insts_l[..prefix]
+      .iter()
+      .copied()
+      .zip(insts_r[..prefix].iter().copied())
+      .collect();
+    let mut insts = Vec::new();
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+      if i < n && j < m && self.value_eq(mid_l[i], mid_r[j]) {
+        self.push_gap(&mut insts, &mut removed, &mut added);
+        matched.push((mid_l[i], mid_r[j]));
+        i += 1;
+        j += 1;
+      } else if j == m || (i < n && lcs[i + 1][j] >= lcs[i][j + 1]) {
+        removed.push(mid_l[i]);
+        i += 1;
+      } else {
+        added.push(mid_r[j]);
+        j += 1;
+      }
+    }
+    self.push_gap(&mut insts, &mut removed, &mut added);
+    let suffix_l = insts_l[insts_l.len() - suffix..].iter().copied();
+    matched.extend(suffix_l.zip(insts_r[insts_r.len() - suffix..].iter().copied()));
+    self.leaves.extend(matched);
+    // compare types of parameters
+    let tys = |dfg: &DataFlowGraph, bb| -> Vec<_> {
+      let params = dfg.bb(bb).params().iter();
+      params.map(|p| dfg.value(*p).ty().clone()).collect()
+    };
+    let label = self.texts.1.labels[&br].clone();
+    let params_changed = tys(l.dfg(), bl) != tys(r.dfg(), br);
+    (params_changed || !insts.is_empty()).then(|| BlockDiff::Changed {
+      before: if params_changed {
+        self.texts.0.labels[&bl].clone()
+      } else {
+        label.clone()
+      },
+      after: label,
+      insts,
+    })
+  }
+
+  /// Pushes the removed and added instructions between two matched
+  /// instructions to the given diffs. Removed and added instructions
+  /// at the same position are paired as changed instructions.
+  fn push_gap(&self, insts: &mut Vec<InstDiff>, removed: &mut Vec<Value>, added: &mut Vec<Value>) {
+    let (text_l, text_r) = (&self.texts.0.insts, &self.texts.1.insts);
+    let changed = removed.len().min(added.len());
+    for (l, r) in removed.iter().zip(added.iter()) {
+      insts.push(InstDiff::Changed(text_l[l].clone(), text_r[r].clone()));
+    }
+    let removed = removed.drain(..).skip(changed);
+    insts.extend(removed.map(|v| InstDiff::Removed(text_l[&v].clone())));
+    let added = added.drain(..).skip(changed);
+    insts.extend(added.map(|v| InstDiff::Added(text_r[&v].clone())));
+  }
+
+  /// Checks if the two given values are structurally equal.
+  fn value_eq(&self, lhs: Value, rhs: Value) -> bool {
+    let (dfg_l, dfg_r) = (self.funcs.0.dfg(), self.funcs.1.dfg());
+    let bb_eq = |l, r| self.bbs.get(&l) == Some(&r);
+    let func_eq = |l, r| self.programs.0.func(l).name() == self.programs.1.func(r).name();
+    // pairs of operands still to be compared
+    let mut worklist = vec![(lhs, rhs)];
+    while let Some((l, r)) = worklist.pop() {
+      if let Some(v) = self.leaves.get(&l) {
+        if *v != r {
+          return false;
+        }
+        continue;
+      }
+      let (Some(l), Some(r)) = (dfg_l.values().get(&l), dfg_r.values().get(&r)) else {
+        // unmatched global values
+        return false;
+      };
+      if matches!(
+        l.kind(),
+        ValueKind::FuncArgRef(_) | ValueKind::BlockArgRef(_)
+      ) || !DataFlowGraph::shallow_eq_by(l, r, bb_eq, func_eq)
+      {
+        return false;
+      }
+      worklist.extend(l.kind().value_uses().zip(r.kind().value_uses()));
+    }
+    true
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::front::Driver;
+
+  fn parse(src: &str) -> Program {
+    let driver: Driver<_> = src.into();
+    driver.generate_program().unwrap()
+  }
+
+  #[test]
+  fn same_programs() {
+    let src = r#"
+      global @g = alloc i32, zeroinit
+
+      fun @f(@x: i32): i32 {
+      %entry:
+        %0 = load @g
+        %1 = add %0, @x
+        br %1, %then, %end(%1)
+      %then:
+        %2 = call @f(%1)
+        jump %end(%2)
+      %end(%r: i32):
+        ret %r
+      }
+    "#;
+    let diff = diff_programs(&parse(src), &parse(src));
+    assert!(diff.is_empty());
+    assert_eq!(diff.to_string(), "");
+  }
+
+  #[test]
+  fn changed_funcs() {
+    let before = parse(
+      r#"
+      global @g = alloc i32, zeroinit
+      decl @getint(): i32
+
+      fun @removed(): i32 {
+      %entry:
+        ret 0
+      }
+
+      fun @f(@x: i32): i32 {
+      %entry:
+        %0 = load @g
+        %1 = mul %0, 2
+        %2 = add %1, @x
+        br %2, %then, %end
+      %then:
+        %3 = call @getint()
+        jump %end
+      %end:
+        ret %1
+      }
+    "#,
+    );
+    let after = parse(
+      r#"
+      global @g = alloc i32, zeroinit
+      decl @getint(): i32
+
+      fun @f(@x: i32, @y: i32): i32 {
+      %entry:
+        %v = load @g
+        %0 = shl %v, 1
+        %1 = add %0, @x
+        jump %end
+      %end:
+        ret %0
+      }
+
+      fun @added() {
+      %entry:
+        ret
+      }
+    "#,
+    );
+    let diff = diff_programs(&before, &after);
+    assert_eq!(
+      diff.funcs,
+      [
+        FunctionDiff::Removed {
+          name: "@removed".into(),
+          lines: vec![
+            "fun @removed(): i32 {".into(),
+            "%entry:".into(),
+            "  ret 0".into(),
+            "}".into(),
+          ],
+        },
+        FunctionDiff::Changed {
+          name: "@f".into(),
+          header: Some((
+            "fun @f(@x: i32): i32".into(),
+            "fun @f(@x: i32, @y: i32): i32".into()
+          )),
+          bbs: vec![
+            BlockDiff::Changed {
+              before: "%entry".into(),
+              after: "%entry".into(),
+              insts: vec![
+                InstDiff::Changed("%1 = mul %0, 2".into(), "%0 = shl %v, 1".into()),
+                InstDiff::Changed("%2 = add %1, @x".into(), "%1 = add %0, @x".into()),
+                InstDiff::Changed("br %2, %then, %end".into(), "jump %end".into()),
+              ],
+            },
+            BlockDiff::Removed {
+              label: "%then".into(),
+              insts: vec!["%3 = call @getint()".into(), "jump %end".into()],
+            },
+            BlockDiff::Changed {
+              before: "%end".into(),
+              after: "%end".into(),
+              insts: vec![InstDiff::Changed("ret %1".into(), "ret %0".into())],
+            },
+          ],
+        },
+        FunctionDiff::Added {
+          name: "@added".into(),
+          lines: vec![
+            "fun @added() {".into(),
+            "%entry:".into(),
+            "  ret".into(),
+            "}".into(),
+          ],
+        },
+      ]
+    );
+  }
+}
@@ -2,27 +2,44 @@
 //! ([`Function`], [`FunctionData`]), basic blocks ([`BasicBlock`],
 //! [`BasicBlockData`]) and values ([`Value`], [`ValueData`]).
 
+use crate::analysis::dom::DomTree;
+use crate::ir::arena::decode_id;
 use crate::ir::builder::GlobalBuilder;
+use crate::ir::builder_traits::*;
 use crate::ir::dfg::DataFlowGraph;
+use crate::ir::error::Error;
 use crate::ir::idman::{is_global_id, next_func_id, next_global_value_id};
 use crate::ir::idman::{BasicBlockId, FunctionId, ValueId};
 use crate::ir::layout::Layout;
-use crate::ir::types::Type;
+use crate::ir::symbol::{checked_symbol, is_valid_symbol};
+use crate::ir::types::{Type, TypeKind};
+use crate::ir::validation::Validation;
+use crate::ir::value_list::ValueList;
 use crate::ir::values;
-use std::cell::{Ref, RefCell};
-use std::collections::{HashMap, HashSet};
-use std::rc::{Rc, Weak};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::num::NonZeroU64;
+use std::ops::Deref;
+use std::sync::{Arc, RwLock, RwLockReadGuard, Weak};
+use std::thread;
 
 /// A Koopa IR program.
 ///
 /// Programs can hold global values and functions.
+///
+/// Programs and all data in them are [`Send`] and [`Sync`], and functions
+/// in a program can be modified in parallel by calling
+/// [`Program::par_funcs_mut`].
 #[derive(Default)]
 pub struct Program {
-  pub(in crate::ir) values: Rc<RefCell<HashMap<Value, ValueData>>>,
+  pub(in crate::ir) values: Arc<RwLock<HashMap<Value, ValueData>>>,
   pub(in crate::ir) inst_layout: Vec<Value>,
   funcs: HashMap<Function, FunctionData>,
-  func_tys: Rc<RefCell<HashMap<Function, Type>>>,
+  func_tys: Arc<RwLock<HashMap<Function, Type>>>,
+  func_names: Arc<RwLock<HashMap<Function, String>>>,
+  func_users: Arc<RwLock<FuncUserMap>>,
   func_layout: Vec<Function>,
+  validation: Validation,
 }
 
 /// Returns a mutable reference to the global value data by the given
@@ -31,7 +48,8 @@ macro_rules! data_mut {
   ($self:ident, $value:expr) => {
     $self
       .values
-      .borrow_mut()
+      .write()
+      .unwrap()
       .get_mut(&$value)
       .expect("value does not exist")
   };
@@ -45,7 +63,7 @@ impl Program {
 
   /// Creates a new global value in the current program.
   /// Returns a [`GlobalBuilder`] for building the new global value.
-  pub fn new_value(&mut self) -> GlobalBuilder {
+  pub fn new_value(&mut self) -> GlobalBuilder<'_> {
     GlobalBuilder { program: self }
   }
 
@@ -60,7 +78,7 @@ impl Program {
     for v in data.kind().value_uses() {
       data_mut!(self, v).used_by.insert(value);
     }
-    self.values.borrow_mut().insert(value, data);
+    self.values.write().unwrap().insert(value, data);
     value
   }
 
@@ -74,7 +92,8 @@ impl Program {
   pub fn remove_value(&mut self, value: Value) -> ValueData {
     let data = self
       .values
-      .borrow_mut()
+      .write()
+      .unwrap()
       .remove(&value)
       .expect("`value` does not exist");
     if data.kind().is_global_alloc() {
@@ -89,24 +108,141 @@ impl Program {
     data
   }
 
+  /// Sets the initializer of the given global allocation to `init`.
+  ///
+  /// The old initializer will be removed from the program if it is no
+  /// longer used, together with all elements of it (for aggregates)
+  /// that are no longer used.
+  ///
+  /// # Errors
+  ///
+  /// Returns an error if `init` is not a constant, or the type of `init`
+  /// is not the base type of the global allocation.
+  ///
+  /// # Panics
+  ///
+  /// Panics if any of the given values does not exist,
+  /// or `global` is not a global allocation.
+  pub fn set_global_init(&mut self, global: Value, init: Value) -> Result<(), Error> {
+    {
+      let values = self.values.read().unwrap();
+      let data = values.get(&global).expect("`global` does not exist");
+      assert!(
+        data.kind().is_global_alloc(),
+        "`global` must be a global allocation"
+      );
+      let init = values.get(&init).expect("`init` does not exist");
+      if !init.kind().is_const() {
+        return Err(Error::NotConstant);
+      }
+      match data.ty().kind() {
+        TypeKind::Pointer(base) if base == init.ty() => {}
+        TypeKind::Pointer(base) => return Err(Error::InitTypeMismatch { ty: base.clone() }),
+        _ => panic!("invalid global allocation type"),
+      }
+    }
+    let old = std::mem::replace(
+      data_mut!(self, global)
+        .kind_mut()
+        .as_global_alloc_mut()
+        .unwrap()
+        .init_mut(),
+      init,
+    );
+    if old != init {
+      data_mut!(self, old).used_by.remove(&global);
+      data_mut!(self, init).used_by.insert(global);
+      self.remove_unused_const(old);
+    }
+    Ok(())
+  }
+
+  /// Removes the given constant if it is not used, and then removes its
+  /// unused elements, until there are no more unused elements.
+  fn remove_unused_const(&mut self, value: Value) {
+    let mut worklist = vec![value];
+    while let Some(value) = worklist.pop() {
+      let unused = self
+        .values
+        .read()
+        .unwrap()
+        .get(&value)
+        .is_some_and(|d| d.kind().is_const() && d.used_by.is_empty());
+      if unused {
+        worklist.extend(self.remove_value(value).kind().value_uses());
+      }
+    }
+  }
+
   /// Sets the name of the given global value.
   ///
   /// # Panics
   ///
   /// Panics if the given value does not exist,
-  /// or the given name (if exists) not starts with `%` or `@`.
+  /// or the given name (if exists) is not a valid symbol name.
   pub fn set_value_name(&mut self, value: Value, name: Option<String>) {
     self
       .values
-      .borrow_mut()
+      .write()
+      .unwrap()
       .get_mut(&value)
       .expect("`value` does not exist")
       .set_name(name);
   }
 
+  /// Renames the given global value to `name`.
+  ///
+  /// Values refer to global values by handle, so all uses of the global
+  /// value will show the new name.
+  ///
+  /// # Errors
+  ///
+  /// Returns an error if `name` is not a valid global symbol name
+  /// (`@` followed by an identifier), or it is already used by another
+  /// function or global value.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given value does not exist.
+  pub fn rename_value(&mut self, value: Value, name: &str) -> Result<(), Error> {
+    assert!(
+      self.values.read().unwrap().contains_key(&value),
+      "`value` does not exist"
+    );
+    self.check_symbol_name(name, None, Some(value))?;
+    data_mut!(self, value).set_name(Some(name.into()));
+    Ok(())
+  }
+
+  /// Sets the source location of the given global value.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given value does not exist.
+  pub fn set_value_span(&mut self, value: Value, span: Option<SourceSpan>) {
+    data_mut!(self, value).set_span(span);
+  }
+
+  /// Sets the attribute `key` of the given global value to `attr`.
+  /// Returns the old attribute if exists.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given value does not exist.
+  pub fn set_value_attr(&mut self, value: Value, key: &str, attr: AttrValue) -> Option<AttrValue> {
+    data_mut!(self, value).set_attr(key, attr)
+  }
+
   /// Immutably borrows the global value map.
-  pub fn borrow_values(&self) -> Ref<HashMap<Value, ValueData>> {
-    self.values.borrow()
+  ///
+  /// The map is also updated by data flow graphs of functions when local
+  /// values start or stop using global values, for example, creating a
+  /// `load` of a global allocation in a function removed from the
+  /// program. Such updates block until the returned guard is dropped,
+  /// so do not hold the guard while modifying function data on the same
+  /// thread. The same applies to [`Program::borrow_value`].
+  pub fn borrow_values(&self) -> RwLockReadGuard<'_, HashMap<Value, ValueData>> {
+    self.values.read().unwrap()
   }
 
   /// Returns a reference to the layout of all global values.
@@ -119,32 +255,303 @@ impl Program {
   /// # Panics
   ///
   /// Panics if the given value does not exist.
-  pub fn borrow_value(&self, value: Value) -> Ref<ValueData> {
-    Ref::map(self.values.borrow(), |m| {
-      m.get(&value).expect("`value` does not exist")
-    })
+  pub fn borrow_value(&self, value: Value) -> ValueRef<'_> {
+    let values = self.values.read().unwrap();
+    assert!(values.contains_key(&value), "`value` does not exist");
+    ValueRef(ValueRefInner::Global { values, value })
+  }
+
+  /// Returns an iterator of all global values in the program, including
+  /// global allocations and their initializers, in creation order.
+  ///
+  /// The global value map is locked for reading only while a returned
+  /// [`ValueRef`] is alive, not during the whole iteration.
+  pub fn iter_globals(&self) -> impl Iterator<Item = (Value, ValueRef<'_>)> + '_ {
+    let mut values: Vec<_> = self.borrow_values().keys().copied().collect();
+    values.sort_unstable_by_key(|v| v.0);
+    values.into_iter().map(|v| (v, self.borrow_value(v)))
+  }
+
+  /// Returns an iterator of all values in the program, including global
+  /// values (see [`Program::iter_globals`]), followed by local values of
+  /// all functions in the function layout order.
+  ///
+  /// See [`ValueLocation`] for the location of each value.
+  pub fn iter_values(&self) -> impl Iterator<Item = (ValueLocation, Value, ValueRef<'_>)> + '_ {
+    let globals = self
+      .iter_globals()
+      .map(|(v, data)| (ValueLocation::Global, v, data));
+    let locals = self.func_layout.iter().flat_map(|&func| {
+      let data = &self.funcs[&func];
+      let bb_params: HashMap<_, _> = data
+        .dfg()
+        .bbs()
+        .iter()
+        .flat_map(|(&bb, bb_data)| bb_data.params().iter().map(move |&p| (p, bb)))
+        .collect();
+      data.dfg().values().iter().map(move |(&v, value)| {
+        let bb = data
+          .layout()
+          .parent_bb(v)
+          .or_else(|| bb_params.get(&v).copied());
+        (
+          ValueLocation::Local(func, bb),
+          v,
+          ValueRef(ValueRefInner::Local(value)),
+        )
+      })
+    });
+    globals.chain(locals)
+  }
+
+  /// Calls `f` with mutable references to all values in the program,
+  /// in the same order as [`Program::iter_values`].
+  ///
+  /// The `used_by` sets of values and basic blocks are updated if `f`
+  /// changes the operands or the targets of a value.
+  pub fn for_each_value_mut<F>(&mut self, mut f: F)
+  where
+    F: FnMut(ValueLocation, Value, &mut ValueData),
+  {
+    {
+      let mut values = self.values.write().unwrap();
+      let mut handles: Vec<_> = values.keys().copied().collect();
+      handles.sort_unstable_by_key(|v| v.0);
+      for v in handles {
+        let mut data = values.remove(&v).unwrap();
+        let old_uses: Vec<_> = data.kind().value_uses().collect();
+        f(ValueLocation::Global, v, &mut data);
+        for u in old_uses {
+          values.get_mut(&u).unwrap().used_by.remove(&v);
+        }
+        for u in data.kind().value_uses() {
+          values
+            .get_mut(&u)
+            .expect("global values can only use global values")
+            .used_by
+            .insert(v);
+        }
+        values.insert(v, data);
+      }
+    }
+    for &func in &self.func_layout {
+      let data = self.funcs.get_mut(&func).unwrap();
+      let bb_params: HashMap<_, _> = data
+        .dfg
+        .bbs()
+        .iter()
+        .flat_map(|(&bb, bb_data)| bb_data.params().iter().map(move |&p| (p, bb)))
+        .collect();
+      let handles: Vec<_> = data.dfg.values().keys().copied().collect();
+      for v in handles {
+        let bb = data
+          .layout
+          .parent_bb(v)
+          .or_else(|| bb_params.get(&v).copied());
+        data
+          .dfg
+          .update_value_data(v, |value| f(ValueLocation::Local(func, bb), v, value));
+      }
+    }
   }
 
   /// Creates a new function in the current program.
   pub fn new_func(&mut self, mut data: FunctionData) -> Function {
     let func = Function(next_func_id());
-    data.dfg.globals = Rc::downgrade(&self.values);
-    data.dfg.func_tys = Rc::downgrade(&self.func_tys);
-    self.func_tys.borrow_mut().insert(func, data.ty.clone());
+    data.dfg.globals = Arc::downgrade(&self.values);
+    data.dfg.func_tys = Arc::downgrade(&self.func_tys);
+    data.dfg.func_names = Arc::downgrade(&self.func_names);
+    data.dfg.func_users = Arc::downgrade(&self.func_users);
+    data.dfg.func = Some(func);
+    data
+      .dfg
+      .set_validation(data.dfg.validation().max(self.validation));
+    self.func_tys.write().unwrap().insert(func, data.ty.clone());
+    self
+      .func_names
+      .write()
+      .unwrap()
+      .insert(func, data.name.clone());
+    self.func_users.write().unwrap().entry(func).or_default();
+    for (value, v) in data.dfg.values() {
+      data.dfg.update_func_user(*value, v.kind(), true);
+    }
     self.funcs.insert(func, data);
     self.func_layout.push(func);
     func
   }
 
+  /// Creates a new function in the current program, like
+  /// [`Program::new_func`]. Returns an error if the name of the function
+  /// is not a valid global symbol name, or conflicts with the name of
+  /// an existing function or global value.
+  pub fn try_new_func(&mut self, data: FunctionData) -> Result<Function, Error> {
+    self.check_symbol_name(data.name(), None, None)?;
+    Ok(self.new_func(data))
+  }
+
   /// Removes the given function by its handle.
   ///
   /// Returns the function data if the function was previously in the program.
+  /// Values in the removed function will also be removed from the `used_by`
+  /// set of global values, and calls in the removed function will be
+  /// removed from the users of their callees.
   pub fn remove_func(&mut self, func: Function) -> Option<FunctionData> {
-    self.func_tys.borrow_mut().remove(&func);
+    let mut data = self.funcs.remove(&func)?;
+    self.func_tys.write().unwrap().remove(&func);
+    self.func_names.write().unwrap().remove(&func);
+    for (value, v) in data.dfg.values() {
+      data.dfg.update_func_user(*value, v.kind(), false);
+    }
+    self.func_users.write().unwrap().remove(&func);
+    data.dfg.func_users = FuncUserMapCell::new();
+    data.dfg.func = None;
     self
       .func_layout
       .remove(self.func_layout.iter().position(|f| *f == func).unwrap());
-    self.funcs.remove(&func)
+    let mut values = self.values.write().unwrap();
+    for (user, v) in data.dfg().values() {
+      for value in v.kind().value_uses() {
+        if let Some(global) = values.get_mut(&value) {
+          global.used_by.remove(user);
+        }
+      }
+    }
+    Some(data)
+  }
+
+  /// Returns the call sites of the given function, i.e. pairs of the
+  /// caller and the `call` instruction in the caller.
+  ///
+  /// Call sites are updated when calls are created, replaced or removed
+  /// by the data flow graphs of functions in the program. The map of call
+  /// sites is locked for reading until the returned guard is dropped, so
+  /// the data flow graphs can not be modified at the same time.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given function does not exist.
+  pub fn func_users(&self, func: Function) -> FuncUsers<'_> {
+    assert!(self.funcs.contains_key(&func), "`func` does not exist");
+    FuncUsers {
+      users: self.func_users.read().unwrap(),
+      func,
+    }
+  }
+
+  /// Renames the given function to `name`.
+  ///
+  /// Function calls refer to functions by handle, so all call sites
+  /// will show the new name.
+  ///
+  /// # Errors
+  ///
+  /// Returns an error if `name` is not a valid global symbol name
+  /// (`@` followed by an identifier), or it is already used by another
+  /// function or global value.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given function does not exist.
+  pub fn rename_func(&mut self, func: Function, name: &str) -> Result<(), Error> {
+    assert!(self.funcs.contains_key(&func), "`func` does not exist");
+    self.check_symbol_name(name, Some(func), None)?;
+    self.func_mut(func).set_name(name.into());
+    Ok(())
+  }
+
+  /// Removes the parameter at the given index from the given function,
+  /// and removes the corresponding argument from all calls to the
+  /// function. Returns the removed parameter, or `None` if the function
+  /// is a declaration.
+  ///
+  /// The type of the function is updated, and the remaining parameters
+  /// are renumbered. Removed arguments are still in the data flow graphs
+  /// of their callers (if they are local values), even if they are no
+  /// longer used.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given function does not exist, the index is out of
+  /// range, or the parameter is used by other values.
+  pub fn remove_func_param(&mut self, func: Function, index: usize) -> Option<ValueData> {
+    let data = self.funcs.get_mut(&func).expect("`func` does not exist");
+    let TypeKind::Function(params_ty, ret_ty) = data.ty.kind() else {
+      panic!("invalid function type");
+    };
+    assert!(
+      index < params_ty.len(),
+      "parameter index {} out of range ({} parameters)",
+      index,
+      params_ty.len()
+    );
+    let mut params_ty = params_ty.clone();
+    params_ty.remove(index);
+    data.ty = Type::get_function(params_ty, ret_ty.clone());
+    self.func_tys.write().unwrap().insert(func, data.ty.clone());
+    // remove the parameter from the function definition
+    let param = (!data.params.is_empty()).then(|| {
+      let param = data.params.remove(index);
+      assert!(
+        data.dfg.value(param).used_by().is_empty(),
+        "function parameter is used by other values"
+      );
+      let param = data.dfg.remove_value(param);
+      for p in data.params[index..].iter().copied() {
+        let mut arg = data.dfg.value(p).clone();
+        match arg.kind_mut() {
+          ValueKind::FuncArgRef(arg) => *arg.index_mut() -= 1,
+          _ => panic!("invalid function parameter"),
+        }
+        data.dfg.replace_value_with(p).raw(arg);
+      }
+      param
+    });
+    // fix up the argument lists of all calls
+    for data in self.funcs.values_mut() {
+      let calls: Vec<_> = data
+        .dfg
+        .values()
+        .iter()
+        .filter(|(_, v)| matches!(v.kind(), ValueKind::Call(call) if call.callee() == func))
+        .map(|(c, _)| *c)
+        .collect();
+      for call in calls {
+        let mut inst = data.dfg.value(call).clone();
+        if let ValueKind::Call(c) = inst.kind_mut() {
+          c.args_mut().remove(index);
+        }
+        data.dfg.replace_value_with(call).raw(inst);
+      }
+    }
+    param
+  }
+
+  /// Checks if `name` can be used as the name of the given function
+  /// or global value.
+  fn check_symbol_name(
+    &self,
+    name: &str,
+    func: Option<Function>,
+    value: Option<Value>,
+  ) -> Result<(), Error> {
+    if !name.starts_with('@') || !is_valid_symbol(name) {
+      return Err(Error::InvalidName { name: name.into() });
+    }
+    let func_conflict = self
+      .funcs
+      .iter()
+      .any(|(f, data)| Some(*f) != func && data.name() == name);
+    let value_conflict = self
+      .values
+      .read()
+      .unwrap()
+      .iter()
+      .any(|(v, data)| Some(*v) != value && data.name().as_deref() == Some(name));
+    if func_conflict || value_conflict {
+      return Err(Error::NameConflict { name: name.into() });
+    }
+    Ok(())
   }
 
   /// Returns a reference to the function map.
@@ -157,11 +564,110 @@ impl Program {
     &mut self.funcs
   }
 
+  /// Calls `f` with mutable references to all function data in the
+  /// program, using `threads` worker threads.
+  ///
+  /// Global values are still accessible in `f` through the data flow
+  /// graph of the function, and their `used_by` sets will be updated as
+  /// usual. The pointer size set by [`Type::set_ptr_size`] in the current
+  /// thread is also used in the worker threads.
+  ///
+  /// If `threads` is one, or the target does not support threads (like
+  /// `wasm32-unknown-unknown`), `f` is called in the current thread.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `threads` is zero, or `f` panics in any worker thread.
+  pub fn par_funcs_mut<F>(&mut self, threads: usize, f: F)
+  where
+    F: Fn(&mut FunctionData) + Sync,
+  {
+    assert!(threads != 0, "`threads` can not be zero");
+    let mut funcs: Vec<_> = self.funcs.values_mut().collect();
+    if funcs.is_empty() {
+      return;
+    }
+    if threads == 1 || cfg!(all(target_arch = "wasm32", target_os = "unknown")) {
+      funcs.into_iter().for_each(f);
+      return;
+    }
+    let chunk_size = funcs.len().div_ceil(threads);
+    let ptr_size = Type::ptr_size();
+    let f = &f;
+    thread::scope(|s| {
+      for chunk in funcs.chunks_mut(chunk_size) {
+        s.spawn(move || {
+          Type::set_ptr_size(ptr_size);
+          chunk.iter_mut().for_each(|data| f(data));
+        });
+      }
+    });
+  }
+
+  /// Sets the level of the checks performed when mutating the data flow
+  /// graphs of all functions in the current program, see [`Validation`].
+  ///
+  /// Functions added to the program later are checked at this level, or
+  /// at their own levels if higher.
+  pub fn set_validation(&mut self, level: Validation) {
+    self.validation = level;
+    for data in self.funcs.values_mut() {
+      data.dfg.set_validation(level);
+    }
+  }
+
+  /// Returns the level of the checks set by [`Program::set_validation`].
+  pub fn validation(&self) -> Validation {
+    self.validation
+  }
+
   /// Returns a reference to the layout of all functions.
   pub fn func_layout(&self) -> &[Function] {
     &self.func_layout
   }
 
+  /// Moves the given function to the given position in the layout of
+  /// all functions, shifting the functions after the position.
+  ///
+  /// The position is the index of the function in the layout after
+  /// moving. Since functions can only call functions defined before
+  /// them in the text form, passes that create functions should move
+  /// them before their callers.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given function does not exist, or the position is
+  /// out of bounds.
+  pub fn move_func(&mut self, func: Function, index: usize) {
+    let old = self
+      .func_layout
+      .iter()
+      .position(|f| *f == func)
+      .expect("`func` does not exist");
+    assert!(index < self.func_layout.len(), "`index` out of bounds");
+    let func = self.func_layout.remove(old);
+    self.func_layout.insert(index, func);
+  }
+
+  /// Sorts global values and functions in their layouts by name, so
+  /// programs with the same items are always printed in the same order,
+  /// no matter in which order the items were created.
+  ///
+  /// Global values without names are placed after the named ones, in
+  /// their original order. Basic blocks and instructions in functions
+  /// are not reordered. Canonicalizing a canonicalized program again
+  /// changes nothing.
+  pub fn canonicalize_order(&mut self) {
+    let values = self.values.read().unwrap();
+    self.inst_layout.sort_by_cached_key(|v| {
+      let name = values[v].name().clone();
+      (name.is_none(), name)
+    });
+    self
+      .func_layout
+      .sort_by_cached_key(|f| self.funcs[f].name().to_string());
+  }
+
   /// Returns a reference to the function data by
   /// the given function handle.
   ///
@@ -183,15 +689,99 @@ impl Program {
   }
 }
 
-/// Weak pointer for the `RefCell` of global value map.
+/// Weak pointer for the `RwLock` of global value map.
+///
+/// For [`DataFlowGraph`]s in function.
+pub(in crate::ir) type GlobalValueMapCell = Weak<RwLock<HashMap<Value, ValueData>>>;
+
+/// Weak pointer for the `RwLock` of function type map.
+///
+/// For [`DataFlowGraph`]s in function.
+pub(in crate::ir) type FuncTypeMapCell = Weak<RwLock<HashMap<Function, Type>>>;
+
+/// Weak pointer for the `RwLock` of function name map.
 ///
 /// For [`DataFlowGraph`]s in function.
-pub(in crate::ir) type GlobalValueMapCell = Weak<RefCell<HashMap<Value, ValueData>>>;
+pub(in crate::ir) type FuncNameMapCell = Weak<RwLock<HashMap<Function, String>>>;
 
-/// Weak pointer for the `RefCell` of function type map.
+/// Map of functions to their call sites.
+type FuncUserMap = HashMap<Function, HashSet<(Function, Value)>>;
+
+/// Weak pointer for the `RwLock` of function user map.
 ///
 /// For [`DataFlowGraph`]s in function.
-pub(in crate::ir) type FuncTypeMapCell = Weak<RefCell<HashMap<Function, Type>>>;
+pub(in crate::ir) type FuncUserMapCell = Weak<RwLock<FuncUserMap>>;
+
+/// An immutable reference to the call sites of a function, returned by
+/// [`Program::func_users`].
+pub struct FuncUsers<'a> {
+  users: RwLockReadGuard<'a, FuncUserMap>,
+  func: Function,
+}
+
+impl Deref for FuncUsers<'_> {
+  type Target = HashSet<(Function, Value)>;
+
+  fn deref(&self) -> &Self::Target {
+    &self.users[&self.func]
+  }
+}
+
+/// An immutable reference to value data, returned by
+/// [`Program::borrow_value`], [`Program::iter_values`] and
+/// [`DataFlowGraph::value_ref`].
+///
+/// For global values returned by the program, the global value map is
+/// locked for reading until the guard is dropped. For global values
+/// returned by data flow graphs, the guard holds a copy of the data.
+pub struct ValueRef<'a>(ValueRefInner<'a>);
+
+/// Inner of [`ValueRef`].
+enum ValueRefInner<'a> {
+  Global {
+    values: RwLockReadGuard<'a, HashMap<Value, ValueData>>,
+    value: Value,
+  },
+  Local(&'a ValueData),
+  Copied(Box<ValueData>),
+}
+
+impl<'a> ValueRef<'a> {
+  /// Creates a reference to the given local value data.
+  pub(in crate::ir) fn local(data: &'a ValueData) -> Self {
+    Self(ValueRefInner::Local(data))
+  }
+
+  /// Creates a guard that holds the given copy of value data.
+  pub(in crate::ir) fn copied(data: ValueData) -> Self {
+    Self(ValueRefInner::Copied(Box::new(data)))
+  }
+}
+
+impl Deref for ValueRef<'_> {
+  type Target = ValueData;
+
+  fn deref(&self) -> &ValueData {
+    match &self.0 {
+      ValueRefInner::Global { values, value } => &values[value],
+      ValueRefInner::Local(data) => data,
+      ValueRefInner::Copied(data) => data,
+    }
+  }
+}
+
+/// Location of a value in a program, returned by [`Program::iter_values`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ValueLocation {
+  /// Global value.
+  Global,
+  /// Local value in the given function.
+  ///
+  /// The basic block is the parent basic block of instructions in the
+  /// layout, or the basic block of basic block parameters. It is `None`
+  /// for other local values, like function parameters and constants.
+  Local(Function, Option<BasicBlock>),
+}
 
 /// A handle of Koopa IR function.
 ///
@@ -208,6 +798,9 @@ pub struct FunctionData {
   params: Vec<Value>,
   dfg: DataFlowGraph,
   layout: Layout,
+  func_attrs: FuncAttrs,
+  attrs: BTreeMap<String, AttrValue>,
+  order_constraints: Vec<(Value, Value)>,
 }
 
 impl FunctionData {
@@ -215,11 +808,13 @@ impl FunctionData {
   ///
   /// # Panics
   ///
-  /// Panics if the given name not starts with `%` or `@`, or the given
-  /// type can not construct a valid function type.
+  /// Panics if the given name is not a valid symbol name (see
+  /// [`is_valid_symbol`]), or the given type can not construct a valid
+  /// function type, e.g. the return type is an array type (see
+  /// [`Error::ArrayReturn`]).
   pub fn new(name: String, params_ty: Vec<Type>, ret_ty: Type) -> Self {
     use crate::ir::values::FuncArgRef;
-    Self::check_sanity(&name, params_ty.iter());
+    let name = Self::check_sanity(name, params_ty.iter(), &ret_ty);
     // create function argument references
     let mut dfg = DataFlowGraph::new();
     let params = params_ty
@@ -233,6 +828,9 @@ impl FunctionData {
       params,
       dfg,
       layout: Layout::new(),
+      func_attrs: FuncAttrs::default(),
+      attrs: BTreeMap::new(),
+      order_constraints: Vec::new(),
     }
   }
 
@@ -240,11 +838,13 @@ impl FunctionData {
   ///
   /// # Panics
   ///
-  /// Panics if the given name not starts with `%` or `@`, or the given
-  /// type can not construct a valid function type.
+  /// Panics if the given name is not a valid symbol name (see
+  /// [`is_valid_symbol`]), or the given type can not construct a valid
+  /// function type, e.g. the return type is an array type (see
+  /// [`Error::ArrayReturn`]).
   pub fn with_param_names(name: String, params: Vec<(Option<String>, Type)>, ret_ty: Type) -> Self {
     use crate::ir::values::FuncArgRef;
-    Self::check_sanity(&name, params.iter().map(|(_, ty)| ty));
+    let name = Self::check_sanity(name, params.iter().map(|(_, ty)| ty), &ret_ty);
     // create function argument references
     let mut dfg = DataFlowGraph::new();
     let (params, params_ty) = params
@@ -262,6 +862,9 @@ impl FunctionData {
       params,
       dfg,
       layout: Layout::new(),
+      func_attrs: FuncAttrs::default(),
+      attrs: BTreeMap::new(),
+      order_constraints: Vec::new(),
     }
   }
 
@@ -269,36 +872,42 @@ impl FunctionData {
   ///
   /// # Panics
   ///
-  /// Panics if the given name not starts with `%` or `@`, or the given
-  /// type can not construct a valid function type.
+  /// Panics if the given name is not a valid symbol name (see
+  /// [`is_valid_symbol`]), or the given type can not construct a valid
+  /// function type, e.g. the return type is an array type (see
+  /// [`Error::ArrayReturn`]).
   pub fn new_decl(name: String, params_ty: Vec<Type>, ret_ty: Type) -> Self {
-    Self::check_sanity(&name, params_ty.iter());
+    let name = Self::check_sanity(name, params_ty.iter(), &ret_ty);
     Self {
       ty: Type::get_function(params_ty, ret_ty),
       name,
       params: Vec::new(),
       dfg: DataFlowGraph::new(),
       layout: Layout::new(),
+      func_attrs: FuncAttrs::default(),
+      attrs: BTreeMap::new(),
+      order_constraints: Vec::new(),
     }
   }
 
   /// Checks if the given name and type is valid.
+  /// Returns the (possibly sanitized) name.
   ///
   /// # Panics
   ///
   /// Panics if the given name and type is invalid.
-  fn check_sanity<'a, T>(name: &str, mut params: T)
+  fn check_sanity<'a, T>(name: String, mut params: T, ret_ty: &Type) -> String
   where
     T: Iterator<Item = &'a Type>,
   {
-    assert!(
-      name.len() > 1 && (name.starts_with('%') || name.starts_with('@')),
-      "invalid function name"
-    );
     assert!(
       params.all(|p| !p.is_unit()),
       "parameter type must not be `unit`!"
     );
+    if let TypeKind::Array(..) = ret_ty.kind() {
+      panic!("{}", Error::ArrayReturn { ty: ret_ty.clone() });
+    }
+    checked_symbol(name, "function")
   }
 
   /// Returns a reference to the function's type.
@@ -312,8 +921,17 @@ impl FunctionData {
   }
 
   /// Sets the function's name.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given name is not a valid symbol name
+  /// (see [`is_valid_symbol`]).
   pub fn set_name(&mut self, name: String) {
-    self.name = name;
+    self.name = checked_symbol(name, "function");
+    // keep the name map of the program up to date
+    if let (Some(func), Some(names)) = (self.dfg.func, self.dfg.func_names.upgrade()) {
+      names.write().unwrap().insert(func, self.name.clone());
+    }
   }
 
   /// Returns a reference to the function parameters.
@@ -331,67 +949,945 @@ impl FunctionData {
     &mut self.dfg
   }
 
-  /// Returns a reference to the layout.
-  pub fn layout(&self) -> &Layout {
-    &self.layout
+  /// Appends a new parameter of type `ty` to the given basic block,
+  /// and appends an `undef` argument to all branches and jumps that
+  /// target the basic block. Returns the new parameter.
+  ///
+  /// See [`DataFlowGraph::add_bb_param`].
+  pub fn add_bb_param(&mut self, bb: BasicBlock, ty: Type) -> Value {
+    self.dfg.add_bb_param(bb, ty)
   }
 
-  /// Returns a mutable reference to the layout.
-  pub fn layout_mut(&mut self) -> &mut Layout {
-    &mut self.layout
+  /// Removes the parameter at the given index from the given basic block,
+  /// and removes the corresponding argument from all branches and jumps
+  /// that target the basic block. Returns the removed parameter.
+  ///
+  /// See [`DataFlowGraph::remove_bb_param`].
+  pub fn remove_bb_param(&mut self, bb: BasicBlock, index: usize) -> ValueData {
+    self.dfg.remove_bb_param(bb, index)
   }
-}
-
-/// A handle of Koopa IR basic block.
-///
-/// You can fetch [`BasicBlockData`] from [`DataFlowGraph`] in
-/// [`FunctionData`] by using this handle.
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
-pub struct BasicBlock(pub(in crate::ir) BasicBlockId);
-
-/// Data of Koopa IR basic block.
-///
-/// `BasicBlockData` only holds parameters about this basic block, and
-/// which values (branch/jump instructions) the current basic block is
-/// used by. Other information, such as the data and order of instructions
-/// in this basic block, can be found in [`FunctionData`] (in the data flow
-/// graph or the layout).
-pub struct BasicBlockData {
-  name: Option<String>,
-  params: Vec<Value>,
-  pub(in crate::ir) used_by: HashSet<Value>,
-}
 
-impl BasicBlockData {
-  pub(in crate::ir) fn new(name: Option<String>) -> Self {
-    Self {
-      name,
-      params: Vec::new(),
-      used_by: HashSet::new(),
+  /// Removes the given basic block from both the layout and the data flow
+  /// graph, together with its parameters and instructions. Returns the
+  /// corresponding basic block data.
+  ///
+  /// Branches and jumps that target the basic block from other basic
+  /// blocks are retargeted to `replacement` first, see
+  /// [`DataFlowGraph::replace_bb_with`]. Returns an error if there are
+  /// such branches or jumps but `replacement` is `None`, or if the
+  /// parameters or instructions of the basic block are used outside of
+  /// it. The function is not changed on error.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `replacement` is the given basic block, or if it is
+  /// rejected by [`DataFlowGraph::replace_bb_with`].
+  pub fn remove_bb_forced(
+    &mut self,
+    bb: BasicBlock,
+    replacement: Option<BasicBlock>,
+  ) -> Result<BasicBlockData, Error> {
+    assert!(replacement != Some(bb), "`replacement` must not be `bb`");
+    let data = self
+      .dfg
+      .bbs()
+      .get(&bb)
+      .ok_or(Error::BasicBlockNotFound { bb })?;
+    let insts: Vec<_> = self
+      .layout
+      .bbs()
+      .node(&bb)
+      .map(|node| node.insts().keys().copied().collect())
+      .unwrap_or_default();
+    let is_inner = |v: &Value| insts.contains(v);
+    if replacement.is_none() && !data.used_by().iter().all(is_inner) {
+      return Err(Error::BasicBlockInUse { bb });
+    }
+    for &value in data.params().iter().chain(&insts) {
+      if !self.dfg.value(value).used_by().iter().all(is_inner) {
+        return Err(Error::ValueInUse { value });
+      }
     }
+    if let Some(replacement) = replacement {
+      self.dfg.replace_bb_with(bb, replacement);
+    }
+    if self.layout.bbs().node(&bb).is_some() {
+      for inst in &insts {
+        self.layout.bb_mut(bb).insts_mut().remove(inst);
+      }
+      self.layout.bbs_mut().remove(&bb);
+    }
+    // instructions only use the previous ones and the parameters
+    for inst in insts.into_iter().rev() {
+      self.dfg.remove_value(inst);
+    }
+    Ok(self.dfg.remove_bb(bb))
   }
 
-  pub(in crate::ir) fn with_params(name: Option<String>, params: Vec<Value>) -> Self {
-    Self {
-      name,
-      params,
-      used_by: HashSet::new(),
-    }
+  /// Returns the entry basic block of the function, which is the first
+  /// basic block in the layout. Returns `None` if the function is a
+  /// declaration.
+  ///
+  /// The entry basic block must have no parameters and no predecessors,
+  /// which is checked by the verifier. See
+  /// [`FunctionData::ensure_entry_has_no_preds`] for fixing functions
+  /// that break this.
+  pub fn entry(&self) -> Option<BasicBlock> {
+    self.layout.entry_bb()
   }
 
-  /// Returns a reference to the basic block's name.
-  pub fn name(&self) -> &Option<String> {
-    &self.name
+  /// Inserts a new entry basic block if the current entry basic block has
+  /// parameters, or is targeted by branches or jumps in the layout.
+  /// Returns the new entry basic block, or `None` if nothing is changed.
+  ///
+  /// The new entry basic block is placed at the front of the layout, and
+  /// jumps to the old one, passing `undef`s to its parameters. Leading
+  /// `alloc`s of the old entry basic block are moved into the new one, so
+  /// they are still executed only once.
+  pub fn ensure_entry_has_no_preds(&mut self) -> Option<BasicBlock> {
+    let entry = self.entry()?;
+    let data = self.dfg.bb(entry);
+    let has_preds = data
+      .used_by()
+      .iter()
+      .any(|u| self.layout.parent_bb(*u).is_some());
+    if data.params().is_empty() && !has_preds {
+      return None;
+    }
+    let param_tys: Vec<_> = data
+      .params()
+      .iter()
+      .map(|p| self.dfg.value(*p).ty().clone())
+      .collect();
+    let new_entry = self.dfg.new_bb().basic_block(None);
+    self.layout.bbs_mut().push_key_front(new_entry).unwrap();
+    let allocs: Vec<_> = self
+      .layout
+      .bb_mut(entry)
+      .insts()
+      .keys()
+      .take_while(|i| matches!(self.dfg.value(**i).kind(), ValueKind::Alloc(_)))
+      .copied()
+      .collect();
+    for alloc in allocs {
+      self.layout.bb_mut(entry).insts_mut().remove(&alloc);
+      self.push_inst(new_entry, alloc);
+    }
+    let args: Vec<_> = param_tys
+      .into_iter()
+      .map(|ty| self.dfg.new_value().undef(ty))
+      .collect();
+    let jump = self.dfg.new_value().jump_with_args(entry, args);
+    self.push_inst(new_entry, jump);
+    Some(new_entry)
   }
 
-  /// Sets the basic block's name.
-  pub fn set_name(&mut self, name: Option<String>) {
-    self.name = name;
+  /// Splits the edge from `pred` to `succ` by inserting a new basic block
+  /// which only contains a jump to `succ`. Returns the new basic block.
+  ///
+  /// Arguments passed to `succ` along the edge are moved to the new jump,
+  /// and the new basic block is placed right before `succ` in the layout.
+  /// If both targets of the branch in `pred` are `succ`, only the true
+  /// edge will be split.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `pred` does not end with a branch or jump to `succ`.
+  pub fn split_edge(&mut self, pred: BasicBlock, succ: BasicBlock) -> BasicBlock {
+    let term = self.terminator(pred).expect("`pred` has no terminator");
+    let index = self
+      .dfg
+      .value(term)
+      .kind()
+      .edges()
+      .iter()
+      .position(|(bb, _)| *bb == succ)
+      .expect("`pred` does not branch to `succ`");
+    self.split_edge_at(term, index)
   }
 
-  /// Returns a reference to the basic block parameters.
-  pub fn params(&self) -> &[Value] {
-    &self.params
+  /// Splits all critical edges in the function, i.e. edges from a basic
+  /// block with multiple successors to a basic block with multiple
+  /// predecessors. Returns the number of split edges.
+  ///
+  /// See [`FunctionData::split_edge`].
+  pub fn split_critical_edges(&mut self) -> usize {
+    let mut num_preds: HashMap<BasicBlock, usize> = HashMap::new();
+    let mut edges = Vec::new();
+    for &bb in self.layout.bbs().keys() {
+      let Some(term) = self.terminator(bb) else {
+        continue;
+      };
+      let succs = self.dfg.value(term).kind().edges();
+      for (index, (succ, _)) in succs.iter().enumerate() {
+        if succs.len() > 1 {
+          edges.push((term, index, *succ));
+        }
+        *num_preds.entry(*succ).or_default() += 1;
+      }
+    }
+    let mut count = 0;
+    for (term, index, succ) in edges {
+      if num_preds[&succ] > 1 {
+        self.split_edge_at(term, index);
+        count += 1;
+      }
+    }
+    count
+  }
+
+  /// Splits the basic block of the given instruction before the
+  /// instruction. Returns the new basic block.
+  ///
+  /// The instruction and all instructions after it are moved to a new
+  /// basic block with no parameters, which is placed right after the
+  /// original basic block in the layout. The original basic block then
+  /// ends with a jump to the new basic block.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given instruction is not in the layout.
+  pub fn split_bb(&mut self, inst: Value) -> BasicBlock {
+    let bb = self
+      .layout
+      .parent_bb(inst)
+      .expect("`inst` is not in the layout");
+    let new_bb = self.dfg.new_bb().basic_block(None);
+    self
+      .layout
+      .bbs_mut()
+      .cursor_mut(bb)
+      .insert_key_after(new_bb)
+      .unwrap();
+    let insts = self.layout.bb_mut(bb).insts_mut();
+    let moved: Vec<_> = insts.keys().skip_while(|i| **i != inst).copied().collect();
+    for inst in &moved {
+      insts.remove(inst);
+    }
+    let new_insts = self.layout.bb_mut(new_bb).insts_mut();
+    for inst in moved {
+      new_insts.push_key_back(inst).unwrap();
+    }
+    let jump = self.dfg.new_value().jump(new_bb);
+    self
+      .layout
+      .bb_mut(bb)
+      .insts_mut()
+      .push_key_back(jump)
+      .unwrap();
+    new_bb
+  }
+
+  /// Renumbers handles of all local values and basic blocks densely,
+  /// and updates all references to them in the function. Returns maps
+  /// of old handles to new handles, which can be used to migrate side
+  /// tables.
+  ///
+  /// Values are renumbered in the layout order: function parameters
+  /// first, then parameters of each basic block, followed by instructions
+  /// of the basic block, and constant operands are placed right before
+  /// their first users. Values and basic blocks that are not reachable
+  /// from the layout are placed at the end. Old handles become invalid.
+  ///
+  /// Global values are not renumbered and keep their handles, since they
+  /// are shared by all functions in the program.
+  pub fn compact(&mut self) -> (HashMap<Value, Value>, HashMap<BasicBlock, BasicBlock>) {
+    /// Adds the given value after its local constant operands.
+    fn add(
+      dfg: &DataFlowGraph,
+      value: Value,
+      values: &mut Vec<Value>,
+      visited: &mut HashSet<Value>,
+    ) {
+      if !dfg.values().contains_key(&value) || !visited.insert(value) {
+        return;
+      }
+      for operand in dfg.value(value).kind().value_uses() {
+        if dfg
+          .values()
+          .get(&operand)
+          .is_some_and(|d| d.kind().is_const())
+        {
+          add(dfg, operand, values, visited);
+        }
+      }
+      values.push(value);
+    }
+    let dfg = &self.dfg;
+    let mut values = Vec::with_capacity(dfg.values().len());
+    let mut visited = HashSet::new();
+    for &param in &self.params {
+      add(dfg, param, &mut values, &mut visited);
+    }
+    for (bb, node) in self.layout.bbs() {
+      for &param in dfg.bb(*bb).params() {
+        add(dfg, param, &mut values, &mut visited);
+      }
+      for &inst in node.insts().keys() {
+        add(dfg, inst, &mut values, &mut visited);
+      }
+    }
+    for &value in dfg.values().keys() {
+      add(dfg, value, &mut values, &mut visited);
+    }
+    let mut bbs: Vec<_> = self.layout.bbs().keys().copied().collect();
+    let in_layout: HashSet<_> = bbs.iter().copied().collect();
+    bbs.extend(dfg.bbs().keys().filter(|bb| !in_layout.contains(bb)));
+    let (value_map, bb_map) = self.dfg.renumber(&values, &bbs);
+    // rebuild the layout
+    let mut layout = Layout::new();
+    for (bb, node) in self.layout.bbs() {
+      let bb = bb_map[bb];
+      layout.bbs_mut().push_key_back(bb).unwrap();
+      let insts = layout.bb_mut(bb).insts_mut();
+      for inst in node.insts().keys() {
+        insts.push_key_back(value_map[inst]).unwrap();
+      }
+    }
+    layout.set_mutation_log(self.dfg.mutation_log_cell());
+    self.layout = layout;
+    self.params.iter_mut().for_each(|p| *p = value_map[p]);
+    self.order_constraints = self
+      .order_constraints
+      .iter()
+      .filter_map(|(b, a)| Some((*value_map.get(b)?, *value_map.get(a)?)))
+      .collect();
+    (value_map, bb_map)
+  }
+
+  /// Removes values in the data flow graph that are unreachable from the
+  /// function, returns the number of removed values.
+  ///
+  /// Values are reachable if they are function parameters, parameters of
+  /// basic blocks in the data flow graph, instructions in the layout, or
+  /// operands of other reachable values. Unreachable values, like values
+  /// created by passes and then abandoned, can only be used by each
+  /// other, and are removed together, with the `used_by` sets of their
+  /// operands and target basic blocks updated.
+  pub fn collect_garbage(&mut self) -> usize {
+    let mut worklist = self.params.clone();
+    for bb in self.dfg.bbs().values() {
+      worklist.extend(bb.params());
+    }
+    for node in self.layout.bbs().nodes() {
+      worklist.extend(node.insts().keys());
+    }
+    let mut reachable = HashSet::new();
+    while let Some(value) = worklist.pop() {
+      if let Some(data) = self.dfg.values().get(&value) {
+        if reachable.insert(value) {
+          worklist.extend(data.kind().value_uses());
+        }
+      }
+    }
+    let garbage: HashSet<_> = self
+      .dfg
+      .values()
+      .keys()
+      .filter(|v| !reachable.contains(v))
+      .copied()
+      .collect();
+    self.dfg.remove_unused_values(&garbage);
+    garbage.len()
+  }
+
+  /// Removes instructions in the layout that do not exist in the data
+  /// flow graph, like instructions removed by
+  /// [`DataFlowGraph::remove_value`] but not from the layout, returns
+  /// the removed instructions in layout order.
+  ///
+  /// This is a repair utility for broken functions, so tools can still
+  /// inspect, verify or print the rest of the function. Basic blocks
+  /// are never removed, even if they become empty.
+  pub fn prune_dangling_layout_entries(&mut self) -> Vec<Value> {
+    let dangling: Vec<_> = self
+      .layout
+      .bbs()
+      .iter()
+      .flat_map(|(bb, node)| node.insts().keys().map(move |inst| (*bb, *inst)))
+      .filter(|(_, inst)| !self.dfg.values().contains_key(inst))
+      .collect();
+    for (bb, inst) in &dangling {
+      self.layout.bb_mut(*bb).insts_mut().remove(inst);
+    }
+    dangling.into_iter().map(|(_, inst)| inst).collect()
+  }
+
+  /// Appends the given instruction to the end of the given basic block
+  /// in the layout.
+  ///
+  /// # Panics
+  ///
+  /// Panics if [`FunctionData::try_push_inst`] returns an error.
+  pub fn push_inst(&mut self, bb: BasicBlock, inst: impl Into<Inst>) {
+    if let Err(e) = self.try_push_inst(bb, inst) {
+      panic!("{e}");
+    }
+  }
+
+  /// Appends the given instruction to the end of the given basic block
+  /// in the layout. Returns an error if the instruction does not exist
+  /// or is not a local instruction, the basic block is not in the layout,
+  /// the instruction is already in the layout, or the basic block is
+  /// already terminated. The layout is not changed on error.
+  pub fn try_push_inst(&mut self, bb: BasicBlock, inst: impl Into<Inst>) -> Result<(), Error> {
+    let inst = inst.into().value();
+    let data = self
+      .dfg
+      .values()
+      .get(&inst)
+      .ok_or(Error::ValueNotFound { value: inst })?;
+    if !data.kind().is_local_inst() {
+      return Err(Error::NotInstruction { value: inst });
+    }
+    if self.layout.bbs().node(&bb).is_none() {
+      return Err(Error::NotInLayout { bb });
+    }
+    if self.layout.parent_bb(inst).is_some() {
+      return Err(Error::InstInLayout { inst });
+    }
+    if self.terminator(bb).is_some() {
+      return Err(Error::Terminated { bb });
+    }
+    self
+      .layout
+      .bb_mut(bb)
+      .insts_mut()
+      .push_key_back(inst)
+      .unwrap();
+    Ok(())
+  }
+
+  /// Removes the given instruction from both the layout and the data
+  /// flow graph. Returns the corresponding value data.
+  ///
+  /// # Panics
+  ///
+  /// Panics if [`FunctionData::try_remove_inst`] returns an error.
+  pub fn remove_inst(&mut self, inst: Value) -> ValueData {
+    self.try_remove_inst(inst).unwrap_or_else(|e| panic!("{e}"))
+  }
+
+  /// Removes the given instruction from both the layout and the data
+  /// flow graph. Returns the corresponding value data, or an error if
+  /// the instruction does not exist, is not a local instruction, or is
+  /// used by other values. The function is not changed on error.
+  ///
+  /// The instruction is not required to be in the layout.
+  pub fn try_remove_inst(&mut self, inst: Value) -> Result<ValueData, Error> {
+    self.check_removable_inst(inst, |_| false)?;
+    Ok(self.unlink_and_remove_inst(inst))
+  }
+
+  /// Removes the given instructions from both the layout and the data
+  /// flow graph.
+  ///
+  /// # Panics
+  ///
+  /// Panics if [`FunctionData::try_remove_insts`] returns an error.
+  pub fn remove_insts(&mut self, insts: impl IntoIterator<Item = Value>) {
+    if let Err(e) = self.try_remove_insts(insts) {
+      panic!("{e}");
+    }
+  }
+
+  /// Removes the given instructions from both the layout and the data
+  /// flow graph, in any order.
+  ///
+  /// Instructions may be used by the other given instructions, users are
+  /// always removed before the instructions they use. Returns an error if
+  /// any of the instructions does not exist, is not a local instruction,
+  /// or is used by values not in the batch, or if the instructions use
+  /// each other in a cycle. The function is not changed on error.
+  pub fn try_remove_insts(&mut self, insts: impl IntoIterator<Item = Value>) -> Result<(), Error> {
+    let batch: HashSet<_> = insts.into_iter().collect();
+    for &inst in &batch {
+      self.check_removable_inst(inst, |v| batch.contains(v))?;
+    }
+    // sort the batch topologically, an instruction can be removed after
+    // all of its users have been removed
+    let mut users: HashMap<_, _> = batch
+      .iter()
+      .map(|v| (*v, self.dfg.value(*v).used_by().len()))
+      .collect();
+    let mut ready: Vec<_> = users
+      .iter()
+      .filter_map(|(v, n)| (*n == 0).then_some(*v))
+      .collect();
+    let mut order = Vec::with_capacity(batch.len());
+    while let Some(inst) = ready.pop() {
+      order.push(inst);
+      let operands: HashSet<_> = self.dfg.value(inst).kind().value_uses().collect();
+      for v in operands {
+        if let Some(n) = users.get_mut(&v) {
+          *n -= 1;
+          if *n == 0 {
+            ready.push(v);
+          }
+        }
+      }
+    }
+    if let Some(value) = batch.iter().find(|v| users[v] != 0) {
+      return Err(Error::ValueInUse { value: *value });
+    }
+    for inst in order {
+      self.unlink_and_remove_inst(inst);
+    }
+    Ok(())
+  }
+
+  /// Adds an ordering constraint, which requires instruction `before` to
+  /// be placed before instruction `after`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if [`FunctionData::try_add_order_constraint`] returns an
+  /// error.
+  pub fn add_order_constraint(&mut self, before: Value, after: Value) {
+    if let Err(e) = self.try_add_order_constraint(before, after) {
+      panic!("{e}");
+    }
+  }
+
+  /// Adds an ordering constraint, which requires instruction `before` to
+  /// be placed before instruction `after`. Returns an error if any of
+  /// the instructions does not exist or is not a local instruction.
+  ///
+  /// Ordering constraints encode dependences that can not be seen from
+  /// the operands, like a store that initializes a stack slot before a
+  /// call that takes the address of the slot. A constraint holds if both
+  /// instructions are in the same basic block and `before` comes first,
+  /// or the basic block of `before` strictly dominates the basic block
+  /// of `after`. Constraints of instructions that are not in the layout,
+  /// or in unreachable basic blocks, are not checked.
+  ///
+  /// Constraints are checked by the
+  /// [`verifier`](crate::analysis::verifier), and respected by
+  /// [`FunctionData::try_move_inst`] and the passes that move or replace
+  /// instructions, like [`LoopInvariantMotion`](crate::opt::LoopInvariantMotion)
+  /// and [`RewritePass`](crate::opt::RewritePass). Constraints that
+  /// reference removed instructions are dropped automatically.
+  pub fn try_add_order_constraint(&mut self, before: Value, after: Value) -> Result<(), Error> {
+    for value in [before, after] {
+      let data = self
+        .dfg
+        .values()
+        .get(&value)
+        .ok_or(Error::ValueNotFound { value })?;
+      if !data.kind().is_local_inst() {
+        return Err(Error::NotInstruction { value });
+      }
+    }
+    let values = self.dfg.values();
+    self
+      .order_constraints
+      .retain(|(b, a)| values.contains_key(b) && values.contains_key(a));
+    if !self.order_constraints.contains(&(before, after)) {
+      self.order_constraints.push((before, after));
+    }
+    Ok(())
+  }
+
+  /// Removes the given ordering constraint. Returns `true` if the
+  /// constraint existed.
+  pub fn remove_order_constraint(&mut self, before: Value, after: Value) -> bool {
+    let len = self.order_constraints.len();
+    self.order_constraints.retain(|c| *c != (before, after));
+    self.order_constraints.len() != len
+  }
+
+  /// Returns all ordering constraints as pairs of `before` and `after`
+  /// instructions, in the order they were added. Constraints that
+  /// reference removed instructions are skipped.
+  pub fn order_constraints(&self) -> impl Iterator<Item = (Value, Value)> + '_ {
+    let values = self.dfg.values();
+    self
+      .order_constraints
+      .iter()
+      .copied()
+      .filter(|(b, a)| values.contains_key(b) && values.contains_key(a))
+  }
+
+  /// Returns `true` if the given instruction has any ordering
+  /// constraint.
+  pub fn has_order_constraints(&self, inst: Value) -> bool {
+    self
+      .order_constraints()
+      .any(|(b, a)| b == inst || a == inst)
+  }
+
+  /// Returns all violated ordering constraints, see
+  /// [`FunctionData::try_add_order_constraint`].
+  pub fn violated_order_constraints(&self) -> Vec<(Value, Value)> {
+    if self.order_constraints().next().is_none() {
+      return Vec::new();
+    }
+    let dom = DomTree::new(self);
+    self
+      .order_constraints()
+      .filter(|(b, a)| !self.order_holds(&dom, *b, *a))
+      .collect()
+  }
+
+  /// Returns the first violated ordering constraint of the given
+  /// instruction.
+  fn violated_order_constraint_of(&self, inst: Value) -> Option<(Value, Value)> {
+    let mut constraints = self
+      .order_constraints()
+      .filter(|(b, a)| *b == inst || *a == inst)
+      .peekable();
+    constraints.peek()?;
+    let dom = DomTree::new(self);
+    constraints.find(|(b, a)| !self.order_holds(&dom, *b, *a))
+  }
+
+  /// Returns `true` if instruction `before` is placed before instruction
+  /// `after`, or the order can not be checked.
+  fn order_holds(&self, dom: &DomTree, before: Value, after: Value) -> bool {
+    let (Some(bb_b), Some(bb_a)) = (self.layout.parent_bb(before), self.layout.parent_bb(after))
+    else {
+      return true;
+    };
+    if !dom.contains(bb_b) || !dom.contains(bb_a) {
+      return true;
+    }
+    if bb_b != bb_a {
+      return dom.dominates(bb_b, bb_a);
+    }
+    let insts = self.layout.bbs().node(&bb_b).unwrap().insts();
+    let mut cursor = insts.cursor(before);
+    while let Some(inst) = cursor.next_key() {
+      if *inst == after {
+        return true;
+      }
+      cursor.move_next();
+    }
+    false
+  }
+
+  /// Moves the given instruction in the layout to the position right
+  /// before instruction `pos`, which may be in another basic block.
+  ///
+  /// # Panics
+  ///
+  /// Panics if [`FunctionData::try_move_inst`] returns an error.
+  pub fn move_inst(&mut self, inst: Value, pos: Value) {
+    if let Err(e) = self.try_move_inst(inst, pos) {
+      panic!("{e}");
+    }
+  }
+
+  /// Moves the given instruction in the layout to the position right
+  /// before instruction `pos`, which may be in another basic block.
+  /// Returns an error if any of the instructions is not in the layout,
+  /// or the move violates an ordering constraint of the instruction
+  /// (see [`FunctionData::try_add_order_constraint`]). The layout is not
+  /// changed on error.
+  ///
+  /// Operands and users are not checked, so moving an instruction away
+  /// from the definitions of its operands, or after its users, breaks
+  /// the function.
+  pub fn try_move_inst(&mut self, inst: Value, pos: Value) -> Result<(), Error> {
+    let bb = self
+      .layout
+      .parent_bb(inst)
+      .ok_or(Error::InstNotInLayout { inst })?;
+    let pos_bb = self
+      .layout
+      .parent_bb(pos)
+      .ok_or(Error::InstNotInLayout { inst: pos })?;
+    if inst == pos {
+      return Ok(());
+    }
+    let insts = self.layout.bb_mut(bb).insts_mut();
+    let next = insts.cursor(inst).next_key().copied();
+    insts.remove(&inst);
+    let insert_before = |layout: &mut Layout, bb, pos: Option<Value>| {
+      let insts = layout.bb_mut(bb).insts_mut();
+      match pos {
+        Some(pos) => insts.cursor_mut(pos).insert_key_before(inst).unwrap(),
+        None => insts.push_key_back(inst).unwrap(),
+      }
+    };
+    insert_before(&mut self.layout, pos_bb, Some(pos));
+    if let Some((before, after)) = self.violated_order_constraint_of(inst) {
+      self.layout.bb_mut(pos_bb).insts_mut().remove(&inst);
+      insert_before(&mut self.layout, bb, next);
+      return Err(Error::OrderViolation { before, after });
+    }
+    Ok(())
+  }
+
+  /// Checks if the given instruction can be removed, users that satisfy
+  /// `removed` are ignored.
+  fn check_removable_inst<F>(&self, inst: Value, removed: F) -> Result<(), Error>
+  where
+    F: Fn(&Value) -> bool,
+  {
+    let data = self
+      .dfg
+      .values()
+      .get(&inst)
+      .ok_or(Error::ValueNotFound { value: inst })?;
+    if !data.kind().is_local_inst() {
+      return Err(Error::NotInstruction { value: inst });
+    }
+    if !data.used_by().iter().all(removed) {
+      return Err(Error::ValueInUse { value: inst });
+    }
+    Ok(())
+  }
+
+  /// Removes the given instruction from the layout if it is in the
+  /// layout, and then removes it from the data flow graph.
+  fn unlink_and_remove_inst(&mut self, inst: Value) -> ValueData {
+    if let Some(bb) = self.layout.parent_bb(inst) {
+      self.layout.bb_mut(bb).insts_mut().remove(&inst);
+    }
+    self.dfg.remove_value(inst)
+  }
+
+  /// Returns the terminator of the given basic block in the layout,
+  /// or `None` if the basic block is empty or not terminated.
+  fn terminator(&self, bb: BasicBlock) -> Option<Value> {
+    let node = self.layout.bbs().node(&bb).expect("`bb` does not exist");
+    let inst = *node.insts().back_key()?;
+    self.dfg.value(inst).kind().is_terminator().then_some(inst)
+  }
+
+  /// Splits the edge at the given index of the given terminator.
+  fn split_edge_at(&mut self, term: Value, index: usize) -> BasicBlock {
+    let bb = self.dfg.new_bb().basic_block(None);
+    let (succ, args) = {
+      let (succ, args) = self.dfg.value(term).kind().edges()[index];
+      (succ, args.to_vec())
+    };
+    self.dfg.rewrite_edge(term, index, bb, vec![]);
+    let jump = self.dfg.new_value().jump_with_args(succ, args);
+    self
+      .layout
+      .bbs_mut()
+      .cursor_mut(succ)
+      .insert_key_before(bb)
+      .unwrap();
+    self
+      .layout
+      .bb_mut(bb)
+      .insts_mut()
+      .push_key_back(jump)
+      .unwrap();
+    bb
+  }
+
+  /// Returns a reference to the layout.
+  pub fn layout(&self) -> &Layout {
+    &self.layout
+  }
+
+  /// Returns a mutable reference to the layout.
+  pub fn layout_mut(&mut self) -> &mut Layout {
+    &mut self.layout
+  }
+
+  /// Enables the mutation log of the data flow graph, and records
+  /// insertions and removals of instructions in the layout to the
+  /// same log.
+  ///
+  /// Layouts that replace the current layout through
+  /// [`FunctionData::layout_mut`] are not recorded.
+  /// See [`mutation`](crate::ir::mutation) for details.
+  pub fn enable_mutation_log(&mut self) {
+    self.dfg.enable_mutation_log();
+    self.layout.set_mutation_log(self.dfg.mutation_log_cell());
+  }
+
+  /// Sets the function attributes, returns the updated function.
+  pub fn with_func_attrs(mut self, func_attrs: FuncAttrs) -> Self {
+    self.func_attrs = func_attrs;
+    self
+  }
+
+  /// Returns a reference to the function attributes.
+  pub fn func_attrs(&self) -> &FuncAttrs {
+    &self.func_attrs
+  }
+
+  /// Returns a mutable reference to the function attributes.
+  pub fn func_attrs_mut(&mut self) -> &mut FuncAttrs {
+    &mut self.func_attrs
+  }
+
+  /// Returns `true` if the function is marked as pure.
+  pub fn is_pure(&self) -> bool {
+    self.func_attrs.pure
+  }
+
+  /// Returns `true` if the function is marked as never returns.
+  pub fn is_noreturn(&self) -> bool {
+    self.func_attrs.noreturn
+  }
+
+  /// Returns a reference to the function's attributes.
+  pub fn attrs(&self) -> &BTreeMap<String, AttrValue> {
+    &self.attrs
+  }
+
+  /// Returns a reference to the attribute `key` of the function.
+  pub fn attr(&self, key: &str) -> Option<&AttrValue> {
+    self.attrs.get(key)
+  }
+
+  /// Sets the attribute `key` of the function to `attr`.
+  /// Returns the old attribute if exists.
+  pub fn set_attr(&mut self, key: &str, attr: AttrValue) -> Option<AttrValue> {
+    self.attrs.insert(key.into(), attr)
+  }
+
+  /// Removes the attribute `key` of the function.
+  /// Returns the removed attribute if exists.
+  pub fn remove_attr(&mut self, key: &str) -> Option<AttrValue> {
+    self.attrs.remove(key)
+  }
+}
+
+/// Attributes of a function, which describe facts about the function
+/// that may not be derived from its body (or declaration).
+///
+/// In the text form, function attributes are written after the
+/// function signature, like `decl @exit(i32) #[noreturn]`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FuncAttrs {
+  /// The function has no side effects, so calls to this function
+  /// can be removed if their results are unused.
+  pub pure: bool,
+  /// The function never returns to its caller.
+  pub noreturn: bool,
+  /// The calling convention of the function, `None` for the default one.
+  pub conv: Option<String>,
+}
+
+impl FuncAttrs {
+  /// Returns `true` if no attribute is set.
+  pub fn is_empty(&self) -> bool {
+    *self == Self::default()
+  }
+}
+
+impl fmt::Display for FuncAttrs {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut attrs = Vec::new();
+    if self.pure {
+      attrs.push("pure".to_string());
+    }
+    if self.noreturn {
+      attrs.push("noreturn".to_string());
+    }
+    if let Some(conv) = &self.conv {
+      attrs.push(format!("conv = {}", AttrValue::Str(conv.clone())));
+    }
+    write!(f, "#[{}]", attrs.join(", "))
+  }
+}
+
+/// A handle of Koopa IR basic block.
+///
+/// You can fetch [`BasicBlockData`] from [`DataFlowGraph`] in
+/// [`FunctionData`] by using this handle.
+///
+/// Basic block handles are printed as `BasicBlock(#7)` in debug form,
+/// see [`Value`] for the meaning of the number.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BasicBlock(pub(in crate::ir) BasicBlockId);
+
+impl fmt::Debug for BasicBlock {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "BasicBlock({})", HandleId(self.0))
+  }
+}
+
+/// Formats the ID of a value or basic block handle.
+///
+/// Local handles are formatted as `#` followed by the slot index in the
+/// data flow graph, and the generation of the slot if it is not zero
+/// (like `#42.1`). Global value handles are formatted as `#g` followed
+/// by the ID (like `#g42`).
+struct HandleId(NonZeroU64);
+
+impl fmt::Display for HandleId {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if is_global_id(self.0) {
+      return write!(f, "#g{}", self.0);
+    }
+    match decode_id(self.0) {
+      (index, 0) => write!(f, "#{}", index),
+      (index, generation) => write!(f, "#{}.{}", index, generation),
+    }
+  }
+}
+
+/// Data of Koopa IR basic block.
+///
+/// `BasicBlockData` only holds parameters about this basic block, and
+/// which values (branch/jump instructions) the current basic block is
+/// used by. Other information, such as the data and order of instructions
+/// in this basic block, can be found in [`FunctionData`] (in the data flow
+/// graph or the layout).
+pub struct BasicBlockData {
+  name: Option<String>,
+  params: Vec<Value>,
+  span: Option<SourceSpan>,
+  pub(in crate::ir) used_by: HashSet<Value>,
+}
+
+impl BasicBlockData {
+  pub(in crate::ir) fn new(name: Option<String>) -> Self {
+    Self {
+      name,
+      params: Vec::new(),
+      span: None,
+      used_by: HashSet::new(),
+    }
+  }
+
+  pub(in crate::ir) fn with_params(name: Option<String>, params: Vec<Value>) -> Self {
+    Self {
+      name,
+      params,
+      span: None,
+      used_by: HashSet::new(),
+    }
+  }
+
+  /// Returns a reference to the basic block's name.
+  pub fn name(&self) -> &Option<String> {
+    &self.name
+  }
+
+  /// Sets the basic block's name.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given name (if exists) is not a valid symbol name
+  /// (see [`is_valid_symbol`]).
+  pub fn set_name(&mut self, name: Option<String>) {
+    self.name = name.map(|n| checked_symbol(n, "basic block"));
+  }
+
+  /// Returns the source location of the basic block.
+  ///
+  /// Returns `None` if the basic block is not created by the frontend.
+  pub fn span(&self) -> Option<SourceSpan> {
+    self.span
+  }
+
+  /// Sets the source location of the basic block.
+  pub fn set_span(&mut self, span: Option<SourceSpan>) {
+    self.span = span;
+  }
+
+  /// Returns a reference to the basic block parameters.
+  pub fn params(&self) -> &[Value] {
+    &self.params
   }
 
   /// Returns a mutable reference to the basic block parameters.
@@ -410,9 +1906,25 @@ impl BasicBlockData {
 ///
 /// You can fetch [`ValueData`] from [`DataFlowGraph`] in [`FunctionData`]
 /// by using this handle.
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+///
+/// Value handles are displayed as `#42` (or `#g42` for global values),
+/// and printed as `Value(#42)` in debug form. For local values, the
+/// number is the index of the value in the data flow graph.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Value(pub(in crate::ir) ValueId);
 
+impl fmt::Display for Value {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", HandleId(self.0))
+  }
+}
+
+impl fmt::Debug for Value {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Value({})", HandleId(self.0))
+  }
+}
+
 impl Value {
   /// Returns `true` if the current value handle is a global value handle.
   pub fn is_global(self) -> bool {
@@ -420,15 +1932,131 @@ impl Value {
   }
 }
 
+/// Token of a local value, for detecting stale handles in side tables
+/// keyed by values. Returned by [`DataFlowGraph::token`].
+///
+/// A token records the slot index and the generation of the value in
+/// the data flow graph. The slot of a removed value can be reused by a
+/// new value with the next generation, so the new value has the same
+/// index, but is a different value with a different token. Check tokens
+/// by [`DataFlowGraph::is_valid`]. See [`DataFlowGraph`] for the handle
+/// stability guarantees.
+///
+/// Tokens are displayed in the same form as value handles, like `#42`,
+/// or `#42.1` for values with non-zero generations.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ValueToken(pub(in crate::ir) Value);
+
+impl ValueToken {
+  /// Returns the value handle of the token.
+  pub fn value(self) -> Value {
+    self.0
+  }
+
+  /// Returns the slot index of the value in the data flow graph.
+  pub fn index(self) -> u32 {
+    decode_id(self.0 .0).0
+  }
+
+  /// Returns the generation of the slot, which is increased every time
+  /// a value in the slot is removed.
+  pub fn generation(self) -> u8 {
+    decode_id(self.0 .0).1
+  }
+}
+
+impl fmt::Display for ValueToken {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+impl fmt::Debug for ValueToken {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "ValueToken({})", self.0)
+  }
+}
+
+/// Handle of a local instruction, which can be inserted into the layout
+/// of a function.
+///
+/// `Inst` can be used wherever a [`Value`] is expected by converting it
+/// with [`Value::from`] or by dereferencing it. Layout insertion methods
+/// like [`FunctionData::push_inst`] accept `Inst`s, so constants
+/// ([`Const`]) can not be inserted by mistake.
+///
+/// Use [`DataFlowGraph::as_inst`] to get a checked handle. Converting
+/// from a [`Value`] with [`Inst::from`] is not checked, in which case the
+/// value is checked when it is inserted into the layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Inst(Value);
+
+/// Handle of a constant, which can be used as the initializer of a
+/// global allocation or an element of an aggregate.
+///
+/// `Const` can be used wherever a [`Value`] is expected by converting it
+/// with [`Value::from`] or by dereferencing it. Global allocation
+/// builders accept `Const`s, so instructions ([`Inst`]) can not be used
+/// as initializers by mistake.
+///
+/// Use [`DataFlowGraph::as_const`] to get a checked handle. Converting
+/// from a [`Value`] with [`Const::from`] is not checked, in which case the
+/// value is checked when the global allocation is created.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Const(Value);
+
+macro_rules! impl_value_wrapper {
+  ($name:ident) => {
+    impl $name {
+      /// Returns the underlying value handle.
+      pub fn value(self) -> Value {
+        self.0
+      }
+    }
+
+    impl From<Value> for $name {
+      fn from(value: Value) -> Self {
+        Self(value)
+      }
+    }
+
+    impl From<$name> for Value {
+      fn from(handle: $name) -> Self {
+        handle.0
+      }
+    }
+
+    impl Deref for $name {
+      type Target = Value;
+
+      fn deref(&self) -> &Value {
+        &self.0
+      }
+    }
+  };
+}
+
+impl_value_wrapper!(Inst);
+impl_value_wrapper!(Const);
+
 /// Data of Koopa IR value.
 ///
 /// `ValueData` can hold the type and the kind of the value, and which
 /// values the current value is used by.
-#[derive(Debug)]
+///
+/// The debug form of `ValueData` only shows the name, the type, the kind,
+/// the operands and the number of users. Use
+/// [`DataFlowGraph::display_value`] to show the value in text form.
+///
+/// Cloning a `ValueData` copies everything except the `used_by` set,
+/// which is left empty. To clone a value together with its operands
+/// into another function, see [`clone_expr`](crate::ir::dfg::clone_expr).
 pub struct ValueData {
   ty: Type,
   name: Option<String>,
   kind: ValueKind,
+  pub(in crate::ir) span: Option<SourceSpan>,
+  pub(in crate::ir) attrs: BTreeMap<String, AttrValue>,
   pub(in crate::ir) used_by: HashSet<Value>,
 }
 
@@ -439,6 +2067,8 @@ impl ValueData {
       ty,
       name: None,
       kind,
+      span: None,
+      attrs: BTreeMap::new(),
       used_by: HashSet::new(),
     }
   }
@@ -448,38 +2078,161 @@ impl ValueData {
     &self.ty
   }
 
-  /// Returns a reference to the value's name.
-  pub fn name(&self) -> &Option<String> {
-    &self.name
+  /// Returns a reference to the value's name.
+  pub fn name(&self) -> &Option<String> {
+    &self.name
+  }
+
+  /// Sets the name of this value.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given name (if exists) is not a valid symbol name
+  /// (see [`is_valid_symbol`]).
+  pub(in crate::ir) fn set_name(&mut self, name: Option<String>) {
+    self.name = name.map(|n| checked_symbol(n, "value"));
+  }
+
+  /// Returns a reference to the value's kind.
+  pub fn kind(&self) -> &ValueKind {
+    &self.kind
+  }
+
+  /// Returns a mutable reference to the value's kind.
+  pub fn kind_mut(&mut self) -> &mut ValueKind {
+    &mut self.kind
+  }
+
+  /// Returns a list of all value operands of the value.
+  ///
+  /// The list is copied from the value, so it can be held while the
+  /// data flow graph is modified. Operands are listed in a fixed order,
+  /// which is also the order of operand indices (see
+  /// [`ValueKind::operand`]):
+  ///
+  /// * Aggregates: elements in order.
+  /// * Global allocations: the initializer.
+  /// * Loads: the source.
+  /// * Stores: the value, then the destination.
+  /// * Pointer calculations: the source, then the index.
+  /// * Binary operations: the left-hand side, then the right-hand side.
+  /// * Branches: the condition, then the arguments of the true target,
+  ///   then the arguments of the false target.
+  /// * Jumps and calls: arguments in order.
+  /// * Returns: the return value if exists.
+  /// * Other values have no value operands.
+  pub fn operands(&self) -> ValueList {
+    self.kind.value_uses().collect()
+  }
+
+  /// Returns a list of all basic block operands of the value.
+  ///
+  /// Branches list the true target, then the false target. Jumps list
+  /// the target. Other values have no basic block operands.
+  pub fn bb_operands(&self) -> Vec<BasicBlock> {
+    self.kind.bb_uses().collect()
+  }
+
+  /// Replaces all value operands equal to `old` with `new`.
+  /// Returns the number of replaced operands.
+  ///
+  /// This method only modifies the value itself, the `used_by` sets of
+  /// the operands are not updated. Use
+  /// [`DataFlowGraph::set_value_operand`](crate::ir::dfg::DataFlowGraph::set_value_operand)
+  /// to keep them updated.
+  pub fn replace_operand(&mut self, old: Value, new: Value) -> usize {
+    let mut count = 0;
+    for index in 0..self.kind.num_operands() {
+      let operand = self.kind.operand_mut(index).unwrap();
+      if *operand == old {
+        *operand = new;
+        count += 1;
+      }
+    }
+    count
+  }
+
+  /// Returns a reference to the values that the current value is used by.
+  pub fn used_by(&self) -> &HashSet<Value> {
+    &self.used_by
+  }
+
+  /// Returns the source location of the value.
+  ///
+  /// Returns `None` if the value is not created by the frontend.
+  pub fn span(&self) -> Option<SourceSpan> {
+    self.span
+  }
+
+  /// Sets the source location of the value.
+  pub fn set_span(&mut self, span: Option<SourceSpan>) {
+    self.span = span;
+  }
+
+  /// Returns a reference to the value's attributes.
+  pub fn attrs(&self) -> &BTreeMap<String, AttrValue> {
+    &self.attrs
+  }
+
+  /// Returns a reference to the attribute `key` of the value.
+  pub fn attr(&self, key: &str) -> Option<&AttrValue> {
+    self.attrs.get(key)
   }
 
-  /// Sets the name of this value.
-  ///
-  /// # Panics
-  ///
-  /// Panics if the given name (if exists) not starts with `%` or `@`.
-  pub(in crate::ir) fn set_name(&mut self, name: Option<String>) {
-    assert!(
-      name.as_ref().map_or(true, |n| n.len() > 1
-        && (n.starts_with('%') || n.starts_with('@'))),
-      "invalid value name"
-    );
-    self.name = name;
+  /// Sets the attribute `key` of the value to `attr`.
+  /// Returns the old attribute if exists.
+  pub fn set_attr(&mut self, key: &str, attr: AttrValue) -> Option<AttrValue> {
+    self.attrs.insert(key.into(), attr)
   }
 
-  /// Returns a reference to the value's kind.
-  pub fn kind(&self) -> &ValueKind {
-    &self.kind
+  /// Removes the attribute `key` of the value.
+  /// Returns the removed attribute if exists.
+  pub fn remove_attr(&mut self, key: &str) -> Option<AttrValue> {
+    self.attrs.remove(key)
   }
+}
 
-  /// Returns a mutable reference to the value's kind.
-  pub fn kind_mut(&mut self) -> &mut ValueKind {
-    &mut self.kind
+impl fmt::Debug for ValueData {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let kind = DebugKind(&self.kind);
+    let operands: Vec<_> = self.kind.value_uses().collect();
+    f.debug_struct("ValueData")
+      .field("name", &self.name)
+      .field("ty", &self.ty)
+      .field("kind", &kind)
+      .field("operands", &operands)
+      .field("used_by", &self.used_by.len())
+      .finish()
   }
+}
 
-  /// Returns a reference to the values that the current value is used by.
-  pub fn used_by(&self) -> &HashSet<Value> {
-    &self.used_by
+/// Formats the name of the given [`ValueKind`], with the integer value,
+/// the argument index or the binary operator if exists.
+struct DebugKind<'a>(&'a ValueKind);
+
+impl fmt::Debug for DebugKind<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.0 {
+      ValueKind::Integer(v) => write!(f, "Integer({})", v.value()),
+      ValueKind::ZeroInit(_) => write!(f, "ZeroInit"),
+      ValueKind::Undef(_) => write!(f, "Undef"),
+      ValueKind::Aggregate(_) => write!(f, "Aggregate"),
+      ValueKind::Bytes(v) => write!(f, "Bytes({})", v.bytes().len()),
+      ValueKind::FuncArgRef(v) => write!(f, "FuncArgRef({})", v.index()),
+      ValueKind::BlockArgRef(v) => write!(f, "BlockArgRef({})", v.index()),
+      ValueKind::Alloc(_) => write!(f, "Alloc"),
+      ValueKind::GlobalAlloc(_) => write!(f, "GlobalAlloc"),
+      ValueKind::Load(_) => write!(f, "Load"),
+      ValueKind::Store(_) => write!(f, "Store"),
+      ValueKind::GetPtr(_) => write!(f, "GetPtr"),
+      ValueKind::GetElemPtr(_) => write!(f, "GetElemPtr"),
+      ValueKind::Binary(v) => write!(f, "Binary({})", v.op()),
+      ValueKind::Branch(_) => write!(f, "Branch"),
+      ValueKind::Jump(_) => write!(f, "Jump"),
+      ValueKind::Call(_) => write!(f, "Call"),
+      ValueKind::Return(_) => write!(f, "Return"),
+      ValueKind::Unreachable(_) => write!(f, "Unreachable"),
+    }
   }
 }
 
@@ -490,11 +2243,119 @@ impl Clone for ValueData {
       ty: self.ty.clone(),
       name: self.name.clone(),
       kind: self.kind.clone(),
+      span: self.span,
+      attrs: self.attrs.clone(),
       used_by: HashSet::new(),
     }
   }
 }
 
+/// Location of an IR entity in the source code, in byte offsets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SourceSpan {
+  /// The start offset (inclusive).
+  pub start: usize,
+  /// The end offset (exclusive).
+  pub end: usize,
+}
+
+impl SourceSpan {
+  /// Creates a new source span.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `start` is greater than `end`.
+  pub fn new(start: usize, end: usize) -> Self {
+    assert!(start <= end, "`start` must not be greater than `end`");
+    Self { start, end }
+  }
+
+  /// Returns the line number and the column number in bytes (both start
+  /// from 1) of the start offset in the given source code.
+  pub fn start_line_col(&self, src: &str) -> (usize, usize) {
+    Self::line_col(src, self.start)
+  }
+
+  /// Returns the line number and the column number in bytes (both start
+  /// from 1) of the end offset in the given source code.
+  pub fn end_line_col(&self, src: &str) -> (usize, usize) {
+    Self::line_col(src, self.end)
+  }
+
+  /// Converts the given byte offset to the line number and
+  /// the column number.
+  fn line_col(src: &str, offset: usize) -> (usize, usize) {
+    let before = &src.as_bytes()[..offset.min(src.len())];
+    let line = before.iter().filter(|c| **c == b'\n').count() + 1;
+    let line_start = before
+      .iter()
+      .rposition(|c| *c == b'\n')
+      .map_or(0, |i| i + 1);
+    (line, offset - line_start + 1)
+  }
+}
+
+/// Value of an attribute of IR values or functions.
+///
+/// Attributes are used to attach extra information to values and
+/// functions. They do not affect the semantics of the program.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AttrValue {
+  /// Integer attribute.
+  Int(i64),
+  /// String attribute.
+  Str(String),
+  /// Boolean attribute.
+  Bool(bool),
+}
+
+impl fmt::Display for AttrValue {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      AttrValue::Int(i) => write!(f, "{}", i),
+      AttrValue::Bool(b) => write!(f, "{}", b),
+      AttrValue::Str(s) => {
+        write!(f, "\"")?;
+        for c in s.chars() {
+          match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            _ => write!(f, "{}", c)?,
+          }
+        }
+        write!(f, "\"")
+      }
+    }
+  }
+}
+
+impl From<i64> for AttrValue {
+  fn from(i: i64) -> Self {
+    AttrValue::Int(i)
+  }
+}
+
+impl From<bool> for AttrValue {
+  fn from(b: bool) -> Self {
+    AttrValue::Bool(b)
+  }
+}
+
+impl From<&str> for AttrValue {
+  fn from(s: &str) -> Self {
+    AttrValue::Str(s.into())
+  }
+}
+
+impl From<String> for AttrValue {
+  fn from(s: String) -> Self {
+    AttrValue::Str(s)
+  }
+}
+
 /// Kind of Koopa IR value.
 #[derive(Clone, Debug)]
 pub enum ValueKind {
@@ -506,6 +2367,8 @@ pub enum ValueKind {
   Undef(values::Undef),
   /// Aggregate constant.
   Aggregate(values::Aggregate),
+  /// Raw byte data constant.
+  Bytes(values::Bytes),
   /// Function argument reference.
   FuncArgRef(values::FuncArgRef),
   /// Basic block argument reference.
@@ -532,11 +2395,44 @@ pub enum ValueKind {
   Call(values::Call),
   /// Function return.
   Return(values::Return),
+  /// Unreachable terminator.
+  Unreachable(values::Unreachable),
+}
+
+/// Implements `as_*` and `as_*_mut` accessors for the given
+/// variants of [`ValueKind`].
+macro_rules! impl_as_kind {
+  ($($kind:ident, $as:ident, $as_mut:ident;)*) => {
+    $(
+      #[doc = concat!(
+        "Returns a reference to the [`values::", stringify!($kind),
+        "`], or `None` if the `ValueKind` is not `", stringify!($kind), "`."
+      )]
+      pub fn $as(&self) -> Option<&values::$kind> {
+        match self {
+          ValueKind::$kind(v) => Some(v),
+          _ => None,
+        }
+      }
+
+      #[doc = concat!(
+        "Returns a mutable reference to the [`values::", stringify!($kind),
+        "`], or `None` if the `ValueKind` is not `", stringify!($kind), "`."
+      )]
+      pub fn $as_mut(&mut self) -> Option<&mut values::$kind> {
+        match self {
+          ValueKind::$kind(v) => Some(v),
+          _ => None,
+        }
+      }
+    )*
+  };
 }
 
 impl ValueKind {
-  /// Returns an iterator of all values that used by the `ValueKind`.
-  pub fn value_uses(&self) -> ValueUses {
+  /// Returns an iterator of all values that used by the `ValueKind`,
+  /// in the order described in [`ValueData::operands`].
+  pub fn value_uses(&self) -> ValueUses<'_> {
     ValueUses {
       kind: self,
       index: 0,
@@ -544,22 +2440,300 @@ impl ValueKind {
   }
 
   /// Returns an iterator of all basic blocks that used by the `ValueKind`.
-  pub fn bb_uses(&self) -> BasicBlockUses {
+  pub fn bb_uses(&self) -> BasicBlockUses<'_> {
     BasicBlockUses {
       kind: self,
       index: 0,
     }
   }
 
+  /// Returns the number of values that used by the `ValueKind`.
+  pub fn num_operands(&self) -> usize {
+    self.value_uses().count()
+  }
+
+  /// Returns the value operand at the given index.
+  ///
+  /// Operands are indexed in the same order as [`ValueKind::value_uses`].
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given index is out of range.
+  pub fn operand(&self, index: usize) -> Value {
+    self.value_uses().nth(index).unwrap_or_else(|| {
+      panic!(
+        "operand index {} out of range ({} operands)",
+        index,
+        self.num_operands()
+      )
+    })
+  }
+
+  /// Sets the value operand at the given index to the given value.
+  /// Returns the old operand.
+  ///
+  /// Operands are indexed in the same order as [`ValueKind::value_uses`].
+  /// This method only modifies the `ValueKind` itself, use
+  /// [`DataFlowGraph::set_value_operand`](crate::ir::dfg::DataFlowGraph::set_value_operand)
+  /// to keep the `used_by` sets updated.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given index is out of range.
+  pub fn set_operand(&mut self, index: usize, value: Value) -> Value {
+    let num = self.num_operands();
+    let operand = self
+      .operand_mut(index)
+      .unwrap_or_else(|| panic!("operand index {} out of range ({} operands)", index, num));
+    std::mem::replace(operand, value)
+  }
+
+  /// Returns a mutable reference to the value operand at the given index,
+  /// or `None` if the index is out of range.
+  fn operand_mut(&mut self, index: usize) -> Option<&mut Value> {
+    match self {
+      ValueKind::Aggregate(v) => v.elems_mut().get_mut(index),
+      ValueKind::GlobalAlloc(v) => (index == 0).then(|| v.init_mut()),
+      ValueKind::Load(v) => (index == 0).then(|| v.src_mut()),
+      ValueKind::Store(v) => match index {
+        0 => Some(v.value_mut()),
+        1 => Some(v.dest_mut()),
+        _ => None,
+      },
+      ValueKind::GetPtr(v) => match index {
+        0 => Some(v.src_mut()),
+        1 => Some(v.index_mut()),
+        _ => None,
+      },
+      ValueKind::GetElemPtr(v) => match index {
+        0 => Some(v.src_mut()),
+        1 => Some(v.index_mut()),
+        _ => None,
+      },
+      ValueKind::Binary(v) => match index {
+        0 => Some(v.lhs_mut()),
+        1 => Some(v.rhs_mut()),
+        _ => None,
+      },
+      ValueKind::Branch(v) => {
+        let tlen = v.true_args().len();
+        if index == 0 {
+          Some(v.cond_mut())
+        } else if index <= tlen {
+          v.true_args_mut().get_mut(index - 1)
+        } else {
+          v.false_args_mut().get_mut(index - tlen - 1)
+        }
+      }
+      ValueKind::Jump(v) => v.args_mut().get_mut(index),
+      ValueKind::Call(v) => v.args_mut().get_mut(index),
+      ValueKind::Return(v) => match index {
+        0 => v.value_mut().as_mut(),
+        _ => None,
+      },
+      _ => None,
+    }
+  }
+
+  /// Returns the outgoing edges of the `ValueKind`, i.e. the target basic
+  /// blocks and the argument lists passed to them.
+  ///
+  /// Branches have two edges (the true edge comes first, even if both
+  /// edges target the same basic block), jumps have one edge, and other
+  /// kinds (including returns) have no edges.
+  pub fn edges(&self) -> Vec<(BasicBlock, &[Value])> {
+    match self {
+      ValueKind::Branch(br) => vec![
+        (br.true_bb(), br.true_args()),
+        (br.false_bb(), br.false_args()),
+      ],
+      ValueKind::Jump(jump) => vec![(jump.target(), jump.args())],
+      _ => Vec::new(),
+    }
+  }
+
+  /// Sets the target basic block of the edge at the given index.
+  /// Returns the old target.
+  ///
+  /// Edges are indexed in the same order as [`ValueKind::edges`].
+  /// This method only modifies the `ValueKind` itself, use
+  /// [`DataFlowGraph::rewrite_edge`](crate::ir::dfg::DataFlowGraph::rewrite_edge)
+  /// to keep the `used_by` sets updated.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given index is out of range.
+  pub fn set_edge_target(&mut self, index: usize, bb: BasicBlock) -> BasicBlock {
+    std::mem::replace(self.edge_mut(index).0, bb)
+  }
+
+  /// Returns a mutable reference to the argument list of the edge at the
+  /// given index.
+  ///
+  /// Edges are indexed in the same order as [`ValueKind::edges`].
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given index is out of range.
+  pub fn edge_args_mut(&mut self, index: usize) -> &mut ValueList {
+    self.edge_mut(index).1
+  }
+
+  /// Returns mutable references to the target and the argument list of
+  /// the edge at the given index.
+  fn edge_mut(&mut self, index: usize) -> (&mut BasicBlock, &mut ValueList) {
+    let num = self.edges().len();
+    let edge = match self {
+      ValueKind::Branch(br) => br.edge_mut(index),
+      ValueKind::Jump(jump) => (index == 0).then(|| jump.edge_mut()),
+      _ => None,
+    };
+    edge.unwrap_or_else(|| panic!("edge index {} out of range ({} edges)", index, num))
+  }
+
   /// Returns `true` if the `ValueKind` represents a constant value.
+  ///
+  /// Constant values are integers, zero initializers, undefined values,
+  /// byte data and aggregates (which can only contain constants).
   pub fn is_const(&self) -> bool {
-    matches!(
-      self,
+    match self {
       ValueKind::Integer(..)
-        | ValueKind::ZeroInit(..)
-        | ValueKind::Undef(..)
-        | ValueKind::Aggregate(..)
-    )
+      | ValueKind::ZeroInit(..)
+      | ValueKind::Undef(..)
+      | ValueKind::Aggregate(..)
+      | ValueKind::Bytes(..) => true,
+      ValueKind::FuncArgRef(..)
+      | ValueKind::BlockArgRef(..)
+      | ValueKind::Alloc(..)
+      | ValueKind::GlobalAlloc(..)
+      | ValueKind::Load(..)
+      | ValueKind::Store(..)
+      | ValueKind::GetPtr(..)
+      | ValueKind::GetElemPtr(..)
+      | ValueKind::Binary(..)
+      | ValueKind::Branch(..)
+      | ValueKind::Jump(..)
+      | ValueKind::Call(..)
+      | ValueKind::Return(..)
+      | ValueKind::Unreachable(..) => false,
+    }
+  }
+
+  /// Returns `true` if the `ValueKind` represents a terminator
+  /// of basic block, i.e. a branch, a jump, a return or an unreachable.
+  pub fn is_terminator(&self) -> bool {
+    match self {
+      ValueKind::Branch(..)
+      | ValueKind::Jump(..)
+      | ValueKind::Return(..)
+      | ValueKind::Unreachable(..) => true,
+      ValueKind::Integer(..)
+      | ValueKind::ZeroInit(..)
+      | ValueKind::Undef(..)
+      | ValueKind::Aggregate(..)
+      | ValueKind::Bytes(..)
+      | ValueKind::FuncArgRef(..)
+      | ValueKind::BlockArgRef(..)
+      | ValueKind::Alloc(..)
+      | ValueKind::GlobalAlloc(..)
+      | ValueKind::Load(..)
+      | ValueKind::Store(..)
+      | ValueKind::GetPtr(..)
+      | ValueKind::GetElemPtr(..)
+      | ValueKind::Binary(..)
+      | ValueKind::Call(..) => false,
+    }
+  }
+
+  /// Returns `true` if the `ValueKind` represents a memory operation,
+  /// including allocations, loads, stores and pointer calculations.
+  pub fn is_memory_op(&self) -> bool {
+    match self {
+      ValueKind::Alloc(..)
+      | ValueKind::GlobalAlloc(..)
+      | ValueKind::Load(..)
+      | ValueKind::Store(..)
+      | ValueKind::GetPtr(..)
+      | ValueKind::GetElemPtr(..) => true,
+      ValueKind::Integer(..)
+      | ValueKind::ZeroInit(..)
+      | ValueKind::Undef(..)
+      | ValueKind::Aggregate(..)
+      | ValueKind::Bytes(..)
+      | ValueKind::FuncArgRef(..)
+      | ValueKind::BlockArgRef(..)
+      | ValueKind::Binary(..)
+      | ValueKind::Branch(..)
+      | ValueKind::Jump(..)
+      | ValueKind::Call(..)
+      | ValueKind::Return(..)
+      | ValueKind::Unreachable(..) => false,
+    }
+  }
+
+  /// Returns `true` if the `ValueKind` may have side effects, i.e. it can
+  /// not be removed even if it is not used by any other values.
+  ///
+  /// Stores, function calls and terminators are considered to have
+  /// side effects.
+  pub fn has_side_effects(&self) -> bool {
+    match self {
+      ValueKind::Store(..)
+      | ValueKind::Call(..)
+      | ValueKind::Branch(..)
+      | ValueKind::Jump(..)
+      | ValueKind::Return(..)
+      | ValueKind::Unreachable(..) => true,
+      ValueKind::Integer(..)
+      | ValueKind::ZeroInit(..)
+      | ValueKind::Undef(..)
+      | ValueKind::Aggregate(..)
+      | ValueKind::Bytes(..)
+      | ValueKind::FuncArgRef(..)
+      | ValueKind::BlockArgRef(..)
+      | ValueKind::Alloc(..)
+      | ValueKind::GlobalAlloc(..)
+      | ValueKind::Load(..)
+      | ValueKind::GetPtr(..)
+      | ValueKind::GetElemPtr(..)
+      | ValueKind::Binary(..) => false,
+    }
+  }
+
+  /// Returns the value of the integer constant, or `None` if the
+  /// `ValueKind` is not an integer constant.
+  pub fn as_integer(&self) -> Option<i32> {
+    match self {
+      ValueKind::Integer(v) => Some(v.value()),
+      _ => None,
+    }
+  }
+
+  /// Returns a mutable reference to the integer constant, or `None` if
+  /// the `ValueKind` is not an integer constant.
+  pub fn as_integer_mut(&mut self) -> Option<&mut values::Integer> {
+    match self {
+      ValueKind::Integer(v) => Some(v),
+      _ => None,
+    }
+  }
+
+  impl_as_kind! {
+    Aggregate, as_aggregate, as_aggregate_mut;
+    Bytes, as_bytes, as_bytes_mut;
+    FuncArgRef, as_func_arg_ref, as_func_arg_ref_mut;
+    BlockArgRef, as_block_arg_ref, as_block_arg_ref_mut;
+    GlobalAlloc, as_global_alloc, as_global_alloc_mut;
+    Load, as_load, as_load_mut;
+    Store, as_store, as_store_mut;
+    GetPtr, as_get_ptr, as_get_ptr_mut;
+    GetElemPtr, as_get_elem_ptr, as_get_elem_ptr_mut;
+    Binary, as_binary, as_binary_mut;
+    Branch, as_branch, as_branch_mut;
+    Jump, as_jump, as_jump_mut;
+    Call, as_call, as_call_mut;
+    Return, as_return, as_return_mut;
+    Unreachable, as_unreachable, as_unreachable_mut;
   }
 
   /// Returns `true` if the `ValueKind` represents a global allocation.
@@ -581,6 +2755,7 @@ impl ValueKind {
         | ValueKind::Jump(..)
         | ValueKind::Call(..)
         | ValueKind::Return(..)
+        | ValueKind::Unreachable(..)
     )
   }
 }
@@ -678,3 +2853,804 @@ impl<'a> Iterator for BasicBlockUses<'a> {
     }
   }
 }
+
+#[cfg(test)]
+mod test {
+  use crate::analysis::verifier::verify;
+  use crate::back::KoopaGenerator;
+  use crate::front::Driver;
+  use crate::ir::builder_traits::*;
+  use crate::ir::{BinaryOp, Error, FunctionData, Program, Type, ValueKind, ValueLocation};
+  use std::str;
+
+  #[test]
+  fn split_critical_edges() {
+    let driver: Driver<_> = r#"
+      fun @f(%c: i32): i32 {
+      %entry:
+        br %c, %a, %b(1)
+      %a:
+        br %c, %b(2), %end
+      %b(%x: i32):
+        br %x, %end, %end
+      %end:
+        ret 0
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    let func = program.func_layout()[0];
+    assert_eq!(program.func_mut(func).split_critical_edges(), 5);
+    assert_eq!(program.func_mut(func).split_critical_edges(), 0);
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    assert_eq!(
+      str::from_utf8(&gen.writer()).unwrap(),
+      r#"fun @f(%c: i32): i32 {
+%entry:
+  br %c, %a, %0
+
+%a:
+  br %c, %1, %2
+
+%0:
+  jump %b(1)
+
+%1:
+  jump %b(2)
+
+%b(%x: i32):
+  br %x, %3, %4
+
+%2:
+  jump %end
+
+%3:
+  jump %end
+
+%4:
+  jump %end
+
+%end:
+  ret 0
+}
+"#
+    );
+  }
+
+  #[test]
+  fn split_bb() {
+    let driver: Driver<_> = r#"
+      fun @f(%c: i32): i32 {
+      %entry:
+        %0 = add %c, 1
+        %1 = mul %0, 2
+        jump %end(%0)
+      %end(%x: i32):
+        ret %x
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    let func = program.func_mut(program.func_layout()[0]);
+    let entry = func.layout().entry_bb().unwrap();
+    let mul = func
+      .layout()
+      .bbs()
+      .node(&entry)
+      .unwrap()
+      .insts()
+      .keys()
+      .nth(1)
+      .copied()
+      .unwrap();
+    let bb = func.split_bb(mul);
+    assert_eq!(func.layout().parent_bb(mul), Some(bb));
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    assert_eq!(
+      str::from_utf8(&gen.writer()).unwrap(),
+      r#"fun @f(%c: i32): i32 {
+%entry:
+  %0 = add %c, 1
+  jump %1
+
+%1:
+  %2 = mul %0, 2
+  jump %end(%0)
+
+%end(%x: i32):
+  ret %x
+}
+"#
+    );
+  }
+
+  #[test]
+  fn remove_bb_forced() {
+    let driver: Driver<_> = r#"
+      fun @f(@c: i32): i32 {
+      %entry:
+        br @c, %a, %b(1)
+
+      %a:
+        %0 = add @c, 1
+        jump %b(%0)
+
+      %b(%x: i32):
+        jump %end(%x)
+
+      %end(%y: i32):
+        ret %y
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    let func = program.func_mut(program.func_layout()[0]);
+    let bbs: Vec<_> = func.layout().bbs().keys().copied().collect();
+    let (a, b, end) = (bbs[1], bbs[2], bbs[3]);
+    // `%b` is used by `%entry` and `%a`, and `%x` is used by `%b`
+    assert_eq!(
+      func.remove_bb_forced(b, None).map(|_| ()),
+      Err(Error::BasicBlockInUse { bb: b })
+    );
+    func.remove_bb_forced(b, Some(end)).unwrap();
+    assert_eq!(
+      func.remove_bb_forced(a, None).map(|_| ()),
+      Err(Error::BasicBlockInUse { bb: a })
+    );
+    assert_eq!(func.layout().bbs().len(), 3);
+    assert_eq!(func.dfg().bb(end).used_by().len(), 2);
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    assert_eq!(
+      str::from_utf8(&gen.writer()).unwrap(),
+      r#"fun @f(@c: i32): i32 {
+%entry:
+  br @c, %a, %end(1)
+
+%a:
+  %0 = add @c, 1
+  jump %end(%0)
+
+%end(%y: i32):
+  ret %y
+}
+"#
+    );
+  }
+
+  #[test]
+  fn remove_insts() {
+    let driver: Driver<_> = r#"
+      fun @f(@c: i32): i32 {
+      %entry:
+        %0 = add @c, 1
+        %1 = mul %0, %0
+        %2 = sub %1, %0
+        %3 = add %0, 2
+        ret %3
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    let func = program.func_mut(program.func_layout()[0]);
+    let entry = func.layout().entry_bb().unwrap();
+    let insts: Vec<_> = func
+      .layout()
+      .bbs()
+      .node(&entry)
+      .unwrap()
+      .insts()
+      .keys()
+      .copied()
+      .collect();
+    let c = func.params()[0];
+    // nothing is removed if any of the instructions can not be removed
+    assert_eq!(
+      func.try_remove_insts([insts[0], insts[1], insts[2]]),
+      Err(Error::ValueInUse { value: insts[0] })
+    );
+    assert_eq!(
+      func.try_remove_inst(insts[0]).map(|_| ()),
+      Err(Error::ValueInUse { value: insts[0] })
+    );
+    assert_eq!(
+      func.try_remove_inst(c).map(|_| ()),
+      Err(Error::NotInstruction { value: c })
+    );
+    let values = func.dfg().values().len();
+    let data = func.remove_inst(insts[4]);
+    assert!(data.kind().is_terminator());
+    // users in the batch do not block the removal
+    func.remove_insts([insts[0], insts[2], insts[1], insts[1], insts[3]]);
+    assert!(func.layout().parent_bb(insts[1]).is_none());
+    assert!(func.dfg().values().get(&insts[1]).is_none());
+    assert_eq!(func.dfg().values().len(), values - 5);
+    assert!(func.dfg().value(c).used_by().is_empty());
+    assert!(func.layout().bbs().node(&entry).unwrap().insts().is_empty());
+  }
+
+  #[test]
+  fn ensure_entry_has_no_preds() {
+    let driver: Driver<_> = r#"
+      fun @f(): i32 {
+      %entry:
+        %x = alloc i32
+        store 1, %x
+        jump %body
+
+      %body:
+        %0 = load %x
+        br %0, %body, %end
+
+      %end:
+        ret 0
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    let f = program.func_layout()[0];
+    let func = program.func_mut(f);
+    assert_eq!(func.ensure_entry_has_no_preds(), None);
+    // make `%body` branch back to `%entry`
+    let (entry, body) = (
+      func.entry().unwrap(),
+      func.layout().bbs().keys().copied().nth(1).unwrap(),
+    );
+    let br = func.terminator(body).unwrap();
+    let cond = match func.dfg().value(br).kind() {
+      ValueKind::Branch(br) => br.cond(),
+      _ => unreachable!(),
+    };
+    let end = func.layout().bbs().back_key().copied().unwrap();
+    func
+      .dfg_mut()
+      .replace_value_with(br)
+      .branch(cond, entry, end);
+    let errors = verify(&program).unwrap_err();
+    assert_eq!(
+      errors[0].to_string(),
+      "@f, %entry: entry basic block has predecessors"
+    );
+    let func = program.func_mut(f);
+    let new_entry = func.ensure_entry_has_no_preds().unwrap();
+    assert_eq!(func.entry(), Some(new_entry));
+    assert_eq!(verify(&program), Ok(()));
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    assert_eq!(
+      str::from_utf8(&gen.writer()).unwrap(),
+      r#"fun @f(): i32 {
+%0:
+  %x = alloc i32
+  jump %entry
+
+%entry:
+  store 1, %x
+  jump %body
+
+%body:
+  %1 = load %x
+  br %1, %entry, %end
+
+%end:
+  ret 0
+}
+"#
+    );
+  }
+
+  #[test]
+  fn compact() {
+    let driver: Driver<_> = r#"
+      global @g = alloc i32, zeroinit
+
+      fun @f(@x: i32): i32 {
+      %entry:
+        %0 = add @x, 1
+        %1 = mul %0, 2
+        store %1, @g
+        br %1, %then, %end(%0)
+
+      %then:
+        %2 = load @g
+        jump %end(%2)
+
+      %end(%r: i32):
+        ret %r
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    let func = program.func_layout()[0];
+    let dump = |program: &Program| {
+      let mut gen = KoopaGenerator::new(Vec::new());
+      gen.generate_on(program).unwrap();
+      String::from_utf8(gen.writer()).unwrap()
+    };
+    // make the handles sparse
+    let data = program.func_mut(func);
+    for _ in 0..10 {
+      let v = data.dfg_mut().new_value().integer(42);
+      data.dfg_mut().remove_value(v);
+    }
+    let entry = data.layout().entry_bb().unwrap();
+    let mul = data
+      .layout()
+      .bbs()
+      .node(&entry)
+      .unwrap()
+      .insts()
+      .keys()
+      .nth(1)
+      .copied()
+      .unwrap();
+    let two = data.dfg().value(mul).kind().operand(1);
+    let three = data.dfg_mut().new_value().integer(3);
+    data.dfg_mut().set_value_operand(mul, 1, three);
+    data.dfg_mut().remove_value(two);
+    let before = dump(&program);
+    let data = program.func_mut(func);
+    let (values, bbs) = data.compact();
+    assert_eq!(dump(&program), before);
+    let data = program.func(func);
+    assert_eq!((values.len(), bbs.len()), (data.dfg().values().len(), 3));
+    assert_eq!(values[&mul].to_string(), "#4");
+    let handles: Vec<_> = data.dfg().values().keys().map(|v| v.to_string()).collect();
+    let expected: Vec<_> = (0..handles.len()).map(|i| format!("#{i}")).collect();
+    assert_eq!(handles, expected);
+    // `used_by` sets of the global value are updated
+    let global = program.inst_layout()[0];
+    let users = program.borrow_value(global).used_by().clone();
+    assert!(users.iter().all(|u| data.dfg().values().contains_key(u)));
+    assert_eq!(users.len(), 2);
+  }
+
+  #[test]
+  fn set_global_init() {
+    let driver: Driver<_> = "global @t = alloc [i32, 2], {1, 2}".into();
+    let mut program = driver.generate_program().unwrap();
+    let t = program.inst_layout()[0];
+    let one = program.new_value().integer(1);
+    assert_eq!(
+      program.set_global_init(t, one),
+      Err(Error::InitTypeMismatch {
+        ty: Type::get_array(Type::get_i32(), 2)
+      })
+    );
+    assert_eq!(program.set_global_init(t, t), Err(Error::NotConstant));
+    let three = program.new_value().integer(3);
+    let init = program.new_value().aggregate(vec![one, three]);
+    program.set_global_init(t, init).unwrap();
+    // the old initializer and its elements have been removed
+    assert_eq!(program.borrow_values().len(), 4);
+    assert!(program.borrow_value(init).used_by().contains(&t));
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    assert_eq!(
+      str::from_utf8(&gen.writer()).unwrap(),
+      "global @t = alloc [i32, 2], {1, 3}\n\n"
+    );
+  }
+
+  #[test]
+  fn try_push_inst() {
+    let mut program = Program::new();
+    let data = FunctionData::new("@f".into(), vec![], Type::get_unit());
+    let f = program.try_new_func(data).unwrap();
+    let data = FunctionData::new("@f".into(), vec![], Type::get_unit());
+    assert_eq!(
+      program.try_new_func(data).map(|_| ()),
+      Err(Error::NameConflict { name: "@f".into() })
+    );
+    let func = program.func_mut(f);
+    let entry = func.dfg_mut().new_bb().basic_block(None);
+    let one = func.dfg_mut().new_value().integer(1);
+    let ret = func.dfg_mut().new_value().ret(None);
+    assert_eq!(
+      func.try_push_inst(entry, ret),
+      Err(Error::NotInLayout { bb: entry })
+    );
+    func.layout_mut().bbs_mut().push_key_back(entry).unwrap();
+    assert_eq!(
+      func.try_push_inst(entry, one),
+      Err(Error::NotInstruction { value: one })
+    );
+    func.push_inst(entry, ret);
+    assert_eq!(
+      func.try_push_inst(entry, ret),
+      Err(Error::InstInLayout { inst: ret })
+    );
+    let ret2 = func.dfg_mut().new_value().ret(None);
+    assert_eq!(
+      func.try_push_inst(entry, ret2),
+      Err(Error::Terminated { bb: entry })
+    );
+    // removing used values or basic blocks
+    let jump = func.dfg_mut().new_value().jump(entry);
+    assert_eq!(
+      func.dfg_mut().try_remove_bb(entry).map(|_| ()),
+      Err(Error::BasicBlockInUse { bb: entry })
+    );
+    func.dfg_mut().try_remove_value(jump).unwrap();
+    assert_eq!(
+      func.dfg_mut().try_remove_value(jump).map(|_| ()),
+      Err(Error::ValueNotFound { value: jump })
+    );
+  }
+
+  #[test]
+  fn typed_handles() {
+    let mut program = Program::new();
+    let func = program.new_func(FunctionData::new("@f".into(), vec![], Type::get_unit()));
+    let func = program.func_mut(func);
+    let entry = func.dfg_mut().new_bb().basic_block(None);
+    func.layout_mut().bbs_mut().push_key_back(entry).unwrap();
+    let one = func.dfg_mut().new_value().integer(1);
+    let ret = func.dfg_mut().new_value().ret(None);
+    assert_eq!(func.dfg().as_inst(one), None);
+    assert_eq!(func.dfg().as_const(ret), None);
+    assert_eq!(func.dfg().const_int(one), Some(1));
+    assert_eq!(func.dfg().const_int(ret), None);
+    let ret = func.dfg().as_inst(ret).unwrap();
+    assert_eq!(func.dfg().as_const(one).map(|c| c.value()), Some(one));
+    func.push_inst(entry, ret);
+    assert_eq!(*ret, func.terminator(entry).unwrap());
+    // unchecked conversions are checked on insertion
+    assert_eq!(
+      func.try_push_inst(entry, one),
+      Err(Error::NotInstruction { value: one })
+    );
+    // global allocations require constant initializers
+    let zero = program.new_value().zero_init(Type::get_i32());
+    let x = program.new_value().global_alloc(zero);
+    assert_eq!(
+      program.new_value().try_global_alloc(x).map(|_| ()),
+      Err(Error::NotConstant)
+    );
+  }
+
+  #[test]
+  fn par_funcs_mut() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Program>();
+    assert_send_sync::<FunctionData>();
+
+    // removes unused loads and binary operations
+    fn dce(data: &mut FunctionData) {
+      loop {
+        let dead: Vec<_> = data
+          .dfg()
+          .values()
+          .iter()
+          .filter(|(_, v)| {
+            v.used_by().is_empty() && matches!(v.kind(), ValueKind::Load(_) | ValueKind::Binary(_))
+          })
+          .map(|(v, _)| *v)
+          .collect();
+        if dead.is_empty() {
+          break;
+        }
+        data.remove_insts(dead);
+      }
+    }
+
+    let mut src = String::from("global @g = alloc i32, 1\n");
+    for i in 0..100 {
+      src += &format!(
+        "fun @f{i}(@x: i32): i32 {{\n%entry:\n  %0 = load @g\n  %1 = add %0, @x\n  %2 = mul %1, {i}\n  %3 = add @x, {i}\n  ret %3\n}}\n"
+      );
+    }
+    let dump = |program: &Program| {
+      let mut gen = KoopaGenerator::new(Vec::new());
+      gen.generate_on(program).unwrap();
+      String::from_utf8(gen.writer()).unwrap()
+    };
+    let driver: Driver<_> = src.as_str().into();
+    let mut serial = driver.generate_program().unwrap();
+    serial.funcs_mut().values_mut().for_each(dce);
+    let driver: Driver<_> = src.as_str().into();
+    let mut parallel = driver.generate_program().unwrap();
+    parallel.par_funcs_mut(4, dce);
+    assert_eq!(dump(&serial), dump(&parallel));
+    let g = parallel.inst_layout()[0];
+    assert!(parallel.borrow_value(g).used_by().is_empty());
+  }
+
+  #[test]
+  fn canonicalize_order() {
+    let dump = |program: &Program| {
+      let mut gen = KoopaGenerator::new(Vec::new());
+      gen.generate_on(program).unwrap();
+      String::from_utf8(gen.writer()).unwrap()
+    };
+    let build = |src: &str| {
+      let driver: Driver<_> = src.into();
+      let mut program = driver.generate_program().unwrap();
+      program.canonicalize_order();
+      program
+    };
+    let a = build(
+      r#"
+      decl @f(): i32
+      global @b = alloc i32, 2
+      global @a = alloc i32, 1
+
+      fun @main(): i32 {
+      %entry:
+        %0 = call @f()
+        ret %0
+      }
+
+      fun @g() {
+      %entry:
+        ret
+      }
+    "#,
+    );
+    let mut b = build(
+      r#"
+      fun @g() {
+      %entry:
+        ret
+      }
+
+      decl @f(): i32
+      global @a = alloc i32, 1
+
+      fun @main(): i32 {
+      %entry:
+        %0 = call @f()
+        ret %0
+      }
+
+      global @b = alloc i32, 2
+    "#,
+    );
+    assert_eq!(dump(&a), dump(&b));
+    let names: Vec<_> = b.func_layout().iter().map(|f| b.func(*f).name()).collect();
+    assert_eq!(names, ["@f", "@g", "@main"]);
+    assert_eq!(
+      b.borrow_value(b.inst_layout()[0]).name().as_deref(),
+      Some("@a")
+    );
+    // idempotent
+    let text = dump(&b);
+    b.canonicalize_order();
+    assert_eq!(dump(&b), text);
+  }
+
+  #[test]
+  fn collect_garbage() {
+    let driver: Driver<_> = r#"
+      global @g = alloc i32, 0
+      decl @h(): i32
+
+      fun @f(@x: i32): i32 {
+      %entry:
+        %0 = add @x, 1
+        jump %end
+
+      %end:
+        ret %0
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    let (h, f) = (program.func_layout()[0], program.func_layout()[1]);
+    let g = program.inst_layout()[0];
+    let data = program.func_mut(f);
+    let x = data.params()[0];
+    let bbs: Vec<_> = data.layout().bbs().keys().copied().collect();
+    let len = data.dfg().values().len();
+    // abandoned values: a chain, a cycle, a jump, a call and a load
+    let dfg = data.dfg_mut();
+    let two = dfg.new_value().integer(2);
+    let a = dfg.new_value().binary(BinaryOp::Mul, x, two);
+    dfg.new_value().binary(BinaryOp::Sub, a, x);
+    let c1 = dfg.new_value().binary(BinaryOp::Add, x, x);
+    let c2 = dfg.new_value().binary(BinaryOp::Add, c1, x);
+    dfg.set_value_operand(c1, 1, c2);
+    dfg.new_value().jump(bbs[1]);
+    dfg.new_value().call(h, vec![]);
+    dfg.new_value().load(g);
+    // a value not in the layout, but used by the layout
+    let three = dfg.new_value().integer(3);
+    let used = dfg.new_value().binary(BinaryOp::Add, x, three);
+    let ret = *data
+      .layout()
+      .bbs()
+      .node(&bbs[1])
+      .unwrap()
+      .insts()
+      .back_key()
+      .unwrap();
+    data.dfg_mut().set_value_operand(ret, 0, used);
+    assert_eq!(data.collect_garbage(), 8);
+    assert_eq!(data.dfg().values().len(), len + 2);
+    assert!(data.dfg().values().contains_key(&used));
+    assert_eq!(data.dfg().value(x).used_by().len(), 2);
+    assert_eq!(data.dfg().bb(bbs[1]).used_by().len(), 1);
+    assert_eq!(data.collect_garbage(), 0);
+    assert!(program.func_users(h).is_empty());
+    assert!(program.borrow_value(g).used_by().is_empty());
+    // place the kept value, so the function is valid again
+    let data = program.func_mut(f);
+    data
+      .layout_mut()
+      .bb_mut(bbs[1])
+      .insts_mut()
+      .push_key_front(used)
+      .unwrap();
+    assert!(verify(&program).is_ok());
+  }
+
+  #[test]
+  fn deeply_nested_aggregate() {
+    const DEPTH: usize = 100_000;
+    let mut program = Program::new();
+    let mut build = |elem| {
+      let mut value = program.new_value().integer(elem);
+      for _ in 0..DEPTH {
+        value = program.new_value().aggregate(vec![value]);
+      }
+      value
+    };
+    let (a, b, c) = (build(1), build(1), build(2));
+    let global = program.new_value().global_alloc(a);
+    program.set_value_name(global, Some("@g".into()));
+    let func = program.new_func(FunctionData::new("@f".into(), Vec::new(), Type::get_unit()));
+    let dfg = program.func(func).dfg();
+    assert!(dfg.value_eq(a, b));
+    assert!(!dfg.value_eq(a, c));
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    let text = String::from_utf8(gen.writer()).unwrap();
+    let init = format!("{}1{}", "{".repeat(DEPTH), "}".repeat(DEPTH));
+    assert!(text.starts_with("global @g = alloc [[["));
+    assert!(text.contains(&format!(", {init}\n")));
+    // replacing the initializer removes the whole nested aggregate
+    program.set_global_init(global, b).unwrap();
+    assert_eq!(program.borrow_values().len(), 2 * (DEPTH + 1) + 1);
+  }
+
+  #[test]
+  fn iter_values() {
+    let driver: Driver<_> = r#"
+      global @g = alloc i32, 1
+
+      fun @f(@x: i32): i32 {
+      %entry:
+        %0 = load @g
+        jump %next(%0)
+
+      %next(%y: i32):
+        ret @x
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    let f = program.func_layout()[0];
+    let data = program.func(f);
+    let bbs: Vec<_> = data.layout().bbs().keys().copied().collect();
+    let values: Vec<_> = program
+      .iter_values()
+      .map(|(loc, _, data)| (loc, data.name().clone(), data.kind().is_const()))
+      .collect();
+    let local = |bb| ValueLocation::Local(f, bb);
+    assert_eq!(
+      values,
+      [
+        (ValueLocation::Global, None, true),
+        (ValueLocation::Global, Some("@g".into()), false),
+        (local(None), Some("@x".into()), false),
+        (local(Some(bbs[1])), Some("%y".into()), false),
+        (local(Some(bbs[0])), None, false),
+        (local(Some(bbs[0])), None, false),
+        (local(Some(bbs[1])), None, false),
+      ]
+    );
+    assert_eq!(program.iter_globals().count(), 2);
+    // return `%y` instead of `@x`, and update `used_by` sets
+    let (x, y) = (data.params()[0], data.dfg().bb(bbs[1]).params()[0]);
+    program.for_each_value_mut(|loc, _, data| {
+      if loc == local(Some(bbs[1])) {
+        if let ValueKind::Return(ret) = data.kind_mut() {
+          *ret.value_mut() = Some(y);
+        }
+      }
+    });
+    let dfg = program.func(f).dfg();
+    assert!(dfg.value(x).used_by().is_empty());
+    assert_eq!(dfg.value(y).used_by().len(), 1);
+  }
+
+  #[test]
+  fn func_users() {
+    let driver: Driver<_> = r#"
+      decl @f(): i32
+
+      fun @main(): i32 {
+      %entry:
+        %0 = call @f()
+        %1 = call @f()
+        %2 = add %0, %1
+        ret %2
+      }
+
+      fun @g() {
+      %entry:
+        %0 = call @f()
+        ret
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    let (f, main, g) = (
+      program.func_layout()[0],
+      program.func_layout()[1],
+      program.func_layout()[2],
+    );
+    let entry_insts = |data: &FunctionData| -> Vec<_> {
+      let entry = data.layout().entry_bb().unwrap();
+      data
+        .layout()
+        .bbs()
+        .node(&entry)
+        .unwrap()
+        .insts()
+        .keys()
+        .copied()
+        .collect()
+    };
+    assert_eq!(program.func_users(f).len(), 3);
+    assert!(program.func_users(main).is_empty());
+    // calls are tracked when replaced
+    let call_in_g = entry_insts(program.func(g))[0];
+    let data = program.func_mut(main);
+    let insts = entry_insts(data);
+    data.dfg_mut().replace_value_with(insts[1]).integer(1);
+    assert_eq!(
+      *program.func_users(f),
+      [(main, insts[0]), (g, call_in_g)].into()
+    );
+    // handles are updated after compacting
+    let data = program.func_mut(main);
+    let (values, _) = data.compact();
+    assert_eq!(program.func_users(f).len(), 2);
+    assert!(program.func_users(f).contains(&(main, values[&insts[0]])));
+    // calls in the removed functions are also removed
+    program.remove_func(g);
+    assert_eq!(*program.func_users(f), [(main, values[&insts[0]])].into());
+  }
+
+  #[test]
+  fn operands() {
+    let mut program = Program::new();
+    let func = program.new_func(FunctionData::new(
+      "@f".into(),
+      vec![Type::get_i32(), Type::get_i32()],
+      Type::get_unit(),
+    ));
+    let data = program.func_mut(func);
+    let (x, y) = (data.params()[0], data.params()[1]);
+    let dfg = data.dfg_mut();
+    let then = dfg
+      .new_bb()
+      .basic_block_with_params(None, vec![Type::get_i32(), Type::get_i32()]);
+    let end = dfg
+      .new_bb()
+      .basic_block_with_params(None, vec![Type::get_i32()]);
+    let br = dfg
+      .new_value()
+      .branch_with_args(x, then, end, vec![x, y], vec![y]);
+    let mut br = dfg.value(br).clone();
+    assert_eq!(br.operands(), [x, x, y, y][..]);
+    assert_eq!(br.bb_operands(), [then, end]);
+    // pure data edit, the operand indices are kept
+    assert_eq!(br.replace_operand(x, y), 2);
+    assert_eq!(br.operands(), [y, y, y, y][..]);
+    assert_eq!(br.replace_operand(x, y), 0);
+  }
+}
@@ -0,0 +1,627 @@
+//! Equivalence checks of programs up to renaming ([`equiv`]) related
+//! implementations.
+//!
+//! Two programs are equivalent if they only differ in names of values
+//! and basic blocks, handles and the order of basic blocks in layouts.
+//! This is useful for testing passes that should not change anything
+//! observable, like merging basic blocks that have already been merged.
+//!
+//! # Example
+//!
+//! ```
+//! use koopa::front::Driver;
+//! use koopa::ir::equiv::*;
+//!
+//! let parse = |src: &str| Driver::from(src).generate_program().unwrap();
+//! let a = parse(r#"
+//!   fun @f(@x: i32): i32 {
+//!   %entry:
+//!     %0 = add @x, 1
+//!     br %0, %then, %end
+//!   %then:
+//!     jump %end
+//!   %end:
+//!     ret %0
+//!   }
+//! "#);
+//! let b = parse(r#"
+//!   fun @f(@y: i32): i32 {
+//!   %bb0:
+//!     %sum = add @y, 1
+//!     br %sum, %bb1, %bb2
+//!   %bb2:
+//!     ret %sum
+//!   %bb1:
+//!     jump %bb2
+//!   }
+//! "#);
+//! let c = parse(r#"
+//!   fun @f(@x: i32): i32 {
+//!   %entry:
+//!     %0 = add @x, 2
+//!     ret %0
+//!   }
+//! "#);
+//!
+//! assert_eq!(equiv(&a, &b), Ok(()));
+//! assert_eq!(
+//!   equiv(&a, &c).unwrap_err().to_string(),
+//!   "@f: instructions differ\n  left:  %0 = add @x, 1\n  right: %0 = add @x, 2",
+//! );
+//! ```
+
+use crate::ir::dfg::DataFlowGraph;
+use crate::ir::diff::Text;
+use crate::ir::{BasicBlock, Function, FunctionData, Program, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::hash::Hash;
+
+/// Checks if program `a` and program `b` are equivalent up to renaming.
+/// Returns the first found mismatch if they are not.
+///
+/// Global allocations are matched by their positions in the layout, and
+/// their initializers are compared structurally. Functions are matched
+/// by names, and must have the same types.
+///
+/// For every pair of functions, the checker builds a bijection between
+/// basic blocks and values of the two functions. Starting from the entry
+/// basic blocks, it walks the control flow graphs with a work list:
+/// parameters of matched basic blocks are matched by their positions,
+/// instructions are matched by their positions in basic blocks, and
+/// must be structurally equal (like [`DataFlowGraph::value_eq`]) with
+/// their operands mapped to matched operands. Targets of matched
+/// terminators are matched edge by edge. Basic blocks that are not
+/// reached this way (unreachable ones) are matched by their positions
+/// in the layout.
+///
+/// The matching is deterministic and never backtracks, so the check is
+/// incomplete: programs that are equivalent but have, for example,
+/// independent instructions reordered, or arms of branches swapped with
+/// negated conditions, are reported as mismatches.
+pub fn equiv(a: &Program, b: &Program) -> Result<(), Mismatch> {
+  check(a, b).map_err(|found| found.describe(a, b))
+}
+
+/// A mismatch between two programs, returned by [`equiv`].
+///
+/// The mismatch can be displayed as a text with both sides.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+  /// Name of the function or the global allocation that mismatches.
+  pub name: String,
+  /// Kind of the mismatch.
+  pub kind: MismatchKind,
+  /// The mismatched part in program `a` in text form, or `None` if it
+  /// has no counterpart in program `a`.
+  ///
+  /// This is an instruction, a label of basic block (like `%bb(%0: i32)`),
+  /// a function header (like `fun @f(@x: i32): i32`) or a global
+  /// allocation, depending on the kind.
+  pub left: Option<String>,
+  /// The mismatched part in program `b` in text form, or `None` if it
+  /// has no counterpart in program `b`.
+  pub right: Option<String>,
+}
+
+impl fmt::Display for Mismatch {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}: {}", self.name, self.kind)?;
+    let none = "<none>".to_string();
+    write!(f, "\n  left:  {}", self.left.as_ref().unwrap_or(&none))?;
+    write!(f, "\n  right: {}", self.right.as_ref().unwrap_or(&none))
+  }
+}
+
+/// Kind of mismatches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MismatchKind {
+  /// The function or the global allocation only exists in one of the
+  /// programs.
+  Missing,
+  /// The global allocations have different types or initializers.
+  Global,
+  /// The functions have different types, or one of them is a
+  /// declaration while the other is not.
+  Header,
+  /// The basic blocks have different parameters, or can not be matched.
+  BasicBlock,
+  /// The instructions are different, or only exist in one side.
+  Inst,
+}
+
+impl fmt::Display for MismatchKind {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(match self {
+      Self::Missing => "only exists in one of the programs",
+      Self::Global => "global allocations differ",
+      Self::Header => "function headers differ",
+      Self::BasicBlock => "basic blocks differ",
+      Self::Inst => "instructions differ",
+    })
+  }
+}
+
+/// A mismatch found by the checker, which refers to the handles.
+enum Found {
+  Global(Option<Value>, Option<Value>),
+  Func(Option<Function>, Option<Function>),
+  Header(Function, Function),
+  Bb(Function, Function, Option<BasicBlock>, Option<BasicBlock>),
+  Inst(Function, Function, Option<Value>, Option<Value>),
+}
+
+impl Found {
+  /// Converts the found mismatch to a mismatch with texts.
+  ///
+  /// Programs are printed only here, so equivalent programs
+  /// will never be printed.
+  fn describe(self, a: &Program, b: &Program) -> Mismatch {
+    let ((globals_l, texts_l), (globals_r, texts_r)) = (Text::new(a), Text::new(b));
+    let global_name =
+      |program: &Program, v| program.borrow_value(v).name().clone().unwrap_or_default();
+    let (name, kind, left, right) = match self {
+      Self::Global(l, r) => {
+        let name = match (l, r) {
+          (Some(l), _) => global_name(a, l),
+          (None, Some(r)) => global_name(b, r),
+          (None, None) => unreachable!(),
+        };
+        let kind = if l.is_some() && r.is_some() {
+          MismatchKind::Global
+        } else {
+          MismatchKind::Missing
+        };
+        (
+          name,
+          kind,
+          l.map(|l| globals_l[&l].clone()),
+          r.map(|r| globals_r[&r].clone()),
+        )
+      }
+      Self::Func(l, r) => {
+        let name = match (l, r) {
+          (Some(l), _) => a.func(l).name().into(),
+          (None, Some(r)) => b.func(r).name().into(),
+          (None, None) => unreachable!(),
+        };
+        let left = l.map(|l| texts_l[&l].header.clone());
+        let right = r.map(|r| texts_r[&r].header.clone());
+        (name, MismatchKind::Missing, left, right)
+      }
+      Self::Header(l, r) => (
+        a.func(l).name().into(),
+        MismatchKind::Header,
+        Some(texts_l[&l].header.clone()),
+        Some(texts_r[&r].header.clone()),
+      ),
+      Self::Bb(fl, fr, l, r) => (
+        a.func(fl).name().into(),
+        MismatchKind::BasicBlock,
+        l.map(|l| texts_l[&fl].labels[&l].clone()),
+        r.map(|r| texts_r[&fr].labels[&r].clone()),
+      ),
+      Self::Inst(fl, fr, l, r) => (
+        a.func(fl).name().into(),
+        MismatchKind::Inst,
+        l.map(|l| texts_l[&fl].insts[&l].clone()),
+        r.map(|r| texts_r[&fr].insts[&r].clone()),
+      ),
+    };
+    Mismatch {
+      name,
+      kind,
+      left,
+      right,
+    }
+  }
+}
+
+/// Checks if the two given programs are equivalent.
+fn check(a: &Program, b: &Program) -> Result<(), Found> {
+  // check global allocations
+  let globals: HashMap<_, _> = a
+    .inst_layout()
+    .iter()
+    .copied()
+    .zip(b.inst_layout().iter().copied())
+    .collect();
+  {
+    let (values_l, values_r) = (a.borrow_values(), b.borrow_values());
+    for &gl in a.inst_layout() {
+      let Some(&gr) = globals.get(&gl) else {
+        return Err(Found::Global(Some(gl), None));
+      };
+      // initializers may only refer to constants, so `gl` and `gr` are
+      // compared element by element without binding any values
+      let mut worklist = vec![(gl, gr)];
+      while let Some((l, r)) = worklist.pop() {
+        let (l, r) = (&values_l[&l], &values_r[&r]);
+        if !DataFlowGraph::shallow_eq_by(l, r, |_, _| false, |_, _| false) {
+          return Err(Found::Global(Some(gl), Some(gr)));
+        }
+        worklist.extend(l.kind().value_uses().zip(r.kind().value_uses()));
+      }
+    }
+    if let Some(r) = b.inst_layout().get(globals.len()) {
+      return Err(Found::Global(None, Some(*r)));
+    }
+  }
+  // check functions
+  let funcs_r: HashMap<_, _> = b
+    .func_layout()
+    .iter()
+    .map(|f| (b.func(*f).name(), *f))
+    .collect();
+  for &l in a.func_layout() {
+    let Some(&r) = funcs_r.get(a.func(l).name()) else {
+      return Err(Found::Func(Some(l), None));
+    };
+    FunctionChecker {
+      programs: (a, b),
+      funcs: (l, r),
+      globals: &globals,
+      bbs: Bijection::default(),
+      values: Bijection::default(),
+      worklist: VecDeque::new(),
+    }
+    .check()?;
+  }
+  let names_l: HashSet<_> = a.func_layout().iter().map(|f| a.func(*f).name()).collect();
+  match b
+    .func_layout()
+    .iter()
+    .find(|f| !names_l.contains(b.func(**f).name()))
+  {
+    Some(r) => Err(Found::Func(None, Some(*r))),
+    None => Ok(()),
+  }
+}
+
+/// Checker of two functions with the same name.
+struct FunctionChecker<'a> {
+  programs: (&'a Program, &'a Program),
+  funcs: (Function, Function),
+  /// Matched global allocations.
+  globals: &'a HashMap<Value, Value>,
+  /// Matched basic blocks.
+  bbs: Bijection<BasicBlock>,
+  /// Matched function parameters, basic block parameters
+  /// and instructions.
+  values: Bijection<Value>,
+  /// Matched basic blocks to be checked.
+  worklist: VecDeque<(BasicBlock, BasicBlock)>,
+}
+
+impl<'a> FunctionChecker<'a> {
+  /// Returns the data of the two functions.
+  fn data(&self) -> (&'a FunctionData, &'a FunctionData) {
+    let (l, r) = self.funcs;
+    (self.programs.0.func(l), self.programs.1.func(r))
+  }
+
+  /// Checks the two functions.
+  fn check(mut self) -> Result<(), Found> {
+    let (fl, fr) = self.funcs;
+    let (l, r) = self.data();
+    let (bbs_l, bbs_r) = (l.layout().bbs(), r.layout().bbs());
+    if l.ty() != r.ty() || bbs_l.is_empty() != bbs_r.is_empty() {
+      return Err(Found::Header(fl, fr));
+    }
+    let (Some(&entry_l), Some(&entry_r)) = (bbs_l.front_key(), bbs_r.front_key()) else {
+      // declarations
+      return Ok(());
+    };
+    for (pl, pr) in l.params().iter().zip(r.params()) {
+      self.values.bind(*pl, *pr);
+    }
+    self.bbs.bind(entry_l, entry_r);
+    self.worklist.push_back((entry_l, entry_r));
+    loop {
+      while let Some((bl, br)) = self.worklist.pop_front() {
+        self.check_bb(bl, br)?;
+      }
+      // match the remaining basic blocks by their positions
+      let (l, r) = self.data();
+      let mut rest_l = l
+        .layout()
+        .bbs()
+        .keys()
+        .filter(|b| !self.bbs.contains_left(b));
+      let mut rest_r = r
+        .layout()
+        .bbs()
+        .keys()
+        .filter(|b| !self.bbs.contains_right(b));
+      match (rest_l.next().copied(), rest_r.next().copied()) {
+        (None, None) => return Ok(()),
+        (Some(bl), Some(br)) => {
+          self.bbs.bind(bl, br);
+          self.worklist.push_back((bl, br));
+        }
+        (bl, br) => return Err(Found::Bb(fl, fr, bl, br)),
+      }
+    }
+  }
+
+  /// Checks the two matched basic blocks.
+  fn check_bb(&mut self, bl: BasicBlock, br: BasicBlock) -> Result<(), Found> {
+    let (fl, fr) = self.funcs;
+    let (l, r) = self.data();
+    let (params_l, params_r) = (l.dfg().bb(bl).params(), r.dfg().bb(br).params());
+    let params_eq = params_l.len() == params_r.len()
+      && params_l
+        .iter()
+        .zip(params_r)
+        .all(|(pl, pr)| l.dfg().value(*pl).ty() == r.dfg().value(*pr).ty());
+    let params: Vec<_> = params_l
+      .iter()
+      .copied()
+      .zip(params_r.iter().copied())
+      .collect();
+    if !params_eq
+      || !params
+        .into_iter()
+        .all(|(pl, pr)| self.values.bind(pl, pr).is_some())
+    {
+      return Err(Found::Bb(fl, fr, Some(bl), Some(br)));
+    }
+    let (l, r) = self.data();
+    let insts_l: Vec<_> = l.layout().bbs()[&bl].insts().keys().copied().collect();
+    let insts_r: Vec<_> = r.layout().bbs()[&br].insts().keys().copied().collect();
+    for i in 0..insts_l.len().max(insts_r.len()) {
+      match (insts_l.get(i), insts_r.get(i)) {
+        (Some(&il), Some(&ir)) if self.check_inst(il, ir) => {}
+        (il, ir) => return Err(Found::Inst(fl, fr, il.copied(), ir.copied())),
+      }
+    }
+    Ok(())
+  }
+
+  /// Checks if the two given instructions are equal, and matches them,
+  /// their operands and their targets.
+  fn check_inst(&mut self, il: Value, ir: Value) -> bool {
+    let (programs, globals) = (self.programs, self.globals);
+    let (l, r) = self.data();
+    let (dfg_l, dfg_r) = (l.dfg(), r.dfg());
+    let (dl, dr) = (dfg_l.value(il), dfg_r.value(ir));
+    let func_eq = |l, r| programs.0.func(l).name() == programs.1.func(r).name();
+    if !DataFlowGraph::shallow_eq_by(dl, dr, |_, _| true, func_eq) {
+      return false;
+    }
+    if self.values.bind(il, ir).is_none() {
+      return false;
+    }
+    // constant operands are compared by contents, others by bindings
+    let mut worklist: Vec<_> = dl.kind().value_uses().zip(dr.kind().value_uses()).collect();
+    while let Some((ol, or)) = worklist.pop() {
+      let eq = match (dfg_l.values().get(&ol), dfg_r.values().get(&or)) {
+        (Some(l), Some(r)) if l.kind().is_const() => {
+          let eq = DataFlowGraph::shallow_eq_by(l, r, |_, _| false, |_, _| false);
+          worklist.extend(l.kind().value_uses().zip(r.kind().value_uses()));
+          eq
+        }
+        // operands defined in unchecked basic blocks are matched here,
+        // and checked when their basic blocks are checked
+        (Some(l), Some(r)) => l.ty() == r.ty() && self.values.bind(ol, or).is_some(),
+        (None, None) => globals.get(&ol) == Some(&or),
+        _ => false,
+      };
+      if !eq {
+        return false;
+      }
+    }
+    let edges: Vec<_> = dl
+      .kind()
+      .edges()
+      .into_iter()
+      .zip(dr.kind().edges())
+      .map(|((tl, _), (tr, _))| (tl, tr))
+      .collect();
+    for (tl, tr) in edges {
+      match self.bbs.bind(tl, tr) {
+        Some(true) => self.worklist.push_back((tl, tr)),
+        Some(false) => {}
+        None => return false,
+      }
+    }
+    true
+  }
+}
+
+/// A bijection between items of the two sides.
+struct Bijection<T> {
+  left: HashMap<T, T>,
+  right: HashMap<T, T>,
+}
+
+impl<T> Default for Bijection<T> {
+  fn default() -> Self {
+    Self {
+      left: HashMap::new(),
+      right: HashMap::new(),
+    }
+  }
+}
+
+impl<T: Copy + Eq + Hash> Bijection<T> {
+  /// Matches the given items. Returns `Some(true)` if they are newly
+  /// matched, `Some(false)` if they have already been matched, or `None`
+  /// if any of them has been matched to another item.
+  fn bind(&mut self, l: T, r: T) -> Option<bool> {
+    match (self.left.get(&l), self.right.get(&r)) {
+      (None, None) => {
+        self.left.insert(l, r);
+        self.right.insert(r, l);
+        Some(true)
+      }
+      (Some(v), _) if *v == r => Some(false),
+      _ => None,
+    }
+  }
+
+  fn contains_left(&self, l: &T) -> bool {
+    self.left.contains_key(l)
+  }
+
+  fn contains_right(&self, r: &T) -> bool {
+    self.right.contains_key(r)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::front::Driver;
+  use crate::ir::Type;
+
+  fn parse(src: &str) -> Program {
+    let driver: Driver<_> = src.into();
+    driver.generate_program().unwrap()
+  }
+
+  #[test]
+  fn equivalent() {
+    let a = parse(
+      r#"
+      global @g = alloc [i32, 2], {1, 2}
+      decl @getint(): i32
+
+      fun @f(@x: i32): i32 {
+      %entry:
+        %p = getelemptr @g, 1
+        %0 = load %p
+        %1 = add %0, @x
+        br %1, %loop(%1), %end(0)
+      %loop(%i: i32):
+        %2 = call @getint()
+        %3 = sub %i, %2
+        br %3, %loop(%3), %end(%3)
+      %end(%r: i32):
+        ret %r
+      }
+    "#,
+    );
+    let b = parse(
+      r#"
+      global @h = alloc [i32, 2], {1, 2}
+      decl @getint(): i32
+
+      fun @f(@y: i32): i32 {
+      %bb0:
+        %q = getelemptr @h, 1
+        %v = load %q
+        %s = add %v, @y
+        br %s, %bb1(%s), %bb2(0)
+      %bb2(%res: i32):
+        ret %res
+      %bb1(%n: i32):
+        %t = call @getint()
+        %u = sub %n, %t
+        br %u, %bb1(%u), %bb2(%u)
+      }
+    "#,
+    );
+    assert_eq!(equiv(&a, &a), Ok(()));
+    assert_eq!(equiv(&a, &b), Ok(()));
+  }
+
+  #[test]
+  fn mismatches() {
+    let a = parse(
+      r#"
+      global @g = alloc i32, 1
+
+      fun @f(@x: i32): i32 {
+      %entry:
+        br @x, %then, %end(1)
+      %then:
+        jump %end(@x)
+      %end(%r: i32):
+        ret %r
+      }
+    "#,
+    );
+    let err = equiv(&a, &parse("global @g = alloc i32, 2")).unwrap_err();
+    assert_eq!(
+      err,
+      Mismatch {
+        name: "@g".into(),
+        kind: MismatchKind::Global,
+        left: Some("global @g = alloc i32, 1".into()),
+        right: Some("global @g = alloc i32, 2".into()),
+      }
+    );
+    let b = parse(
+      r#"
+      global @g = alloc i32, 1
+
+      fun @f(@x: i32): i32 {
+      %entry:
+        br @x, %end(1), %then
+      %then:
+        jump %end(@x)
+      %end(%r: i32):
+        ret %r
+      }
+    "#,
+    );
+    let err = equiv(&a, &b).unwrap_err();
+    assert_eq!(err.kind, MismatchKind::Inst);
+    assert_eq!(err.right.as_deref(), Some("br @x, %end(1), %then"));
+    let c = parse(
+      r#"
+      global @g = alloc i32, 1
+
+      fun @f(@x: i32): i32 {
+      %entry:
+        br @x, %then, %end(1)
+      %then:
+        %0 = add @x, 0
+        jump %end(%0)
+      %end(%r: i32):
+        ret %r
+      }
+
+      fun @main() {
+      %entry:
+        ret
+      }
+    "#,
+    );
+    let err = equiv(&a, &c).unwrap_err();
+    assert_eq!(err.kind, MismatchKind::Inst);
+    assert_eq!(err.left.as_deref(), Some("jump %end(@x)"));
+    let err = equiv(&c, &a).unwrap_err();
+    assert_eq!(err.right.as_deref(), Some("jump %end(@x)"));
+    let mut d = parse(
+      r#"
+      global @g = alloc i32, 1
+
+      fun @f(@x: i32): i32 {
+      %entry:
+        br @x, %then, %end(1)
+      %then:
+        jump %end(@x)
+      %end(%r: i32):
+        ret %r
+      }
+    "#,
+    );
+    assert_eq!(equiv(&a, &d), Ok(()));
+    d.new_func(FunctionData::new(
+      "@main".into(),
+      Vec::new(),
+      Type::get_unit(),
+    ));
+    assert_eq!(
+      equiv(&a, &d).unwrap_err(),
+      Mismatch {
+        name: "@main".into(),
+        kind: MismatchKind::Missing,
+        left: None,
+        right: Some("decl @main()".into()),
+      }
+    );
+  }
+}
@@ -0,0 +1,301 @@
+//! Errors reported by fallible operations on the in-memory form IR.
+
+use crate::ir::entities::{BasicBlock, Function, Value};
+use crate::ir::symbol::check_symbol;
+use crate::ir::types::Type;
+use std::{error, fmt};
+
+/// Errors reported by fallible IR operations.
+///
+/// Most panicking methods of the IR have fallible counterparts named
+/// `try_*`, which return this error instead of panicking.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+  /// The initializer list has more elements than the array type.
+  ExcessElements {
+    /// The target array type.
+    ty: Type,
+    /// Number of elements in the initializer list.
+    len: usize,
+  },
+  /// The initializer does not match the target type,
+  /// e.g. an integer for an array type, or a list for a non-array type.
+  InitTypeMismatch {
+    /// The target type.
+    ty: Type,
+  },
+  /// The pointer can not be indexed at the given step.
+  NotIndexable {
+    /// Index of the step (starting from 0).
+    step: usize,
+    /// Type of the pointer at the step.
+    ty: Type,
+  },
+  /// The value is not a constant.
+  NotConstant,
+  /// The name is not a valid global symbol name.
+  InvalidName {
+    /// The invalid name.
+    name: String,
+  },
+  /// The name is already used by another function or global value, or
+  /// by another local value at [`Validation::Full`](crate::ir::Validation::Full).
+  NameConflict {
+    /// The conflicting name.
+    name: String,
+  },
+  /// The value does not exist, or has been removed.
+  ValueNotFound {
+    /// Handle of the value.
+    value: Value,
+  },
+  /// The basic block does not exist, or has been removed.
+  BasicBlockNotFound {
+    /// Handle of the basic block.
+    bb: BasicBlock,
+  },
+  /// The function does not exist, or has been removed.
+  FunctionNotFound {
+    /// Handle of the function.
+    func: Function,
+  },
+  /// The operand of an instruction or a constant has an unexpected type.
+  TypeMismatch {
+    /// Description of the operand, e.g. `` `cond` ``.
+    operand: &'static str,
+    /// Description of the expected type, e.g. `an integer`.
+    expected: &'static str,
+    /// The actual type.
+    found: Type,
+  },
+  /// The arguments do not match the parameters of the target basic block.
+  ArgMismatch {
+    /// Description of the target, e.g. `` `true_bb` ``.
+    target: &'static str,
+  },
+  /// The arguments of a call do not match the parameters of the callee.
+  CallMismatch {
+    /// Name of the callee.
+    callee: String,
+    /// Number of parameters of the callee.
+    expected: usize,
+    /// Number of arguments.
+    found: usize,
+    /// Index, parameter type and argument type of the first argument
+    /// whose type does not match the parameter, if any.
+    arg: Option<(usize, Type, Type)>,
+  },
+  /// The returned value does not match the return type of the function.
+  RetMismatch {
+    /// The return type of the function.
+    expected: Type,
+    /// Type of the returned value, or unit if nothing is returned.
+    found: Type,
+  },
+  /// The return type of the function is an array type, which is not
+  /// supported, since no calling convention is defined for arrays.
+  ArrayReturn {
+    /// The return type of the function.
+    ty: Type,
+  },
+  /// The stored value does not match the type pointed to by the
+  /// destination pointer.
+  StoreMismatch {
+    /// Type of the stored value.
+    value: Type,
+    /// Type of the destination.
+    dest: Type,
+  },
+  /// The true and false targets of a branch are the same, but arguments
+  /// are passed to them.
+  SameTargetArgs,
+  /// The aggregate or the byte data has no elements.
+  EmptyAggregate,
+  /// The value is still used by other values.
+  ValueInUse {
+    /// Handle of the value.
+    value: Value,
+  },
+  /// The basic block is still used by other values.
+  BasicBlockInUse {
+    /// Handle of the basic block.
+    bb: BasicBlock,
+  },
+  /// The basic block is not in the layout of the function.
+  NotInLayout {
+    /// Handle of the basic block.
+    bb: BasicBlock,
+  },
+  /// The instruction is already in the layout of the function.
+  InstInLayout {
+    /// Handle of the instruction.
+    inst: Value,
+  },
+  /// The instruction is not in the layout of the function.
+  InstNotInLayout {
+    /// Handle of the instruction.
+    inst: Value,
+  },
+  /// Moving the instruction violates an ordering constraint, see
+  /// [`FunctionData::try_add_order_constraint`](crate::ir::FunctionData::try_add_order_constraint).
+  OrderViolation {
+    /// The instruction that must be placed first.
+    before: Value,
+    /// The instruction that must be placed after `before`.
+    after: Value,
+  },
+  /// A global allocation is created in a data flow graph of a function.
+  GlobalAllocInFunction,
+  /// The value is not a local instruction.
+  NotInstruction {
+    /// Handle of the value.
+    value: Value,
+  },
+  /// The value is neither a constant nor a pure instruction, so it can
+  /// not be cloned into another data flow graph.
+  NotCloneable {
+    /// Handle of the value.
+    value: Value,
+  },
+  /// The basic block has already been terminated,
+  /// no more instructions can be appended to it.
+  Terminated {
+    /// Handle of the basic block.
+    bb: BasicBlock,
+  },
+  /// The incoming values of a phi function do not match the
+  /// predecessors of the basic block.
+  IncomingMismatch {
+    /// Handle of the basic block.
+    bb: BasicBlock,
+  },
+  /// Failed to parse the text form Koopa IR.
+  ///
+  /// The message is empty if the error has been reported by the
+  /// front-end logger.
+  Parse {
+    /// The error message.
+    message: String,
+  },
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Error::ExcessElements { ty, len } => {
+        write!(f, "too many elements ({len}) in initializer of type {ty}")
+      }
+      Error::InitTypeMismatch { ty } => {
+        write!(f, "initializer does not match type {ty}")
+      }
+      Error::NotIndexable { step, ty } => {
+        write!(f, "can not index pointer of type {ty} at step {step}")
+      }
+      Error::NotConstant => write!(f, "value is not a constant"),
+      Error::InvalidName { name } => {
+        let reason = match check_symbol(name) {
+          Ok(()) => "global symbols must start with '@'".into(),
+          Err(e) => e,
+        };
+        write!(f, "invalid symbol name '{name}': {reason}")
+      }
+      Error::NameConflict { name } => write!(f, "symbol '{name}' has already been defined"),
+      Error::ValueNotFound { value } => write!(f, "value {value:?} does not exist"),
+      Error::BasicBlockNotFound { bb } => write!(f, "basic block {bb:?} does not exist"),
+      Error::FunctionNotFound { func } => write!(f, "function {func:?} does not exist"),
+      Error::TypeMismatch {
+        operand,
+        expected,
+        found,
+      } => write!(f, "{operand} must be {expected}, found type {found}"),
+      Error::ArgMismatch { target } => {
+        write!(f, "arguments do not match the parameters of {target}")
+      }
+      Error::CallMismatch {
+        callee,
+        expected,
+        found,
+        arg,
+      } => {
+        write!(
+          f,
+          "arguments of call to {callee} do not match its parameters"
+        )?;
+        if expected != found {
+          write!(f, ", expected {expected} arguments, found {found}")?;
+        }
+        match arg {
+          Some((i, param, arg)) => {
+            write!(
+              f,
+              ", argument {i} must be of type {param}, found type {arg}"
+            )
+          }
+          None => Ok(()),
+        }
+      }
+      Error::RetMismatch { expected, found } if found.is_unit() => write!(
+        f,
+        "expected return value of type {expected}, but returned nothing"
+      ),
+      Error::RetMismatch { expected, found } if expected.is_unit() => write!(
+        f,
+        "function has no return value, but a value of type {found} has been returned"
+      ),
+      Error::RetMismatch { expected, found } => {
+        write!(
+          f,
+          "return value must be of type {expected}, found type {found}"
+        )
+      }
+      Error::ArrayReturn { ty } => {
+        write!(f, "functions can not return arrays, found return type {ty}")
+      }
+      Error::StoreMismatch { value, dest } => {
+        write!(
+          f,
+          "can not store value of type {value} to pointer of type {dest}"
+        )
+      }
+      Error::SameTargetArgs => write!(
+        f,
+        "branches with same targets and one or more arguments are illegal"
+      ),
+      Error::EmptyAggregate => write!(f, "aggregate must have at least one element"),
+      Error::ValueInUse { value } => write!(f, "value {value:?} is used by other values"),
+      Error::BasicBlockInUse { bb } => {
+        write!(f, "basic block {bb:?} is used by other values")
+      }
+      Error::NotInLayout { bb } => write!(f, "basic block {bb:?} is not in the layout"),
+      Error::InstInLayout { inst } => {
+        write!(f, "instruction {inst:?} is already in the layout")
+      }
+      Error::InstNotInLayout { inst } => {
+        write!(f, "instruction {inst:?} is not in the layout")
+      }
+      Error::OrderViolation { before, after } => write!(
+        f,
+        "instruction {before:?} must be placed before instruction {after:?}"
+      ),
+      Error::GlobalAllocInFunction => {
+        write!(f, "global allocations can not be created in functions")
+      }
+      Error::NotInstruction { value } => write!(f, "value {value:?} is not an instruction"),
+      Error::NotCloneable { value } => write!(
+        f,
+        "value {value:?} is not a constant or a pure instruction, and can not be cloned"
+      ),
+      Error::Terminated { bb } => {
+        write!(f, "basic block {bb:?} has already been terminated")
+      }
+      Error::IncomingMismatch { bb } => write!(
+        f,
+        "incoming values do not match the predecessors of basic block {bb:?}"
+      ),
+      Error::Parse { message } if message.is_empty() => write!(f, "failed to parse the IR"),
+      Error::Parse { message } => write!(f, "failed to parse the IR: {message}"),
+    }
+  }
+}
+
+impl error::Error for Error {}
@@ -1,34 +1,33 @@
 //! Value/basic block/function ID manager.
 
-use std::cell::Cell;
-use std::num::NonZeroU32;
+use std::num::{NonZeroU32, NonZeroU64};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 
 /// Type of `Value` identifier.
 ///
 /// The IDs of `Value`s are unique, but two different IDs may correspond to
 /// the same `Value`. For example, two integer constants are the same but
 /// have different IDs.
-pub(in crate::ir) type ValueId = NonZeroU32;
+///
+/// IDs of global values are allocated by the ID manager, and IDs of local
+/// values are allocated by the [`Arena`](crate::ir::arena::Arena) of
+/// the data flow graph.
+pub(in crate::ir) type ValueId = NonZeroU64;
 
 /// The value of `ValueId` (global value) should start from 1,
-/// because we want to use `NonZeroU32` to enable some
+/// because we want to use `NonZeroU64` to enable some
 /// memory layout optimization.
-const GLOBAL_VALUE_ID_STARTS_FROM: ValueId = unsafe { NonZeroU32::new_unchecked(1) };
+const GLOBAL_VALUE_ID_STARTS_FROM: ValueId = NonZeroU64::new(1).unwrap();
 
-/// The value of `ValueId` (local value) should start from 1,
-/// because we want to use `NonZeroU32` to enable some
-/// memory layout optimization.
-const LOCAL_VALUE_ID_STARTS_FROM: ValueId = unsafe { NonZeroU32::new_unchecked(0x40000000) };
+/// All IDs allocated by arenas (local values and basic blocks) have
+/// this bit set.
+pub(in crate::ir) const LOCAL_ID_FLAG: u64 = 1 << 63;
 
 /// Type of `BasicBlock` identifier.
 ///
-/// The IDs of `BasicBlock`s are unique.
-pub(in crate::ir) type BasicBlockId = NonZeroU32;
-
-/// The value of `BasicBlockId` should start from 1,
-/// because we want to use `NonZeroU32` to enable some
-/// memory layout optimization.
-const BB_ID_STARTS_FROM: BasicBlockId = unsafe { NonZeroU32::new_unchecked(1) };
+/// The IDs of `BasicBlock`s are unique, and they are allocated by the
+/// [`Arena`](crate::ir::arena::Arena) of the data flow graph.
+pub(in crate::ir) type BasicBlockId = NonZeroU64;
 
 /// Type of `Function` identifier.
 ///
@@ -38,42 +37,50 @@ pub(in crate::ir) type FunctionId = NonZeroU32;
 /// The value of `FunctionId` should start from 1,
 /// because we want to use `NonZeroU32` to enable some
 /// memory layout optimization.
-const FUNC_ID_STARTS_FROM: FunctionId = unsafe { NonZeroU32::new_unchecked(1) };
-
-thread_local! {
-  /// The next global value ID.
-  static NEXT_GLOBAL_VALUE_ID: Cell<ValueId> = Cell::new(GLOBAL_VALUE_ID_STARTS_FROM);
-  /// The next local value ID.
-  static NEXT_LOCAL_VALUE_ID: Cell<ValueId> = Cell::new(LOCAL_VALUE_ID_STARTS_FROM);
-  /// The next basic block ID.
-  static NEXT_BB_ID: Cell<BasicBlockId> = Cell::new(BB_ID_STARTS_FROM);
-  /// The next function ID.
-  static NEXT_FUNC_ID: Cell<FunctionId> = Cell::new(FUNC_ID_STARTS_FROM);
-}
+const FUNC_ID_STARTS_FROM: FunctionId = NonZeroU32::new(1).unwrap();
+
+/// Type of arena identifier.
+///
+/// The IDs of arenas are unique, and they are encoded into the IDs of
+/// local values and basic blocks.
+pub(in crate::ir) type ArenaId = u32;
+
+/// Maximum number of arena IDs.
+const MAX_ARENA_ID: ArenaId = 1 << 23;
+
+/// The next global value ID.
+static NEXT_GLOBAL_VALUE_ID: AtomicU64 = AtomicU64::new(GLOBAL_VALUE_ID_STARTS_FROM.get());
+
+/// The next function ID.
+static NEXT_FUNC_ID: AtomicU32 = AtomicU32::new(FUNC_ID_STARTS_FROM.get());
+
+/// The next arena ID.
+static NEXT_ARENA_ID: AtomicU32 = AtomicU32::new(0);
 
 /// Returns the next global value ID.
 pub(in crate::ir) fn next_global_value_id() -> ValueId {
-  NEXT_GLOBAL_VALUE_ID
-    .with(|id| id.replace(unsafe { NonZeroU32::new_unchecked(id.get().get() + 1) }))
-}
-
-/// Returns the next local value ID.
-pub(in crate::ir) fn next_local_value_id() -> ValueId {
-  NEXT_LOCAL_VALUE_ID
-    .with(|id| id.replace(unsafe { NonZeroU32::new_unchecked(id.get().get() + 1) }))
+  NonZeroU64::new(NEXT_GLOBAL_VALUE_ID.fetch_add(1, Ordering::Relaxed)).unwrap()
 }
 
 /// Returns `true` if the given value ID is a global value ID.
 pub(in crate::ir) fn is_global_id(value: ValueId) -> bool {
-  value >= GLOBAL_VALUE_ID_STARTS_FROM && value < LOCAL_VALUE_ID_STARTS_FROM
-}
-
-/// Returns the next basic block ID.
-pub(in crate::ir) fn next_bb_id() -> BasicBlockId {
-  NEXT_BB_ID.with(|id| id.replace(unsafe { NonZeroU32::new_unchecked(id.get().get() + 1) }))
+  value.get() & LOCAL_ID_FLAG == 0
 }
 
 /// Returns the next function ID.
 pub(in crate::ir) fn next_func_id() -> FunctionId {
-  NEXT_FUNC_ID.with(|id| id.replace(unsafe { NonZeroU32::new_unchecked(id.get().get() + 1) }))
+  NonZeroU32::new(NEXT_FUNC_ID.fetch_add(1, Ordering::Relaxed)).unwrap()
+}
+
+/// Returns the next arena ID.
+///
+/// # Panics
+///
+/// Panics if too many arenas have been created.
+pub(in crate::ir) fn next_arena_id() -> ArenaId {
+  NEXT_ARENA_ID
+    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |id| {
+      (id < MAX_ARENA_ID).then_some(id + 1)
+    })
+    .expect("too many data flow graphs")
 }
@@ -2,12 +2,12 @@
 //! related implementations.
 
 use crate::ir::entities::{BasicBlock, Value};
+use crate::ir::mutation::{MutationLogCell, MutationOp};
 use key_node_list::{impl_node, KeyNodeList, Map};
 use std::borrow::Borrow;
-use std::cell::RefCell;
 use std::collections::{hash_map::Entry, HashMap};
 use std::hash::Hash;
-use std::rc::{Rc, Weak};
+use std::sync::{Arc, RwLock, Weak};
 
 /// Layout of instructions and basic blocks in a function.
 ///
@@ -15,7 +15,7 @@ use std::rc::{Rc, Weak};
 /// blocks ([`BasicBlock`]) in function.
 pub struct Layout {
   bbs: BasicBlockList,
-  inst_bb: Rc<RefCell<HashMap<Value, BasicBlock>>>,
+  inst_bb: Arc<RwLock<InstBBMap>>,
 }
 
 impl Layout {
@@ -53,15 +53,21 @@ impl Layout {
   /// Returns the parent basic block of the given instruction, returns
   /// `None` if the given instruction is not in the current layout.
   pub fn parent_bb(&self, inst: Value) -> Option<BasicBlock> {
-    self.inst_bb.as_ref().borrow().get(&inst).copied()
+    self.inst_bb.read().unwrap().map.get(&inst).copied()
+  }
+
+  /// Records insertions and removals of instructions to the given
+  /// mutation log, or stops recording if `log` is `None`.
+  pub(in crate::ir) fn set_mutation_log(&mut self, log: Option<MutationLogCell>) {
+    self.inst_bb.write().unwrap().log = log;
   }
 }
 
 impl Default for Layout {
   fn default() -> Self {
-    let inst_bb = Rc::new(RefCell::new(HashMap::new()));
+    let inst_bb = Arc::new(RwLock::new(InstBBMap::default()));
     Self {
-      bbs: BasicBlockList::with_map(BasicBlockMap::new(Rc::downgrade(&inst_bb))),
+      bbs: BasicBlockList::with_map(BasicBlockMap::new(Arc::downgrade(&inst_bb))),
       inst_bb,
     }
   }
@@ -80,7 +86,15 @@ impl Default for Layout {
 /// [`insert_key_after`](key_node_list::CursorMut::insert_key_after).
 pub type BasicBlockList = KeyNodeList<BasicBlock, BasicBlockNode, BasicBlockMap>;
 
-type InstBBCell = Weak<RefCell<HashMap<Value, BasicBlock>>>;
+/// Parent basic blocks of all instructions in the layout, and the
+/// mutation log of the layout.
+#[derive(Default)]
+struct InstBBMap {
+  map: HashMap<Value, BasicBlock>,
+  log: Option<MutationLogCell>,
+}
+
+type InstBBCell = Weak<RwLock<InstBBMap>>;
 
 /// The underlying hash map of the [`BasicBlockList`].
 pub struct BasicBlockMap {
@@ -106,18 +120,18 @@ impl Map<BasicBlock, BasicBlockNode> for BasicBlockMap {
     self.map.clear()
   }
 
-  fn get<Q: ?Sized>(&self, k: &Q) -> Option<&BasicBlockNode>
+  fn get<Q>(&self, k: &Q) -> Option<&BasicBlockNode>
   where
     BasicBlock: Borrow<Q>,
-    Q: Hash + Eq,
+    Q: ?Sized + Hash + Eq,
   {
     self.map.get(k)
   }
 
-  fn get_mut<Q: ?Sized>(&mut self, k: &Q) -> Option<&mut BasicBlockNode>
+  fn get_mut<Q>(&mut self, k: &Q) -> Option<&mut BasicBlockNode>
   where
     BasicBlock: Borrow<Q>,
-    Q: Hash + Eq,
+    Q: ?Sized + Hash + Eq,
   {
     self.map.get_mut(k)
   }
@@ -135,10 +149,10 @@ impl Map<BasicBlock, BasicBlockNode> for BasicBlockMap {
     }
   }
 
-  fn remove_entry<Q: ?Sized>(&mut self, k: &Q) -> Option<(BasicBlock, BasicBlockNode)>
+  fn remove_entry<Q>(&mut self, k: &Q) -> Option<(BasicBlock, BasicBlockNode)>
   where
     BasicBlock: Borrow<Q>,
-    Q: Hash + Eq,
+    Q: ?Sized + Hash + Eq,
   {
     self.map.remove_entry(k)
   }
@@ -225,18 +239,18 @@ impl Map<Value, InstNode> for InstMap {
     self.map.clear()
   }
 
-  fn get<Q: ?Sized>(&self, k: &Q) -> Option<&InstNode>
+  fn get<Q>(&self, k: &Q) -> Option<&InstNode>
   where
     Value: Borrow<Q>,
-    Q: Hash + Eq,
+    Q: ?Sized + Hash + Eq,
   {
     self.map.get(k)
   }
 
-  fn get_mut<Q: ?Sized>(&mut self, k: &Q) -> Option<&mut InstNode>
+  fn get_mut<Q>(&mut self, k: &Q) -> Option<&mut InstNode>
   where
     Value: Borrow<Q>,
-    Q: Hash + Eq,
+    Q: ?Sized + Hash + Eq,
   {
     self.map.get_mut(k)
   }
@@ -248,32 +262,32 @@ impl Map<Value, InstNode> for InstMap {
     if self.contains_key(&k) {
       Err((k, v))
     } else {
-      self
-        .inst_bb
-        .upgrade()
-        .unwrap()
-        .as_ref()
-        .borrow_mut()
-        .insert(k, self.bb);
+      let inst_bb = self.inst_bb.upgrade().unwrap();
+      let mut inst_bb = inst_bb.write().unwrap();
+      inst_bb.map.insert(k, self.bb);
+      if let Some(log) = &inst_bb.log {
+        let mut log = log.lock().unwrap();
+        log.record(MutationOp::InsertInst, Some(k), Some(self.bb), None);
+      }
       self.map.insert(k, v.into());
       Ok(())
     }
   }
 
-  fn remove_entry<Q: ?Sized>(&mut self, k: &Q) -> Option<(Value, InstNode)>
+  fn remove_entry<Q>(&mut self, k: &Q) -> Option<(Value, InstNode)>
   where
     Value: Borrow<Q>,
-    Q: Hash + Eq,
+    Q: ?Sized + Hash + Eq,
   {
     let kv = self.map.remove_entry(k);
-    if kv.is_some() {
-      self
-        .inst_bb
-        .upgrade()
-        .unwrap()
-        .as_ref()
-        .borrow_mut()
-        .remove(k);
+    if let Some((inst, _)) = &kv {
+      let inst_bb = self.inst_bb.upgrade().unwrap();
+      let mut inst_bb = inst_bb.write().unwrap();
+      inst_bb.map.remove(k);
+      if let Some(log) = &inst_bb.log {
+        let mut log = log.lock().unwrap();
+        log.record(MutationOp::RemoveInst, Some(*inst), Some(self.bb), None);
+      }
     }
     kv
   }
@@ -0,0 +1,483 @@
+//! The [`koopa_ir!`](crate::koopa_ir) macro for writing in-memory
+//! Koopa IR programs inline.
+
+/// Builds an in-memory Koopa IR [`Program`](crate::ir::Program) from the
+/// Koopa IR syntax written inline.
+///
+/// The syntax accepted by this macro is almost the same as the text form
+/// Koopa IR, except:
+///
+/// * Every global allocation, function declaration, and every statement
+///   in function bodies must be terminated by a semicolon (`;`).
+/// * Names of values, basic blocks and functions must be valid Rust
+///   identifiers which are not keywords, e.g. `%x`, `@main`, `%entry`.
+///   Numbered values like `%0` are not supported.
+/// * Local values must be defined before they are used in the text.
+///
+/// Every named entity is bound to a Rust variable with the same name,
+/// so using an undefined name (like `%y` in `ret %y`) is a compile error.
+///
+/// Supported instructions are `alloc`, `load`, `store`, `getptr`,
+/// `getelemptr`, binary operations, `br`, `jump`, `call`, `ret` and
+/// `unreachable`.
+/// Global allocations can be initialized by integers, `zeroinit`,
+/// `undef` or aggregates.
+///
+/// # Examples
+///
+/// ```
+/// use koopa::koopa_ir;
+///
+/// let program = koopa_ir! {
+///   global @g = alloc [i32, 2], {1, 2};
+///
+///   decl @putint(i32);
+///
+///   fun @main(): i32 {
+///   %entry:
+///     %x = add 1, 2;
+///     br %x, %then, %end(0);
+///   %then:
+///     call @putint(%x);
+///     jump %end(%x);
+///   %end(%r: i32):
+///     ret %r;
+///   }
+/// };
+/// assert_eq!(program.func_layout().len(), 2);
+/// ```
+#[macro_export]
+macro_rules! koopa_ir {
+  // types
+  (@ty i32) => { $crate::ir::Type::get_i32() };
+  (@ty * $($t:tt)+) => { $crate::ir::Type::get_pointer($crate::koopa_ir!(@ty $($t)+)) };
+  (@ty [ $($t:tt)+ ]) => { $crate::koopa_ir!(@arr [] $($t)+) };
+  (@arr [$($base:tt)*] , $len:literal) => {
+    $crate::ir::Type::get_array($crate::koopa_ir!(@ty $($base)*), $len)
+  };
+  (@arr [$($base:tt)*] $t:tt $($rest:tt)*) => { $crate::koopa_ir!(@arr [$($base)* $t] $($rest)*) };
+
+  // type lists, like `i32, *i32`
+  (@tys [$($acc:tt)*] []) => { vec![$($acc)*] };
+  (@tys [$($acc:tt)*] [$($ty:tt)+]) => {
+    vec![$($acc)* $crate::koopa_ir!(@ty $($ty)+)]
+  };
+  (@tys [$($acc:tt)*] [$($ty:tt)+] , $($rest:tt)*) => {
+    $crate::koopa_ir!(@tys [$($acc)* $crate::koopa_ir!(@ty $($ty)+),] [] $($rest)*)
+  };
+  (@tys [$($acc:tt)*] [$($ty:tt)*] $t:tt $($rest:tt)*) => {
+    $crate::koopa_ir!(@tys [$($acc)*] [$($ty)* $t] $($rest)*)
+  };
+
+  // parameter lists, like `@x: i32, @y: *i32`
+  (@params [$($acc:tt)*]) => { vec![$($acc)*] };
+  (@params [$($acc:tt)*] $sig:tt $p:ident : $($rest:tt)*) => {
+    $crate::koopa_ir!(@param [$($acc)*] [$sig $p] [] $($rest)*)
+  };
+  (@param [$($acc:tt)*] [$sig:tt $p:ident] [$($ty:tt)+] $(, $($rest:tt)*)?) => {
+    $crate::koopa_ir!(@params [
+      $($acc)*
+      (
+        Some(String::from(concat!(stringify!($sig), stringify!($p)))),
+        $crate::koopa_ir!(@ty $($ty)+),
+      ),
+    ] $($($rest)*)?)
+  };
+  (@param [$($acc:tt)*] [$sig:tt $p:ident] [$($ty:tt)*] $t:tt $($rest:tt)*) => {
+    $crate::koopa_ir!(@param [$($acc)*] [$sig $p] [$($ty)* $t] $($rest)*)
+  };
+
+  // binds parameter values to variables
+  (@bind $iter:ident;) => {};
+  (@bind $iter:ident; $sig:tt $p:ident : $($rest:tt)*) => {
+    #[allow(unused_variables)]
+    let $p: $crate::ir::Value = $iter.next().unwrap();
+    $crate::koopa_ir!(@bind_skip $iter; $($rest)*);
+  };
+  (@bind_skip $iter:ident;) => {};
+  (@bind_skip $iter:ident; , $($rest:tt)*) => { $crate::koopa_ir!(@bind $iter; $($rest)*); };
+  (@bind_skip $iter:ident; $t:tt $($rest:tt)*) => { $crate::koopa_ir!(@bind_skip $iter; $($rest)*); };
+
+  // binary operators
+  (@op ne) => { $crate::ir::BinaryOp::NotEq };
+  (@op eq) => { $crate::ir::BinaryOp::Eq };
+  (@op gt) => { $crate::ir::BinaryOp::Gt };
+  (@op lt) => { $crate::ir::BinaryOp::Lt };
+  (@op ge) => { $crate::ir::BinaryOp::Ge };
+  (@op le) => { $crate::ir::BinaryOp::Le };
+  (@op add) => { $crate::ir::BinaryOp::Add };
+  (@op sub) => { $crate::ir::BinaryOp::Sub };
+  (@op mul) => { $crate::ir::BinaryOp::Mul };
+  (@op div) => { $crate::ir::BinaryOp::Div };
+  (@op mod) => { $crate::ir::BinaryOp::Mod };
+  (@op and) => { $crate::ir::BinaryOp::And };
+  (@op or) => { $crate::ir::BinaryOp::Or };
+  (@op xor) => { $crate::ir::BinaryOp::Xor };
+  (@op shl) => { $crate::ir::BinaryOp::Shl };
+  (@op shr) => { $crate::ir::BinaryOp::Shr };
+  (@op sar) => { $crate::ir::BinaryOp::Sar };
+
+  // operands
+  (@opnd $b:ident; % $v:ident) => { $v };
+  (@opnd $b:ident; @ $v:ident) => { $v };
+  (@opnd $b:ident; $l:literal) => { $b.integer($l) };
+  (@opnd $b:ident; - $l:literal) => { $b.integer(-$l) };
+
+  // names a local value
+  (@named $b:ident; $v:ident; $e:expr) => {{
+    let value = $e;
+    let name = String::from(concat!("%", stringify!($v)));
+    $b.func_mut().dfg_mut().set_value_name(value, Some(name));
+    value
+  }};
+
+  // emits the given statements only when building instructions
+  (@emit [bbs] $($t:tt)*) => {};
+  (@emit [insts] $($t:tt)*) => { $($t)* };
+
+  // function bodies, processed in two passes:
+  // creates all basic blocks in pass `bbs`,
+  // and builds all instructions in pass `insts`.
+  (@body $m:tt $b:ident;) => {};
+  (@body [bbs] $b:ident; % $bb:ident $(( $($ps:tt)* ))? : $($rest:tt)*) => {
+    #[allow(unused_variables)]
+    let $bb = {
+      let name = Some(String::from(concat!("%", stringify!($bb))));
+      let params = $crate::koopa_ir!(@params [] $($($ps)*)?);
+      let func = $b.func_mut();
+      let bb = func.dfg_mut().new_bb().basic_block_with_param_names(name, params);
+      func.layout_mut().bbs_mut().push_key_back(bb).unwrap();
+      bb
+    };
+    #[allow(unused_mut, unused_variables)]
+    let mut params = $b.func().dfg().bb($bb).params().to_vec().into_iter();
+    $crate::koopa_ir!(@bind params; $($($ps)*)?);
+    $crate::koopa_ir!(@body [bbs] $b; $($rest)*);
+  };
+  (@body [insts] $b:ident; % $bb:ident $(( $($ps:tt)* ))? : $($rest:tt)*) => {
+    $b.position_at_end($bb);
+    $crate::koopa_ir!(@body [insts] $b; $($rest)*);
+  };
+  (@body $m:tt $b:ident; % $v:ident = alloc $($rest:tt)*) => {
+    $crate::koopa_ir!(@alloc $m $b; $v; [] $($rest)*);
+  };
+  (@body $m:tt $b:ident;
+    % $v:ident = load $(% $s:ident)? $(@ $sg:ident)? ; $($rest:tt)*
+  ) => {
+    $crate::koopa_ir!(@emit $m
+      #[allow(unused_variables)]
+      let $v = $crate::koopa_ir!(@named $b; $v; $b.load($($s)? $($sg)?));
+    );
+    $crate::koopa_ir!(@body $m $b; $($rest)*);
+  };
+  (@body $m:tt $b:ident;
+    % $v:ident = $inst:ident
+      $(% $l:ident)? $(@ $lg:ident)? $($ll:literal)? ,
+      $(% $r:ident)? $(@ $rg:ident)? $($rl:literal)? ;
+    $($rest:tt)*
+  ) => {
+    $crate::koopa_ir!(@emit $m
+      #[allow(unused_variables)]
+      let $v = {
+        let lhs = $crate::koopa_ir!(@opnd $b; $(% $l)? $(@ $lg)? $($ll)?);
+        let rhs = $crate::koopa_ir!(@opnd $b; $(% $r)? $(@ $rg)? $($rl)?);
+        $crate::koopa_ir!(@named $b; $v; $crate::koopa_ir!(@inst $b; $inst; lhs, rhs))
+      };
+    );
+    $crate::koopa_ir!(@body $m $b; $($rest)*);
+  };
+  (@body $m:tt $b:ident;
+    % $v:ident = call @ $f:ident (
+      $($as:tt $($a:ident)? $($al:literal)?),*
+    ) ; $($rest:tt)*
+  ) => {
+    $crate::koopa_ir!(@emit $m
+      #[allow(unused_variables)]
+      let $v = {
+        let args = vec![$($crate::koopa_ir!(@opnd $b; $as $($a)? $($al)?)),*];
+        $crate::koopa_ir!(@named $b; $v; $b.call($f, args))
+      };
+    );
+    $crate::koopa_ir!(@body $m $b; $($rest)*);
+  };
+  (@body $m:tt $b:ident;
+    store
+      $(% $v:ident)? $(@ $vg:ident)? $($vl:literal)? ,
+      $(% $d:ident)? $(@ $dg:ident)? ;
+    $($rest:tt)*
+  ) => {
+    $crate::koopa_ir!(@emit $m {
+      let value = $crate::koopa_ir!(@opnd $b; $(% $v)? $(@ $vg)? $($vl)?);
+      $b.store(value, $($d)? $($dg)?);
+    });
+    $crate::koopa_ir!(@body $m $b; $($rest)*);
+  };
+  (@body $m:tt $b:ident;
+    br $(% $c:ident)? $($cl:literal)? ,
+      % $t:ident $(( $($tas:tt $($ta:ident)? $($tal:literal)?),* ))? ,
+      % $f:ident $(( $($fas:tt $($fa:ident)? $($fal:literal)?),* ))? ;
+    $($rest:tt)*
+  ) => {
+    $crate::koopa_ir!(@emit $m {
+      let cond = $crate::koopa_ir!(@opnd $b; $(% $c)? $($cl)?);
+      let targs = vec![$($($crate::koopa_ir!(@opnd $b; $tas $($ta)? $($tal)?)),*)?];
+      let fargs = vec![$($($crate::koopa_ir!(@opnd $b; $fas $($fa)? $($fal)?)),*)?];
+      $b.branch_with_args(cond, $t, $f, targs, fargs);
+    });
+    $crate::koopa_ir!(@body $m $b; $($rest)*);
+  };
+  (@body $m:tt $b:ident;
+    jump % $t:ident $(( $($as:tt $($a:ident)? $($al:literal)?),* ))? ;
+    $($rest:tt)*
+  ) => {
+    $crate::koopa_ir!(@emit $m {
+      let args = vec![$($($crate::koopa_ir!(@opnd $b; $as $($a)? $($al)?)),*)?];
+      $b.jump_with_args($t, args);
+    });
+    $crate::koopa_ir!(@body $m $b; $($rest)*);
+  };
+  (@body $m:tt $b:ident;
+    call @ $f:ident ( $($as:tt $($a:ident)? $($al:literal)?),* ) ;
+    $($rest:tt)*
+  ) => {
+    $crate::koopa_ir!(@emit $m {
+      let args = vec![$($crate::koopa_ir!(@opnd $b; $as $($a)? $($al)?)),*];
+      $b.call($f, args);
+    });
+    $crate::koopa_ir!(@body $m $b; $($rest)*);
+  };
+  (@body $m:tt $b:ident; unreachable ; $($rest:tt)*) => {
+    $crate::koopa_ir!(@emit $m { $b.unreachable(); });
+    $crate::koopa_ir!(@body $m $b; $($rest)*);
+  };
+  (@body $m:tt $b:ident; ret ; $($rest:tt)*) => {
+    $crate::koopa_ir!(@emit $m { $b.ret(None); });
+    $crate::koopa_ir!(@body $m $b; $($rest)*);
+  };
+  (@body $m:tt $b:ident;
+    ret $(% $v:ident)? $(@ $vg:ident)? $($vl:literal)? ; $($rest:tt)*
+  ) => {
+    $crate::koopa_ir!(@emit $m {
+      let value = $crate::koopa_ir!(@opnd $b; $(% $v)? $(@ $vg)? $($vl)?);
+      $b.ret(Some(value));
+    });
+    $crate::koopa_ir!(@body $m $b; $($rest)*);
+  };
+
+  // local allocations
+  (@alloc $m:tt $b:ident; $v:ident; [$($ty:tt)+] ; $($rest:tt)*) => {
+    $crate::koopa_ir!(@emit $m
+      #[allow(unused_variables)]
+      let $v = $crate::koopa_ir!(@named $b; $v; $b.alloc($crate::koopa_ir!(@ty $($ty)+)));
+    );
+    $crate::koopa_ir!(@body $m $b; $($rest)*);
+  };
+  (@alloc $m:tt $b:ident; $v:ident; [$($ty:tt)*] $t:tt $($rest:tt)*) => {
+    $crate::koopa_ir!(@alloc $m $b; $v; [$($ty)* $t] $($rest)*);
+  };
+
+  // instructions with two operands
+  (@inst $b:ident; getptr; $l:expr, $r:expr) => { $b.get_ptr($l, $r) };
+  (@inst $b:ident; getelemptr; $l:expr, $r:expr) => { $b.get_elem_ptr($l, $r) };
+  (@inst $b:ident; $op:ident; $l:expr, $r:expr) => { $b.binary($crate::koopa_ir!(@op $op), $l, $r) };
+
+  // global initializers
+  (@init $p:ident; $ty:expr; zeroinit) => { $p.new_value().zero_init($ty) };
+  (@init $p:ident; $ty:expr; undef) => { $p.new_value().undef($ty) };
+  (@init $p:ident; $ty:expr; $l:literal) => { $p.new_value().integer($l) };
+  (@init $p:ident; $ty:expr; { $($($l:literal)? $({ $($e:tt)* })?),* }) => {{
+    let elems = vec![$($crate::koopa_ir!(@init $p; (); $($l)? $({ $($e)* })?)),*];
+    $p.new_value().aggregate(elems)
+  }};
+
+  // global items, processed in two passes:
+  // creates all global allocations and functions in pass `decls`,
+  // and builds all function bodies in pass `bodies`.
+  (@items $m:tt $p:ident;) => {};
+  (@items $m:tt $p:ident; global @ $g:ident = alloc $($rest:tt)*) => {
+    $crate::koopa_ir!(@global $m $p; $g; [] $($rest)*);
+  };
+  (@items $m:tt $p:ident; decl @ $f:ident ( $($tys:tt)* ) ; $($rest:tt)*) => {
+    $crate::koopa_ir!(@decl $m $p; $f; [$($tys)*] [] ; $($rest)*);
+  };
+  (@items $m:tt $p:ident; decl @ $f:ident ( $($tys:tt)* ) : $($rest:tt)*) => {
+    $crate::koopa_ir!(@decl $m $p; $f; [$($tys)*] [] $($rest)*);
+  };
+  (@items $m:tt $p:ident; fun @ $f:ident ( $($ps:tt)* ) { $($body:tt)* } $($rest:tt)*) => {
+    $crate::koopa_ir!(@fun $m $p; $f; [$($ps)*] [] { $($body)* } $($rest)*);
+  };
+  (@items $m:tt $p:ident; fun @ $f:ident ( $($ps:tt)* ) : $($rest:tt)*) => {
+    $crate::koopa_ir!(@fun $m $p; $f; [$($ps)*] [] $($rest)*);
+  };
+
+  // global allocations
+  (@global [decls] $p:ident; $g:ident; [$($ty:tt)+] , $($rest:tt)*) => {
+    $crate::koopa_ir!(@global_init $p; $g; [$($ty)+] [] $($rest)*);
+  };
+  (@global [bodies] $p:ident; $g:ident; [$($ty:tt)+] , $($rest:tt)*) => {
+    $crate::koopa_ir!(@global_skip $p; $($rest)*);
+  };
+  (@global $m:tt $p:ident; $g:ident; [$($ty:tt)*] $t:tt $($rest:tt)*) => {
+    $crate::koopa_ir!(@global $m $p; $g; [$($ty)* $t] $($rest)*);
+  };
+  (@global_init $p:ident; $g:ident; [$($ty:tt)+] [$($init:tt)+] ; $($rest:tt)*) => {
+    #[allow(unused_variables)]
+    let $g = {
+      let init = $crate::koopa_ir!(@init $p; $crate::koopa_ir!(@ty $($ty)+); $($init)+);
+      let alloc = $p.new_value().global_alloc(init);
+      $p.set_value_name(alloc, Some(String::from(concat!("@", stringify!($g)))));
+      alloc
+    };
+    $crate::koopa_ir!(@items [decls] $p; $($rest)*);
+  };
+  (@global_init $p:ident; $g:ident; [$($ty:tt)+] [$($init:tt)*] $t:tt $($rest:tt)*) => {
+    $crate::koopa_ir!(@global_init $p; $g; [$($ty)+] [$($init)* $t] $($rest)*);
+  };
+  (@global_skip $p:ident; ; $($rest:tt)*) => { $crate::koopa_ir!(@items [bodies] $p; $($rest)*); };
+  (@global_skip $p:ident; $t:tt $($rest:tt)*) => { $crate::koopa_ir!(@global_skip $p; $($rest)*); };
+
+  // function declarations
+  (@decl $m:tt $p:ident; $f:ident; [$($tys:tt)*] [$($ret:tt)*] ; $($rest:tt)*) => {
+    $crate::koopa_ir!(@emit_decl $m
+      #[allow(unused_variables)]
+      let $f = $p.new_func($crate::ir::FunctionData::new_decl(
+        String::from(concat!("@", stringify!($f))),
+        $crate::koopa_ir!(@tys [] [] $($tys)*),
+        $crate::koopa_ir!(@ret $($ret)*),
+      ));
+    );
+    $crate::koopa_ir!(@items $m $p; $($rest)*);
+  };
+  (@decl $m:tt $p:ident; $f:ident; [$($tys:tt)*] [$($ret:tt)*] $t:tt $($rest:tt)*) => {
+    $crate::koopa_ir!(@decl $m $p; $f; [$($tys)*] [$($ret)* $t] $($rest)*);
+  };
+
+  // function definitions
+  (@fun [decls] $p:ident; $f:ident; [$($ps:tt)*] [$($ret:tt)*] { $($body:tt)* } $($rest:tt)*) => {
+    #[allow(unused_variables)]
+    let $f = $p.new_func($crate::ir::FunctionData::with_param_names(
+      String::from(concat!("@", stringify!($f))),
+      $crate::koopa_ir!(@params [] $($ps)*),
+      $crate::koopa_ir!(@ret $($ret)*),
+    ));
+    $crate::koopa_ir!(@items [decls] $p; $($rest)*);
+  };
+  (@fun [bodies] $p:ident; $f:ident; [$($ps:tt)*] [$($ret:tt)*] { $($body:tt)* } $($rest:tt)*) => {
+    {
+      let mut builder = $crate::ir::builder::FunctionBuilder::new($p.func_mut($f));
+      #[allow(unused_mut, unused_variables)]
+      let mut params = builder.func().params().to_vec().into_iter();
+      $crate::koopa_ir!(@bind params; $($ps)*);
+      $crate::koopa_ir!(@body [bbs] builder; $($body)*);
+      $crate::koopa_ir!(@body [insts] builder; $($body)*);
+    }
+    $crate::koopa_ir!(@items [bodies] $p; $($rest)*);
+  };
+  (@fun $m:tt $p:ident; $f:ident; [$($ps:tt)*] [$($ret:tt)*] $t:tt $($rest:tt)*) => {
+    $crate::koopa_ir!(@fun $m $p; $f; [$($ps)*] [$($ret)* $t] $($rest)*);
+  };
+
+  // return types
+  (@ret) => { $crate::ir::Type::get_unit() };
+  (@ret $($ty:tt)+) => { $crate::koopa_ir!(@ty $($ty)+) };
+
+  (@emit_decl [decls] $($t:tt)*) => { $($t)* };
+  (@emit_decl [bodies] $($t:tt)*) => {};
+
+  ($($items:tt)*) => {{
+    #[allow(unused_imports)]
+    use $crate::ir::builder_traits::*;
+    #[allow(unused_mut)]
+    let mut program = $crate::ir::Program::new();
+    $crate::koopa_ir!(@items [decls] program; $($items)*);
+    $crate::koopa_ir!(@items [bodies] program; $($items)*);
+    program
+  }};
+}
+
+#[cfg(test)]
+mod test {
+  use crate::back::KoopaGenerator;
+  use crate::front::Driver;
+  use crate::ir::Program;
+
+  fn dump(program: &Program) -> String {
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(program).unwrap();
+    std::str::from_utf8(&gen.writer()).unwrap().into()
+  }
+
+  #[test]
+  fn build_program() {
+    let program = koopa_ir! {
+      global @arr = alloc [[i32, 2], 2], {{1, -2}, {3, 4}};
+      global @ptr = alloc *i32, zeroinit;
+
+      decl @getint(): i32;
+
+      fun @max(@a: i32, @b: i32): i32 {
+      %entry:
+        %c = gt @a, @b;
+        br %c, %then, %end(@b);
+      %then:
+        jump %end(@a);
+      %end(%ans: i32):
+        ret %ans;
+      }
+
+      fun @main(): i32 {
+      %entry:
+        %p = alloc *[i32, 2];
+        %x = call @getint();
+        %y = call @max(%x, 10);
+        %e = getelemptr @arr, 1;
+        store %e, %p;
+        %q = load %p;
+        %z = getelemptr %q, 0;
+        %w = load %z;
+        %v = mod %y, %w;
+        jump %exit;
+      %exit:
+        ret %v;
+      }
+    };
+    let expected: Driver<_> = r#"
+global @arr = alloc [[i32, 2], 2], {{1, -2}, {3, 4}}
+
+global @ptr = alloc *i32, zeroinit
+
+decl @getint(): i32
+
+fun @max(@a: i32, @b: i32): i32 {
+%entry:
+  %c = gt @a, @b
+  br %c, %then, %end(@b)
+
+%then:
+  jump %end(@a)
+
+%end(%ans: i32):
+  ret %ans
+}
+
+fun @main(): i32 {
+%entry:
+  %p = alloc *[i32, 2]
+  %x = call @getint()
+  %y = call @max(%x, 10)
+  %e = getelemptr @arr, 1
+  store %e, %p
+  %q = load %p
+  %z = getelemptr %q, 0
+  %w = load %z
+  %v = mod %y, %w
+  jump %exit
+
+%exit:
+  ret %v
+}
+"#
+    .into();
+    assert_eq!(dump(&program), dump(&expected.generate_program().unwrap()));
+  }
+}
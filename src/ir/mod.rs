@@ -9,6 +9,14 @@
 //!   [`ValueData`](entities::ValueData)).
 //! * Types of IR values ([`Type`]).
 //! * IR builders and IR builder traits ([`builder`]).
+//! * Errors of fallible IR operations ([`Error`]).
+//! * Structural diffs between programs ([`diff`]), and equivalence
+//!   checks of programs up to renaming ([`equiv`]).
+//! * Conversions between basic block parameters and phi functions
+//!   ([`phi`]).
+//! * Validation and sanitization of symbol names ([`is_valid_symbol`],
+//!   [`sanitize_symbol`]).
+//! * Visitors for traversing programs ([`visit`]).
 //!
 //! # Example
 //!
@@ -76,14 +84,25 @@
 //! fib_data.layout_mut().bb_mut(else_bb).insts_mut().extend([sub1, call1, sub2, call2, ans, ret]);
 //! ```
 
+pub mod arena;
 pub mod builder;
 pub mod dfg;
+pub mod diff;
 pub mod entities;
+pub mod equiv;
+pub mod error;
 pub mod layout;
+pub mod mutation;
+pub mod phi;
 pub mod types;
+pub mod validation;
+pub mod value_list;
 pub mod values;
+pub mod visit;
 
 mod idman;
+mod macros;
+mod symbol;
 
 pub mod builder_traits {
   //! Re-exportations of IR builder traits.
@@ -102,6 +121,12 @@ pub mod builder_traits {
   pub use super::builder::{BasicBlockBuilder, GlobalInstBuilder, LocalInstBuilder, ValueBuilder};
 }
 
-pub use entities::{BasicBlock, Function, FunctionData, Program, Value, ValueKind};
+pub use entities::{
+  AttrValue, BasicBlock, Const, FuncAttrs, FuncUsers, Function, FunctionData, Inst, Program, Value,
+  ValueKind, ValueLocation, ValueRef, ValueToken,
+};
+pub use error::Error;
+pub use symbol::{is_valid_symbol, sanitize_symbol};
 pub use types::{Type, TypeKind};
-pub use values::BinaryOp;
+pub use validation::Validation;
+pub use values::{BinaryOp, OverflowMode};
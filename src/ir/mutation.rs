@@ -0,0 +1,203 @@
+//! Mutation logs ([`MutationLog`]) of data flow graphs and layouts,
+//! for debugging passes.
+//!
+//! The log is disabled by default, and only costs a check of an empty
+//! [`Option`] on every mutation. After enabling the log by
+//! [`DataFlowGraph::enable_mutation_log`](crate::ir::dfg::DataFlowGraph::enable_mutation_log),
+//! all the following mutations of the data flow graph are recorded as
+//! [`MutationEvent`]s:
+//!
+//! * Creating values (interned constants that already exist are not
+//!   recorded), replacing values and removing values.
+//! * Creating basic blocks and removing basic blocks. Parameters of the
+//!   removed basic blocks are recorded as removed values.
+//!
+//! [`FunctionData::enable_mutation_log`](crate::ir::FunctionData::enable_mutation_log)
+//! also records insertions and removals of instructions in the layout
+//! to the same log.
+//!
+//! # Example
+//!
+//! ```
+//! use koopa::ir::builder_traits::*;
+//! use koopa::ir::mutation::MutationOp;
+//! use koopa::ir::{FunctionData, Type};
+//!
+//! let mut func = FunctionData::new("@f".into(), Vec::new(), Type::get_unit());
+//! func.enable_mutation_log();
+//! func.dfg_mut().set_mutation_label("build");
+//! let entry = func.dfg_mut().new_bb().basic_block(None);
+//! func.layout_mut().bbs_mut().push_key_back(entry).unwrap();
+//! let ret = func.dfg_mut().new_value().ret(None);
+//! func.layout_mut().bb_mut(entry).insts_mut().push_key_back(ret).unwrap();
+//!
+//! let log = func.dfg().mutation_log().unwrap();
+//! let ops: Vec<_> = log.events().iter().map(|e| e.op).collect();
+//! assert_eq!(
+//!   ops,
+//!   [MutationOp::NewBasicBlock, MutationOp::NewValue, MutationOp::InsertInst],
+//! );
+//! assert_eq!(log.events()[2].label.as_deref(), Some("build"));
+//! ```
+
+use crate::ir::entities::{BasicBlock, Value, ValueKind};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Operations recorded in [`MutationLog`]s.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MutationOp {
+  /// A value is created.
+  NewValue,
+  /// A value is replaced with a new value data.
+  ReplaceValue,
+  /// A value is removed.
+  RemoveValue,
+  /// A basic block is created.
+  NewBasicBlock,
+  /// A basic block is removed.
+  RemoveBasicBlock,
+  /// An instruction is inserted into a basic block in the layout.
+  InsertInst,
+  /// An instruction is removed from a basic block in the layout.
+  RemoveInst,
+}
+
+impl fmt::Display for MutationOp {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(match self {
+      MutationOp::NewValue => "new_value",
+      MutationOp::ReplaceValue => "replace_value",
+      MutationOp::RemoveValue => "remove_value",
+      MutationOp::NewBasicBlock => "new_bb",
+      MutationOp::RemoveBasicBlock => "remove_bb",
+      MutationOp::InsertInst => "insert_inst",
+      MutationOp::RemoveInst => "remove_inst",
+    })
+  }
+}
+
+/// A mutation recorded in a [`MutationLog`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MutationEvent {
+  /// The operation.
+  pub op: MutationOp,
+  /// The value involved, if any.
+  pub value: Option<Value>,
+  /// The basic block involved, if any. For instructions in the layout,
+  /// this is the basic block of the instruction.
+  pub bb: Option<BasicBlock>,
+  /// Kind name of the value, like `binary` or `integer`. For replaced
+  /// values, this is the kind of the new value data. `None` for basic
+  /// blocks and instructions in the layout.
+  pub kind: Option<&'static str>,
+  /// The label at the time of the mutation, see
+  /// [`DataFlowGraph::set_mutation_label`](crate::ir::dfg::DataFlowGraph::set_mutation_label).
+  pub label: Option<Arc<str>>,
+}
+
+impl fmt::Display for MutationEvent {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if let Some(label) = &self.label {
+      write!(f, "[{label}] ")?;
+    }
+    write!(f, "{}", self.op)?;
+    if let Some(value) = self.value {
+      write!(f, " {value:?}")?;
+    }
+    if let Some(kind) = self.kind {
+      write!(f, " ({kind})")?;
+    }
+    if let Some(bb) = self.bb {
+      let sep = if self.value.is_some() { " in" } else { "" };
+      write!(f, "{sep} {bb:?}")?;
+    }
+    Ok(())
+  }
+}
+
+/// Log of mutations of a data flow graph, and optionally the layout of
+/// the function.
+///
+/// The log is printed as one event per line.
+#[derive(Debug, Default)]
+pub struct MutationLog {
+  events: Vec<MutationEvent>,
+  label: Option<Arc<str>>,
+}
+
+impl MutationLog {
+  /// Returns all recorded events, in the order of the mutations.
+  pub fn events(&self) -> &[MutationEvent] {
+    &self.events
+  }
+
+  /// Returns the current label.
+  pub fn label(&self) -> Option<&str> {
+    self.label.as_deref()
+  }
+
+  /// Removes all recorded events. The label is kept.
+  pub fn clear(&mut self) {
+    self.events.clear();
+  }
+
+  /// Sets the label of the following events.
+  pub(in crate::ir) fn set_label(&mut self, label: Option<&str>) {
+    self.label = label.map(Arc::from);
+  }
+
+  /// Records an event.
+  pub(in crate::ir) fn record(
+    &mut self,
+    op: MutationOp,
+    value: Option<Value>,
+    bb: Option<BasicBlock>,
+    kind: Option<&ValueKind>,
+  ) {
+    self.events.push(MutationEvent {
+      op,
+      value,
+      bb,
+      kind: kind.map(kind_name),
+      label: self.label.clone(),
+    });
+  }
+}
+
+impl fmt::Display for MutationLog {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for event in &self.events {
+      writeln!(f, "{event}")?;
+    }
+    Ok(())
+  }
+}
+
+/// A mutation log shared by a data flow graph and a layout.
+pub(in crate::ir) type MutationLogCell = Arc<Mutex<MutationLog>>;
+
+/// Returns the kind name of the given value kind.
+fn kind_name(kind: &ValueKind) -> &'static str {
+  match kind {
+    ValueKind::Integer(_) => "integer",
+    ValueKind::ZeroInit(_) => "zeroinit",
+    ValueKind::Undef(_) => "undef",
+    ValueKind::Aggregate(_) => "aggregate",
+    ValueKind::Bytes(_) => "bytes",
+    ValueKind::FuncArgRef(_) => "func_arg_ref",
+    ValueKind::BlockArgRef(_) => "block_arg_ref",
+    ValueKind::Alloc(_) => "alloc",
+    ValueKind::GlobalAlloc(_) => "global_alloc",
+    ValueKind::Load(_) => "load",
+    ValueKind::Store(_) => "store",
+    ValueKind::GetPtr(_) => "getptr",
+    ValueKind::GetElemPtr(_) => "getelemptr",
+    ValueKind::Binary(_) => "binary",
+    ValueKind::Branch(_) => "br",
+    ValueKind::Jump(_) => "jump",
+    ValueKind::Call(_) => "call",
+    ValueKind::Return(_) => "ret",
+    ValueKind::Unreachable(_) => "unreachable",
+  }
+}
@@ -0,0 +1,326 @@
+//! Conversions between basic block parameters and phi functions.
+//!
+//! Koopa IR passes values between basic blocks by basic block parameters
+//! and arguments of branches and jumps, while many other IRs use phi
+//! functions. A basic block parameter is equivalent to a phi function,
+//! whose incoming values are the corresponding arguments passed by the
+//! predecessors:
+//!
+//! ```koopa
+//! %end(%x: i32):        // %x = phi [1, %then], [2, %else]
+//!   ret %x
+//! ```
+//!
+//! [`to_phi_view`] collects the incoming values of all basic block
+//! parameters in a function, and [`from_phi`] creates a new basic block
+//! parameter from incoming values.
+
+use crate::ir::builder::FunctionBuilder;
+use crate::ir::{BasicBlock, Error, FunctionData, Type, Value, ValueKind};
+use std::collections::HashMap;
+
+/// Incoming values of basic block parameters in a function,
+/// see [`to_phi_view`].
+///
+/// The view is a snapshot of the function, it is not updated when the
+/// function is modified.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PhiView {
+  incoming: HashMap<Value, Vec<(BasicBlock, Value)>>,
+}
+
+impl PhiView {
+  /// Returns pairs of the predecessor and the incoming value of the given
+  /// basic block parameter, in the layout order of the predecessors.
+  ///
+  /// Returns `None` if the value is not a basic block parameter of the
+  /// viewed function.
+  pub fn incoming(&self, param: Value) -> Option<&[(BasicBlock, Value)]> {
+    self.incoming.get(&param).map(|i| i.as_slice())
+  }
+}
+
+/// Returns incoming values of all basic block parameters in the given
+/// function.
+///
+/// Predecessors are basic blocks whose terminators target the basic
+/// block of the parameter, only basic blocks in the layout are
+/// considered. A predecessor that targets the basic block by both edges
+/// of a branch appears only once. This can only happen to basic blocks
+/// without parameters in a valid function, since branches with the same
+/// targets can not pass arguments.
+pub fn to_phi_view(func: &FunctionData) -> PhiView {
+  let dfg = func.dfg();
+  let mut incoming: HashMap<_, Vec<_>> = HashMap::new();
+  for data in dfg.bbs().values() {
+    for param in data.params() {
+      incoming.insert(*param, Vec::new());
+    }
+  }
+  for (&pred, node) in func.layout().bbs() {
+    let Some(term) = node.insts().back_key() else {
+      continue;
+    };
+    for (target, args) in dfg.value(*term).kind().edges() {
+      let Some(params) = dfg.bbs().get(&target).map(|d| d.params()) else {
+        continue;
+      };
+      for (param, arg) in params.iter().zip(args) {
+        let incoming = incoming.get_mut(param).unwrap();
+        if !incoming.contains(&(pred, *arg)) {
+          incoming.push((pred, *arg));
+        }
+      }
+    }
+  }
+  PhiView { incoming }
+}
+
+/// Creates a new parameter for the basic block at the current insert
+/// point of the given builder, from the given pairs of predecessors and
+/// incoming values. Returns the new parameter.
+///
+/// The parameter is appended to the parameter list, and the incoming
+/// values are appended to the argument lists of the edges from the
+/// predecessors, so the argument lists stay in the same order as the
+/// parameters. The same predecessor can appear more than once if the
+/// incoming values are the same.
+///
+/// This is a snapshot of the current predecessors: predecessors added
+/// later must pass the argument by themselves.
+///
+/// # Panics
+///
+/// Panics if the insert point is unset, or with the errors reported by
+/// [`try_from_phi`].
+pub fn from_phi(builder: &mut FunctionBuilder, incoming: &[(BasicBlock, Value)]) -> Value {
+  match try_from_phi(builder, incoming) {
+    Ok(param) => param,
+    Err(e) => panic!("{e}"),
+  }
+}
+
+/// Fallible version of [`from_phi`].
+/// The function is not changed on error.
+///
+/// Returns an error if:
+///
+/// * The predecessors in `incoming` do not match the current
+///   predecessors of the basic block, or a predecessor has different
+///   incoming values ([`Error::IncomingMismatch`]).
+/// * The incoming values have different types, or the unit type
+///   ([`Error::TypeMismatch`]).
+/// * A predecessor targets the basic block by both edges of a branch,
+///   which can not pass arguments ([`Error::SameTargetArgs`]).
+///
+/// # Panics
+///
+/// Panics if the insert point is unset, or any of the incoming values
+/// does not exist.
+pub fn try_from_phi(
+  builder: &mut FunctionBuilder,
+  incoming: &[(BasicBlock, Value)],
+) -> Result<Value, Error> {
+  let bb = builder.current_bb().expect("insert point is unset");
+  let func = builder.func_mut();
+  let mismatch = Error::IncomingMismatch { bb };
+  // terminators of the current predecessors in the layout
+  let terms: Vec<_> = func
+    .dfg()
+    .bb(bb)
+    .used_by()
+    .iter()
+    .copied()
+    .filter_map(|term| Some((func.layout().parent_bb(term)?, term)))
+    .collect();
+  let mut values = HashMap::new();
+  for &(pred, value) in incoming {
+    if *values.entry(pred).or_insert(value) != value {
+      return Err(mismatch);
+    }
+  }
+  if values.is_empty()
+    || values.len() != terms.len()
+    || terms.iter().any(|(pred, _)| !values.contains_key(pred))
+  {
+    return Err(mismatch);
+  }
+  // check types and the terminators
+  let ty = value_type(func, incoming[0].1);
+  if ty.is_unit() {
+    return Err(type_mismatch(ty));
+  }
+  for &(_, value) in incoming {
+    let found = value_type(func, value);
+    if found != ty {
+      return Err(type_mismatch(found));
+    }
+  }
+  for &(_, term) in &terms {
+    if let ValueKind::Branch(br) = func.dfg().value(term).kind() {
+      if br.true_bb() == br.false_bb() {
+        return Err(Error::SameTargetArgs);
+      }
+    }
+  }
+  // create the parameter, and replace the `undef`s passed by the
+  // predecessors with the incoming values
+  let dfg = func.dfg_mut();
+  let param = dfg.add_bb_param(bb, ty);
+  let mut undef = None;
+  for (pred, term) in terms {
+    let edges: Vec<_> = dfg
+      .value(term)
+      .kind()
+      .edges()
+      .into_iter()
+      .enumerate()
+      .filter(|(_, (target, _))| *target == bb)
+      .map(|(i, (_, args))| (i, args.to_vec()))
+      .collect();
+    for (index, mut args) in edges {
+      undef = args.pop();
+      args.push(values[&pred]);
+      dfg.rewrite_edge(term, index, bb, args);
+    }
+  }
+  if let Some(undef) = undef {
+    if dfg.value(undef).used_by().is_empty() {
+      dfg.remove_value(undef);
+    }
+  }
+  Ok(param)
+}
+
+/// Returns the type of the given local or global value.
+fn value_type(func: &FunctionData, value: Value) -> Type {
+  let dfg = func.dfg();
+  match dfg.values().get(&value) {
+    Some(data) => data.ty().clone(),
+    None => dfg
+      .global_value_data(value)
+      .expect("value does not exist")
+      .ty()
+      .clone(),
+  }
+}
+
+/// Returns a type mismatch error of incoming values.
+fn type_mismatch(found: Type) -> Error {
+  Error::TypeMismatch {
+    operand: "incoming values",
+    expected: "of the same non-unit type",
+    found,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::back::KoopaGenerator;
+  use crate::front::Driver;
+  use crate::ir::builder_traits::*;
+  use crate::ir::Program;
+  use std::str;
+
+  const SRC: &str = r#"
+    fun @f(@c: i32): i32 {
+    %entry:
+      br @c, %then, %else
+
+    %then:
+      br @c, %end(1), %other
+
+    %else:
+      jump %end(2)
+
+    %other:
+      br @c, %loop, %loop
+
+    %loop:
+      jump %end(3)
+
+    %end(%x: i32):
+      ret %x
+    }
+  "#;
+
+  fn bb_named(func: &FunctionData, name: &str) -> BasicBlock {
+    let (bb, _) = func
+      .dfg()
+      .bbs()
+      .iter()
+      .find(|(_, d)| d.name().as_deref() == Some(name))
+      .unwrap();
+    *bb
+  }
+
+  #[test]
+  fn view() {
+    let driver: Driver<_> = SRC.into();
+    let program = driver.generate_program().unwrap();
+    let func = program.func(program.func_layout()[0]);
+    let end = bb_named(func, "%end");
+    let view = to_phi_view(func);
+    let x = func.dfg().bb(end).params()[0];
+    let incoming: Vec<_> = view
+      .incoming(x)
+      .unwrap()
+      .iter()
+      .map(|(bb, v)| {
+        let name = func.dfg().bb(*bb).name().clone().unwrap();
+        (name, func.dfg().value(*v).kind().as_integer().unwrap())
+      })
+      .collect();
+    assert_eq!(
+      incoming,
+      [
+        ("%then".into(), 1),
+        ("%else".into(), 2),
+        ("%loop".into(), 3)
+      ]
+    );
+    assert_eq!(view.incoming(func.params()[0]), None);
+  }
+
+  #[test]
+  fn create_phi() {
+    let driver: Driver<_> = SRC.into();
+    let mut program: Program = driver.generate_program().unwrap();
+    let main = program.func_layout()[0];
+    let func = program.func_mut(main);
+    let (then, else_bb, other, lp) = (
+      bb_named(func, "%then"),
+      bb_named(func, "%else"),
+      bb_named(func, "%other"),
+      bb_named(func, "%loop"),
+    );
+    let c = func.params()[0];
+    let ten = func.dfg_mut().new_value().integer(10);
+    let mut builder = FunctionBuilder::new(func);
+    // `%loop` is targeted by both edges of the branch in `%other`
+    builder.position_at_end(lp);
+    assert_eq!(
+      try_from_phi(&mut builder, &[(other, c), (other, c)]),
+      Err(Error::SameTargetArgs)
+    );
+    // `%else` has only one predecessor
+    builder.position_at_end(else_bb);
+    assert_eq!(
+      try_from_phi(&mut builder, &[(then, c)]),
+      Err(Error::IncomingMismatch { bb: else_bb })
+    );
+    let entry = builder.func().layout().entry_bb().unwrap();
+    assert_eq!(
+      try_from_phi(&mut builder, &[(entry, c), (entry, ten)]),
+      Err(Error::IncomingMismatch { bb: else_bb })
+    );
+    let y = from_phi(&mut builder, &[(entry, ten), (entry, ten)]);
+    let view = to_phi_view(builder.func());
+    assert_eq!(view.incoming(y), Some(&[(entry, ten)][..]));
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    let text = str::from_utf8(&gen.writer()).unwrap().to_string();
+    assert!(text.contains("br @c, %then, %else(10)"));
+  }
+}
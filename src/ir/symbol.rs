@@ -0,0 +1,209 @@
+//! Validation and sanitization of symbol names.
+//!
+//! Symbols are names of functions, basic blocks and values, which
+//! start with `@` (named symbols) or `%` (temporary symbols). The
+//! remaining part of a symbol must be either:
+//!
+//! * A non-empty sequence of letters, digits and underscores, which
+//!   does not start with a digit.
+//! * A number without leading zeros, only for temporary symbols,
+//!   like `%0` and `%42`.
+//!
+//! Letters and digits are Unicode alphanumeric characters, which are
+//! the same as the ones accepted by the lexer of the text form.
+
+use std::fmt::Write;
+
+/// Returns `true` if the given name is a valid symbol name, which can be
+/// generated to the text form Koopa IR and parsed back.
+///
+/// # Examples
+///
+/// ```
+/// use koopa::ir::is_valid_symbol;
+///
+/// assert!(is_valid_symbol("@main"));
+/// assert!(is_valid_symbol("%loop_1"));
+/// assert!(is_valid_symbol("%42"));
+/// assert!(!is_valid_symbol("main"));
+/// assert!(!is_valid_symbol("@1st"));
+/// assert!(!is_valid_symbol("%a.b"));
+/// ```
+pub fn is_valid_symbol(name: &str) -> bool {
+  check_symbol(name).is_ok()
+}
+
+/// Converts the given name to a valid symbol name.
+///
+/// Valid names are returned unchanged. Otherwise, invalid characters
+/// are replaced with `_`, and a hash of the original name is appended,
+/// so different invalid names are unlikely to be converted to the same
+/// symbol. Names without `@` or `%` are treated as named symbols.
+///
+/// # Examples
+///
+/// ```
+/// use koopa::ir::{is_valid_symbol, sanitize_symbol};
+///
+/// assert_eq!(sanitize_symbol("@main"), "@main");
+/// let name = sanitize_symbol("@a.b");
+/// assert!(name.starts_with("@a_b_"));
+/// assert!(is_valid_symbol(&name));
+/// assert_ne!(name, sanitize_symbol("@a-b"));
+/// ```
+pub fn sanitize_symbol(name: &str) -> String {
+  if is_valid_symbol(name) {
+    return name.into();
+  }
+  let (tag, rest) = match name.chars().next() {
+    Some(c @ ('@' | '%')) => (c, &name[1..]),
+    _ => ('@', name),
+  };
+  let mut symbol = String::from(tag);
+  if rest.chars().next().is_none_or(|c| c.is_numeric()) {
+    symbol.push('_');
+  }
+  symbol.extend(rest.chars().map(|c| {
+    if c.is_alphanumeric() || c == '_' {
+      c
+    } else {
+      '_'
+    }
+  }));
+  write!(symbol, "_{:08x}", fnv1a(name)).unwrap();
+  symbol
+}
+
+/// Checks if the given name is a valid symbol name.
+/// Returns the reason if it is invalid.
+pub(in crate::ir) fn check_symbol(name: &str) -> Result<(), String> {
+  let mut chars = name.chars();
+  let tag = chars.next();
+  if !matches!(tag, Some('@' | '%')) {
+    return Err("missing prefix '@' or '%'".into());
+  }
+  let rest = chars.as_str();
+  match rest.chars().next() {
+    None => Err("empty name".into()),
+    Some(c) if c.is_numeric() => {
+      if tag == Some('@') {
+        Err("named symbols can not start with a digit".into())
+      } else if let Some(c) = rest.chars().find(|c| !c.is_numeric()) {
+        Err(format!("invalid character '{c}' in number"))
+      } else if c == '0' && rest.len() > 1 {
+        Err("numbers can not have leading zeros".into())
+      } else {
+        Ok(())
+      }
+    }
+    Some(_) => match rest.chars().find(|c| !c.is_alphanumeric() && *c != '_') {
+      Some(c) => Err(format!("invalid character '{c}'")),
+      None => Ok(()),
+    },
+  }
+}
+
+/// Returns the given symbol name of the given kind (like `function`)
+/// if it is valid.
+///
+/// If feature `sanitize-symbols` is enabled, invalid names will be
+/// sanitized by [`sanitize_symbol`].
+///
+/// # Panics
+///
+/// Panics if the name is invalid and feature `sanitize-symbols` is not
+/// enabled. The panic message contains the reason.
+pub(in crate::ir) fn checked_symbol(name: String, kind: &str) -> String {
+  match check_symbol(&name) {
+    Ok(()) => name,
+    #[cfg(feature = "sanitize-symbols")]
+    Err(_) => {
+      let _ = kind;
+      sanitize_symbol(&name)
+    }
+    #[cfg(not(feature = "sanitize-symbols"))]
+    Err(e) => panic!("invalid {kind} name '{name}': {e}"),
+  }
+}
+
+/// Returns the 32-bit FNV-1a hash of the given string, which is stable
+/// across runs and platforms.
+fn fnv1a(s: &str) -> u32 {
+  s.bytes()
+    .fold(0x811c9dc5, |h, b| (h ^ b as u32).wrapping_mul(0x01000193))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::front::Driver;
+
+  #[test]
+  fn validate() {
+    for name in [
+      "@main",
+      "@_",
+      "%x1",
+      "%_0",
+      "@中文",
+      "%0",
+      "%10",
+      "%2147483648",
+    ] {
+      assert_eq!(check_symbol(name), Ok(()), "{name}");
+    }
+    let invalid = [
+      ("main", "missing prefix '@' or '%'"),
+      ("@", "empty name"),
+      ("@1", "named symbols can not start with a digit"),
+      ("%01", "numbers can not have leading zeros"),
+      ("%1a", "invalid character 'a' in number"),
+      ("%a-b", "invalid character '-'"),
+      ("@a b", "invalid character ' '"),
+      ("@a@", "invalid character '@'"),
+    ];
+    for (name, reason) in invalid {
+      assert_eq!(check_symbol(name), Err(reason.into()), "{name}");
+    }
+  }
+
+  #[test]
+  #[cfg(not(feature = "sanitize-symbols"))]
+  #[should_panic(expected = "invalid function name '@a-b': invalid character '-'")]
+  fn invalid_func_name() {
+    use crate::ir::{FunctionData, Type};
+    FunctionData::new_decl("@a-b".into(), vec![], Type::get_unit());
+  }
+
+  #[test]
+  #[cfg(feature = "sanitize-symbols")]
+  fn sanitize_func_name() {
+    use crate::ir::{FunctionData, Type};
+    let func = FunctionData::new_decl("@a-b".into(), vec![], Type::get_unit());
+    assert_eq!(func.name(), sanitize_symbol("@a-b"));
+  }
+
+  #[test]
+  fn sanitize() {
+    let names = [
+      "main", "@", "@1", "%01", "%1a", "%a-b", "@a b", "@a.b", "@a.b.c", "%",
+    ];
+    let sanitized: Vec<_> = names.iter().map(|n| sanitize_symbol(n)).collect();
+    for (name, s) in names.iter().zip(&sanitized) {
+      assert!(is_valid_symbol(s), "{name} => {s}");
+    }
+    assert!(sanitized[0].starts_with("@main_"));
+    assert!(sanitized[5].starts_with("%a_b_"));
+    // sanitized names are unique
+    let mut unique = sanitized.clone();
+    unique.sort();
+    unique.dedup();
+    assert_eq!(unique.len(), sanitized.len());
+    // sanitized names can be parsed
+    for name in sanitized.iter().filter(|n| n.starts_with('@')) {
+      let driver: Driver<_> = format!("decl {name}()").into();
+      let program = driver.generate_program().unwrap();
+      assert_eq!(program.func(program.func_layout()[0]).name(), name);
+    }
+  }
+}
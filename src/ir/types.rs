@@ -4,9 +4,9 @@
 //! a 32-bit integer type, a unit type, an array type, a pointer type,
 //! or a function type.
 
-use std::cell::{Cell, RefCell};
+use std::cell::Cell;
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::sync::{Arc, LazyLock, Mutex};
 use std::{cmp, fmt, hash, mem};
 
 /// Kind of type.
@@ -26,53 +26,72 @@ pub enum TypeKind {
 
 impl fmt::Display for TypeKind {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    match self {
-      TypeKind::Int32 => write!(f, "i32"),
-      TypeKind::Unit => write!(f, "unit"),
-      TypeKind::Array(t, len) => write!(f, "[{}, {}]", t, len),
-      TypeKind::Pointer(t) => write!(f, "*{}", t),
-      TypeKind::Function(params, ret) => {
-        write!(f, "(")?;
-        let mut first = true;
-        for param in params {
-          if !first {
-            write!(f, ", ")?;
-          }
-          write!(f, "{}", param)?;
-          first = false;
+    enum Item<'a> {
+      Kind(&'a TypeKind),
+      Str(&'static str),
+      Len(usize),
+    }
+    // parts of the type still to be written, the next one on top
+    let mut stack = vec![Item::Kind(self)];
+    while let Some(item) = stack.pop() {
+      match item {
+        Item::Kind(TypeKind::Int32) => write!(f, "i32")?,
+        Item::Kind(TypeKind::Unit) => write!(f, "unit")?,
+        Item::Kind(TypeKind::Array(t, len)) => {
+          write!(f, "[")?;
+          stack.push(Item::Len(*len));
+          stack.push(Item::Kind(t.kind()));
+        }
+        Item::Kind(TypeKind::Pointer(t)) => {
+          write!(f, "*")?;
+          stack.push(Item::Kind(t.kind()));
         }
-        if !ret.is_unit() {
-          write!(f, "): {}", ret)
-        } else {
-          write!(f, ")")
+        Item::Kind(TypeKind::Function(params, ret)) => {
+          write!(f, "(")?;
+          if !ret.is_unit() {
+            stack.push(Item::Kind(ret.kind()));
+            stack.push(Item::Str("): "));
+          } else {
+            stack.push(Item::Str(")"));
+          }
+          for (i, param) in params.iter().enumerate().rev() {
+            stack.push(Item::Kind(param.kind()));
+            if i != 0 {
+              stack.push(Item::Str(", "));
+            }
+          }
         }
+        Item::Str(s) => write!(f, "{}", s)?,
+        Item::Len(len) => write!(f, ", {}]", len)?,
       }
     }
+    Ok(())
   }
 }
 
 /// Types of Koopa IR values.
 #[derive(Clone, Eq)]
-pub struct Type(Rc<TypeKind>);
+pub struct Type(Arc<TypeKind>);
+
+/// Pool of all created types.
+///
+/// The pool is shared by all threads, so types created in different
+/// threads can be compared by pointers.
+static POOL: LazyLock<Mutex<HashMap<TypeKind, Type>>> = LazyLock::new(Default::default);
 
 impl Type {
   thread_local! {
-    /// Pool of all created types.
-    static POOL: RefCell<HashMap<TypeKind, Type>> = RefCell::new(HashMap::new());
-
     /// Size of pointers.
-    static PTR_SIZE: Cell<usize> = Cell::new(mem::size_of::<*const ()>());
+    static PTR_SIZE: Cell<usize> = const { Cell::new(mem::size_of::<*const ()>()) };
   }
 
   /// Returns a type by the given [`TypeKind`].
   pub fn get(type_data: TypeKind) -> Type {
-    Self::POOL.with(|pool| {
-      let mut pool = pool.borrow_mut();
-      pool.get(&type_data).cloned().unwrap_or_else(|| {
-        let v = Self(Rc::new(type_data.clone()));
-        pool.insert(type_data, v.clone());
-        v
-      })
+    let mut pool = POOL.lock().unwrap();
+    pool.get(&type_data).cloned().unwrap_or_else(|| {
+      let v = Self(Arc::new(type_data.clone()));
+      pool.insert(type_data, v.clone());
+      v
     })
   }
 
@@ -102,11 +121,16 @@ impl Type {
     Type::get(TypeKind::Function(params, ret))
   }
 
-  /// Sets the size of pointers.
+  /// Sets the size of pointers in the current thread.
   pub fn set_ptr_size(size: usize) {
     Self::PTR_SIZE.with(|ptr_size| ptr_size.set(size));
   }
 
+  /// Returns the size of pointers in the current thread.
+  pub fn ptr_size() -> usize {
+    Self::PTR_SIZE.with(|s| s.get())
+  }
+
   /// Returns a reference to the kind of the current type.
   pub fn kind(&self) -> &TypeKind {
     &self.0
@@ -128,14 +152,14 @@ impl Type {
       TypeKind::Int32 => 4,
       TypeKind::Unit => 0,
       TypeKind::Array(ty, len) => ty.size() * len,
-      TypeKind::Pointer(..) | TypeKind::Function(..) => Self::PTR_SIZE.with(|s| s.get()),
+      TypeKind::Pointer(..) | TypeKind::Function(..) => Self::ptr_size(),
     }
   }
 }
 
 impl cmp::PartialEq for Type {
   fn eq(&self, other: &Self) -> bool {
-    Rc::ptr_eq(&self.0, &other.0)
+    Arc::ptr_eq(&self.0, &other.0)
   }
 }
 
@@ -153,7 +177,9 @@ impl fmt::Debug for Type {
 
 impl hash::Hash for Type {
   fn hash<H: hash::Hasher>(&self, state: &mut H) {
-    self.0.hash(state);
+    // types are interned, so hashing the pointer is consistent with
+    // `PartialEq`, and will not recurse into the nested types
+    Arc::as_ptr(&self.0).hash(state);
   }
 }
 
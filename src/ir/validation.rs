@@ -0,0 +1,510 @@
+//! Validation levels ([`Validation`]) of the inline checks performed
+//! when mutating data flow graphs.
+
+use crate::ir::builder::{check_bb_arg_types, check_call_args};
+use crate::ir::builder::{DfgBasedInfoQuerier, EntityInfoQuerier};
+use crate::ir::dfg::DataFlowGraph;
+use crate::ir::entities::{Value, ValueData, ValueKind};
+use crate::ir::error::Error;
+use crate::ir::types::{Type, TypeKind};
+use crate::ir::values::{gep_result_type, get_ptr_result_type};
+
+/// Levels of the checks performed by data flow graphs when values are
+/// created, replaced and modified.
+///
+/// Unlike the [verifier](crate::analysis::verifier), which checks a
+/// whole program in a batch, these checks are performed at the time of
+/// every mutation, so the code that breaks the IR panics with the reason
+/// before the data flow graph is changed. The checks do not depend on the
+/// compile profile: they are performed in release builds if enabled, and
+/// are never performed otherwise.
+///
+/// The typed builders (like [`LocalInstBuilder`](crate::ir::builder::LocalInstBuilder))
+/// and [`FunctionBuilder`](crate::ir::builder::FunctionBuilder) always check the types of operands and the
+/// placement of terminators. The levels control the checks of the
+/// following methods, which trust their callers by default:
+///
+/// * Creating values from value data by
+///   [`ValueBuilder::raw`](crate::ir::builder::ValueBuilder::raw), and
+///   replacing values by
+///   [`DataFlowGraph::replace_value_with`].
+/// * [`DataFlowGraph::set_value_operand`] and
+///   [`DataFlowGraph::replace_all_uses_with`].
+/// * [`DataFlowGraph::set_value_name`].
+///
+/// Values created by the typed builders are also checked, which is
+/// redundant but cheap at [`Validation::Fast`].
+///
+/// The level can be set by [`DataFlowGraph::set_validation`] for a
+/// function, by [`Program::set_validation`](crate::ir::Program::set_validation)
+/// for all functions of a program, or by
+/// [`FunctionBuilder::with_validation`](crate::ir::builder::FunctionBuilder::with_validation)
+/// during building.
+///
+/// # Example
+///
+/// ```
+/// use koopa::ir::builder_traits::*;
+/// use koopa::ir::{BinaryOp, FunctionData, Type, Validation};
+/// use std::panic::{self, AssertUnwindSafe};
+///
+/// let mut func = FunctionData::new("@f".into(), Vec::new(), Type::get_unit());
+/// let dfg = func.dfg_mut();
+/// let one = dfg.new_value().integer(1);
+/// let add = dfg.new_value().binary(BinaryOp::Add, one, one);
+/// let ptr = dfg.new_value().alloc(Type::get_i32());
+///
+/// // a binary operation on a pointer
+/// let mut data = dfg.value(add).clone();
+/// data.kind_mut().set_operand(1, ptr);
+/// assert!(dfg.validate_value_data(&data, Validation::Fast).is_ok());
+/// assert!(dfg.validate_value_data(&data, Validation::Full).is_err());
+///
+/// // creating the value panics at `Full`
+/// dfg.set_validation(Validation::Full);
+/// let result = panic::catch_unwind(AssertUnwindSafe(|| dfg.new_value().raw(data)));
+/// assert!(result.is_err());
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Validation {
+  /// No checks, the default.
+  #[default]
+  Off,
+  /// Cheap checks, which take constant time for every operand:
+  ///
+  /// * Operands, target basic blocks and callees exist.
+  /// * Global allocations are not created in functions.
+  /// * Aggregates have as many elements as their array types, calls
+  ///   have as many arguments as the parameters of their callees, and
+  ///   basic block targets have as many arguments as the parameters of
+  ///   the basic blocks.
+  /// * New operands set by [`DataFlowGraph::set_value_operand`] and
+  ///   [`DataFlowGraph::replace_all_uses_with`] exist.
+  /// * Removed values are only used by each other when collecting
+  ///   garbage, and no values are lost when compacting functions.
+  Fast,
+  /// All checks of [`Validation::Fast`], and:
+  ///
+  /// * The types of operands and results match, as the typed builders
+  ///   check.
+  /// * New operands have the same types as the old operands.
+  /// * Names set by [`DataFlowGraph::set_value_name`] are not used by
+  ///   other local values of the function. Names of copied value data
+  ///   are not checked, since passes like
+  ///   [`LoopUnroll`](crate::opt::LoopUnroll) keep the names of the
+  ///   copied values, and the generators make them unique.
+  ///
+  /// Checking names takes time linear in the number of values, so this
+  /// level is intended for tests.
+  Full,
+}
+
+/// An entity information querier of the given data flow graph.
+struct Querier<'a>(&'a DataFlowGraph);
+
+impl DfgBasedInfoQuerier for Querier<'_> {
+  fn dfg(&self) -> &DataFlowGraph {
+    self.0
+  }
+}
+
+/// Checks the given value data to be created or replaced in the given
+/// data flow graph at the given level.
+pub(in crate::ir) fn check_value_data(
+  dfg: &DataFlowGraph,
+  data: &ValueData,
+  level: Validation,
+) -> Result<(), Error> {
+  if level == Validation::Off {
+    return Ok(());
+  }
+  let querier = Querier(dfg);
+  for v in data.kind().value_uses() {
+    querier.try_value_type(v)?;
+  }
+  for (i, (bb, args)) in data.kind().edges().into_iter().enumerate() {
+    if querier.try_bb_params(bb)?.len() != args.len() {
+      return Err(Error::ArgMismatch {
+        target: target_name(data.kind(), i),
+      });
+    }
+  }
+  match data.kind() {
+    ValueKind::GlobalAlloc(_) => return Err(Error::GlobalAllocInFunction),
+    ValueKind::Aggregate(agg) => match data.ty().kind() {
+      TypeKind::Array(_, len) if *len == agg.elems().len() => {}
+      _ => {
+        return Err(mismatch(
+          "`elems`",
+          "an array of the same length",
+          data.ty(),
+        ))
+      }
+    },
+    ValueKind::Call(call) => {
+      let ty = querier.try_func_type(call.callee())?;
+      if let TypeKind::Function(params, _) = ty.kind() {
+        if params.len() != call.args().len() {
+          return Err(Error::CallMismatch {
+            callee: querier
+              .try_func_name(call.callee())
+              .unwrap_or_else(|_| format!("{:?}", call.callee())),
+            expected: params.len(),
+            found: call.args().len(),
+            arg: None,
+          });
+        }
+      }
+    }
+    _ => {}
+  }
+  if level == Validation::Full {
+    check_types(&querier, data)?;
+  }
+  Ok(())
+}
+
+/// Checks that the given new operand exists, and has the same type as
+/// the old operand at [`Validation::Full`].
+pub(in crate::ir) fn check_operand(
+  dfg: &DataFlowGraph,
+  old: Value,
+  new: Value,
+  level: Validation,
+) -> Result<(), Error> {
+  if level == Validation::Off {
+    return Ok(());
+  }
+  let querier = Querier(dfg);
+  let ty = querier.try_value_type(new)?;
+  if level == Validation::Full && querier.try_value_type(old)? != ty {
+    return Err(mismatch(
+      "the new operand",
+      "the type of the old operand",
+      &ty,
+    ));
+  }
+  Ok(())
+}
+
+/// Checks that the given name is not used by other local values than
+/// the given value.
+pub(in crate::ir) fn check_name(
+  dfg: &DataFlowGraph,
+  name: &Option<String>,
+  value: Value,
+) -> Result<(), Error> {
+  let Some(name) = name else {
+    return Ok(());
+  };
+  let conflict = dfg
+    .values()
+    .iter()
+    .any(|(v, data)| *v != value && data.name().as_ref() == Some(name));
+  if conflict {
+    Err(Error::NameConflict { name: name.clone() })
+  } else {
+    Ok(())
+  }
+}
+
+/// Checks the types of the operands and the result of the given value
+/// data, like the typed builders.
+fn check_types(querier: &Querier, data: &ValueData) -> Result<(), Error> {
+  let ty = data.ty();
+  let result = |expected: Type, desc| {
+    if *ty == expected {
+      Ok(())
+    } else {
+      Err(mismatch("the result", desc, ty))
+    }
+  };
+  let check_i32 = |operand, value| {
+    let ty = querier.try_value_type(value)?;
+    if ty.is_i32() {
+      Ok(())
+    } else {
+      Err(mismatch(operand, "an integer", &ty))
+    }
+  };
+  match data.kind() {
+    ValueKind::Integer(_) => result(Type::get_i32(), "an integer"),
+    ValueKind::ZeroInit(_) | ValueKind::Undef(_) if ty.is_unit() => {
+      Err(mismatch("`ty`", "a non-unit type", ty))
+    }
+    ValueKind::Aggregate(agg) => {
+      let TypeKind::Array(base, _) = ty.kind() else {
+        unreachable!()
+      };
+      for elem in agg.elems() {
+        if !querier.is_const(*elem) {
+          return Err(Error::NotConstant);
+        }
+        let elem_ty = querier.try_value_type(*elem)?;
+        if elem_ty != *base {
+          return Err(mismatch(
+            "elements of `elems`",
+            "the element type",
+            &elem_ty,
+          ));
+        }
+      }
+      Ok(())
+    }
+    ValueKind::Alloc(_) => match ty.kind() {
+      TypeKind::Pointer(base) if !base.is_unit() => Ok(()),
+      _ => Err(mismatch("the result", "a pointer of a non-unit type", ty)),
+    },
+    ValueKind::Load(load) => {
+      let src_ty = querier.try_value_type(load.src())?;
+      match src_ty.kind() {
+        TypeKind::Pointer(base) => result(base.clone(), "the type pointed to by `src`"),
+        _ => Err(mismatch("`src`", "a pointer", &src_ty)),
+      }
+    }
+    ValueKind::Store(store) => {
+      let value = querier.try_value_type(store.value())?;
+      let dest = querier.try_value_type(store.dest())?;
+      if Type::get_pointer(value.clone()) != dest {
+        return Err(Error::StoreMismatch { value, dest });
+      }
+      result(Type::get_unit(), "unit")
+    }
+    ValueKind::GetPtr(gp) => {
+      check_i32("`index`", gp.index())?;
+      let src_ty = querier.try_value_type(gp.src())?;
+      result(get_ptr_result_type(&src_ty)?, "the type of `src`")
+    }
+    ValueKind::GetElemPtr(gep) => {
+      check_i32("`index`", gep.index())?;
+      let src_ty = querier.try_value_type(gep.src())?;
+      result(gep_result_type(&src_ty)?, "a pointer of the element type")
+    }
+    ValueKind::Binary(bin) => {
+      check_i32("`lhs`", bin.lhs())?;
+      check_i32("`rhs`", bin.rhs())?;
+      result(Type::get_i32(), "an integer")
+    }
+    ValueKind::Branch(br) => {
+      check_i32("`cond`", br.cond())?;
+      check_bb_arg_types(querier, "`true_bb`", br.true_bb(), br.true_args())?;
+      check_bb_arg_types(querier, "`false_bb`", br.false_bb(), br.false_args())?;
+      result(Type::get_unit(), "unit")
+    }
+    ValueKind::Jump(jump) => {
+      check_bb_arg_types(querier, "`target`", jump.target(), jump.args())?;
+      result(Type::get_unit(), "unit")
+    }
+    ValueKind::Call(call) => {
+      let ret = check_call_args(querier, call.callee(), call.args())?;
+      result(ret, "the return type of the callee")
+    }
+    ValueKind::Return(ret) => {
+      let found = match ret.value() {
+        Some(value) => querier.try_value_type(value)?,
+        None => Type::get_unit(),
+      };
+      match querier.ret_type() {
+        Some(expected) if expected != found => Err(Error::RetMismatch { expected, found }),
+        _ => result(Type::get_unit(), "unit"),
+      }
+    }
+    ValueKind::Unreachable(_) => result(Type::get_unit(), "unit"),
+    _ => Ok(()),
+  }
+}
+
+/// Returns the description of the basic block target at the given index
+/// of the edges of the given value kind.
+fn target_name(kind: &ValueKind, index: usize) -> &'static str {
+  match (kind, index) {
+    (ValueKind::Branch(_), 0) => "`true_bb`",
+    (ValueKind::Branch(_), _) => "`false_bb`",
+    _ => "`target`",
+  }
+}
+
+/// Returns a type mismatch error of the given operand.
+fn mismatch(operand: &'static str, expected: &'static str, found: &Type) -> Error {
+  Error::TypeMismatch {
+    operand,
+    expected,
+    found: found.clone(),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::front::Driver;
+  use crate::ir::builder::FunctionBuilder;
+  use crate::ir::builder_traits::*;
+  use crate::ir::{BinaryOp, Function, FunctionData, Program};
+
+  /// Returns the program for the tests, and `@f` in it.
+  fn program() -> (Program, Function) {
+    let driver: Driver<_> = r#"
+      decl @g(i32): i32
+
+      fun @f(@p: *i32, @q: **i32): i32 {
+      %entry:
+        %x = load @p
+        %y = add %x, 1
+        store %y, @p
+        %a = alloc [i32, 2]
+        store {1, 2}, %a
+        %z = call @g(%y)
+        jump %end(%z)
+
+      %end(%r: i32):
+        ret %r
+      }
+
+      fun @h(): i32 {
+      %entry:
+        %w = add 1, 2
+        ret %w
+      }
+    "#
+    .into();
+    let program = driver.generate_program().unwrap();
+    let f = program.func_layout()[1];
+    (program, f)
+  }
+
+  /// Returns the value with the given name, or the instruction of the
+  /// given kind in the given function.
+  fn find(dfg: &DataFlowGraph, f: impl Fn(&ValueData) -> bool) -> Value {
+    *dfg.values().iter().find(|(_, v)| f(v)).unwrap().0
+  }
+
+  fn named(dfg: &DataFlowGraph, name: &str) -> Value {
+    find(dfg, |v| v.name().as_deref() == Some(name))
+  }
+
+  #[test]
+  fn construction_bugs() {
+    let (program, f) = program();
+    let dfg = program.func(f).dfg();
+    let h = program.func(program.func_layout()[2]).dfg();
+    let data = |value: Value| dfg.value(value).clone();
+    let with_operand = |value: Value, index, operand| {
+      let mut data = data(value);
+      data.kind_mut().set_operand(index, operand);
+      data
+    };
+    let (p, q) = (named(dfg, "@p"), named(dfg, "@q"));
+    let (y, z) = (named(dfg, "%y"), named(dfg, "%z"));
+    let store = find(dfg, |v| matches!(v.kind(), ValueKind::Store(_)));
+    let jump = find(dfg, |v| matches!(v.kind(), ValueKind::Jump(_)));
+    let ret = find(dfg, |v| matches!(v.kind(), ValueKind::Return(_)));
+    let agg = find(dfg, |v| matches!(v.kind(), ValueKind::Aggregate(_)));
+    let mut call = data(z);
+    let mut jump_data = data(jump);
+    let mut agg_data = data(agg);
+    if let ValueKind::Call(call) = call.kind_mut() {
+      call.args_mut().push(y);
+    }
+    if let ValueKind::Jump(jump) = jump_data.kind_mut() {
+      jump.args_mut().clear();
+    }
+    if let ValueKind::Aggregate(agg) = agg_data.kind_mut() {
+      let elem = agg.elems()[0];
+      agg.elems_mut().push(elem);
+    }
+    // bugs, and whether they are caught at `Fast`
+    let bugs = [
+      // values copied from other functions
+      (h.value(named(h, "%w")).clone(), true),
+      (with_operand(y, 0, named(h, "%w")), true),
+      // wrong numbers of arguments or elements
+      (call, true),
+      (jump_data, true),
+      (agg_data, true),
+      // wrong types
+      (with_operand(y, 0, p), false),
+      (with_operand(named(dfg, "%x"), 0, q), false),
+      (with_operand(store, 1, q), false),
+      (with_operand(z, 0, p), false),
+      (with_operand(ret, 0, p), false),
+    ];
+    for (i, (data, fast)) in bugs.iter().enumerate() {
+      assert!(
+        dfg.validate_value_data(data, Validation::Off).is_ok(),
+        "{i}"
+      );
+      let result = dfg.validate_value_data(data, Validation::Fast);
+      assert_eq!(result.is_err(), *fast, "{i}");
+      assert!(
+        dfg.validate_value_data(data, Validation::Full).is_err(),
+        "{i}"
+      );
+    }
+    assert_eq!(
+      dfg.validate_value_data(&with_operand(store, 1, q), Validation::Full),
+      Err(Error::StoreMismatch {
+        value: Type::get_i32(),
+        dest: Type::get_pointer(Type::get_pointer(Type::get_i32())),
+      })
+    );
+    // all values in the program are valid
+    for (_, data) in dfg.values() {
+      assert!(dfg.validate_value_data(data, Validation::Full).is_ok());
+    }
+  }
+
+  #[test]
+  #[should_panic(expected = "validation failed: value")]
+  fn raw_at_fast() {
+    let (mut program, f) = program();
+    program.set_validation(Validation::Fast);
+    let h = program.func(program.func_layout()[2]).dfg();
+    let data = h.value(named(h, "%w")).clone();
+    program.func_mut(f).dfg_mut().new_value().raw(data);
+  }
+
+  #[test]
+  #[should_panic(expected = "validation failed: symbol '%x' has already been defined")]
+  fn rename_at_full() {
+    let (mut program, f) = program();
+    let dfg = program.func_mut(f).dfg_mut();
+    let y = named(dfg, "%y");
+    // duplicate names are allowed by default
+    dfg.set_value_name(y, Some("%x".into()));
+    dfg.set_value_name(y, Some("%y".into()));
+    dfg.set_validation(Validation::Full);
+    dfg.set_value_name(y, Some("%y".into()));
+    dfg.set_value_name(y, Some("%x".into()));
+  }
+
+  #[test]
+  #[should_panic(
+    expected = "validation failed: the new operand must be the type of the old operand"
+  )]
+  fn replace_uses_at_full() {
+    let (mut program, f) = program();
+    program.set_validation(Validation::Full);
+    let dfg = program.func_mut(f).dfg_mut();
+    let (x, p) = (named(dfg, "%x"), named(dfg, "@p"));
+    dfg.replace_all_uses_with(x, p);
+  }
+
+  #[test]
+  fn builder_level() {
+    let (mut program, f) = program();
+    program.set_validation(Validation::Fast);
+    let g = program.new_func(FunctionData::new("@k".into(), Vec::new(), Type::get_unit()));
+    assert_eq!(program.func(g).dfg().validation(), Validation::Fast);
+    let data = program.func_mut(f);
+    let entry = data.layout().entry_bb().unwrap();
+    let x = named(data.dfg(), "%x");
+    let mut builder = FunctionBuilder::new(data).with_validation(Validation::Full);
+    builder.position_before(x);
+    let one = builder.integer(1);
+    builder.binary(BinaryOp::Add, one, one);
+    // the level of the function is not changed
+    assert_eq!(builder.func().dfg().validation(), Validation::Fast);
+    let data = program.func(f);
+    assert_eq!(data.layout().bbs().node(&entry).unwrap().insts().len(), 8);
+  }
+}
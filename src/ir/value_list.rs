@@ -0,0 +1,368 @@
+//! Operand lists ([`ValueList`]) of instructions and constants.
+
+use crate::ir::entities::Value;
+use std::num::NonZeroU64;
+use std::ops::{Deref, DerefMut};
+use std::{fmt, iter, slice, vec};
+
+/// Number of values that can be stored in a [`ValueList`] without
+/// allocating on the heap.
+const INLINE_CAP: usize = 4;
+
+/// A list of operand values, used by instructions with a variable number
+/// of operands, like argument lists of calls, branches and jumps, and
+/// element lists of aggregates.
+///
+/// Most of these lists are short, so up to 4 values are stored inline,
+/// and only longer lists are allocated on the heap. `ValueList`
+/// dereferences to `[Value]`, and supports the common mutating methods
+/// of `Vec`.
+#[derive(Clone)]
+pub struct ValueList {
+  repr: Repr,
+}
+
+#[derive(Clone)]
+enum Repr {
+  /// Values stored inline, slots after `len` are unused.
+  Inline { len: u8, buf: [Value; INLINE_CAP] },
+  /// Values stored on the heap.
+  Heap(Vec<Value>),
+}
+
+/// Placeholder of unused inline slots, which is never exposed.
+const UNUSED: Value = Value(NonZeroU64::MAX);
+
+impl ValueList {
+  /// Creates a new empty list.
+  pub fn new() -> Self {
+    Self {
+      repr: Repr::Inline {
+        len: 0,
+        buf: [UNUSED; INLINE_CAP],
+      },
+    }
+  }
+
+  /// Returns `true` if the values are stored on the heap.
+  pub fn spilled(&self) -> bool {
+    matches!(self.repr, Repr::Heap(_))
+  }
+
+  /// Returns the values as a slice.
+  pub fn as_slice(&self) -> &[Value] {
+    match &self.repr {
+      Repr::Inline { len, buf } => &buf[..*len as usize],
+      Repr::Heap(vec) => vec,
+    }
+  }
+
+  /// Returns the values as a mutable slice.
+  pub fn as_mut_slice(&mut self) -> &mut [Value] {
+    match &mut self.repr {
+      Repr::Inline { len, buf } => &mut buf[..*len as usize],
+      Repr::Heap(vec) => vec,
+    }
+  }
+
+  /// Returns the values as a `Vec`, moving inline values to the heap.
+  fn as_vec_mut(&mut self) -> &mut Vec<Value> {
+    if let Repr::Inline { len, buf } = &self.repr {
+      self.repr = Repr::Heap(buf[..*len as usize].to_vec());
+    }
+    match &mut self.repr {
+      Repr::Heap(vec) => vec,
+      Repr::Inline { .. } => unreachable!(),
+    }
+  }
+
+  /// Appends a value to the back of the list.
+  pub fn push(&mut self, value: Value) {
+    match &mut self.repr {
+      Repr::Inline { len, buf } if (*len as usize) < INLINE_CAP => {
+        buf[*len as usize] = value;
+        *len += 1;
+      }
+      _ => self.as_vec_mut().push(value),
+    }
+  }
+
+  /// Removes the last value and returns it, or `None` if the list is
+  /// empty.
+  pub fn pop(&mut self) -> Option<Value> {
+    match &mut self.repr {
+      Repr::Inline { len: 0, .. } => None,
+      Repr::Inline { len, buf } => {
+        *len -= 1;
+        Some(buf[*len as usize])
+      }
+      Repr::Heap(vec) => vec.pop(),
+    }
+  }
+
+  /// Inserts a value at the given index, shifting all values after it
+  /// to the right.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `index > len`.
+  pub fn insert(&mut self, index: usize, value: Value) {
+    let len = self.len();
+    assert!(
+      index <= len,
+      "insertion index {index} out of range ({len} values)"
+    );
+    self.push(value);
+    self.as_mut_slice()[index..].rotate_right(1);
+  }
+
+  /// Removes and returns the value at the given index, shifting all
+  /// values after it to the left.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given index is out of range.
+  pub fn remove(&mut self, index: usize) -> Value {
+    let len = self.len();
+    assert!(
+      index < len,
+      "removal index {index} out of range ({len} values)"
+    );
+    self.as_mut_slice()[index..].rotate_left(1);
+    self.pop().unwrap()
+  }
+
+  /// Retains only the values specified by the predicate, in order.
+  pub fn retain<F>(&mut self, mut f: F)
+  where
+    F: FnMut(&Value) -> bool,
+  {
+    let mut kept = 0;
+    for i in 0..self.len() {
+      let value = self[i];
+      if f(&value) {
+        self[kept] = value;
+        kept += 1;
+      }
+    }
+    self.truncate(kept);
+  }
+
+  /// Shortens the list to the given length. Does nothing if the list
+  /// is not longer than that.
+  pub fn truncate(&mut self, new_len: usize) {
+    match &mut self.repr {
+      Repr::Inline { len, .. } => *len = (*len).min(new_len as u8),
+      Repr::Heap(vec) => vec.truncate(new_len),
+    }
+  }
+
+  /// Removes all values.
+  pub fn clear(&mut self) {
+    self.truncate(0);
+  }
+}
+
+impl Default for ValueList {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Deref for ValueList {
+  type Target = [Value];
+
+  fn deref(&self) -> &[Value] {
+    self.as_slice()
+  }
+}
+
+impl DerefMut for ValueList {
+  fn deref_mut(&mut self) -> &mut [Value] {
+    self.as_mut_slice()
+  }
+}
+
+impl fmt::Debug for ValueList {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_list().entries(self.iter()).finish()
+  }
+}
+
+impl PartialEq for ValueList {
+  fn eq(&self, other: &Self) -> bool {
+    self.as_slice() == other.as_slice()
+  }
+}
+
+impl Eq for ValueList {}
+
+impl PartialEq<[Value]> for ValueList {
+  fn eq(&self, other: &[Value]) -> bool {
+    self.as_slice() == other
+  }
+}
+
+impl PartialEq<Vec<Value>> for ValueList {
+  fn eq(&self, other: &Vec<Value>) -> bool {
+    self.as_slice() == other.as_slice()
+  }
+}
+
+impl From<Vec<Value>> for ValueList {
+  /// Moves the values inline if they fit, otherwise reuses the `Vec`.
+  fn from(vec: Vec<Value>) -> Self {
+    if vec.len() <= INLINE_CAP {
+      vec.into_iter().collect()
+    } else {
+      Self {
+        repr: Repr::Heap(vec),
+      }
+    }
+  }
+}
+
+impl From<&[Value]> for ValueList {
+  fn from(values: &[Value]) -> Self {
+    values.iter().copied().collect()
+  }
+}
+
+impl<const N: usize> From<[Value; N]> for ValueList {
+  fn from(values: [Value; N]) -> Self {
+    values.into_iter().collect()
+  }
+}
+
+impl From<ValueList> for Vec<Value> {
+  fn from(list: ValueList) -> Self {
+    match list.repr {
+      Repr::Inline { len, buf } => buf[..len as usize].to_vec(),
+      Repr::Heap(vec) => vec,
+    }
+  }
+}
+
+impl FromIterator<Value> for ValueList {
+  fn from_iter<T: IntoIterator<Item = Value>>(iter: T) -> Self {
+    let mut list = Self::new();
+    list.extend(iter);
+    list
+  }
+}
+
+impl Extend<Value> for ValueList {
+  fn extend<T: IntoIterator<Item = Value>>(&mut self, iter: T) {
+    let iter = iter.into_iter();
+    if self.len() + iter.size_hint().0 > INLINE_CAP {
+      let vec = self.as_vec_mut();
+      vec.extend(iter);
+    } else {
+      for value in iter {
+        self.push(value);
+      }
+    }
+  }
+}
+
+impl<'a> IntoIterator for &'a ValueList {
+  type Item = &'a Value;
+  type IntoIter = slice::Iter<'a, Value>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.iter()
+  }
+}
+
+impl<'a> IntoIterator for &'a mut ValueList {
+  type Item = &'a mut Value;
+  type IntoIter = slice::IterMut<'a, Value>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.iter_mut()
+  }
+}
+
+impl IntoIterator for ValueList {
+  type Item = Value;
+  type IntoIter = IntoIter;
+
+  fn into_iter(self) -> Self::IntoIter {
+    match self.repr {
+      Repr::Inline { len, buf } => IntoIter::Inline(buf.into_iter().take(len as usize)),
+      Repr::Heap(vec) => IntoIter::Heap(vec.into_iter()),
+    }
+  }
+}
+
+/// An owning iterator of values in a [`ValueList`].
+pub enum IntoIter {
+  #[doc(hidden)]
+  Inline(iter::Take<std::array::IntoIter<Value, INLINE_CAP>>),
+  #[doc(hidden)]
+  Heap(vec::IntoIter<Value>),
+}
+
+impl Iterator for IntoIter {
+  type Item = Value;
+
+  fn next(&mut self) -> Option<Value> {
+    match self {
+      IntoIter::Inline(iter) => iter.next(),
+      IntoIter::Heap(iter) => iter.next(),
+    }
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    match self {
+      IntoIter::Inline(iter) => iter.size_hint(),
+      IntoIter::Heap(iter) => iter.size_hint(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn values(n: u64) -> Vec<Value> {
+    (1..=n)
+      .map(|i| Value(NonZeroU64::new(i).unwrap()))
+      .collect()
+  }
+
+  #[test]
+  fn inline_and_spill() {
+    let vs = values(6);
+    let mut list = ValueList::from(vs[..3].to_vec());
+    assert!(!list.spilled());
+    list.push(vs[3]);
+    assert!(!list.spilled());
+    assert_eq!(list, vs[..4]);
+    list.insert(0, vs[4]);
+    assert!(list.spilled());
+    assert_eq!(list, vec![vs[4], vs[0], vs[1], vs[2], vs[3]]);
+    assert_eq!(list.remove(1), vs[0]);
+    list.retain(|v| *v != vs[2]);
+    assert_eq!(list, vec![vs[4], vs[1], vs[3]]);
+    assert!(ValueList::from(vs.clone()).spilled());
+    assert_eq!(Vec::from(ValueList::from(vs.clone())), vs);
+  }
+
+  #[test]
+  fn inline_ops() {
+    let vs = values(4);
+    let mut list: ValueList = vs.iter().copied().collect();
+    list.insert(2, list[0]);
+    assert!(list.spilled());
+    let mut list: ValueList = vs[..3].iter().copied().collect();
+    list.insert(1, vs[3]);
+    assert_eq!(list, vec![vs[0], vs[3], vs[1], vs[2]]);
+    assert_eq!(list.remove(3), vs[2]);
+    assert_eq!(list.pop(), Some(vs[1]));
+    list.retain(|v| *v == vs[3]);
+    assert_eq!(list.into_iter().collect::<Vec<_>>(), [vs[3]]);
+    assert!(!ValueList::new().spilled());
+    assert_eq!(ValueList::new().pop(), None);
+  }
+}
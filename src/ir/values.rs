@@ -1,7 +1,9 @@
 //! Definitions of Koopa IR values, including constants and instructions.
 
 use crate::ir::entities::{BasicBlock, Function, Value, ValueData, ValueKind};
-use crate::ir::types::Type;
+use crate::ir::error::Error;
+use crate::ir::types::{Type, TypeKind};
+use crate::ir::value_list::ValueList;
 use std::fmt;
 
 /// Integer constant.
@@ -49,11 +51,11 @@ impl Undef {
 /// Aggregate constant.
 #[derive(Clone, Debug)]
 pub struct Aggregate {
-  elems: Vec<Value>,
+  elems: ValueList,
 }
 
 impl Aggregate {
-  pub(in crate::ir) fn new_data(elems: Vec<Value>, ty: Type) -> ValueData {
+  pub(in crate::ir) fn new_data(elems: ValueList, ty: Type) -> ValueData {
     ValueData::new(ty, ValueKind::Aggregate(Self { elems }))
   }
 
@@ -63,11 +65,67 @@ impl Aggregate {
   }
 
   /// Returns a mutable reference to the aggregate elements.
-  pub fn elems_mut(&mut self) -> &mut Vec<Value> {
+  pub fn elems_mut(&mut self) -> &mut ValueList {
     &mut self.elems
   }
 }
 
+/// Raw byte data constant, e.g. a string.
+///
+/// Byte data has type `[i32, n]`, where `n` is the number of bytes,
+/// and is only valid as (part of) the initializer of global allocations.
+#[derive(Clone, Debug)]
+pub struct Bytes {
+  bytes: Vec<u8>,
+}
+
+impl Bytes {
+  pub(in crate::ir) fn new_data(bytes: Vec<u8>) -> ValueData {
+    let ty = Type::get_array(Type::get_i32(), bytes.len());
+    ValueData::new(ty, ValueKind::Bytes(Self { bytes }))
+  }
+
+  /// Returns a reference to the bytes.
+  pub fn bytes(&self) -> &[u8] {
+    &self.bytes
+  }
+
+  /// Returns a mutable reference to the bytes.
+  ///
+  /// The length of the bytes must match the length of the array type.
+  pub fn bytes_mut(&mut self) -> &mut Vec<u8> {
+    &mut self.bytes
+  }
+
+  /// Returns an iterator over the bytes as integer elements of the array,
+  /// for backends that treat byte data as ordinary aggregates.
+  pub fn elems(&self) -> impl Iterator<Item = i32> + '_ {
+    self.bytes.iter().map(|b| *b as i32)
+  }
+}
+
+/// Formats the bytes as a string literal of Koopa IR, like `"hi\n\00"`.
+///
+/// Printable ASCII characters are written as is, other bytes are written
+/// as escape sequences.
+impl fmt::Display for Bytes {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("\"")?;
+    for b in &self.bytes {
+      match b {
+        b'"' => f.write_str("\\\"")?,
+        b'\\' => f.write_str("\\\\")?,
+        b'\n' => f.write_str("\\n")?,
+        b'\r' => f.write_str("\\r")?,
+        b'\t' => f.write_str("\\t")?,
+        b' '..=b'~' => write!(f, "{}", *b as char)?,
+        _ => write!(f, "\\{:02X}", b)?,
+      }
+    }
+    f.write_str("\"")
+  }
+}
+
 /// Function argument reference.
 #[derive(Clone, Debug)]
 pub struct FuncArgRef {
@@ -266,6 +324,64 @@ impl GetElemPtr {
   }
 }
 
+/// Returns the result type of `getptr` on a source pointer of the given
+/// type, which is the same type as the source pointer.
+///
+/// Returns an error if the given type is not a pointer type.
+///
+/// # Examples
+///
+/// ```
+/// use koopa::ir::values::get_ptr_result_type;
+/// use koopa::ir::Type;
+///
+/// // a decayed `int a[][3]` parameter
+/// let ty = Type::get_pointer(Type::get_array(Type::get_i32(), 3));
+/// assert_eq!(get_ptr_result_type(&ty), Ok(ty));
+/// assert!(get_ptr_result_type(&Type::get_i32()).is_err());
+/// ```
+pub fn get_ptr_result_type(src_ty: &Type) -> Result<Type, Error> {
+  match src_ty.kind() {
+    TypeKind::Pointer(_) => Ok(src_ty.clone()),
+    _ => Err(Error::TypeMismatch {
+      operand: "`src`",
+      expected: "a pointer",
+      found: src_ty.clone(),
+    }),
+  }
+}
+
+/// Returns the result type of `getelemptr` on a source pointer of the
+/// given type, which is `*T` for a source of type `*[T, n]`.
+///
+/// Returns an error if the given type is not a pointer type of array.
+///
+/// # Examples
+///
+/// ```
+/// use koopa::ir::values::gep_result_type;
+/// use koopa::ir::Type;
+///
+/// let row = Type::get_array(Type::get_i32(), 3);
+/// let ty = Type::get_pointer(Type::get_array(row.clone(), 2));
+/// assert_eq!(gep_result_type(&ty), Ok(Type::get_pointer(row)));
+/// // `getelemptr` can not index a pointer of integer
+/// let ptr = Type::get_pointer(Type::get_i32());
+/// assert!(gep_result_type(&ptr).is_err());
+/// ```
+pub fn gep_result_type(src_ty: &Type) -> Result<Type, Error> {
+  if let TypeKind::Pointer(ty) = src_ty.kind() {
+    if let TypeKind::Array(base, _) = ty.kind() {
+      return Ok(Type::get_pointer(base.clone()));
+    }
+  }
+  Err(Error::TypeMismatch {
+    operand: "`src`",
+    expected: "a pointer of array",
+    found: src_ty.clone(),
+  })
+}
+
 /// Binary operation.
 #[derive(Clone, Debug)]
 pub struct Binary {
@@ -349,6 +465,85 @@ pub enum BinaryOp {
   Sar,
 }
 
+impl BinaryOp {
+  /// Returns `true` if the binary operator is commutative.
+  pub fn is_commutative(self) -> bool {
+    matches!(
+      self,
+      BinaryOp::NotEq
+        | BinaryOp::Eq
+        | BinaryOp::Add
+        | BinaryOp::Mul
+        | BinaryOp::And
+        | BinaryOp::Or
+        | BinaryOp::Xor
+    )
+  }
+
+  /// Evaluates the binary operation on the given integer operands,
+  /// with wrapping semantics.
+  ///
+  /// Returns `None` if the right-hand side of the division or modulo
+  /// is zero.
+  pub fn eval(self, lhs: i32, rhs: i32) -> Option<i32> {
+    self.eval_with(lhs, rhs, OverflowMode::Wrapping)
+  }
+
+  /// Evaluates the binary operation on the given integer operands,
+  /// with the given overflow semantics.
+  ///
+  /// Returns `None` if the right-hand side of the division or modulo
+  /// is zero, or the operation overflows in [`OverflowMode::Trap`].
+  /// See [`OverflowMode`] for the operations that can overflow.
+  pub fn eval_with(self, lhs: i32, rhs: i32, mode: OverflowMode) -> Option<i32> {
+    let arith = |wrapping: fn(i32, i32) -> i32,
+                 saturating: fn(i32, i32) -> i32,
+                 checked: fn(i32, i32) -> Option<i32>| match mode {
+      OverflowMode::Wrapping => Some(wrapping(lhs, rhs)),
+      OverflowMode::Saturating => Some(saturating(lhs, rhs)),
+      OverflowMode::Trap => checked(lhs, rhs),
+    };
+    match self {
+      BinaryOp::NotEq => Some((lhs != rhs) as i32),
+      BinaryOp::Eq => Some((lhs == rhs) as i32),
+      BinaryOp::Gt => Some((lhs > rhs) as i32),
+      BinaryOp::Lt => Some((lhs < rhs) as i32),
+      BinaryOp::Ge => Some((lhs >= rhs) as i32),
+      BinaryOp::Le => Some((lhs <= rhs) as i32),
+      BinaryOp::Add => arith(i32::wrapping_add, i32::saturating_add, i32::checked_add),
+      BinaryOp::Sub => arith(i32::wrapping_sub, i32::saturating_sub, i32::checked_sub),
+      BinaryOp::Mul => arith(i32::wrapping_mul, i32::saturating_mul, i32::checked_mul),
+      BinaryOp::Div if rhs == 0 => None,
+      BinaryOp::Div => arith(i32::wrapping_div, i32::saturating_div, i32::checked_div),
+      BinaryOp::Mod if rhs == 0 => None,
+      // the remainder of `i32::MIN / -1` is 0, even if saturated
+      BinaryOp::Mod => arith(i32::wrapping_rem, i32::wrapping_rem, i32::checked_rem),
+      BinaryOp::And => Some(lhs & rhs),
+      BinaryOp::Or => Some(lhs | rhs),
+      BinaryOp::Xor => Some(lhs ^ rhs),
+      BinaryOp::Shl => Some(lhs.wrapping_shl(rhs as u32)),
+      BinaryOp::Shr => Some((lhs as u32).wrapping_shr(rhs as u32) as i32),
+      BinaryOp::Sar => Some(lhs.wrapping_shr(rhs as u32)),
+    }
+  }
+}
+
+/// Semantics of integer overflows in `add`, `sub`, `mul`, `div` and
+/// `mod`. Only `i32::MIN / -1` and `i32::MIN % -1` overflow in `div`
+/// and `mod`. Other operations never overflow, and shift amounts are
+/// always taken modulo 32.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OverflowMode {
+  /// Results wrap around, like most hardware does.
+  #[default]
+  Wrapping,
+  /// Results are clamped to `i32::MIN` or `i32::MAX`.
+  /// `i32::MIN % -1` is still 0.
+  Saturating,
+  /// Overflows are errors. Overflowing operations are never folded.
+  Trap,
+}
+
 impl fmt::Display for BinaryOp {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match self {
@@ -379,8 +574,8 @@ pub struct Branch {
   cond: Value,
   true_bb: BasicBlock,
   false_bb: BasicBlock,
-  true_args: Vec<Value>,
-  false_args: Vec<Value>,
+  true_args: ValueList,
+  false_args: ValueList,
 }
 
 impl Branch {
@@ -395,8 +590,8 @@ impl Branch {
         cond,
         true_bb,
         false_bb,
-        true_args: Vec::new(),
-        false_args: Vec::new(),
+        true_args: ValueList::new(),
+        false_args: ValueList::new(),
       }),
     )
   }
@@ -405,8 +600,8 @@ impl Branch {
     cond: Value,
     true_bb: BasicBlock,
     false_bb: BasicBlock,
-    true_args: Vec<Value>,
-    false_args: Vec<Value>,
+    true_args: ValueList,
+    false_args: ValueList,
   ) -> ValueData {
     ValueData::new(
       Type::get_unit(),
@@ -458,7 +653,7 @@ impl Branch {
 
   /// Returns a mutable reference to the arguments passed to
   /// the true target basic block.
-  pub fn true_args_mut(&mut self) -> &mut Vec<Value> {
+  pub fn true_args_mut(&mut self) -> &mut ValueList {
     &mut self.true_args
   }
 
@@ -470,16 +665,35 @@ impl Branch {
 
   /// Returns a mutable reference to the arguments passed to
   /// the false target basic block.
-  pub fn false_args_mut(&mut self) -> &mut Vec<Value> {
+  pub fn false_args_mut(&mut self) -> &mut ValueList {
     &mut self.false_args
   }
+
+  /// Returns mutable references to both the true and the false
+  /// argument lists.
+  pub fn args_mut(&mut self) -> (&mut ValueList, &mut ValueList) {
+    (&mut self.true_args, &mut self.false_args)
+  }
+
+  /// Returns mutable references to the target and the argument list of
+  /// the true edge (index 0) or the false edge (index 1).
+  pub(in crate::ir) fn edge_mut(
+    &mut self,
+    index: usize,
+  ) -> Option<(&mut BasicBlock, &mut ValueList)> {
+    match index {
+      0 => Some((&mut self.true_bb, &mut self.true_args)),
+      1 => Some((&mut self.false_bb, &mut self.false_args)),
+      _ => None,
+    }
+  }
 }
 
 /// Unconditional jump.
 #[derive(Clone, Debug)]
 pub struct Jump {
   target: BasicBlock,
-  args: Vec<Value>,
+  args: ValueList,
 }
 
 impl Jump {
@@ -488,12 +702,12 @@ impl Jump {
       Type::get_unit(),
       ValueKind::Jump(Self {
         target,
-        args: Vec::new(),
+        args: ValueList::new(),
       }),
     )
   }
 
-  pub(in crate::ir) fn with_args(target: BasicBlock, args: Vec<Value>) -> ValueData {
+  pub(in crate::ir) fn with_args(target: BasicBlock, args: ValueList) -> ValueData {
     ValueData::new(Type::get_unit(), ValueKind::Jump(Self { target, args }))
   }
 
@@ -513,20 +727,25 @@ impl Jump {
   }
 
   /// Returns a mutable reference to the arguments passed to the target basic block.
-  pub fn args_mut(&mut self) -> &mut Vec<Value> {
+  pub fn args_mut(&mut self) -> &mut ValueList {
     &mut self.args
   }
+
+  /// Returns mutable references to the target and the argument list.
+  pub(in crate::ir) fn edge_mut(&mut self) -> (&mut BasicBlock, &mut ValueList) {
+    (&mut self.target, &mut self.args)
+  }
 }
 
 /// Function call.
 #[derive(Clone, Debug)]
 pub struct Call {
   callee: Function,
-  args: Vec<Value>,
+  args: ValueList,
 }
 
 impl Call {
-  pub(in crate::ir) fn new_data(callee: Function, args: Vec<Value>, ty: Type) -> ValueData {
+  pub(in crate::ir) fn new_data(callee: Function, args: ValueList, ty: Type) -> ValueData {
     ValueData::new(ty, ValueKind::Call(Self { callee, args }))
   }
 
@@ -546,7 +765,7 @@ impl Call {
   }
 
   /// Returns a mutable reference to the argument list.
-  pub fn args_mut(&mut self) -> &mut Vec<Value> {
+  pub fn args_mut(&mut self) -> &mut ValueList {
     &mut self.args
   }
 }
@@ -572,3 +791,16 @@ impl Return {
     &mut self.value
   }
 }
+
+/// Unreachable terminator.
+///
+/// Marks the end of a basic block that can never be reached at runtime,
+/// e.g. the block after a call to a `noreturn` function.
+#[derive(Clone, Debug)]
+pub struct Unreachable;
+
+impl Unreachable {
+  pub(in crate::ir) fn new_data() -> ValueData {
+    ValueData::new(Type::get_unit(), ValueKind::Unreachable(Self))
+  }
+}
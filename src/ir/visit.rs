@@ -0,0 +1,410 @@
+//! Visitor ([`Visitor`]) for traversing Koopa IR programs.
+//!
+//! [`walk`] traverses a program in layout order, and calls the methods
+//! of the given visitor on the program, global values, functions, basic
+//! blocks and instructions. All methods of [`Visitor`] do nothing by
+//! default, so visitors only need to implement the methods they are
+//! interested in. Instructions and global values are dispatched to the
+//! `visit_*` method of their kinds by [`visit_value`], which can also be
+//! used to visit operands.
+//!
+//! # Example
+//!
+//! ```
+//! use koopa::front::Driver;
+//! use koopa::ir::visit::{walk, Context, Visitor};
+//! use koopa::ir::values::Call;
+//! use koopa::ir::entities::ValueData;
+//! use koopa::ir::Value;
+//! use std::convert::Infallible;
+//!
+//! /// Collects names of callees in the program.
+//! struct Callees(Vec<String>);
+//!
+//! impl Visitor for Callees {
+//!   type Error = Infallible;
+//!
+//!   fn visit_call(
+//!     &mut self,
+//!     ctx: &Context,
+//!     _: Value,
+//!     _: &ValueData,
+//!     call: &Call,
+//!   ) -> Result<(), Infallible> {
+//!     self.0.push(ctx.program().func(call.callee()).name().into());
+//!     Ok(())
+//!   }
+//! }
+//!
+//! let driver: Driver<_> = r#"
+//!   decl @getint(): i32
+//!
+//!   fun @main(): i32 {
+//!   %entry:
+//!     %0 = call @getint()
+//!     ret %0
+//!   }
+//! "#
+//! .into();
+//! let program = driver.generate_program().unwrap();
+//! let mut callees = Callees(Vec::new());
+//! walk(&program, &mut callees).unwrap();
+//! assert_eq!(callees.0, ["@getint"]);
+//! ```
+
+use crate::ir::dfg::DataFlowGraph;
+use crate::ir::entities::{BasicBlockData, ValueData};
+use crate::ir::values::*;
+use crate::ir::{BasicBlock, Function, FunctionData, Program, Value, ValueKind};
+
+/// Context of a visitor method, which holds the program and the
+/// current function and basic block.
+pub struct Context<'a> {
+  program: &'a Program,
+  func: Option<(Function, &'a FunctionData)>,
+  bb: Option<BasicBlock>,
+}
+
+impl<'a> Context<'a> {
+  /// Creates a new context of the given program, which is not in any
+  /// function.
+  pub fn new(program: &'a Program) -> Self {
+    Self {
+      program,
+      func: None,
+      bb: None,
+    }
+  }
+
+  /// Returns a new context in the given function of the program.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the given function does not exist.
+  pub fn in_func(&self, func: Function) -> Self {
+    Self {
+      program: self.program,
+      func: Some((func, self.program.func(func))),
+      bb: None,
+    }
+  }
+
+  /// Returns a new context in the given basic block of the current
+  /// function.
+  pub fn in_bb(&self, bb: BasicBlock) -> Self {
+    Self {
+      program: self.program,
+      func: self.func,
+      bb: Some(bb),
+    }
+  }
+
+  /// Returns a reference to the program.
+  pub fn program(&self) -> &'a Program {
+    self.program
+  }
+
+  /// Returns the current function, or `None` if not in any function.
+  pub fn func(&self) -> Option<Function> {
+    self.func.map(|(func, _)| func)
+  }
+
+  /// Returns a reference to the data of the current function,
+  /// or `None` if not in any function.
+  pub fn func_data(&self) -> Option<&'a FunctionData> {
+    self.func.map(|(_, data)| data)
+  }
+
+  /// Returns a reference to the data flow graph of the current function,
+  /// or `None` if not in any function.
+  pub fn dfg(&self) -> Option<&'a DataFlowGraph> {
+    self.func_data().map(FunctionData::dfg)
+  }
+
+  /// Returns the current basic block, or `None` if not in any basic block.
+  pub fn bb(&self) -> Option<BasicBlock> {
+    self.bb
+  }
+}
+
+/// Implements [`Visitor`] and [`visit_value`] with `visit_*` methods
+/// for the given kinds of values.
+macro_rules! impl_visitor {
+  ($($kind:ident, $method:ident;)*) => {
+    /// A visitor of Koopa IR programs, see the
+    /// [module-level documentation](crate::ir::visit) for more details.
+    pub trait Visitor {
+      /// The error type of all visitor methods.
+      type Error;
+
+      /// Visits the program, before all global values and functions.
+      fn visit_program(&mut self, ctx: &Context) -> Result<(), Self::Error> {
+        let _ = ctx;
+        Ok(())
+      }
+
+      /// Leaves the program, after all global values and functions.
+      fn leave_program(&mut self, ctx: &Context) -> Result<(), Self::Error> {
+        let _ = ctx;
+        Ok(())
+      }
+
+      /// Visits a global value in the layout. Dispatches it to
+      /// [`Visitor::visit_global_alloc`] by default.
+      fn visit_global(&mut self, ctx: &Context, value: Value, data: &ValueData) -> Result<(), Self::Error> {
+        visit_value(self, ctx, value, data)
+      }
+
+      /// Visits a function declaration.
+      fn visit_decl(&mut self, ctx: &Context, func: Function, data: &FunctionData) -> Result<(), Self::Error> {
+        let _ = (ctx, func, data);
+        Ok(())
+      }
+
+      /// Visits a function definition, before all basic blocks.
+      fn visit_func(&mut self, ctx: &Context, func: Function, data: &FunctionData) -> Result<(), Self::Error> {
+        let _ = (ctx, func, data);
+        Ok(())
+      }
+
+      /// Leaves a function definition, after all basic blocks.
+      fn leave_func(&mut self, ctx: &Context, func: Function, data: &FunctionData) -> Result<(), Self::Error> {
+        let _ = (ctx, func, data);
+        Ok(())
+      }
+
+      /// Visits a basic block, before all instructions.
+      fn visit_bb(&mut self, ctx: &Context, bb: BasicBlock, data: &BasicBlockData) -> Result<(), Self::Error> {
+        let _ = (ctx, bb, data);
+        Ok(())
+      }
+
+      /// Leaves a basic block, after all instructions.
+      fn leave_bb(&mut self, ctx: &Context, bb: BasicBlock, data: &BasicBlockData) -> Result<(), Self::Error> {
+        let _ = (ctx, bb, data);
+        Ok(())
+      }
+
+      /// Visits an instruction in the layout. Dispatches it to the
+      /// `visit_*` method of its kind by default.
+      fn visit_inst(&mut self, ctx: &Context, value: Value, data: &ValueData) -> Result<(), Self::Error> {
+        visit_value(self, ctx, value, data)
+      }
+
+      $(
+        #[doc = concat!(
+          "Visits a value of kind [`", stringify!($kind), "`](ValueKind::", stringify!($kind), ")."
+        )]
+        fn $method(&mut self, ctx: &Context, value: Value, data: &ValueData, kind: &$kind) -> Result<(), Self::Error> {
+          let _ = (ctx, value, data, kind);
+          Ok(())
+        }
+      )*
+    }
+
+    /// Dispatches the given value to the `visit_*` method of its kind.
+    pub fn visit_value<V>(visitor: &mut V, ctx: &Context, value: Value, data: &ValueData) -> Result<(), V::Error>
+    where
+      V: Visitor + ?Sized,
+    {
+      match data.kind() {
+        $(ValueKind::$kind(kind) => visitor.$method(ctx, value, data, kind),)*
+      }
+    }
+  };
+}
+
+impl_visitor! {
+  Integer, visit_integer;
+  ZeroInit, visit_zero_init;
+  Undef, visit_undef;
+  Aggregate, visit_aggregate;
+  Bytes, visit_bytes;
+  FuncArgRef, visit_func_arg_ref;
+  BlockArgRef, visit_block_arg_ref;
+  Alloc, visit_alloc;
+  GlobalAlloc, visit_global_alloc;
+  Load, visit_load;
+  Store, visit_store;
+  GetPtr, visit_get_ptr;
+  GetElemPtr, visit_get_elem_ptr;
+  Binary, visit_binary;
+  Branch, visit_branch;
+  Jump, visit_jump;
+  Call, visit_call;
+  Return, visit_return;
+  Unreachable, visit_unreachable;
+}
+
+/// Traverses the given program with the given visitor.
+///
+/// Global values are visited in the global value layout order, then
+/// functions are visited in the function layout order. Basic blocks and
+/// instructions of each function definition are visited in the layout
+/// order. Returns the first error returned by the visitor.
+pub fn walk<V>(program: &Program, visitor: &mut V) -> Result<(), V::Error>
+where
+  V: Visitor + ?Sized,
+{
+  let ctx = Context::new(program);
+  visitor.visit_program(&ctx)?;
+  for &value in program.inst_layout() {
+    visitor.visit_global(&ctx, value, &program.borrow_value(value))?;
+  }
+  for &func in program.func_layout() {
+    let ctx = ctx.in_func(func);
+    let data = program.func(func);
+    if data.layout().entry_bb().is_none() {
+      visitor.visit_decl(&ctx, func, data)?;
+      continue;
+    }
+    visitor.visit_func(&ctx, func, data)?;
+    for (&bb, node) in data.layout().bbs() {
+      let ctx = ctx.in_bb(bb);
+      let bb_data = data.dfg().bb(bb);
+      visitor.visit_bb(&ctx, bb, bb_data)?;
+      for &inst in node.insts().keys() {
+        visitor.visit_inst(&ctx, inst, data.dfg().value(inst))?;
+      }
+      visitor.leave_bb(&ctx, bb, bb_data)?;
+    }
+    visitor.leave_func(&ctx, func, data)?;
+  }
+  visitor.leave_program(&ctx)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::front::Driver;
+  use std::convert::Infallible;
+
+  /// Records the traversal order.
+  #[derive(Default)]
+  struct Trace(Vec<String>);
+
+  impl Visitor for Trace {
+    type Error = Infallible;
+
+    fn visit_global_alloc(
+      &mut self,
+      ctx: &Context,
+      _: Value,
+      data: &ValueData,
+      alloc: &GlobalAlloc,
+    ) -> Result<(), Infallible> {
+      // visit the initializer as an operand
+      let init = ctx.program().borrow_value(alloc.init());
+      visit_value(self, ctx, alloc.init(), &init)?;
+      self
+        .0
+        .push(format!("global {}", data.name().as_ref().unwrap()));
+      Ok(())
+    }
+
+    fn visit_integer(
+      &mut self,
+      _: &Context,
+      _: Value,
+      _: &ValueData,
+      int: &Integer,
+    ) -> Result<(), Infallible> {
+      self.0.push(format!("int {}", int.value()));
+      Ok(())
+    }
+
+    fn visit_decl(
+      &mut self,
+      _: &Context,
+      _: Function,
+      data: &FunctionData,
+    ) -> Result<(), Infallible> {
+      self.0.push(format!("decl {}", data.name()));
+      Ok(())
+    }
+
+    fn visit_func(
+      &mut self,
+      _: &Context,
+      _: Function,
+      data: &FunctionData,
+    ) -> Result<(), Infallible> {
+      self.0.push(format!("func {}", data.name()));
+      Ok(())
+    }
+
+    fn leave_func(&mut self, _: &Context, _: Function, _: &FunctionData) -> Result<(), Infallible> {
+      self.0.push("end".into());
+      Ok(())
+    }
+
+    fn visit_bb(
+      &mut self,
+      ctx: &Context,
+      bb: BasicBlock,
+      data: &BasicBlockData,
+    ) -> Result<(), Infallible> {
+      assert_eq!(ctx.bb(), Some(bb));
+      self.0.push(data.name().clone().unwrap());
+      Ok(())
+    }
+
+    fn visit_inst(
+      &mut self,
+      ctx: &Context,
+      value: Value,
+      data: &ValueData,
+    ) -> Result<(), Infallible> {
+      assert_eq!(ctx.func_data().unwrap().layout().parent_bb(value), ctx.bb());
+      self.0.push(format!("{:?}", data.ty()));
+      Ok(())
+    }
+
+    fn visit_jump(
+      &mut self,
+      _: &Context,
+      _: Value,
+      _: &ValueData,
+      _: &Jump,
+    ) -> Result<(), Infallible> {
+      panic!("`visit_inst` is overridden")
+    }
+  }
+
+  #[test]
+  fn walk_program() {
+    let driver: Driver<_> = r#"
+      global @x = alloc i32, 42
+
+      decl @getint(): i32
+
+      fun @main(): i32 {
+      %entry:
+        %0 = call @getint()
+        jump %end
+
+      %end:
+        ret %0
+      }
+    "#
+    .into();
+    let program = driver.generate_program().unwrap();
+    let mut trace = Trace::default();
+    walk(&program, &mut trace).unwrap();
+    assert_eq!(
+      trace.0,
+      [
+        "int 42",
+        "global @x",
+        "decl @getint",
+        "func @main",
+        "%entry",
+        "i32",
+        "unit",
+        "%end",
+        "unit",
+        "end",
+      ]
+    );
+  }
+}
@@ -74,7 +74,15 @@
 //! Koopa IR library is heavily influenced by [LLVM](https://llvm.org/)
 //! and [Cranelift](https://wasmtime.dev/).
 
+#![forbid(unsafe_code)]
+
+pub mod analysis;
 pub mod back;
+pub mod diagnostic;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub mod driver;
 pub mod front;
 pub mod ir;
 pub mod opt;
+pub mod test_utils;
+pub mod text;
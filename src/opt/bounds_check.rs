@@ -0,0 +1,301 @@
+//! Bounds check instrumentation pass ([`BoundsCheck`]) related
+//! implementations.
+
+use crate::ir::builder::FunctionBuilder;
+use crate::ir::builder_traits::*;
+use crate::ir::{BasicBlock, BinaryOp, Function, FunctionData, Program, Type, TypeKind};
+use crate::ir::{Value, ValueKind};
+use crate::opt::profile::runtime_func;
+use crate::opt::ModulePass;
+
+/// A module pass that checks the indices of `getelemptr`s at runtime.
+///
+/// Before every `getelemptr` whose source has type `*[T, n]`, the pass
+/// splits the basic block, and inserts a check of `0 <= index < n`.
+/// If the check fails, the program branches to a trap basic block,
+/// which calls the handler function with the index and `n`, and then
+/// executes `unreachable`. Each function has at most one trap basic
+/// block, which is shared by all checks in the function.
+///
+/// `getelemptr`s with constant in-range indices are not checked.
+/// Constant out-of-range indices are checked like non-constant ones,
+/// and also reported by the pass without running the program.
+///
+/// By default, the handler function is `@__koopa_oob(i32, i32)`. It is
+/// declared if it does not exist, and it is not instrumented if it is
+/// a function definition. Function declarations are skipped.
+///
+/// # Example
+///
+/// ```
+/// use koopa::front::Driver;
+/// use koopa::opt::*;
+///
+/// let driver: Driver<_> = r#"
+///   global @arr = alloc [i32, 4], zeroinit
+///
+///   fun @main(@i: i32): i32 {
+///   %entry:
+///     %0 = getelemptr @arr, @i
+///     %1 = getelemptr @arr, 5
+///     %2 = getelemptr @arr, 3
+///     ret 0
+///   }
+/// "#
+/// .into();
+/// let mut program = driver.generate_program().unwrap();
+///
+/// let reports = BoundsCheck::new().run(&mut program);
+/// assert_eq!(reports.len(), 1);
+/// assert_eq!((reports[0].index, reports[0].len), (5, 4));
+/// // the entry basic block is split twice, and the trap basic block
+/// // is added
+/// let main = program.func(program.func_layout()[0]);
+/// assert_eq!(main.layout().bbs().len(), 4);
+/// ```
+pub struct BoundsCheck {
+  handler: String,
+  reports: Vec<OutOfBounds>,
+}
+
+/// A `getelemptr` with a constant out-of-range index, which is
+/// reported by [`BoundsCheck`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutOfBounds {
+  /// Name of the function of the `getelemptr`.
+  pub func: String,
+  /// The `getelemptr` instruction.
+  pub inst: Value,
+  /// The index.
+  pub index: i32,
+  /// The length of the array.
+  pub len: usize,
+}
+
+impl BoundsCheck {
+  /// Creates a new pass with `@__koopa_oob` as the handler function.
+  pub fn new() -> Self {
+    Self::with_handler("@__koopa_oob".into())
+  }
+
+  /// Creates a new pass with the given name of the handler function.
+  pub fn with_handler(handler: String) -> Self {
+    Self {
+      handler,
+      reports: Vec::new(),
+    }
+  }
+
+  /// Returns the constant out-of-range indices reported by the last run
+  /// of the pass as a [`ModulePass`].
+  pub fn reports(&self) -> &[OutOfBounds] {
+    &self.reports
+  }
+
+  /// Runs the pass on the given program. Returns the constant
+  /// out-of-range indices, in the function layout order and then the
+  /// instruction layout order.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the name of the handler function is not a valid global
+  /// symbol name, or is used by a global value or a function with type
+  /// other than `(i32, i32)`.
+  pub fn run(&self, program: &mut Program) -> Vec<OutOfBounds> {
+    let mut reports = Vec::new();
+    let mut sites = Vec::new();
+    for &func in program.func_layout() {
+      let data = program.func(func);
+      if data.name() == self.handler {
+        continue;
+      }
+      let checks = collect_checks(program, data, &mut reports);
+      if !checks.is_empty() {
+        sites.push((func, checks));
+      }
+    }
+    if sites.is_empty() {
+      return reports;
+    }
+    let handler = runtime_func(program, &self.handler, vec![Type::get_i32(); 2]);
+    for (func, checks) in sites {
+      let data = program.func_mut(func);
+      let trap = new_trap(data, handler);
+      for (gep, index, len) in checks {
+        insert_check(data, trap, gep, index, len);
+      }
+    }
+    reports
+  }
+}
+
+impl Default for BoundsCheck {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl ModulePass for BoundsCheck {
+  fn run_on(&mut self, program: &mut Program) {
+    self.reports = self.run(program);
+  }
+
+  fn name(&self) -> &str {
+    "bounds-check"
+  }
+}
+
+/// Collects `getelemptr`s to be checked in the given function, as tuples
+/// of the instruction, the index and the array length. Reports constant
+/// out-of-range indices to `reports`.
+fn collect_checks(
+  program: &Program,
+  data: &FunctionData,
+  reports: &mut Vec<OutOfBounds>,
+) -> Vec<(Value, Value, usize)> {
+  let dfg = data.dfg();
+  let mut checks = Vec::new();
+  for node in data.layout().bbs().nodes() {
+    for &inst in node.insts().keys() {
+      let ValueKind::GetElemPtr(gep) = dfg.value(inst).kind() else {
+        continue;
+      };
+      let src_ty = if gep.src().is_global() {
+        program.borrow_value(gep.src()).ty().clone()
+      } else {
+        dfg.value(gep.src()).ty().clone()
+      };
+      let len = match src_ty.kind() {
+        TypeKind::Pointer(base) => match base.kind() {
+          TypeKind::Array(_, len) => *len,
+          _ => continue,
+        },
+        _ => continue,
+      };
+      if let ValueKind::Integer(i) = dfg.value(gep.index()).kind() {
+        if (0..len as i64).contains(&(i.value() as i64)) {
+          continue;
+        }
+        reports.push(OutOfBounds {
+          func: data.name().into(),
+          inst,
+          index: i.value(),
+          len,
+        });
+      }
+      checks.push((inst, gep.index(), len));
+    }
+  }
+  checks
+}
+
+/// Creates the trap basic block at the end of the given function,
+/// which calls the given handler function with its parameters.
+fn new_trap(data: &mut FunctionData, handler: Function) -> BasicBlock {
+  let trap = data.dfg_mut().new_bb().basic_block_with_param_names(
+    Some("%oob".into()),
+    vec![
+      (Some("%index".into()), Type::get_i32()),
+      (Some("%len".into()), Type::get_i32()),
+    ],
+  );
+  data.layout_mut().bbs_mut().push_key_back(trap).unwrap();
+  let args = data.dfg().bb(trap).params().to_vec();
+  let mut builder = FunctionBuilder::new(data);
+  builder.position_at_end(trap);
+  builder.call(handler, args);
+  builder.unreachable();
+  trap
+}
+
+/// Splits the basic block before the given `getelemptr`, and checks the
+/// index before it.
+fn insert_check(data: &mut FunctionData, trap: BasicBlock, gep: Value, index: Value, len: usize) {
+  let bb = data.layout().parent_bb(gep).unwrap();
+  let cont = data.split_bb(gep);
+  let jump = *data
+    .layout()
+    .bbs()
+    .node(&bb)
+    .unwrap()
+    .insts()
+    .back_key()
+    .unwrap();
+  let mut builder = FunctionBuilder::new(data);
+  builder.position_before(jump);
+  let zero = builder.integer(0);
+  let len = builder.integer(len as i32);
+  let lower = builder.binary(BinaryOp::Ge, index, zero);
+  let upper = builder.binary(BinaryOp::Lt, index, len);
+  let in_bounds = builder.binary(BinaryOp::And, lower, upper);
+  data.dfg_mut().replace_value_with(jump).branch_with_args(
+    in_bounds,
+    cont,
+    trap,
+    vec![],
+    vec![index, len],
+  );
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::analysis::verifier::verify;
+  use crate::back::KoopaGenerator;
+  use crate::front::Driver;
+  use std::str;
+
+  #[test]
+  fn insert_checks() {
+    let driver: Driver<_> = r#"
+      fun @f(@p: *[[i32, 3], 2], @i: i32): i32 {
+      %entry:
+        %0 = getelemptr @p, 0
+        %1 = getelemptr %0, @i
+        %2 = getptr %1, 1
+        %3 = getelemptr @p, -1
+        ret 0
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    let mut pass = BoundsCheck::new();
+    pass.run_on(&mut program);
+    assert_eq!(pass.reports().len(), 1);
+    assert_eq!(pass.reports()[0].func, "@f");
+    assert_eq!(pass.reports()[0].index, -1);
+    assert!(verify(&program).is_ok());
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    assert_eq!(
+      str::from_utf8(&gen.writer()).unwrap(),
+      r#"fun @f(@p: *[[i32, 3], 2], @i: i32): i32 {
+%entry:
+  %0 = getelemptr @p, 0
+  %1 = ge @i, 0
+  %2 = lt @i, 3
+  %3 = and %1, %2
+  br %3, %4, %oob(@i, 3)
+
+%4:
+  %5 = getelemptr %0, @i
+  %6 = getptr %5, 1
+  %7 = ge -1, 0
+  %8 = lt -1, 2
+  %9 = and %7, %8
+  br %9, %10, %oob(-1, 2)
+
+%10:
+  %11 = getelemptr @p, -1
+  ret 0
+
+%oob(%index: i32, %len: i32):
+  call @__koopa_oob(%index, %len)
+  unreachable
+}
+
+decl @__koopa_oob(i32, i32)
+"#
+    );
+  }
+}
@@ -0,0 +1,222 @@
+//! Dead argument elimination pass ([`DeadArgElimination`]) related
+//! implementations.
+
+use crate::ir::{Function, Program, Value, ValueKind};
+use crate::opt::rewrite::{is_dead, remove_dead};
+use crate::opt::ModulePass;
+use std::collections::HashSet;
+
+/// A module pass that removes unused parameters of function definitions,
+/// and the corresponding arguments of all calls to them.
+///
+/// Argument computations that become dead after the removal are also
+/// removed, which may make parameters of the callers unused. So the pass
+/// runs until no more parameters can be removed.
+///
+/// Exported functions are called from outside the program, and
+/// declarations may be defined with parameters used elsewhere. So
+/// parameters of them are never removed. By default, the only exported
+/// function is `@main`.
+///
+/// # Example
+///
+/// ```
+/// use koopa::front::Driver;
+/// use koopa::opt::*;
+///
+/// let driver: Driver<_> = r#"
+///   fun @first(@a: i32, @b: i32): i32 {
+///   %entry:
+///     ret @a
+///   }
+///
+///   fun @main(): i32 {
+///   %entry:
+///     %0 = add 1, 2
+///     %1 = call @first(3, %0)
+///     ret %1
+///   }
+/// "#
+/// .into();
+/// let mut program = driver.generate_program().unwrap();
+///
+/// assert_eq!(DeadArgElimination::new().run(&mut program), 1);
+/// let first = program.func(program.func_layout()[0]);
+/// assert_eq!(first.params().len(), 1);
+/// ```
+pub struct DeadArgElimination {
+  exported: Vec<String>,
+}
+
+impl DeadArgElimination {
+  /// Creates a new pass with function `@main` as the only exported
+  /// function.
+  pub fn new() -> Self {
+    Self::with_exported(vec!["@main".into()])
+  }
+
+  /// Creates a new pass with the given names of exported functions.
+  pub fn with_exported(exported: Vec<String>) -> Self {
+    Self { exported }
+  }
+
+  /// Runs the pass on the given program.
+  /// Returns the number of removed parameters.
+  pub fn run(&self, program: &mut Program) -> usize {
+    let mut count = 0;
+    let mut changed = true;
+    while changed {
+      changed = false;
+      for func in program.func_layout().to_vec() {
+        for index in self.dead_params(program, func) {
+          remove_param(program, func, index);
+          count += 1;
+          changed = true;
+        }
+      }
+    }
+    count
+  }
+
+  /// Returns indices of unused parameters of the given function,
+  /// in the descending order.
+  fn dead_params(&self, program: &Program, func: Function) -> Vec<usize> {
+    let data = program.func(func);
+    if data.layout().entry_bb().is_none() || self.exported.iter().any(|e| e == data.name()) {
+      return Vec::new();
+    }
+    let dfg = data.dfg();
+    let mut params: Vec<_> = data
+      .params()
+      .iter()
+      .enumerate()
+      .filter(|(_, p)| dfg.value(**p).used_by().is_empty())
+      .map(|(i, _)| i)
+      .collect();
+    params.reverse();
+    params
+  }
+}
+
+impl Default for DeadArgElimination {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl ModulePass for DeadArgElimination {
+  fn run_on(&mut self, program: &mut Program) {
+    self.run(program);
+  }
+
+  fn name(&self) -> &str {
+    "dead-args"
+  }
+}
+
+/// Removes the parameter at the given index from the given function,
+/// and removes the dead arguments of all calls to the function.
+fn remove_param(program: &mut Program, func: Function, index: usize) {
+  // collect the arguments to be removed of each caller
+  let args: Vec<(Function, HashSet<Value>)> = program
+    .funcs()
+    .iter()
+    .map(|(f, data)| {
+      let args = data.dfg().values().values().filter_map(|v| match v.kind() {
+        ValueKind::Call(call) if call.callee() == func => Some(call.args()[index]),
+        _ => None,
+      });
+      (*f, args.collect())
+    })
+    .collect();
+  program.remove_func_param(func, index);
+  for (caller, args) in args {
+    let data = program.func_mut(caller);
+    let args = args.into_iter().filter(|v| is_dead(data.dfg(), *v));
+    remove_dead(data, args.collect());
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::back::KoopaGenerator;
+  use crate::front::Driver;
+  use std::str;
+
+  const SRC: &str = r#"
+    decl @getint(): i32
+
+    fun @ext(@x: i32, @y: i32): i32 {
+    %entry:
+      ret @x
+    }
+
+    fun @f(@a: i32, @b: i32, @c: i32): i32 {
+    %entry:
+      %0 = add @b, 1
+      ret %0
+    }
+
+    fun @g(@x: i32, @y: i32): i32 {
+    %entry:
+      %y1 = add @y, 2
+      %r = call @f(@x, 10, %y1)
+      %s = call @f(@x, @x, @x)
+      %t = add %r, %s
+      ret %t
+    }
+
+    fun @main(@argc: i32): i32 {
+    %entry:
+      %in = call @getint()
+      %g = call @g(%in, %in)
+      %ext = call @ext(%g, 1)
+      ret %ext
+    }
+  "#;
+
+  #[test]
+  fn remove_dead_args() {
+    let driver: Driver<_> = SRC.into();
+    let mut program = driver.generate_program().unwrap();
+    let pass = DeadArgElimination::with_exported(vec!["@main".into(), "@ext".into()]);
+    // `@a` and `@c` of `@f`, and then `@y` of `@g`
+    assert_eq!(pass.run(&mut program), 3);
+    assert_eq!(pass.run(&mut program), 0);
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    assert_eq!(
+      str::from_utf8(&gen.writer()).unwrap(),
+      r#"decl @getint(): i32
+
+fun @ext(@x: i32, @y: i32): i32 {
+%entry:
+  ret @x
+}
+
+fun @f(@b: i32): i32 {
+%entry:
+  %0 = add @b, 1
+  ret %0
+}
+
+fun @g(@x: i32): i32 {
+%entry:
+  %r = call @f(10)
+  %s = call @f(@x)
+  %t = add %r, %s
+  ret %t
+}
+
+fun @main(@argc: i32): i32 {
+%entry:
+  %in = call @getint()
+  %g = call @g(%in)
+  %ext = call @ext(%g, 1)
+  ret %ext
+}
+"#
+    );
+  }
+}
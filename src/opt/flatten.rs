@@ -0,0 +1,227 @@
+//! Operand flattening ([`flatten_operands`]) related implementations.
+
+use crate::ir::builder_traits::*;
+use crate::ir::{BasicBlock, FunctionData, TypeKind, Value, ValueKind};
+use crate::opt::rewrite::remove_dead;
+
+/// Rewrites stores of composite constants in the given function into
+/// stores of scalar constants, for backends that can only handle
+/// scalar operands.
+///
+/// Composite constants are constants of array types: aggregates,
+/// `zeroinit`s, `undef`s and byte data. The value operand of `store` is
+/// the only position that is rewritten. Each such store is replaced
+/// with a `getelemptr` and a store for every element, in element order,
+/// and elements that are composite constants are flattened recursively,
+/// so only non-array constants are stored. For example:
+///
+/// ```text
+/// store {{1, 2}, zeroinit}, %p
+/// ```
+///
+/// becomes:
+///
+/// ```text
+/// %0 = getelemptr %p, 0
+/// %1 = getelemptr %0, 0
+/// store 1, %1
+/// %2 = getelemptr %0, 1
+/// store 2, %2
+/// %3 = getelemptr %p, 1
+/// %4 = getelemptr %3, 0
+/// store 0, %4
+/// %5 = getelemptr %3, 1
+/// store 0, %5
+/// ```
+///
+/// Zero elements of type `i32` are stored as integer `0`. The new
+/// instructions inherit the source location of the store.
+/// Stores of `undef` elements are kept, so the elements are still
+/// undefined after the stores.
+///
+/// Arguments of calls and basic block targets can also be composite
+/// constants, but they are not rewritten, since no instruction can
+/// construct an array value. Functions that contain no stores of
+/// composite constants are not changed, so flattening an already
+/// flattened function does nothing.
+///
+/// # Example
+///
+/// ```
+/// use koopa::front::Driver;
+/// use koopa::opt::flatten_operands;
+///
+/// let driver: Driver<_> = r#"
+///   fun @f() {
+///   %entry:
+///     %a = alloc [i32, 2]
+///     store {1, 2}, %a
+///     ret
+///   }
+/// "#
+/// .into();
+/// let mut program = driver.generate_program().unwrap();
+/// let func = program.func_mut(program.func_layout()[0]);
+/// flatten_operands(func);
+/// let entry = func.layout().entry_bb().unwrap();
+/// // `alloc`, two `getelemptr`s, two `store`s and `ret`
+/// assert_eq!(func.layout().bbs().node(&entry).unwrap().insts().len(), 6);
+/// ```
+pub fn flatten_operands(func: &mut FunctionData) {
+  let stores: Vec<_> = func
+    .layout()
+    .bbs()
+    .iter()
+    .flat_map(|(bb, node)| node.insts().keys().map(move |inst| (*bb, *inst)))
+    .filter_map(|(bb, inst)| match func.dfg().value(inst).kind() {
+      ValueKind::Store(store) if is_composite(func, store.value()) => {
+        Some((bb, inst, store.value(), store.dest()))
+      }
+      _ => None,
+    })
+    .collect();
+  for (bb, store, value, dest) in stores {
+    flatten_store(func, bb, store, value, dest);
+  }
+}
+
+/// Returns `true` if the given value is a constant of array type.
+fn is_composite(func: &FunctionData, value: Value) -> bool {
+  if value.is_global() {
+    return false;
+  }
+  let data = func.dfg().value(value);
+  data.kind().is_const() && matches!(data.ty().kind(), TypeKind::Array(..))
+}
+
+/// Replaces the given store of a composite constant with stores of
+/// its scalar elements.
+fn flatten_store(func: &mut FunctionData, bb: BasicBlock, store: Value, value: Value, dest: Value) {
+  /// Destination of a value, the pointer itself, or an element of it.
+  enum Dest {
+    Ptr(Value),
+    Elem(Value, i32),
+  }
+  let span = func.dfg().value(store).span();
+  let mut insts = Vec::new();
+  // constants still to be stored, and where to store them
+  let mut stack = vec![(value, Dest::Ptr(dest))];
+  while let Some((value, dest)) = stack.pop() {
+    let dfg = func.dfg_mut();
+    let dest = match dest {
+      Dest::Ptr(ptr) => ptr,
+      Dest::Elem(ptr, index) => {
+        let index = dfg.new_value().integer(index);
+        let ptr = dfg.new_value().get_elem_ptr(ptr, index);
+        insts.push(ptr);
+        ptr
+      }
+    };
+    let data = dfg.value(value);
+    let (base, len) = match data.ty().kind() {
+      TypeKind::Array(base, len) => (base.clone(), *len),
+      _ => {
+        insts.push(dfg.new_value().store(value, dest));
+        continue;
+      }
+    };
+    let elems: Vec<_> = match data.kind() {
+      ValueKind::Aggregate(agg) => agg.elems().to_vec(),
+      ValueKind::Bytes(bytes) => {
+        let bytes: Vec<_> = bytes.elems().collect();
+        bytes
+          .into_iter()
+          .map(|b| dfg.new_value().integer(b))
+          .collect()
+      }
+      ValueKind::ZeroInit(_) if base.is_i32() => vec![dfg.new_value().integer(0); len],
+      ValueKind::ZeroInit(_) => vec![dfg.new_value().zero_init(base); len],
+      ValueKind::Undef(_) => vec![dfg.new_value().undef(base); len],
+      _ => panic!("invalid constant"),
+    };
+    for (i, elem) in elems.into_iter().enumerate().rev() {
+      stack.push((elem, Dest::Elem(dest, i as i32)));
+    }
+  }
+  let mut cursor = func.layout_mut().bb_mut(bb).insts_mut().cursor_mut(store);
+  for &inst in &insts {
+    cursor.insert_key_before(inst).unwrap();
+  }
+  for inst in insts {
+    func.dfg_mut().set_value_span(inst, span);
+  }
+  remove_dead(func, vec![store]);
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::back::KoopaGenerator;
+  use crate::front::Driver;
+  use crate::ir::Program;
+  use std::str;
+
+  #[test]
+  fn flatten_stores() {
+    let driver: Driver<_> = r#"
+      global @g = alloc [i32, 2], {1, 2}
+
+      fun @f(@p: *[[i32, 2], 2]) {
+      %entry:
+        store {{1, 2}, zeroinit}, @p
+        %q = getelemptr @p, 1
+        store undef, %q
+        store {3, 4}, @g
+        ret
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    let func = program.func_layout()[0];
+    let dump = |program: &Program| {
+      let mut gen = KoopaGenerator::new(Vec::new());
+      gen.generate_on(program).unwrap();
+      str::from_utf8(&gen.writer()).unwrap().to_string()
+    };
+    flatten_operands(program.func_mut(func));
+    let flattened = dump(&program);
+    assert_eq!(
+      flattened,
+      r#"global @g = alloc [i32, 2], {1, 2}
+
+fun @f(@p: *[[i32, 2], 2]) {
+%entry:
+  %0 = getelemptr @p, 0
+  %1 = getelemptr %0, 0
+  store 1, %1
+  %2 = getelemptr %0, 1
+  store 2, %2
+  %3 = getelemptr @p, 1
+  %4 = getelemptr %3, 0
+  store 0, %4
+  %5 = getelemptr %3, 1
+  store 0, %5
+  %q = getelemptr @p, 1
+  %6 = getelemptr %q, 0
+  store undef, %6
+  %7 = getelemptr %q, 1
+  store undef, %7
+  %8 = getelemptr @g, 0
+  store 3, %8
+  %9 = getelemptr @g, 1
+  store 4, %9
+  ret
+}
+"#
+    );
+    // the aggregates are removed, and flattening again does nothing
+    let data = program.func(func);
+    assert!(data
+      .dfg()
+      .values()
+      .values()
+      .all(|v| !matches!(v.kind(), ValueKind::Aggregate(_))));
+    flatten_operands(program.func_mut(func));
+    assert_eq!(dump(&program), flattened);
+  }
+}
@@ -0,0 +1,291 @@
+//! Dead function and global value elimination pass ([`GlobalDce`])
+//! related implementations.
+
+use crate::ir::{Function, Program, Value, ValueKind};
+use crate::opt::ModulePass;
+use std::collections::HashSet;
+
+/// A module pass that removes functions and global values that are not
+/// reachable from the root functions.
+///
+/// Functions are reachable if they are roots, or called by reachable
+/// functions. Global values are reachable if they are used by values
+/// in reachable functions, or by other reachable global values. All
+/// unreachable functions (including declarations) and global values
+/// are removed, even if they refer to each other.
+///
+/// By default, the only root is function `@main`. Programs without
+/// root functions will be emptied.
+///
+/// # Example
+///
+/// ```
+/// use koopa::front::Driver;
+/// use koopa::opt::*;
+///
+/// let driver: Driver<_> = r#"
+///   global @used = alloc i32, zeroinit
+///   global @unused = alloc i32, 1
+///   decl @getint(): i32
+///
+///   fun @helper(): i32 {
+///   %entry:
+///     %0 = call @getint()
+///     ret %0
+///   }
+///
+///   fun @main(): i32 {
+///   %entry:
+///     %0 = load @used
+///     ret %0
+///   }
+/// "#
+/// .into();
+/// let mut program = driver.generate_program().unwrap();
+///
+/// let removed = GlobalDce::new().run(&mut program);
+/// assert_eq!(removed.funcs, ["@getint", "@helper"]);
+/// assert_eq!(removed.globals, [Some("@unused".into())]);
+/// assert_eq!(program.func_layout().len(), 1);
+/// ```
+pub struct GlobalDce {
+  roots: Vec<String>,
+  removed: Removed,
+}
+
+/// Functions and global allocations removed by [`GlobalDce`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Removed {
+  /// Names of the removed functions, in the order of the function layout.
+  pub funcs: Vec<String>,
+  /// Names of the removed global allocations, in the order of the
+  /// global value layout.
+  ///
+  /// Removed constants (like initializers) are not recorded.
+  pub globals: Vec<Option<String>>,
+}
+
+impl GlobalDce {
+  /// Creates a new pass with function `@main` as the root.
+  pub fn new() -> Self {
+    Self::with_roots(vec!["@main".into()])
+  }
+
+  /// Creates a new pass with the given names of root functions.
+  pub fn with_roots(roots: Vec<String>) -> Self {
+    Self {
+      roots,
+      removed: Removed::default(),
+    }
+  }
+
+  /// Returns the functions and global allocations removed by the last
+  /// run of the pass as a [`ModulePass`].
+  pub fn removed(&self) -> &Removed {
+    &self.removed
+  }
+
+  /// Runs the pass on the given program.
+  /// Returns the removed functions and global allocations.
+  pub fn run(&self, program: &mut Program) -> Removed {
+    let (funcs, values) = self.mark(program);
+    let mut removed = Removed::default();
+    // remove functions first, so that dead global values will not be
+    // used by values in dead functions
+    for func in program.func_layout().to_vec() {
+      if !funcs.contains(&func) {
+        let data = program.remove_func(func).unwrap();
+        removed.funcs.push(data.name().into());
+      }
+    }
+    let globals = program.borrow_values();
+    removed.globals = program
+      .inst_layout()
+      .iter()
+      .filter(|v| !values.contains(v))
+      .map(|v| globals[v].name().clone())
+      .collect();
+    let mut dead: Vec<_> = globals
+      .keys()
+      .filter(|v| !values.contains(v))
+      .copied()
+      .collect();
+    drop(globals);
+    // dead global values can only be used by other dead global values,
+    // so remove the unused ones until all of them are removed
+    while !dead.is_empty() {
+      let len = dead.len();
+      dead.retain(|v| {
+        let unused = program.borrow_value(*v).used_by().is_empty();
+        if unused {
+          program.remove_value(*v);
+        }
+        !unused
+      });
+      assert!(
+        dead.len() < len,
+        "dead global values are used by each other"
+      );
+    }
+    removed
+  }
+
+  /// Marks all reachable functions and global values.
+  fn mark(&self, program: &Program) -> (HashSet<Function>, HashSet<Value>) {
+    let globals = program.borrow_values();
+    let mut funcs = HashSet::new();
+    let mut values = HashSet::new();
+    let mut func_worklist: Vec<_> = program
+      .func_layout()
+      .iter()
+      .filter(|f| self.roots.iter().any(|r| r == program.func(**f).name()))
+      .copied()
+      .collect();
+    let mut value_worklist = Vec::new();
+    loop {
+      if let Some(func) = func_worklist.pop() {
+        if !funcs.insert(func) {
+          continue;
+        }
+        for data in program.func(func).dfg().values().values() {
+          if let ValueKind::Call(call) = data.kind() {
+            func_worklist.push(call.callee());
+          }
+          value_worklist.extend(data.kind().value_uses().filter(|v| v.is_global()));
+        }
+      } else if let Some(value) = value_worklist.pop() {
+        if values.insert(value) {
+          value_worklist.extend(globals[&value].kind().value_uses());
+        }
+      } else {
+        break;
+      }
+    }
+    (funcs, values)
+  }
+}
+
+impl Default for GlobalDce {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl ModulePass for GlobalDce {
+  fn run_on(&mut self, program: &mut Program) {
+    self.removed = self.run(program);
+  }
+
+  fn name(&self) -> &str {
+    "global-dce"
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::back::KoopaGenerator;
+  use crate::front::Driver;
+  use crate::ir::builder::FunctionBuilder;
+  use crate::ir::{FunctionData, Type};
+  use std::str;
+
+  const SRC: &str = r#"
+    global @a = alloc [i32, 2], {1, 2}
+    global @b = alloc i32, 3
+    global @c = alloc i32, zeroinit
+    decl @putint(i32)
+    decl @getint(): i32
+
+    fun @even(@n: i32): i32 {
+    %entry:
+      %0 = load @a
+      %1 = call @getint()
+      ret %1
+    }
+
+    fun @odd(@n: i32): i32 {
+    %entry:
+      store @n, @b
+      %0 = call @even(@n)
+      ret %0
+    }
+
+    fun @lib(): i32 {
+    %entry:
+      %0 = getelemptr @a, 1
+      %1 = load %0
+      call @putint(%1)
+      ret %1
+    }
+
+    fun @main(): i32 {
+    %entry:
+      %0 = load @c
+      ret %0
+    }
+  "#;
+
+  fn dump(program: &Program) -> String {
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(program).unwrap();
+    str::from_utf8(&gen.writer()).unwrap().into()
+  }
+
+  #[test]
+  fn remove_unreachable() {
+    let driver: Driver<_> = SRC.into();
+    let mut program = driver.generate_program().unwrap();
+    // a dead recursive function
+    let rec = program.new_func(FunctionData::new("@rec".into(), vec![], Type::get_unit()));
+    let mut builder = FunctionBuilder::new(program.func_mut(rec));
+    let entry = builder.create_bb(None);
+    builder.position_at_end(entry);
+    builder.call(rec, vec![]);
+    builder.ret(None);
+    let mut pass = GlobalDce::new();
+    pass.run_on(&mut program);
+    // dead declarations and functions called by dead functions are removed
+    assert_eq!(
+      pass.removed().funcs,
+      ["@putint", "@getint", "@even", "@odd", "@lib", "@rec"]
+    );
+    assert_eq!(
+      pass.removed().globals,
+      [Some("@a".into()), Some("@b".into())]
+    );
+    // initializers of the dead global allocations are also removed
+    assert_eq!(program.borrow_values().len(), 2);
+    assert_eq!(
+      dump(&program),
+      r#"global @c = alloc i32, zeroinit
+
+fun @main(): i32 {
+%entry:
+  %0 = load @c
+  ret %0
+}
+"#
+    );
+  }
+
+  #[test]
+  fn custom_roots() {
+    let driver: Driver<_> = SRC.into();
+    let mut program = driver.generate_program().unwrap();
+    let removed = GlobalDce::with_roots(vec!["@lib".into()]).run(&mut program);
+    assert_eq!(removed.funcs, ["@getint", "@even", "@odd", "@main"]);
+    assert_eq!(removed.globals, [Some("@b".into()), Some("@c".into())]);
+    let names: Vec<_> = program
+      .func_layout()
+      .iter()
+      .map(|f| program.func(*f).name().to_string())
+      .collect();
+    assert_eq!(names, ["@putint", "@lib"]);
+    // nothing is removed in the second run
+    assert_eq!(
+      GlobalDce::with_roots(vec!["@lib".into()]).run(&mut program),
+      Removed::default()
+    );
+  }
+}
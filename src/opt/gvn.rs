@@ -0,0 +1,343 @@
+//! Global value numbering pass ([`GlobalValueNumbering`]) related
+//! implementations.
+
+use crate::analysis::dom::DomTree;
+use crate::analysis::memory::MemoryInfo;
+use crate::ir::dfg::DataFlowGraph;
+use crate::ir::{BinaryOp, Function, FunctionData, Value, ValueKind};
+use crate::opt::rewrite::remove_dead;
+use crate::opt::FunctionPass;
+use std::collections::HashMap;
+
+/// A function pass that removes redundant instructions, which compute
+/// the same results as instructions in their dominators.
+///
+/// Basic blocks are visited in the preorder of the dominator tree (see
+/// [`DomTree`]), with a scoped table of available expressions. An
+/// instruction is redundant if an equivalent expression is available,
+/// all uses of it are replaced with the available one, and it is
+/// removed. Expressions defined in a basic block are available in the
+/// basic blocks it dominates, and are discarded when leaving them.
+///
+/// The following instructions are numbered:
+///
+/// * `binary`s, `getptr`s and `getelemptr`s, which are equivalent if
+///   they have the same operator and the same operands. Integer
+///   constants are compared by their values, and operands of
+///   commutative operators can be swapped.
+/// * `load`s, which are equivalent if they load from the same pointer.
+///   Loads are only available in the basic block they are in, until the
+///   next instruction that may clobber them (see
+///   [`MemoryInfo::clobbers`]), like a `store` to a pointer that may
+///   alias, or a `call`.
+///
+/// # Example
+///
+/// ```
+/// use koopa::front::Driver;
+/// use koopa::opt::*;
+///
+/// let driver: Driver<_> = r#"
+///   fun @f(@x: i32): i32 {
+///   %entry:
+///     %0 = add @x, 1
+///     br %0, %then, %end
+///
+///   %then:
+///     %1 = add 1, @x
+///     jump %end
+///
+///   %end:
+///     %2 = add @x, 1
+///     ret %2
+///   }
+/// "#
+/// .into();
+/// let mut program = driver.generate_program().unwrap();
+///
+/// let func = program.func_mut(program.func_layout()[0]);
+/// assert_eq!(GlobalValueNumbering::new().run(func), 2);
+/// ```
+#[derive(Default)]
+pub struct GlobalValueNumbering;
+
+impl GlobalValueNumbering {
+  /// Creates a new global value numbering pass.
+  pub fn new() -> Self {
+    Self
+  }
+
+  /// Runs the pass on the given function.
+  /// Returns the number of removed instructions.
+  pub fn run(&self, data: &mut FunctionData) -> usize {
+    let dom = DomTree::new(data);
+    let memory = MemoryInfo::new(data);
+    let mut available = HashMap::new();
+    let mut removed = 0;
+    // scopes of the visiting basic blocks, with expressions defined in
+    // them, use an explicit stack so deep trees will not overflow
+    let mut stack = Vec::new();
+    if let Some(root) = dom.root() {
+      stack.push((root, None));
+    }
+    while let Some((bb, defined)) = stack.pop() {
+      if let Some(defined) = defined {
+        // leave the scope
+        for expr in defined {
+          available.remove(&expr);
+        }
+        continue;
+      }
+      let mut defined = Vec::new();
+      let mut loads = HashMap::new();
+      let insts: Vec<_> = data
+        .layout()
+        .bbs()
+        .node(&bb)
+        .unwrap()
+        .insts()
+        .keys()
+        .copied()
+        .collect();
+      for inst in insts {
+        let dfg = data.dfg();
+        let found = match Expr::new(dfg, inst) {
+          Some(expr @ Expr::Load(_)) => lookup(&mut loads, expr, inst),
+          Some(expr) => {
+            let found = expr
+              .swapped()
+              .and_then(|e| available.get(&e).copied())
+              .or_else(|| lookup(&mut available, expr.clone(), inst));
+            if found.is_none() {
+              defined.push(expr);
+            }
+            found
+          }
+          None => {
+            loads.retain(|_, load| !memory.clobbers(inst, *load));
+            None
+          }
+        };
+        if let Some(value) = found {
+          data.dfg_mut().replace_all_uses_with(inst, value);
+          remove_dead(data, vec![inst]);
+          removed += 1;
+        }
+      }
+      stack.push((bb, Some(defined)));
+      stack.extend(dom.children(bb).iter().rev().map(|bb| (*bb, None)));
+    }
+    removed
+  }
+}
+
+impl FunctionPass for GlobalValueNumbering {
+  fn run_on(&mut self, _: Function, data: &mut FunctionData) {
+    self.run(data);
+  }
+
+  fn name(&self) -> &str {
+    "gvn"
+  }
+
+  fn fork(&self) -> Option<Box<dyn FunctionPass + Send>> {
+    Some(Box::new(Self))
+  }
+}
+
+/// Returns the value of the given expression in the given table,
+/// or inserts the given instruction as the value if it is absent.
+fn lookup(table: &mut HashMap<Expr, Value>, expr: Expr, inst: Value) -> Option<Value> {
+  match table.get(&expr) {
+    Some(value) => Some(*value),
+    None => {
+      table.insert(expr, inst);
+      None
+    }
+  }
+}
+
+/// An expression computed by an instruction.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Expr {
+  Binary(BinaryOp, Operand, Operand),
+  GetPtr(Operand, Operand),
+  GetElemPtr(Operand, Operand),
+  Load(Operand),
+}
+
+impl Expr {
+  /// Returns the expression of the given instruction,
+  /// or `None` if the instruction is not numbered.
+  fn new(dfg: &DataFlowGraph, inst: Value) -> Option<Self> {
+    let op = |value| Operand::new(dfg, value);
+    Some(match dfg.value(inst).kind() {
+      ValueKind::Binary(bin) => Expr::Binary(bin.op(), op(bin.lhs()), op(bin.rhs())),
+      ValueKind::GetPtr(gp) => Expr::GetPtr(op(gp.src()), op(gp.index())),
+      ValueKind::GetElemPtr(gep) => Expr::GetElemPtr(op(gep.src()), op(gep.index())),
+      ValueKind::Load(load) => Expr::Load(op(load.src())),
+      _ => return None,
+    })
+  }
+
+  /// Returns the expression with operands swapped, or `None` if the
+  /// expression is not commutative or the operands are the same.
+  fn swapped(&self) -> Option<Self> {
+    match self {
+      Expr::Binary(op, lhs, rhs) if op.is_commutative() && lhs != rhs => {
+        Some(Expr::Binary(*op, rhs.clone(), lhs.clone()))
+      }
+      _ => None,
+    }
+  }
+}
+
+/// An operand of expressions.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Operand {
+  Int(i32),
+  Value(Value),
+}
+
+impl Operand {
+  fn new(dfg: &DataFlowGraph, value: Value) -> Self {
+    match dfg.values().get(&value).and_then(|v| v.kind().as_integer()) {
+      Some(i) => Operand::Int(i),
+      None => Operand::Value(value),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::analysis::verifier::verify;
+  use crate::back::KoopaGenerator;
+  use crate::front::Driver;
+  use std::str;
+
+  fn gvn(src: &str) -> (usize, String) {
+    let driver: Driver<_> = src.into();
+    let mut program = driver.generate_program().unwrap();
+    let func = *program.func_layout().last().unwrap();
+    let removed = GlobalValueNumbering::new().run(program.func_mut(func));
+    assert_eq!(verify(&program), Ok(()));
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    (removed, str::from_utf8(&gen.writer()).unwrap().into())
+  }
+
+  #[test]
+  fn diamond() {
+    let (removed, text) = gvn(
+      r#"
+      fun @f(@x: i32, @y: i32): i32 {
+      %entry:
+        %a = mul @x, @y
+        br @x, %then, %else
+
+      %then:
+        %b = mul @y, @x
+        %c = add %b, 1
+        jump %end(%c)
+
+      %else:
+        %d = mul @x, @y
+        %e = add %d, 1
+        jump %end(%e)
+
+      %end(%p: i32):
+        %f = mul @x, @y
+        %g = add %f, 1
+        %h = add %p, %g
+        ret %h
+      }
+    "#,
+    );
+    // `%b`, `%d` and `%f` are replaced with `%a`, but the `add`s are
+    // kept, since neither of the arms dominates the others
+    assert_eq!(removed, 3);
+    assert_eq!(
+      text,
+      r#"fun @f(@x: i32, @y: i32): i32 {
+%entry:
+  %a = mul @x, @y
+  br @x, %then, %else
+
+%then:
+  %c = add %a, 1
+  jump %end(%c)
+
+%else:
+  %e = add %a, 1
+  jump %end(%e)
+
+%end(%p: i32):
+  %g = add %a, 1
+  %h = add %p, %g
+  ret %h
+}
+"#
+    );
+  }
+
+  #[test]
+  fn memory() {
+    let (removed, text) = gvn(
+      r#"
+      decl @g()
+
+      fun @f(@p: *i32): i32 {
+      %entry:
+        %a = load @p
+        %b = load @p
+        call @g()
+        %c = load @p
+        %d = add %a, %b
+        %e = add %d, %c
+        jump %next
+
+      %next:
+        %f = load @p
+        %g = add %e, %f
+        ret %g
+      }
+    "#,
+    );
+    // loads are invalidated by calls, and not available in other blocks
+    assert_eq!(removed, 1);
+    assert!(!text.contains("%b = load"));
+    assert!(text.contains("%d = add %a, %a"));
+    assert!(text.contains("%c = load") && text.contains("%f = load"));
+  }
+
+  #[test]
+  fn private_memory() {
+    let (removed, text) = gvn(
+      r#"
+      decl @g()
+
+      fun @f(): i32 {
+      %entry:
+        %x = alloc i32
+        %y = alloc i32
+        store 1, %x
+        %a = load %x
+        store 2, %y
+        call @g()
+        %b = load %x
+        store 3, %x
+        %c = load %x
+        %d = add %a, %b
+        %e = add %d, %c
+        ret %e
+      }
+    "#,
+    );
+    // `%x` does not escape, so only the store to `%x` invalidates `%a`
+    assert_eq!(removed, 1);
+    assert!(!text.contains("%b = load"));
+    assert!(text.contains("%d = add %a, %a"));
+    assert!(text.contains("%c = load %x"));
+  }
+}
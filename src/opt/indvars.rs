@@ -0,0 +1,503 @@
+//! Induction variable simplification pass ([`InductionVarSimplify`])
+//! related implementations.
+
+use crate::analysis::loops::{Loop, LoopInfo};
+use crate::ir::builder::FunctionBuilder;
+use crate::ir::dfg::DataFlowGraph;
+use crate::ir::{BasicBlock, BinaryOp, Function, FunctionData, OverflowMode, Value, ValueKind};
+use crate::opt::rewrite::remove_dead;
+use crate::opt::FunctionPass;
+
+/// A function pass that rewrites derived induction variables of loops
+/// into basic block parameters, which are updated by constant steps.
+///
+/// Loops are found by [`LoopInfo`]. A basic induction variable is a
+/// parameter of the loop header, which is updated by adding or
+/// subtracting a constant on the back edge. A derived induction
+/// variable is an instruction in the loop that computes an affine
+/// function of a basic induction variable `%i`:
+///
+/// * `mul %i, k` or `mul k, %i`, where `k` is a constant.
+/// * `getptr %p, %i` or `getelemptr %p, %i`, where `%p` is defined
+///   outside the loop.
+///
+/// Each derived induction variable is replaced with a new parameter of
+/// the header. The initial value is computed before entering the loop
+/// from the initial value of `%i`, and the value is increased by the
+/// scaled step on the back edge, by an `add` or a `getptr`:
+///
+/// ```koopa
+/// %header(%i: i32, %p: *i32):   // %p = getelemptr @arr, %i
+///   ...
+///   %i1 = add %i, 1
+///   %p1 = getptr %p, 1
+///   jump %header(%i1, %p1)
+/// ```
+///
+/// New parameters are basic induction variables too, so chains like
+/// `getptr @p, %j` where `%j = mul %i, 4` are rewritten one by one.
+///
+/// Only loops with exactly one edge from outside the loop and exactly
+/// one back edge are rewritten, other loops are left unchanged.
+/// `mul`s are only rewritten in [`OverflowMode::Wrapping`], since the
+/// accumulated results may overflow after the last iteration. Pointers
+/// are advanced after the last iteration too, so they may point past the
+/// end of the allocation when the loop exits, but will not be accessed.
+///
+/// By default, a derived induction variable is rewritten only if it is
+/// only used for address computations, that is, `mul`s are only used as
+/// indices of `getptr`s and `getelemptr`s, and pointers are only used
+/// as pointers of `load`s and `store`s, or sources of `getptr`s and
+/// `getelemptr`s. Use [`InductionVarSimplify::with_always`] to rewrite
+/// all derived induction variables.
+///
+/// # Example
+///
+/// ```
+/// use koopa::front::Driver;
+/// use koopa::opt::*;
+///
+/// let driver: Driver<_> = r#"
+///   global @arr = alloc [i32, 8], zeroinit
+///
+///   fun @sum(): i32 {
+///   %entry:
+///     jump %loop(0, 0)
+///
+///   %loop(%i: i32, %s: i32):
+///     %p = getelemptr @arr, %i
+///     %v = load %p
+///     %s1 = add %s, %v
+///     %i1 = add %i, 1
+///     %c = lt %i1, 8
+///     br %c, %loop(%i1, %s1), %end
+///
+///   %end:
+///     ret %s1
+///   }
+/// "#
+/// .into();
+/// let mut program = driver.generate_program().unwrap();
+///
+/// let func = program.func_mut(program.func_layout()[0]);
+/// assert_eq!(InductionVarSimplify::new().run(func), 1);
+/// ```
+#[derive(Clone, Default)]
+pub struct InductionVarSimplify {
+  always: bool,
+}
+
+/// Edges into the header of a loop.
+struct Edges {
+  header: BasicBlock,
+  /// Terminator of the only edge from outside the loop.
+  entry: Value,
+  /// Terminator of the only back edge.
+  latch: Value,
+}
+
+/// A derived induction variable.
+struct Derived {
+  inst: Value,
+  /// Index of the basic induction variable in the header parameters.
+  base: usize,
+  /// Step of the basic induction variable.
+  step: i32,
+  kind: DerivedKind,
+}
+
+/// Kind of derived induction variables.
+enum DerivedKind {
+  /// `mul %i, k`.
+  Scale(i32),
+  /// `getptr %p, %i`.
+  Ptr(Value),
+  /// `getelemptr %p, %i`.
+  ElemPtr(Value),
+}
+
+impl InductionVarSimplify {
+  /// Creates a new induction variable simplification pass, which only
+  /// rewrites derived induction variables used for address computations.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Sets whether to rewrite all derived induction variables, or only
+  /// the ones used for address computations.
+  pub fn with_always(mut self, always: bool) -> Self {
+    self.always = always;
+    self
+  }
+
+  /// Runs the pass on the given function.
+  /// Returns the number of rewritten derived induction variables.
+  pub fn run(&self, data: &mut FunctionData) -> usize {
+    let mut count = 0;
+    // rewriting only adds instructions and parameters,
+    // the control flow graph does not change
+    for lp in LoopInfo::new(data).loops() {
+      let Some(edges) = Edges::new(data, lp) else {
+        continue;
+      };
+      while let Some(derived) = self.find_derived(data, lp, &edges) {
+        rewrite(data, &edges, derived);
+        count += 1;
+      }
+    }
+    count
+  }
+
+  /// Finds the first derived induction variable that can be rewritten
+  /// in the given loop.
+  fn find_derived(&self, data: &FunctionData, lp: &Loop, edges: &Edges) -> Option<Derived> {
+    let dfg = data.dfg();
+    let params = dfg.bb(edges.header).params();
+    let latch_args = edge_args(dfg, edges.latch, edges.header);
+    // returns the index and the step of the given basic induction variable
+    let basic = |value: Value| {
+      let index = params.iter().position(|p| *p == value)?;
+      let next = latch_args[index];
+      data
+        .layout()
+        .parent_bb(next)
+        .filter(|bb| lp.contains(*bb))?;
+      let ValueKind::Binary(bin) = dfg.value(next).kind() else {
+        return None;
+      };
+      let step = match bin.op() {
+        BinaryOp::Add if bin.lhs() == value => dfg.const_int(bin.rhs())?,
+        BinaryOp::Add if bin.rhs() == value => dfg.const_int(bin.lhs())?,
+        BinaryOp::Sub if bin.lhs() == value => dfg.const_int(bin.rhs())?.checked_neg()?,
+        _ => return None,
+      };
+      Some((index, step))
+    };
+    let wrapping = dfg.overflow_mode() == OverflowMode::Wrapping;
+    for bb in lp.blocks() {
+      for &inst in data.layout().bbs().node(bb).unwrap().insts().keys() {
+        let (var, kind) = match dfg.value(inst).kind() {
+          ValueKind::Binary(bin) if bin.op() == BinaryOp::Mul && wrapping => {
+            match (dfg.const_int(bin.lhs()), dfg.const_int(bin.rhs())) {
+              (None, Some(k)) => (bin.lhs(), DerivedKind::Scale(k)),
+              (Some(k), None) => (bin.rhs(), DerivedKind::Scale(k)),
+              _ => continue,
+            }
+          }
+          ValueKind::GetPtr(gp) if is_invariant(data, lp, gp.src()) => {
+            (gp.index(), DerivedKind::Ptr(gp.src()))
+          }
+          ValueKind::GetElemPtr(gep) if is_invariant(data, lp, gep.src()) => {
+            (gep.index(), DerivedKind::ElemPtr(gep.src()))
+          }
+          _ => continue,
+        };
+        let Some((base, step)) = basic(var) else {
+          continue;
+        };
+        let users = dfg.value(inst).used_by();
+        if users.is_empty() || (!self.always && !users.iter().all(|u| is_address(dfg, inst, *u))) {
+          continue;
+        }
+        return Some(Derived {
+          inst,
+          base,
+          step,
+          kind,
+        });
+      }
+    }
+    None
+  }
+}
+
+impl FunctionPass for InductionVarSimplify {
+  fn run_on(&mut self, _: Function, data: &mut FunctionData) {
+    self.run(data);
+  }
+
+  fn name(&self) -> &str {
+    "indvars"
+  }
+
+  fn fork(&self) -> Option<Box<dyn FunctionPass + Send>> {
+    Some(Box::new(self.clone()))
+  }
+}
+
+impl Edges {
+  /// Returns the edges into the header of the given loop, or `None` if
+  /// the loop does not have exactly one entry edge and one back edge.
+  fn new(data: &FunctionData, lp: &Loop) -> Option<Self> {
+    let (dfg, layout) = (data.dfg(), data.layout());
+    let header = lp.header();
+    let [latch_bb] = lp.latches() else {
+      return None;
+    };
+    let mut entry = None;
+    let mut latch = None;
+    for &term in dfg.bb(header).used_by() {
+      let Some(bb) = layout.parent_bb(term) else {
+        continue;
+      };
+      // both edges of a branch can not target the header
+      let targets = dfg.value(term).kind().edges();
+      if targets.iter().filter(|(t, _)| *t == header).count() != 1 {
+        return None;
+      }
+      let slot = if bb == *latch_bb {
+        &mut latch
+      } else {
+        &mut entry
+      };
+      if slot.replace(term).is_some() || (lp.contains(bb) && bb != *latch_bb) {
+        return None;
+      }
+    }
+    Some(Self {
+      header,
+      entry: entry?,
+      latch: latch?,
+    })
+  }
+}
+
+/// Rewrites the given derived induction variable into a new parameter of
+/// the loop header.
+fn rewrite(data: &mut FunctionData, edges: &Edges, derived: Derived) {
+  let ty = data.dfg().value(derived.inst).ty().clone();
+  let init_var = edge_args(data.dfg(), edges.entry, edges.header)[derived.base];
+  let param = data.dfg_mut().add_bb_param(edges.header, ty);
+  let mut builder = FunctionBuilder::new(data);
+  // compute the initial value before entering the loop
+  builder.position_before(edges.entry);
+  let init = match derived.kind {
+    DerivedKind::Scale(k) => {
+      let k = builder.integer(k);
+      builder.binary_folded(BinaryOp::Mul, init_var, k)
+    }
+    DerivedKind::Ptr(src) => builder.get_ptr(src, init_var),
+    DerivedKind::ElemPtr(src) => builder.get_elem_ptr(src, init_var),
+  };
+  // advance the value on the back edge
+  builder.position_before(edges.latch);
+  let next = match derived.kind {
+    DerivedKind::Scale(k) => {
+      let step = builder.integer(k.wrapping_mul(derived.step));
+      builder.binary(BinaryOp::Add, param, step)
+    }
+    DerivedKind::Ptr(_) | DerivedKind::ElemPtr(_) => {
+      let step = builder.integer(derived.step);
+      builder.get_ptr(param, step)
+    }
+  };
+  // pass the values, and replace the derived induction variable
+  let dfg = data.dfg_mut();
+  let mut undef = None;
+  for (term, value) in [(edges.entry, init), (edges.latch, next)] {
+    let (index, mut args) = dfg
+      .value(term)
+      .kind()
+      .edges()
+      .into_iter()
+      .enumerate()
+      .find(|(_, (target, _))| *target == edges.header)
+      .map(|(i, (_, args))| (i, args.to_vec()))
+      .unwrap();
+    undef = args.pop();
+    args.push(value);
+    dfg.rewrite_edge(term, index, edges.header, args);
+  }
+  dfg.replace_all_uses_with(derived.inst, param);
+  remove_dead(data, vec![derived.inst]);
+  if let Some(undef) = undef {
+    if data.dfg().value(undef).used_by().is_empty() {
+      data.dfg_mut().remove_value(undef);
+    }
+  }
+}
+
+/// Returns the arguments passed to the given basic block by the given
+/// terminator.
+fn edge_args(dfg: &DataFlowGraph, term: Value, target: BasicBlock) -> Vec<Value> {
+  let edges = dfg.value(term).kind().edges();
+  let (_, args) = edges.into_iter().find(|(t, _)| *t == target).unwrap();
+  args.to_vec()
+}
+
+/// Returns `true` if the given value is defined outside the given loop.
+fn is_invariant(data: &FunctionData, lp: &Loop, value: Value) -> bool {
+  let dfg = data.dfg();
+  if !dfg.values().contains_key(&value) {
+    // global values
+    return true;
+  }
+  match data.layout().parent_bb(value) {
+    Some(bb) => !lp.contains(bb),
+    None => !lp
+      .blocks()
+      .iter()
+      .any(|bb| dfg.bb(*bb).params().contains(&value)),
+  }
+}
+
+/// Returns `true` if the given user uses the given value for address
+/// computations.
+fn is_address(dfg: &DataFlowGraph, value: Value, user: Value) -> bool {
+  match dfg.value(user).kind() {
+    ValueKind::GetPtr(gp) => gp.src() == value || gp.index() == value,
+    ValueKind::GetElemPtr(gep) => gep.src() == value || gep.index() == value,
+    ValueKind::Load(_) => true,
+    ValueKind::Store(store) => store.value() != value,
+    _ => false,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::analysis::verifier::verify;
+  use crate::back::KoopaGenerator;
+  use crate::front::Driver;
+  use std::str;
+
+  fn indvars(pass: InductionVarSimplify, src: &str) -> (usize, String) {
+    let driver: Driver<_> = src.into();
+    let mut program = driver.generate_program().unwrap();
+    let func = *program.func_layout().last().unwrap();
+    let count = pass.run(program.func_mut(func));
+    assert_eq!(verify(&program), Ok(()));
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    (count, str::from_utf8(&gen.writer()).unwrap().into())
+  }
+
+  #[test]
+  fn rewrite_chain() {
+    let (count, text) = indvars(
+      InductionVarSimplify::new(),
+      r#"
+      fun @f(@p: *i32, @n: i32, @init: i32): i32 {
+      %entry:
+        jump %loop(@init, 0)
+
+      %loop(%i: i32, %s: i32):
+        %j = mul %i, 4
+        %q = getptr @p, %j
+        %v = load %q
+        %s1 = add %s, %v
+        %i1 = sub %i, 1
+        %c = gt %i1, @n
+        br %c, %loop(%i1, %s1), %end
+
+      %end:
+        ret %s1
+      }
+    "#,
+    );
+    // `%j` is rewritten first, then `%q`
+    assert_eq!(count, 2);
+    assert_eq!(
+      text,
+      r#"fun @f(@p: *i32, @n: i32, @init: i32): i32 {
+%entry:
+  %0 = mul @init, 4
+  %1 = getptr @p, %0
+  jump %loop(@init, 0, %0, %1)
+
+%loop(%i: i32, %s: i32, %2: i32, %3: *i32):
+  %v = load %3
+  %s1 = add %s, %v
+  %i1 = sub %i, 1
+  %c = gt %i1, @n
+  %4 = add %2, -4
+  %5 = getptr %3, -4
+  br %c, %loop(%i1, %s1, %4, %5), %end
+
+%end:
+  ret %s1
+}
+"#
+    );
+  }
+
+  #[test]
+  fn profitability() {
+    let src = r#"
+      fun @f(@n: i32): i32 {
+      %entry:
+        jump %loop(0, 0)
+
+      %loop(%i: i32, %s: i32):
+        %j = mul %i, 3
+        %s1 = add %s, %j
+        %i1 = add %i, 1
+        %c = lt %i1, @n
+        br %c, %loop(%i1, %s1), %end
+
+      %end:
+        ret %s1
+      }
+    "#;
+    // `%j` is not used for address computations
+    assert_eq!(indvars(InductionVarSimplify::new(), src).0, 0);
+    let (count, text) = indvars(InductionVarSimplify::new().with_always(true), src);
+    assert_eq!(count, 1);
+    assert!(text.contains("jump %loop(0, 0, 0)"));
+    assert!(text.contains("%s1 = add %s, %0"));
+  }
+
+  #[test]
+  fn bail_out() {
+    // two back edges
+    let (count, _) = indvars(
+      InductionVarSimplify::new().with_always(true),
+      r#"
+      fun @f(@p: *i32, @n: i32) {
+      %entry:
+        jump %loop(0)
+
+      %loop(%i: i32):
+        %q = getptr @p, %i
+        store 0, %q
+        %i1 = add %i, 1
+        %c = lt %i1, @n
+        br %c, %again, %end
+
+      %again:
+        %d = eq %i1, 5
+        br %d, %loop(%i1), %skip
+
+      %skip:
+        %i2 = add %i1, 1
+        jump %loop(%i2)
+
+      %end:
+        ret
+      }
+    "#,
+    );
+    assert_eq!(count, 0);
+    // no basic induction variables
+    let (count, _) = indvars(
+      InductionVarSimplify::new().with_always(true),
+      r#"
+      fun @f(@p: *i32, @n: i32) {
+      %entry:
+        jump %loop(1)
+
+      %loop(%i: i32):
+        %q = getptr @p, %i
+        store 0, %q
+        %i1 = mul %i, 2
+        %c = lt %i1, @n
+        br %c, %loop(%i1), %end
+
+      %end:
+        ret
+      }
+    "#,
+    );
+    assert_eq!(count, 0);
+  }
+}
@@ -0,0 +1,273 @@
+//! Loop invariant code motion pass ([`LoopInvariantMotion`]) related
+//! implementations.
+
+use crate::analysis::cfg;
+use crate::analysis::loops::{Loop, LoopInfo};
+use crate::ir::{BinaryOp, Error, Function, FunctionData, OverflowMode, Value, ValueKind};
+use crate::opt::FunctionPass;
+use std::collections::HashSet;
+
+/// A function pass that hoists loop invariant instructions out of
+/// loops.
+///
+/// Loops are found by [`LoopInfo`]. An instruction in a loop is
+/// invariant if all of its operands are defined outside the loop, or
+/// are invariant instructions that have been hoisted. Only instructions
+/// without side effects that never trap are hoisted: `getptr`s,
+/// `getelemptr`s, and binary operations other than `div` and `mod`.
+/// Binary operations are not hoisted in [`OverflowMode::Trap`].
+///
+/// Invariant instructions are moved to the end of the only predecessor
+/// of the loop header outside the loop, before its terminator. Loops
+/// with more than one edge from outside the loop are left unchanged.
+/// Inner loops are processed before outer loops, so instructions that
+/// are invariant in several nested loops are hoisted out of all of them.
+///
+/// Hoisting is done by [`FunctionData::try_move_inst`], so instructions
+/// are not hoisted if the move violates an ordering constraint (see
+/// [`FunctionData::try_add_order_constraint`]).
+///
+/// # Example
+///
+/// ```
+/// use koopa::front::Driver;
+/// use koopa::opt::*;
+///
+/// let driver: Driver<_> = r#"
+///   fun @f(@n: i32, @k: i32): i32 {
+///   %entry:
+///     jump %loop(0, 0)
+///
+///   %loop(%i: i32, %s: i32):
+///     %k2 = mul @k, 2
+///     %s1 = add %s, %k2
+///     %i1 = add %i, 1
+///     %c = lt %i1, @n
+///     br %c, %loop(%i1, %s1), %end
+///
+///   %end:
+///     ret %s1
+///   }
+/// "#
+/// .into();
+/// let mut program = driver.generate_program().unwrap();
+///
+/// let func = program.func_mut(program.func_layout()[0]);
+/// assert_eq!(LoopInvariantMotion::new().run(func), 1);
+/// // `%k2` is hoisted to the entry basic block
+/// let entry = func.layout().entry_bb().unwrap();
+/// assert_eq!(func.layout().bbs().node(&entry).unwrap().insts().len(), 2);
+/// ```
+#[derive(Clone, Default)]
+pub struct LoopInvariantMotion;
+
+impl LoopInvariantMotion {
+  /// Creates a new loop invariant code motion pass.
+  pub fn new() -> Self {
+    Self
+  }
+
+  /// Runs the pass on the given function.
+  /// Returns the number of hoisted instructions.
+  pub fn run(&self, data: &mut FunctionData) -> usize {
+    // hoisting only moves instructions,
+    // the control flow graph does not change
+    let info = LoopInfo::new(data);
+    let preds = cfg::preds_map(data);
+    let mut count = 0;
+    for lp in info.loops().iter().rev() {
+      let mut outside = preds[&lp.header()].iter().filter(|bb| !lp.contains(**bb));
+      let (Some(pre), None) = (outside.next(), outside.next()) else {
+        continue;
+      };
+      let term = *data
+        .layout()
+        .bbs()
+        .node(pre)
+        .unwrap()
+        .insts()
+        .back_key()
+        .unwrap();
+      count += hoist(data, lp, term);
+    }
+    count
+  }
+}
+
+impl FunctionPass for LoopInvariantMotion {
+  fn run_on(&mut self, _: Function, data: &mut FunctionData) {
+    self.run(data);
+  }
+
+  fn name(&self) -> &str {
+    "licm"
+  }
+
+  fn fork(&self) -> Option<Box<dyn FunctionPass + Send>> {
+    Some(Box::new(self.clone()))
+  }
+}
+
+/// Hoists invariant instructions of the given loop to the position
+/// before the given terminator until the fixpoint.
+/// Returns the number of hoisted instructions.
+fn hoist(data: &mut FunctionData, lp: &Loop, term: Value) -> usize {
+  let mut declined = HashSet::new();
+  let mut count = 0;
+  loop {
+    let insts: Vec<_> = lp
+      .blocks()
+      .iter()
+      .flat_map(|bb| {
+        data
+          .layout()
+          .bbs()
+          .node(bb)
+          .unwrap()
+          .insts()
+          .keys()
+          .copied()
+      })
+      .filter(|inst| !declined.contains(inst) && is_hoistable(data, lp, *inst))
+      .collect();
+    if insts.is_empty() {
+      return count;
+    }
+    for inst in insts {
+      match data.try_move_inst(inst, term) {
+        Ok(()) => count += 1,
+        Err(Error::OrderViolation { .. }) => {
+          declined.insert(inst);
+        }
+        Err(e) => panic!("{e}"),
+      }
+    }
+  }
+}
+
+/// Returns `true` if the given instruction can be hoisted out of the
+/// given loop.
+fn is_hoistable(data: &FunctionData, lp: &Loop, inst: Value) -> bool {
+  let value = data.dfg().value(inst);
+  let pure = match value.kind() {
+    ValueKind::GetPtr(_) | ValueKind::GetElemPtr(_) => true,
+    ValueKind::Binary(bin) => {
+      !matches!(bin.op(), BinaryOp::Div | BinaryOp::Mod)
+        && data.dfg().overflow_mode() != OverflowMode::Trap
+    }
+    _ => false,
+  };
+  pure && value.kind().value_uses().all(|v| is_invariant(data, lp, v))
+}
+
+/// Returns `true` if the given value is defined outside the given loop.
+fn is_invariant(data: &FunctionData, lp: &Loop, value: Value) -> bool {
+  let dfg = data.dfg();
+  if !dfg.values().contains_key(&value) {
+    // global values
+    return true;
+  }
+  match data.layout().parent_bb(value) {
+    Some(bb) => !lp.contains(bb),
+    None => !lp
+      .blocks()
+      .iter()
+      .any(|bb| dfg.bb(*bb).params().contains(&value)),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::analysis::verifier::verify;
+  use crate::diagnostic::code;
+  use crate::front::Driver;
+  use crate::text::print_program;
+
+  #[test]
+  fn hoist_with_constraints() {
+    let driver: Driver<_> = r#"
+      decl @use(*i32)
+
+      fun @f(@n: i32) {
+      %entry:
+        %a = alloc [i32, 4]
+        jump %loop(0)
+
+      %loop(%i: i32):
+        store zeroinit, %a
+        %p = getelemptr %a, 0
+        %q = getptr %p, 1
+        call @use(%q)
+        %d = div @n, 2
+        %i1 = add %i, 1
+        %c = lt %i1, @n
+        br %c, %loop(%i1), %end
+
+      %end:
+        ret
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    let func = program.func_layout()[1];
+    let data = program.func_mut(func);
+    let inst = |data: &FunctionData, name: &str| {
+      let name = Some(name.to_string());
+      let (inst, _) = data
+        .dfg()
+        .values()
+        .iter()
+        .find(|(_, v)| *v.name() == name)
+        .unwrap();
+      *inst
+    };
+    // the backend requires the store to precede address computations
+    let store = data
+      .dfg()
+      .values()
+      .iter()
+      .find(|(_, v)| matches!(v.kind(), ValueKind::Store(_)))
+      .map(|(v, _)| *v)
+      .unwrap();
+    let p = inst(data, "%p");
+    data.add_order_constraint(store, p);
+    // `%p` is declined, so `%q` that uses it stays too,
+    // and `div` is never hoisted
+    assert_eq!(LoopInvariantMotion::new().run(data), 0);
+    assert!(verify(&program).is_ok());
+
+    // without the constraint, `%p` and `%q` are hoisted
+    let data = program.func_mut(func);
+    assert!(data.remove_order_constraint(store, p));
+    assert_eq!(LoopInvariantMotion::new().run(data), 2);
+    let text = print_program(&program);
+    assert!(text.contains("%a = alloc [i32, 4]\n  %p = getelemptr %a, 0\n  %q = getptr %p, 1\n"));
+
+    // the store in the loop does not dominate the entry basic block
+    let data = program.func_mut(func);
+    let q = inst(data, "%q");
+    data.add_order_constraint(store, q);
+    assert!(verify(&program).is_err_and(|e| e[0].code == code::ORDER_VIOLATION));
+    let data = program.func_mut(func);
+    assert!(data.remove_order_constraint(store, q));
+    // moving `%p` back into the loop after the store is refused,
+    // and the layout is unchanged
+    data.add_order_constraint(p, store);
+    let d = inst(data, "%d");
+    assert_eq!(
+      data.try_move_inst(p, d),
+      Err(Error::OrderViolation {
+        before: p,
+        after: store
+      })
+    );
+    assert_eq!(print_program(&program), text);
+    // constraints of removed instructions are dropped
+    let data = program.func_mut(func);
+    data.add_order_constraint(d, store);
+    assert_eq!(data.order_constraints().count(), 2);
+    data.remove_inst(d);
+    assert_eq!(data.order_constraints().collect::<Vec<_>>(), [(p, store)]);
+  }
+}
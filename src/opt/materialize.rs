@@ -0,0 +1,352 @@
+//! Constant materialization ([`materialize_constants`]) related
+//! implementations.
+
+use crate::analysis::dom::DomTree;
+use crate::ir::builder_traits::*;
+use crate::ir::dfg::DataFlowGraph;
+use crate::ir::{BasicBlock, BinaryOp, FunctionData, Value};
+use std::collections::HashMap;
+
+/// Key of the attribute of instructions created by
+/// [`materialize_constants`], the attribute value is always `true`.
+pub const MATERIALIZED_ATTR: &str = "materialized";
+
+/// Configuration of [`materialize_constants`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MaterializeConfig {
+  min_imm: i32,
+  max_imm: i32,
+  across_bbs: bool,
+}
+
+impl MaterializeConfig {
+  /// Creates a new configuration, in which integers that fit in 12-bit
+  /// signed immediates (`-2048..=2047`) stay inline, and constants are
+  /// only shared in basic blocks.
+  pub fn new() -> Self {
+    Self {
+      min_imm: -2048,
+      max_imm: 2047,
+      across_bbs: false,
+    }
+  }
+
+  /// Sets the range of integers that stay inline, including `min` and
+  /// `max`.
+  pub fn with_imm_range(mut self, min: i32, max: i32) -> Self {
+    self.min_imm = min;
+    self.max_imm = max;
+    self
+  }
+
+  /// Sets whether constants used in different basic blocks are shared,
+  /// by defining them in the nearest common dominator of the uses.
+  pub fn with_across_bbs(mut self, across_bbs: bool) -> Self {
+    self.across_bbs = across_bbs;
+    self
+  }
+
+  /// Returns `true` if the given integer stays inline.
+  pub fn is_inline(&self, imm: i32) -> bool {
+    (self.min_imm..=self.max_imm).contains(&imm)
+  }
+}
+
+impl Default for MaterializeConfig {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Materializes large integer constants in the given function as
+/// instructions, for backends that can only encode small immediates.
+/// Returns the number of created instructions.
+///
+/// Integers outside the inline range of the configuration (see
+/// [`MaterializeConfig::with_imm_range`]), that are used more than once
+/// in a basic block, by the same or different constants, get a single
+/// defining instruction:
+///
+/// ```text
+/// %0 = add 0, 100000
+/// ```
+///
+/// which is inserted before the first use in the basic block, and all
+/// uses of the integer in the basic block are replaced with it.
+/// Uses count operands, so `mul 100000, 100000` also materializes the
+/// constant. The instructions have the attribute [`MATERIALIZED_ATTR`]
+/// set to `true`, so backends can emit them as `lui`/`addi` sequences
+/// instead of additions. Constants that become unused are removed.
+/// Constant folding passes may fold the instructions back, so run this
+/// after all optimizations.
+///
+/// If the configuration shares constants across basic blocks (see
+/// [`MaterializeConfig::with_across_bbs`]), all uses of the integer in
+/// the function are replaced with a single instruction instead,
+/// which is inserted in the nearest common dominator of the uses:
+/// before the first use if the dominator uses the integer, or before
+/// its terminator otherwise. Uses in unreachable basic blocks are not
+/// changed.
+///
+/// All operand positions of instructions are considered, including
+/// arguments of calls and basic block targets. Elements of aggregates
+/// are never changed, since they must be constants, and neither are
+/// initializers of global allocations, which are not in functions.
+///
+/// # Example
+///
+/// ```
+/// use koopa::front::Driver;
+/// use koopa::opt::{materialize_constants, MaterializeConfig};
+///
+/// let driver: Driver<_> = r#"
+///   fun @f(@x: i32): i32 {
+///   %entry:
+///     %0 = add @x, 100000
+///     %1 = mul %0, 100000
+///     %2 = add %1, 1
+///     ret %2
+///   }
+/// "#
+/// .into();
+/// let mut program = driver.generate_program().unwrap();
+/// let func = program.func_mut(program.func_layout()[0]);
+/// // `100000` is materialized, and `1` stays inline
+/// assert_eq!(materialize_constants(func, &MaterializeConfig::new()), 1);
+/// let entry = func.layout().entry_bb().unwrap();
+/// assert_eq!(func.layout().bbs().node(&entry).unwrap().insts().len(), 5);
+/// ```
+pub fn materialize_constants(func: &mut FunctionData, config: &MaterializeConfig) -> usize {
+  // uses of large integers in the layout order,
+  // as basic blocks, instructions and operand indices
+  let mut consts = Vec::new();
+  let mut uses: HashMap<_, Vec<_>> = HashMap::new();
+  for (&bb, node) in func.layout().bbs() {
+    for &inst in node.insts().keys() {
+      for (i, value) in func.dfg().value(inst).kind().value_uses().enumerate() {
+        if let Some(n) = large_integer(func.dfg(), config, value) {
+          uses
+            .entry(n)
+            .or_insert_with(|| {
+              consts.push(n);
+              Vec::new()
+            })
+            .push((bb, inst, i));
+        }
+      }
+    }
+  }
+  let mut count = 0;
+  if config.across_bbs {
+    let dom = DomTree::new(func);
+    for n in consts {
+      let mut uses = uses.remove(&n).unwrap();
+      uses.retain(|(bb, _, _)| dom.contains(*bb));
+      if uses.len() > 1 {
+        let bb = uses
+          .iter()
+          .map(|(bb, _, _)| *bb)
+          .reduce(|a, b| common_dominator(&dom, a, b))
+          .unwrap();
+        let pos = match uses.iter().find(|(use_bb, _, _)| *use_bb == bb) {
+          Some((_, inst, _)) => *inst,
+          None => *func
+            .layout()
+            .bbs()
+            .node(&bb)
+            .unwrap()
+            .insts()
+            .back_key()
+            .unwrap(),
+        };
+        materialize(func, n, pos, &uses);
+        count += 1;
+      }
+    }
+  } else {
+    for n in consts {
+      let mut uses = uses.remove(&n).unwrap();
+      while !uses.is_empty() {
+        // uses are grouped by basic blocks in the layout order
+        let bb = uses[0].0;
+        let len = uses
+          .iter()
+          .take_while(|(use_bb, _, _)| *use_bb == bb)
+          .count();
+        let bb_uses: Vec<_> = uses.drain(..len).collect();
+        if bb_uses.len() > 1 {
+          materialize(func, n, bb_uses[0].1, &bb_uses);
+          count += 1;
+        }
+      }
+    }
+  }
+  count
+}
+
+/// Returns the integer if the given value is an integer constant that
+/// can not stay inline.
+fn large_integer(dfg: &DataFlowGraph, config: &MaterializeConfig, value: Value) -> Option<i32> {
+  dfg
+    .values()
+    .get(&value)
+    .and_then(|data| data.kind().as_integer())
+    .filter(|n| !config.is_inline(*n))
+}
+
+/// Returns the nearest common dominator of the given basic blocks,
+/// which must be in the given dominator tree.
+fn common_dominator(dom: &DomTree, mut a: BasicBlock, b: BasicBlock) -> BasicBlock {
+  while !dom.dominates(a, b) {
+    a = dom.idom(a).unwrap();
+  }
+  a
+}
+
+/// Inserts the definition of the given integer before the given
+/// instruction, and replaces the given uses with the definition.
+/// Constants that become unused are removed.
+fn materialize(func: &mut FunctionData, n: i32, pos: Value, uses: &[(BasicBlock, Value, usize)]) {
+  let dfg = func.dfg_mut();
+  let zero = dfg.new_value().integer(0);
+  let value = dfg.new_value().integer(n);
+  let def = dfg.new_value().binary(BinaryOp::Add, zero, value);
+  dfg.set_value_attr(def, MATERIALIZED_ATTR, true.into());
+  for &(_, inst, index) in uses {
+    let old = dfg.set_value_operand(inst, index, def);
+    if dfg.value(old).used_by().is_empty() {
+      dfg.remove_value(old);
+    }
+  }
+  let bb = func.layout().parent_bb(pos).unwrap();
+  let mut cursor = func.layout_mut().bb_mut(bb).insts_mut().cursor_mut(pos);
+  cursor.insert_key_before(def).unwrap();
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::analysis::verifier::verify;
+  use crate::back::KoopaGenerator;
+  use crate::front::Driver;
+  use crate::ir::{AttrValue, Program};
+  use std::str;
+
+  const SRC: &str = r#"
+    global @g = alloc i32, 100000
+
+    fun @f(@x: i32): i32 {
+    %entry:
+      %a = add @x, 100000
+      %c = lt %a, 100000
+      br %c, %then, %else
+
+    %then:
+      %b = mul %a, 70000
+      jump %end(%b, 70000)
+
+    %else:
+      %d = add %a, 70000
+      jump %end(70000, %d)
+
+    %end(%p: i32, %q: i32):
+      %s = add %p, %q
+      store %s, @g
+      %r = load @g
+      ret %r
+    }
+  "#;
+
+  /// Materializes constants in `SRC`, returns the number of created
+  /// instructions and the text form of the program.
+  fn materialize(config: MaterializeConfig) -> (usize, Program, String) {
+    let driver: Driver<_> = SRC.into();
+    let mut program = driver.generate_program().unwrap();
+    let func = program.func_layout()[0];
+    let count = materialize_constants(program.func_mut(func), &config);
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    let text = str::from_utf8(&gen.writer()).unwrap().to_string();
+    (count, program, text)
+  }
+
+  #[test]
+  fn materialize_in_bbs() {
+    let (count, program, text) = materialize(MaterializeConfig::new());
+    assert_eq!(count, 3);
+    // the global initializer is never changed,
+    // and the branch arguments are replaced
+    assert_eq!(
+      text,
+      r#"global @g = alloc i32, 100000
+
+fun @f(@x: i32): i32 {
+%entry:
+  %0 = add 0, 100000
+  %a = add @x, %0
+  %c = lt %a, %0
+  br %c, %then, %else
+
+%then:
+  %1 = add 0, 70000
+  %b = mul %a, %1
+  jump %end(%b, %1)
+
+%else:
+  %2 = add 0, 70000
+  %d = add %a, %2
+  jump %end(%2, %d)
+
+%end(%p: i32, %q: i32):
+  %s = add %p, %q
+  store %s, @g
+  %r = load @g
+  ret %r
+}
+"#
+    );
+    // the old constants are removed
+    let func = program.func(program.func_layout()[0]);
+    let values = func.dfg().values().values();
+    let large = values.filter(|v| v.kind().as_integer().is_some_and(|n| n > 2047));
+    assert_eq!(large.count(), 3);
+    let attr = AttrValue::Bool(true);
+    let defs = func.dfg().values().values();
+    assert_eq!(
+      defs
+        .filter(|v| v.attr(MATERIALIZED_ATTR) == Some(&attr))
+        .count(),
+      3
+    );
+    assert!(verify(&program).is_ok());
+  }
+
+  #[test]
+  fn materialize_across_bbs() {
+    let (count, program, text) = materialize(MaterializeConfig::new().with_across_bbs(true));
+    assert_eq!(count, 2);
+    // `70000` is not used in the entry basic block,
+    // so its definition is inserted before the terminator
+    assert!(text.contains(
+      r#"%entry:
+  %0 = add 0, 100000
+  %a = add @x, %0
+  %c = lt %a, %0
+  %1 = add 0, 70000
+  br %c, %then, %else
+
+%then:
+  %b = mul %a, %1
+  jump %end(%b, %1)
+
+%else:
+  %d = add %a, %1
+  jump %end(%1, %d)
+"#
+    ));
+    assert!(verify(&program).is_ok());
+    // all integers stay inline
+    let config = MaterializeConfig::new().with_imm_range(i32::MIN, i32::MAX);
+    assert_eq!(materialize(config.with_across_bbs(true)).0, 0);
+  }
+}
@@ -7,6 +7,30 @@
 //!   function pass trait ([`FunctionPass`](pass::FunctionPass)).
 //! * The pass manager ([`PassManager`]) that holds all registered passes,
 //!   and uses them to optimize the given Koopa IR program.
+//! * The rewriter trait ([`Rewriter`]) for peephole optimizations, and
+//!   the rewrite pass ([`RewritePass`]) that applies rewriters to
+//!   functions until the fixpoint.
+//! * Built-in passes, like the algebraic simplification pass
+//!   ([`AlgebraicSimplify`]), the pointer calculation canonicalization
+//!   pass ([`PtrCanonicalize`]), the dead function and global value
+//!   elimination pass ([`GlobalDce`]), the dead argument elimination
+//!   pass ([`DeadArgElimination`]), the global value numbering pass
+//!   ([`GlobalValueNumbering`]), the return value propagation pass
+//!   ([`ReturnPropagation`]), the loop unrolling pass
+//!   ([`LoopUnroll`]), the induction variable simplification pass
+//!   ([`InductionVarSimplify`]), the loop invariant code motion pass
+//!   ([`LoopInvariantMotion`]), the function specialization pass
+//!   ([`FunctionSpecialization`]), the profiling instrumentation pass
+//!   ([`ProfileInstrument`]), the bounds check instrumentation pass
+//!   ([`BoundsCheck`]) and the name normalization pass
+//!   ([`NormalizeNames`]).
+//! * Transformations that are not passes, like outlining single-entry
+//!   regions of basic blocks into new functions ([`extract_region`]),
+//!   flattening stores of composite constants into stores of scalar
+//!   constants ([`flatten_operands`]), and materializing large integer
+//!   constants as instructions ([`materialize_constants`]).
+//! * Analyses, like the interprocedural function purity analysis
+//!   ([`PurityInfo`]).
 //!
 //! # Example
 //!
@@ -37,8 +61,44 @@
 //! passman.run_passes(&mut program);
 //! ```
 
+mod bounds_check;
+mod dead_args;
+mod flatten;
+mod global_dce;
+mod gvn;
+mod indvars;
+mod licm;
+mod materialize;
+mod normalize_names;
+mod outline;
 mod pass;
 mod passman;
+mod profile;
+mod ptr_canon;
+mod purity;
+mod ret_prop;
+mod rewrite;
+mod simplify;
+mod specialize;
+mod unroll;
 
+pub use bounds_check::{BoundsCheck, OutOfBounds};
+pub use dead_args::DeadArgElimination;
+pub use flatten::flatten_operands;
+pub use global_dce::{GlobalDce, Removed};
+pub use gvn::GlobalValueNumbering;
+pub use indvars::InductionVarSimplify;
+pub use licm::LoopInvariantMotion;
+pub use materialize::{materialize_constants, MaterializeConfig, MATERIALIZED_ATTR};
+pub use normalize_names::{NameStyle, NormalizeNames};
+pub use outline::{extract_region, ExtractError};
 pub use pass::*;
-pub use passman::PassManager;
+pub use passman::{PassManager, PassRecord};
+pub use profile::{CounterStyle, ProfileInstrument, ProfiledBlock};
+pub use ptr_canon::PtrCanonicalize;
+pub use purity::{Purity, PurityInfo};
+pub use ret_prop::ReturnPropagation;
+pub use rewrite::{Rewrite, RewritePass, Rewriter};
+pub use simplify::AlgebraicSimplify;
+pub use specialize::{FunctionSpecialization, SPECIALIZED_ATTR};
+pub use unroll::LoopUnroll;
@@ -0,0 +1,292 @@
+//! Name normalization pass ([`NormalizeNames`]) related implementations.
+
+use crate::ir::{FunctionData, Program};
+use crate::opt::ModulePass;
+use std::collections::HashSet;
+
+/// How [`NormalizeNames`] renames basic blocks and local values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NameStyle {
+  /// Removes all names, so the generator numbers all basic blocks and
+  /// local values as temporaries.
+  Strip,
+  /// Keeps names, but removes the `_N` suffixes added by copying basic
+  /// blocks or values with the same names, like `%loop_0_1`. Names that
+  /// are still not unique get one new suffix, so the first `%loop` of a
+  /// function is named `%loop`, and the following ones are named
+  /// `%loop_1`, `%loop_2` and so on.
+  #[default]
+  Compact,
+  /// Prefixes all names with the names of their functions, like
+  /// `%main_entry` for `%entry` in function `@main`. Names are unique in
+  /// the whole program instead of in their functions.
+  FuncPrefix,
+}
+
+/// A module pass that renames basic blocks and local values of all
+/// functions, see [`NameStyle`] for details.
+///
+/// Names of functions and global allocations are never changed. Apart
+/// from [`NameStyle::Strip`], the new names of basic blocks are unique
+/// among basic blocks, and the new names of local values are unique
+/// among local values, functions and global allocations, so the
+/// generator does not add suffixes to them, and the generated text form
+/// can be parsed back. Numbered names like `%0` are removed, since they
+/// may conflict with temporaries. Basic blocks and local values without
+/// names stay unnamed.
+///
+/// Function parameters, basic block parameters and instructions are
+/// renamed in the layout order.
+///
+/// # Example
+///
+/// ```
+/// use koopa::front::Driver;
+/// use koopa::opt::*;
+///
+/// let driver: Driver<_> = r#"
+///   fun @f(@x_1: i32): i32 {
+///   %loop_0_1:
+///     ret @x_1
+///   }
+/// "#
+/// .into();
+/// let mut program = driver.generate_program().unwrap();
+///
+/// NormalizeNames::new().run(&mut program);
+/// let func = program.func(program.func_layout()[0]);
+/// let entry = func.layout().entry_bb().unwrap();
+/// assert_eq!(func.dfg().bb(entry).name().as_deref(), Some("%loop"));
+/// let param = func.dfg().value(func.params()[0]);
+/// assert_eq!(param.name().as_deref(), Some("@x"));
+/// ```
+pub struct NormalizeNames {
+  style: NameStyle,
+}
+
+impl NormalizeNames {
+  /// Creates a new pass with the default name style.
+  pub fn new() -> Self {
+    Self::with_style(NameStyle::default())
+  }
+
+  /// Creates a new pass with the given name style.
+  pub fn with_style(style: NameStyle) -> Self {
+    Self { style }
+  }
+
+  /// Runs the pass on the given program.
+  pub fn run(&self, program: &mut Program) {
+    let mut globals: HashSet<String> = program.funcs().values().map(|f| f.name().into()).collect();
+    globals.extend(
+      program
+        .inst_layout()
+        .iter()
+        .filter_map(|v| program.borrow_value(*v).name().clone()),
+    );
+    // sets of used basic block names and local value names
+    let mut bb_names = HashSet::new();
+    let mut value_names = globals.clone();
+    let funcs = program.func_layout().to_vec();
+    for func in funcs {
+      if self.style != NameStyle::FuncPrefix {
+        bb_names.clear();
+        value_names.clone_from(&globals);
+      }
+      let data = program.func_mut(func);
+      self.rename(data, &mut bb_names, &mut value_names);
+    }
+  }
+
+  /// Renames basic blocks and local values of the given function.
+  fn rename(
+    &self,
+    data: &mut FunctionData,
+    bb_names: &mut HashSet<String>,
+    value_names: &mut HashSet<String>,
+  ) {
+    let prefix = &data.name()[1..];
+    let style = self.style;
+    let new_name = |name: &Option<String>, names: &mut HashSet<String>| {
+      let name = name.as_deref().filter(|n| !is_numbered(n))?;
+      let base = match style {
+        NameStyle::Strip => return None,
+        NameStyle::Compact => strip_suffixes(name).to_string(),
+        NameStyle::FuncPrefix => format!("{}{prefix}_{}", &name[..1], &name[1..]),
+      };
+      Some(unique_name(names, base))
+    };
+    let bbs: Vec<_> = data.layout().bbs().keys().copied().collect();
+    let mut values = data.params().to_vec();
+    for &bb in &bbs {
+      values.extend(data.dfg().bb(bb).params());
+      let node = data.layout().bbs().node(&bb).unwrap();
+      values.extend(node.insts().keys());
+    }
+    let bb_names: Vec<_> = bbs
+      .iter()
+      .map(|bb| new_name(data.dfg().bb(*bb).name(), bb_names))
+      .collect();
+    let value_names: Vec<_> = values
+      .iter()
+      .map(|v| new_name(data.dfg().value(*v).name(), value_names))
+      .collect();
+    let dfg = data.dfg_mut();
+    for (bb, name) in bbs.into_iter().zip(bb_names) {
+      dfg.bb_mut(bb).set_name(name);
+    }
+    for (value, name) in values.into_iter().zip(value_names) {
+      dfg.set_value_name(value, name);
+    }
+  }
+}
+
+impl Default for NormalizeNames {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl ModulePass for NormalizeNames {
+  fn run_on(&mut self, program: &mut Program) {
+    self.run(program);
+  }
+
+  fn name(&self) -> &str {
+    "normalize-names"
+  }
+}
+
+/// Returns `true` if the given symbol is a numbered name like `%0`.
+fn is_numbered(name: &str) -> bool {
+  name[1..].chars().all(|c| c.is_ascii_digit())
+}
+
+/// Removes all trailing `_N` suffixes of the given symbol.
+fn strip_suffixes(mut name: &str) -> &str {
+  while let Some((base, suffix)) = name.rsplit_once('_') {
+    if base.len() <= 1 || suffix.is_empty() || !suffix.chars().all(|c| c.is_ascii_digit()) {
+      break;
+    }
+    name = base;
+  }
+  name
+}
+
+/// Returns the given name if it is not used, or the name with the
+/// smallest unused `_N` suffix, and records the returned name.
+fn unique_name(names: &mut HashSet<String>, base: String) -> String {
+  let name = if names.contains(&base) {
+    (1..)
+      .map(|i| format!("{base}_{i}"))
+      .find(|n| !names.contains(n))
+      .unwrap()
+  } else {
+    base
+  };
+  names.insert(name.clone());
+  name
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::opt::{LoopUnroll, Pass, PassManager};
+  use crate::text::{parse_text, print_program};
+
+  // names with suffixes added by previous copies
+  const SRC: &str = r#"global @g = alloc i32, 0
+
+fun @g_x(): i32 {
+%entry:
+  ret 0
+}
+
+fun @main(): i32 {
+%entry:
+  jump %loop_1(0, 0)
+
+%loop_1(%i: i32, %sum: i32):
+  %c = lt %i, 3
+  br %c, %body_0_1, %end
+
+%body_0_1:
+  %x_2 = add %sum, %i
+  %i_0 = add %i, 1
+  jump %loop_1(%i_0, %x_2)
+
+%end:
+  ret %sum
+}
+"#;
+
+  /// Unrolls the loop in `SRC`, and then normalizes the names.
+  fn unroll_and_normalize(style: Option<NameStyle>) -> String {
+    let mut program = parse_text(SRC).unwrap();
+    let mut passman = PassManager::new();
+    passman.register(Pass::Function(Box::new(LoopUnroll::new())));
+    if let Some(style) = style {
+      passman.register(Pass::Module(Box::new(NormalizeNames::with_style(style))));
+    }
+    passman.run_passes(&mut program);
+    let text = print_program(&program);
+    assert!(parse_text(&text).is_ok());
+    text
+  }
+
+  #[test]
+  fn normalize_after_unroll() {
+    // the generator adds more suffixes to the copies
+    assert!(unroll_and_normalize(None).contains("%body_0_1_0:"));
+    let compact = unroll_and_normalize(Some(NameStyle::Compact));
+    assert_eq!(
+      compact,
+      r#"global @g = alloc i32, 0
+
+fun @g_x(): i32 {
+%entry:
+  ret 0
+}
+
+fun @main(): i32 {
+%entry:
+  jump %body
+
+%loop(%i: i32, %sum: i32):
+  jump %end
+
+%body:
+  %x = add 0, 0
+  jump %body_1
+
+%body_1:
+  %x_1 = add %x, 1
+  jump %body_2
+
+%body_2:
+  %x_2 = add %x_1, 2
+  jump %loop(3, %x_2)
+
+%end:
+  ret %sum
+}
+"#
+    );
+    // normalizing again does nothing
+    let mut program = parse_text(&compact).unwrap();
+    let text = print_program(&program);
+    NormalizeNames::new().run(&mut program);
+    assert_eq!(print_program(&program), text);
+    let stripped = unroll_and_normalize(Some(NameStyle::Strip));
+    assert!(!stripped.contains("%body") && !stripped.contains("%x"));
+    assert!(stripped.contains("fun @g_x(): i32 {\n%0:\n"));
+  }
+
+  #[test]
+  fn func_prefix() {
+    let text = unroll_and_normalize(Some(NameStyle::FuncPrefix));
+    assert!(text.contains("fun @g_x(): i32 {\n%g_x_entry:\n"));
+    assert!(text.contains("%main_loop_1(%main_i: i32, %main_sum: i32):"));
+    assert!(text.contains("%main_body_0_1_1:\n  %main_x_2_1 = add %main_x_2, 1\n"));
+  }
+}
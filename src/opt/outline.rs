@@ -0,0 +1,672 @@
+//! Region outlining ([`extract_region`]) related implementations.
+
+use crate::analysis::cfg;
+use crate::ir::builder::FunctionBuilder;
+use crate::ir::builder_traits::*;
+use crate::ir::entities::ValueData;
+use crate::ir::{self, is_valid_symbol, BasicBlock, Function, FunctionData, Program, Type};
+use crate::ir::{Value, ValueKind};
+use crate::opt::rewrite::{is_dead, remove_dead};
+use std::collections::{HashMap, HashSet};
+use std::{error, fmt};
+
+/// Outlines the given region of basic blocks in the given function into
+/// a new function named `name`. Returns the new function.
+///
+/// The region must have a single entry, which is the only basic block
+/// of the region that can be reached from outside the region, and a
+/// single exit, which is the only basic block outside the region that
+/// can be reached from the region. Returns in the region are not
+/// allowed.
+///
+/// Parameters of the new function are the parameters of the entry,
+/// followed by the live-in values, which are used by the region and
+/// defined outside of it, in the order of their first uses. The new
+/// function returns the argument passed to the exit, or the only value
+/// defined in the region and used outside of it, if any. So there can
+/// be at most one such value in total.
+///
+/// In the original function, all instructions of the entry are replaced
+/// with a call to the new function and a jump to the exit, and other
+/// basic blocks of the region are removed. The new function is placed
+/// right before the original function in the layout, so it is defined
+/// before the call in the text form.
+///
+/// The program is not changed on error.
+///
+/// # Example
+///
+/// ```
+/// use koopa::front::Driver;
+/// use koopa::opt::extract_region;
+///
+/// let driver: Driver<_> = r#"
+///   fun @f(@x: i32): i32 {
+///   %entry:
+///     jump %body
+///
+///   %body:
+///     %y = mul @x, 2
+///     jump %end
+///
+///   %end:
+///     ret %y
+///   }
+/// "#
+/// .into();
+/// let mut program = driver.generate_program().unwrap();
+/// let func = program.func_layout()[0];
+/// let body = program.func(func).layout().bbs().keys().copied().nth(1).unwrap();
+///
+/// let outlined = extract_region(&mut program, func, &[body], "@f_body").unwrap();
+/// assert_eq!(program.func(outlined).params().len(), 1);
+/// assert_eq!(program.func_layout(), [outlined, func]);
+/// ```
+pub fn extract_region(
+  program: &mut Program,
+  func: Function,
+  blocks: &[BasicBlock],
+  name: &str,
+) -> Result<Function, ExtractError> {
+  let region = Region::new(program.func(func), blocks)?;
+  if !is_valid_symbol(name) {
+    return Err(ExtractError::Func(ir::Error::InvalidName {
+      name: name.into(),
+    }));
+  }
+  let params = region.blocks[0]
+    .params
+    .iter()
+    .map(|(_, _, ty)| (None, ty.clone()))
+    .chain(
+      region
+        .live_ins
+        .iter()
+        .map(|(_, n, ty)| (n.clone(), ty.clone())),
+    )
+    .collect();
+  let data = FunctionData::with_param_names(name.into(), params, region.ret_ty.clone());
+  let outlined = program.try_new_func(data).map_err(ExtractError::Func)?;
+  // the new function must be defined before its caller in the text form
+  let pos = program.func_layout().iter().position(|f| *f == func);
+  program.move_func(outlined, pos.unwrap());
+  region.build(program.func_mut(outlined));
+  region.replace(program.func_mut(func), outlined);
+  Ok(outlined)
+}
+
+/// Errors returned by [`extract_region`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExtractError {
+  /// The region has no basic blocks.
+  Empty,
+  /// The basic block is not in the layout of the function, or appears
+  /// more than once in the region.
+  InvalidBlock(BasicBlock),
+  /// The region does not have exactly one entry. Contains all basic
+  /// blocks of the region that can be reached from outside the region.
+  Entries(Vec<BasicBlock>),
+  /// The region does not have exactly one exit. Contains all basic
+  /// blocks outside the region that can be reached from the region.
+  Exits(Vec<BasicBlock>),
+  /// The basic block of the region returns from the function.
+  Return(BasicBlock),
+  /// The region produces more than one value for the code after it.
+  LiveOuts {
+    /// Values defined in the region and used outside of it.
+    values: Vec<Value>,
+    /// Number of parameters of the exit.
+    exit_params: usize,
+  },
+  /// The new function can not be created, e.g. its name is invalid or
+  /// conflicts with an existing function or global value.
+  Func(ir::Error),
+}
+
+impl fmt::Display for ExtractError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Self::Empty => write!(f, "the region is empty"),
+      Self::InvalidBlock(bb) => write!(f, "invalid basic block {bb:?} in the region"),
+      Self::Entries(bbs) => write!(f, "expected one entry of the region, found {bbs:?}"),
+      Self::Exits(bbs) => write!(f, "expected one exit of the region, found {bbs:?}"),
+      Self::Return(bb) => write!(f, "basic block {bb:?} of the region returns"),
+      Self::LiveOuts {
+        values,
+        exit_params,
+      } => write!(
+        f,
+        "expected at most one value out of the region, found {} live-out \
+         value(s) and {exit_params} exit parameter(s)",
+        values.len()
+      ),
+      Self::Func(e) => write!(f, "failed to create the function: {e}"),
+    }
+  }
+}
+
+impl error::Error for ExtractError {}
+
+/// A region to be outlined, with the data copied from the original
+/// function.
+struct Region {
+  /// Basic blocks of the region, in the layout order.
+  bbs: Vec<BasicBlock>,
+  /// Basic blocks reachable from the entry in the region, in reverse
+  /// post order, the first one is the entry.
+  blocks: Vec<Block>,
+  exit: BasicBlock,
+  exit_params: Vec<(Option<String>, Type)>,
+  /// Local constants used by the region, operands come first.
+  consts: Vec<(Value, ValueData)>,
+  /// Live-in values with their names and types.
+  live_ins: Vec<(Value, Option<String>, Type)>,
+  live_out: Option<Value>,
+  ret_ty: Type,
+}
+
+/// A basic block of the region.
+struct Block {
+  bb: BasicBlock,
+  name: Option<String>,
+  params: Vec<(Value, Option<String>, Type)>,
+  insts: Vec<(Value, ValueData)>,
+}
+
+impl Region {
+  /// Checks the given region and collects its data.
+  fn new(data: &FunctionData, blocks: &[BasicBlock]) -> Result<Self, ExtractError> {
+    let (dfg, layout) = (data.dfg(), data.layout());
+    if blocks.is_empty() {
+      return Err(ExtractError::Empty);
+    }
+    let mut set = HashSet::new();
+    for &bb in blocks {
+      if !layout.bbs().contains_key(&bb) || !set.insert(bb) {
+        return Err(ExtractError::InvalidBlock(bb));
+      }
+    }
+    let bbs: Vec<_> = layout
+      .bbs()
+      .keys()
+      .copied()
+      .filter(|bb| set.contains(bb))
+      .collect();
+    let insts = |bb| layout.bbs().node(&bb).unwrap().insts().keys().copied();
+    let outside = |user| layout.parent_bb(user).is_some_and(|bb| !set.contains(&bb));
+
+    // the entry of the function is reached from its callers
+    let entries: Vec<_> = bbs
+      .iter()
+      .copied()
+      .filter(|bb| {
+        layout.entry_bb() == Some(*bb) || dfg.bb(*bb).used_by().iter().any(|u| outside(*u))
+      })
+      .collect();
+    let [entry] = entries[..] else {
+      return Err(ExtractError::Entries(entries));
+    };
+    let mut exits = Vec::new();
+    for &bb in &bbs {
+      for inst in insts(bb) {
+        let kind = dfg.value(inst).kind();
+        if matches!(kind, ValueKind::Return(_)) {
+          return Err(ExtractError::Return(bb));
+        }
+        for (target, _) in kind.edges() {
+          if !set.contains(&target) && !exits.contains(&target) {
+            exits.push(target);
+          }
+        }
+      }
+    }
+    let [exit] = exits[..] else {
+      return Err(ExtractError::Exits(exits));
+    };
+
+    // parameters of the entry stay in the original function
+    let mut defined = HashSet::new();
+    let mut live_outs = Vec::new();
+    for &bb in &bbs {
+      let params = dfg.bb(bb).params().iter().copied();
+      for value in params.filter(|_| bb != entry).chain(insts(bb)) {
+        if dfg.value(value).used_by().iter().any(|u| outside(*u)) {
+          live_outs.push(value);
+        }
+      }
+      defined.extend(dfg.bb(bb).params());
+      defined.extend(insts(bb));
+    }
+    let exit_params = dfg.bb(exit).params();
+    if exit_params.len() + live_outs.len() > 1 {
+      return Err(ExtractError::LiveOuts {
+        values: live_outs,
+        exit_params: exit_params.len(),
+      });
+    }
+    let ret_ty = match exit_params.first().or(live_outs.first()) {
+      Some(v) => dfg.value(*v).ty().clone(),
+      None => Type::get_unit(),
+    };
+
+    let mut live_ins = Vec::new();
+    let mut consts = Vec::new();
+    let mut visited = HashSet::new();
+    for &bb in &bbs {
+      for inst in insts(bb) {
+        for v in dfg.value(inst).kind().value_uses() {
+          if defined.contains(&v) || !visited.insert(v) {
+            continue;
+          }
+          // global values are shared by all functions
+          let Some(value) = dfg.values().get(&v) else {
+            continue;
+          };
+          if !value.kind().is_const() {
+            live_ins.push((v, value.name().clone(), value.ty().clone()));
+            continue;
+          }
+          // collect operands of aggregates before the aggregates
+          let mut stack = vec![(v, false)];
+          while let Some((v, ready)) = stack.pop() {
+            if ready {
+              consts.push((v, dfg.value(v).clone()));
+              continue;
+            }
+            stack.push((v, true));
+            for op in dfg.value(v).kind().value_uses() {
+              if visited.insert(op) {
+                stack.push((op, false));
+              }
+            }
+          }
+        }
+      }
+    }
+
+    // reverse post order of the region from the entry
+    let mut po = Vec::new();
+    let mut reached = HashSet::from([entry]);
+    let mut stack = vec![(entry, cfg::successors(data, entry), 0)];
+    while let Some((bb, succs, i)) = stack.last_mut() {
+      match succs.get(*i) {
+        Some(&succ) => {
+          *i += 1;
+          if set.contains(&succ) && reached.insert(succ) {
+            stack.push((succ, cfg::successors(data, succ), 0));
+          }
+        }
+        None => {
+          po.push(*bb);
+          stack.pop();
+        }
+      }
+    }
+    let blocks = po
+      .into_iter()
+      .rev()
+      .map(|bb| Block {
+        bb,
+        name: dfg.bb(bb).name().clone(),
+        params: (dfg.bb(bb).params().iter())
+          .map(|p| (*p, dfg.value(*p).name().clone(), dfg.value(*p).ty().clone()))
+          .collect(),
+        insts: insts(bb).map(|i| (i, dfg.value(i).clone())).collect(),
+      })
+      .collect();
+
+    Ok(Self {
+      bbs,
+      blocks,
+      exit,
+      exit_params: (exit_params.iter())
+        .map(|p| (dfg.value(*p).name().clone(), dfg.value(*p).ty().clone()))
+        .collect(),
+      consts,
+      live_ins,
+      live_out: live_outs.first().copied(),
+      ret_ty,
+    })
+  }
+
+  /// Builds the body of the new function.
+  fn build(&self, data: &mut FunctionData) {
+    let params = data.params().to_vec();
+    let (entry_args, live_in_args) = params.split_at(self.blocks[0].params.len());
+    let mut value_map: HashMap<_, _> = (self.live_ins.iter().map(|(v, ..)| *v))
+      .zip(live_in_args.iter().copied())
+      .collect();
+    let mut bb_map = HashMap::new();
+    let entry = FunctionBuilder::new(data).create_bb(Some("%entry".into()));
+    for block in &self.blocks {
+      let params = (block.params.iter())
+        .map(|(_, name, ty)| (name.clone(), ty.clone()))
+        .collect();
+      let bb = (data.dfg_mut().new_bb()).basic_block_with_param_names(block.name.clone(), params);
+      data.layout_mut().bbs_mut().push_key_back(bb).unwrap();
+      let new_params = data.dfg().bb(bb).params();
+      value_map.extend(
+        block
+          .params
+          .iter()
+          .map(|(p, ..)| *p)
+          .zip(new_params.iter().copied()),
+      );
+      bb_map.insert(block.bb, bb);
+    }
+    // edges to the exit return from the new function
+    let exit = (data.dfg_mut().new_bb())
+      .basic_block_with_param_names(Some("%exit".into()), self.exit_params.clone());
+    data.layout_mut().bbs_mut().push_key_back(exit).unwrap();
+    bb_map.insert(self.exit, exit);
+
+    for (value, value_data) in &self.consts {
+      let value_data = remap(value_data.clone(), &value_map, &bb_map);
+      let new = data.dfg_mut().new_value().raw(value_data);
+      value_map.insert(*value, new);
+    }
+    for block in &self.blocks {
+      for (inst, inst_data) in &block.insts {
+        let inst_data = remap(inst_data.clone(), &value_map, &bb_map);
+        let new = data.dfg_mut().new_value().raw(inst_data);
+        let bb = data.layout_mut().bb_mut(bb_map[&block.bb]);
+        bb.insts_mut().push_key_back(new).unwrap();
+        value_map.insert(*inst, new);
+      }
+    }
+
+    let ret = match data.dfg().bb(exit).params().first() {
+      Some(param) => Some(*param),
+      None => self.live_out.map(|v| value_map[&v]),
+    };
+    let mut builder = FunctionBuilder::new(data);
+    builder.position_at_end(entry);
+    builder.jump_with_args(bb_map[&self.blocks[0].bb], entry_args.to_vec());
+    builder.position_at_end(exit);
+    builder.ret(ret);
+  }
+
+  /// Replaces the region in the original function with a call to the
+  /// new function.
+  fn replace(&self, data: &mut FunctionData, outlined: Function) {
+    let entry = self.blocks[0].bb;
+    let mut insts = Vec::new();
+    for bb in &self.bbs {
+      let node = data.layout_mut().bb_mut(*bb);
+      let bb_insts: Vec<_> = node.insts().keys().copied().collect();
+      for inst in bb_insts {
+        node.insts_mut().remove(&inst);
+        insts.push(inst);
+      }
+    }
+    let mut args = data.dfg().bb(entry).params().to_vec();
+    args.extend(self.live_ins.iter().map(|(v, ..)| *v));
+    let mut builder = FunctionBuilder::new(data);
+    builder.position_at_end(entry);
+    let call = builder.call(outlined, args);
+    match self.exit_params.is_empty() {
+      true => builder.jump(self.exit),
+      false => builder.jump_with_args(self.exit, vec![call]),
+    };
+    if let Some(live_out) = self.live_out {
+      data.dfg_mut().replace_all_uses_with(live_out, call);
+    }
+
+    // instructions of the region are only used by each other now
+    while !insts.is_empty() {
+      let len = insts.len();
+      insts.retain(|inst| data.dfg_mut().try_remove_value(*inst).is_err());
+      assert!(
+        insts.len() < len,
+        "instructions are used outside the region"
+      );
+    }
+    for bb in self.bbs.iter().filter(|bb| **bb != entry) {
+      data.layout_mut().bbs_mut().remove(bb);
+      data.dfg_mut().remove_bb(*bb);
+    }
+    let consts = (self.consts.iter())
+      .map(|(v, _)| *v)
+      .filter(|v| is_dead(data.dfg(), *v))
+      .collect();
+    remove_dead(data, consts);
+  }
+}
+
+/// Replaces operands and target basic blocks of the given value data by
+/// the given maps.
+fn remap(
+  mut data: ValueData,
+  value_map: &HashMap<Value, Value>,
+  bb_map: &HashMap<BasicBlock, BasicBlock>,
+) -> ValueData {
+  let kind = data.kind_mut();
+  for i in 0..kind.num_operands() {
+    if let Some(value) = value_map.get(&kind.operand(i)) {
+      kind.set_operand(i, *value);
+    }
+  }
+  for i in 0..kind.edges().len() {
+    if let Some(bb) = bb_map.get(&kind.edges()[i].0) {
+      kind.set_edge_target(i, *bb);
+    }
+  }
+  data
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::analysis::verifier::verify;
+  use crate::back::KoopaGenerator;
+  use crate::front::Driver;
+  use crate::text::print_program;
+  use std::str;
+
+  fn parse(src: &str) -> (Program, Function, Vec<BasicBlock>) {
+    let driver: Driver<_> = src.into();
+    let program = driver.generate_program().unwrap();
+    let func = *program.func_layout().last().unwrap();
+    let bbs = program.func(func).layout().bbs().keys().copied().collect();
+    (program, func, bbs)
+  }
+
+  #[test]
+  fn live_out() {
+    let (mut program, func, bbs) = parse(
+      r#"
+      global @g = alloc i32, 1
+
+      fun @f(@x: i32): i32 {
+      %entry:
+        jump %region(@x)
+
+      %region(%a: i32):
+        %v = load @g
+        %b = add %a, %v
+        br %b, %then, %end
+
+      %then:
+        %c = mul %b, 2
+        store %c, @g
+        jump %end
+
+      %end:
+        %r = add %b, 1
+        ret %r
+      }
+    "#,
+    );
+    let outlined = extract_region(&mut program, func, &bbs[1..3], "@region").unwrap();
+    assert_eq!(verify(&program), Ok(()));
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    assert_eq!(
+      str::from_utf8(&gen.writer()).unwrap(),
+      r#"global @g = alloc i32, 1
+
+fun @region(%0: i32): i32 {
+%entry:
+  jump %region(%0)
+
+%region(%a: i32):
+  %v = load @g
+  %b = add %a, %v
+  br %b, %then, %exit
+
+%then:
+  %c = mul %b, 2
+  store %c, @g
+  jump %exit
+
+%exit:
+  ret %b
+}
+
+fun @f(@x: i32): i32 {
+%entry:
+  jump %region(@x)
+
+%region(%a: i32):
+  %1 = call @region(%a)
+  jump %end
+
+%end:
+  %r = add %1, 1
+  ret %r
+}
+"#
+    );
+    assert_eq!(program.func(outlined).name(), "@region");
+  }
+
+  #[test]
+  fn loop_body() {
+    let (mut program, func, bbs) = parse(
+      r#"
+      fun @f(@n: i32): i32 {
+      %entry:
+        %sum = alloc i32
+        store 0, %sum
+        jump %header(0)
+
+      %header(%i: i32):
+        %c = lt %i, @n
+        br %c, %body, %end
+
+      %body:
+        %s = load %sum
+        %s1 = add %s, %i
+        store %s1, %sum
+        %i1 = add %i, 1
+        jump %header(%i1)
+
+      %end:
+        %r = load %sum
+        ret %r
+      }
+    "#,
+    );
+    let body = extract_region(&mut program, func, &bbs[2..3], "@f_body").unwrap();
+    assert_eq!(verify(&program), Ok(()));
+    assert_eq!(program.func_layout(), [body, func]);
+    let text = print_program(&program);
+    // the loop body takes `%sum` and `%i`, and returns `%i1` for the header
+    assert!(text.contains(
+      r#"fun @f_body(%sum: *i32, %i: i32): i32 {
+%entry:
+  jump %body
+
+%body:
+  %s = load %sum
+  %s1 = add %s, %i
+  store %s1, %sum
+  %i1 = add %i, 1
+  jump %exit(%i1)
+
+%exit(%i_0: i32):
+  ret %i_0
+}
+"#
+    ));
+    assert!(text.contains(
+      r#"%body:
+  %0 = call @f_body(%sum, %i)
+  jump %header(%0)
+"#
+    ));
+    // the printed program can be parsed again
+    let (reparsed, ..) = parse(&text);
+    assert_eq!(verify(&reparsed), Ok(()));
+    assert_eq!(print_program(&reparsed), text);
+  }
+
+  #[test]
+  fn errors() {
+    let (mut program, func, bbs) = parse(
+      r#"
+      fun @f(@x: i32): i32 {
+      %entry:
+        br @x, %a, %b
+
+      %a:
+        jump %b
+
+      %b:
+        br @x, %c, %d(1)
+
+      %c:
+        %y = add @x, 1
+        %w = add @x, 2
+        jump %e(%w)
+
+      %d(%r: i32):
+        ret %r
+
+      %e(%p: i32):
+        %s = add %p, %y
+        jump %d(%s)
+      }
+    "#,
+    );
+    let data = program.func(func);
+    let (y, _) = (data.dfg().values().iter())
+      .find(|(_, d)| d.name().as_deref() == Some("%y"))
+      .unwrap();
+    let y = *y;
+    let mut extract = |bbs: &[BasicBlock], name| extract_region(&mut program, func, bbs, name);
+    assert_eq!(extract(&[], "@g"), Err(ExtractError::Empty));
+    assert_eq!(
+      extract(&[bbs[1], bbs[1]], "@g"),
+      Err(ExtractError::InvalidBlock(bbs[1]))
+    );
+    assert_eq!(
+      extract(&[bbs[1], bbs[2]], "@g"),
+      Err(ExtractError::Entries(vec![bbs[1], bbs[2]]))
+    );
+    assert_eq!(
+      extract(&[bbs[2]], "@g"),
+      Err(ExtractError::Exits(vec![bbs[3], bbs[4]]))
+    );
+    assert_eq!(extract(&[bbs[4]], "@g"), Err(ExtractError::Return(bbs[4])));
+    // `%e` takes the argument, so `%y` can not be returned
+    assert_eq!(
+      extract(&[bbs[3]], "@g"),
+      Err(ExtractError::LiveOuts {
+        values: vec![y],
+        exit_params: 1
+      })
+    );
+    assert!(matches!(
+      extract(&[bbs[3], bbs[5]], "g"),
+      Err(ExtractError::Func(ir::Error::InvalidName { .. }))
+    ));
+    assert!(matches!(
+      extract(&[bbs[3], bbs[5]], "@f"),
+      Err(ExtractError::Func(ir::Error::NameConflict { .. }))
+    ));
+    // the program is not changed on error
+    assert_eq!(program.funcs().len(), 1);
+    assert_eq!(program.func(func).layout().bbs().len(), 6);
+  }
+}
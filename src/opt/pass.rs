@@ -6,6 +6,11 @@
 //! make up the compiler.
 
 use crate::ir::{Function, FunctionData, Program};
+use crate::opt::{
+  AlgebraicSimplify, DeadArgElimination, FunctionSpecialization, GlobalDce, GlobalValueNumbering,
+  InductionVarSimplify, LoopInvariantMotion, LoopUnroll, NormalizeNames, PtrCanonicalize,
+  ReturnPropagation,
+};
 
 /// A Koopa IR pass.
 ///
@@ -17,12 +22,64 @@ pub enum Pass {
   Function(Box<dyn FunctionPass>),
 }
 
+impl Pass {
+  /// Creates a built-in pass with default options by the given name.
+  ///
+  /// The available names are:
+  ///
+  /// * `simplify`: [`AlgebraicSimplify`].
+  /// * `canonicalize-ptr`: [`PtrCanonicalize`].
+  /// * `unroll`: [`LoopUnroll`].
+  /// * `indvars`: [`InductionVarSimplify`].
+  /// * `licm`: [`LoopInvariantMotion`].
+  /// * `gvn`: [`GlobalValueNumbering`].
+  /// * `dead-args`: [`DeadArgElimination`].
+  /// * `return-prop`: [`ReturnPropagation`].
+  /// * `global-dce`: [`GlobalDce`].
+  /// * `specialize`: [`FunctionSpecialization`].
+  /// * `normalize-names`: [`NormalizeNames`].
+  ///
+  /// Returns [`None`] if there is no pass with the given name.
+  pub fn builtin(name: &str) -> Option<Self> {
+    Some(match name {
+      "simplify" => Pass::Function(Box::new(AlgebraicSimplify::new())),
+      "canonicalize-ptr" => Pass::Function(Box::new(PtrCanonicalize::new())),
+      "unroll" => Pass::Function(Box::new(LoopUnroll::new())),
+      "indvars" => Pass::Function(Box::new(InductionVarSimplify::new())),
+      "licm" => Pass::Function(Box::new(LoopInvariantMotion::new())),
+      "gvn" => Pass::Function(Box::new(GlobalValueNumbering::new())),
+      "dead-args" => Pass::Module(Box::new(DeadArgElimination::new())),
+      "return-prop" => Pass::Module(Box::new(ReturnPropagation::new())),
+      "global-dce" => Pass::Module(Box::new(GlobalDce::new())),
+      "specialize" => Pass::Module(Box::new(FunctionSpecialization::new())),
+      "normalize-names" => Pass::Module(Box::new(NormalizeNames::new())),
+      _ => return None,
+    })
+  }
+
+  /// Returns the name of the pass.
+  pub fn name(&self) -> &str {
+    match self {
+      Pass::Module(p) => p.name(),
+      Pass::Function(p) => p.name(),
+    }
+  }
+}
+
 /// Trait of a module pass.
 ///
 /// Module passes can run on IR programs.
 pub trait ModulePass {
   /// Runs on the given IR program.
   fn run_on(&mut self, program: &mut Program);
+
+  /// Returns the name of the pass, which is used by the
+  /// [`PassManager`](crate::opt::PassManager) to identify the pass.
+  ///
+  /// Defaults to the type name without the module path.
+  fn name(&self) -> &str {
+    type_name::<Self>()
+  }
 }
 
 /// Trait of a function pass.
@@ -31,4 +88,29 @@ pub trait ModulePass {
 pub trait FunctionPass {
   /// Runs on the given function data.
   fn run_on(&mut self, func: Function, data: &mut FunctionData);
+
+  /// Returns the name of the pass, see [`ModulePass::name`].
+  fn name(&self) -> &str {
+    type_name::<Self>()
+  }
+
+  /// Returns a new instance of the pass with the same options, for
+  /// running on functions in another thread, see
+  /// [`PassManager::run_parallel`](crate::opt::PassManager::run_parallel).
+  ///
+  /// Forked instances are dropped after running, so passes that collect
+  /// results across functions should not be forked.
+  ///
+  /// Defaults to [`None`], which means the pass always runs in the
+  /// current thread.
+  fn fork(&self) -> Option<Box<dyn FunctionPass + Send>> {
+    None
+  }
+}
+
+/// Returns the name of type `T` without the module path.
+fn type_name<T: ?Sized>() -> &'static str {
+  let name = std::any::type_name::<T>();
+  let path = name.split('<').next().unwrap();
+  path.rsplit("::").next().unwrap()
 }
@@ -1,15 +1,52 @@
 //! Pass manager ([`PassManager`]) related implementations.
 
-use crate::ir::Program;
-use crate::opt::pass::Pass;
+use crate::back::koopa::{self, Config, Visitor};
+use crate::back::KoopaGenerator;
+use crate::ir::{Function, Program, Type};
+use crate::opt::pass::{FunctionPass, Pass};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::time::Instant;
 
 /// The Koopa IR pass manager.
 ///
 /// Pass manager manages all registed passes, and processes the input
 /// IR program by using registered passes.
+///
+/// # Debugging
+///
+/// The pass manager records every pass it runs (see [`PassRecord`]).
+/// Passes are considered to have changed the program if the text form
+/// of the program is different after running them.
+///
+/// For finding out which pass broke the program, the program can be
+/// dumped after passes by [`PassManager::dump_after_each`], or inspected
+/// by callbacks registered by [`PassManager::on_after_pass`].
 #[derive(Default)]
 pub struct PassManager {
   passes: Vec<Pass>,
+  records: Vec<PassRecord>,
+  dump_dir: Option<PathBuf>,
+  dump_passes: Option<HashSet<String>>,
+  callbacks: Vec<Callback>,
+  collect_garbage: bool,
+}
+
+/// Record of a pass run by [`PassManager::run_passes`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PassRecord {
+  /// Name of the pass, see [`Pass::name`].
+  pub name: String,
+  /// `true` if the pass changed the program.
+  pub changed: bool,
+  /// Time spent in the pass.
+  ///
+  /// Always zero on `wasm32-unknown-unknown`, which has no clock.
+  pub duration: Duration,
 }
 
 impl PassManager {
@@ -23,17 +60,146 @@ impl PassManager {
     self.passes.push(pass);
   }
 
+  /// Dumps the program to the given directory after every pass that
+  /// changed the program. The directory is created if it does not exist.
+  ///
+  /// Every function changed by the `N`-th pass (starting from 1) named
+  /// `pass` is dumped to file `NN_pass_func.koopa`, where `func` is the
+  /// function name without `@`. The file contains all global values, the
+  /// function and declarations of all other functions, so it can be
+  /// parsed and verified separately. If the pass changed global values
+  /// or removed functions, the whole program is dumped to `NN_pass.koopa`.
+  ///
+  /// Temporary names are numbered separately in every function (see
+  /// [`Config::stable_temps`]), so dumps of the same function after
+  /// different passes can be diffed.
+  pub fn dump_after_each(&mut self, dir: impl Into<PathBuf>) {
+    self.dump_dir = Some(dir.into());
+  }
+
+  /// Only dumps the program after the passes with the given names.
+  ///
+  /// This method only takes effect if the dump directory is set by
+  /// [`PassManager::dump_after_each`].
+  pub fn dump_after<I, S>(&mut self, passes: I)
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    self.dump_passes = Some(passes.into_iter().map(Into::into).collect());
+  }
+
+  /// Collects garbage in all functions (see
+  /// [`FunctionData::collect_garbage`](crate::ir::FunctionData::collect_garbage))
+  /// after every pass, before the
+  /// changes are checked, dumped and passed to the callbacks.
+  pub fn collect_garbage_after_each(&mut self) {
+    self.collect_garbage = true;
+  }
+
+  /// Registers a callback, which will be called after every pass with
+  /// the name of the pass and the program, whether the pass changed the
+  /// program or not.
+  pub fn on_after_pass<F>(&mut self, callback: F)
+  where
+    F: FnMut(&str, &Program) + 'static,
+  {
+    self.callbacks.push(Box::new(callback));
+  }
+
+  /// Returns records of the passes run by the last call of
+  /// [`PassManager::run_passes`] or [`PassManager::run_parallel`], in
+  /// the running order.
+  pub fn records(&self) -> &[PassRecord] {
+    &self.records
+  }
+
   /// Runs all registered passes on the given IR program.
+  ///
+  /// While running a pass, the pass name is set as the mutation label of
+  /// all functions, so mutations recorded in the mutation logs of the
+  /// functions can be traced back to the passes, see
+  /// [`mutation`](crate::ir::mutation).
+  ///
+  /// # Panics
+  ///
+  /// Panics if failed to write the dumps.
   pub fn run_passes(&mut self, program: &mut Program) {
-    for pass in &mut self.passes {
-      match pass {
+    self.run(program, 1);
+  }
+
+  /// Runs all registered passes on the given IR program, like
+  /// [`PassManager::run_passes`], but runs function passes on functions
+  /// in parallel, using `threads` worker threads.
+  ///
+  /// Functions are partitioned across the worker threads, and each
+  /// thread runs an instance of the function pass forked by
+  /// [`FunctionPass::fork`] on its functions. Function passes that can
+  /// not be forked, and all module passes, run in the current thread.
+  /// Every pass still finishes on all functions before the next pass
+  /// starts, so the program after every pass, the records, the dumps
+  /// and the arguments of the callbacks are all the same as
+  /// [`PassManager::run_passes`], no matter how threads are scheduled.
+  /// Durations in the records are the wall time of the passes.
+  ///
+  /// Function passes only have mutable access to their own functions.
+  /// Global values are still readable through the data flow graphs, see
+  /// [`Program::par_funcs_mut`].
+  ///
+  /// If `threads` is one, or the target does not support threads (like
+  /// `wasm32-unknown-unknown`), this method is the same as
+  /// [`PassManager::run_passes`].
+  ///
+  /// # Panics
+  ///
+  /// Panics if `threads` is zero, failed to write the dumps, or any
+  /// pass panics in a worker thread.
+  pub fn run_parallel(&mut self, program: &mut Program, threads: usize) {
+    assert!(threads != 0, "`threads` can not be zero");
+    self.run(program, threads);
+  }
+
+  /// Runs all registered passes on the given IR program, using
+  /// `threads` threads for function passes.
+  fn run(&mut self, program: &mut Program, threads: usize) {
+    self.records.clear();
+    let mut before = koopa::snapshot(program);
+    for (i, pass) in self.passes.iter_mut().enumerate() {
+      let label = pass.name().to_string();
+      set_mutation_labels(program, Some(&label));
+      let duration = timed(|| match pass {
         Pass::Module(p) => p.run_on(program),
-        Pass::Function(p) => {
-          for (func, data) in program.funcs_mut() {
-            p.run_on(*func, data);
-          }
+        Pass::Function(p) => run_function_pass(p.as_mut(), program, threads),
+      });
+      if self.collect_garbage {
+        program.funcs_mut().values_mut().for_each(|data| {
+          data.collect_garbage();
+        });
+      }
+      set_mutation_labels(program, None);
+      let after = koopa::snapshot(program);
+      let changed = after != before;
+      let name = pass.name();
+      if let Some(dir) = &self.dump_dir {
+        let dumped = self
+          .dump_passes
+          .as_ref()
+          .is_none_or(|passes| passes.contains(name));
+        if changed && dumped {
+          let prefix = format!("{:02}_{name}", i + 1);
+          dump(dir, &prefix, program, &before, &after)
+            .unwrap_or_else(|e| panic!("failed to dump the program: {e}"));
         }
       }
+      for callback in &mut self.callbacks {
+        callback(name, program);
+      }
+      self.records.push(PassRecord {
+        name: name.into(),
+        changed,
+        duration,
+      });
+      before = after;
     }
   }
 }
@@ -41,6 +207,104 @@ impl PassManager {
 /// Creates a new pass manager from a [`Vec`] of passes.
 impl From<Vec<Pass>> for PassManager {
   fn from(passes: Vec<Pass>) -> Self {
-    Self { passes }
+    Self {
+      passes,
+      ..Self::default()
+    }
+  }
+}
+
+/// Callback called after passes, see [`PassManager::on_after_pass`].
+type Callback = Box<dyn FnMut(&str, &Program)>;
+
+/// Snapshot of a program, see [`koopa::snapshot`].
+type Snapshot = (String, Vec<(Function, String)>);
+
+/// Sets or clears the mutation labels of all functions in the program.
+fn set_mutation_labels(program: &mut Program, label: Option<&str>) {
+  for data in program.funcs_mut().values_mut() {
+    match label {
+      Some(label) => data.dfg_mut().set_mutation_label(label),
+      None => data.dfg_mut().clear_mutation_label(),
+    }
+  }
+}
+
+/// Runs the given function pass on all functions in the program, using
+/// `threads` threads if the pass can be forked.
+fn run_function_pass(pass: &mut dyn FunctionPass, program: &mut Program, threads: usize) {
+  let mut funcs: Vec<_> = program.funcs_mut().iter_mut().collect();
+  let threads = threads.min(funcs.len());
+  let forks = if threads > 1 && !cfg!(all(target_arch = "wasm32", target_os = "unknown")) {
+    (0..threads)
+      .map(|_| pass.fork())
+      .collect::<Option<Vec<_>>>()
+  } else {
+    None
+  };
+  let Some(forks) = forks else {
+    for (func, data) in funcs {
+      pass.run_on(*func, data);
+    }
+    return;
+  };
+  let chunk_size = funcs.len().div_ceil(threads);
+  let ptr_size = Type::ptr_size();
+  thread::scope(|s| {
+    for (chunk, mut fork) in funcs.chunks_mut(chunk_size).zip(forks) {
+      s.spawn(move || {
+        Type::set_ptr_size(ptr_size);
+        for (func, data) in chunk {
+          fork.run_on(**func, data);
+        }
+      });
+    }
+  });
+}
+
+/// Runs `f` and returns the elapsed time.
+fn timed(f: impl FnOnce()) -> Duration {
+  #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+  {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+  }
+  #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+  {
+    f();
+    Duration::ZERO
+  }
+}
+
+/// Dumps the changes of the program to files in the given directory,
+/// whose names start with `prefix`.
+fn dump(
+  dir: &Path,
+  prefix: &str,
+  program: &Program,
+  before: &Snapshot,
+  after: &Snapshot,
+) -> std::io::Result<()> {
+  fs::create_dir_all(dir)?;
+  let config = Config {
+    stable_temps: true,
+    ..Config::default()
+  };
+  let old_funcs: HashMap<_, _> = before.1.iter().map(|(f, t)| (*f, t)).collect();
+  for (func, text) in &after.1 {
+    if old_funcs.get(func) != Some(&text) {
+      let name = &program.func(*func).name()[1..];
+      let path = dir.join(format!("{prefix}_{name}.koopa"));
+      let visitor = Visitor::new(config).with_focus(*func);
+      KoopaGenerator::with_visitor(fs::File::create(path)?, visitor).generate_on(program)?;
+    }
+  }
+  let removed = old_funcs.keys().any(|f| !program.funcs().contains_key(f));
+  if before.0 != after.0 || removed {
+    let path = dir.join(format!("{prefix}.koopa"));
+    KoopaGenerator::with_visitor(fs::File::create(path)?, Visitor::new(config))
+      .generate_on(program)?;
   }
+  Ok(())
 }
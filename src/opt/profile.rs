@@ -0,0 +1,370 @@
+//! Profiling instrumentation pass ([`ProfileInstrument`]) related
+//! implementations.
+
+use crate::ir::builder::FunctionBuilder;
+use crate::ir::builder_traits::*;
+use crate::ir::{BasicBlock, BinaryOp, Function, FunctionData, Program, Type, Value};
+use crate::opt::ModulePass;
+
+/// How the instrumented program counts the executions of basic blocks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CounterStyle {
+  /// Counts in a global `i32` array with the given name, which has one
+  /// slot for each instrumented basic block.
+  ///
+  /// Each basic block loads its slot, adds 1 to it, and stores it back.
+  Array(String),
+  /// Calls the runtime function with the given name, which takes the
+  /// index of the executed basic block as its only parameter.
+  ///
+  /// The function is declared as `(i32)` if it does not exist.
+  /// Functions with the same name as the runtime function are not
+  /// instrumented, to avoid infinite recursions.
+  Call(String),
+}
+
+impl Default for CounterStyle {
+  /// Counts in the global array `@__koopa_profile_counters`.
+  fn default() -> Self {
+    CounterStyle::Array("@__koopa_profile_counters".into())
+  }
+}
+
+/// A basic block instrumented by [`ProfileInstrument`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProfiledBlock {
+  /// Name of the function of the basic block.
+  pub func: String,
+  /// Name of the basic block.
+  pub bb: Option<String>,
+}
+
+/// A module pass that inserts profiling counters at the top of every
+/// basic block of all function definitions, see [`CounterStyle`] for
+/// details. Function declarations are skipped.
+///
+/// Counters of the entry basic blocks are inserted after the leading
+/// `alloc`s. Basic blocks are indexed in the function layout order,
+/// then in the basic block layout order. The instrumented basic blocks
+/// are recorded as a side table, which maps indices to basic blocks.
+///
+/// # Example
+///
+/// ```
+/// use koopa::front::Driver;
+/// use koopa::opt::*;
+///
+/// let driver: Driver<_> = r#"
+///   decl @getint(): i32
+///
+///   fun @main(): i32 {
+///   %entry:
+///     %0 = call @getint()
+///     br %0, %then, %end
+///
+///   %then:
+///     jump %end
+///
+///   %end:
+///     ret 0
+///   }
+/// "#
+/// .into();
+/// let mut program = driver.generate_program().unwrap();
+///
+/// let blocks = ProfileInstrument::new().run(&mut program);
+/// assert_eq!(blocks.len(), 3);
+/// assert_eq!(blocks[1].func, "@main");
+/// assert_eq!(blocks[1].bb.as_deref(), Some("%then"));
+/// ```
+pub struct ProfileInstrument {
+  style: CounterStyle,
+  blocks: Vec<ProfiledBlock>,
+}
+
+impl ProfileInstrument {
+  /// Creates a new pass with the default counter style.
+  pub fn new() -> Self {
+    Self::with_style(CounterStyle::default())
+  }
+
+  /// Creates a new pass with the given counter style.
+  pub fn with_style(style: CounterStyle) -> Self {
+    Self {
+      style,
+      blocks: Vec::new(),
+    }
+  }
+
+  /// Returns the basic blocks instrumented by the last run of the pass
+  /// as a [`ModulePass`]. The counter of a basic block is the slot at
+  /// (or the runtime function argument equal to) its index.
+  pub fn blocks(&self) -> &[ProfiledBlock] {
+    &self.blocks
+  }
+
+  /// Runs the pass on the given program.
+  /// Returns the instrumented basic blocks.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the name in the counter style is not a valid global
+  /// symbol name, the counter array name is already used, or the
+  /// runtime function does not have type `(i32)`.
+  pub fn run(&self, program: &mut Program) -> Vec<ProfiledBlock> {
+    let funcs: Vec<_> = program
+      .func_layout()
+      .iter()
+      .copied()
+      .filter(|f| {
+        let data = program.func(*f);
+        data.layout().entry_bb().is_some()
+          && !matches!(&self.style, CounterStyle::Call(name) if name == data.name())
+      })
+      .collect();
+    let mut blocks = Vec::new();
+    let mut bbs = Vec::new();
+    for &func in &funcs {
+      let data = program.func(func);
+      for &bb in data.layout().bbs().keys() {
+        blocks.push(ProfiledBlock {
+          func: data.name().into(),
+          bb: data.dfg().bb(bb).name().clone(),
+        });
+        bbs.push((func, bb));
+      }
+    }
+    if bbs.is_empty() {
+      return blocks;
+    }
+    let counter = match &self.style {
+      CounterStyle::Array(name) => Counter::Array(new_counters(program, name, bbs.len())),
+      CounterStyle::Call(name) => Counter::Call(runtime_func(program, name, vec![Type::get_i32()])),
+    };
+    for (index, (func, bb)) in bbs.into_iter().enumerate() {
+      let mut builder = FunctionBuilder::new(program.func_mut(func));
+      if builder.func().layout().entry_bb() == Some(bb) {
+        builder.with_entry_block(|b| counter.insert(b, index));
+      } else {
+        position_at_top(&mut builder, bb);
+        counter.insert(&mut builder, index);
+      }
+    }
+    blocks
+  }
+}
+
+impl Default for ProfileInstrument {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl ModulePass for ProfileInstrument {
+  fn run_on(&mut self, program: &mut Program) {
+    self.blocks = self.run(program);
+  }
+
+  fn name(&self) -> &str {
+    "profile"
+  }
+}
+
+/// Counter of the instrumented program.
+enum Counter {
+  /// The global counter array.
+  Array(Value),
+  /// The runtime function.
+  Call(Function),
+}
+
+impl Counter {
+  /// Inserts the counter of the basic block at the given index at the
+  /// current insert point of the given builder.
+  fn insert(&self, builder: &mut FunctionBuilder, index: usize) {
+    let index = builder.integer(index as i32);
+    match self {
+      Counter::Array(counters) => {
+        let ptr = builder.get_elem_ptr(*counters, index);
+        let count = builder.load(ptr);
+        let one = builder.integer(1);
+        let count = builder.binary(BinaryOp::Add, count, one);
+        builder.store(count, ptr);
+      }
+      Counter::Call(func) => {
+        builder.call(*func, vec![index]);
+      }
+    }
+  }
+}
+
+/// Creates a zero-initialized global counter array with the given name
+/// and length.
+fn new_counters(program: &mut Program, name: &str, len: usize) -> Value {
+  assert!(
+    program
+      .iter_globals()
+      .all(|(_, v)| v.name().as_deref() != Some(name))
+      && program.funcs().values().all(|f| f.name() != name),
+    "name '{name}' is already used"
+  );
+  let init = program
+    .new_value()
+    .zero_init(Type::get_array(Type::get_i32(), len));
+  let counters = program.new_value().global_alloc(init);
+  program.set_value_name(counters, Some(name.into()));
+  counters
+}
+
+/// Returns the runtime function with the given name and parameter
+/// types, which returns nothing. Declares one if it does not exist.
+///
+/// # Panics
+///
+/// Panics if the name is not a valid global symbol name, or is used by
+/// a global value or a function of a different type.
+pub(in crate::opt) fn runtime_func(
+  program: &mut Program,
+  name: &str,
+  params_ty: Vec<Type>,
+) -> Function {
+  let ty = Type::get_function(params_ty.clone(), Type::get_unit());
+  let func = program
+    .func_layout()
+    .iter()
+    .find(|f| program.func(**f).name() == name)
+    .copied();
+  match func {
+    Some(func) => {
+      assert_eq!(
+        program.func(func).ty(),
+        &ty,
+        "runtime function '{name}' must have type {ty}"
+      );
+      func
+    }
+    None => program
+      .try_new_func(FunctionData::new_decl(
+        name.into(),
+        params_ty,
+        Type::get_unit(),
+      ))
+      .unwrap_or_else(|e| panic!("{e}")),
+  }
+}
+
+/// Sets the insert point of the given builder to the top of the given
+/// basic block.
+fn position_at_top(builder: &mut FunctionBuilder, bb: BasicBlock) {
+  let first = builder
+    .func()
+    .layout()
+    .bbs()
+    .node(&bb)
+    .unwrap()
+    .insts()
+    .front_key()
+    .copied();
+  match first {
+    Some(inst) => builder.position_before(inst),
+    None => builder.position_at_end(bb),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::back::KoopaGenerator;
+  use crate::front::Driver;
+  use std::str;
+
+  const SRC: &str = r#"
+    decl @getint(): i32
+
+    fun @main(): i32 {
+    %entry:
+      %x = alloc i32
+      %0 = call @getint()
+      br %0, %then, %end
+
+    %then:
+      unreachable
+
+    %end:
+      ret 0
+    }
+  "#;
+
+  fn dump(program: &Program) -> String {
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(program).unwrap();
+    str::from_utf8(&gen.writer()).unwrap().into()
+  }
+
+  #[test]
+  fn counter_array() {
+    let driver: Driver<_> = SRC.into();
+    let mut program = driver.generate_program().unwrap();
+    let mut pass = ProfileInstrument::new();
+    pass.run_on(&mut program);
+    let names: Vec<_> = pass
+      .blocks()
+      .iter()
+      .map(|b| b.bb.clone().unwrap())
+      .collect();
+    assert_eq!(names, ["%entry", "%then", "%end"]);
+    assert_eq!(
+      dump(&program),
+      r#"global @__koopa_profile_counters = alloc [i32, 3], zeroinit
+
+decl @getint(): i32
+
+fun @main(): i32 {
+%entry:
+  %x = alloc i32
+  %0 = getelemptr @__koopa_profile_counters, 0
+  %1 = load %0
+  %2 = add %1, 1
+  store %2, %0
+  %3 = call @getint()
+  br %3, %then, %end
+
+%then:
+  %4 = getelemptr @__koopa_profile_counters, 1
+  %5 = load %4
+  %6 = add %5, 1
+  store %6, %4
+  unreachable
+
+%end:
+  %7 = getelemptr @__koopa_profile_counters, 2
+  %8 = load %7
+  %9 = add %8, 1
+  store %9, %7
+  ret 0
+}
+"#
+    );
+  }
+
+  #[test]
+  fn runtime_call() {
+    let driver: Driver<_> = SRC.into();
+    let mut program = driver.generate_program().unwrap();
+    let pass = ProfileInstrument::with_style(CounterStyle::Call("@__koopa_profile".into()));
+    assert_eq!(pass.run(&mut program).len(), 3);
+    let text = dump(&program);
+    assert!(text.contains("decl @__koopa_profile(i32)"));
+    assert!(text.contains("%then:\n  call @__koopa_profile(1)\n  unreachable"));
+    // the runtime function is not instrumented if it is defined
+    let driver: Driver<_> = r#"
+      fun @__koopa_profile(@i: i32) {
+      %entry:
+        ret
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    assert!(pass.run(&mut program).is_empty());
+  }
+}
@@ -0,0 +1,253 @@
+//! Pointer calculation canonicalization pass ([`PtrCanonicalize`])
+//! related implementations.
+
+use crate::ir::dfg::DataFlowGraph;
+use crate::ir::{Function, FunctionData, Value, ValueKind};
+use crate::opt::{FunctionPass, Rewrite, RewritePass, Rewriter};
+
+/// A function pass that canonicalizes chains of `getptr`s and
+/// `getelemptr`s.
+///
+/// The following rewrites are performed, where `a` and `b` are integer
+/// constants and `a + b` does not overflow:
+///
+/// * `getptr p, 0` => `p`.
+/// * `getptr (getptr p, a), b` => `getptr p, a + b`.
+/// * `getptr (getelemptr p, a), b` => `getelemptr p, a + b`.
+///
+/// `getelemptr` changes the pointer type at each step, for example from
+/// `*[[i32, 3], 2]` to `*[i32, 3]` and then to `*i32`, so a chain of
+/// `getelemptr`s can not be merged into a single instruction. Only
+/// `getptr`s on top of the chain are merged, which keep the type of the
+/// source pointer. Merged indices may be out of the range of the array,
+/// the computed address is the same.
+///
+/// # Example
+///
+/// ```
+/// use koopa::front::Driver;
+/// use koopa::opt::*;
+///
+/// let driver: Driver<_> = r#"
+///   fun @f(@p: *[i32, 4]): i32 {
+///   %entry:
+///     %0 = getptr @p, 0
+///     %1 = getelemptr %0, 1
+///     %2 = getptr %1, 2
+///     %3 = load %2
+///     ret %3
+///   }
+/// "#
+/// .into();
+/// let mut program = driver.generate_program().unwrap();
+///
+/// let func = program.func_mut(program.func_layout()[0]);
+/// assert_eq!(PtrCanonicalize::new().run(func), 2);
+/// // only `getelemptr @p, 3`, the load and the return remain
+/// assert_eq!(func.layout().bbs().front_node().unwrap().insts().len(), 3);
+/// ```
+pub struct PtrCanonicalize {
+  pass: RewritePass,
+}
+
+impl PtrCanonicalize {
+  /// Creates a new pointer calculation canonicalization pass.
+  pub fn new() -> Self {
+    let mut pass = RewritePass::new();
+    pass.register(Box::new(GetPtrZero));
+    pass.register(Box::new(MergeGetPtr));
+    Self { pass }
+  }
+
+  /// Runs the pass on the given function.
+  /// Returns the number of applied rewrites.
+  pub fn run(&self, data: &mut FunctionData) -> usize {
+    self.pass.run(data)
+  }
+}
+
+impl Default for PtrCanonicalize {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl FunctionPass for PtrCanonicalize {
+  fn run_on(&mut self, _: Function, data: &mut FunctionData) {
+    self.run(data);
+  }
+
+  fn name(&self) -> &str {
+    "canonicalize-ptr"
+  }
+
+  fn fork(&self) -> Option<Box<dyn FunctionPass + Send>> {
+    Some(Box::new(Self::new()))
+  }
+}
+
+/// Returns the source and the index of the given `getptr` instruction.
+fn as_get_ptr(dfg: &DataFlowGraph, value: Value) -> Option<(Value, Value)> {
+  match dfg.values().get(&value)?.kind() {
+    ValueKind::GetPtr(gp) => Some((gp.src(), gp.index())),
+    _ => None,
+  }
+}
+
+/// `getptr p, 0` => `p`.
+struct GetPtrZero;
+
+impl Rewriter for GetPtrZero {
+  fn rewrite(&self, dfg: &DataFlowGraph, inst: Value) -> Option<Rewrite> {
+    let (src, index) = as_get_ptr(dfg, inst)?;
+    (dfg.const_int(index)? == 0).then_some(Rewrite::Value(src))
+  }
+}
+
+/// `getptr (getptr p, a), b` => `getptr p, a + b`, and
+/// `getptr (getelemptr p, a), b` => `getelemptr p, a + b`.
+struct MergeGetPtr;
+
+impl Rewriter for MergeGetPtr {
+  fn rewrite(&self, dfg: &DataFlowGraph, inst: Value) -> Option<Rewrite> {
+    let (src, index) = as_get_ptr(dfg, inst)?;
+    let b = dfg.const_int(index)?;
+    let (is_gep, p, a) = match dfg.values().get(&src)?.kind() {
+      ValueKind::GetPtr(gp) => (false, gp.src(), gp.index()),
+      ValueKind::GetElemPtr(gep) => (true, gep.src(), gep.index()),
+      _ => return None,
+    };
+    let sum = dfg.const_int(a)?.checked_add(b)?;
+    Some(Rewrite::build(move |builder| {
+      let index = builder.integer(sum);
+      if is_gep {
+        builder.get_elem_ptr(p, index)
+      } else {
+        builder.get_ptr(p, index)
+      }
+    }))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::back::KoopaGenerator;
+  use crate::front::Driver;
+  use crate::ir::values::{gep_result_type, get_ptr_result_type};
+  use crate::ir::{Error, Type};
+  use std::str;
+
+  fn canonicalize(src: &str) -> String {
+    let driver: Driver<_> = src.into();
+    let mut program = driver.generate_program().unwrap();
+    for func in program.func_layout().to_vec() {
+      PtrCanonicalize::new().run(program.func_mut(func));
+    }
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    str::from_utf8(&gen.writer()).unwrap().into()
+  }
+
+  #[test]
+  fn result_types() {
+    let i32 = Type::get_i32();
+    let a4 = Type::get_array(i32.clone(), 4);
+    let a34 = Type::get_array(a4.clone(), 3);
+    let a234 = Type::get_array(a34.clone(), 2);
+    // `int a[2][3][4]`
+    let ptr = Type::get_pointer(a234);
+    let ty = gep_result_type(&ptr).unwrap();
+    assert_eq!(ty, Type::get_pointer(a34.clone()));
+    let ty = gep_result_type(&ty).unwrap();
+    assert_eq!(ty, Type::get_pointer(a4.clone()));
+    let ty = gep_result_type(&ty).unwrap();
+    assert_eq!(ty, Type::get_pointer(i32.clone()));
+    assert_eq!(
+      gep_result_type(&ty),
+      Err(Error::TypeMismatch {
+        operand: "`src`",
+        expected: "a pointer of array",
+        found: ty.clone(),
+      })
+    );
+    // decayed `int a[][3][4]` parameter, which must be offset by
+    // `getptr` before being indexed by `getelemptr`
+    let param = Type::get_pointer(a34.clone());
+    assert_eq!(get_ptr_result_type(&param), Ok(param.clone()));
+    assert_eq!(gep_result_type(&param), Ok(Type::get_pointer(a4)));
+    assert!(get_ptr_result_type(&a34).is_err());
+    assert!(gep_result_type(&a34).is_err());
+  }
+
+  #[test]
+  fn merge_chains() {
+    let result = canonicalize(
+      r#"
+      global @a = alloc [[[i32, 4], 3], 2], zeroinit
+
+      fun @f(@p: *[[i32, 4], 3], @i: i32): i32 {
+      %entry:
+        %0 = getptr @p, 0
+        %1 = getelemptr %0, @i
+        %2 = getelemptr %1, 1
+        %3 = getptr %2, 2
+        %4 = getptr %3, -3
+        %5 = load %4
+        %6 = getptr @p, 1
+        %7 = getptr %6, 1
+        %8 = getelemptr %7, 0
+        %9 = getelemptr %8, 0
+        %10 = load %9
+        %11 = getelemptr @a, 1
+        %12 = getelemptr %11, 2
+        %13 = getelemptr %12, 3
+        %14 = getptr %13, 0
+        %15 = load %14
+        %16 = add %5, %10
+        %17 = add %16, %15
+        ret %17
+      }
+    "#,
+    );
+    assert_eq!(
+      result,
+      r#"global @a = alloc [[[i32, 4], 3], 2], zeroinit
+
+fun @f(@p: *[[i32, 4], 3], @i: i32): i32 {
+%entry:
+  %0 = getelemptr @p, @i
+  %1 = getelemptr %0, 0
+  %2 = load %1
+  %3 = getptr @p, 2
+  %4 = getelemptr %3, 0
+  %5 = getelemptr %4, 0
+  %6 = load %5
+  %7 = getelemptr @a, 1
+  %8 = getelemptr %7, 2
+  %9 = getelemptr %8, 3
+  %10 = load %9
+  %11 = add %2, %6
+  %12 = add %11, %10
+  ret %12
+}
+"#
+    );
+  }
+
+  #[test]
+  fn keep_non_constant() {
+    let src = r#"fun @f(@p: *i32, @i: i32): i32 {
+%entry:
+  %0 = getptr @p, @i
+  %1 = getptr %0, 1
+  %2 = getptr %1, 2147483647
+  %3 = load %2
+  ret %3
+}
+"#;
+    // the index of `%0` is not a constant,
+    // and the index overflows if `%1` and `%2` are merged
+    assert_eq!(canonicalize(src), src);
+  }
+}
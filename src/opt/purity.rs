@@ -0,0 +1,322 @@
+//! Function purity analysis ([`PurityInfo`]) related implementations.
+
+use crate::analysis::memory::{Access, MemRoot, MemoryInfo};
+use crate::ir::{Function, FunctionData, Program, ValueKind};
+use std::collections::{HashMap, HashSet};
+
+/// Purity of a function, ordered from the most pure to the least pure.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Purity {
+  /// The function has no side effects, and does not read any memory
+  /// except its local allocations. So the result of the function only
+  /// depends on its arguments.
+  Const,
+  /// The function has no side effects, but may read global variables
+  /// or memory pointed to by its arguments.
+  Pure,
+  /// The function may have side effects, or the purity is unknown.
+  Impure,
+}
+
+/// Purity information of all functions in a program.
+///
+/// The purity of a function definition is derived from its body:
+///
+/// * Storing to memory that is not a local allocation makes the
+///   function [`Impure`](Purity::Impure).
+/// * Loading from memory that is not a local allocation makes the
+///   function at most [`Pure`](Purity::Pure).
+/// * Calling a function makes the function at most as pure as the callee.
+///
+/// Functions calling each other are resolved by a fixpoint iteration
+/// on the strongly connected components of the call graph.
+///
+/// Declarations are impure, unless they are marked as `#[pure]`. And
+/// the `#[pure]` attribute of definitions is also trusted. Functions
+/// marked as `#[noreturn]` are always impure, since calls to them can
+/// not be removed.
+///
+/// # Example
+///
+/// ```
+/// use koopa::front::Driver;
+/// use koopa::opt::{Purity, PurityInfo};
+///
+/// let driver: Driver<_> = r#"
+///   global @g = alloc i32, 1
+///
+///   fun @get(): i32 {
+///   %entry:
+///     %0 = load @g
+///     ret %0
+///   }
+///
+///   fun @inc(): i32 {
+///   %entry:
+///     %0 = call @get()
+///     %1 = add %0, 1
+///     store %1, @g
+///     ret %1
+///   }
+/// "#
+/// .into();
+/// let program = driver.generate_program().unwrap();
+/// let (get, inc) = (program.func_layout()[0], program.func_layout()[1]);
+///
+/// let info = PurityInfo::new(&program);
+/// assert_eq!(info.purity(get), Purity::Pure);
+/// assert!(info.is_pure(get));
+/// assert!(!info.is_pure(inc));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct PurityInfo {
+  purity: HashMap<Function, Purity>,
+}
+
+impl PurityInfo {
+  /// Analyzes all functions in the given program.
+  pub fn new(program: &Program) -> Self {
+    let funcs = program.func_layout();
+    let mut local = HashMap::new();
+    let mut callees = HashMap::new();
+    for &func in funcs {
+      let (purity, calls) = local_purity(program.func(func));
+      local.insert(func, purity);
+      callees.insert(func, calls);
+    }
+    // SCCs are visited in the reverse topological order,
+    // so callees are always resolved before their callers
+    let mut purity = HashMap::new();
+    for scc in sccs(funcs, &callees) {
+      for &func in &scc {
+        purity.insert(func, local[&func]);
+      }
+      let mut changed = true;
+      while changed {
+        changed = false;
+        for &func in &scc {
+          let mut p = callees[&func]
+            .iter()
+            .map(|f| purity[f])
+            .fold(local[&func], Purity::max);
+          let data = program.func(func);
+          if data.is_pure() && !data.is_noreturn() {
+            p = p.min(Purity::Pure);
+          }
+          if p != purity[&func] {
+            purity.insert(func, p);
+            changed = true;
+          }
+        }
+      }
+    }
+    Self { purity }
+  }
+
+  /// Returns the purity of the given function.
+  ///
+  /// Returns [`Purity::Impure`] if the given function
+  /// was not in the analyzed program.
+  pub fn purity(&self, func: Function) -> Purity {
+    self.purity.get(&func).copied().unwrap_or(Purity::Impure)
+  }
+
+  /// Returns `true` if the given function has no side effects,
+  /// so unused calls to it can be removed.
+  pub fn is_pure(&self, func: Function) -> bool {
+    self.purity(func) <= Purity::Pure
+  }
+
+  /// Returns `true` if the given function has no side effects and
+  /// does not read memory, so calls to it with the same arguments
+  /// always return the same result.
+  pub fn is_const(&self, func: Function) -> bool {
+    self.purity(func) == Purity::Const
+  }
+}
+
+/// Returns the purity of the given function without considering calls,
+/// and callees of the function.
+fn local_purity(data: &FunctionData) -> (Purity, Vec<Function>) {
+  if data.is_noreturn() {
+    return (Purity::Impure, Vec::new());
+  }
+  if data.layout().entry_bb().is_none() {
+    // purity of declarations will be set by attributes
+    return (Purity::Impure, Vec::new());
+  }
+  let memory = MemoryInfo::new(data);
+  let is_local = |root| matches!(root, MemRoot::Alloc(_));
+  let mut purity = Purity::Const;
+  let mut callees = Vec::new();
+  for (_, node) in data.layout().bbs() {
+    for &inst in node.insts().keys() {
+      match memory.access(inst) {
+        Some(Access::Write(root)) if !is_local(root) => purity = Purity::Impure,
+        Some(Access::Read(root)) if !is_local(root) => purity = purity.max(Purity::Pure),
+        Some(Access::Call) => match data.dfg().value(inst).kind() {
+          ValueKind::Call(call) => callees.push(call.callee()),
+          _ => unreachable!(),
+        },
+        _ => {}
+      }
+    }
+  }
+  (purity, callees)
+}
+
+/// Returns strongly connected components of the call graph in the
+/// reverse topological order, by using Tarjan's algorithm.
+fn sccs(funcs: &[Function], callees: &HashMap<Function, Vec<Function>>) -> Vec<Vec<Function>> {
+  let mut index = HashMap::new();
+  let mut low = HashMap::new();
+  let mut stack = Vec::new();
+  let mut on_stack = HashSet::new();
+  let mut sccs = Vec::new();
+  for &root in funcs {
+    if index.contains_key(&root) {
+      continue;
+    }
+    // simulate the recursion by a stack of functions and
+    // the index of the next callee to be visited
+    let mut dfs = vec![(root, 0)];
+    while let Some((func, i)) = dfs.last_mut() {
+      let func = *func;
+      if *i == 0 && !index.contains_key(&func) {
+        index.insert(func, index.len());
+        low.insert(func, low.len());
+        stack.push(func);
+        on_stack.insert(func);
+      }
+      if let Some(&callee) = callees[&func].get(*i) {
+        *i += 1;
+        if !index.contains_key(&callee) {
+          dfs.push((callee, 0));
+        } else if on_stack.contains(&callee) {
+          let l = low[&func].min(index[&callee]);
+          low.insert(func, l);
+        }
+        continue;
+      }
+      dfs.pop();
+      if let Some((parent, _)) = dfs.last() {
+        let l = low[parent].min(low[&func]);
+        low.insert(*parent, l);
+      }
+      if low[&func] == index[&func] {
+        let mut scc = Vec::new();
+        loop {
+          let f = stack.pop().unwrap();
+          on_stack.remove(&f);
+          scc.push(f);
+          if f == func {
+            break;
+          }
+        }
+        sccs.push(scc);
+      }
+    }
+  }
+  sccs
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::front::Driver;
+  use crate::ir::builder::FunctionBuilder;
+  use crate::ir::Type;
+
+  #[test]
+  fn purity() {
+    let driver: Driver<_> = r#"
+      global @g = alloc i32, 1
+      decl @getint(): i32
+      decl @abs(i32): i32 #[pure]
+      decl @exit(i32) #[pure, noreturn]
+
+      fun @square(@x: i32): i32 {
+      %entry:
+        %p = alloc [i32, 2]
+        %q = getelemptr %p, 1
+        store @x, %q
+        %0 = load %q
+        %1 = mul %0, %0
+        ret %1
+      }
+
+      fun @fact(@n: i32): i32 {
+      %entry:
+        br @n, %rec, %end
+      %rec:
+        %0 = sub @n, 1
+        %1 = call @fact(%0)
+        %2 = mul @n, %1
+        ret %2
+      %end:
+        ret 1
+      }
+
+      fun @deref(@p: *i32): i32 {
+      %entry:
+        %0 = load @p
+        %1 = call @abs(%0)
+        %2 = call @square(%1)
+        ret %2
+      }
+
+      fun @input(): i32 {
+      %entry:
+        %0 = call @getint()
+        ret %0
+      }
+
+      fun @set(@p: *i32) {
+      %entry:
+        store 1, @p
+        ret
+      }
+
+      fun @trusted(): i32 #[pure] {
+      %entry:
+        store 1, @g
+        %0 = call @input()
+        ret %0
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    // functions calling each other
+    let ping = program.new_func(FunctionData::new("@ping".into(), vec![], Type::get_unit()));
+    let pong = program.new_func(FunctionData::new("@pong".into(), vec![], Type::get_unit()));
+    for (from, to) in [(ping, pong), (pong, ping)] {
+      let mut builder = FunctionBuilder::new(program.func_mut(from));
+      let entry = builder.create_bb(None);
+      builder.position_at_end(entry);
+      builder.call(to, vec![]);
+      builder.ret(None);
+    }
+    let info = PurityInfo::new(&program);
+    let purity: Vec<_> = program
+      .func_layout()
+      .iter()
+      .map(|f| (program.func(*f).name(), info.purity(*f)))
+      .collect();
+    assert_eq!(
+      purity,
+      [
+        ("@getint", Purity::Impure),
+        ("@abs", Purity::Pure),
+        ("@exit", Purity::Impure),
+        ("@square", Purity::Const),
+        ("@fact", Purity::Const),
+        ("@deref", Purity::Pure),
+        ("@input", Purity::Impure),
+        ("@set", Purity::Impure),
+        ("@trusted", Purity::Pure),
+        ("@ping", Purity::Const),
+        ("@pong", Purity::Const),
+      ]
+    );
+  }
+}
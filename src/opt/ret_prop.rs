@@ -0,0 +1,262 @@
+//! Return value propagation pass ([`ReturnPropagation`]) related
+//! implementations.
+
+use crate::analysis::cfg;
+use crate::ir::builder_traits::*;
+use crate::ir::{FunctionData, Program, ValueKind};
+use crate::opt::rewrite::remove_dead;
+use crate::opt::{ModulePass, PurityInfo};
+
+/// A module pass that propagates return values of functions to the call
+/// sites of them.
+///
+/// A function definition is analyzed if it always returns:
+///
+/// * The same integer constant. Results of calls to the function are
+///   replaced with the constant.
+/// * The same parameter. Results of calls to the function are replaced
+///   with the corresponding arguments.
+///
+/// Only reachable basic blocks are analyzed, and functions that have no
+/// `ret`s, or have reachable `unreachable`s, are skipped. Calls are
+/// removed after the replacement if the callee has no side effects (see
+/// [`PurityInfo::is_pure`]), otherwise they are kept for the side
+/// effects. Replacements may make callers return constants or their
+/// parameters too, so the pass runs until no more calls can be folded.
+///
+/// # Example
+///
+/// ```
+/// use koopa::front::Driver;
+/// use koopa::opt::*;
+///
+/// let driver: Driver<_> = r#"
+///   fun @answer(): i32 {
+///   %entry:
+///     ret 42
+///   }
+///
+///   fun @main(): i32 {
+///   %entry:
+///     %0 = call @answer()
+///     %1 = add %0, 1
+///     ret %1
+///   }
+/// "#
+/// .into();
+/// let mut program = driver.generate_program().unwrap();
+///
+/// assert_eq!(ReturnPropagation::new().run(&mut program), 1);
+/// let main = program.func(program.func_layout()[1]);
+/// // the call is removed, since `@answer` has no side effects
+/// assert_eq!(main.dfg().values().len(), 4);
+/// ```
+#[derive(Default)]
+pub struct ReturnPropagation;
+
+impl ReturnPropagation {
+  /// Creates a new return value propagation pass.
+  pub fn new() -> Self {
+    Self
+  }
+
+  /// Runs the pass on the given program.
+  /// Returns the number of folded calls.
+  pub fn run(&self, program: &mut Program) -> usize {
+    let mut count = 0;
+    let mut changed = true;
+    while changed {
+      changed = false;
+      let purity = PurityInfo::new(program);
+      for func in program.func_layout().to_vec() {
+        let Some(ret) = returned(program.func(func)) else {
+          continue;
+        };
+        let is_pure = purity.is_pure(func);
+        let sites: Vec<_> = program.func_users(func).iter().copied().collect();
+        for (caller, call) in sites {
+          let data = program.func_mut(caller);
+          let dfg = data.dfg();
+          let used = !dfg.value(call).used_by().is_empty();
+          if !used && !is_pure {
+            continue;
+          }
+          if used {
+            let new = match ret {
+              Returned::Integer(i) => data.dfg_mut().new_value().integer(i),
+              Returned::Param(i) => match dfg.value(call).kind() {
+                ValueKind::Call(c) => c.args()[i],
+                _ => unreachable!(),
+              },
+            };
+            data.dfg_mut().replace_all_uses_with(call, new);
+          }
+          if is_pure {
+            remove_dead(data, vec![call]);
+          }
+          count += 1;
+          changed = true;
+        }
+      }
+    }
+    count
+  }
+}
+
+impl ModulePass for ReturnPropagation {
+  fn run_on(&mut self, program: &mut Program) {
+    self.run(program);
+  }
+
+  fn name(&self) -> &str {
+    "return-prop"
+  }
+}
+
+/// Value always returned by a function.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Returned {
+  /// An integer constant.
+  Integer(i32),
+  /// The parameter at the given index.
+  Param(usize),
+}
+
+/// Returns the value always returned by the given function,
+/// or `None` if there is no such value.
+fn returned(data: &FunctionData) -> Option<Returned> {
+  let dfg = data.dfg();
+  let mut result = None;
+  for bb in cfg::rpo(data) {
+    let term = *data.layout().bbs().node(&bb).unwrap().insts().back_key()?;
+    let value = match dfg.value(term).kind() {
+      ValueKind::Return(ret) => ret.value()?,
+      ValueKind::Unreachable(_) => return None,
+      _ => continue,
+    };
+    let ret = match dfg.value(value).kind() {
+      ValueKind::Integer(i) => Returned::Integer(i.value()),
+      _ => Returned::Param(data.params().iter().position(|p| *p == value)?),
+    };
+    if result.is_some_and(|r| r != ret) {
+      return None;
+    }
+    result = Some(ret);
+  }
+  result
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::back::KoopaGenerator;
+  use crate::front::Driver;
+  use std::str;
+
+  #[test]
+  fn fold_returns() {
+    let driver: Driver<_> = r#"
+      global @g = alloc i32, 0
+
+      fun @pure_const(@x: i32): i32 {
+      %entry:
+        br @x, %a, %b
+
+      %a:
+        ret 7
+
+      %b:
+        ret 7
+      }
+
+      fun @impure_const(): i32 {
+      %entry:
+        store 1, @g
+        ret 7
+      }
+
+      fun @pure_id(@x: i32, @y: i32): i32 {
+      %entry:
+        ret @y
+      }
+
+      fun @impure_id(@x: i32): i32 {
+      %entry:
+        store @x, @g
+        ret @x
+      }
+
+      fun @differ(@x: i32): i32 {
+      %entry:
+        br @x, %a, %b
+
+      %a:
+        ret 1
+
+      %b:
+        ret @x
+      }
+
+      fun @main(@x: i32): i32 {
+      %entry:
+        %a = call @pure_const(@x)
+        %b = call @impure_const()
+        %c = call @pure_id(1, @x)
+        %d = call @impure_id(@x)
+        %e = call @differ(@x)
+        %f = add %a, %b
+        %g = add %c, %d
+        %h = add %f, %g
+        %i = add %h, %e
+        ret %i
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    assert_eq!(ReturnPropagation::new().run(&mut program), 4);
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    let text = str::from_utf8(&gen.writer()).unwrap().to_string();
+    let main = &text[text.find("fun @main").unwrap()..];
+    assert_eq!(
+      main,
+      r#"fun @main(@x: i32): i32 {
+%entry:
+  %b = call @impure_const()
+  %d = call @impure_id(@x)
+  %e = call @differ(@x)
+  %f = add 7, 7
+  %g = add @x, @x
+  %h = add %f, %g
+  %i = add %h, %e
+  ret %i
+}
+"#
+    );
+  }
+
+  #[test]
+  fn skip_unreachable() {
+    let driver: Driver<_> = r#"
+      fun @f(@x: i32): i32 {
+      %entry:
+        br @x, %a, %b
+
+      %a:
+        ret 1
+
+      %b:
+        unreachable
+      }
+
+      fun @main(): i32 {
+      %entry:
+        %0 = call @f(0)
+        ret %0
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    assert_eq!(ReturnPropagation::new().run(&mut program), 0);
+  }
+}
@@ -0,0 +1,437 @@
+//! Pattern-matching rewriter ([`Rewriter`]) related implementations.
+//!
+//! A rewriter matches a small pattern on an instruction, and describes
+//! how to rewrite it by returning a [`Rewrite`]. The [`RewritePass`]
+//! applies rewriters to all instructions of a function until no more
+//! rewrites can be applied, and handles creating the replacement values,
+//! inserting them into the layout, replacing all uses of the rewritten
+//! instruction and removing it.
+//!
+//! # Example
+//!
+//! Rewrite `add x, 0` and `add 0, x` to `x`:
+//!
+//! ```
+//! use koopa::back::KoopaGenerator;
+//! use koopa::front::Driver;
+//! use koopa::ir::dfg::DataFlowGraph;
+//! use koopa::ir::{BinaryOp, Value, ValueKind};
+//! use koopa::opt::*;
+//!
+//! struct AddZero;
+//!
+//! impl Rewriter for AddZero {
+//!   fn rewrite(&self, dfg: &DataFlowGraph, inst: Value) -> Option<Rewrite> {
+//!     let ValueKind::Binary(bin) = dfg.value(inst).kind() else {
+//!       return None;
+//!     };
+//!     let is_zero = |v| dfg.value(v).kind().as_integer() == Some(0);
+//!     match bin.op() {
+//!       BinaryOp::Add if is_zero(bin.rhs()) => Some(Rewrite::Value(bin.lhs())),
+//!       BinaryOp::Add if is_zero(bin.lhs()) => Some(Rewrite::Value(bin.rhs())),
+//!       _ => None,
+//!     }
+//!   }
+//! }
+//!
+//! let driver: Driver<_> = r#"
+//!   fun @f(@x: i32): i32 {
+//!   %entry:
+//!     %0 = add @x, 0
+//!     %1 = add 0, %0
+//!     ret %1
+//!   }
+//! "#
+//! .into();
+//! let mut program = driver.generate_program().unwrap();
+//!
+//! let mut pass = RewritePass::new();
+//! pass.register(Box::new(AddZero));
+//! let mut passman = PassManager::new();
+//! passman.register(Pass::Function(Box::new(pass)));
+//! passman.run_passes(&mut program);
+//!
+//! let mut gen = KoopaGenerator::new(Vec::new());
+//! gen.generate_on(&program).unwrap();
+//! assert_eq!(
+//!   std::str::from_utf8(&gen.writer()).unwrap(),
+//!   "fun @f(@x: i32): i32 {\n%entry:\n  ret @x\n}\n",
+//! );
+//! ```
+
+use crate::ir::builder::FunctionBuilder;
+use crate::ir::dfg::DataFlowGraph;
+use crate::ir::{Function, FunctionData, Value, ValueKind};
+use crate::opt::FunctionPass;
+
+/// Trait of a rewriter.
+///
+/// Rewriters match patterns on instructions, and describe how to
+/// rewrite the matched instructions.
+///
+/// Rewriters must be [`Send`], so built-in passes that consist of
+/// rewriters can run in parallel, see
+/// [`PassManager::run_parallel`](crate::opt::PassManager::run_parallel).
+pub trait Rewriter: Send {
+  /// Tries to match a pattern on the given instruction in the given
+  /// data flow graph. Returns a [`Rewrite`] if the instruction can be
+  /// rewritten, or `None` otherwise.
+  ///
+  /// The returned rewrite must make progress, or [`RewritePass`] may
+  /// never reach the fixpoint.
+  fn rewrite(&self, dfg: &DataFlowGraph, inst: Value) -> Option<Rewrite>;
+}
+
+/// A rewrite of an instruction, returned by [`Rewriter::rewrite`].
+///
+/// The replacement value must have the same type as the rewritten
+/// instruction.
+pub enum Rewrite {
+  /// Replaces the instruction with an existing value.
+  Value(Value),
+  /// Creates new values by the given closure, and replaces the
+  /// instruction with the value returned by the closure.
+  ///
+  /// The builder passed to the closure inserts new instructions
+  /// before the rewritten instruction.
+  Build(Box<dyn FnOnce(&mut FunctionBuilder) -> Value>),
+}
+
+impl Rewrite {
+  /// Creates a [`Rewrite::Build`] by the given closure.
+  pub fn build<F>(f: F) -> Self
+  where
+    F: FnOnce(&mut FunctionBuilder) -> Value + 'static,
+  {
+    Rewrite::Build(Box::new(f))
+  }
+}
+
+/// A function pass that applies the registered rewriters to all
+/// instructions of a function, until no more rewrites can be applied.
+///
+/// For each instruction, rewriters are tried in the order of
+/// registration, and only the first matched rewrite is applied.
+/// Instructions and constants that become unused after rewriting, and
+/// have no side effects, are removed. Instructions with ordering
+/// constraints (see
+/// [`FunctionData::try_add_order_constraint`](crate::ir::FunctionData::try_add_order_constraint))
+/// are never rewritten.
+#[derive(Default)]
+pub struct RewritePass {
+  rewriters: Vec<Box<dyn Rewriter>>,
+}
+
+impl RewritePass {
+  /// Creates a new rewrite pass with no rewriters.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Registers a new rewriter.
+  pub fn register(&mut self, rewriter: Box<dyn Rewriter>) {
+    self.rewriters.push(rewriter);
+  }
+
+  /// Applies rewriters to the given function until the fixpoint.
+  /// Returns the number of applied rewrites.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the type of the replacement value is different from
+  /// the type of the rewritten instruction.
+  pub fn run(&self, data: &mut FunctionData) -> usize {
+    let mut count = 0;
+    loop {
+      let changed = self.run_once(data);
+      if changed == 0 {
+        return count;
+      }
+      count += changed;
+    }
+  }
+
+  /// Applies rewriters to all instructions in the layout once.
+  /// Returns the number of applied rewrites.
+  fn run_once(&self, data: &mut FunctionData) -> usize {
+    let insts: Vec<_> = data
+      .layout()
+      .bbs()
+      .iter()
+      .flat_map(|(_, node)| node.insts().keys().copied())
+      .collect();
+    let mut count = 0;
+    for inst in insts {
+      // the instruction may have been removed as a dead operand,
+      // and instructions with ordering constraints are never replaced
+      if data.layout().parent_bb(inst).is_none() || data.has_order_constraints(inst) {
+        continue;
+      }
+      let rewrite = self
+        .rewriters
+        .iter()
+        .find_map(|r| r.rewrite(data.dfg(), inst));
+      if rewrite.is_some_and(|r| apply(data, inst, r)) {
+        count += 1;
+      }
+    }
+    count
+  }
+}
+
+impl FunctionPass for RewritePass {
+  fn run_on(&mut self, _: Function, data: &mut FunctionData) {
+    self.run(data);
+  }
+
+  fn name(&self) -> &str {
+    "rewrite"
+  }
+}
+
+/// Applies the given rewrite to the given instruction.
+/// Returns `false` if the instruction is replaced with itself.
+fn apply(data: &mut FunctionData, inst: Value, rewrite: Rewrite) -> bool {
+  let new = match rewrite {
+    Rewrite::Value(value) => value,
+    Rewrite::Build(f) => {
+      let mut builder = FunctionBuilder::new(data);
+      builder.position_before(inst);
+      f(&mut builder)
+    }
+  };
+  if new == inst {
+    return false;
+  }
+  if let Some(new) = data.dfg().values().get(&new) {
+    assert!(
+      new.ty() == data.dfg().value(inst).ty(),
+      "type of the replacement is different from the instruction"
+    );
+  }
+  data.dfg_mut().replace_all_uses_with(inst, new);
+  remove_dead(data, vec![inst]);
+  true
+}
+
+/// Removes the given unused values, and all operands of them that
+/// become dead (see [`is_dead`]).
+pub(super) fn remove_dead(data: &mut FunctionData, mut worklist: Vec<Value>) {
+  while let Some(value) = worklist.pop() {
+    if let Some(bb) = data.layout().parent_bb(value) {
+      data.layout_mut().bb_mut(bb).insts_mut().remove(&value);
+    }
+    let removed = data.dfg_mut().remove_value(value);
+    for v in removed.kind().value_uses() {
+      if is_dead(data.dfg(), v) && !worklist.contains(&v) {
+        worklist.push(v);
+      }
+    }
+  }
+}
+
+/// Returns `true` if the given value is a local value that is no
+/// longer used and has no side effects.
+pub(super) fn is_dead(dfg: &DataFlowGraph, value: Value) -> bool {
+  dfg.values().get(&value).is_some_and(|data| {
+    data.used_by().is_empty()
+      && matches!(
+        data.kind(),
+        ValueKind::Integer(_)
+          | ValueKind::ZeroInit(_)
+          | ValueKind::Undef(_)
+          | ValueKind::Aggregate(_)
+          | ValueKind::Bytes(_)
+          | ValueKind::GetPtr(_)
+          | ValueKind::GetElemPtr(_)
+          | ValueKind::Binary(_)
+      )
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::back::KoopaGenerator;
+  use crate::front::Driver;
+  use crate::ir::BinaryOp;
+  use std::str;
+
+  /// `add x, 0` => `x`, `add 0, x` => `x`.
+  struct AddZero;
+
+  impl Rewriter for AddZero {
+    fn rewrite(&self, dfg: &DataFlowGraph, inst: Value) -> Option<Rewrite> {
+      let ValueKind::Binary(bin) = dfg.value(inst).kind() else {
+        return None;
+      };
+      match bin.op() {
+        BinaryOp::Add if dfg.const_int(bin.rhs()) == Some(0) => Some(Rewrite::Value(bin.lhs())),
+        BinaryOp::Add if dfg.const_int(bin.lhs()) == Some(0) => Some(Rewrite::Value(bin.rhs())),
+        _ => None,
+      }
+    }
+  }
+
+  /// `mul x, 2^k` => `shl x, k`, for `k > 0`.
+  struct MulPow2;
+
+  impl Rewriter for MulPow2 {
+    fn rewrite(&self, dfg: &DataFlowGraph, inst: Value) -> Option<Rewrite> {
+      let ValueKind::Binary(bin) = dfg.value(inst).kind() else {
+        return None;
+      };
+      if bin.op() != BinaryOp::Mul {
+        return None;
+      }
+      let rhs = dfg.const_int(bin.rhs())? as u32;
+      if rhs <= 1 || !rhs.is_power_of_two() {
+        return None;
+      }
+      let lhs = bin.lhs();
+      Some(Rewrite::build(move |builder| {
+        let k = builder.integer(rhs.trailing_zeros() as i32);
+        builder.binary(BinaryOp::Shl, lhs, k)
+      }))
+    }
+  }
+
+  /// `sub 0, (sub 0, x)` => `x`.
+  struct DoubleNeg;
+
+  impl Rewriter for DoubleNeg {
+    fn rewrite(&self, dfg: &DataFlowGraph, inst: Value) -> Option<Rewrite> {
+      let as_neg = |v: Value| match dfg.values().get(&v)?.kind() {
+        ValueKind::Binary(bin)
+          if bin.op() == BinaryOp::Sub && dfg.const_int(bin.lhs()) == Some(0) =>
+        {
+          Some(bin.rhs())
+        }
+        _ => None,
+      };
+      Some(Rewrite::Value(as_neg(as_neg(inst)?)?))
+    }
+  }
+
+  fn rewrite(src: &str) -> (usize, String) {
+    let driver: Driver<_> = src.into();
+    let mut program = driver.generate_program().unwrap();
+    let mut pass = RewritePass::new();
+    pass.register(Box::new(AddZero));
+    pass.register(Box::new(MulPow2));
+    pass.register(Box::new(DoubleNeg));
+    let func = program.func_layout()[0];
+    let count = pass.run(program.func_mut(func));
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    (count, str::from_utf8(&gen.writer()).unwrap().into())
+  }
+
+  #[test]
+  fn add_zero() {
+    let (count, ir) = rewrite(
+      r#"
+      fun @f(@x: i32): i32 {
+      %entry:
+        %0 = add @x, 0
+        %1 = add 0, %0
+        %2 = add %1, 1
+        ret %2
+      }
+    "#,
+    );
+    assert_eq!(count, 2);
+    assert_eq!(
+      ir,
+      "fun @f(@x: i32): i32 {\n%entry:\n  %0 = add @x, 1\n  ret %0\n}\n"
+    );
+  }
+
+  #[test]
+  fn mul_pow2() {
+    let (count, ir) = rewrite(
+      r#"
+      fun @f(@x: i32): i32 {
+      %entry:
+        %0 = mul @x, 8
+        %1 = mul %0, 6
+        %2 = mul %1, 1
+        ret %2
+      }
+    "#,
+    );
+    assert_eq!(count, 1);
+    assert_eq!(
+      ir,
+      r#"fun @f(@x: i32): i32 {
+%entry:
+  %0 = shl @x, 3
+  %1 = mul %0, 6
+  %2 = mul %1, 1
+  ret %2
+}
+"#
+    );
+  }
+
+  #[test]
+  fn double_neg() {
+    // rewrites apply to the new instructions until the fixpoint
+    let (count, ir) = rewrite(
+      r#"
+      fun @f(@x: i32): i32 {
+      %entry:
+        %0 = sub 0, @x
+        %1 = sub 0, %0
+        %2 = mul %1, 4
+        %3 = sub 0, %2
+        %4 = sub 0, %3
+        %5 = add %4, 0
+        ret %5
+      }
+    "#,
+    );
+    assert_eq!(count, 4);
+    assert_eq!(
+      ir,
+      "fun @f(@x: i32): i32 {\n%entry:\n  %0 = shl @x, 2\n  ret %0\n}\n"
+    );
+  }
+
+  #[test]
+  fn keep_constrained() {
+    let driver: Driver<_> = r#"
+      fun @f(@x: i32): i32 {
+      %entry:
+        %0 = add @x, 0
+        %1 = mul %0, 4
+        ret %1
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    let data = program.func_mut(program.func_layout()[0]);
+    let entry = data.layout().entry_bb().unwrap();
+    let insts: Vec<_> = data
+      .layout()
+      .bbs()
+      .node(&entry)
+      .unwrap()
+      .insts()
+      .keys()
+      .copied()
+      .collect();
+    data.add_order_constraint(insts[0], insts[2]);
+    let mut pass = RewritePass::new();
+    pass.register(Box::new(AddZero));
+    pass.register(Box::new(MulPow2));
+    // only the `mul` is rewritten
+    assert_eq!(pass.run(data), 1);
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    assert_eq!(
+      str::from_utf8(&gen.writer()).unwrap(),
+      "fun @f(@x: i32): i32 {\n%entry:\n  %0 = add @x, 0\n  %1 = shl %0, 2\n  ret %1\n}\n"
+    );
+  }
+}
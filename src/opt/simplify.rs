@@ -0,0 +1,424 @@
+//! Algebraic simplification pass ([`AlgebraicSimplify`]) related
+//! implementations.
+
+use crate::analysis::cost::CostModel;
+use crate::ir::builder::FunctionBuilder;
+use crate::ir::dfg::DataFlowGraph;
+use crate::ir::{BinaryOp, Function, FunctionData, OverflowMode, Type, Value, ValueKind};
+use crate::opt::{FunctionPass, Rewrite, RewritePass, Rewriter};
+use std::sync::Arc;
+
+/// A function pass that performs strength reduction and algebraic
+/// simplification on integer binary operations.
+///
+/// The following rewrites are performed, where `k > 0`:
+///
+/// * `mul x, 0` => `0`, `mul x, 1` => `x`.
+/// * `mul x, 2^k` => `shl x, k`.
+/// * `sub x, x` => `0`.
+/// * `div x, 1` => `x`, `mod x, 1` => `0`.
+/// * `div x, 2^k` and `mod x, 2^k` => shifts and masks.
+///
+/// Signed division rounds towards zero, but the arithmetic shift right
+/// rounds towards negative infinity. So `x` is biased by `2^k - 1` if it
+/// is negative before shifting, which takes three more instructions:
+///
+/// ```koopa
+/// %sign = sar x, k - 1
+/// %bias = shr %sign, 32 - k
+/// %biased = add x, %bias
+/// %div = sar %biased, k
+/// ```
+///
+/// And `mod x, 2^k` is computed as `x - (%biased & -2^k)`.
+///
+/// Divisions and modulos by negative constants are left unchanged.
+/// Multiplications by `2^k` are only rewritten in data flow graphs with
+/// [`OverflowMode::Wrapping`], since shifts never saturate or trap.
+///
+/// By default, divisions and modulos by `2^k` are always expanded.
+/// With a [`CostModel`], they are only expanded if the new instructions
+/// cost no more than the division or modulo, see
+/// [`AlgebraicSimplify::with_cost_model`].
+pub struct AlgebraicSimplify {
+  pass: RewritePass,
+  cost_model: Option<CostModelRef>,
+}
+
+/// A shared cost model.
+type CostModelRef = Arc<dyn CostModel + Send + Sync>;
+
+impl AlgebraicSimplify {
+  /// Creates a new algebraic simplification pass.
+  pub fn new() -> Self {
+    Self::with_cost_model_ref(None)
+  }
+
+  /// Creates a new algebraic simplification pass, which only expands
+  /// divisions and modulos by `2^k` if the expansion is profitable in
+  /// the given cost model.
+  pub fn with_cost_model<M>(cost_model: M) -> Self
+  where
+    M: CostModel + Send + Sync + 'static,
+  {
+    Self::with_cost_model_ref(Some(Arc::new(cost_model)))
+  }
+
+  /// Creates a new algebraic simplification pass with the given
+  /// optional cost model.
+  fn with_cost_model_ref(cost_model: Option<CostModelRef>) -> Self {
+    let mut pass = RewritePass::new();
+    pass.register(Box::new(MulConst));
+    pass.register(Box::new(SubSelf));
+    pass.register(Box::new(DivPow2(cost_model.clone())));
+    pass.register(Box::new(ModPow2(cost_model.clone())));
+    Self { pass, cost_model }
+  }
+
+  /// Runs the pass on the given function.
+  /// Returns the number of applied rewrites.
+  pub fn run(&self, data: &mut FunctionData) -> usize {
+    self.pass.run(data)
+  }
+}
+
+impl Default for AlgebraicSimplify {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl FunctionPass for AlgebraicSimplify {
+  fn run_on(&mut self, _: Function, data: &mut FunctionData) {
+    self.run(data);
+  }
+
+  fn name(&self) -> &str {
+    "simplify"
+  }
+
+  fn fork(&self) -> Option<Box<dyn FunctionPass + Send>> {
+    Some(Box::new(Self::with_cost_model_ref(self.cost_model.clone())))
+  }
+}
+
+/// Returns the binary operator and operands of the given instruction.
+fn as_binary(dfg: &DataFlowGraph, inst: Value) -> Option<(BinaryOp, Value, Value)> {
+  match dfg.value(inst).kind() {
+    ValueKind::Binary(bin) => Some((bin.op(), bin.lhs(), bin.rhs())),
+    _ => None,
+  }
+}
+
+/// Returns `k` if the given value is a positive constant `2^k`.
+fn as_pow2(dfg: &DataFlowGraph, value: Value) -> Option<u32> {
+  let n = dfg.const_int(value)?;
+  (n > 0 && (n as u32).is_power_of_two()).then(|| n.trailing_zeros())
+}
+
+/// Builds `x + (x < 0 ? 2^k - 1 : 0)`, for `k > 0`.
+fn build_biased(builder: &mut FunctionBuilder, x: Value, k: u32) -> Value {
+  let sign = if k == 1 {
+    // the sign bit is shifted to the lowest bit directly
+    x
+  } else {
+    let amount = builder.integer(k as i32 - 1);
+    builder.binary(BinaryOp::Sar, x, amount)
+  };
+  let amount = builder.integer(32 - k as i32);
+  let bias = builder.binary(BinaryOp::Shr, sign, amount);
+  builder.binary(BinaryOp::Add, x, bias)
+}
+
+/// Builds `div x, 2^k` by shifts, for `k > 0`.
+fn build_div(builder: &mut FunctionBuilder, x: Value, k: u32) -> Value {
+  let biased = build_biased(builder, x, k);
+  let amount = builder.integer(k as i32);
+  builder.binary(BinaryOp::Sar, biased, amount)
+}
+
+/// Builds `mod x, 2^k` by shifts and masks, for `k > 0`.
+fn build_mod(builder: &mut FunctionBuilder, x: Value, k: u32) -> Value {
+  let biased = build_biased(builder, x, k);
+  let mask = builder.integer(-(1 << k));
+  let rounded = builder.binary(BinaryOp::And, biased, mask);
+  builder.binary(BinaryOp::Sub, x, rounded)
+}
+
+/// Returns `true` if expanding the given instruction by the given
+/// function costs no more than the instruction in the given cost model,
+/// or there is no cost model.
+///
+/// The expansion is built in a scratch function to be measured.
+fn is_profitable(
+  cost_model: &Option<CostModelRef>,
+  dfg: &DataFlowGraph,
+  inst: Value,
+  k: u32,
+  expand: fn(&mut FunctionBuilder, Value, u32) -> Value,
+) -> bool {
+  let Some(cost_model) = cost_model else {
+    return true;
+  };
+  let mut scratch = FunctionData::new("@scratch".into(), vec![Type::get_i32()], Type::get_i32());
+  let x = scratch.params()[0];
+  let mut builder = FunctionBuilder::new(&mut scratch);
+  let entry = builder.create_bb(None);
+  builder.position_at_end(entry);
+  expand(&mut builder, x, k);
+  cost_model.total_cost(&scratch) <= cost_model.cost(dfg, inst)
+}
+
+/// `mul x, 0` => `0`, `mul x, 1` => `x`, `mul x, 2^k` => `shl x, k`,
+/// and the same for constants on the left-hand side.
+///
+/// Multiplications wrap around, so `i32::MIN` is treated as `2^31`.
+/// Shifts are not used if the overflow semantics is not wrapping.
+struct MulConst;
+
+impl Rewriter for MulConst {
+  fn rewrite(&self, dfg: &DataFlowGraph, inst: Value) -> Option<Rewrite> {
+    let (BinaryOp::Mul, lhs, rhs) = as_binary(dfg, inst)? else {
+      return None;
+    };
+    let (x, n) = match (dfg.const_int(lhs), dfg.const_int(rhs)) {
+      (_, Some(n)) => (lhs, n),
+      (Some(n), None) => (rhs, n),
+      (None, None) => return None,
+    };
+    match n as u32 {
+      0 => Some(Rewrite::build(|b| b.integer(0))),
+      1 => Some(Rewrite::Value(x)),
+      n if n.is_power_of_two() && dfg.overflow_mode() == OverflowMode::Wrapping => {
+        Some(Rewrite::build(move |b| {
+          let k = b.integer(n.trailing_zeros() as i32);
+          b.binary(BinaryOp::Shl, x, k)
+        }))
+      }
+      _ => None,
+    }
+  }
+}
+
+/// `sub x, x` => `0`.
+struct SubSelf;
+
+impl Rewriter for SubSelf {
+  fn rewrite(&self, dfg: &DataFlowGraph, inst: Value) -> Option<Rewrite> {
+    match as_binary(dfg, inst)? {
+      (BinaryOp::Sub, lhs, rhs) if lhs == rhs => Some(Rewrite::build(|b| b.integer(0))),
+      _ => None,
+    }
+  }
+}
+
+/// `div x, 1` => `x`, `div x, 2^k` => biased arithmetic shift right.
+struct DivPow2(Option<CostModelRef>);
+
+impl Rewriter for DivPow2 {
+  fn rewrite(&self, dfg: &DataFlowGraph, inst: Value) -> Option<Rewrite> {
+    let (BinaryOp::Div, x, rhs) = as_binary(dfg, inst)? else {
+      return None;
+    };
+    match as_pow2(dfg, rhs)? {
+      0 => Some(Rewrite::Value(x)),
+      k if is_profitable(&self.0, dfg, inst, k, build_div) => {
+        Some(Rewrite::build(move |b| build_div(b, x, k)))
+      }
+      _ => None,
+    }
+  }
+}
+
+/// `mod x, 1` => `0`, `mod x, 2^k` => `x - (biased & -2^k)`.
+struct ModPow2(Option<CostModelRef>);
+
+impl Rewriter for ModPow2 {
+  fn rewrite(&self, dfg: &DataFlowGraph, inst: Value) -> Option<Rewrite> {
+    let (BinaryOp::Mod, x, rhs) = as_binary(dfg, inst)? else {
+      return None;
+    };
+    match as_pow2(dfg, rhs)? {
+      0 => Some(Rewrite::build(|b| b.integer(0))),
+      k if is_profitable(&self.0, dfg, inst, k, build_mod) => {
+        Some(Rewrite::build(move |b| build_mod(b, x, k)))
+      }
+      _ => None,
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::analysis::cost::{UnitCost, WeightedCost};
+  use crate::back::KoopaGenerator;
+  use crate::front::Driver;
+  use crate::ir::Program;
+  use std::str;
+
+  const SRC: &str = r#"
+    fun @div2(@x: i32): i32 {
+    %entry:
+      %0 = div @x, 2
+      ret %0
+    }
+
+    fun @div8(@x: i32): i32 {
+    %entry:
+      %0 = div @x, 8
+      ret %0
+    }
+
+    fun @mod4(@x: i32): i32 {
+    %entry:
+      %0 = mod @x, 4
+      ret %0
+    }
+
+    fun @mod1024(@x: i32): i32 {
+    %entry:
+      %0 = mod @x, 1024
+      ret %0
+    }
+
+    fun @div_neg(@x: i32): i32 {
+    %entry:
+      %0 = div @x, -4
+      %1 = mod @x, -4
+      %2 = add %0, %1
+      ret %2
+    }
+
+    fun @mul(@x: i32): i32 {
+    %entry:
+      %0 = mul @x, 16
+      %1 = mul -2147483648, @x
+      %2 = mul @x, 0
+      %3 = mul %2, @x
+      %4 = add %0, %1
+      %5 = add %4, %3
+      ret %5
+    }
+
+    fun @misc(@x: i32): i32 {
+    %entry:
+      %0 = sub @x, @x
+      %1 = div @x, 1
+      %2 = mod @x, 1
+      %3 = add %0, %1
+      %4 = add %3, %2
+      %5 = div %4, 1073741824
+      ret %5
+    }
+  "#;
+
+  fn parse() -> Program {
+    let driver: Driver<_> = SRC.into();
+    driver.generate_program().unwrap()
+  }
+
+  #[test]
+  fn eliminate_divisions() {
+    // results are compared before and after by the interpreter example,
+    // see test `simplify_same_results` in `examples/interpreter`
+    let mut after = parse();
+    let pass = AlgebraicSimplify::new();
+    for &func in after.func_layout().to_vec().iter() {
+      pass.run(after.func_mut(func));
+    }
+    // all divisions and multiplications are eliminated,
+    // except the ones by negative constants
+    for &func in after.func_layout() {
+      let func = after.func(func);
+      let ops = func.dfg().values().values().filter_map(|d| match d.kind() {
+        ValueKind::Binary(bin) => Some(bin.op()),
+        _ => None,
+      });
+      let divs = ops
+        .filter(|op| matches!(op, BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod))
+        .count();
+      assert_eq!(divs, if func.name() == "@div_neg" { 2 } else { 0 });
+    }
+  }
+
+  #[test]
+  fn div_sequence() {
+    let mut program = parse();
+    let func = program.func_layout()[1];
+    assert_eq!(AlgebraicSimplify::new().run(program.func_mut(func)), 1);
+    program.remove_func(program.func_layout()[0]);
+    while program.func_layout().len() > 1 {
+      program.remove_func(program.func_layout()[1]);
+    }
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(&program).unwrap();
+    assert_eq!(
+      str::from_utf8(&gen.writer()).unwrap(),
+      r#"fun @div8(@x: i32): i32 {
+%entry:
+  %0 = sar @x, 2
+  %1 = shr %0, 29
+  %2 = add @x, %1
+  %3 = sar %2, 3
+  ret %3
+}
+"#
+    );
+  }
+
+  #[test]
+  fn cost_models() {
+    // returns names of functions with remaining divisions and modulos
+    let kept = |pass: AlgebraicSimplify| {
+      let mut program = parse();
+      for &func in program.func_layout().to_vec().iter() {
+        pass.run(program.func_mut(func));
+      }
+      let mut names = Vec::new();
+      for &func in program.func_layout() {
+        let func = program.func(func);
+        let kept = func.dfg().values().values().any(|d| {
+          matches!(d.kind(), ValueKind::Binary(bin)
+            if matches!(bin.op(), BinaryOp::Div | BinaryOp::Mod))
+        });
+        if kept && func.name() != "@div_neg" {
+          names.push(func.name().to_string());
+        }
+      }
+      names
+    };
+    assert!(kept(AlgebraicSimplify::new()).is_empty());
+    // the expansions take 3 to 5 instructions
+    assert_eq!(
+      kept(AlgebraicSimplify::with_cost_model(UnitCost)),
+      ["@div2", "@div8", "@mod4", "@mod1024", "@misc"]
+    );
+    // divisions cost 4, and so do the expansions of divisions,
+    // but the expansions of modulos cost 5
+    assert_eq!(
+      kept(AlgebraicSimplify::with_cost_model(WeightedCost)),
+      ["@mod4", "@mod1024"]
+    );
+  }
+
+  #[test]
+  fn keep_trapping_mul() {
+    let driver: Driver<_> = r#"
+      fun @mul(@x: i32): i32 {
+      %entry:
+        %0 = mul @x, 4
+        %1 = mul %0, 1
+        ret %1
+      }
+    "#
+    .into();
+    let mut program = driver.generate_program().unwrap();
+    let func = program.func_mut(program.func_layout()[0]);
+    func.dfg_mut().set_overflow_mode(OverflowMode::Trap);
+    // only `mul %0, 1` is rewritten
+    assert_eq!(AlgebraicSimplify::new().run(func), 1);
+    func.dfg_mut().set_overflow_mode(OverflowMode::Wrapping);
+    assert_eq!(AlgebraicSimplify::new().run(func), 1);
+  }
+}
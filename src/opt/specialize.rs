@@ -0,0 +1,586 @@
+//! Function specialization pass ([`FunctionSpecialization`]) related
+//! implementations.
+
+use crate::analysis::cfg;
+use crate::ir::builder_traits::*;
+use crate::ir::dfg::DataFlowGraph;
+use crate::ir::{AttrValue, Function, FunctionData, Program, TypeKind, Value, ValueKind};
+use crate::opt::rewrite::{is_dead, remove_dead};
+use crate::opt::ModulePass;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+/// Key of the attribute of functions created by
+/// [`FunctionSpecialization`].
+///
+/// The attribute value is a string like `"@pow,1,2"`, which is the name
+/// of the original function, the index of the removed parameter and
+/// the constant argument, separated by commas.
+pub const SPECIALIZED_ATTR: &str = "specialized";
+
+/// A module pass that specializes functions on constant arguments.
+///
+/// For each combination of a function definition, a parameter index
+/// and an integer constant, that is passed to the function by at least
+/// the given number of call sites (see
+/// [`FunctionSpecialization::with_min_calls`]), the function is cloned
+/// with the parameter replaced with the constant and removed from the
+/// signature. The matching call sites are changed to call the clone
+/// without the argument, and other call sites are left unchanged. The
+/// original function is kept, so it can still be called from outside
+/// the program.
+///
+/// Arguments are constants if they are integer constants, or binary
+/// operations on constants which can be folded in the overflow mode of
+/// the caller (see
+/// [`DataFlowGraph::overflow_mode`](crate::ir::dfg::DataFlowGraph::overflow_mode)).
+/// Combinations with more call sites are specialized first, and each
+/// call site is only changed once, so run the pass again to specialize
+/// the other constant arguments of the call sites.
+///
+/// Clones are named like `@pow_1_2` for function `@pow` specialized
+/// on parameter 1 with constant `2`, negative constants are written
+/// like `n2`. A `_N` suffix is added if the name is already used. The
+/// clones have the attribute [`SPECIALIZED_ATTR`], so running the pass
+/// again reuses them instead of creating duplicates, and changes the
+/// new matching call sites regardless of the number of them.
+///
+/// Clones are placed right after the original functions in the layout,
+/// so they are still defined before the callers in the text form. For
+/// the same reason, recursive calls in the original functions are not
+/// changed. Unreachable basic blocks are not cloned. The clones inherit the
+/// overflow mode, ordering constraints and attributes of the original
+/// functions. Fold the constants in the clones by other passes, like
+/// [`LoopUnroll`](crate::opt::LoopUnroll) and
+/// [`AlgebraicSimplify`](crate::opt::AlgebraicSimplify).
+///
+/// # Example
+///
+/// ```
+/// use koopa::front::Driver;
+/// use koopa::opt::*;
+///
+/// let driver: Driver<_> = r#"
+///   fun @scale(@x: i32, @k: i32): i32 {
+///   %entry:
+///     %0 = mul @x, @k
+///     ret %0
+///   }
+///
+///   fun @main(): i32 {
+///   %entry:
+///     %0 = call @scale(3, 8)
+///     %1 = call @scale(%0, 8)
+///     ret %1
+///   }
+/// "#
+/// .into();
+/// let mut program = driver.generate_program().unwrap();
+///
+/// assert_eq!(FunctionSpecialization::new().run(&mut program), 2);
+/// let scale = program.func(program.func_layout()[1]);
+/// assert_eq!(scale.name(), "@scale_1_8");
+/// assert_eq!(scale.params().len(), 1);
+/// ```
+pub struct FunctionSpecialization {
+  min_calls: usize,
+}
+
+impl FunctionSpecialization {
+  /// Creates a new function specialization pass, which specializes
+  /// combinations with at least 2 call sites.
+  pub fn new() -> Self {
+    Self { min_calls: 2 }
+  }
+
+  /// Sets the minimum number of call sites of specialized combinations.
+  pub fn with_min_calls(mut self, min_calls: usize) -> Self {
+    self.min_calls = min_calls;
+    self
+  }
+
+  /// Runs the pass on the given program.
+  /// Returns the number of changed call sites.
+  pub fn run(&self, program: &mut Program) -> usize {
+    let mut clones: HashMap<_, _> = program
+      .func_layout()
+      .iter()
+      .filter_map(|f| match program.func(*f).attr(SPECIALIZED_ATTR) {
+        Some(AttrValue::Str(key)) => Some((key.clone(), *f)),
+        _ => None,
+      })
+      .collect();
+    let layout = program.func_layout().to_vec();
+    let mut combos: Vec<_> = call_sites(program).into_iter().collect();
+    let pos: HashMap<_, _> = layout.iter().enumerate().map(|(i, f)| (*f, i)).collect();
+    combos.sort_by_key(|((func, index, n), sites)| (Reverse(sites.len()), pos[func], *index, *n));
+    let mut changed = HashSet::new();
+    let mut count = 0;
+    for ((func, index, n), sites) in combos {
+      let sites: Vec<_> = sites.into_iter().filter(|s| !changed.contains(s)).collect();
+      let key = format!("{},{index},{n}", program.func(func).name());
+      let clone = match clones.get(&key) {
+        Some(clone) => *clone,
+        None if !sites.is_empty() && sites.len() >= self.min_calls => {
+          let clone = specialize(program, func, index, n, &key);
+          clones.insert(key, clone);
+          clone
+        }
+        None => continue,
+      };
+      for (caller, call) in sites {
+        change_call(program.func_mut(caller), call, clone, index);
+        changed.insert((caller, call));
+        count += 1;
+      }
+    }
+    count
+  }
+}
+
+impl Default for FunctionSpecialization {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl ModulePass for FunctionSpecialization {
+  fn run_on(&mut self, program: &mut Program) {
+    self.run(program);
+  }
+
+  fn name(&self) -> &str {
+    "specialize"
+  }
+}
+
+/// Call sites of function definitions, grouped by combinations of the
+/// callee, the argument index and the constant argument.
+type CallSites = HashMap<(Function, usize, i32), Vec<(Function, Value)>>;
+
+/// Returns call sites with constant arguments in the given program,
+/// in the layout order. Recursive calls are excluded.
+fn call_sites(program: &Program) -> CallSites {
+  let mut sites = CallSites::new();
+  for &caller in program.func_layout() {
+    let data = program.func(caller);
+    let consts = const_values(data);
+    for (_, node) in data.layout().bbs() {
+      for &inst in node.insts().keys() {
+        let ValueKind::Call(call) = data.dfg().value(inst).kind() else {
+          continue;
+        };
+        if call.callee() == caller || program.func(call.callee()).layout().entry_bb().is_none() {
+          continue;
+        }
+        for (index, arg) in call.args().iter().enumerate() {
+          if let Some(n) = consts.get(arg) {
+            let key = (call.callee(), index, *n);
+            sites.entry(key).or_default().push((caller, inst));
+          }
+        }
+      }
+    }
+  }
+  sites
+}
+
+/// Returns values of integer constants, and instructions that can be
+/// folded to integer constants, in the given function.
+fn const_values(data: &FunctionData) -> HashMap<Value, i32> {
+  let dfg = data.dfg();
+  let mut consts: HashMap<_, _> = dfg
+    .values()
+    .iter()
+    .filter_map(|(v, d)| Some((*v, d.kind().as_integer()?)))
+    .collect();
+  // operands are defined before their users in the reverse post order
+  for bb in cfg::rpo(data) {
+    for &inst in data.layout().bbs().node(&bb).unwrap().insts().keys() {
+      if let ValueKind::Binary(bin) = dfg.value(inst).kind() {
+        let (Some(lhs), Some(rhs)) = (consts.get(&bin.lhs()), consts.get(&bin.rhs())) else {
+          continue;
+        };
+        if let Some(n) = bin.op().eval_with(*lhs, *rhs, dfg.overflow_mode()) {
+          consts.insert(inst, n);
+        }
+      }
+    }
+  }
+  consts
+}
+
+/// Creates a clone of the given function specialized on the given
+/// parameter and constant, with the given attribute key.
+fn specialize(program: &mut Program, func: Function, index: usize, n: i32, key: &str) -> Function {
+  let mut names: HashSet<String> = program.funcs().values().map(|f| f.name().into()).collect();
+  names.extend(
+    program
+      .inst_layout()
+      .iter()
+      .filter_map(|v| program.borrow_value(*v).name().clone()),
+  );
+  let src = program.func(func);
+  let suffix = if n < 0 {
+    format!("n{}", n.unsigned_abs())
+  } else {
+    n.to_string()
+  };
+  let base = format!("{}_{index}_{suffix}", src.name());
+  let name = if names.contains(&base) {
+    (1..)
+      .map(|i| format!("{base}_{i}"))
+      .find(|n| !names.contains(n))
+      .unwrap()
+  } else {
+    base
+  };
+  let mut data = clone_func(src, index, n, name);
+  data.set_attr(SPECIALIZED_ATTR, key.into());
+  // place the clone right after the original function,
+  // so it is defined before the callers in the text form
+  let pos = program.func_layout().iter().position(|f| *f == func);
+  let clone = program.new_func(data);
+  program.move_func(clone, pos.unwrap() + 1);
+  clone
+}
+
+/// Clones the given function definition with the given name, replaces
+/// the given parameter with the given constant and removes it.
+fn clone_func(src: &FunctionData, index: usize, n: i32, name: String) -> FunctionData {
+  let (src_dfg, src_layout) = (src.dfg(), src.layout());
+  let TypeKind::Function(_, ret_ty) = src.ty().kind() else {
+    panic!("invalid function type");
+  };
+  let param_info = |p: &Value| {
+    let data = src_dfg.value(*p);
+    (data.name().clone(), data.ty().clone())
+  };
+  let params = src
+    .params()
+    .iter()
+    .enumerate()
+    .filter(|(i, _)| *i != index)
+    .map(|(_, p)| param_info(p))
+    .collect();
+  let mut data = FunctionData::with_param_names(name, params, ret_ty.clone())
+    .with_func_attrs(src.func_attrs().clone());
+  for (key, attr) in src.attrs() {
+    data.set_attr(key, attr.clone());
+  }
+  let dfg = data.dfg_mut();
+  dfg.set_overflow_mode(src_dfg.overflow_mode());
+  let mut values = HashMap::new();
+  let mut new_params = data.params().to_vec().into_iter();
+  for (i, p) in src.params().iter().enumerate() {
+    let value = if i == index {
+      data.dfg_mut().new_value().integer(n)
+    } else {
+      new_params.next().unwrap()
+    };
+    values.insert(*p, value);
+  }
+  // create basic blocks in the layout order
+  let order = cfg::rpo(src);
+  let mut bbs = HashMap::new();
+  for bb in src_layout.bbs().keys().filter(|bb| order.contains(bb)) {
+    let bb_data = src_dfg.bb(*bb);
+    let params = bb_data.params().iter().map(param_info).collect();
+    let new_bb = data
+      .dfg_mut()
+      .new_bb()
+      .basic_block_with_param_names(bb_data.name().clone(), params);
+    let new_params = data.dfg().bb(new_bb).params();
+    values.extend(
+      bb_data
+        .params()
+        .iter()
+        .copied()
+        .zip(new_params.iter().copied()),
+    );
+    data.layout_mut().bbs_mut().push_key_back(new_bb).unwrap();
+    bbs.insert(*bb, new_bb);
+  }
+  // clone instructions in the reverse post order,
+  // so operands are cloned before their users
+  for bb in order {
+    for &inst in src_layout.bbs().node(&bb).unwrap().insts().keys() {
+      let mut inst_data = src_dfg.value(inst).clone();
+      let kind = inst_data.kind_mut();
+      for i in 0..kind.num_operands() {
+        let value = copy_value(src_dfg, data.dfg_mut(), kind.operand(i), &mut values);
+        kind.set_operand(i, value);
+      }
+      for i in 0..kind.edges().len() {
+        let target = bbs[&kind.edges()[i].0];
+        kind.set_edge_target(i, target);
+      }
+      let new_inst = data.dfg_mut().new_value().raw(inst_data);
+      values.insert(inst, new_inst);
+      let insts = data.layout_mut().bb_mut(bbs[&bb]).insts_mut();
+      insts.push_key_back(new_inst).unwrap();
+    }
+  }
+  for (before, after) in src.order_constraints() {
+    if let (Some(before), Some(after)) = (values.get(&before), values.get(&after)) {
+      data.add_order_constraint(*before, *after);
+    }
+  }
+  data
+}
+
+/// Returns the copy of the given value in data flow graph `dst`.
+///
+/// Values that are not in the given map are constants, which are copied
+/// with their elements by using an explicit stack, or global values,
+/// which are returned unchanged.
+fn copy_value(
+  src: &DataFlowGraph,
+  dst: &mut DataFlowGraph,
+  value: Value,
+  values: &mut HashMap<Value, Value>,
+) -> Value {
+  if value.is_global() {
+    return value;
+  }
+  let mut stack = vec![value];
+  while let Some(&value) = stack.last() {
+    if values.contains_key(&value) {
+      stack.pop();
+      continue;
+    }
+    let data = src.value(value);
+    let pending: Vec<_> = data
+      .kind()
+      .value_uses()
+      .filter(|v| !values.contains_key(v))
+      .collect();
+    if pending.is_empty() {
+      stack.pop();
+      let mut data = data.clone();
+      let kind = data.kind_mut();
+      for i in 0..kind.num_operands() {
+        kind.set_operand(i, values[&kind.operand(i)]);
+      }
+      values.insert(value, dst.new_value().raw(data));
+    } else {
+      stack.extend(pending);
+    }
+  }
+  values[&value]
+}
+
+/// Changes the given call to call the given specialized function,
+/// without the argument at the given index.
+fn change_call(data: &mut FunctionData, call: Value, clone: Function, index: usize) {
+  let mut inst = data.dfg().value(call).clone();
+  let ValueKind::Call(c) = inst.kind_mut() else {
+    panic!("invalid call");
+  };
+  *c.callee_mut() = clone;
+  let arg = c.args_mut().remove(index);
+  data.dfg_mut().replace_value_with(call).raw(inst);
+  if is_dead(data.dfg(), arg) {
+    remove_dead(data, vec![arg]);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::analysis::verifier::verify;
+  use crate::front::Driver;
+  use crate::opt::{AlgebraicSimplify, LoopUnroll, Pass, PassManager};
+  use crate::text::print_program;
+
+  const POW: &str = r#"
+    fun @pow(@b: i32, @n: i32): i32 {
+    %entry:
+      jump %loop(0, 1)
+
+    %loop(%i: i32, %acc: i32):
+      %c = lt %i, @n
+      br %c, %body, %end
+
+    %body:
+      %acc1 = mul %acc, @b
+      %i1 = add %i, 1
+      jump %loop(%i1, %acc1)
+
+    %end:
+      ret %acc
+    }
+
+    fun @main(@x: i32): i32 {
+    %entry:
+      %0 = call @pow(@x, 2)
+      %1 = add 1, 1
+      %2 = call @pow(%0, %1)
+      %3 = call @pow(@x, 3)
+      %4 = add %2, %3
+      %5 = call @pow(%4, 2)
+      ret %5
+    }
+  "#;
+
+  fn parse(src: &str) -> Program {
+    let driver: Driver<_> = src.into();
+    driver.generate_program().unwrap()
+  }
+
+  #[test]
+  fn specialize_pow() {
+    let mut program = parse(POW);
+    assert_eq!(FunctionSpecialization::new().run(&mut program), 3);
+    assert!(verify(&program).is_ok());
+    let text = print_program(&program);
+    // the folded argument `%1` is removed, and `@pow(@x, 3)` is kept
+    assert!(text.contains(
+      r#"%entry:
+  %0 = call @pow_1_2(@x)
+  %1 = call @pow_1_2(%0)
+  %2 = call @pow(@x, 3)
+  %3 = add %1, %2
+  %4 = call @pow_1_2(%3)
+  ret %4
+"#
+    ));
+    assert!(text.contains("fun @pow_1_2(@b: i32): i32 {\n"));
+    assert!(text.contains("  %c = lt %i, 2\n"));
+    let clone = program.func_layout()[1];
+    assert_eq!(
+      program.func(clone).attr(SPECIALIZED_ATTR),
+      Some(&AttrValue::Str("@pow,1,2".into()))
+    );
+    assert_eq!(program.func_users(clone).iter().count(), 3);
+
+    // running again changes nothing
+    assert_eq!(FunctionSpecialization::new().run(&mut program), 0);
+    assert_eq!(print_program(&program), text);
+    // lowering the threshold specializes `@pow(@x, 3)` only
+    let pass = FunctionSpecialization::new().with_min_calls(1);
+    assert_eq!(pass.run(&mut program), 1);
+    assert_eq!(program.func_layout().len(), 4);
+    assert_eq!(program.func(program.func_layout()[1]).name(), "@pow_1_3");
+    assert_eq!(pass.run(&mut program), 0);
+  }
+
+  #[test]
+  fn fold_after_specialization() {
+    let mut program = parse(POW);
+    let mut passman = PassManager::new();
+    passman.register(Pass::Module(Box::new(FunctionSpecialization::new())));
+    passman.register(Pass::Function(Box::new(LoopUnroll::new())));
+    passman.register(Pass::Function(Box::new(AlgebraicSimplify::new())));
+    passman.run_passes(&mut program);
+    assert!(verify(&program).is_ok());
+    let text = print_program(&program);
+    // the loop of the clone is unrolled, and `mul 1, @b` is simplified,
+    // while the loop of the original function is kept
+    assert!(text.contains(
+      r#"fun @pow_1_2(@b: i32): i32 {
+%entry:
+  jump %body
+
+%loop(%i: i32, %acc: i32):
+  jump %end
+
+%body:
+  jump %body_0
+
+%body_0:
+  %acc1 = mul @b, @b
+  jump %loop(2, %acc1)
+
+%end:
+  ret %acc
+}
+"#
+    ));
+    assert!(text.contains("  %c = lt %i, @n\n"));
+  }
+
+  #[test]
+  fn parse_printed() {
+    let mut program = parse(
+      r#"
+      fun @f(@x: i32, @k: i32): i32 {
+      %entry:
+        %c = gt @x, 0
+        br %c, %rec, %end
+
+      %rec:
+        %x1 = sub @x, 1
+        %r = call @f(%x1, 3)
+        %s = add %r, @k
+        ret %s
+
+      %end:
+        ret 0
+      }
+
+      fun @main(@x: i32): i32 {
+      %entry:
+        %0 = call @f(@x, 3)
+        %1 = call @f(%0, 3)
+        %2 = call @f(%1, 3)
+        ret %2
+      }
+    "#,
+    );
+    assert_eq!(FunctionSpecialization::new().run(&mut program), 3);
+    let names: Vec<_> = program
+      .func_layout()
+      .iter()
+      .map(|f| program.func(*f).name())
+      .collect();
+    assert_eq!(names, ["@f", "@f_1_3", "@main"]);
+    // the clone is defined before its callers, and the recursive call in
+    // `@f` is kept, so the printed program can be parsed again
+    let text = print_program(&program);
+    assert!(text.contains(
+      "  %r = call @f(%x1, 3)
+"
+    ));
+    let reparsed = parse(&text);
+    assert!(verify(&reparsed).is_ok());
+    assert_eq!(print_program(&reparsed), text);
+    // the recursive call in the clone is specialized by the next run
+    assert_eq!(FunctionSpecialization::new().run(&mut program), 1);
+    let text = print_program(&program);
+    assert_eq!(text.matches("call @f_1_3(").count(), 4);
+    assert_eq!(print_program(&parse(&text)), text);
+  }
+
+  #[test]
+  fn name_conflicts() {
+    let mut program = parse(
+      r#"
+      global @scale_1_n8 = alloc i32, 0
+
+      fun @scale(@x: i32, @k: i32): i32 {
+      %entry:
+        %0 = mul @x, @k
+        ret %0
+      }
+
+      decl @ext(i32): i32
+
+      fun @main(): i32 {
+      %entry:
+        %0 = call @scale(3, -8)
+        %1 = call @scale(%0, -8)
+        %2 = call @ext(1)
+        %3 = call @ext(1)
+        ret %1
+      }
+    "#,
+    );
+    // declarations are never specialized
+    assert_eq!(FunctionSpecialization::new().run(&mut program), 2);
+    let clone = program.func(program.func_layout()[1]);
+    assert_eq!(clone.name(), "@scale_1_n8_1");
+    assert_eq!(program.func_layout().len(), 4);
+    assert_eq!(print_program(&program).matches("call @ext(1)").count(), 2);
+  }
+}
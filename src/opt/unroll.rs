@@ -0,0 +1,775 @@
+//! Loop unrolling pass ([`LoopUnroll`]) related implementations.
+
+use crate::analysis::cost::{CostModel, UnitCost};
+use crate::ir::builder_traits::*;
+use crate::ir::dfg::DataFlowGraph;
+use crate::ir::{BasicBlock, BinaryOp, Function, FunctionData, Value, ValueKind};
+use crate::opt::FunctionPass;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// A function pass that fully unrolls small loops with constant trip
+/// counts.
+///
+/// Only loops in the following canonical form are unrolled, and all
+/// other loops are left unchanged:
+///
+/// ```koopa
+/// %pred:
+///   // the only edge from outside the loop to the header
+///   jump %header(0, ...)
+///
+/// %header(%i: i32, ...):
+///   // compares the induction variable with a constant
+///   %cond = lt %i, 4
+///   br %cond, %body, %exit
+///
+/// %body:
+///   // basic blocks of the loop body, they can only be reached from
+///   // the header, and can only leave the loop through the latch
+///   ...
+///   %next = add %i, 1
+///   // the only back edge, which updates the induction variable
+///   // by a constant step
+///   jump %header(%next, ...)
+/// ```
+///
+/// The loop body is cloned once for each iteration, in which the
+/// induction variable is replaced with its concrete value, and other
+/// parameters of the header are replaced with the arguments of the
+/// previous iteration. After unrolling, the header only jumps to the
+/// exit, and its parameters receive the values of the last iteration.
+///
+/// Instructions that use the induction variable are not folded, run
+/// a constant folding pass after this pass if necessary.
+///
+/// The size of loop bodies is measured by a [`CostModel`], which is
+/// [`UnitCost`] by default, so the limit is the number of instructions.
+#[derive(Clone)]
+pub struct LoopUnroll {
+  max_trip_count: usize,
+  max_body_cost: u32,
+  cost_model: Arc<dyn CostModel + Send + Sync>,
+}
+
+/// A loop in the canonical form.
+struct Loop {
+  header: BasicBlock,
+  exit: BasicBlock,
+  /// Basic blocks of the loop body, the first one is the entry.
+  body: Vec<BasicBlock>,
+  /// The terminator outside the loop that targets the header.
+  pred: Value,
+  init_args: Vec<Value>,
+  latch: Value,
+  /// Index of the induction variable in the header parameters.
+  induction: usize,
+  /// Values of the induction variable in each iteration, followed by
+  /// the value when the loop exits.
+  values: Vec<i32>,
+}
+
+impl LoopUnroll {
+  /// Creates a new loop unrolling pass, which unrolls loops with at
+  /// most 8 iterations and 32 instructions in the loop body.
+  pub fn new() -> Self {
+    Self {
+      max_trip_count: 8,
+      max_body_cost: 32,
+      cost_model: Arc::new(UnitCost),
+    }
+  }
+
+  /// Sets the maximum trip count of unrolled loops.
+  pub fn with_max_trip_count(mut self, max_trip_count: usize) -> Self {
+    self.max_trip_count = max_trip_count;
+    self
+  }
+
+  /// Sets the maximum total cost of instructions in the body of
+  /// unrolled loops, including the latch.
+  pub fn with_max_body_cost(mut self, max_body_cost: u32) -> Self {
+    self.max_body_cost = max_body_cost;
+    self
+  }
+
+  /// Sets the cost model for measuring the body of loops.
+  pub fn with_cost_model<M>(mut self, cost_model: M) -> Self
+  where
+    M: CostModel + Send + Sync + 'static,
+  {
+    self.cost_model = Arc::new(cost_model);
+    self
+  }
+
+  /// Runs the pass on the given function.
+  /// Returns the number of unrolled loops.
+  pub fn run(&self, data: &mut FunctionData) -> usize {
+    let mut count = 0;
+    // unrolling a loop may create new candidates,
+    // so restart after each unrolling
+    while let Some(lp) = self.find_loop(data) {
+      unroll(data, lp);
+      count += 1;
+    }
+    count
+  }
+
+  /// Finds the first loop that can be unrolled in the layout.
+  fn find_loop(&self, data: &FunctionData) -> Option<Loop> {
+    let entry = data.layout().entry_bb()?;
+    data
+      .layout()
+      .bbs()
+      .keys()
+      .filter(|bb| **bb != entry)
+      .find_map(|bb| self.analyze(data, *bb))
+  }
+
+  /// Checks if the given basic block is the header of a loop in the
+  /// canonical form, and the loop is small enough to be unrolled.
+  fn analyze(&self, data: &FunctionData, header: BasicBlock) -> Option<Loop> {
+    let (dfg, layout) = (data.dfg(), data.layout());
+    let insts: Vec<_> = layout
+      .bbs()
+      .node(&header)?
+      .insts()
+      .keys()
+      .copied()
+      .collect();
+    let [cond, br] = insts[..] else {
+      return None;
+    };
+    // check the header
+    let ValueKind::Branch(branch) = dfg.value(br).kind() else {
+      return None;
+    };
+    let (entry, exit) = (branch.true_bb(), branch.false_bb());
+    if branch.cond() != cond
+      || !branch.true_args().is_empty()
+      || !branch.false_args().is_empty()
+      || entry == header
+      || exit == header
+      || entry == exit
+      || !dfg.bb(entry).params().is_empty()
+      || dfg.value(cond).used_by().len() != 1
+    {
+      return None;
+    }
+    let ValueKind::Binary(cmp) = dfg.value(cond).kind() else {
+      return None;
+    };
+    let params = dfg.bb(header).params();
+    let induction = params.iter().position(|p| *p == cmp.lhs())?;
+    let bound = dfg.const_int(cmp.rhs())?;
+    use BinaryOp::{Eq, Ge, Gt, Le, Lt, NotEq};
+    if !matches!(cmp.op(), NotEq | Eq | Gt | Lt | Ge | Le) {
+      return None;
+    }
+    // collect basic blocks of the loop body
+    let mut body = vec![entry];
+    let mut body_set = HashSet::from([entry]);
+    let mut latch = None;
+    let mut body_cost = 0u32;
+    let mut i = 0;
+    while let Some(&bb) = body.get(i) {
+      i += 1;
+      let insts = layout.bbs().node(&bb)?.insts();
+      for inst in insts.keys() {
+        body_cost = body_cost.saturating_add(self.cost_model.cost(dfg, *inst));
+      }
+      let term = dfg.value(*insts.back_key()?).kind();
+      match term {
+        ValueKind::Jump(jump) if jump.target() == header => {
+          if latch.replace(*insts.back_key().unwrap()).is_some() {
+            return None;
+          }
+        }
+        ValueKind::Jump(_) | ValueKind::Branch(_) => {
+          for succ in term.bb_uses() {
+            if succ == header || succ == exit {
+              return None;
+            }
+            if body_set.insert(succ) {
+              body.push(succ);
+            }
+          }
+        }
+        _ => return None,
+      }
+    }
+    let latch = latch?;
+    if body_cost > self.max_body_cost {
+      return None;
+    }
+    // the loop body can only be entered from the header,
+    // and values in the loop body can only be used in the loop body
+    let in_body = |v: &Value| {
+      layout
+        .parent_bb(*v)
+        .is_some_and(|bb| body_set.contains(&bb))
+    };
+    for &bb in &body {
+      let data = dfg.bb(bb);
+      if bb == entry {
+        if data.used_by().iter().any(|u| *u != br) {
+          return None;
+        }
+      } else if !data.used_by().iter().all(in_body) {
+        return None;
+      }
+      let insts = layout.bbs().node(&bb).unwrap().insts();
+      let mut values = data.params().iter().chain(insts.keys());
+      if !values.all(|v| dfg.value(*v).used_by().iter().all(in_body)) {
+        return None;
+      }
+    }
+    // check the edges to the header
+    let users = dfg.bb(header).used_by();
+    let pred = *users.iter().find(|u| **u != latch)?;
+    if users.len() != 2 || layout.parent_bb(pred).is_none() {
+      return None;
+    }
+    let init_args = match dfg.value(pred).kind() {
+      ValueKind::Jump(jump) => jump.args().to_vec(),
+      ValueKind::Branch(br) if br.true_bb() == header && br.false_bb() != header => {
+        br.true_args().to_vec()
+      }
+      ValueKind::Branch(br) if br.false_bb() == header && br.true_bb() != header => {
+        br.false_args().to_vec()
+      }
+      _ => return None,
+    };
+    // check the induction variable
+    let ValueKind::Jump(jump) = dfg.value(latch).kind() else {
+      unreachable!()
+    };
+    let ValueKind::Binary(update) = dfg.values().get(&jump.args()[induction])?.kind() else {
+      return None;
+    };
+    let var = params[induction];
+    let mode = dfg.overflow_mode();
+    let step: Box<dyn Fn(i32) -> Option<i32>> = match update.op() {
+      BinaryOp::Add | BinaryOp::Sub if update.lhs() == var => {
+        let (op, rhs) = (update.op(), dfg.const_int(update.rhs())?);
+        Box::new(move |i| op.eval_with(i, rhs, mode))
+      }
+      BinaryOp::Add if update.rhs() == var => {
+        let lhs = dfg.const_int(update.lhs())?;
+        Box::new(move |i| BinaryOp::Add.eval_with(lhs, i, mode))
+      }
+      _ => return None,
+    };
+    // compute the trip count
+    let mut values = vec![dfg.const_int(init_args[induction])?];
+    loop {
+      let i = *values.last().unwrap();
+      if cmp.op().eval(i, bound)? == 0 {
+        break;
+      }
+      if values.len() > self.max_trip_count {
+        return None;
+      }
+      values.push(step(i)?);
+    }
+    Some(Loop {
+      header,
+      exit,
+      body,
+      pred,
+      init_args,
+      latch,
+      induction,
+      values,
+    })
+  }
+}
+
+impl Default for LoopUnroll {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl FunctionPass for LoopUnroll {
+  fn run_on(&mut self, _: Function, data: &mut FunctionData) {
+    self.run(data);
+  }
+
+  fn name(&self) -> &str {
+    "unroll"
+  }
+
+  fn fork(&self) -> Option<Box<dyn FunctionPass + Send>> {
+    Some(Box::new(self.clone()))
+  }
+}
+
+/// Fully unrolls the given loop.
+fn unroll(data: &mut FunctionData, lp: Loop) {
+  let trip = lp.values.len() - 1;
+  let params = data.dfg().bb(lp.header).params().to_vec();
+  let ValueKind::Jump(jump) = data.dfg().value(lp.latch).kind() else {
+    unreachable!()
+  };
+  let latch_args = jump.args().to_vec();
+  // create basic blocks for all copies, in the layout order
+  let order: Vec<_> = data
+    .layout()
+    .bbs()
+    .keys()
+    .filter(|bb| lp.body.contains(bb))
+    .copied()
+    .collect();
+  let body_entry = lp.body[0];
+  let mut bb_maps = Vec::with_capacity(trip);
+  for _ in 0..trip {
+    let mut bb_map = HashMap::new();
+    for &bb in &order {
+      let old = data.dfg().bb(bb);
+      let name = old.name().clone();
+      let params = old
+        .params()
+        .iter()
+        .map(|p| {
+          let param = data.dfg().value(*p);
+          (param.name().clone(), param.ty().clone())
+        })
+        .collect();
+      let new = data
+        .dfg_mut()
+        .new_bb()
+        .basic_block_with_param_names(name, params);
+      data
+        .layout_mut()
+        .bbs_mut()
+        .cursor_mut(body_entry)
+        .insert_key_before(new)
+        .unwrap();
+      bb_map.insert(bb, new);
+    }
+    bb_maps.push(bb_map);
+  }
+  // clone instructions for all copies, in the order of the loop body,
+  // so that definitions are always cloned before their uses
+  let mut args = lp.init_args;
+  let mut cloned = Vec::new();
+  for (k, bb_map) in bb_maps.iter().enumerate() {
+    let mut value_map = HashMap::new();
+    for (j, (param, arg)) in params.iter().zip(&args).enumerate() {
+      let arg = match j == lp.induction {
+        true => data.dfg_mut().new_value().integer(lp.values[k]),
+        false => *arg,
+      };
+      value_map.insert(*param, arg);
+    }
+    for &bb in &lp.body {
+      let new_bb = bb_map[&bb];
+      for (old, new) in data
+        .dfg()
+        .bb(bb)
+        .params()
+        .iter()
+        .zip(data.dfg().bb(new_bb).params())
+      {
+        value_map.insert(*old, *new);
+      }
+      let insts = bb_insts(data, bb);
+      for inst in insts {
+        let new = if inst == lp.latch {
+          // the latch jumps to the next copy,
+          // or the header if this is the last copy
+          let map = |v: &Value| *value_map.get(v).unwrap_or(v);
+          args = latch_args.iter().map(map).collect();
+          match bb_maps.get(k + 1) {
+            Some(next) => data.dfg_mut().new_value().jump(next[&body_entry]),
+            None => {
+              args[lp.induction] = data.dfg_mut().new_value().integer(lp.values[trip]);
+              data
+                .dfg_mut()
+                .new_value()
+                .jump_with_args(lp.header, args.clone())
+            }
+          }
+        } else {
+          clone_inst(data.dfg_mut(), inst, &value_map, bb_map)
+        };
+        data.push_inst(new_bb, new);
+        value_map.insert(inst, new);
+        cloned.push(new);
+      }
+    }
+  }
+  // rewire the edge to the header, and make the header jump to the exit
+  if let Some(bb_map) = bb_maps.first() {
+    retarget(data.dfg_mut(), lp.pred, lp.header, bb_map[&body_entry]);
+  }
+  let insts = bb_insts(data, lp.header);
+  for inst in insts.into_iter().rev() {
+    data
+      .layout_mut()
+      .bb_mut(lp.header)
+      .insts_mut()
+      .remove(&inst);
+    data.dfg_mut().remove_value(inst);
+  }
+  let jump = data.dfg_mut().new_value().jump(lp.exit);
+  data.push_inst(lp.header, jump);
+  // remove the original loop body
+  let mut values = Vec::new();
+  for &bb in &lp.body {
+    let insts = bb_insts(data, bb);
+    for inst in insts {
+      data.layout_mut().bb_mut(bb).insts_mut().remove(&inst);
+      values.push(inst);
+    }
+  }
+  remove_unused(data.dfg_mut(), values);
+  for bb in lp.body {
+    data.layout_mut().bbs_mut().remove(&bb);
+    data.dfg_mut().remove_bb(bb);
+  }
+  // remove the cloned updates of the induction variable,
+  // which are replaced with constants
+  for inst in cloned.into_iter().rev() {
+    let value = data.dfg().value(inst);
+    if value.used_by().is_empty() && matches!(value.kind(), ValueKind::Binary(_)) {
+      let bb = data.layout().parent_bb(inst).unwrap();
+      data.layout_mut().bb_mut(bb).insts_mut().remove(&inst);
+      data.dfg_mut().remove_value(inst);
+    }
+  }
+}
+
+/// Returns instructions in the given basic block.
+fn bb_insts(data: &FunctionData, bb: BasicBlock) -> Vec<Value> {
+  let node = data.layout().bbs().node(&bb).unwrap();
+  node.insts().keys().copied().collect()
+}
+
+/// Clones the given instruction, and replaces its operands and target
+/// basic blocks by the given maps.
+fn clone_inst(
+  dfg: &mut DataFlowGraph,
+  inst: Value,
+  value_map: &HashMap<Value, Value>,
+  bb_map: &HashMap<BasicBlock, BasicBlock>,
+) -> Value {
+  let mut data = dfg.value(inst).clone();
+  let kind = data.kind_mut();
+  for i in 0..kind.num_operands() {
+    if let Some(value) = value_map.get(&kind.operand(i)) {
+      kind.set_operand(i, *value);
+    }
+  }
+  for i in 0..kind.edges().len() {
+    if let Some(bb) = bb_map.get(&kind.edges()[i].0) {
+      kind.set_edge_target(i, *bb);
+    }
+  }
+  dfg.new_value().raw(data)
+}
+
+/// Replaces the edge from the given terminator to basic block `from`
+/// with an edge to basic block `to` without arguments.
+fn retarget(dfg: &mut DataFlowGraph, term: Value, from: BasicBlock, to: BasicBlock) {
+  let edges = dfg.value(term).kind().edges();
+  let index = edges.iter().position(|(bb, _)| *bb == from).unwrap();
+  dfg.rewrite_edge(term, index, to, vec![]);
+}
+
+/// Removes the given values, which are only used by each other.
+fn remove_unused(dfg: &mut DataFlowGraph, mut values: Vec<Value>) {
+  while !values.is_empty() {
+    let len = values.len();
+    values.retain(|v| {
+      let unused = dfg.value(*v).used_by().is_empty();
+      if unused {
+        dfg.remove_value(*v);
+      }
+      !unused
+    });
+    assert!(values.len() < len, "values are used outside the loop");
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::analysis::cost::WeightedCost;
+  use crate::back::KoopaGenerator;
+  use crate::front::Driver;
+  use crate::ir::Program;
+  use std::str;
+
+  /// Returns the value of the given operand.
+  fn operand(dfg: &DataFlowGraph, vals: &HashMap<Value, i32>, value: Value) -> i32 {
+    match vals.get(&value) {
+      Some(v) => *v,
+      None => dfg.const_int(value).unwrap(),
+    }
+  }
+
+  /// Evaluates the given function, which contains only binary
+  /// operations, branches, jumps and returns.
+  fn eval(func: &FunctionData, args: &[i32]) -> i32 {
+    let dfg = func.dfg();
+    let mut vals: HashMap<_, _> = func
+      .params()
+      .iter()
+      .copied()
+      .zip(args.iter().copied())
+      .collect();
+    let mut bb = func.layout().entry_bb().unwrap();
+    loop {
+      let node = func.layout().bbs().node(&bb).unwrap();
+      for &inst in node.insts().keys() {
+        let (target, args) = match dfg.value(inst).kind() {
+          ValueKind::Binary(bin) => {
+            let lhs = operand(dfg, &vals, bin.lhs());
+            let rhs = operand(dfg, &vals, bin.rhs());
+            vals.insert(inst, bin.op().eval(lhs, rhs).unwrap());
+            continue;
+          }
+          ValueKind::Branch(br) if operand(dfg, &vals, br.cond()) != 0 => {
+            (br.true_bb(), br.true_args())
+          }
+          ValueKind::Branch(br) => (br.false_bb(), br.false_args()),
+          ValueKind::Jump(jump) => (jump.target(), jump.args()),
+          ValueKind::Return(ret) => return operand(dfg, &vals, ret.value().unwrap()),
+          _ => unreachable!(),
+        };
+        let args: Vec<_> = args.iter().map(|v| operand(dfg, &vals, *v)).collect();
+        vals.extend(dfg.bb(target).params().iter().copied().zip(args));
+        bb = target;
+        break;
+      }
+    }
+  }
+
+  fn parse(src: &str) -> Program {
+    let driver: Driver<_> = src.into();
+    driver.generate_program().unwrap()
+  }
+
+  /// Runs the given pass on the first function of the given program,
+  /// checks the results of the function before and after unrolling.
+  fn unroll(pass: LoopUnroll, src: &str) -> (usize, Program) {
+    let before = parse(src);
+    let mut after = parse(src);
+    let func = after.func_layout()[0];
+    let count = pass.run(after.func_mut(func));
+    let (f1, f2) = (before.func(before.func_layout()[0]), after.func(func));
+    for x in -5..=5 {
+      assert_eq!(eval(f1, &[x]), eval(f2, &[x]), "x = {x}");
+    }
+    (count, after)
+  }
+
+  fn dump(program: &Program) -> String {
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(program).unwrap();
+    str::from_utf8(&gen.writer()).unwrap().into()
+  }
+
+  #[test]
+  fn unroll_simple() {
+    let (count, program) = unroll(
+      LoopUnroll::new(),
+      r#"
+      fun @f(@x: i32): i32 {
+      %entry:
+        jump %header(0, @x)
+
+      %header(%i: i32, %acc: i32):
+        %cond = lt %i, 2
+        br %cond, %body, %exit
+
+      %body:
+        %t = mul %acc, %i
+        %next = add %i, 1
+        jump %header(%next, %t)
+
+      %exit:
+        %ans = add %acc, %i
+        ret %ans
+      }
+    "#,
+    );
+    assert_eq!(count, 1);
+    assert_eq!(
+      dump(&program),
+      r#"fun @f(@x: i32): i32 {
+%entry:
+  jump %body
+
+%header(%i: i32, %acc: i32):
+  jump %exit
+
+%body:
+  %t = mul @x, 0
+  jump %body_0
+
+%body_0:
+  %t_0 = mul %t, 1
+  jump %header(2, %t_0)
+
+%exit:
+  %ans = add %acc, %i
+  ret %ans
+}
+"#
+    );
+  }
+
+  #[test]
+  fn unroll_branches() {
+    // the loop body contains branches and basic block parameters,
+    // and the induction variable counts down
+    let (count, program) = unroll(
+      LoopUnroll::new(),
+      r#"
+      fun @f(@x: i32): i32 {
+      %entry:
+        %c = gt @x, 0
+        br %c, %pre, %end(0)
+
+      %pre:
+        jump %header(@x, 10, 1)
+
+      %header(%acc: i32, %i: i32, %prod: i32):
+        %cond = gt %i, 0
+        br %cond, %body, %exit
+
+      %body:
+        %odd = and %i, 1
+        br %odd, %then, %latch(%acc)
+
+      %then:
+        %t = mul %acc, %i
+        jump %latch(%t)
+
+      %latch(%v: i32):
+        %s = add %v, %i
+        %p = mul %prod, %s
+        %next = sub %i, 3
+        jump %header(%s, %next, %p)
+
+      %exit:
+        %ans = add %acc, %prod
+        jump %end(%ans)
+
+      %end(%r: i32):
+        ret %r
+      }
+    "#,
+    );
+    assert_eq!(count, 1);
+    let func = program.func(program.func_layout()[0]);
+    // 4 iterations, 3 basic blocks for each iteration
+    assert_eq!(func.layout().bbs().len(), 5 + 4 * 3);
+  }
+
+  #[test]
+  fn unroll_zero_trip() {
+    let (count, program) = unroll(
+      LoopUnroll::new(),
+      r#"
+      fun @f(@x: i32): i32 {
+      %entry:
+        jump %header(5, @x)
+
+      %header(%i: i32, %acc: i32):
+        %cond = lt %i, 5
+        br %cond, %body, %exit
+
+      %body:
+        %t = add %acc, %i
+        %next = add %i, 1
+        jump %header(%next, %t)
+
+      %exit:
+        ret %acc
+      }
+    "#,
+    );
+    assert_eq!(count, 1);
+    assert_eq!(
+      dump(&program),
+      r#"fun @f(@x: i32): i32 {
+%entry:
+  jump %header(5, @x)
+
+%header(%i: i32, %acc: i32):
+  jump %exit
+
+%exit:
+  ret %acc
+}
+"#
+    );
+  }
+
+  #[test]
+  fn bail_out() {
+    let src = |bound, step, exit| {
+      format!(
+        r#"
+        fun @f(@x: i32): i32 {{
+        %entry:
+          jump %header(0, @x)
+
+        %header(%i: i32, %acc: i32):
+          %cond = lt %i, {bound}
+          br %cond, %body, %exit
+
+        %body:
+          %c = eq %acc, 100
+          br %c, {exit}, %latch
+
+        %latch:
+          %t = add %acc, %i
+          %next = add %i, {step}
+          jump %header(%next, %t)
+
+        %exit:
+          ret %acc
+        }}
+      "#
+      )
+    };
+    let count = |pass: LoopUnroll, src: &str| {
+      let mut program = parse(src);
+      let func = program.func_layout()[0];
+      pass.run(program.func_mut(func))
+    };
+    let new = LoopUnroll::new;
+    assert_eq!(count(new(), &src("9", "1", "%latch")), 0);
+    assert_eq!(
+      unroll(new().with_max_trip_count(9), &src("9", "1", "%latch")).0,
+      1
+    );
+    assert_eq!(
+      count(new().with_max_body_cost(4), &src("4", "1", "%latch")),
+      0
+    );
+    // the body costs 5 in `UnitCost`, and 6 in `WeightedCost`
+    // since the branch costs 2
+    let limited = || new().with_max_body_cost(5);
+    assert_eq!(count(limited(), &src("4", "1", "%latch")), 1);
+    assert_eq!(
+      count(
+        limited().with_cost_model(WeightedCost),
+        &src("4", "1", "%latch")
+      ),
+      0
+    );
+    // non-constant bound or step
+    assert_eq!(count(new(), &src("@x", "1", "%latch")), 0);
+    assert_eq!(count(new(), &src("4", "@x", "%latch")), 0);
+    // leaving the loop from the body
+    assert_eq!(count(new(), &src("4", "1", "%exit")), 0);
+    // infinite loop
+    assert_eq!(count(new(), &src("4", "0", "%latch")), 0);
+  }
+}
@@ -0,0 +1,725 @@
+//! Utilities for testing IR transformations.
+//!
+//! This module provides a FileCheck-style output matcher ([`check`]),
+//! which checks the text form IR against patterns instead of comparing
+//! the whole text, so tests will not break on unrelated changes.
+//!
+//! # Patterns
+//!
+//! Patterns are given as lines containing directives. Text before the
+//! directive is ignored, so patterns can be written in comments, like
+//! `// CHECK: ret`. The supported directives are:
+//!
+//! * `CHECK: <pattern>`: the pattern matches a line after the line
+//!   matched by the previous directive.
+//! * `CHECK-NEXT: <pattern>`: the pattern matches the line right after
+//!   the line matched by the previous directive.
+//! * `CHECK-NOT: <pattern>`: the pattern does not match any line between
+//!   the lines matched by the surrounding directives, or the end of the
+//!   text if there are no directives after it.
+//!
+//! A pattern matches a line if it matches a substring of the line.
+//! Patterns are literal text, except:
+//!
+//! * A sequence of whitespaces matches one or more whitespaces.
+//! * `{{regex}}` matches the regular expression.
+//! * `[[NAME:regex]]` matches the regular expression, and binds the
+//!   matched text to variable `NAME`.
+//! * `[[NAME]]` matches the text bound to variable `NAME`.
+//!
+//! Variables defined by `CHECK` and `CHECK-NEXT` can be used in the
+//! following patterns, and a variable can be redefined. Regular
+//! expressions support characters, `.`, character classes like `[0-9]`
+//! and `[^,]`, escapes `\d`, `\w`, `\s` (and their negations `\D`, `\W`,
+//! `\S`), groups, alternations, and greedy quantifiers `*`, `+` and `?`.
+//!
+//! # Example
+//!
+//! ```
+//! use koopa::test_utils::check;
+//!
+//! let ir = r#"
+//! fun @main(): i32 {
+//! %entry:
+//!   %0 = add 1, 2
+//!   %1 = mul %0, 3
+//!   ret %1
+//! }
+//! "#;
+//!
+//! check(
+//!   ir,
+//!   r#"
+//!   CHECK: fun @main
+//!   CHECK: [[X:%[0-9]+]] = add
+//!   CHECK-NOT: load
+//!   CHECK-NEXT: [[Y:%[0-9]+]] = mul [[X]], 3
+//!   CHECK-NEXT: ret [[Y]]
+//!   "#,
+//! );
+//! ```
+
+use crate::back::KoopaGenerator;
+use crate::ir::Program;
+use std::fmt;
+
+/// Checks the given text against the given patterns.
+///
+/// See the [module-level documentation](self) for the syntax of patterns.
+///
+/// # Panics
+///
+/// Panics if the check fails. The panic message contains the failed
+/// pattern, the searched region of the text and the variables in scope.
+pub fn check(text: &str, patterns: &str) {
+  if let Err(e) = try_check(text, patterns) {
+    panic!("{e}");
+  }
+}
+
+/// Generates the given program into the text form, and checks the text
+/// against the given patterns.
+///
+/// # Panics
+///
+/// Panics if the check fails, see [`check`].
+pub fn check_program(program: &Program, patterns: &str) {
+  let mut gen = KoopaGenerator::new(Vec::new());
+  gen.generate_on(program).unwrap();
+  check(&String::from_utf8(gen.writer()).unwrap(), patterns);
+}
+
+/// Checks the given text against the given patterns.
+/// Returns the error if the check fails.
+///
+/// See the [module-level documentation](self) for the syntax of patterns.
+pub fn try_check(text: &str, patterns: &str) -> Result<(), CheckError> {
+  let lines: Vec<Vec<char>> = text.lines().map(|l| l.chars().collect()).collect();
+  let mut checker = Checker {
+    lines: &lines,
+    vars: Vec::new(),
+    cur: 0,
+    matched: None,
+    nots: Vec::new(),
+  };
+  let mut has_directive = false;
+  for (i, line) in patterns.lines().enumerate() {
+    let Some((kind, pattern)) = parse_directive(line) else {
+      continue;
+    };
+    has_directive = true;
+    let directive = Directive {
+      kind,
+      text: pattern.trim().into(),
+      line: i + 1,
+    };
+    checker.check(directive)?;
+  }
+  if !has_directive {
+    return Err(CheckError {
+      message: "no directives found in patterns".into(),
+      directive: None,
+      region: Vec::new(),
+      vars: Vec::new(),
+    });
+  }
+  checker.check_nots(lines.len())
+}
+
+/// Error returned by [`try_check`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CheckError {
+  /// The error message.
+  pub message: String,
+  /// The failed directive and its line number in patterns, if exists.
+  pub directive: Option<(usize, String)>,
+  /// The searched region of the text, with line numbers.
+  pub region: Vec<(usize, String)>,
+  /// Variables in scope when the check fails.
+  pub vars: Vec<(String, String)>,
+}
+
+impl fmt::Display for CheckError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "check failed: {}", self.message)?;
+    if let Some((line, directive)) = &self.directive {
+      writeln!(f, "pattern (line {line}):")?;
+      writeln!(f, "  {directive}")?;
+    }
+    if !self.region.is_empty() {
+      writeln!(f, "searched region:")?;
+      let width = self.region.last().unwrap().0.to_string().len();
+      for (line, text) in &self.region {
+        writeln!(f, "  {line:>width$} | {text}")?;
+      }
+    }
+    if !self.vars.is_empty() {
+      writeln!(f, "variables in scope:")?;
+      for (name, value) in &self.vars {
+        writeln!(f, "  {name} = {value:?}")?;
+      }
+    }
+    Ok(())
+  }
+}
+
+impl std::error::Error for CheckError {}
+
+/// Kind of directives.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DirectiveKind {
+  Check,
+  Next,
+  Not,
+}
+
+impl DirectiveKind {
+  /// Returns the name of the directive.
+  fn name(self) -> &'static str {
+    match self {
+      Self::Check => "CHECK",
+      Self::Next => "CHECK-NEXT",
+      Self::Not => "CHECK-NOT",
+    }
+  }
+}
+
+/// A directive in patterns.
+struct Directive {
+  kind: DirectiveKind,
+  text: String,
+  line: usize,
+}
+
+impl Directive {
+  /// Returns the directive and its line number for error reporting.
+  fn report(&self) -> Option<(usize, String)> {
+    Some((self.line, format!("{}: {}", self.kind.name(), self.text)))
+  }
+}
+
+/// Returns the directive kind and the pattern of the given line.
+fn parse_directive(line: &str) -> Option<(DirectiveKind, &str)> {
+  let mut rest = line;
+  while let Some(i) = rest.find("CHECK") {
+    let after = &rest[i + 5..];
+    for (prefix, kind) in [
+      (":", DirectiveKind::Check),
+      ("-NEXT:", DirectiveKind::Next),
+      ("-NOT:", DirectiveKind::Not),
+    ] {
+      if let Some(pattern) = after.strip_prefix(prefix) {
+        return Some((kind, pattern));
+      }
+    }
+    rest = after;
+  }
+  None
+}
+
+/// State of a check.
+struct Checker<'a> {
+  lines: &'a [Vec<char>],
+  /// Defined variables, the latest definition comes last.
+  vars: Vec<(String, String)>,
+  /// Index of the first line to be searched.
+  cur: usize,
+  /// Index of the line matched by the previous directive.
+  matched: Option<usize>,
+  /// Pending `CHECK-NOT` directives and their patterns.
+  nots: Vec<(Directive, Vec<Item>)>,
+}
+
+impl<'a> Checker<'a> {
+  /// Checks the given directive.
+  fn check(&mut self, directive: Directive) -> Result<(), CheckError> {
+    let items = match parse_pattern(&directive.text) {
+      Ok(items) => items,
+      Err(e) => return Err(self.error(&directive, format!("invalid pattern, {e}"), 0..0)),
+    };
+    if let Some(name) = undefined_var(&items, &self.vars) {
+      let msg = format!("undefined variable `{name}`");
+      return Err(self.error(&directive, msg, 0..0));
+    }
+    match directive.kind {
+      DirectiveKind::Check => {
+        let found = (self.cur..self.lines.len()).find(|i| self.match_line(&items, *i));
+        match found {
+          Some(i) => self.advance(i),
+          None => {
+            let region = self.cur..self.lines.len();
+            Err(self.error(&directive, "no match found".into(), region))
+          }
+        }
+      }
+      DirectiveKind::Next => {
+        let Some(prev) = self.matched else {
+          let msg = "`CHECK-NEXT` without a previous match".into();
+          return Err(self.error(&directive, msg, 0..0));
+        };
+        let next = prev + 1;
+        if next < self.lines.len() && self.match_line(&items, next) {
+          self.advance(next)
+        } else {
+          let region = prev..(next + 1).min(self.lines.len());
+          Err(self.error(&directive, "the next line does not match".into(), region))
+        }
+      }
+      DirectiveKind::Not => {
+        self.nots.push((directive, items));
+        Ok(())
+      }
+    }
+  }
+
+  /// Sets the given line as the matched line, and checks the pending
+  /// `CHECK-NOT` directives.
+  fn advance(&mut self, line: usize) -> Result<(), CheckError> {
+    self.check_nots(line)?;
+    self.matched = Some(line);
+    self.cur = line + 1;
+    Ok(())
+  }
+
+  /// Checks the pending `CHECK-NOT` directives in the region between
+  /// the current line and the given line.
+  fn check_nots(&mut self, end: usize) -> Result<(), CheckError> {
+    for (directive, items) in std::mem::take(&mut self.nots) {
+      let vars_len = self.vars.len();
+      let found = (self.cur..end).find(|i| self.match_line(&items, *i));
+      // variables defined in `CHECK-NOT` are not in scope
+      self.vars.truncate(vars_len);
+      if let Some(i) = found {
+        let msg = format!("excluded pattern found at line {}", i + 1);
+        return Err(self.error(&directive, msg, self.cur..end));
+      }
+    }
+    Ok(())
+  }
+
+  /// Returns `true` if the given pattern matches the given line. Variables
+  /// defined by the pattern will be added to the scope.
+  fn match_line(&mut self, items: &[Item], line: usize) -> bool {
+    let text = &self.lines[line];
+    (0..=text.len()).any(|start| match_items(items, text, start, &mut self.vars, &mut |_, _| true))
+  }
+
+  /// Creates a new error.
+  fn error(
+    &self,
+    directive: &Directive,
+    message: String,
+    region: std::ops::Range<usize>,
+  ) -> CheckError {
+    let mut vars: Vec<(String, String)> = Vec::new();
+    for (name, value) in self.vars.iter().rev() {
+      if !vars.iter().any(|(n, _)| n == name) {
+        vars.push((name.clone(), value.clone()));
+      }
+    }
+    vars.sort();
+    CheckError {
+      message,
+      directive: directive.report(),
+      region: region
+        .map(|i| (i + 1, self.lines[i].iter().collect()))
+        .collect(),
+      vars,
+    }
+  }
+}
+
+/// An item of a pattern, which is an atom with a repetition range.
+#[derive(Debug)]
+struct Item {
+  atom: Atom,
+  min: usize,
+  max: Option<usize>,
+}
+
+impl Item {
+  /// Creates an item that matches the given atom exactly once.
+  fn once(atom: Atom) -> Self {
+    Self {
+      atom,
+      min: 1,
+      max: Some(1),
+    }
+  }
+}
+
+/// An atom of a pattern.
+#[derive(Debug)]
+enum Atom {
+  /// A character.
+  Char(char),
+  /// Any character.
+  Any,
+  /// A character class.
+  Class(Class),
+  /// One or more whitespaces.
+  Space,
+  /// A group of alternatives.
+  Group(Vec<Vec<Item>>),
+  /// A group of alternatives whose matched text is bound to a variable.
+  Capture(String, Vec<Vec<Item>>),
+  /// The text bound to a variable.
+  Var(String),
+}
+
+/// A character class.
+#[derive(Debug)]
+struct Class {
+  negated: bool,
+  ranges: Vec<(char, char)>,
+}
+
+impl Class {
+  /// Returns `true` if the given character is in the class.
+  fn matches(&self, c: char) -> bool {
+    self.ranges.iter().any(|(l, r)| (*l..=*r).contains(&c)) != self.negated
+  }
+}
+
+/// Parses the given pattern.
+fn parse_pattern(pattern: &str) -> Result<Vec<Item>, String> {
+  let chars: Vec<_> = pattern.chars().collect();
+  let mut items = Vec::new();
+  let mut i = 0;
+  while i < chars.len() {
+    if chars[i..].starts_with(&['{', '{']) {
+      let mut parser = RegexParser {
+        chars: &chars,
+        pos: i + 2,
+      };
+      let alts = parser.parse_alts(&['}', '}'])?;
+      items.push(Item::once(Atom::Group(alts)));
+      i = parser.pos + 2;
+    } else if chars[i..].starts_with(&['[', '[']) {
+      let name_end = (i + 2..chars.len())
+        .find(|j| !(chars[*j].is_ascii_alphanumeric() || chars[*j] == '_'))
+        .ok_or("unterminated variable")?;
+      let name: String = chars[i + 2..name_end].iter().collect();
+      if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(format!("invalid variable name `{name}`"));
+      }
+      if chars[name_end..].starts_with(&[']', ']']) {
+        items.push(Item::once(Atom::Var(name)));
+        i = name_end + 2;
+      } else if chars[name_end] == ':' {
+        let mut parser = RegexParser {
+          chars: &chars,
+          pos: name_end + 1,
+        };
+        let alts = parser.parse_alts(&[']', ']'])?;
+        items.push(Item::once(Atom::Capture(name, alts)));
+        i = parser.pos + 2;
+      } else {
+        return Err(format!("invalid variable name `{name}{}`", chars[name_end]));
+      }
+    } else if chars[i].is_whitespace() {
+      while i < chars.len() && chars[i].is_whitespace() {
+        i += 1;
+      }
+      items.push(Item::once(Atom::Space));
+    } else {
+      items.push(Item::once(Atom::Char(chars[i])));
+      i += 1;
+    }
+  }
+  Ok(items)
+}
+
+/// Parser of regular expressions.
+struct RegexParser<'a> {
+  chars: &'a [char],
+  pos: usize,
+}
+
+impl<'a> RegexParser<'a> {
+  /// Parses alternatives until the given terminator is found.
+  /// The terminator will not be consumed.
+  fn parse_alts(&mut self, term: &[char]) -> Result<Vec<Vec<Item>>, String> {
+    let mut alts = vec![Vec::new()];
+    loop {
+      let rest = &self.chars[self.pos..];
+      if rest.starts_with(term) {
+        return Ok(alts);
+      }
+      let Some(&c) = rest.first() else {
+        let term: String = term.iter().collect();
+        return Err(format!("missing `{term}`"));
+      };
+      self.pos += 1;
+      let atom = match c {
+        '|' => {
+          alts.push(Vec::new());
+          continue;
+        }
+        '(' => {
+          let group = self.parse_alts(&[')'])?;
+          self.pos += 1;
+          Atom::Group(group)
+        }
+        '[' => Atom::Class(self.parse_class()?),
+        '.' => Atom::Any,
+        '\\' => self.parse_escape()?,
+        '*' | '+' | '?' => return Err(format!("nothing to repeat before `{c}`")),
+        _ => Atom::Char(c),
+      };
+      let (min, max) = match self.chars.get(self.pos) {
+        Some('*') => (0, None),
+        Some('+') => (1, None),
+        Some('?') => (0, Some(1)),
+        _ => (1, Some(1)),
+      };
+      if max != Some(1) || min != 1 {
+        self.pos += 1;
+      }
+      alts.last_mut().unwrap().push(Item { atom, min, max });
+    }
+  }
+
+  /// Parses a character class after `[`.
+  fn parse_class(&mut self) -> Result<Class, String> {
+    let negated = self.chars.get(self.pos) == Some(&'^');
+    if negated {
+      self.pos += 1;
+    }
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+      let Some(&c) = self.chars.get(self.pos) else {
+        return Err("missing `]`".into());
+      };
+      self.pos += 1;
+      if c == ']' && !first {
+        return Ok(Class { negated, ranges });
+      }
+      first = false;
+      let c = if c == '\\' {
+        match self.chars.get(self.pos) {
+          Some(&e) => {
+            self.pos += 1;
+            e
+          }
+          None => return Err("missing `]`".into()),
+        }
+      } else {
+        c
+      };
+      let is_range = self.chars.get(self.pos) == Some(&'-')
+        && self.chars.get(self.pos + 1).is_some_and(|c| *c != ']');
+      if is_range {
+        let r = self.chars[self.pos + 1];
+        self.pos += 2;
+        if r < c {
+          return Err(format!("invalid range `{c}-{r}`"));
+        }
+        ranges.push((c, r));
+      } else {
+        ranges.push((c, c));
+      }
+    }
+  }
+
+  /// Parses an escape sequence after `\`.
+  fn parse_escape(&mut self) -> Result<Atom, String> {
+    let Some(&c) = self.chars.get(self.pos) else {
+      return Err("incomplete escape sequence".into());
+    };
+    self.pos += 1;
+    let ranges = match c.to_ascii_lowercase() {
+      'd' => vec![('0', '9')],
+      'w' => vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')],
+      's' => vec![(' ', ' '), ('\t', '\t'), ('\n', '\n'), ('\r', '\r')],
+      _ => return Ok(Atom::Char(c)),
+    };
+    Ok(Atom::Class(Class {
+      negated: c.is_ascii_uppercase(),
+      ranges,
+    }))
+  }
+}
+
+/// Returns the name of the first variable used in the given pattern
+/// that is neither in the given scope nor defined before the use.
+fn undefined_var(items: &[Item], vars: &[(String, String)]) -> Option<String> {
+  fn visit(items: &[Item], defined: &mut Vec<String>) -> Option<String> {
+    for item in items {
+      match &item.atom {
+        Atom::Var(name) if !defined.contains(name) => return Some(name.clone()),
+        Atom::Group(alts) => {
+          for alt in alts {
+            visit(alt, defined)?;
+          }
+        }
+        Atom::Capture(name, alts) => {
+          for alt in alts {
+            if let Some(name) = visit(alt, defined) {
+              return Some(name);
+            }
+          }
+          defined.push(name.clone());
+        }
+        _ => {}
+      }
+    }
+    None
+  }
+  let mut defined = vars.iter().map(|(n, _)| n.clone()).collect();
+  visit(items, &mut defined)
+}
+
+/// Continuation of the matcher, which is called with the position after
+/// the matched text and the variables.
+type Cont<'a> = &'a mut dyn FnMut(usize, &mut Vec<(String, String)>) -> bool;
+
+/// Matches the given items at the given position, and calls the
+/// continuation if matched. Returns `true` if the continuation succeeds.
+fn match_items(
+  items: &[Item],
+  text: &[char],
+  pos: usize,
+  vars: &mut Vec<(String, String)>,
+  k: Cont,
+) -> bool {
+  match items.split_first() {
+    Some((first, rest)) => match_repeat(first, 0, text, pos, vars, &mut |p, vars| {
+      match_items(rest, text, p, vars, k)
+    }),
+    None => k(pos, vars),
+  }
+}
+
+/// Matches the given item greedily, which has already matched
+/// `count` times.
+fn match_repeat(
+  item: &Item,
+  count: usize,
+  text: &[char],
+  pos: usize,
+  vars: &mut Vec<(String, String)>,
+  k: Cont,
+) -> bool {
+  if item.max.is_none_or(|max| count < max) {
+    let matched = match_atom(&item.atom, text, pos, vars, &mut |p, vars| {
+      // stop repeating empty matches
+      (p != pos || count < item.min) && match_repeat(item, count + 1, text, p, vars, k)
+    });
+    if matched {
+      return true;
+    }
+  }
+  count >= item.min && k(pos, vars)
+}
+
+/// Matches the given atom at the given position.
+fn match_atom(
+  atom: &Atom,
+  text: &[char],
+  pos: usize,
+  vars: &mut Vec<(String, String)>,
+  k: Cont,
+) -> bool {
+  match atom {
+    Atom::Char(c) => text.get(pos) == Some(c) && k(pos + 1, vars),
+    Atom::Any => pos < text.len() && k(pos + 1, vars),
+    Atom::Class(class) => text.get(pos).is_some_and(|c| class.matches(*c)) && k(pos + 1, vars),
+    Atom::Space => {
+      let len = text[pos.min(text.len())..]
+        .iter()
+        .take_while(|c| c.is_whitespace())
+        .count();
+      len > 0 && k(pos + len, vars)
+    }
+    Atom::Group(alts) => alts
+      .iter()
+      .any(|alt| match_items(alt, text, pos, vars, &mut *k)),
+    Atom::Capture(name, alts) => alts.iter().any(|alt| {
+      match_items(alt, text, pos, vars, &mut |p, vars| {
+        vars.push((name.clone(), text[pos..p].iter().collect()));
+        let matched = k(p, vars);
+        if !matched {
+          vars.pop();
+        }
+        matched
+      })
+    }),
+    Atom::Var(name) => {
+      let value: Vec<char> = match vars.iter().rev().find(|(n, _)| n == name) {
+        Some((_, v)) => v.chars().collect(),
+        None => return false,
+      };
+      text.get(pos..pos + value.len()) == Some(&value[..]) && k(pos + value.len(), vars)
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  const IR: &str = r#"global @x = alloc i32, zeroinit
+
+fun @main(): i32 {
+%entry:
+  %0 = load @x
+  %1 = add %0, 1
+  store %1, @x
+  ret %1
+}"#;
+
+  #[test]
+  fn check_directives() {
+    check(
+      IR,
+      r#"
+      // CHECK: global @x
+      // CHECK:      fun @main(): i32 {
+      // CHECK-NEXT: %entry:
+      // CHECK-NOT:  call
+      // CHECK:      [[V:%\d+]] = load @x
+      // CHECK-NEXT: [[W:%[0-9]+]] = {{add|sub}} [[V]], 1
+      // CHECK-NEXT: store [[W]], {{@[a-z_]+}}
+      // CHECK:      ret [[W]]
+      // CHECK-NOT:  {{\w}}
+      "#,
+    );
+  }
+
+  #[test]
+  fn check_failures() {
+    // no match
+    let e = try_check(IR, "CHECK: ret\nCHECK: load").unwrap_err();
+    assert_eq!(e.message, "no match found");
+    assert_eq!(e.directive, Some((2, "CHECK: load".into())));
+    assert_eq!(e.region, vec![(9, "}".into())]);
+    // wrong next line
+    let e = try_check(IR, "CHECK: load\nCHECK-NEXT: store").unwrap_err();
+    assert_eq!(e.message, "the next line does not match");
+    assert_eq!(e.region.len(), 2);
+    // excluded pattern, with variables in scope
+    let patterns = r#"
+      CHECK: [[X:%[0-9]+]] = load
+      CHECK-NOT: store
+      CHECK: ret
+    "#;
+    let e = try_check(IR, patterns).unwrap_err();
+    assert_eq!(e.message, "excluded pattern found at line 7");
+    assert_eq!(e.vars, vec![("X".into(), "%0".into())]);
+    assert!(e.to_string().contains("  7 |   store %1, @x"));
+    // mismatched variable
+    let e = try_check(IR, "CHECK: [[X:%[0-9]+]] = load\nCHECK: store [[X]]").unwrap_err();
+    assert_eq!(e.message, "no match found");
+    // invalid patterns
+    let e = try_check(IR, "CHECK: [[Y]]").unwrap_err();
+    assert_eq!(e.message, "undefined variable `Y`");
+    let e = try_check(IR, "CHECK: {{[0-9}}").unwrap_err();
+    assert_eq!(e.message, "invalid pattern, missing `]`");
+    let e = try_check(IR, "CHECK-NEXT: ret").unwrap_err();
+    assert_eq!(e.message, "`CHECK-NEXT` without a previous match");
+    let e = try_check(IR, "nothing").unwrap_err();
+    assert_eq!(e.message, "no directives found in patterns");
+  }
+}
@@ -0,0 +1,85 @@
+//! String-in/string-out helpers for text form Koopa IR.
+//!
+//! These functions do not touch the file system or the standard I/O,
+//! so they can be used in environments like `wasm32-unknown-unknown`.
+//! Errors are returned as strings. Enable feature `no-front-logger` to
+//! get the detailed parse error messages instead of printing them.
+//!
+//! # Examples
+//!
+//! ```
+//! use koopa::text::optimize_text;
+//!
+//! let src = r#"fun @id(@x: i32): i32 {
+//! %entry:
+//!   %0 = mul @x, 1
+//!   ret %0
+//! }
+//! "#;
+//! let output = optimize_text(src, &["simplify"]).unwrap();
+//! assert!(!output.contains("mul"));
+//! ```
+
+use crate::back::KoopaGenerator;
+use crate::front::Driver;
+use crate::ir::{Error, Program};
+use crate::opt::{Pass, PassManager};
+
+/// Parses the given text form Koopa IR into a program.
+pub fn parse_text(src: &str) -> Result<Program, String> {
+  let driver: Driver<_> = src.into();
+  driver
+    .generate_program()
+    .map_err(|e| Error::from(e).to_string())
+}
+
+/// Prints the given program in text form Koopa IR.
+pub fn print_program(program: &Program) -> String {
+  let mut gen = KoopaGenerator::new(Vec::new());
+  gen
+    .generate_on(program)
+    .expect("writing to a vector never fails");
+  String::from_utf8(gen.writer()).expect("generated IR is valid UTF-8")
+}
+
+/// Parses the given text form Koopa IR, runs the given passes on it in
+/// order, and prints the result.
+///
+/// Passes are specified by name, see [`Pass::builtin`] for the available
+/// names. Returns an error if any of the names is unknown, or the input
+/// can not be parsed.
+pub fn optimize_text(src: &str, passes: &[&str]) -> Result<String, String> {
+  let passes = passes
+    .iter()
+    .map(|name| Pass::builtin(name).ok_or_else(|| format!("unknown pass '{name}'")))
+    .collect::<Result<Vec<_>, _>>()?;
+  let mut program = parse_text(src)?;
+  PassManager::from(passes).run_passes(&mut program);
+  Ok(print_program(&program))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn round_trip() {
+    let src = r#"decl @getint(): i32
+
+fun @main(): i32 {
+%entry:
+  %0 = call @getint()
+  %1 = mul %0, 1
+  ret %1
+}
+"#;
+    assert_eq!(print_program(&parse_text(src).unwrap()), src);
+    let output = optimize_text(src, &["simplify", "global-dce"]).unwrap();
+    assert!(!output.contains("mul"));
+    assert_eq!(
+      optimize_text(src, &["simplify", "inline"]),
+      Err("unknown pass 'inline'".into())
+    );
+    assert!(optimize_text("fun @main() {", &[]).is_err());
+  }
+}
@@ -0,0 +1,155 @@
+use koopa::diagnostic::code;
+use koopa::driver::{Driver, Emit, Error, ErrorFormat};
+use koopa::ir::builder_traits::*;
+use koopa::ir::{FunctionData, Program, Type};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::{fs, str};
+
+/// Returns the path of the given fixture file.
+fn fixture(name: &str) -> PathBuf {
+  Path::new(env!("CARGO_MANIFEST_DIR"))
+    .join("tests/driver")
+    .join(name)
+}
+
+/// Runs `koopac` with the given arguments and standard input.
+fn koopac(args: &[&str], stdin: &str) -> Output {
+  let mut child = Command::new(env!("CARGO_BIN_EXE_koopac"))
+    .args(args)
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .unwrap();
+  child
+    .stdin
+    .take()
+    .unwrap()
+    .write_all(stdin.as_bytes())
+    .unwrap();
+  child.wait_with_output().unwrap()
+}
+
+#[test]
+fn run_passes() {
+  let src = fs::read_to_string(fixture("simplify.koopa")).unwrap();
+  let expected = fs::read_to_string(fixture("simplify.expected")).unwrap();
+  let mut output = Vec::new();
+  Driver::new()
+    .passes(["simplify", "global-dce"])
+    .run_on_text(&src, &mut output)
+    .unwrap();
+  assert_eq!(str::from_utf8(&output).unwrap(), expected);
+}
+
+#[test]
+fn run_on_files() {
+  let output_path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("driver_simplify.koopa");
+  let input = fixture("simplify.koopa");
+  let args = [
+    input.to_str().unwrap(),
+    "--passes",
+    "simplify,global-dce",
+    "-o",
+    output_path.to_str().unwrap(),
+  ];
+  let output = koopac(&args, "");
+  assert!(output.status.success());
+  assert!(output.stdout.is_empty());
+  let expected = fs::read_to_string(fixture("simplify.expected")).unwrap();
+  assert_eq!(fs::read_to_string(&output_path).unwrap(), expected);
+}
+
+#[test]
+fn run_on_stdin() {
+  let src = fs::read_to_string(fixture("simplify.koopa")).unwrap();
+  let output = koopac(&["--emit=dot", "-"], &src);
+  assert!(output.status.success());
+  let dot = str::from_utf8(&output.stdout).unwrap();
+  assert!(dot.starts_with("digraph program {"));
+  assert!(dot.contains("[label = \"%entry\"]"));
+  // verify only
+  let output = koopac(&["--verify"], &src);
+  assert!(output.status.success());
+  assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn exit_codes() {
+  let output = koopac(&["--passes", "inline"], "");
+  assert_eq!(output.status.code(), Some(1));
+  assert!(str::from_utf8(&output.stderr)
+    .unwrap()
+    .contains("unknown pass 'inline'"));
+  let missing = fixture("missing.koopa");
+  let output = koopac(&[missing.to_str().unwrap()], "");
+  assert_eq!(output.status.code(), Some(2));
+  let invalid = fixture("invalid.koopa");
+  let output = koopac(&[invalid.to_str().unwrap()], "");
+  assert_eq!(output.status.code(), Some(3));
+  // verification failures can only be caused by in-memory programs,
+  // since the parser rejects malformed functions
+  let mut program = Program::new();
+  let main = program.new_func(FunctionData::new("@main".into(), vec![], Type::get_unit()));
+  let data = program.func_mut(main);
+  let entry = data.dfg_mut().new_bb().basic_block(Some("%entry".into()));
+  data.layout_mut().bbs_mut().push_key_back(entry).unwrap();
+  let error = Driver::new()
+    .emit(Emit::Llvm)
+    .run_on(program, Vec::new())
+    .unwrap_err();
+  assert_eq!(error.exit_code(), 4);
+  assert!(matches!(&error, Error::Verify(errors) if errors.len() == 1));
+  assert_eq!(
+    error.to_string(),
+    "verification failed\n  @main, %entry: basic block is empty"
+  );
+}
+
+#[test]
+fn json_errors() {
+  let invalid = fixture("invalid.koopa");
+  let output = koopac(&["--error-format=json", invalid.to_str().unwrap()], "");
+  assert_eq!(output.status.code(), Some(3));
+  let stderr = str::from_utf8(&output.stderr).unwrap();
+  assert!(!stderr.is_empty());
+  for line in stderr.lines() {
+    assert!(line.starts_with(r#"{"code":"E0010","severity":"error","message":"#));
+    assert!(line.contains(r#""primary":{"file":""#));
+    assert!(line.ends_with('}'));
+  }
+  // verification failures
+  let mut program = Program::new();
+  let main = program.new_func(FunctionData::new("@main".into(), vec![], Type::get_unit()));
+  let data = program.func_mut(main);
+  let entry = data.dfg_mut().new_bb().basic_block(Some("%entry".into()));
+  data.layout_mut().bbs_mut().push_key_back(entry).unwrap();
+  let driver = Driver::new().error_format(ErrorFormat::Json);
+  let error = driver.run_on(program, Vec::new()).unwrap_err();
+  let diags = error.diagnostics();
+  assert_eq!(diags.len(), 1);
+  assert_eq!(diags[0].code, code::EMPTY_BB);
+  assert_eq!(
+    diags[0].to_json(),
+    r#"{"code":"E0113","severity":"error","message":"basic block is empty","primary":{"func":"@main","bb":"%entry"},"secondary":[],"notes":[]}"#
+  );
+  // front-end diagnostics are returned instead of being printed
+  let error = driver.run_on_text("fun @f(", Vec::new()).unwrap_err();
+  assert!(matches!(&error, Error::Parse(diags) if !diags.is_empty()));
+  assert!(error
+    .diagnostics()
+    .iter()
+    .all(|d| d.code == code::FRONT_ERROR));
+}
+
+#[test]
+fn print_help() {
+  let output = koopac(&["--help"], "");
+  assert!(output.status.success());
+  assert_eq!(
+    str::from_utf8(&output.stdout).unwrap(),
+    format!("{}\n", koopa::driver::USAGE)
+  );
+}
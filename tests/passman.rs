@@ -0,0 +1,302 @@
+use koopa::analysis::verifier::verify;
+use koopa::diagnostic::code;
+use koopa::front::Driver;
+use koopa::ir::builder_traits::*;
+use koopa::ir::mutation::MutationOp;
+use koopa::ir::{BinaryOp, Function, FunctionData, ValueKind};
+use koopa::opt::{FunctionPass, Pass, PassManager};
+use koopa::text::print_program;
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+const SRC: &str = r#"
+global @unused = alloc i32, zeroinit
+
+decl @getint(): i32
+
+fun @id(@x: i32): i32 {
+%entry:
+  %0 = mul @x, 1
+  ret %0
+}
+
+fun @main(): i32 {
+%entry:
+  %x = call @getint()
+  %y = mul %x, 8
+  %z = call @id(%y)
+  ret %z
+}
+
+fun @dead(): i32 {
+%entry:
+  ret 0
+}
+"#;
+
+#[test]
+fn dump_after_passes() {
+  let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("passman_dumps");
+  let _ = fs::remove_dir_all(&dir);
+  let mut program = Driver::from(SRC).generate_program().unwrap();
+  let mut passman = PassManager::new();
+  passman.register(Pass::builtin("simplify").unwrap());
+  passman.register(Pass::builtin("global-dce").unwrap());
+  passman.register(Pass::builtin("simplify").unwrap());
+  passman.dump_after_each(&dir);
+  let names = Rc::new(RefCell::new(Vec::new()));
+  let callback_names = names.clone();
+  passman.on_after_pass(move |name, program| {
+    assert!(verify(program).is_ok());
+    callback_names.borrow_mut().push(name.to_string());
+  });
+  passman.run_passes(&mut program);
+  // the callback fires after every pass
+  assert_eq!(*names.borrow(), ["simplify", "global-dce", "simplify"]);
+  let changed: Vec<_> = passman
+    .records()
+    .iter()
+    .map(|r| (r.name.as_str(), r.changed))
+    .collect();
+  assert_eq!(
+    changed,
+    [
+      ("simplify", true),
+      ("global-dce", true),
+      ("simplify", false)
+    ]
+  );
+  // only changes are dumped, and every dump verifies
+  let mut files: Vec<_> = fs::read_dir(&dir)
+    .unwrap()
+    .map(|e| e.unwrap().file_name().into_string().unwrap())
+    .collect();
+  files.sort();
+  assert_eq!(
+    files,
+    [
+      "01_simplify_id.koopa",
+      "01_simplify_main.koopa",
+      "02_global-dce.koopa"
+    ]
+  );
+  for file in &files {
+    let src = fs::read_to_string(dir.join(file)).unwrap();
+    let program = Driver::from(src).generate_program().unwrap();
+    assert!(verify(&program).is_ok(), "{file} does not verify");
+  }
+  // other functions are dumped as declarations
+  let dump = fs::read_to_string(dir.join("01_simplify_id.koopa")).unwrap();
+  assert!(dump.contains("decl @main(): i32\n"));
+  assert!(dump.contains("fun @id(@x: i32): i32 {\n"));
+}
+
+#[test]
+fn dump_after_given_passes() {
+  let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("passman_dumps_dce");
+  let _ = fs::remove_dir_all(&dir);
+  let mut program = Driver::from(SRC).generate_program().unwrap();
+  let mut passman = PassManager::from(vec![
+    Pass::builtin("simplify").unwrap(),
+    Pass::builtin("global-dce").unwrap(),
+  ]);
+  passman.dump_after_each(&dir);
+  passman.dump_after(["global-dce"]);
+  passman.run_passes(&mut program);
+  let files: Vec<_> = fs::read_dir(&dir)
+    .unwrap()
+    .map(|e| e.unwrap().file_name().into_string().unwrap())
+    .collect();
+  assert_eq!(files, ["02_global-dce.koopa"]);
+  let dump = fs::read_to_string(dir.join(&files[0])).unwrap();
+  assert!(!dump.contains("@dead") && !dump.contains("@unused"));
+}
+
+/// Creates a constant in every function, and then abandons it.
+struct Abandon;
+
+impl FunctionPass for Abandon {
+  fn run_on(&mut self, _: Function, data: &mut FunctionData) {
+    data.dfg_mut().new_value().integer(42);
+  }
+
+  fn name(&self) -> &str {
+    "abandon"
+  }
+}
+
+#[test]
+fn collect_garbage_after_passes() {
+  let mut program = Driver::from(SRC).generate_program().unwrap();
+  let count = |program: &koopa::ir::Program| -> usize {
+    program
+      .funcs()
+      .values()
+      .map(|f| f.dfg().values().len())
+      .sum()
+  };
+  let before = count(&program);
+  let mut passman = PassManager::from(vec![
+    Pass::Function(Box::new(Abandon)),
+    Pass::Function(Box::new(Abandon)),
+  ]);
+  passman.collect_garbage_after_each();
+  let counts = Rc::new(RefCell::new(Vec::new()));
+  let callback_counts = counts.clone();
+  passman.on_after_pass(move |_, program| callback_counts.borrow_mut().push(count(program)));
+  passman.run_passes(&mut program);
+  assert_eq!(*counts.borrow(), [before, before]);
+  // abandoned values do not change the text form
+  assert!(passman.records().iter().all(|r| !r.changed));
+}
+
+/// Simplifies `ret (mul %x, 1)` to `ret %x`, but forgets to remove the
+/// multiplication from the layout.
+struct BrokenPeephole;
+
+impl FunctionPass for BrokenPeephole {
+  fn run_on(&mut self, _: Function, data: &mut FunctionData) {
+    let insts: Vec<_> = data
+      .layout()
+      .bbs()
+      .nodes()
+      .flat_map(|node| node.insts().keys().copied())
+      .collect();
+    for inst in insts {
+      let lhs = match data.dfg().value(inst).kind() {
+        ValueKind::Binary(bin) if bin.op() == BinaryOp::Mul => {
+          match data.dfg().value(bin.rhs()).kind() {
+            ValueKind::Integer(i) if i.value() == 1 => bin.lhs(),
+            _ => continue,
+          }
+        }
+        _ => continue,
+      };
+      let users: Vec<_> = data.dfg().value(inst).used_by().iter().copied().collect();
+      for user in users {
+        data.dfg_mut().replace_value_with(user).ret(Some(lhs));
+      }
+      data.dfg_mut().remove_value(inst);
+    }
+  }
+
+  fn name(&self) -> &str {
+    "broken-peephole"
+  }
+}
+
+#[test]
+fn mutation_log_of_passes() {
+  let mut program = Driver::from(SRC).generate_program().unwrap();
+  for data in program.funcs_mut().values_mut() {
+    data.enable_mutation_log();
+  }
+  let id = *program
+    .func_layout()
+    .iter()
+    .find(|f| program.func(**f).name() == "@id")
+    .unwrap();
+  let data = program.func(id);
+  let entry = data.layout().entry_bb().unwrap();
+  let mul = *data
+    .layout()
+    .bbs()
+    .node(&entry)
+    .unwrap()
+    .insts()
+    .front_key()
+    .unwrap();
+  let mut passman = PassManager::from(vec![Pass::Function(Box::new(BrokenPeephole))]);
+  passman.run_passes(&mut program);
+  // the value is still in the layout
+  let errors = verify(&program).unwrap_err();
+  assert!(errors.iter().any(|e| e.code == code::INST_NOT_EXIST));
+  // the log pinpoints the pass that removed it
+  let log = program.func(id).dfg().mutation_log().unwrap();
+  let event = log
+    .events()
+    .iter()
+    .rev()
+    .find(|e| e.value == Some(mul))
+    .unwrap();
+  assert_eq!(event.op, MutationOp::RemoveValue);
+  assert_eq!(event.kind, Some("binary"));
+  assert_eq!(event.label.as_deref(), Some("broken-peephole"));
+  // without being removed from the layout
+  assert!(!log.events().iter().any(|e| e.op == MutationOp::RemoveInst));
+  assert!(log.to_string().contains("[broken-peephole] remove_value"));
+  assert!(log.label().is_none());
+}
+
+#[test]
+fn run_passes_in_parallel() {
+  let mut src = String::from("global @g = alloc [i32, 4], {1, 2, 3, 4}\n");
+  for i in 0..200 {
+    src += &format!(
+      r#"
+fun @f{i}(@x: i32): i32 {{
+%entry:
+  %p = getelemptr @g, 0
+  %q = getptr %p, 0
+  %a = mul @x, 8
+  %b = mul @x, 8
+  %c = add %a, %b
+  jump %loop(0, %c)
+
+%loop(%i: i32, %s: i32):
+  %v = load %q
+  %s1 = add %s, %v
+  %i1 = add %i, 1
+  %cond = lt %i1, {n}
+  br %cond, %loop(%i1, %s1), %end
+
+%end:
+  ret %s1
+}}
+"#,
+      n = i % 4 + 1,
+    );
+  }
+  let run = |threads| {
+    let mut program = Driver::from(src.as_str()).generate_program().unwrap();
+    let mut passman = PassManager::new();
+    for name in [
+      "canonicalize-ptr",
+      "simplify",
+      "gvn",
+      "global-dce",
+      "unroll",
+    ] {
+      passman.register(Pass::builtin(name).unwrap());
+    }
+    // a pass that can not be forked
+    passman.register(Pass::Function(Box::new(Abandon)));
+    passman.register(Pass::builtin("indvars").unwrap());
+    passman.collect_garbage_after_each();
+    let texts = Rc::new(RefCell::new(Vec::new()));
+    let callback_texts = texts.clone();
+    passman.on_after_pass(move |name, program| {
+      callback_texts
+        .borrow_mut()
+        .push((name.to_string(), print_program(program)));
+    });
+    passman.run_parallel(&mut program, threads);
+    let changed: Vec<_> = passman
+      .records()
+      .iter()
+      .map(|r| (r.name.clone(), r.changed))
+      .collect();
+    let texts = texts.take();
+    (print_program(&program), changed, texts)
+  };
+  let serial = run(1);
+  let parallel = run(8);
+  assert_eq!(serial.0, parallel.0);
+  assert_eq!(serial.1, parallel.1);
+  // the program after every pass is the same
+  assert!(serial.2 == parallel.2);
+  assert!(serial.1.iter().filter(|(_, changed)| *changed).count() >= 4);
+  assert!(!serial.0.contains("getptr") && !serial.0.contains("mul"));
+}
@@ -0,0 +1,44 @@
+//! Tests of the Rust builder code generator.
+//!
+//! `rust_gen/generated.rs` is the code generated from
+//! `rust_gen/program.koopa`, which is compiled with this test.
+//! Run this test with environment variable `UPDATE_GENERATED` set
+//! to update the generated code.
+
+use koopa::back::RustGenerator;
+use koopa::front::Driver;
+use koopa::ir::diff::diff_programs;
+use std::{env, fs, str};
+
+mod generated {
+  include!("rust_gen/generated.rs");
+}
+
+const SRC: &str = include_str!("rust_gen/program.koopa");
+
+#[test]
+fn generated_code_is_up_to_date() {
+  let driver: Driver<_> = SRC.into();
+  let program = driver.generate_program().unwrap();
+  let mut gen = RustGenerator::new(Vec::new());
+  gen.generate_on(&program).unwrap();
+  let code = gen.writer();
+  if env::var_os("UPDATE_GENERATED").is_some() {
+    fs::write("tests/rust_gen/generated.rs", code).unwrap();
+    return;
+  }
+  assert_eq!(
+    str::from_utf8(&code).unwrap(),
+    include_str!("rust_gen/generated.rs"),
+    "the generated code is outdated",
+  );
+}
+
+#[test]
+fn rebuild_program() {
+  let driver: Driver<_> = SRC.into();
+  let program = driver.generate_program().unwrap();
+  let rebuilt = generated::build_program();
+  let diff = diff_programs(&program, &rebuilt);
+  assert!(diff.is_empty(), "{diff}");
+}
@@ -0,0 +1,110 @@
+use koopa::ir::builder::FunctionBuilder;
+use koopa::ir::builder_traits::*;
+use koopa::ir::*;
+
+/// Builds the program.
+pub fn build_program() -> Program {
+  let mut program = Program::new();
+
+  // global values
+  let c0 = program.new_value().integer(1);
+  let g_x = program.new_value().global_alloc(c0);
+  program.set_value_name(g_x, Some("@x".into()));
+  let c1 = program.new_value().integer(1);
+  let c2 = program.new_value().integer(2);
+  let c3 = program.new_value().integer(3);
+  let c4 = program.new_value().aggregate(vec![c1, c2, c3]);
+  let g_arr = program.new_value().global_alloc(c4);
+  program.set_value_name(g_arr, Some("@arr".into()));
+  let c5 = program.new_value().zero_init(Type::get_array(Type::get_array(Type::get_i32(), 2), 2));
+  let g_buf = program.new_value().global_alloc(c5);
+  program.set_value_name(g_buf, Some("@buf".into()));
+
+  // functions
+  let f_getint = program.new_func(FunctionData::new_decl("@getint".into(), vec![], Type::get_i32()));
+  let f_putint = program.new_func(FunctionData::new_decl("@putint".into(), vec![Type::get_i32()], Type::get_unit()));
+  let f_exit = program.new_func(FunctionData::new_decl("@exit".into(), vec![Type::get_i32()], Type::get_unit()).with_func_attrs(FuncAttrs { noreturn: true, ..Default::default() }));
+  let f_sum = program.new_func(FunctionData::with_param_names("@sum".into(), vec![(Some("@n".into()), Type::get_i32())], Type::get_i32()));
+  let f_main = program.new_func(FunctionData::with_param_names("@main".into(), vec![], Type::get_i32()));
+
+  // function `@sum`
+  {
+    let func = program.func_mut(f_sum);
+    let p_n = func.params()[0];
+    let mut b = FunctionBuilder::new(func);
+    let bb_entry = b.create_bb(Some("%entry".into()));
+    let bb_loop = b.func_mut().dfg_mut().new_bb().basic_block_with_param_names(Some("%loop".into()), vec![(Some("%i".into()), Type::get_i32()), (Some("%s".into()), Type::get_i32())]);
+    b.func_mut().layout_mut().bbs_mut().push_key_back(bb_loop).unwrap();
+    let v_i = b.func().dfg().bb(bb_loop).params()[0];
+    let v_s = b.func().dfg().bb(bb_loop).params()[1];
+    let bb_body = b.create_bb(Some("%body".into()));
+    let bb_end = b.create_bb(Some("%end".into()));
+
+    b.position_at_end(bb_entry);
+    let c0 = b.integer(0);
+    let c1 = b.integer(0);
+    b.jump_with_args(bb_loop, vec![c0, c1]);
+
+    b.position_at_end(bb_loop);
+    let v_cond = b.binary(BinaryOp::Lt, v_i, p_n);
+    b.func_mut().dfg_mut().set_value_name(v_cond, Some("%cond".into()));
+    b.branch(v_cond, bb_body, bb_end);
+
+    b.position_at_end(bb_end);
+    b.ret(Some(v_s));
+
+    b.position_at_end(bb_body);
+    let v_p = b.get_elem_ptr(g_arr, v_i);
+    b.func_mut().dfg_mut().set_value_name(v_p, Some("%p".into()));
+    let v_v = b.load(v_p);
+    b.func_mut().dfg_mut().set_value_name(v_v, Some("%v".into()));
+    let v_s1 = b.binary(BinaryOp::Add, v_s, v_v);
+    b.func_mut().dfg_mut().set_value_name(v_s1, Some("%s1".into()));
+    let c2 = b.integer(1);
+    let v_i1 = b.binary(BinaryOp::Add, v_i, c2);
+    b.func_mut().dfg_mut().set_value_name(v_i1, Some("%i1".into()));
+    b.jump_with_args(bb_loop, vec![v_i1, v_s1]);
+  }
+
+  // function `@main`
+  {
+    let func = program.func_mut(f_main);
+    let mut b = FunctionBuilder::new(func);
+    let bb_entry = b.create_bb(Some("%entry".into()));
+    let bb_ok = b.create_bb(Some("%ok".into()));
+    let bb_fail = b.create_bb(Some("%fail".into()));
+
+    b.position_at_end(bb_entry);
+    let v_a = b.alloc(Type::get_array(Type::get_i32(), 2));
+    b.func_mut().dfg_mut().set_value_name(v_a, Some("%a".into()));
+    let c0 = b.integer(0);
+    let v_p = b.get_elem_ptr(v_a, c0);
+    b.func_mut().dfg_mut().set_value_name(v_p, Some("%p".into()));
+    let c1 = b.integer(1);
+    let v_q = b.get_ptr(v_p, c1);
+    b.func_mut().dfg_mut().set_value_name(v_q, Some("%q".into()));
+    let c2 = b.integer(3);
+    let c3 = b.integer(4);
+    let c4 = b.func_mut().dfg_mut().new_value().aggregate(vec![c2, c3]);
+    b.store(c4, v_a);
+    let v_n = b.call(f_getint, vec![]);
+    b.func_mut().dfg_mut().set_value_name(v_n, Some("%n".into()));
+    b.store(v_n, g_x);
+    let v_r = b.call(f_sum, vec![v_n]);
+    b.func_mut().dfg_mut().set_value_name(v_r, Some("%r".into()));
+    b.call(f_putint, vec![v_r]);
+    b.branch(v_r, bb_ok, bb_fail);
+
+    b.position_at_end(bb_fail);
+    let c5 = b.integer(1);
+    b.call(f_exit, vec![c5]);
+    b.unreachable();
+
+    b.position_at_end(bb_ok);
+    let v_x = b.load(g_x);
+    b.func_mut().dfg_mut().set_value_name(v_x, Some("%x".into()));
+    b.ret(Some(v_x));
+  }
+
+  program
+}